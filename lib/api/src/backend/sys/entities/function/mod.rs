@@ -8,8 +8,8 @@ use crate::{
     entities::store::{AsStoreMut, AsStoreRef, StoreMut},
     utils::{FromToNativeWasmType, IntoResult, NativeWasmTypeInto, WasmTypeList},
     vm::{VMExtern, VMExternFunction},
-    BackendFunction, FunctionEnv, FunctionEnvMut, FunctionType, HostFunction, RuntimeError,
-    StoreInner, Value, WithEnv, WithoutEnv,
+    BackendFunction, FunctionEnv, FunctionEnvMut, FunctionType, HostFunction, HostFuture,
+    RuntimeError, StoreInner, Value, WithEnv, WithoutEnv,
 };
 use std::panic::{self, AssertUnwindSafe};
 use std::{cell::UnsafeCell, cmp::max, ffi::c_void};
@@ -40,6 +40,44 @@ impl Function {
         ty: FT,
         func: F,
     ) -> Self
+    where
+        FT: Into<FunctionType>,
+        F: Fn(FunctionEnvMut<T>, &[Value]) -> Result<Vec<Value>, RuntimeError>
+            + 'static
+            + Send
+            + Sync,
+    {
+        Self::new_dynamic(store, env, ty, true, func)
+    }
+
+    pub(crate) fn new_with_env_async<FT, F, T: Send + 'static>(
+        store: &mut impl AsStoreMut,
+        env: &FunctionEnv<T>,
+        ty: FT,
+        func: F,
+    ) -> Self
+    where
+        FT: Into<FunctionType>,
+        F: for<'a> Fn(FunctionEnvMut<'a, T>, &'a [Value]) -> HostFuture<'a> + 'static + Send + Sync,
+    {
+        // Async host functions stay on the Wasm stack, so that it can be
+        // suspended while their future is pending.
+        Self::new_dynamic(store, env, ty, false, move |env, args| {
+            wasmer_vm::await_future(func(env, args)).unwrap_or_else(|| {
+                Err(RuntimeError::new(
+                    "async host functions can only be called through `call_async`",
+                ))
+            })
+        })
+    }
+
+    fn new_dynamic<FT, F, T: Send + 'static>(
+        store: &mut impl AsStoreMut,
+        env: &FunctionEnv<T>,
+        ty: FT,
+        host_stack: bool,
+        func: F,
+    ) -> Self
     where
         FT: Into<FunctionType>,
         F: Fn(FunctionEnvMut<T>, &[Value]) -> Result<Vec<Value>, RuntimeError>
@@ -89,7 +127,10 @@ impl Function {
         };
         let mut host_data = Box::new(VMDynamicFunctionContext {
             address: std::ptr::null(),
-            ctx: DynamicFunction { func: wrapper },
+            ctx: DynamicFunction {
+                func: wrapper,
+                host_stack,
+            },
         });
         host_data.address = host_data.ctx.func_body_ptr();
 
@@ -233,13 +274,6 @@ impl Function {
         params: &[Value],
         results: &mut [Value],
     ) -> Result<(), RuntimeError> {
-        let format_types_for_error_message = |items: &[Value]| {
-            items
-                .iter()
-                .map(|param| param.ty().to_string())
-                .collect::<Vec<String>>()
-                .join(", ")
-        };
         // TODO: Avoid cloning the signature here, it's expensive.
         let signature = self.ty(store);
         if signature.params().len() != params.len() {
@@ -257,16 +291,31 @@ impl Function {
             )));
         }
 
-        let mut values_vec = vec![RawValue { i32: 0 }; max(params.len(), results.len())];
+        let values_vec = self.params_to_raw(store, &signature, params, results.len())?;
+
+        // Invoke the call
+        self.call_wasm_raw(store, trampoline, values_vec, results)?;
+        Ok(())
+    }
+
+    /// Stores the argument values into a buffer that is large enough to
+    /// also hold `num_results` return values.
+    fn params_to_raw(
+        &self,
+        store: &mut impl AsStoreMut,
+        signature: &FunctionType,
+        params: &[Value],
+        num_results: usize,
+    ) -> Result<Vec<RawValue>, RuntimeError> {
+        let mut values_vec = vec![RawValue { i32: 0 }; max(params.len(), num_results)];
 
-        // Store the argument values into `values_vec`.
         let param_tys = signature.params().iter();
         for ((arg, slot), ty) in params.iter().zip(&mut values_vec).zip(param_tys) {
             if arg.ty() != *ty {
                 let param_types = format_types_for_error_message(params);
                 return Err(RuntimeError::new(format!(
                     "Parameters of type [{}] did not match signature {}",
-                    param_types, &signature,
+                    param_types, signature,
                 )));
             }
             if !arg.is_from_store(store) {
@@ -274,10 +323,7 @@ impl Function {
             }
             *slot = arg.as_raw(store);
         }
-
-        // Invoke the call
-        self.call_wasm_raw(store, trampoline, values_vec, results)?;
-        Ok(())
+        Ok(values_vec)
     }
 
     fn call_wasm_raw(
@@ -361,6 +407,58 @@ impl Function {
         Ok(results.into_boxed_slice())
     }
 
+    pub(crate) async fn call_async(
+        &self,
+        store: &mut impl AsStoreMut,
+        params: &[Value],
+    ) -> Result<Box<[Value]>, RuntimeError> {
+        let signature = self.ty(store);
+        if signature.params().len() != params.len() {
+            return Err(RuntimeError::new(format!(
+                "Parameters of type [{}] did not match signature {}",
+                format_types_for_error_message(params),
+                &signature
+            )));
+        }
+        let mut values_vec =
+            self.params_to_raw(store, &signature, params, signature.results().len())?;
+
+        let call = {
+            let storeref = store.as_store_ref();
+            let anyfunc = unsafe {
+                *self
+                    .handle
+                    .get(storeref.objects().as_sys())
+                    .anyfunc
+                    .as_ptr()
+                    .as_ref()
+            };
+            let config = storeref.engine().tunables().vmconfig();
+            unsafe {
+                wasmer_vm::wasmer_call_trampoline_async(
+                    storeref.signal_handler(),
+                    config,
+                    anyfunc.vmctx,
+                    anyfunc.call_trampoline,
+                    anyfunc.func_ptr,
+                    values_vec.as_mut_ptr() as *mut u8,
+                )
+            }
+        };
+        call.await?;
+
+        // Load the return values out of `values_vec`.
+        let results = signature
+            .results()
+            .iter()
+            .enumerate()
+            .map(|(index, &value_type)| unsafe {
+                Value::from_raw(store, value_type, values_vec[index])
+            })
+            .collect();
+        Ok(results)
+    }
+
     #[doc(hidden)]
     #[allow(missing_docs)]
     pub(crate) fn call_raw(
@@ -430,9 +528,19 @@ impl Function {
     }
 }
 
+fn format_types_for_error_message(items: &[Value]) -> String {
+    items
+        .iter()
+        .map(|param| param.ty().to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
 /// Host state for a dynamic function.
 pub(crate) struct DynamicFunction<F> {
     func: F,
+    /// Whether `func` should run on the host stack rather than the Wasm one.
+    host_stack: bool,
 }
 
 impl<F> DynamicFunction<F>
@@ -445,8 +553,12 @@ where
         this: &mut VMDynamicFunctionContext<Self>,
         values_vec: *mut RawValue,
     ) {
-        let result =
-            on_host_stack(|| panic::catch_unwind(AssertUnwindSafe(|| (this.ctx.func)(values_vec))));
+        let call = || panic::catch_unwind(AssertUnwindSafe(|| (this.ctx.func)(values_vec)));
+        let result = if this.ctx.host_stack {
+            on_host_stack(call)
+        } else {
+            call()
+        };
 
         match result {
             Ok(Ok(())) => {}
@@ -99,6 +99,52 @@ macro_rules! impl_native_traits {
                 // Ok(Rets::from_c_struct(results))
             }
 
+            /// Call the typed func asynchronously and return results.
+            #[allow(clippy::too_many_arguments)]
+            pub(crate) async fn call_async_sys(&self, store: &mut impl AsStoreMut, $( $x: $x, )* ) -> Result<Rets, RuntimeError> {
+                let anyfunc = unsafe {
+                    *self.func.as_sys()
+                        .handle
+                        .get(store.as_store_ref().objects().as_sys())
+                        .anyfunc
+                        .as_ptr()
+                        .as_ref()
+                };
+                // Ensure all parameters come from the same context.
+                if $(!FromToNativeWasmType::is_from_store(&$x, store) ||)* false {
+                    return Err(RuntimeError::new(
+                        "cross-`Store` values are not supported",
+                    ));
+                }
+                let params_list = [ $( $x.to_native().into_raw(store) ),* ];
+                let mut rets_list_array = Rets::empty_array();
+                let num_rets = rets_list_array.as_mut().len();
+
+                // The arguments and results must live on the heap, as they are
+                // accessed while the call is suspended.
+                let mut args_rets = vec![RawValue { i32: 0 }; params_list.len().max(num_rets)];
+                args_rets[..params_list.len()].copy_from_slice(&params_list);
+
+                let call = {
+                    let storeref = store.as_store_ref();
+                    let config = storeref.engine().tunables().vmconfig();
+                    unsafe {
+                        wasmer_vm::wasmer_call_trampoline_async(
+                            storeref.signal_handler(),
+                            config,
+                            anyfunc.vmctx,
+                            anyfunc.call_trampoline,
+                            anyfunc.func_ptr,
+                            args_rets.as_mut_ptr() as *mut u8,
+                        )
+                    }
+                };
+                call.await?;
+
+                rets_list_array.as_mut().copy_from_slice(&args_rets[..num_rets]);
+                Ok(unsafe { Rets::from_array(store, rets_list_array) })
+            }
+
             #[doc(hidden)]
             #[allow(missing_docs)]
             #[allow(unused_mut)]
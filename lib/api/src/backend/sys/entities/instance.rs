@@ -2,8 +2,9 @@
 
 use crate::{
     error::InstantiationError, exports::Exports, imports::Imports, module::Module,
-    store::AsStoreMut, Extern,
+    store::AsStoreMut, vm::VMExtern, Extern, Global, Memory,
 };
+use wasmer_types::ExportIndex;
 use wasmer_vm::{StoreHandle, VMInstance};
 
 use super::store::Store;
@@ -94,6 +95,50 @@ impl Instance {
             })
             .collect::<Exports>()
     }
+
+    /// Returns all the memories and globals of the instance in index order,
+    /// the imported ones first and including the ones that are not exported.
+    #[cfg(feature = "coredump")]
+    pub(crate) fn memories_and_globals(
+        &self,
+        store: &mut impl AsStoreMut,
+    ) -> (Vec<Memory>, Vec<Global>) {
+        let (memories, globals): (Vec<_>, Vec<_>) = {
+            let handle = self._handle.get_mut(store.objects_mut().as_sys_mut());
+            let module = handle.module().clone();
+            let memories = module
+                .memories
+                .keys()
+                .map(|index| handle.lookup_by_declaration(ExportIndex::Memory(index)))
+                .collect();
+            let globals = module
+                .globals
+                .keys()
+                .map(|index| handle.lookup_by_declaration(ExportIndex::Global(index)))
+                .collect();
+            (memories, globals)
+        };
+
+        let memories = memories
+            .into_iter()
+            .filter_map(
+                |export| match Extern::from_vm_extern(store, VMExtern::Sys(export)) {
+                    Extern::Memory(memory) => Some(memory),
+                    _ => None,
+                },
+            )
+            .collect();
+        let globals = globals
+            .into_iter()
+            .filter_map(
+                |export| match Extern::from_vm_extern(store, VMExtern::Sys(export)) {
+                    Extern::Global(global) => Some(global),
+                    _ => None,
+                },
+            )
+            .collect();
+        (memories, globals)
+    }
 }
 
 impl crate::BackendInstance {
@@ -7,7 +7,7 @@ use std::{
 };
 
 use tracing::warn;
-use wasmer_types::{MemoryType, Pages};
+use wasmer_types::{MemoryStyle, MemoryType, Pages};
 use wasmer_vm::{LinearMemory, MemoryError, StoreHandle, ThreadConditionsHandle, VMMemory};
 
 use crate::{
@@ -53,6 +53,12 @@ impl Memory {
             .ty()
     }
 
+    pub(crate) fn style(&self, store: &impl AsStoreRef) -> MemoryStyle {
+        self.handle
+            .get(store.as_store_ref().objects().as_sys())
+            .style()
+    }
+
     pub(crate) fn grow<IntoPages>(
         &self,
         store: &mut impl AsStoreMut,
@@ -0,0 +1,529 @@
+//! Guest core dumps in the [WebAssembly coredump format].
+//!
+//! A core dump is itself a WebAssembly module: the memories and globals of
+//! the instance are stored as regular memory, global and data sections and
+//! the stack of every thread is stored in `corestack` custom sections, so
+//! the dump can be opened by any debugger that understands the format.
+//!
+//! [WebAssembly coredump format]: https://github.com/WebAssembly/tool-conventions/blob/main/Coredump.md
+
+use wasm_encoder::{
+    ConstExpr, CoreDumpInstancesSection, CoreDumpModulesSection, CoreDumpSection,
+    CoreDumpStackSection, CoreDumpValue, DataSection, GlobalSection, MemorySection, Module,
+    ValType,
+};
+use wasmer_types::{
+    entity::EntityRef, FrameInfo, FrameValue, FunctionIndex, GlobalType, MemoryType, Mutability,
+    Type, WASM_MAX_PAGES, WASM_PAGE_SIZE,
+};
+
+use crate::{AsStoreMut, Extern, Global, Instance, Memory, Value};
+
+/// Runs of zeroed memory shorter than this are kept inside a data segment
+/// rather than splitting the segment in two.
+const MIN_ZERO_RUN: usize = 64;
+
+/// A frame on the stack of a thread in a core dump.
+#[derive(Debug, Clone)]
+pub struct CoredumpFrame {
+    /// The function and the instruction that the frame is at.
+    pub info: FrameInfo,
+    /// The locals of the function, starting with its parameters. A local
+    /// whose value is not known is `None`.
+    pub locals: Vec<Option<Value>>,
+    /// The operand stack of the frame, bottom first. A value that is not
+    /// known is `None`.
+    pub stack: Vec<Option<Value>>,
+}
+
+impl From<FrameInfo> for CoredumpFrame {
+    fn from(info: FrameInfo) -> Self {
+        let locals = info
+            .locals()
+            .iter()
+            .map(|value| value.map(frame_value))
+            .collect();
+        Self {
+            info,
+            locals,
+            stack: Vec::new(),
+        }
+    }
+}
+
+/// Builds a core dump of a guest out of the frames of its threads and the
+/// state of its instance.
+#[derive(Debug, Clone, Default)]
+pub struct CoredumpBuilder {
+    executable_name: String,
+    module_name: String,
+    threads: Vec<(String, Vec<CoredumpFrame>)>,
+    memories: Vec<(MemoryType, Vec<u8>)>,
+    memory64: bool,
+    globals: Vec<(GlobalType, Value)>,
+}
+
+impl CoredumpBuilder {
+    /// Creates an empty core dump of the given executable.
+    pub fn new(executable_name: impl Into<String>) -> Self {
+        let executable_name = executable_name.into();
+        Self {
+            module_name: executable_name.clone(),
+            executable_name,
+            ..Default::default()
+        }
+    }
+
+    /// Adds a thread with the frames of its stack, innermost frame first.
+    ///
+    /// The locals are the ones that were recovered when the frames were
+    /// captured, which requires a compiler that records where locals live.
+    /// For the other frames [`CoredumpBuilder::with_instance`] adds a missing
+    /// value for each local that is a parameter of the function. The operand
+    /// stacks are not known.
+    pub fn with_thread(self, name: impl Into<String>, frames: &[FrameInfo]) -> Self {
+        let frames = frames.iter().cloned().map(CoredumpFrame::from).collect();
+        self.with_thread_frames(name, frames)
+    }
+
+    /// Adds a thread with the frames of its stack, innermost frame first,
+    /// along with the values of their locals and operand stacks.
+    pub fn with_thread_frames(
+        mut self,
+        name: impl Into<String>,
+        frames: Vec<CoredumpFrame>,
+    ) -> Self {
+        if let Some(frame) = frames.first() {
+            self.module_name = frame.info.module_name().to_string();
+        }
+        self.threads.push((name.into(), frames));
+        self
+    }
+
+    /// Marks the memories as 64-bit memories.
+    ///
+    /// [`MemoryType`] does not record whether a memory is indexed with 64-bit
+    /// addresses, so this has to be set by whoever knows how the module was
+    /// compiled. A memory that is larger than a 32-bit address space is
+    /// always dumped as a 64-bit memory.
+    pub fn with_memory64(mut self, memory64: bool) -> Self {
+        self.memory64 = memory64;
+        self
+    }
+
+    /// Captures all the memories and globals of an instance in index order,
+    /// including the ones that are imported or not exported.
+    ///
+    /// Frames of the instance's module that have no locals yet get a missing
+    /// value for each parameter of their function.
+    pub fn with_instance(mut self, store: &mut impl AsStoreMut, instance: &Instance) -> Self {
+        let (memories, globals) = match instance.memories_and_globals(store) {
+            Some(externs) => externs,
+            // Backends that don't expose them only give access to the exports
+            None => exported_memories_and_globals(instance),
+        };
+        for memory in memories {
+            let ty = memory.ty(store);
+            let data = memory.view(store).copy_to_vec().unwrap_or_default();
+            self.memories.push((ty, data));
+        }
+        for global in globals {
+            let ty = global.ty(store);
+            let value = global.get(store);
+            self.globals.push((ty, value));
+        }
+
+        let info = instance.module().info();
+        for frame in self.threads.iter_mut().flat_map(|(_, frames)| frames) {
+            if !frame.locals.is_empty() {
+                continue;
+            }
+            let index = FunctionIndex::new(frame.info.func_index() as usize);
+            let params = info
+                .functions
+                .get(index)
+                .and_then(|sig| info.signatures.get(*sig))
+                .map(|ty| ty.params().len())
+                .unwrap_or_default();
+            frame.locals = vec![None; params];
+        }
+        self
+    }
+
+    /// Encodes the core dump.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut module = Module::new();
+        module.section(&CoreDumpSection::new(self.executable_name.as_str()));
+
+        let mut modules = CoreDumpModulesSection::new();
+        modules.module(self.module_name.as_str());
+        module.section(&modules);
+
+        let mut instances = CoreDumpInstancesSection::new();
+        instances.instance(
+            0,
+            0..self.memories.len() as u32,
+            0..self.globals.len() as u32,
+        );
+        module.section(&instances);
+
+        for (name, frames) in &self.threads {
+            let mut stack = CoreDumpStackSection::new(name.as_str());
+            for frame in frames {
+                stack.frame(
+                    0,
+                    frame.info.func_index(),
+                    frame.info.func_offset() as u32,
+                    frame.locals.iter().map(coredump_value),
+                    frame.stack.iter().map(coredump_value),
+                );
+            }
+            module.section(&stack);
+        }
+
+        let mut memories = MemorySection::new();
+        let mut data = DataSection::new();
+        for (index, (ty, contents)) in self.memories.iter().enumerate() {
+            let pages = contents.len() / WASM_PAGE_SIZE;
+            let memory64 = self.memory64 || pages > WASM_MAX_PAGES as usize;
+            memories.memory(wasm_encoder::MemoryType {
+                minimum: pages as u64,
+                maximum: ty.maximum.map(|max| max.0 as u64),
+                memory64,
+                shared: ty.shared,
+                page_size_log2: None,
+            });
+            for (offset, segment) in data_segments(contents) {
+                let offset = match memory64 {
+                    true => ConstExpr::i64_const(offset as i64),
+                    false => ConstExpr::i32_const(offset as i32),
+                };
+                data.active(index as u32, &offset, segment.iter().copied());
+            }
+        }
+        module.section(&memories);
+
+        let mut globals = GlobalSection::new();
+        for (ty, value) in &self.globals {
+            let (val_type, init) = match value {
+                Value::I32(v) => (ValType::I32, ConstExpr::i32_const(*v)),
+                Value::I64(v) => (ValType::I64, ConstExpr::i64_const(*v)),
+                Value::F32(v) => (ValType::F32, ConstExpr::f32_const(*v)),
+                Value::F64(v) => (ValType::F64, ConstExpr::f64_const(*v)),
+                Value::V128(v) => (ValType::V128, ConstExpr::v128_const(*v as i128)),
+                // References can not be serialized so they are dumped as null
+                _ => match ty.ty {
+                    Type::FuncRef => (
+                        ValType::FUNCREF,
+                        ConstExpr::ref_null(wasm_encoder::HeapType::FUNC),
+                    ),
+                    _ => (
+                        ValType::EXTERNREF,
+                        ConstExpr::ref_null(wasm_encoder::HeapType::EXTERN),
+                    ),
+                },
+            };
+            globals.global(
+                wasm_encoder::GlobalType {
+                    val_type,
+                    mutable: ty.mutability == Mutability::Var,
+                    shared: false,
+                },
+                &init,
+            );
+        }
+        module.section(&globals);
+        module.section(&data);
+
+        module.finish()
+    }
+}
+
+/// Returns the memories and globals that an instance exports, in the order
+/// they are exported.
+fn exported_memories_and_globals(instance: &Instance) -> (Vec<Memory>, Vec<Global>) {
+    let mut memories = Vec::new();
+    let mut globals = Vec::new();
+    for (_, export) in instance.exports.iter() {
+        match export {
+            Extern::Memory(memory) => memories.push(memory.clone()),
+            Extern::Global(global) => globals.push(global.clone()),
+            _ => {}
+        }
+    }
+    (memories, globals)
+}
+
+fn frame_value(value: FrameValue) -> Value {
+    match value {
+        FrameValue::I32(v) => Value::I32(v),
+        FrameValue::I64(v) => Value::I64(v),
+        FrameValue::F32(v) => Value::F32(v),
+        FrameValue::F64(v) => Value::F64(v),
+        FrameValue::V128(v) => Value::V128(v),
+    }
+}
+
+/// Converts a local or operand of a frame, the format can only hold numbers
+/// so anything else is missing.
+fn coredump_value(value: &Option<Value>) -> CoreDumpValue {
+    match value {
+        Some(Value::I32(v)) => CoreDumpValue::I32(*v),
+        Some(Value::I64(v)) => CoreDumpValue::I64(*v),
+        Some(Value::F32(v)) => CoreDumpValue::F32(*v),
+        Some(Value::F64(v)) => CoreDumpValue::F64(*v),
+        _ => CoreDumpValue::Missing,
+    }
+}
+
+/// Splits the contents of a memory into the segments that are not zeroed.
+fn data_segments(contents: &[u8]) -> Vec<(usize, &[u8])> {
+    let mut segments = Vec::new();
+    let mut start = None;
+    let mut zeros = 0;
+    for (offset, byte) in contents.iter().enumerate() {
+        match (*byte, start) {
+            (0, Some(begin)) => {
+                zeros += 1;
+                if zeros >= MIN_ZERO_RUN {
+                    segments.push((begin, &contents[begin..offset + 1 - zeros]));
+                    start = None;
+                }
+            }
+            (0, None) => {}
+            (_, None) => {
+                start = Some(offset);
+                zeros = 0;
+            }
+            (_, Some(_)) => zeros = 0,
+        }
+    }
+    if let Some(begin) = start {
+        segments.push((begin, &contents[begin..contents.len() - zeros]));
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmer_types::SourceLoc;
+    use wasmparser::{
+        CoreDumpInstancesSection as Instances, CoreDumpSection as Process,
+        CoreDumpStackSection as Stack, CoreDumpValue as DumpValue, KnownCustom, Operator, Parser,
+        Payload,
+    };
+
+    use super::*;
+
+    /// The function index, code offset, locals and operand stack of a frame.
+    type DecodedFrame = (u32, u32, Vec<Option<Value>>, Vec<Option<Value>>);
+
+    /// The parts of a core dump that the tests look at.
+    #[derive(Debug, Default)]
+    struct Decoded {
+        executable_name: String,
+        instance_memories: Vec<u32>,
+        instance_globals: Vec<u32>,
+        threads: Vec<(String, Vec<DecodedFrame>)>,
+        memories: Vec<(u64, Option<u64>, bool)>,
+        data: Vec<(u32, i64, Vec<u8>)>,
+        globals: Vec<(wasmparser::ValType, bool, Operator<'static>)>,
+    }
+
+    fn decode(dump: &[u8]) -> Decoded {
+        let mut decoded = Decoded::default();
+        for payload in Parser::new(0).parse_all(dump) {
+            match payload.unwrap() {
+                Payload::CustomSection(reader) => match reader.as_known() {
+                    KnownCustom::CoreDump(Process { name }) => {
+                        decoded.executable_name = name.to_string();
+                    }
+                    KnownCustom::CoreDumpInstances(Instances { instances }) => {
+                        assert_eq!(instances.len(), 1);
+                        decoded.instance_memories = instances[0].memories.clone();
+                        decoded.instance_globals = instances[0].globals.clone();
+                    }
+                    KnownCustom::CoreDumpStack(Stack { name, frames }) => {
+                        let frames = frames
+                            .into_iter()
+                            .map(|frame| {
+                                let locals = frame.locals.iter().map(value).collect();
+                                let stack = frame.stack.iter().map(value).collect();
+                                (frame.funcidx, frame.codeoffset, locals, stack)
+                            })
+                            .collect();
+                        decoded.threads.push((name.to_string(), frames));
+                    }
+                    _ => {}
+                },
+                Payload::MemorySection(reader) => {
+                    for memory in reader {
+                        let memory = memory.unwrap();
+                        decoded
+                            .memories
+                            .push((memory.initial, memory.maximum, memory.memory64));
+                    }
+                }
+                Payload::DataSection(reader) => {
+                    for segment in reader {
+                        let segment = segment.unwrap();
+                        let wasmparser::DataKind::Active {
+                            memory_index,
+                            offset_expr,
+                        } = segment.kind
+                        else {
+                            panic!("passive data segment");
+                        };
+                        let offset = match offset_expr.get_operators_reader().read().unwrap() {
+                            Operator::I32Const { value } => value as i64,
+                            Operator::I64Const { value } => value,
+                            op => panic!("unexpected offset {op:?}"),
+                        };
+                        decoded
+                            .data
+                            .push((memory_index, offset, segment.data.to_vec()));
+                    }
+                }
+                Payload::GlobalSection(reader) => {
+                    for global in reader {
+                        let global = global.unwrap();
+                        let init = match global.init_expr.get_operators_reader().read().unwrap() {
+                            Operator::I32Const { value } => Operator::I32Const { value },
+                            Operator::I64Const { value } => Operator::I64Const { value },
+                            op => panic!("unexpected initializer {op:?}"),
+                        };
+                        decoded
+                            .globals
+                            .push((global.ty.content_type, global.ty.mutable, init));
+                    }
+                }
+                _ => {}
+            }
+        }
+        decoded
+    }
+
+    fn value(value: &DumpValue) -> Option<Value> {
+        match value {
+            DumpValue::Missing => None,
+            DumpValue::I32(v) => Some(Value::I32(*v)),
+            DumpValue::I64(v) => Some(Value::I64(*v)),
+            DumpValue::F32(v) => Some(Value::F32(*v)),
+            DumpValue::F64(v) => Some(Value::F64(*v)),
+        }
+    }
+
+    fn frame(func_index: u32, offset: u32) -> FrameInfo {
+        FrameInfo::new(
+            "test".to_string(),
+            func_index,
+            None,
+            SourceLoc::new(0),
+            SourceLoc::new(offset),
+        )
+    }
+
+    #[test]
+    fn test_data_segments() {
+        let mut contents = vec![0u8; 1024];
+        contents[10] = 1;
+        contents[12] = 2;
+        contents[500..503].copy_from_slice(&[3, 4, 5]);
+
+        let segments = data_segments(&contents);
+        assert_eq!(segments, vec![(10, &[1, 0, 2][..]), (500, &[3, 4, 5][..])]);
+        assert!(data_segments(&[0; 128]).is_empty());
+    }
+
+    #[test]
+    fn test_serialize_decodes() {
+        let main = vec![
+            CoredumpFrame {
+                info: frame(3, 12),
+                locals: vec![Some(Value::I32(-1)), None, Some(Value::F64(0.5))],
+                stack: vec![Some(Value::I64(9))],
+            },
+            frame(1, 4).into(),
+        ];
+        let mut builder = CoredumpBuilder::new("test.wasm")
+            .with_thread_frames("main", main)
+            .with_thread("worker", &[frame(2, 7)]);
+        builder.memories.push((MemoryType::new(1, Some(2), false), {
+            let mut data = vec![0; WASM_PAGE_SIZE];
+            data[8] = 42;
+            data
+        }));
+        builder.memories.push((MemoryType::new(1, Some(4), true), {
+            let mut data = vec![0; WASM_PAGE_SIZE];
+            data[100..102].copy_from_slice(&[1, 2]);
+            data
+        }));
+        builder
+            .globals
+            .push((GlobalType::new(Type::I32, Mutability::Var), Value::I32(7)));
+        builder.globals.push((
+            GlobalType::new(Type::I64, Mutability::Const),
+            Value::I64(-3),
+        ));
+
+        let dump = builder.serialize();
+        wasmparser::Validator::new().validate_all(&dump).unwrap();
+        let decoded = decode(&dump);
+
+        assert_eq!(decoded.executable_name, "test.wasm");
+        assert_eq!(decoded.instance_memories, vec![0, 1]);
+        assert_eq!(decoded.instance_globals, vec![0, 1]);
+        assert_eq!(
+            decoded.threads,
+            vec![
+                (
+                    "main".to_string(),
+                    vec![
+                        (
+                            3,
+                            12,
+                            vec![Some(Value::I32(-1)), None, Some(Value::F64(0.5))],
+                            vec![Some(Value::I64(9))]
+                        ),
+                        (1, 4, vec![], vec![]),
+                    ]
+                ),
+                ("worker".to_string(), vec![(2, 7, vec![], vec![])]),
+            ]
+        );
+        assert_eq!(
+            decoded.memories,
+            vec![(1, Some(2), false), (1, Some(4), false)]
+        );
+        assert_eq!(decoded.data, vec![(0, 8, vec![42]), (1, 100, vec![1, 2])]);
+        assert_eq!(
+            decoded.globals,
+            vec![
+                (
+                    wasmparser::ValType::I32,
+                    true,
+                    Operator::I32Const { value: 7 }
+                ),
+                (
+                    wasmparser::ValType::I64,
+                    false,
+                    Operator::I64Const { value: -3 }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_memory64() {
+        let mut builder = CoredumpBuilder::new("test.wasm").with_memory64(true);
+        builder.memories.push((MemoryType::new(1, None, false), {
+            let mut data = vec![0; WASM_PAGE_SIZE];
+            data[16] = 1;
+            data
+        }));
+
+        let decoded = decode(&builder.serialize());
+        assert_eq!(decoded.memories, vec![(1, None, true)]);
+        assert_eq!(decoded.data, vec![(0, 16, vec![1])]);
+    }
+}
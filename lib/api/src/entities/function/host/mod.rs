@@ -1,8 +1,10 @@
 mod imp;
 
+use std::{future::Future, pin::Pin};
+
 use crate::{
     vm::{VMFunctionCallback, VMTrampoline},
-    BackendKind, WasmTypeList,
+    BackendKind, RuntimeError, Value, WasmTypeList,
 };
 
 /// The `HostFunction` trait represents the set of functions that
@@ -22,6 +24,10 @@ where
     fn call_trampoline_address(rt: BackendKind) -> crate::vm::VMTrampoline;
 }
 
+/// The future returned by an asynchronous host function, see
+/// [`Function::new_with_env_async`](crate::Function::new_with_env_async).
+pub type HostFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<Value>, RuntimeError>> + 'a>>;
+
 /// Empty trait to specify the kind of `HostFunction`: With or
 /// without an environment.
 ///
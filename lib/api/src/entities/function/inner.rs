@@ -139,6 +139,36 @@ impl BackendFunction {
         }
     }
 
+    /// Creates a new asynchronous host `Function` (dynamic) with the provided
+    /// signature.
+    ///
+    /// Only the `sys` backend supports asynchronous host functions.
+    #[cfg(feature = "sys")]
+    #[inline]
+    pub fn new_with_env_async<FT, F, T: Send + 'static>(
+        store: &mut impl AsStoreMut,
+        env: &FunctionEnv<T>,
+        ty: FT,
+        func: F,
+    ) -> Self
+    where
+        FT: Into<FunctionType>,
+        F: for<'a> Fn(FunctionEnvMut<'a, T>, &'a [Value]) -> crate::HostFuture<'a>
+            + 'static
+            + Send
+            + Sync,
+    {
+        match &store.as_store_mut().inner.store {
+            crate::BackendStore::Sys(_) => Self::Sys(
+                crate::backend::sys::entities::function::Function::new_with_env_async(
+                    store, env, ty, func,
+                ),
+            ),
+            #[allow(unreachable_patterns)]
+            _ => panic!("async host functions are only supported by the `sys` backend"),
+        }
+    }
+
     /// Creates a new host `Function` from a native function.
     #[inline]
     pub fn new_typed<F, Args, Rets>(store: &mut impl AsStoreMut, func: F) -> Self
@@ -358,6 +388,23 @@ impl BackendFunction {
         })
     }
 
+    /// Call the function asynchronously, see [`crate::Function::call_async`].
+    #[cfg(feature = "sys")]
+    #[inline]
+    pub async fn call_async(
+        &self,
+        store: &mut impl AsStoreMut,
+        params: &[Value],
+    ) -> Result<Box<[Value]>, RuntimeError> {
+        match self {
+            Self::Sys(f) => f.call_async(store, params).await,
+            #[allow(unreachable_patterns)]
+            _ => Err(RuntimeError::new(
+                "asynchronous calls are only supported by the `sys` backend",
+            )),
+        }
+    }
+
     #[doc(hidden)]
     #[allow(missing_docs)]
     #[inline]
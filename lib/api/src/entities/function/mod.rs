@@ -10,6 +10,9 @@ pub use host::*;
 pub(crate) mod env;
 pub use env::*;
 
+#[cfg(feature = "sys")]
+use std::future::Future;
+
 use wasmer_types::{FunctionType, RawValue};
 
 use crate::{
@@ -106,6 +109,81 @@ impl Function {
         Self(BackendFunction::new_with_env(store, env, ty, func))
     }
 
+    /// Creates a new asynchronous host `Function` (dynamic) with the provided
+    /// signature.
+    ///
+    /// The returned future may await I/O or any other asynchronous operation:
+    /// while it is pending, the WebAssembly stack is suspended and control
+    /// goes back to the executor driving [`Function::call_async`] or
+    /// [`TypedFunction::call_async`]. The guest does not need to be
+    /// instrumented (with asyncify or otherwise) for this to work.
+    ///
+    /// Calling an asynchronous host function through a synchronous call (such
+    /// as [`Function::call`]) results in a [`RuntimeError`].
+    ///
+    /// Only the `sys` backend supports asynchronous host functions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmer::{Function, FunctionType, Type, Store, Value};
+    /// # let mut store = Store::default();
+    /// #
+    /// let signature = FunctionType::new(vec![Type::I32], vec![Type::I32]);
+    ///
+    /// let f = Function::new_async(&mut store, &signature, |args| {
+    ///     let value = args[0].unwrap_i32();
+    ///     async move { Ok(vec![Value::I32(value * 2)]) }
+    /// });
+    /// ```
+    #[cfg(feature = "sys")]
+    pub fn new_async<FT, F, Fut>(store: &mut impl AsStoreMut, ty: FT, func: F) -> Self
+    where
+        FT: Into<FunctionType>,
+        F: Fn(&[Value]) -> Fut + 'static + Send + Sync,
+        Fut: Future<Output = Result<Vec<Value>, RuntimeError>> + 'static,
+    {
+        let env = FunctionEnv::new(&mut store.as_store_mut(), ());
+        Self::new_with_env_async(store, &env, ty, move |_env, args| Box::pin(func(args)))
+    }
+
+    /// Creates a new asynchronous host `Function` (dynamic) with the provided
+    /// signature and environment.
+    ///
+    /// The [`FunctionEnvMut`] is handed to the returned future, so the host
+    /// state and the store can be accessed across `.await` points. See
+    /// [`Function::new_async`] for more details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmer::{Function, FunctionEnv, FunctionType, Type, Store, Value};
+    /// # let mut store = Store::default();
+    /// # let env = FunctionEnv::new(&mut store, 0_i32);
+    /// #
+    /// let signature = FunctionType::new(vec![Type::I32], vec![]);
+    ///
+    /// let f = Function::new_with_env_async(&mut store, &env, &signature, |mut env, args| {
+    ///     Box::pin(async move {
+    ///         *env.data_mut() += args[0].unwrap_i32();
+    ///         Ok(vec![])
+    ///     })
+    /// });
+    /// ```
+    #[cfg(feature = "sys")]
+    pub fn new_with_env_async<FT, F, T: Send + 'static>(
+        store: &mut impl AsStoreMut,
+        env: &FunctionEnv<T>,
+        ty: FT,
+        func: F,
+    ) -> Self
+    where
+        FT: Into<FunctionType>,
+        F: for<'a> Fn(FunctionEnvMut<'a, T>, &'a [Value]) -> HostFuture<'a> + 'static + Send + Sync,
+    {
+        Self(BackendFunction::new_with_env_async(store, env, ty, func))
+    }
+
     /// Creates a new host `Function` from a native function.
     pub fn new_typed<F, Args, Rets>(store: &mut impl AsStoreMut, func: F) -> Self
     where
@@ -250,6 +328,28 @@ impl Function {
         self.0.call(store, params)
     }
 
+    /// Call the function asynchronously.
+    ///
+    /// Unlike [`Function::call`], the WebAssembly code runs on a stack that
+    /// can be suspended: whenever an asynchronous host function (see
+    /// [`Function::new_async`]) awaits a pending future, the returned future
+    /// yields back to its executor as well.
+    ///
+    /// The returned future is not `Send`, as the suspended WebAssembly stack
+    /// must be resumed on the thread it was started on. Dropping it before it
+    /// completes abandons the call without running the destructors of the
+    /// host frames that were still on the WebAssembly stack.
+    ///
+    /// Only the `sys` backend supports asynchronous calls.
+    #[cfg(feature = "sys")]
+    pub async fn call_async(
+        &self,
+        store: &mut impl AsStoreMut,
+        params: &[Value],
+    ) -> Result<Box<[Value]>, RuntimeError> {
+        self.0.call_async(store, params).await
+    }
+
     #[doc(hidden)]
     #[allow(missing_docs)]
     pub fn call_raw(
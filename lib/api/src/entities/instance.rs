@@ -1,6 +1,6 @@
 use crate::{
     error::InstantiationError, exports::Exports, imports::Imports, macros::backend::gen_rt_ty,
-    module::Module, store::AsStoreMut, Extern,
+    module::Module, store::AsStoreMut, Extern, Global, Memory,
 };
 
 /// A WebAssembly Instance is a stateful, executable
@@ -168,6 +168,25 @@ impl Instance {
     pub fn module(&self) -> &Module {
         &self.module
     }
+
+    /// Returns all the memories and globals of the instance in index order,
+    /// including the ones that are not exported.
+    ///
+    /// Returns `None` if the backend doesn't expose them.
+    #[allow(unused_variables)]
+    #[cfg(feature = "coredump")]
+
+    pub(crate) fn memories_and_globals(
+        &self,
+        store: &mut impl AsStoreMut,
+    ) -> Option<(Vec<Memory>, Vec<Global>)> {
+        match &self._inner {
+            #[cfg(feature = "sys")]
+            BackendInstance::Sys(s) => Some(s.memories_and_globals(store)),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
 }
 
 impl std::fmt::Debug for Instance {
@@ -1,5 +1,5 @@
 use super::{shared::SharedMemory, view::*};
-use wasmer_types::{MemoryError, MemoryType, Pages};
+use wasmer_types::{MemoryError, MemoryStyle, MemoryType, Pages};
 
 use crate::{
     macros::backend::{gen_rt_ty, match_rt},
@@ -280,6 +280,17 @@ impl BackendMemory {
         })
     }
 
+    /// Returns how the memory is allocated, if the backend exposes it.
+    #[inline]
+    pub fn style(&self, store: &impl AsStoreRef) -> Option<MemoryStyle> {
+        match self {
+            #[cfg(feature = "sys")]
+            Self::Sys(s) => Some(s.style(store)),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+
     /// Attempt to create a new reference to the underlying memory; this new reference can then be
     /// used within a different store (from the same implementer).
     ///
@@ -1,5 +1,5 @@
 pub use shared::SharedMemory;
-use wasmer_types::{MemoryError, MemoryType, Pages};
+use wasmer_types::{MemoryError, MemoryStyle, MemoryType, Pages};
 
 use crate::{
     vm::{VMExtern, VMExternMemory, VMMemory},
@@ -159,6 +159,14 @@ impl Memory {
         self.0.is_from_store(store)
     }
 
+    /// Returns how the memory is allocated by the backend, a
+    /// [`MemoryStyle::Static`] memory never moves when it grows.
+    ///
+    /// Returns `None` if the backend doesn't expose it.
+    pub fn style(&self, store: &impl AsStoreRef) -> Option<MemoryStyle> {
+        self.0.style(store)
+    }
+
     /// Attempt to create a new reference to the underlying memory; this new reference can then be
     /// used within a different store (from the same implementer).
     ///
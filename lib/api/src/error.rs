@@ -168,6 +168,16 @@ impl RuntimeError {
         &self.inner.wasm_trace
     }
 
+    /// Starts a core dump with the frames of this error as the stack of
+    /// the main thread.
+    ///
+    /// Use [`CoredumpBuilder::with_instance`][crate::CoredumpBuilder::with_instance]
+    /// to also capture the memories and globals of the instance that trapped.
+    #[cfg(feature = "coredump")]
+    pub fn to_coredump(&self, executable_name: impl Into<String>) -> crate::CoredumpBuilder {
+        crate::CoredumpBuilder::new(executable_name).with_thread("main", self.trace())
+    }
+
     /// Returns trap code, if it's a Trap
     pub fn to_trap(self) -> Option<TrapCode> {
         self.inner.trap_code
@@ -235,6 +245,28 @@ impl std::fmt::Display for RuntimeError {
                 func_index,
                 frame.module_offset()
             )?;
+            let symbols = frame.symbols();
+            for (i, symbol) in symbols.iter().enumerate() {
+                let Some(file) = symbol.file() else {
+                    continue;
+                };
+                writeln!(f)?;
+                write!(f, "        ")?;
+                // Every symbol but the last one is a function that got inlined
+                // into the enclosing wasm function.
+                if i + 1 < symbols.len() {
+                    if let Some(function) = symbol.function() {
+                        write!(f, "inlined {function} ")?;
+                    }
+                }
+                write!(f, "at {file}")?;
+                if let Some(line) = symbol.line() {
+                    write!(f, ":{line}")?;
+                    if let Some(column) = symbol.column() {
+                        write!(f, ":{column}")?;
+                    }
+                }
+            }
         }
         Ok(())
     }
@@ -78,6 +78,23 @@ macro_rules! impl_native_traits {
                 }
             }
 
+            /// Call the typed func asynchronously and return results.
+            ///
+            /// See [`Function::call_async`] for details, only the `sys` backend
+            /// supports asynchronous calls.
+            #[cfg(feature = "sys")]
+            #[allow(clippy::too_many_arguments)]
+            pub async fn call_async(&self, store: &mut impl AsStoreMut, $( $x: $x, )* ) -> Result<Rets, RuntimeError> where $( $x: FromToNativeWasmType, )*
+            {
+                match store.as_store_mut().inner.store {
+                    BackendStore::Sys(_) => self.call_async_sys(store, $( $x ),*).await,
+                    #[allow(unreachable_patterns)]
+                    _ => Err(RuntimeError::new(
+                        "asynchronous calls are only supported by the `sys` backend",
+                    )),
+                }
+            }
+
             #[doc(hidden)]
             #[allow(missing_docs)]
             #[allow(unused_mut)]
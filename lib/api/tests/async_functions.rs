@@ -0,0 +1,252 @@
+#![cfg(feature = "sys")]
+
+use std::{
+    future::Future,
+    pin::pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+};
+
+use anyhow::Result;
+use wasmer::*;
+
+/// Wakes the thread that is blocked on a future.
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Runs a future to completion, returning how many times it was pending.
+fn block_on<F: Future>(future: F) -> (F::Output, usize) {
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    let mut pending = 0;
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return (output, pending),
+            Poll::Pending => {
+                pending += 1;
+                thread::park();
+            }
+        }
+    }
+}
+
+/// A future that is pending a given number of times before completing.
+struct YieldNow(usize);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 == 0 {
+            return Poll::Ready(());
+        }
+        self.0 -= 1;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+const WAT: &str = r#"
+    (module
+        (import "host" "double" (func $double (param i32) (result i32)))
+        (func (export "quadruple") (param i32) (result i32)
+            (call $double (call $double (local.get 0))))
+    )
+"#;
+
+fn instantiate(store: &mut Store, double: Function) -> Result<Instance> {
+    let module = Module::new(&store, WAT)?;
+    let imports = imports! {
+        "host" => {
+            "double" => double,
+        },
+    };
+    Ok(Instance::new(store, &module, &imports)?)
+}
+
+#[test]
+fn async_host_function_suspends_the_call() -> Result<()> {
+    let mut store = Store::default();
+    let double = Function::new_async(
+        &mut store,
+        FunctionType::new(vec![Type::I32], vec![Type::I32]),
+        |args| {
+            let value = args[0].unwrap_i32();
+            async move {
+                YieldNow(2).await;
+                Ok(vec![Value::I32(value * 2)])
+            }
+        },
+    );
+    let instance = instantiate(&mut store, double)?;
+    let quadruple = instance.exports.get_function("quadruple")?;
+
+    let (result, pending) = block_on(quadruple.call_async(&mut store, &[Value::I32(5)]));
+    assert_eq!(&*result?, &[Value::I32(20)]);
+    assert_eq!(pending, 4);
+
+    let typed = quadruple.typed::<i32, i32>(&store)?;
+    let (result, pending) = block_on(typed.call_async(&mut store, 3));
+    assert_eq!(result?, 12);
+    assert_eq!(pending, 4);
+
+    Ok(())
+}
+
+#[test]
+fn async_host_function_with_env() -> Result<()> {
+    let mut store = Store::default();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let env = FunctionEnv::new(&mut store, calls.clone());
+    let double = Function::new_with_env_async(
+        &mut store,
+        &env,
+        FunctionType::new(vec![Type::I32], vec![Type::I32]),
+        |env, args| {
+            Box::pin(async move {
+                YieldNow(1).await;
+                env.data().fetch_add(1, Ordering::SeqCst);
+                Ok(vec![Value::I32(args[0].unwrap_i32() * 2)])
+            })
+        },
+    );
+    let instance = instantiate(&mut store, double)?;
+    let quadruple = instance.exports.get_function("quadruple")?;
+
+    let (result, _) = block_on(quadruple.call_async(&mut store, &[Value::I32(1)]));
+    assert_eq!(&*result?, &[Value::I32(4)]);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    Ok(())
+}
+
+#[test]
+fn async_host_function_errors_are_traps() -> Result<()> {
+    let mut store = Store::default();
+    let double = Function::new_async(
+        &mut store,
+        FunctionType::new(vec![Type::I32], vec![Type::I32]),
+        |_args| async move {
+            YieldNow(1).await;
+            Err(RuntimeError::new("boom"))
+        },
+    );
+    let instance = instantiate(&mut store, double)?;
+    let quadruple = instance.exports.get_function("quadruple")?;
+
+    let (result, _) = block_on(quadruple.call_async(&mut store, &[Value::I32(1)]));
+    assert_eq!(result.unwrap_err().message(), "boom");
+
+    // The store can still be used afterwards.
+    let (result, _) = block_on(quadruple.call_async(&mut store, &[Value::I32(2)]));
+    assert_eq!(result.unwrap_err().message(), "boom");
+
+    Ok(())
+}
+
+#[test]
+fn async_host_function_requires_call_async() -> Result<()> {
+    let mut store = Store::default();
+    let double = Function::new_async(
+        &mut store,
+        FunctionType::new(vec![Type::I32], vec![Type::I32]),
+        |args| {
+            let value = args[0].unwrap_i32();
+            async move { Ok(vec![Value::I32(value * 2)]) }
+        },
+    );
+    let instance = instantiate(&mut store, double)?;
+    let quadruple = instance.exports.get_function("quadruple")?;
+
+    let err = quadruple.call(&mut store, &[Value::I32(1)]).unwrap_err();
+    assert!(err.message().contains("call_async"), "{err}");
+
+    Ok(())
+}
+
+#[test]
+fn dropping_a_suspended_call() -> Result<()> {
+    let mut store = Store::default();
+    let double = Function::new_async(
+        &mut store,
+        FunctionType::new(vec![Type::I32], vec![Type::I32]),
+        |args| {
+            let value = args[0].unwrap_i32();
+            async move {
+                YieldNow(1).await;
+                Ok(vec![Value::I32(value * 2)])
+            }
+        },
+    );
+    let instance = instantiate(&mut store, double)?;
+    let quadruple = instance.exports.get_function("quadruple")?;
+
+    {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut call = Box::pin(quadruple.call_async(&mut store, &[Value::I32(1)]));
+        assert!(call.as_mut().poll(&mut cx).is_pending());
+    }
+
+    let (result, _) = block_on(quadruple.call_async(&mut store, &[Value::I32(1)]));
+    assert_eq!(&*result?, &[Value::I32(4)]);
+
+    Ok(())
+}
+
+#[test]
+fn trap_after_nested_sync_call() -> Result<()> {
+    let mut store = Store::default();
+    let env = FunctionEnv::new(&mut store, None::<Function>);
+    let nested = Function::new_with_env_async(
+        &mut store,
+        &env,
+        FunctionType::new(vec![], vec![]),
+        |mut env, _args| {
+            Box::pin(async move {
+                // Call back into Wasm synchronously, then suspend the outer
+                // call again.
+                let (callee, mut store) = env.data_and_store_mut();
+                callee.clone().unwrap().call(&mut store, &[])?;
+                YieldNow(1).await;
+                Ok(vec![])
+            })
+        },
+    );
+
+    let module = Module::new(
+        &store,
+        r#"
+        (module
+            (import "host" "nested" (func $nested))
+            (func (export "noop"))
+            (func (export "run") call $nested unreachable)
+        )
+    "#,
+    )?;
+    let imports = imports! {
+        "host" => {
+            "nested" => nested,
+        },
+    };
+    let instance = Instance::new(&mut store, &module, &imports)?;
+    *env.as_mut(&mut store) = Some(instance.exports.get_function("noop")?.clone());
+    let run = instance.exports.get_function("run")?;
+
+    let (result, pending) = block_on(run.call_async(&mut store, &[]));
+    assert_eq!(pending, 1);
+    let err = result.unwrap_err();
+    assert!(err.message().contains("unreachable"), "{err}");
+
+    Ok(())
+}
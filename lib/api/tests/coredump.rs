@@ -0,0 +1,67 @@
+#![cfg(all(feature = "coredump", feature = "cranelift"))]
+
+use wasmer::{sys::Cranelift, *};
+use wasmparser::{CoreDumpStackSection, CoreDumpValue, KnownCustom, Parser, Payload};
+
+/// Decodes the function index and the locals of the frames of the first
+/// thread of a core dump.
+fn frames(dump: &[u8]) -> Vec<(u32, Vec<CoreDumpValue>)> {
+    for payload in Parser::new(0).parse_all(dump) {
+        if let Payload::CustomSection(reader) = payload.unwrap() {
+            if let KnownCustom::CoreDumpStack(CoreDumpStackSection { frames, .. }) =
+                reader.as_known()
+            {
+                return frames
+                    .into_iter()
+                    .map(|frame| (frame.funcidx, frame.locals))
+                    .collect();
+            }
+        }
+    }
+    panic!("The core dump has no stack");
+}
+
+#[test]
+fn trapping_frame_locals_are_dumped() {
+    let mut store = Store::new(Cranelift::default());
+    let module = Module::new(
+        &store,
+        r#"
+(module
+  (memory 1)
+  (func $inner (param $x i32) (result i32)
+    (local $y i32)
+    (local.set $y (i32.mul (local.get $x) (i32.const 3)))
+    ;; Out of bounds, $x is still needed to compute the address
+    (i32.add (i32.load (local.get $x)) (local.get $y)))
+  (func (export "run") (param $a i32) (result i32)
+    (call $inner (i32.add (local.get $a) (i32.const 0x10000)))))
+"#,
+    )
+    .unwrap();
+    let instance = Instance::new(&mut store, &module, &imports! {}).unwrap();
+    let run = instance
+        .exports
+        .get_typed_function::<i32, i32>(&store, "run")
+        .unwrap();
+
+    let error = run.call(&mut store, 4).unwrap_err();
+    let trace = error.trace();
+    assert_eq!(trace[0].func_index(), 0);
+    assert_eq!(trace[0].locals().len(), 2);
+    assert_eq!(trace[0].locals()[0], Some(FrameValue::I32(0x10004)));
+
+    let dump = error
+        .to_coredump("test.wasm")
+        .with_instance(&mut store, &instance)
+        .serialize();
+    let frames = frames(&dump);
+    assert_eq!(frames.len(), 2);
+    let (func, locals) = &frames[0];
+    assert_eq!(*func, 0);
+    assert_eq!(locals.len(), 2);
+    assert!(matches!(locals[0], CoreDumpValue::I32(0x10004)));
+    // The outer frame has as many locals as its function
+    assert_eq!(frames[1].0, 1);
+    assert_eq!(frames[1].1.len(), 1);
+}
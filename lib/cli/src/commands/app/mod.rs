@@ -10,6 +10,7 @@ pub mod list;
 pub mod logs;
 pub mod purge_cache;
 pub mod regions;
+pub mod run_local;
 pub mod secrets;
 pub mod version;
 pub mod volumes;
@@ -20,6 +21,7 @@ use crate::commands::AsyncCliCommand;
 
 /// Manage Wasmer Deploy apps.
 #[derive(clap::Subcommand, Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum CmdApp {
     Deploy(deploy::CmdAppDeploy),
     Create(create::CmdAppCreate),
@@ -39,6 +41,7 @@ pub enum CmdApp {
     Volume(volumes::CmdAppVolumes),
     #[clap(subcommand, alias = "deployments")]
     Deployment(deployments::CmdAppDeployment),
+    RunLocal(run_local::CmdAppRunLocal),
 }
 
 #[async_trait::async_trait]
@@ -69,6 +72,7 @@ impl AsyncCliCommand for CmdApp {
             Self::Region(cmd) => cmd.run_async().await,
             Self::Volume(cmd) => cmd.run_async().await,
             Self::Deployment(cmd) => cmd.run_async().await,
+            Self::RunLocal(cmd) => cmd.run_async().await,
         }
     }
 }
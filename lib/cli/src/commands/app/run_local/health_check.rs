@@ -0,0 +1,152 @@
+use std::{net::SocketAddr, time::Duration};
+
+use anyhow::Context;
+use wasmer_config::app::{HealthCheckHttpV1, HealthCheckV1, HttpRequest};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A health check from app.yaml, with its defaults applied.
+#[derive(Debug, Clone)]
+pub(super) struct HealthCheck {
+    request: HttpRequest,
+    interval: Duration,
+    unhealthy_threshold: u32,
+    healthy_threshold: u32,
+}
+
+impl HealthCheck {
+    pub(super) fn from_config(check: &HealthCheckV1) -> Result<Self, anyhow::Error> {
+        let HealthCheckV1::Http(HealthCheckHttpV1 {
+            request,
+            interval,
+            unhealthy_threshold,
+            healthy_threshold,
+        }) = check;
+
+        let interval = match interval {
+            Some(interval) => humantime::parse_duration(interval)
+                .with_context(|| format!("invalid health check interval \"{interval}\""))?,
+            None => DEFAULT_INTERVAL,
+        };
+
+        Ok(HealthCheck {
+            request: request.clone(),
+            interval,
+            unhealthy_threshold: unhealthy_threshold.unwrap_or(1).max(1),
+            healthy_threshold: healthy_threshold.unwrap_or(1).max(1),
+        })
+    }
+
+    /// Probe the app every interval, reporting whenever it becomes healthy or
+    /// unhealthy.
+    pub(super) async fn monitor(self, client: reqwest::Client, backend: SocketAddr) {
+        let name = format!(
+            "{} {}",
+            self.request.method.as_deref().unwrap_or("GET"),
+            self.request.path
+        );
+        let mut status = HealthStatus::new(self.healthy_threshold, self.unhealthy_threshold);
+        let mut interval = tokio::time::interval(self.interval);
+
+        loop {
+            interval.tick().await;
+
+            let result = super::http::send(&client, backend, &self.request).await;
+            match status.record(result.is_ok()) {
+                Some(true) => eprintln!("Health check \"{name}\" is healthy"),
+                Some(false) => {
+                    let error = result.err().map(|e| format!("{e:#}")).unwrap_or_default();
+                    eprintln!("Health check \"{name}\" is unhealthy: {error}");
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+/// Tracks consecutive probe results to decide when an app changes between
+/// healthy and unhealthy.
+#[derive(Debug)]
+struct HealthStatus {
+    healthy: Option<bool>,
+    successes: u32,
+    failures: u32,
+    healthy_threshold: u32,
+    unhealthy_threshold: u32,
+}
+
+impl HealthStatus {
+    fn new(healthy_threshold: u32, unhealthy_threshold: u32) -> Self {
+        HealthStatus {
+            healthy: None,
+            successes: 0,
+            failures: 0,
+            healthy_threshold,
+            unhealthy_threshold,
+        }
+    }
+
+    /// Record the outcome of a probe, returning the new state if it changed.
+    fn record(&mut self, success: bool) -> Option<bool> {
+        if success {
+            self.successes += 1;
+            self.failures = 0;
+        } else {
+            self.failures += 1;
+            self.successes = 0;
+        }
+
+        let healthy = if self.successes >= self.healthy_threshold {
+            true
+        } else if self.failures >= self.unhealthy_threshold {
+            false
+        } else {
+            return None;
+        };
+
+        if self.healthy == Some(healthy) {
+            None
+        } else {
+            self.healthy = Some(healthy);
+            Some(healthy)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thresholds_delay_transitions() {
+        let mut status = HealthStatus::new(2, 3);
+
+        assert_eq!(status.record(true), None);
+        assert_eq!(status.record(true), Some(true));
+        assert_eq!(status.record(true), None);
+
+        assert_eq!(status.record(false), None);
+        assert_eq!(status.record(false), None);
+        assert_eq!(status.record(true), None);
+        assert_eq!(status.record(false), None);
+        assert_eq!(status.record(false), None);
+        assert_eq!(status.record(false), Some(false));
+
+        assert_eq!(status.record(true), None);
+        assert_eq!(status.record(true), Some(true));
+    }
+
+    #[test]
+    fn defaults() {
+        let check: HealthCheckV1 = serde_yaml::from_str("!http\npath: /healthz\n").unwrap();
+        let check = HealthCheck::from_config(&check).unwrap();
+        assert_eq!(check.interval, DEFAULT_INTERVAL);
+        assert_eq!(check.healthy_threshold, 1);
+        assert_eq!(check.unhealthy_threshold, 1);
+
+        let check: HealthCheckV1 =
+            serde_yaml::from_str("!http\npath: /healthz\ninterval: 5s\n").unwrap();
+        let check = HealthCheck::from_config(&check).unwrap();
+        assert_eq!(check.interval, Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,105 @@
+use std::net::SocketAddr;
+
+use anyhow::{ensure, Context};
+use wasmer_config::app::{HttpRequest, HttpRequestExpect};
+
+/// Send a request described in app.yaml to the app served at `backend` and
+/// check the response against the request's expectations.
+pub(super) async fn send(
+    client: &reqwest::Client,
+    backend: SocketAddr,
+    request: &HttpRequest,
+) -> Result<(), anyhow::Error> {
+    let method = match request.method.as_deref() {
+        Some(method) => method
+            .parse::<reqwest::Method>()
+            .with_context(|| format!("invalid HTTP method \"{method}\""))?,
+        None => reqwest::Method::GET,
+    };
+    let path = request.path.trim_start_matches('/');
+    let mut builder = client.request(method, format!("http://{backend}/{path}"));
+
+    for header in request.headers.iter().flatten() {
+        builder = builder.header(&header.name, &header.value);
+    }
+    if let Some(body) = &request.body {
+        builder = builder.body(body.clone());
+    }
+    if let Some(timeout) = &request.timeout {
+        builder = builder.timeout(timeout.as_duration());
+    }
+
+    let response = builder.send().await?;
+    let status = response.status().as_u16();
+    let body = response.text().await?;
+
+    check_response(request.expect.as_ref(), status, &body)
+}
+
+/// Check a response against a request's expectations.
+///
+/// Without explicit status codes, any `2xx` status is accepted.
+fn check_response(
+    expect: Option<&HttpRequestExpect>,
+    status: u16,
+    body: &str,
+) -> Result<(), anyhow::Error> {
+    match expect.and_then(|expect| expect.status_codes.as_deref()) {
+        Some(codes) => ensure!(
+            codes.contains(&status),
+            "unexpected status code {status}, expected one of {codes:?}"
+        ),
+        None => ensure!(
+            (200..300).contains(&status),
+            "unexpected status code {status}"
+        ),
+    }
+
+    let Some(expect) = expect else {
+        return Ok(());
+    };
+
+    if let Some(needle) = &expect.body_includes {
+        ensure!(
+            body.contains(needle.as_str()),
+            "the response body doesn't include {needle:?}"
+        );
+    }
+    if let Some(pattern) = &expect.body_regex {
+        let regex = regex::Regex::new(pattern)
+            .with_context(|| format!("invalid body regex {pattern:?}"))?;
+        ensure!(
+            regex.is_match(body),
+            "the response body doesn't match {pattern:?}"
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_expectations_accept_success() {
+        assert!(check_response(None, 200, "").is_ok());
+        assert!(check_response(None, 204, "").is_ok());
+        assert!(check_response(None, 301, "").is_err());
+        assert!(check_response(None, 500, "").is_err());
+    }
+
+    #[test]
+    fn explicit_expectations() {
+        let expect = HttpRequestExpect {
+            status_codes: Some(vec![200, 404]),
+            body_includes: Some("ok".to_string()),
+            body_regex: Some("^status: [a-z]+$".to_string()),
+        };
+
+        assert!(check_response(Some(&expect), 404, "status: ok").is_ok());
+        assert!(check_response(Some(&expect), 201, "status: ok").is_err());
+        assert!(check_response(Some(&expect), 200, "status: fine").is_err());
+        assert!(check_response(Some(&expect), 200, "status: ok!").is_err());
+    }
+}
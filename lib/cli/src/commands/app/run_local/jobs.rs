@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use chrono::Utc;
+use wasmer_config::app::{CronExpression, Job, JobActionCase};
+
+use super::LocalApp;
+
+/// Run a job, retrying it as many times as it allows.
+pub(super) async fn run(app: &LocalApp, job: &Job) -> Result<(), anyhow::Error> {
+    let attempts = job.retries.unwrap_or(0).saturating_add(1);
+    let mut attempt = 1;
+
+    loop {
+        match run_once(app, job).await {
+            Ok(()) => return Ok(()),
+            Err(error) if attempt < attempts => {
+                eprintln!(
+                    "Job \"{}\" failed (attempt {attempt}/{attempts}): {error:#}",
+                    job.name()
+                );
+                attempt += 1;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+async fn run_once(app: &LocalApp, job: &Job) -> Result<(), anyhow::Error> {
+    let action = async {
+        match job.action() {
+            JobActionCase::Fetch(request) => {
+                super::http::send(&app.client, app.backend, request).await
+            }
+            JobActionCase::Execute(executable) => app.execute(executable).await,
+        }
+    };
+
+    match &job.timeout {
+        // Note: an executable that times out keeps running in the background
+        // because runners can't be interrupted.
+        Some(timeout) => tokio::time::timeout(timeout.as_duration(), action)
+            .await
+            .map_err(|_| anyhow!("timed out after {timeout}"))?,
+        None => action.await,
+    }
+}
+
+/// Run a job every time its cron expression fires.
+pub(super) async fn schedule(app: Arc<LocalApp>, job: Job, cron: CronExpression) {
+    let cron = saffron::Cron::new(cron.cron);
+
+    loop {
+        let now = Utc::now();
+        let Some(due) = cron.next_after(now) else {
+            eprintln!("Job \"{}\" is never scheduled", job.name());
+            return;
+        };
+        tokio::time::sleep((due - now).to_std().unwrap_or_default()).await;
+
+        if let Some(max_drift) = &job.max_schedule_drift {
+            let drift = (Utc::now() - due).to_std().unwrap_or_default();
+            if drift > max_drift.as_duration() {
+                eprintln!(
+                    "Skipping job \"{}\" because it is past its due time by {}",
+                    job.name(),
+                    humantime::format_duration(drift)
+                );
+                continue;
+            }
+        }
+
+        eprintln!("Running job \"{}\"", job.name());
+        match run(&app, &job).await {
+            Ok(()) => eprintln!("Job \"{}\" finished", job.name()),
+            Err(error) => eprintln!("Job \"{}\" failed: {error:#}", job.name()),
+        }
+    }
+}
@@ -0,0 +1,291 @@
+//! Run an app locally, emulating its app.yaml configuration.
+
+mod health_check;
+mod http;
+mod jobs;
+mod proxy;
+
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{ensure, Context};
+use tokio::{sync::oneshot, task::JoinSet};
+use wasmer_config::app::{AppConfigV1, AppVolume, ExecutableJob, JobTrigger};
+use wasmer_wasix::runners::MappedDirectory;
+
+use self::health_check::HealthCheck;
+use crate::{
+    backend::RuntimeOptions,
+    commands::{
+        run::{PackageSource, Run, Wasi},
+        AsyncCliCommand,
+    },
+    config::WasmerEnv,
+    logging::Output,
+};
+
+/// Run an app locally, emulating its app.yaml configuration.
+///
+/// The app's package is started with the runner it would get when deployed
+/// and served behind a proxy that applies the app's redirects. Volumes are
+/// mounted from a local directory, health checks are probed and jobs are run
+/// on their triggers.
+#[derive(clap::Parser, Debug)]
+pub struct CmdAppRunLocal {
+    #[clap(flatten)]
+    env: WasmerEnv,
+    #[clap(flatten)]
+    rt: RuntimeOptions,
+    #[clap(flatten)]
+    wasi: Wasi,
+    /// The address to serve the app on.
+    #[clap(long, short, default_value_t = ([127, 0, 0, 1], 8000).into())]
+    addr: SocketAddr,
+    /// The port the app itself listens on.
+    ///
+    /// WCGI, DCGI and DProxy servers are bound to it automatically, WASI
+    /// programs that run their own HTTP server must listen on it.
+    #[clap(long, default_value_t = 8080)]
+    app_port: u16,
+    /// Directory holding the contents of the app's volumes.
+    ///
+    /// Defaults to `.wasmer/volumes` next to the app.yaml file.
+    #[clap(long)]
+    volumes_dir: Option<PathBuf>,
+    /// Don't run the app's jobs.
+    #[clap(long)]
+    no_jobs: bool,
+    /// The app.yaml file, or the directory containing it.
+    #[clap(default_value = ".")]
+    path: PathBuf,
+}
+
+#[async_trait::async_trait]
+impl AsyncCliCommand for CmdAppRunLocal {
+    type Output = ();
+
+    async fn run_async(self) -> Result<(), anyhow::Error> {
+        let config_path = if self.path.is_dir() {
+            self.path.join(AppConfigV1::CANONICAL_FILE_NAME)
+        } else {
+            self.path.clone()
+        };
+        let contents = std::fs::read_to_string(&config_path)
+            .with_context(|| format!("Unable to read \"{}\"", config_path.display()))?;
+        let config = AppConfigV1::parse_yaml(&contents)
+            .with_context(|| format!("Unable to parse \"{}\"", config_path.display()))?;
+
+        let base_dir = config_path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."))
+            .canonicalize()?;
+        let volumes_dir = match self.volumes_dir {
+            Some(dir) => std::path::absolute(dir)?,
+            None => base_dir.join(".wasmer").join("volumes"),
+        };
+
+        let health_checks = config
+            .health_checks
+            .iter()
+            .flatten()
+            .map(HealthCheck::from_config)
+            .collect::<Result<Vec<_>, _>>()?;
+        let jobs = if self.no_jobs {
+            Vec::new()
+        } else {
+            config.jobs.clone().unwrap_or_default()
+        };
+        if config.scheduled_tasks.is_some() {
+            eprintln!("Warning: \"scheduled_tasks\" are ignored, use \"jobs\" instead");
+        }
+
+        let addr = self.addr;
+        let mut wasi = self.wasi;
+        // Deployed apps can always access the network, and nobody is sitting
+        // at their terminal.
+        if wasi.networking.is_none() {
+            wasi.networking = Some(None);
+        }
+        wasi.no_tty = true;
+        wasi.env_vars.extend(config.env.clone());
+
+        let mut app = LocalApp {
+            env: self.env,
+            rt: self.rt,
+            wasi,
+            package: PackageSource::from_config(&config.package, &base_dir)?,
+            base_dir,
+            volumes_dir,
+            backend: ([127, 0, 0, 1], self.app_port).into(),
+            client: reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()?,
+        };
+        let volumes = app.volumes(config.volumes.iter().flatten())?;
+        app.wasi.mapped_dirs.extend(volumes);
+        let app = Arc::new(app);
+
+        for job in &jobs {
+            if let JobTrigger::PreDeployment = job.trigger() {
+                eprintln!("Running pre-deployment job \"{}\"", job.name());
+                jobs::run(&app, job)
+                    .await
+                    .with_context(|| format!("Pre-deployment job \"{}\" failed", job.name()))?;
+            }
+        }
+
+        let server = app.spawn(Run::for_app(
+            app.env.clone(),
+            app.rt.clone(),
+            app.wasi.clone(),
+            app.package.clone(),
+            None,
+            config.cli_args.clone().unwrap_or_default(),
+            app.backend,
+        ));
+        let force_https = config
+            .redirect
+            .as_ref()
+            .and_then(|redirect| redirect.force_https)
+            .unwrap_or(false);
+        let proxy = proxy::serve(addr, app.backend, force_https);
+
+        // Like on Edge, health checks and jobs only start once the app is up
+        let deployment = tokio::spawn({
+            let app = app.clone();
+            async move {
+                app.wait_until_ready().await;
+                eprintln!("App running at http://{addr}/");
+
+                let mut tasks = JoinSet::new();
+                for check in health_checks {
+                    tasks.spawn(check.monitor(app.client.clone(), app.backend));
+                }
+                for job in &jobs {
+                    if let JobTrigger::Cron(cron) = job.trigger() {
+                        tasks.spawn(jobs::schedule(app.clone(), job.clone(), cron.clone()));
+                    }
+                }
+                for job in &jobs {
+                    if let JobTrigger::PostDeployment = job.trigger() {
+                        eprintln!("Running post-deployment job \"{}\"", job.name());
+                        if let Err(error) = jobs::run(&app, job).await {
+                            eprintln!("Job \"{}\" failed: {error:#}", job.name());
+                        }
+                    }
+                }
+
+                while tasks.join_next().await.is_some() {}
+            }
+        });
+
+        let result = tokio::select! {
+            result = server => result.context("The app was interrupted").and_then(|result| {
+                result?;
+                eprintln!("The app exited");
+                Ok(())
+            }),
+            result = proxy => result,
+        };
+        deployment.abort();
+
+        result
+    }
+}
+
+/// Everything needed to run commands from an app's package.
+#[derive(Debug)]
+struct LocalApp {
+    env: WasmerEnv,
+    rt: RuntimeOptions,
+    wasi: Wasi,
+    package: PackageSource,
+    base_dir: PathBuf,
+    volumes_dir: PathBuf,
+    /// The address the app's server listens on.
+    backend: SocketAddr,
+    client: reqwest::Client,
+}
+
+impl LocalApp {
+    /// Map each volume to a directory inside [`LocalApp::volumes_dir`].
+    fn volumes<'a>(
+        &self,
+        volumes: impl IntoIterator<Item = &'a AppVolume>,
+    ) -> Result<Vec<MappedDirectory>, anyhow::Error> {
+        volumes
+            .into_iter()
+            .map(|volume| {
+                ensure!(
+                    !volume.name.is_empty()
+                        && !volume.name.contains(['/', '\\'])
+                        && volume.name != "."
+                        && volume.name != "..",
+                    "invalid volume name \"{}\"",
+                    volume.name
+                );
+                let host = self.volumes_dir.join(&volume.name);
+                std::fs::create_dir_all(&host)
+                    .with_context(|| format!("Unable to create the \"{}\" volume", volume.name))?;
+
+                Ok(MappedDirectory {
+                    host,
+                    guest: volume.mount.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Run an executable job to completion.
+    async fn execute(&self, job: &ExecutableJob) -> Result<(), anyhow::Error> {
+        let package = match job.package() {
+            Some(package) => PackageSource::from_config(package, &self.base_dir)?,
+            None => self.package.clone(),
+        };
+
+        let mut wasi = self.wasi.clone();
+        wasi.env_vars.extend(job.env.clone().into_iter().flatten());
+        let volumes = self.volumes(job.volumes.iter().flatten())?;
+        wasi.mapped_dirs.extend(volumes);
+
+        let run = Run::for_app(
+            self.env.clone(),
+            self.rt.clone(),
+            wasi,
+            package,
+            job.command().map(String::from),
+            job.cli_args().map(<[String]>::to_vec).unwrap_or_default(),
+            self.backend,
+        );
+
+        self.spawn(run).await.context("The job was interrupted")?
+    }
+
+    /// Start a [`Run`] on its own thread, since runners block until the
+    /// program exits.
+    fn spawn(&self, run: Run) -> oneshot::Receiver<Result<(), anyhow::Error>> {
+        let (sender, receiver) = oneshot::channel();
+
+        std::thread::spawn(move || {
+            let output = Output {
+                quiet: true,
+                ..Default::default()
+            };
+            let _ = sender.send(run.execute_inner(output));
+        });
+
+        receiver
+    }
+
+    /// Wait until the app accepts connections.
+    async fn wait_until_ready(&self) {
+        while tokio::net::TcpStream::connect(self.backend).await.is_err() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
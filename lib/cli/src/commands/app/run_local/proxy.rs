@@ -0,0 +1,165 @@
+use std::net::SocketAddr;
+
+use anyhow::Context;
+use bytes::Bytes;
+use http::{header, HeaderMap, HeaderValue, Request, Response, StatusCode};
+use http_body_util::{BodyExt, Full};
+use hyper::{body::Incoming, server::conn::http1::Builder, service::service_fn};
+use hyper_util::rt::tokio::TokioIo;
+use tokio::net::TcpListener;
+
+/// Headers that only apply to a single connection and must not be forwarded.
+const HOP_BY_HOP_HEADERS: &[header::HeaderName] = &[
+    header::CONNECTION,
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// Serve the app on `addr` by forwarding requests to `backend`, applying the
+/// app's redirect rules like the Edge gateway does.
+pub(super) async fn serve(
+    addr: SocketAddr,
+    backend: SocketAddr,
+    force_https: bool,
+) -> Result<(), anyhow::Error> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Unable to listen on {addr}"))?;
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .no_gzip()
+        .build()?;
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let client = client.clone();
+
+        tokio::spawn(async move {
+            let service = service_fn(move |req| forward(client.clone(), backend, force_https, req));
+            if let Err(error) = Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!(%error, "Proxy connection failed");
+            }
+        });
+    }
+}
+
+async fn forward(
+    client: reqwest::Client,
+    backend: SocketAddr,
+    force_https: bool,
+    req: Request<Incoming>,
+) -> Result<Response<Full<Bytes>>, anyhow::Error> {
+    if force_https {
+        if let Some(location) = https_redirect(&req) {
+            return Ok(Response::builder()
+                .status(StatusCode::PERMANENT_REDIRECT)
+                .header(header::LOCATION, location)
+                .body(Full::default())?);
+        }
+    }
+
+    let (parts, body) = req.into_parts();
+    let body = body.collect().await?.to_bytes();
+    let path = parts
+        .uri
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or("/");
+
+    let mut headers = parts.headers;
+    strip_hop_by_hop(&mut headers);
+    headers.insert(
+        "x-forwarded-proto",
+        HeaderValue::from_static(if is_https(&headers) { "https" } else { "http" }),
+    );
+
+    let response = match client
+        .request(parts.method, format!("http://{backend}{path}"))
+        .headers(headers)
+        .body(body)
+        .send()
+        .await
+    {
+        Ok(response) => response,
+        Err(error) => {
+            tracing::debug!(%error, "Unable to reach the app");
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_GATEWAY)
+                .body(Full::new(Bytes::from_static(b"The app is not available")))?);
+        }
+    };
+
+    let mut builder = Response::builder().status(response.status());
+    if let Some(response_headers) = builder.headers_mut() {
+        response_headers.extend(response.headers().clone());
+        strip_hop_by_hop(response_headers);
+    }
+    let body = response.bytes().await?;
+
+    Ok(builder.body(Full::new(body))?)
+}
+
+fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    for name in HOP_BY_HOP_HEADERS {
+        headers.remove(name);
+    }
+}
+
+/// Requests are considered secure when a TLS terminator in front of the
+/// proxy says so.
+fn is_https(headers: &HeaderMap) -> bool {
+    headers
+        .get("x-forwarded-proto")
+        .is_some_and(|proto| proto.as_bytes().eq_ignore_ascii_case(b"https"))
+}
+
+/// The location to redirect a plain HTTP request to, if any.
+fn https_redirect<B>(req: &Request<B>) -> Option<String> {
+    if is_https(req.headers()) {
+        return None;
+    }
+
+    let host = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .or_else(|| req.uri().authority().map(|a| a.as_str()))?;
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or("/");
+
+    Some(format!("https://{host}{path}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redirect_plain_http_requests() {
+        let req = Request::get("/foo?bar=baz")
+            .header(header::HOST, "localhost:8000")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            https_redirect(&req).as_deref(),
+            Some("https://localhost:8000/foo?bar=baz")
+        );
+
+        let req = Request::get("/foo")
+            .header(header::HOST, "localhost:8000")
+            .header("x-forwarded-proto", "HTTPS")
+            .body(())
+            .unwrap();
+        assert_eq!(https_redirect(&req), None);
+    }
+}
@@ -0,0 +1,103 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::Context;
+use clap::Parser;
+use wasmparser::{KnownCustom, Name, Parser as WasmParser, Payload};
+
+use crate::commands::CliCommand;
+
+/// Prints the threads and frames that are stored in a core dump
+#[derive(Debug, Parser)]
+pub struct CmdCoredumpInspect {
+    /// Path to the core dump
+    #[clap(index = 1)]
+    coredump_path: PathBuf,
+    /// The module that was running, used to show the names of the functions
+    #[clap(long)]
+    module: Option<PathBuf>,
+}
+
+impl CliCommand for CmdCoredumpInspect {
+    type Output = ();
+
+    fn run(self) -> Result<(), anyhow::Error> {
+        let coredump = std::fs::read(&self.coredump_path).with_context(|| {
+            format!(
+                "Unable to read the core dump at \"{}\"",
+                self.coredump_path.display()
+            )
+        })?;
+        let names = match &self.module {
+            Some(path) => {
+                let module = std::fs::read(path).with_context(|| {
+                    format!("Unable to read the module at \"{}\"", path.display())
+                })?;
+                function_names(&module)?
+            }
+            None => HashMap::new(),
+        };
+
+        for payload in WasmParser::new(0).parse_all(&coredump) {
+            match payload.context("Invalid core dump")? {
+                Payload::CustomSection(section) => match section.as_known() {
+                    KnownCustom::CoreDump(core) => println!("executable: {}", core.name),
+                    KnownCustom::CoreDumpModules(modules) => {
+                        for (i, module) in modules.modules.iter().enumerate() {
+                            println!("module {i}: {module}");
+                        }
+                    }
+                    KnownCustom::CoreDumpStack(stack) => {
+                        println!();
+                        println!("thread {}:", stack.name);
+                        for (i, frame) in stack.frames.iter().enumerate() {
+                            let name = names
+                                .get(&frame.funcidx)
+                                .map(|name| name.as_str())
+                                .unwrap_or("<unnamed>");
+                            println!(
+                                "  #{i:<3} {name} (func[{}]+0x{:x})",
+                                frame.funcidx, frame.codeoffset
+                            );
+                            if !frame.locals.is_empty() {
+                                println!("        locals: {:?}", frame.locals);
+                            }
+                        }
+                    }
+                    _ => {}
+                },
+                Payload::MemorySection(memories) => {
+                    for (i, memory) in memories.into_iter().enumerate() {
+                        let memory = memory.context("Invalid memory")?;
+                        println!("memory {i}: {} pages", memory.initial);
+                    }
+                }
+                Payload::GlobalSection(globals) => {
+                    println!("globals: {}", globals.count());
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the function names from the name section of a module
+fn function_names(module: &[u8]) -> Result<HashMap<u32, String>, anyhow::Error> {
+    let mut names = HashMap::new();
+    for payload in WasmParser::new(0).parse_all(module) {
+        let Payload::CustomSection(section) = payload.context("Invalid module")? else {
+            continue;
+        };
+        let KnownCustom::Name(reader) = section.as_known() else {
+            continue;
+        };
+        for name in reader.into_iter().flatten() {
+            if let Name::Function(map) = name {
+                for naming in map.into_iter().flatten() {
+                    names.insert(naming.index, naming.name.to_string());
+                }
+            }
+        }
+    }
+    Ok(names)
+}
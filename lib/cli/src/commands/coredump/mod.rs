@@ -0,0 +1,22 @@
+use crate::commands::CliCommand;
+
+mod inspect;
+
+pub use inspect::*;
+
+/// Inspect WebAssembly core dumps
+#[derive(clap::Subcommand, Debug)]
+pub enum CmdCoredump {
+    /// Prints the threads and frames that are stored in a core dump
+    Inspect(CmdCoredumpInspect),
+}
+
+impl CliCommand for CmdCoredump {
+    type Output = ();
+
+    fn run(self) -> Result<(), anyhow::Error> {
+        match self {
+            Self::Inspect(cmd) => cmd.run(),
+        }
+    }
+}
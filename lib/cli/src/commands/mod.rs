@@ -10,6 +10,8 @@ mod compile;
 mod config;
 mod connect;
 mod container;
+#[cfg(feature = "coredump")]
+mod coredump;
 #[cfg(any(feature = "static-artifact-create", feature = "wasmer-artifact-create"))]
 mod create_exe;
 #[cfg(feature = "static-artifact-create")]
@@ -27,7 +29,9 @@ pub(crate) mod namespace;
 mod package;
 mod run;
 mod self_update;
+mod snapshot_init;
 pub mod ssh;
+mod ssh_serve;
 mod validate;
 #[cfg(feature = "wast")]
 mod wast;
@@ -46,11 +50,13 @@ pub use wast::*;
 #[cfg(feature = "static-artifact-create")]
 pub use {create_obj::*, gen_c_header::*};
 
+#[cfg(feature = "coredump")]
+pub use self::coredump::*;
 #[cfg(feature = "journal")]
 pub use self::journal::*;
 pub use self::{
     add::*, auth::*, cache::*, config::*, container::*, init::*, inspect::*, package::*,
-    publish::*, run::Run, self_update::*, validate::*,
+    publish::*, run::Run, self_update::*, snapshot_init::*, validate::*,
 };
 use crate::error::PrettyError;
 
@@ -181,6 +187,7 @@ impl WasmerCmd {
             Some(Cmd::CreateExe(create_exe)) => create_exe.run(),
             #[cfg(feature = "static-artifact-create")]
             Some(Cmd::CreateObj(create_obj)) => create_obj.execute(),
+            Some(Cmd::SnapshotInit(snapshot_init)) => snapshot_init.execute(),
             Some(Cmd::Config(config)) => config.run(),
             Some(Cmd::Inspect(inspect)) => inspect.execute(),
             Some(Cmd::Init(init)) => init.run(),
@@ -212,7 +219,10 @@ impl WasmerCmd {
             Some(Cmd::App(apps)) => apps.run(),
             #[cfg(feature = "journal")]
             Some(Cmd::Journal(journal)) => journal.run(),
+            #[cfg(feature = "coredump")]
+            Some(Cmd::Coredump(coredump)) => coredump.run(),
             Some(Cmd::Ssh(ssh)) => ssh.run(),
+            Some(Cmd::SshServe(cmd)) => cmd.run(),
             Some(Cmd::Namespace(namespace)) => namespace.run(),
             Some(Cmd::Domain(namespace)) => namespace.run(),
             None => {
@@ -375,6 +385,11 @@ enum Cmd {
     #[cfg(feature = "static-artifact-create")]
     GenCHeader(GenCHeader),
 
+    /// Pre-initialize a WebAssembly module by running its init function ahead
+    /// of time and baking the resulting memory and globals into a new module
+    #[clap(name = "snapshot-init")]
+    SnapshotInit(SnapshotInit),
+
     /// Get various configuration information needed
     /// to compile programs which use Wasmer
     Config(Config),
@@ -413,6 +428,11 @@ enum Cmd {
     #[clap(subcommand)]
     Journal(CmdJournal),
 
+    /// Inspect WebAssembly core dumps
+    #[cfg(feature = "coredump")]
+    #[clap(subcommand)]
+    Coredump(CmdCoredump),
+
     #[clap(subcommand)]
     Package(crate::commands::Package),
 
@@ -430,6 +450,9 @@ enum Cmd {
     /// Run commands/packages on Wasmer Edge in an interactive shell session
     Ssh(crate::commands::ssh::CmdSsh),
 
+    /// Serve sandboxed WASIX shells to SSH clients from this machine
+    SshServe(crate::commands::ssh_serve::CmdSshServe),
+
     /// Manage Wasmer namespaces
     #[clap(subcommand, alias = "namespaces")]
     Namespace(crate::commands::namespace::CmdNamespace),
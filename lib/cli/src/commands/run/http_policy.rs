@@ -0,0 +1,195 @@
+//! Loading `--http-policy` files.
+//!
+//! A policy is a TOML file with one `[[rule]]` table per host:
+//!
+//! ```toml
+//! [[rule]]
+//! host = "api.example.com"
+//! methods = ["GET", "POST"]
+//! path-prefixes = ["/v1/"]
+//! inject-headers = { authorization = "Bearer ..." }
+//! strip-headers = ["cookie"]
+//! rate-limit = "100/1m"
+//! max-body-size = "1MiB"
+//! timeout = "30s"
+//! ```
+
+use std::{collections::BTreeMap, path::Path, str::FromStr};
+
+use anyhow::{Context, Error};
+use http::{header::HeaderName, HeaderValue, Method};
+use serde::Deserialize;
+use wasmer_wasix::http::{HttpHostRule, RateLimit};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    rule: Vec<RuleConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RuleConfig {
+    host: String,
+    #[serde(default)]
+    methods: Vec<String>,
+    #[serde(default)]
+    path_prefixes: Vec<String>,
+    #[serde(default)]
+    inject_headers: BTreeMap<String, String>,
+    #[serde(default)]
+    strip_headers: Vec<String>,
+    rate_limit: Option<String>,
+    max_body_size: Option<String>,
+    timeout: Option<String>,
+}
+
+/// Read the rules from a policy file.
+pub(crate) fn load(path: &Path) -> Result<Vec<HttpHostRule>, Error> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Unable to read \"{}\"", path.display()))?;
+    parse(&contents).with_context(|| format!("Invalid HTTP policy in \"{}\"", path.display()))
+}
+
+fn parse(contents: &str) -> Result<Vec<HttpHostRule>, Error> {
+    let file: PolicyFile = toml::from_str(contents)?;
+    file.rule.into_iter().map(RuleConfig::into_rule).collect()
+}
+
+impl RuleConfig {
+    fn into_rule(self) -> Result<HttpHostRule, Error> {
+        let RuleConfig {
+            host,
+            methods,
+            path_prefixes,
+            inject_headers,
+            strip_headers,
+            rate_limit,
+            max_body_size,
+            timeout,
+        } = self;
+
+        let methods = methods
+            .iter()
+            .map(|method| {
+                Method::from_str(&method.to_ascii_uppercase())
+                    .with_context(|| format!("\"{method}\" isn't a valid HTTP method"))
+            })
+            .collect::<Result<_, Error>>()?;
+        let inject_headers = inject_headers
+            .into_iter()
+            .map(|(name, value)| Ok((header_name(&name)?, HeaderValue::from_str(&value)?)))
+            .collect::<Result<_, Error>>()?;
+        let strip_headers = strip_headers
+            .iter()
+            .map(|name| header_name(name))
+            .collect::<Result<_, Error>>()?;
+        let rate_limit = rate_limit.as_deref().map(parse_rate_limit).transpose()?;
+        let max_body_size = max_body_size
+            .as_deref()
+            .map(|size| {
+                size.parse::<bytesize::ByteSize>()
+                    .map(|size| size.as_u64())
+                    .map_err(|e| anyhow::anyhow!("Invalid body size \"{size}\": {e}"))
+            })
+            .transpose()?;
+        let timeout = timeout
+            .as_deref()
+            .map(|timeout| {
+                humantime::parse_duration(timeout)
+                    .with_context(|| format!("Invalid timeout \"{timeout}\""))
+            })
+            .transpose()?;
+
+        Ok(HttpHostRule {
+            host,
+            methods,
+            path_prefixes,
+            inject_headers,
+            strip_headers,
+            rate_limit,
+            max_body_size,
+            timeout,
+        })
+    }
+}
+
+fn header_name(name: &str) -> Result<HeaderName, Error> {
+    HeaderName::from_str(name).with_context(|| format!("\"{name}\" isn't a valid header name"))
+}
+
+/// Parse a rate limit like `100/1m` (100 requests per minute).
+fn parse_rate_limit(s: &str) -> Result<RateLimit, Error> {
+    let (requests, period) = s
+        .split_once('/')
+        .with_context(|| format!("Rate limits must look like \"100/1m\", not \"{s}\""))?;
+    let requests = requests
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid number of requests in \"{s}\""))?;
+    let period = humantime::parse_duration(period.trim())
+        .with_context(|| format!("Invalid period in \"{s}\""))?;
+
+    Ok(RateLimit { requests, period })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use http::header;
+
+    use super::*;
+
+    #[test]
+    fn parse_a_policy() {
+        let rules = parse(
+            r#"
+            [[rule]]
+            host = "api.example.com"
+            methods = ["get", "POST"]
+            path-prefixes = ["/v1/"]
+            inject-headers = { authorization = "Bearer token" }
+            strip-headers = ["Cookie"]
+            rate-limit = "100/1m"
+            max-body-size = "1 KiB"
+            timeout = "30s"
+
+            [[rule]]
+            host = "*.example.org"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            rules,
+            [
+                HttpHostRule {
+                    host: "api.example.com".to_string(),
+                    methods: vec![Method::GET, Method::POST],
+                    path_prefixes: vec!["/v1/".to_string()],
+                    inject_headers: vec![(
+                        header::AUTHORIZATION,
+                        HeaderValue::from_static("Bearer token")
+                    )],
+                    strip_headers: vec![header::COOKIE],
+                    rate_limit: Some(RateLimit {
+                        requests: 100,
+                        period: Duration::from_secs(60),
+                    }),
+                    max_body_size: Some(1024),
+                    timeout: Some(Duration::from_secs(30)),
+                },
+                HttpHostRule::new("*.example.org"),
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_unknown_fields() {
+        let error = parse("[[rule]]\nhost = \"example.com\"\nmethod = \"GET\"\n").unwrap_err();
+
+        assert!(error.to_string().contains("unknown field"), "{error}");
+    }
+}
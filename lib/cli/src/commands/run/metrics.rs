@@ -0,0 +1,51 @@
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+use anyhow::Context;
+use bytes::Bytes;
+use http::{header, Method, Request, Response, StatusCode};
+use http_body_util::Full;
+use hyper::{body::Incoming, server::conn::http1::Builder, service::service_fn};
+use hyper_util::rt::tokio::TokioIo;
+use tokio::net::TcpListener;
+use wasmer_wasix::runtime::metrics::Metrics;
+
+/// Serve `GET /metrics` in the Prometheus text format.
+pub(crate) async fn serve(addr: SocketAddr, metrics: Arc<Metrics>) -> Result<(), anyhow::Error> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Unable to serve metrics on {addr}"))?;
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let metrics = Arc::clone(&metrics);
+
+        tokio::spawn(async move {
+            let service = service_fn(move |req| {
+                let response = respond(&metrics, &req);
+                async move { Ok::<_, Infallible>(response) }
+            });
+            if let Err(error) = Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!(%error, "Metrics connection failed");
+            }
+        });
+    }
+}
+
+fn respond(metrics: &Metrics, req: &Request<Incoming>) -> Response<Full<Bytes>> {
+    let response = Response::builder();
+
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/metrics") => response
+            .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(Full::new(Bytes::from(metrics.render()))),
+        (_, "/metrics") => response
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .body(Full::default()),
+        _ => response.status(StatusCode::NOT_FOUND).body(Full::default()),
+    };
+
+    response.expect("the response is always valid")
+}
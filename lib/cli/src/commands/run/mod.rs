@@ -1,8 +1,13 @@
 #![allow(missing_docs, unused)]
 
 mod capabilities;
+mod http_policy;
+mod metrics;
+mod profile;
 mod wasi;
 
+pub(crate) use self::wasi::Wasi;
+
 use std::{
     collections::{hash_map::DefaultHasher, BTreeMap},
     fmt::{Binary, Display},
@@ -39,16 +44,21 @@ use wasmer_types::ModuleHash;
 use wasmer_wasix::journal::{LogFileJournal, SnapshotTrigger};
 use wasmer_wasix::{
     bin_factory::BinaryPackage,
+    capabilities::Capabilities,
     journal::CompactingLogFileJournal,
     runners::{
         dcgi::{DcgiInstanceFactory, DcgiRunner},
         dproxy::DProxyRunner,
+        proxy_wasm::{self, ProxyWasmRunner},
         wasi::WasiRunner,
         wcgi::{self, AbortHandle, NoOpWcgiCallbacks, WcgiRunner},
-        MappedCommand, MappedDirectory, Runner,
+        MappedCommand, MappedDirectory, Runner, TlsConfig,
     },
     runtime::{
-        module_cache::CacheError, package_loader::PackageLoader, resolver::QueryError,
+        metrics::Metrics,
+        module_cache::{CacheError, ModuleCache},
+        package_loader::PackageLoader,
+        resolver::QueryError,
         task_manager::VirtualTaskManagerExt,
     },
     Runtime, WasiError,
@@ -56,9 +66,10 @@ use wasmer_wasix::{
 use webc::metadata::Manifest;
 use webc::Container;
 
+use self::profile::{GuestProfiler, ProfileFormat};
 use crate::{
-    backend::RuntimeOptions, commands::run::wasi::Wasi, common::HashAlgorithm, config::WasmerEnv,
-    error::PrettyError, logging::Output,
+    backend::RuntimeOptions, common::HashAlgorithm, config::WasmerEnv, error::PrettyError,
+    logging::Output,
 };
 
 const TICK: Duration = Duration::from_millis(250);
@@ -86,6 +97,17 @@ pub struct Run {
     /// Generate a coredump at this path if a WebAssembly trap occurs
     #[clap(name = "COREDUMP_PATH", long)]
     coredump_on_trap: Option<PathBuf>,
+    /// Sample the call stacks of the guest while it runs and write a profile
+    /// to this path (pprof for `.pb`/`.pprof` files, collapsed stacks
+    /// otherwise)
+    #[clap(long = "profile", name = "PROFILE_PATH")]
+    profile: Option<PathBuf>,
+    /// The format of the profile, inferred from its path by default
+    #[clap(long, value_enum, requires = "PROFILE_PATH")]
+    profile_format: Option<ProfileFormat>,
+    /// How many times per second the profiler samples the guest
+    #[clap(long, default_value_t = 99, requires = "PROFILE_PATH")]
+    profile_frequency: u32,
     /// The file, URL, or package to run.
     #[clap(value_parser = PackageSource::infer)]
     input: PackageSource,
@@ -102,8 +124,40 @@ impl Run {
         exit_with_wasi_exit_code(result);
     }
 
+    /// Create a [`Run`] that executes a command from an app's package, with
+    /// any HTTP server it starts listening on `addr`.
+    pub(crate) fn for_app(
+        env: WasmerEnv,
+        rt: RuntimeOptions,
+        wasi: Wasi,
+        input: PackageSource,
+        entrypoint: Option<String>,
+        args: Vec<String>,
+        addr: SocketAddr,
+    ) -> Self {
+        Run {
+            env,
+            rt,
+            wasi,
+            wcgi: WcgiOptions {
+                addr,
+                ..Default::default()
+            },
+            stack_size: None,
+            entrypoint,
+            invoke: None,
+            coredump_on_trap: None,
+            profile: None,
+            profile_format: None,
+            profile_frequency: 99,
+            input,
+            args,
+            hash_algorithm: None,
+        }
+    }
+
     #[tracing::instrument(level = "debug", name = "wasmer_run", skip_all)]
-    fn execute_inner(mut self, output: Output) -> Result<(), Error> {
+    pub(crate) fn execute_inner(mut self, output: Output) -> Result<(), Error> {
         let pb = ProgressBar::new_spinner();
         pb.set_draw_target(output.draw_target());
         pb.enable_steady_tick(TICK);
@@ -143,7 +197,7 @@ impl Run {
 
         let engine = engine.clone();
 
-        let runtime = self.wasi.prepare_runtime(
+        let mut runtime = self.wasi.prepare_runtime(
             engine,
             &self.env,
             &capabilities::get_capability_cache_path(&self.env, &self.input)?,
@@ -151,6 +205,21 @@ impl Run {
             preferred_webc_version,
         )?;
 
+        if let Some(addr) = self.wcgi.metrics_addr {
+            let metrics = Arc::new(Metrics::new());
+            let module_cache = runtime.module_cache.clone();
+            runtime.set_module_cache(module_cache.with_metrics(Arc::clone(&metrics)));
+            handle.spawn({
+                let metrics = Arc::clone(&metrics);
+                async move {
+                    if let Err(e) = metrics::serve(addr, metrics).await {
+                        tracing::error!(error = &*e, "Unable to serve metrics");
+                    }
+                }
+            });
+            self.wcgi.metrics = Some(metrics);
+        }
+
         // This is a slow operation, so let's temporarily wrap the runtime with
         // something that displays progress
         let monitoring_runtime = Arc::new(MonitoringRuntime::new(runtime, pb.clone()));
@@ -170,6 +239,22 @@ impl Run {
         // push the TTY state so we can restore it after the program finishes
         let tty = runtime.tty().map(|tty| tty.tty_get());
 
+        let profiler = match &self.profile {
+            Some(path) => Some(GuestProfiler::start(
+                path.clone(),
+                self.profile_format,
+                self.profile_frequency,
+            )?),
+            None => None,
+        };
+
+        // A core dump left over from a previous run must not be mistaken for
+        // one written by the guest during this run
+        #[cfg(feature = "coredump")]
+        if let Some(coredump) = &self.coredump_on_trap {
+            let _ = std::fs::remove_file(coredump);
+        }
+
         let result = {
             match target {
                 ExecutableTarget::WebAssembly {
@@ -181,6 +266,15 @@ impl Run {
             }
         };
 
+        if let Some(profiler) = profiler {
+            if let Err(e) = profiler.finish() {
+                tracing::warn!(
+                    error = &*e as &dyn std::error::Error,
+                    "Unable to save the profile",
+                );
+            }
+        }
+
         // restore the TTY state as the execution may have changed it
         if let Some(state) = tty {
             if let Some(tty) = runtime.tty() {
@@ -203,13 +297,27 @@ impl Run {
         module_hash: ModuleHash,
         runtime: Arc<dyn Runtime + Send + Sync>,
     ) -> Result<(), Error> {
-        if wasmer_wasix::is_wasi_module(module) || wasmer_wasix::is_wasix_module(module) {
+        if proxy_wasm::is_proxy_wasm_module(module) {
+            self.execute_proxy_wasm_module(path, module, module_hash, runtime)
+        } else if wasmer_wasix::is_wasi_module(module) || wasmer_wasix::is_wasix_module(module) {
             self.execute_wasi_module(path, module, module_hash, runtime)
         } else {
             self.execute_pure_wasm_module(module)
         }
     }
 
+    fn execute_proxy_wasm_module(
+        &self,
+        wasm_path: &Path,
+        module: &Module,
+        module_hash: ModuleHash,
+        runtime: Arc<dyn Runtime + Send + Sync>,
+    ) -> Result<(), Error> {
+        let program_name = wasm_path.display().to_string();
+        let mut runner = self.build_proxy_wasm_runner(&runtime)?;
+        runner.run_module(&program_name, module, module_hash, runtime)
+    }
+
     #[tracing::instrument(skip_all)]
     fn execute_webc(
         &self,
@@ -230,6 +338,8 @@ impl Run {
             self.run_dcgi(id, pkg, uses, runtime)
         } else if DProxyRunner::can_run_command(cmd.metadata())? {
             self.run_dproxy(id, pkg, runtime)
+        } else if ProxyWasmRunner::can_run_command(cmd.metadata())? {
+            self.run_proxy_wasm(id, pkg, runtime)
         } else if WcgiRunner::can_run_command(cmd.metadata())? {
             self.run_wcgi(id, pkg, uses, runtime)
         } else if WasiRunner::can_run_command(cmd.metadata())? {
@@ -301,9 +411,26 @@ impl Run {
             .addr(self.wcgi.addr)
             .envs(self.wasi.env_vars.clone())
             .map_directories(self.wasi.mapped_dirs.clone())
-            .callbacks(Callbacks::new(self.wcgi.addr))
-            .inject_packages(uses);
-        *config.capabilities() = self.wasi.capabilities();
+            .http2(self.wcgi.http2)
+            .callbacks(Callbacks::new(self.wcgi.addr, self.wcgi.tls_cert.is_some()))
+            .inject_packages(uses)
+            .prewarm(self.wcgi.prewarm);
+        if let Some(limit) = self.wcgi.max_concurrency {
+            config.max_concurrency(limit);
+        }
+        if let Some(limit) = self.wcgi.max_queued_requests {
+            config.max_queued_requests(limit);
+        }
+        if let Some(timeout) = self.wcgi.queue_timeout {
+            config.queue_timeout(timeout.into());
+        }
+        if let Some(tls) = self.wcgi.tls() {
+            config.tls(tls);
+        }
+        if let Some(metrics) = &self.wcgi.metrics {
+            config.metrics(Arc::clone(metrics));
+        }
+        *config.capabilities() = self.capabilities()?;
         if self.wasi.forward_host_env {
             config.forward_host_env();
         }
@@ -353,9 +480,55 @@ impl Run {
     ) -> Result<(), Error> {
         let mut inner = self.build_wasi_runner(&runtime)?;
         let mut runner = wasmer_wasix::runners::dproxy::DProxyRunner::new(inner, pkg);
+        runner.config().addr(self.wcgi.addr).http2(self.wcgi.http2);
+        if let Some(tls) = self.wcgi.tls() {
+            runner.config().tls(tls);
+        }
+        if let Some(metrics) = &self.wcgi.metrics {
+            runner.config().metrics(Arc::clone(metrics));
+        }
+        runner.run_command(command_name, pkg, runtime)
+    }
+
+    fn run_proxy_wasm(
+        &self,
+        command_name: &str,
+        pkg: &BinaryPackage,
+        runtime: Arc<dyn Runtime + Send + Sync>,
+    ) -> Result<(), Error> {
+        let mut runner = self.build_proxy_wasm_runner(&runtime)?;
         runner.run_command(command_name, pkg, runtime)
     }
 
+    fn build_proxy_wasm_runner(
+        &self,
+        runtime: &Arc<dyn Runtime + Send + Sync>,
+    ) -> Result<ProxyWasmRunner, Error> {
+        let inner = self.build_wasi_runner(runtime)?;
+        let mut runner = ProxyWasmRunner::new(inner);
+        let config = runner.config();
+        config.addr(self.wcgi.addr).http2(self.wcgi.http2);
+        if let Some(tls) = self.wcgi.tls() {
+            config.tls(tls);
+        }
+        if let Some(metrics) = &self.wcgi.metrics {
+            config.metrics(Arc::clone(metrics));
+        }
+        if let Some(upstream) = &self.wcgi.upstream {
+            config.upstream(upstream.clone());
+        }
+        for (name, url) in &self.wcgi.clusters {
+            config.cluster(name.clone(), url.clone());
+        }
+        if let Some(path) = &self.wcgi.plugin_config {
+            let configuration = std::fs::read(path)
+                .with_context(|| format!("Unable to read \"{}\"", path.display()))?;
+            config.plugin_configuration(configuration);
+        }
+
+        Ok(runner)
+    }
+
     #[tracing::instrument(skip_all)]
     fn execute_pure_wasm_module(&self, module: &Module) -> Result<(), Error> {
         /// The rest of the execution happens in the main thread, so we can create the
@@ -377,7 +550,30 @@ impl Run {
             }
         };
 
-        let return_values = invoke_function(&instance, &mut store, entry_function, &self.args)?;
+        let return_values = match invoke_function(&instance, &mut store, entry_function, &self.args)
+        {
+            Ok(values) => values,
+            Err(e) => {
+                // The instance is only reachable from here, so the core dump
+                // is written now to include its memories and globals
+                #[cfg(feature = "coredump")]
+                if let Some(coredump) = &self.coredump_on_trap {
+                    if let Err(e) = generate_coredump(
+                        &e,
+                        self.input.to_string(),
+                        coredump,
+                        Some((&mut store, &instance)),
+                    ) {
+                        tracing::warn!(
+                            error = &*e as &dyn std::error::Error,
+                            coredump_path=%coredump.display(),
+                            "Unable to generate a coredump",
+                        );
+                    }
+                }
+                return Err(e);
+            }
+        };
 
         println!(
             "{}",
@@ -411,7 +607,7 @@ impl Run {
             .with_home_mapped(is_home_mapped)
             .with_tmp_mapped(is_tmp_mapped)
             .with_forward_host_env(self.wasi.forward_host_env)
-            .with_capabilities(self.wasi.capabilities());
+            .with_capabilities(self.capabilities()?);
 
         if let Some(ref entry_function) = self.invoke {
             runner.with_entry_function(entry_function);
@@ -461,11 +657,25 @@ impl Run {
         )
     }
 
+    /// The capabilities of the WASI environment, including where the core
+    /// dump of the guest is written to when it traps.
+    fn capabilities(&self) -> Result<Capabilities, Error> {
+        #[allow(unused_mut)]
+        let mut caps = self.wasi.capabilities()?;
+        #[cfg(feature = "coredump")]
+        {
+            caps.coredump_on_trap = self.coredump_on_trap.clone();
+        }
+        Ok(caps)
+    }
+
+    /// Writes a core dump with the frames of the trap if the guest did not
+    /// already write one that includes its memories and globals.
     #[allow(unused_variables)]
     fn maybe_save_coredump(&self, e: &Error) {
         #[cfg(feature = "coredump")]
-        if let Some(coredump) = &self.coredump_on_trap {
-            if let Err(e) = generate_coredump(e, self.input.to_string(), coredump) {
+        if let Some(coredump) = self.coredump_on_trap.as_ref().filter(|path| !path.exists()) {
+            if let Err(e) = generate_coredump(e, self.input.to_string(), coredump, None) {
                 tracing::warn!(
                     error = &*e as &dyn std::error::Error,
                     coredump_path=%coredump.display(),
@@ -507,6 +717,9 @@ impl Run {
             entrypoint: Some(original_executable.to_string()),
             invoke: None,
             coredump_on_trap: None,
+            profile: None,
+            profile_format: None,
+            profile_frequency: 99,
             input: PackageSource::infer(executable)?,
             args: args.to_vec(),
             hash_algorithm: None,
@@ -559,7 +772,7 @@ fn parse_value(s: &str, ty: wasmer_types::Type) -> Result<Value, Error> {
 
 /// The input that was passed in via the command-line.
 #[derive(Debug, Clone, PartialEq)]
-enum PackageSource {
+pub(crate) enum PackageSource {
     /// A file on disk (`*.wasm`, `*.webc`, etc.).
     File(PathBuf),
     /// A directory containing a `wasmer.toml` file
@@ -586,6 +799,24 @@ impl PackageSource {
         ))
     }
 
+    /// Convert the package referenced by a config file, resolving relative
+    /// paths against `base_dir`.
+    pub(crate) fn from_config(spec: &PackageSpecifier, base_dir: &Path) -> Result<Self, Error> {
+        match spec {
+            PackageSpecifier::Path(path) => {
+                let path = base_dir.join(path);
+                if path.is_file() {
+                    Ok(PackageSource::File(path))
+                } else if path.is_dir() {
+                    Ok(PackageSource::Dir(path))
+                } else {
+                    bail!("\"{}\" does not exist", path.display());
+                }
+            }
+            other => Ok(PackageSource::Package(other.clone())),
+        }
+    }
+
     /// Try to resolve the [`PackageSource`] to an executable artifact.
     ///
     /// This will try to automatically download and cache any resources from the
@@ -754,7 +985,12 @@ impl ExecutableTarget {
 }
 
 #[cfg(feature = "coredump")]
-fn generate_coredump(err: &Error, source_name: String, coredump_path: &Path) -> Result<(), Error> {
+fn generate_coredump(
+    err: &Error,
+    source_name: String,
+    coredump_path: &Path,
+    instance: Option<(&mut Store, &Instance)>,
+) -> Result<(), Error> {
     let err: &wasmer::RuntimeError = match err.downcast_ref() {
         Some(e) => e,
         None => {
@@ -763,25 +999,11 @@ fn generate_coredump(err: &Error, source_name: String, coredump_path: &Path) ->
         }
     };
 
-    let mut coredump_builder =
-        wasm_coredump_builder::CoredumpBuilder::new().executable_name(&source_name);
-
-    let mut thread_builder = wasm_coredump_builder::ThreadBuilder::new().thread_name("main");
-
-    for frame in err.trace() {
-        let coredump_frame = wasm_coredump_builder::FrameBuilder::new()
-            .codeoffset(frame.func_offset() as u32)
-            .funcidx(frame.func_index())
-            .build();
-        thread_builder.add_frame(coredump_frame);
+    let mut coredump = err.to_coredump(source_name);
+    if let Some((store, instance)) = instance {
+        coredump = coredump.with_instance(store, instance);
     }
-
-    coredump_builder.add_thread(thread_builder.build());
-
-    let coredump = coredump_builder
-        .serialize()
-        .map_err(Error::msg)
-        .context("Coredump serializing failed")?;
+    let coredump = coredump.serialize();
 
     std::fs::write(coredump_path, &coredump).with_context(|| {
         format!(
@@ -798,12 +1020,88 @@ pub(crate) struct WcgiOptions {
     /// The address to serve on.
     #[clap(long, short, env, default_value_t = ([127, 0, 0, 1], 8000).into())]
     pub(crate) addr: SocketAddr,
+    /// Serve HTTP/2 alongside HTTP/1.
+    ///
+    /// HTTP/2 is negotiated through ALPN when TLS is enabled, and must be
+    /// used with prior knowledge (h2c) otherwise.
+    #[clap(long)]
+    pub(crate) http2: bool,
+    /// A PEM file with the certificate chain to terminate TLS with.
+    #[clap(long, requires = "tls_key")]
+    pub(crate) tls_cert: Option<PathBuf>,
+    /// A PEM file with the private key of the TLS certificate.
+    #[clap(long, requires = "tls_cert")]
+    pub(crate) tls_key: Option<PathBuf>,
+    /// Create this many WCGI instances ahead of time, so requests don't have
+    /// to wait for one to be set up.
+    #[clap(long, default_value_t = 0)]
+    pub(crate) prewarm: usize,
+    /// Limit the number of WCGI requests handled at the same time, further
+    /// requests are queued.
+    #[clap(long)]
+    pub(crate) max_concurrency: Option<usize>,
+    /// Limit the number of WCGI requests waiting for a free slot, further
+    /// requests are rejected with `503 Service Unavailable`.
+    #[clap(long, requires = "max_concurrency")]
+    pub(crate) max_queued_requests: Option<usize>,
+    /// Reject WCGI requests with `503 Service Unavailable` once they have
+    /// waited this long for a free slot (e.g. "500ms" or "30s").
+    #[clap(long, requires = "max_concurrency")]
+    pub(crate) queue_timeout: Option<humantime::Duration>,
+    /// Serve Prometheus metrics on `/metrics` at this address.
+    #[clap(long)]
+    pub(crate) metrics_addr: Option<SocketAddr>,
+    /// The registry metrics are recorded in when `--metrics-addr` is set.
+    #[clap(skip)]
+    pub(crate) metrics: Option<Arc<Metrics>>,
+    /// Where a Proxy-Wasm filter forwards requests to.
+    #[clap(long)]
+    pub(crate) upstream: Option<Url>,
+    /// A named upstream Proxy-Wasm filters can make HTTP calls to
+    /// (`NAME=URL`).
+    #[clap(long = "cluster", value_parser = parse_cluster)]
+    pub(crate) clusters: Vec<(String, Url)>,
+    /// A file that is passed to a Proxy-Wasm filter as its plugin
+    /// configuration.
+    #[clap(long)]
+    pub(crate) plugin_config: Option<PathBuf>,
+}
+
+fn parse_cluster(s: &str) -> Result<(String, Url), Error> {
+    let (name, url) = s
+        .split_once('=')
+        .context("Clusters must be in the form NAME=URL")?;
+    let url = url
+        .parse()
+        .with_context(|| format!("\"{url}\" isn't a valid URL"))?;
+    Ok((name.to_string(), url))
+}
+
+impl WcgiOptions {
+    fn tls(&self) -> Option<TlsConfig> {
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => Some(TlsConfig::new(cert, key)),
+            _ => None,
+        }
+    }
 }
 
 impl Default for WcgiOptions {
     fn default() -> Self {
         Self {
             addr: ([127, 0, 0, 1], 8000).into(),
+            http2: false,
+            tls_cert: None,
+            tls_key: None,
+            prewarm: 0,
+            max_concurrency: None,
+            max_queued_requests: None,
+            queue_timeout: None,
+            metrics_addr: None,
+            metrics: None,
+            upstream: None,
+            clusters: Vec::new(),
+            plugin_config: None,
         }
     }
 }
@@ -812,20 +1110,23 @@ impl Default for WcgiOptions {
 struct Callbacks {
     stderr: Mutex<LineWriter<std::io::Stderr>>,
     addr: SocketAddr,
+    tls: bool,
 }
 
 impl Callbacks {
-    fn new(addr: SocketAddr) -> Self {
+    fn new(addr: SocketAddr, tls: bool) -> Self {
         Callbacks {
             stderr: Mutex::new(LineWriter::new(std::io::stderr())),
             addr,
+            tls,
         }
     }
 }
 
 impl wasmer_wasix::runners::wcgi::Callbacks for Callbacks {
     fn started(&self, _abort: AbortHandle) {
-        println!("WCGI Server running at http://{}/", self.addr);
+        let scheme = if self.tls { "https" } else { "http" };
+        println!("WCGI Server running at {scheme}://{}/", self.addr);
     }
 
     fn on_stderr(&self, raw_message: &[u8]) {
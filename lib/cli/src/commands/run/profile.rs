@@ -0,0 +1,353 @@
+//! Output of the guest profiler of `wasmer run --profile`.
+
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Error};
+use clap::ValueEnum;
+use wasmer_types::{FrameInfo, SourceLoc};
+
+/// Module and function name of the frame that stands for host code called by
+/// the guest
+const HOST_MODULE: &str = "[host]";
+
+/// Format of the profile written by `wasmer run --profile`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum ProfileFormat {
+    /// The protobuf format of pprof
+    Pprof,
+    /// Collapsed stacks, one line per stack, as used by flamegraph tools
+    Collapsed,
+}
+
+impl ProfileFormat {
+    /// Picks the format from the extension of the output path
+    fn infer(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("pb" | "pprof") => ProfileFormat::Pprof,
+            _ => ProfileFormat::Collapsed,
+        }
+    }
+}
+
+/// Samples the guest while it runs and writes the profile when finished
+pub(crate) struct GuestProfiler {
+    path: PathBuf,
+    format: ProfileFormat,
+    #[cfg(all(feature = "compiler", target_os = "linux"))]
+    profiler: wasmer_compiler::Profiler,
+}
+
+impl GuestProfiler {
+    #[cfg(all(feature = "compiler", target_os = "linux"))]
+    pub(crate) fn start(
+        path: PathBuf,
+        format: Option<ProfileFormat>,
+        frequency: u32,
+    ) -> Result<Self, Error> {
+        let profiler =
+            wasmer_compiler::Profiler::start(frequency).context("Unable to start the profiler")?;
+        Ok(Self {
+            format: format.unwrap_or_else(|| ProfileFormat::infer(&path)),
+            path,
+            profiler,
+        })
+    }
+
+    #[cfg(not(all(feature = "compiler", target_os = "linux")))]
+    pub(crate) fn start(
+        _path: PathBuf,
+        _format: Option<ProfileFormat>,
+        _frequency: u32,
+    ) -> Result<Self, Error> {
+        anyhow::bail!("Profiling is only supported on Linux with a compiler backend")
+    }
+
+    #[cfg(all(feature = "compiler", target_os = "linux"))]
+    pub(crate) fn finish(self) -> Result<(), Error> {
+        let profile = self.profiler.stop();
+        let stacks = profile
+            .samples
+            .iter()
+            .map(|sample| {
+                let host = sample.host.then(host_frame);
+                let frames = host.into_iter().chain(sample.frames.iter().cloned());
+                (frames.collect::<Vec<_>>(), sample.count)
+            })
+            .collect::<Vec<_>>();
+        let samples = stacks
+            .iter()
+            .map(|(frames, count)| (frames.as_slice(), *count))
+            .collect::<Vec<_>>();
+
+        let data = match self.format {
+            ProfileFormat::Collapsed => collapsed(&samples),
+            ProfileFormat::Pprof => pprof(
+                &samples,
+                profile.period.as_nanos() as i64,
+                profile.duration.as_nanos() as i64,
+            ),
+        };
+        std::fs::write(&self.path, data).with_context(|| {
+            format!("Unable to save the profile to \"{}\"", self.path.display())
+        })?;
+        Ok(())
+    }
+
+    #[cfg(not(all(feature = "compiler", target_os = "linux")))]
+    pub(crate) fn finish(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// The frame of host code called by the guest, whose callers are not known
+fn host_frame() -> FrameInfo {
+    FrameInfo::new(
+        HOST_MODULE.to_string(),
+        u32::MAX,
+        Some(HOST_MODULE.to_string()),
+        SourceLoc::new(0),
+        SourceLoc::new(0),
+    )
+}
+
+fn function_name(frame: &FrameInfo) -> String {
+    match frame.function_name() {
+        Some(name) => rustc_demangle::demangle(name).to_string(),
+        None => format!("{}!func[{}]", frame.module_name(), frame.func_index()),
+    }
+}
+
+/// Writes one `outer;inner count` line per stack
+fn collapsed(samples: &[(&[FrameInfo], u64)]) -> Vec<u8> {
+    let mut stacks: HashMap<String, u64> = HashMap::new();
+    for (frames, count) in samples {
+        let stack = frames
+            .iter()
+            .rev()
+            .map(function_name)
+            .collect::<Vec<_>>()
+            .join(";");
+        *stacks.entry(stack).or_default() += count;
+    }
+
+    let mut stacks = stacks.into_iter().collect::<Vec<_>>();
+    stacks.sort();
+    let mut out = Vec::new();
+    for (stack, count) in stacks {
+        writeln!(out, "{stack} {count}").ok();
+    }
+    out
+}
+
+/// Encodes the samples as a `perftools.profiles.Profile` message
+fn pprof(samples: &[(&[FrameInfo], u64)], period: i64, duration: i64) -> Vec<u8> {
+    let mut strings = StringTable::default();
+    let mut functions: HashMap<(String, String), u64> = HashMap::new();
+    let mut locations: HashMap<(u64, usize), u64> = HashMap::new();
+    let mut function_table = Proto::default();
+    let mut location_table = Proto::default();
+    let mut sample_table = Proto::default();
+
+    for (frames, count) in samples {
+        let mut location_ids = Vec::with_capacity(frames.len());
+        for frame in frames.iter() {
+            // The host frame has no function index of its own, so functions
+            // are told apart by their names
+            let key = (frame.module_name().to_string(), function_name(frame));
+            let function_id = match functions.get(&key) {
+                Some(id) => *id,
+                None => {
+                    let id = functions.len() as u64 + 1;
+                    let name = strings.get(&key.1);
+                    let filename = frame
+                        .symbols()
+                        .last()
+                        .and_then(|symbol| symbol.file())
+                        .unwrap_or(frame.module_name());
+                    let filename = strings.get(filename);
+                    function_table.message(5, |function| {
+                        function.uint(1, id);
+                        function.uint(2, name);
+                        function.uint(3, name);
+                        function.uint(4, filename);
+                    });
+                    functions.insert(key, id);
+                    id
+                }
+            };
+
+            let key = (function_id, frame.module_offset());
+            let location_id = match locations.get(&key) {
+                Some(id) => *id,
+                None => {
+                    let id = locations.len() as u64 + 1;
+                    let line = frame
+                        .symbols()
+                        .last()
+                        .and_then(|symbol| symbol.line())
+                        .unwrap_or_default();
+                    location_table.message(4, |location| {
+                        location.uint(1, id);
+                        location.uint(3, frame.module_offset() as u64);
+                        location.message(4, |l| {
+                            l.uint(1, function_id);
+                            l.uint(2, line as u64);
+                        });
+                    });
+                    locations.insert(key, id);
+                    id
+                }
+            };
+            location_ids.push(location_id);
+        }
+
+        sample_table.message(2, |sample| {
+            sample.packed(1, location_ids.iter().copied());
+            sample.packed(2, [*count, count * period as u64].into_iter());
+        });
+    }
+
+    let samples_type = (strings.get("samples"), strings.get("count"));
+    let cpu_type = (strings.get("cpu"), strings.get("nanoseconds"));
+
+    let mut profile = Proto::default();
+    for (ty, unit) in [samples_type, cpu_type] {
+        profile.message(1, |value_type| {
+            value_type.uint(1, ty);
+            value_type.uint(2, unit);
+        });
+    }
+    profile.0.extend(sample_table.0);
+    profile.0.extend(location_table.0);
+    profile.0.extend(function_table.0);
+    for string in &strings.strings {
+        profile.bytes(6, string.as_bytes());
+    }
+    profile.uint(10, duration as u64);
+    profile.message(11, |value_type| {
+        value_type.uint(1, cpu_type.0);
+        value_type.uint(2, cpu_type.1);
+    });
+    profile.uint(12, period as u64);
+    profile.0
+}
+
+/// The string table of a pprof profile, which must start with ""
+struct StringTable {
+    strings: Vec<String>,
+    index: HashMap<String, u64>,
+}
+
+impl Default for StringTable {
+    fn default() -> Self {
+        Self {
+            strings: vec![String::new()],
+            index: HashMap::from([(String::new(), 0)]),
+        }
+    }
+}
+
+impl StringTable {
+    fn get(&mut self, s: &str) -> u64 {
+        if let Some(index) = self.index.get(s) {
+            return *index;
+        }
+        let index = self.strings.len() as u64;
+        self.strings.push(s.to_string());
+        self.index.insert(s.to_string(), index);
+        index
+    }
+}
+
+/// Minimal protobuf encoder for the few field types pprof uses
+#[derive(Default)]
+struct Proto(Vec<u8>);
+
+impl Proto {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn uint(&mut self, field: u32, value: u64) {
+        self.varint((field as u64) << 3);
+        self.varint(value);
+    }
+
+    fn bytes(&mut self, field: u32, data: &[u8]) {
+        self.varint(((field as u64) << 3) | 2);
+        self.varint(data.len() as u64);
+        self.0.extend_from_slice(data);
+    }
+
+    fn message(&mut self, field: u32, build: impl FnOnce(&mut Proto)) {
+        let mut message = Proto::default();
+        build(&mut message);
+        self.bytes(field, &message.0);
+    }
+
+    fn packed(&mut self, field: u32, values: impl Iterator<Item = u64>) {
+        let mut packed = Proto::default();
+        for value in values {
+            packed.varint(value);
+        }
+        self.bytes(field, &packed.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(func_index: u32, name: &str) -> FrameInfo {
+        FrameInfo::new(
+            "app".to_string(),
+            func_index,
+            Some(name.to_string()),
+            SourceLoc::new(0),
+            SourceLoc::new(func_index * 16),
+        )
+    }
+
+    #[test]
+    fn test_collapsed() {
+        let a = [frame(2, "leaf"), frame(1, "main")];
+        let b = [frame(1, "main")];
+        let out = collapsed(&[(&a, 3), (&b, 1), (&a, 2)]);
+        assert_eq!(String::from_utf8(out).unwrap(), "main 1\nmain;leaf 5\n");
+    }
+
+    #[test]
+    fn test_collapsed_host_frames() {
+        let host = [host_frame()];
+        let main = [frame(1, "main")];
+        let out = collapsed(&[(&host, 2), (&main, 1)]);
+        assert_eq!(String::from_utf8(out).unwrap(), "[host] 2\nmain 1\n");
+    }
+
+    #[test]
+    fn test_infer_format() {
+        assert_eq!(
+            ProfileFormat::infer(Path::new("out.pb")),
+            ProfileFormat::Pprof
+        );
+        assert_eq!(
+            ProfileFormat::infer(Path::new("out.folded")),
+            ProfileFormat::Collapsed
+        );
+    }
+
+    #[test]
+    fn test_varint() {
+        let mut proto = Proto::default();
+        proto.uint(1, 300);
+        assert_eq!(proto.0, vec![0x08, 0xac, 0x02]);
+    }
+}
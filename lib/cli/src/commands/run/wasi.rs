@@ -1,5 +1,6 @@
 use std::{
     collections::{BTreeSet, HashMap},
+    net::{IpAddr, SocketAddr},
     path::{Path, PathBuf},
     str::FromStr,
     sync::{mpsc::Sender, Arc},
@@ -12,7 +13,10 @@ use clap::Parser;
 use tokio::runtime::Handle;
 use url::Url;
 use virtual_fs::{DeviceFile, FileSystem, PassthruFileSystem, RootFileSystemBuilder};
-use virtual_net::ruleset::Ruleset;
+use virtual_net::{
+    ruleset::Ruleset, DynVirtualNetworking, HostsFile, NetworkConditions, ResolverNetworking,
+    ShapedNetworking, Zone, ZoneSource,
+};
 use wasmer::{Engine, Function, Instance, Memory32, Memory64, Module, RuntimeError, Store, Value};
 use wasmer_config::package::PackageSource as PackageSpecifier;
 use wasmer_types::ModuleHash;
@@ -22,7 +26,10 @@ use wasmer_wasix::{
     bin_factory::BinaryPackage,
     capabilities::Capabilities,
     default_fs_backing, get_wasi_versions,
-    http::HttpClient,
+    http::{
+        cassette::{MatchRules, RecordingHttpClient, ReplayHttpClient},
+        HttpClient,
+    },
     journal::{CompactingLogFileJournal, DynJournal},
     os::{tty_sys::SysTty, TtyBridge},
     rewind_ext,
@@ -120,6 +127,35 @@ pub struct Wasi {
     // and when --net=<ruleset> is specified, the inner Option will be initialized: Some(Some(ruleset))
     pub networking: Option<Option<String>>,
 
+    /// A file in the format of `/etc/hosts` with names that instances can
+    /// always resolve, regardless of any DNS servers.
+    #[clap(long)]
+    pub hosts_file: Option<PathBuf>,
+
+    /// Resolve names using this DNS server (`<IP>` or `<IP>:<PORT>`) instead
+    /// of the host's resolver.
+    #[clap(long, value_parser = parse_dns_server)]
+    pub dns_server: Option<SocketAddr>,
+
+    /// Resolve a domain and all of its subdomains using a different DNS
+    /// server, as `<DOMAIN>=<IP>[:<PORT>]`.
+    #[clap(long = "dns-zone", value_parser = parse_dns_zone)]
+    pub dns_zones: Vec<Zone>,
+
+    /// Simulate a bad network by applying these conditions to every TCP
+    /// and UDP socket, as a comma separated list of `latency=<DURATION>`,
+    /// `jitter=<DURATION>`, `bandwidth=<BYTES PER SECOND>`, `loss=<0-1>`,
+    /// `reset=<0-1>` and `reset-after=<BYTES>`.
+    ///
+    /// Example: --net-conditions=latency=100ms,jitter=20ms,loss=0.05
+    #[clap(long, value_parser = parse_net_conditions)]
+    pub net_conditions: Option<NetworkConditions>,
+
+    /// Seed for the random faults of `--net-conditions`, so a run can be
+    /// reproduced.
+    #[clap(long, requires = "net_conditions", default_value_t = 0)]
+    pub net_seed: u64,
+
     /// Disables the TTY bridge
     #[clap(long = "no-tty")]
     pub no_tty: bool,
@@ -135,6 +171,11 @@ pub struct Wasi {
     #[clap(long = "enable-cpu-backoff")]
     pub enable_cpu_backoff: Option<u64>,
 
+    /// Writes a core dump into this directory whenever a process or thread
+    /// of the WASIX program traps
+    #[clap(long = "coredump-dir")]
+    pub coredump_dir: Option<PathBuf>,
+
     /// Specifies one or more journal files that Wasmer will use to restore
     /// and save the state of the WASM process as it executes.
     ///
@@ -195,6 +236,31 @@ pub struct Wasi {
     #[clap(long)]
     pub http_client: bool,
 
+    /// A TOML file with per-host rules for the http requests instances may
+    /// send (allowed methods and paths, headers to inject or strip, rate
+    /// limits, body size limits and timeouts).
+    ///
+    /// Hosts with a rule can be accessed even without `--http-client`.
+    #[clap(long)]
+    pub http_policy: Option<PathBuf>,
+
+    /// Record every http request instances send, along with its response, to
+    /// a cassette file.
+    #[clap(long, conflicts_with = "http_replay")]
+    pub http_record: Option<PathBuf>,
+
+    /// Answer http requests from a cassette file created with
+    /// `--http-record` instead of using the network.
+    #[clap(long)]
+    pub http_replay: Option<PathBuf>,
+
+    /// Extra conditions for a request to match a recording when using
+    /// `--http-replay`: `body`, `ignore-query` or `header=<NAME>`.
+    ///
+    /// The method and URL always need to match.
+    #[clap(long = "http-replay-match", requires = "http_replay", value_parser = parse_match_rule)]
+    pub http_replay_match: Vec<MatchRule>,
+
     /// Require WASI modules to only import 1 version of WASI.
     #[clap(long = "deny-multiple-wasi-versions")]
     pub deny_multiple_wasi_versions: bool,
@@ -387,7 +453,7 @@ impl Wasi {
             }
         };
 
-        *builder.capabilities_mut() = self.capabilities();
+        *builder.capabilities_mut() = self.capabilities()?;
 
         #[cfg(feature = "journal")]
         {
@@ -547,18 +613,22 @@ impl Wasi {
             .collect::<Result<Vec<_>, anyhow::Error>>()
     }
 
-    pub fn capabilities(&self) -> Capabilities {
+    pub fn capabilities(&self) -> Result<Capabilities> {
         let mut caps = Capabilities::default();
 
         if self.http_client {
             caps.http_client = wasmer_wasix::http::HttpClientCapabilityV1::new_allow_all();
         }
+        if let Some(path) = &self.http_policy {
+            caps.http_client.rules = super::http_policy::load(path)?;
+        }
 
         caps.threading.enable_asynchronous_threading = self.enable_async_threads;
         caps.threading.enable_exponential_cpu_backoff =
             self.enable_cpu_backoff.map(Duration::from_millis);
+        caps.coredump_dir = self.coredump_dir.clone();
 
-        caps
+        Ok(caps)
     }
 
     pub fn prepare_runtime<I>(
@@ -568,7 +638,7 @@ impl Wasi {
         pkg_cache_path: &Path,
         rt_or_handle: I,
         preferred_webc_version: webc::Version,
-    ) -> Result<impl Runtime + Send + Sync>
+    ) -> Result<PluggableRuntime>
     where
         I: Into<RuntimeOrHandle>,
     {
@@ -587,18 +657,27 @@ impl Wasi {
             .map(|ruleset| Ruleset::from_str(&ruleset))
             .transpose()?;
 
-        let network = if let Some(ruleset) = ruleset {
-            virtual_net::host::LocalNetworking::with_ruleset(ruleset)
+        let network: DynVirtualNetworking = if let Some(ruleset) = ruleset {
+            Arc::new(virtual_net::host::LocalNetworking::with_ruleset(ruleset))
         } else {
-            virtual_net::host::LocalNetworking::default()
+            Arc::new(virtual_net::host::LocalNetworking::default())
+        };
+        let network = self.wrap_resolver(network)?;
+        let network: DynVirtualNetworking = match &self.net_conditions {
+            Some(conditions) => Arc::new(
+                ShapedNetworking::new(network)
+                    .with_conditions(conditions.clone())
+                    .with_seed(self.net_seed),
+            ),
+            None => network,
         };
 
         if has_networking {
-            rt.set_networking_implementation(network);
+            rt.networking = network;
         } else {
             let net = super::capabilities::net::AskingNetworking::new(
                 pkg_cache_path.to_path_buf(),
-                Arc::new(network),
+                network,
             );
 
             rt.set_networking_implementation(net);
@@ -610,7 +689,7 @@ impl Wasi {
         }
 
         if !self.no_tty {
-            let tty = Arc::new(SysTty);
+            let tty = Arc::new(SysTty::default());
             tty.reset();
             rt.set_tty(tty);
         }
@@ -619,6 +698,20 @@ impl Wasi {
             wasmer_wasix::http::default_http_client().context("No HTTP client available")?;
         let client = Arc::new(client);
 
+        if let Some(path) = &self.http_record {
+            rt.set_http_client(RecordingHttpClient::new(client.clone(), path));
+        } else if let Some(path) = &self.http_replay {
+            let mut rules = MatchRules::default();
+            for rule in &self.http_replay_match {
+                match rule {
+                    MatchRule::Body => rules.body = true,
+                    MatchRule::IgnoreQuery => rules.query = false,
+                    MatchRule::Header(name) => rules.headers.push(name.clone()),
+                }
+            }
+            rt.set_http_client(ReplayHttpClient::from_file(path)?.with_rules(rules));
+        }
+
         let package_loader = self
             .prepare_package_loader(env, client.clone())
             .context("Unable to prepare the package loader")?;
@@ -665,6 +758,31 @@ impl Wasi {
         })
     }
 
+    /// Put a [`ResolverNetworking`] in front of the networking when any of
+    /// the DNS flags were used.
+    fn wrap_resolver(&self, network: DynVirtualNetworking) -> Result<DynVirtualNetworking> {
+        if self.hosts_file.is_none() && self.dns_server.is_none() && self.dns_zones.is_empty() {
+            return Ok(network);
+        }
+
+        let mut resolver = ResolverNetworking::new(network);
+        if let Some(path) = &self.hosts_file {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Unable to read \"{}\"", path.display()))?;
+            let hosts = HostsFile::parse(&contents)
+                .with_context(|| format!("Unable to parse \"{}\"", path.display()))?;
+            resolver = resolver.with_hosts(hosts);
+        }
+        if let Some(server) = self.dns_server {
+            resolver = resolver.with_upstream(server);
+        }
+        for zone in &self.dns_zones {
+            resolver = resolver.with_zone(zone.clone());
+        }
+
+        Ok(Arc::new(resolver))
+    }
+
     fn prepare_package_loader(
         &self,
         env: &WasmerEnv,
@@ -736,6 +854,83 @@ impl Wasi {
     }
 }
 
+/// A condition for replaying a recorded http request (see
+/// `--http-replay-match`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MatchRule {
+    Body,
+    IgnoreQuery,
+    Header(http::header::HeaderName),
+}
+
+fn parse_match_rule(s: &str) -> Result<MatchRule> {
+    match s {
+        "body" => Ok(MatchRule::Body),
+        "ignore-query" => Ok(MatchRule::IgnoreQuery),
+        _ => match s.strip_prefix("header=") {
+            Some(name) => {
+                Ok(MatchRule::Header(name.parse().with_context(|| {
+                    format!("\"{name}\" isn't a valid header name")
+                })?))
+            }
+            None => bail!("Expected \"body\", \"ignore-query\" or \"header=<NAME>\", not \"{s}\""),
+        },
+    }
+}
+
+fn parse_dns_server(s: &str) -> Result<SocketAddr> {
+    if let Ok(ip) = s.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, virtual_net::resolver::DNS_PORT));
+    }
+    s.parse()
+        .with_context(|| format!("\"{s}\" isn't an IP address or socket address"))
+}
+
+fn parse_dns_zone(s: &str) -> Result<Zone> {
+    let Some((suffix, server)) = s.split_once('=') else {
+        bail!("Expected \"<DOMAIN>=<SERVER>\", not \"{s}\"");
+    };
+    let server = parse_dns_server(server)?;
+    Ok(Zone::new(suffix, ZoneSource::Server(server)))
+}
+
+fn parse_net_conditions(s: &str) -> Result<NetworkConditions> {
+    let mut conditions = NetworkConditions::new();
+
+    for setting in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let Some((key, value)) = setting.split_once('=') else {
+            bail!("Expected \"<KEY>=<VALUE>\", not \"{setting}\"");
+        };
+        let invalid = || format!("\"{value}\" isn't a valid value for \"{key}\"");
+
+        match key {
+            "latency" => {
+                conditions.latency = humantime::parse_duration(value).with_context(invalid)?
+            }
+            "jitter" => {
+                conditions.jitter = humantime::parse_duration(value).with_context(invalid)?
+            }
+            "bandwidth" => conditions.bandwidth = Some(value.parse().with_context(invalid)?),
+            "loss" => conditions.packet_loss = parse_probability(value).with_context(invalid)?,
+            "reset" => {
+                conditions.reset_probability = parse_probability(value).with_context(invalid)?
+            }
+            "reset-after" => conditions.reset_after = Some(value.parse().with_context(invalid)?),
+            _ => bail!("Unknown network condition, \"{key}\""),
+        }
+    }
+
+    Ok(conditions)
+}
+
+fn parse_probability(s: &str) -> Result<f64> {
+    let probability: f64 = s.parse()?;
+    if !(0.0..=1.0).contains(&probability) {
+        bail!("Expected a number between 0 and 1");
+    }
+    Ok(probability)
+}
+
 fn parse_registry(r: &str) -> Result<Url> {
     UserRegistry::from(r).graphql_endpoint()
 }
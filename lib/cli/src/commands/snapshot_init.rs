@@ -0,0 +1,141 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use wasmer::{Imports, Instance, Module, Store};
+use wasmer_wasix::{
+    runners::MappedDirectory,
+    runtime::task_manager::tokio::TokioTaskManager,
+    snapshot_init::{SnapshotInit as Initializer, DEFAULT_INIT_FUNCTION},
+    PluggableRuntime,
+};
+
+use crate::{
+    backend::RuntimeOptions,
+    commands::run::Wasi,
+    utils::{parse_envvar, parse_mapdir},
+};
+
+#[derive(Debug, Parser)]
+/// The options for the `wasmer snapshot-init` subcommand
+pub struct SnapshotInit {
+    #[clap(flatten)]
+    rt: RuntimeOptions,
+
+    /// The exported function that initializes the module
+    #[clap(long = "init-func", default_value = DEFAULT_INIT_FUNCTION)]
+    init_func: String,
+
+    /// Keep exporting the init function from the pre-initialized module
+    #[clap(long = "keep-init-func")]
+    keep_init_func: bool,
+
+    /// WASI pre-opened directory, available while initializing
+    #[clap(long = "dir", name = "DIR")]
+    pre_opened_directories: Vec<PathBuf>,
+
+    /// Map a host directory to a different location while initializing
+    #[clap(
+        long = "mapdir",
+        name = "GUEST_DIR:HOST_DIR",
+        value_parser = parse_mapdir,
+    )]
+    mapped_dirs: Vec<MappedDirectory>,
+
+    /// Pass custom environment variables while initializing
+    #[clap(
+        long = "env",
+        name = "KEY=VALUE",
+        value_parser = parse_envvar,
+    )]
+    env_vars: Vec<(String, String)>,
+
+    /// Output file
+    #[clap(short = 'o', long = "output", name = "OUTPUT PATH")]
+    output: PathBuf,
+
+    /// Input file
+    #[clap(name = "FILE")]
+    path: PathBuf,
+
+    /// Command-line arguments passed to WASI modules while initializing
+    args: Vec<String>,
+}
+
+impl SnapshotInit {
+    /// Runs logic for the `snapshot-init` subcommand
+    pub fn execute(&self) -> Result<()> {
+        self.inner_execute().context(format!(
+            "failed to pre-initialize `{}`",
+            self.path.display()
+        ))
+    }
+
+    fn inner_execute(&self) -> Result<()> {
+        let wasm = std::fs::read(&self.path)
+            .with_context(|| format!("Unable to read \"{}\"", self.path.display()))?;
+
+        let initializer =
+            Initializer::new(&self.init_func).with_keep_init_function(self.keep_init_func);
+        let instrumented = initializer.instrument(&wasm)?;
+
+        let engine = self.rt.get_engine()?;
+        let mut store = Store::new(engine.clone());
+        let module = Module::new(&store, &instrumented)?;
+
+        let snapshot =
+            if wasmer_wasix::is_wasi_module(&module) || wasmer_wasix::is_wasix_module(&module) {
+                let tokio = tokio::runtime::Builder::new_multi_thread()
+                    .enable_all()
+                    .build()?;
+                let _guard = tokio.enter();
+
+                let mut runtime =
+                    PluggableRuntime::new(Arc::new(TokioTaskManager::new(tokio.handle().clone())));
+                runtime.set_engine(Some(engine));
+
+                let mut wasi = Wasi::default();
+                wasi.pre_opened_directories = self.pre_opened_directories.clone();
+                wasi.mapped_dirs = self.mapped_dirs.clone();
+                wasi.env_vars = self.env_vars.clone();
+                wasi.no_tty = true;
+                let builder = wasi.prepare(
+                    &module,
+                    self.path.display().to_string(),
+                    self.args.clone(),
+                    Arc::new(runtime),
+                )?;
+
+                // Note: WASI reactors get their `_initialize` export called while
+                // being instantiated, so it must not be run a second time.
+                let (instance, env) = builder.instantiate(module, &mut store)?;
+                let snapshot = if self.init_func == "_initialize" {
+                    initializer.capture(&mut store, &instance)
+                } else {
+                    initializer.snapshot(&mut store, &instance)
+                };
+                env.on_exit(&mut store, None);
+                snapshot?
+            } else {
+                let instance = Instance::new(&mut store, &module, &Imports::default())
+                    .context("Unable to instantiate the WebAssembly module")?;
+                if self.init_func != "_initialize" {
+                    if let Ok(initialize) = instance.exports.get_function("_initialize") {
+                        initialize.call(&mut store, &[])?;
+                    }
+                }
+                initializer.snapshot(&mut store, &instance)?
+            };
+
+        let output = initializer.rewrite(&wasm, &snapshot)?;
+        std::fs::write(&self.output, output)
+            .with_context(|| format!("Unable to write to \"{}\"", self.output.display()))?;
+
+        eprintln!(
+            "✔ Module pre-initialized successfully to `{}`.",
+            self.output.display(),
+        );
+
+        Ok(())
+    }
+}
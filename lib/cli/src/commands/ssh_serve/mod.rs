@@ -0,0 +1,154 @@
+//! Serve sandboxed WASIX shells over SSH.
+
+mod keys;
+mod session;
+mod transport;
+mod wire;
+
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use tokio::net::TcpListener;
+use virtual_net::UnsupportedVirtualNetworking;
+use wasmer_wasix::PluggableRuntime;
+
+use self::{
+    keys::{AuthorizedKeys, HostKey},
+    session::Connection,
+    transport::Transport,
+};
+use crate::{
+    backend::RuntimeOptions,
+    commands::{run::Wasi, AsyncCliCommand},
+    config::WasmerEnv,
+};
+
+/// The version string sent to clients.
+const SERVER_VERSION: &str = "SSH-2.0-Wasmer";
+/// How long a client has to log in after connecting.
+const LOGIN_GRACE_TIME: Duration = Duration::from_secs(120);
+
+/// Run a local SSH server whose sessions are sandboxed WASIX shells.
+///
+/// Every session runs its own instance of the package (a shell by default)
+/// in a fresh in-memory file system, attached to a pseudo-terminal. Clients
+/// log in with one of the keys listed in an `authorized_keys` file. The
+/// sandbox has no network access unless `--net` is given.
+#[derive(clap::Parser, Debug)]
+pub struct CmdSshServe {
+    #[clap(flatten)]
+    env: WasmerEnv,
+    #[clap(flatten)]
+    rt: RuntimeOptions,
+    #[clap(flatten)]
+    wasi: Wasi,
+    /// The address to listen on.
+    #[clap(long, short, default_value_t = ([127, 0, 0, 1], 2222).into())]
+    addr: SocketAddr,
+    /// The public keys that are allowed to log in.
+    ///
+    /// Defaults to `~/.ssh/authorized_keys`.
+    #[clap(long)]
+    authorized_keys: Option<PathBuf>,
+    /// The server's Ed25519 host key, in the OpenSSH format.
+    ///
+    /// Defaults to `ssh/ssh_host_ed25519_key` in the Wasmer directory and is
+    /// generated if it doesn't exist yet.
+    #[clap(long)]
+    host_key: Option<PathBuf>,
+    /// The package every session runs.
+    #[clap(default_value = "sharrattj/bash")]
+    package: String,
+}
+
+/// State shared by all connections.
+#[derive(Debug)]
+struct Server {
+    host_key: Arc<HostKey>,
+    authorized_keys: AuthorizedKeys,
+    runtime: PluggableRuntime,
+    package: String,
+    env: Vec<(String, String)>,
+}
+
+#[async_trait::async_trait]
+impl AsyncCliCommand for CmdSshServe {
+    type Output = ();
+
+    async fn run_async(self) -> Result<(), anyhow::Error> {
+        let authorized_keys = match self.authorized_keys {
+            Some(path) => path,
+            None => dirs::home_dir()
+                .context("Unable to find the home directory, use --authorized-keys")?
+                .join(".ssh")
+                .join("authorized_keys"),
+        };
+        let authorized_keys = AuthorizedKeys::load(&authorized_keys)?;
+        anyhow::ensure!(
+            !authorized_keys.is_empty(),
+            "No usable keys in the authorized_keys file"
+        );
+
+        let host_key = match self.host_key {
+            Some(path) => path,
+            None => self.env.dir().join("ssh").join("ssh_host_ed25519_key"),
+        };
+        let host_key = Arc::new(HostKey::load_or_generate(&host_key)?);
+
+        let mut wasi = self.wasi;
+        wasi.no_tty = true;
+        let engine = self.rt.get_engine()?;
+        let mut runtime = wasi.prepare_runtime(
+            engine,
+            &self.env,
+            // Sessions never ask whether networking should be enabled
+            &self.env.cache_dir().join("ssh"),
+            tokio::runtime::Handle::current(),
+            webc::Version::V3,
+        )?;
+        if wasi.networking.is_none() {
+            runtime.set_networking_implementation(UnsupportedVirtualNetworking::default());
+        }
+
+        let server = Arc::new(Server {
+            host_key,
+            authorized_keys,
+            runtime,
+            package: self.package,
+            env: wasi.env_vars.clone(),
+        });
+
+        let listener = TcpListener::bind(self.addr)
+            .await
+            .with_context(|| format!("Unable to listen on {}", self.addr))?;
+        eprintln!("Listening on {}", listener.local_addr()?);
+        eprintln!("Host key fingerprint: {}", server.host_key.fingerprint());
+
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let server = server.clone();
+            tokio::spawn(async move {
+                tracing::debug!(%peer, "Accepted a connection");
+                if let Err(e) = serve(stream, server).await {
+                    tracing::debug!(%peer, error = &*e, "Connection closed");
+                }
+            });
+        }
+    }
+}
+
+async fn serve(stream: tokio::net::TcpStream, server: Arc<Server>) -> anyhow::Result<()> {
+    stream.set_nodelay(true)?;
+
+    let login = async {
+        let mut transport =
+            Transport::accept(stream, server.host_key.clone(), SERVER_VERSION).await?;
+        let user = session::authenticate(&mut transport, &server.authorized_keys).await?;
+        anyhow::Ok((transport, user))
+    };
+    let (transport, user) = tokio::time::timeout(LOGIN_GRACE_TIME, login)
+        .await
+        .context("The client didn't log in in time")??;
+
+    Connection::new(transport, server, user).run().await
+}
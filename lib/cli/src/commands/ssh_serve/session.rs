@@ -0,0 +1,938 @@
+//! Authentication ([RFC 4252]) and interactive sessions ([RFC 4254]).
+//!
+//! Every session channel runs the configured package in a [`Console`]. When
+//! the client asked for a terminal the session gets its own pseudo-terminal,
+//! whose slave end becomes the stdio of the program while the master end is
+//! bridged to the channel. Otherwise the program's stdio are plain pipes and
+//! stderr is sent as extended data.
+//!
+//! [RFC 4252]: https://www.rfc-editor.org/rfc/rfc4252
+//! [RFC 4254]: https://www.rfc-editor.org/rfc/rfc4254
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::bail;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::{mpsc, watch, Semaphore},
+    task::{AbortHandle, JoinHandle},
+};
+use virtual_fs::{ArcBoxFile, Pipe, VirtualFile};
+use wasmer_wasix::{
+    os::{pty::Pty, Console, TtyForeground},
+    wasmer_wasix_types::wasi::Signal,
+};
+
+use super::{
+    keys::{self, AuthorizedKeys},
+    transport::{Transport, DISCONNECT_NO_MORE_AUTH_METHODS, DISCONNECT_PROTOCOL_ERROR},
+    wire::*,
+    Server,
+};
+
+/// How many failed authentication attempts a client gets.
+const MAX_AUTH_ATTEMPTS: usize = 10;
+/// How many sessions a single connection may have open at once.
+const MAX_CHANNELS: usize = 10;
+/// How much data the client may send before we acknowledge it.
+const LOCAL_WINDOW: u32 = 2 * 1024 * 1024;
+const LOCAL_MAX_PACKET: u32 = 32 * 1024;
+/// How long we keep forwarding output after the program exited.
+const OUTPUT_LINGER: Duration = Duration::from_millis(100);
+
+const OPEN_ADMINISTRATIVELY_PROHIBITED: u32 = 1;
+const OPEN_RESOURCE_SHORTAGE: u32 = 4;
+
+/// The data type of stderr in `SSH_MSG_CHANNEL_EXTENDED_DATA`.
+const EXTENDED_DATA_STDERR: u32 = 1;
+
+/// Authenticates the client with one of the authorized keys, returning the
+/// user name it logged in as.
+///
+/// The client has to request the `ssh-userauth` service before it may send
+/// authentication requests, anything else is a protocol error.
+pub(super) async fn authenticate(
+    transport: &mut Transport,
+    authorized_keys: &AuthorizedKeys,
+) -> anyhow::Result<String> {
+    let mut attempts = 0;
+    let mut service_accepted = false;
+
+    loop {
+        let packet = transport.next_packet().await?;
+        let mut reader = Reader::new(&packet);
+        match reader.u8()? {
+            MSG_SERVICE_REQUEST => {
+                let service = reader.utf8()?;
+                if service != "ssh-userauth" {
+                    transport
+                        .disconnect(DISCONNECT_PROTOCOL_ERROR, "Unknown service")
+                        .await?;
+                    bail!("The client requested the unknown service \"{service}\"");
+                }
+                service_accepted = true;
+                let mut msg = Writer::message(MSG_SERVICE_ACCEPT);
+                msg.string(service);
+                transport.send(msg.as_bytes()).await?;
+            }
+            MSG_USERAUTH_REQUEST if !service_accepted => {
+                transport
+                    .disconnect(DISCONNECT_PROTOCOL_ERROR, "Expected a service request")
+                    .await?;
+                bail!("The client tried to authenticate before requesting the service");
+            }
+            MSG_USERAUTH_REQUEST => {
+                let user = reader.utf8()?;
+                let service = reader.utf8()?;
+                let method = reader.utf8()?;
+
+                if service == "ssh-connection" && method == "publickey" {
+                    let signed = reader.bool()?;
+                    let algorithm = reader.utf8()?;
+                    let key = reader.string()?;
+                    let acceptable = keys::SIGNATURE_ALGORITHMS.contains(&algorithm)
+                        && authorized_keys.contains(key);
+
+                    if acceptable && !signed {
+                        // The client asks whether it is worth signing with this key
+                        let mut msg = Writer::message(MSG_USERAUTH_PK_OK);
+                        msg.string(algorithm).string(key);
+                        transport.send(msg.as_bytes()).await?;
+                        continue;
+                    }
+
+                    if acceptable {
+                        let signature = reader.string()?;
+                        let mut data = Writer::new();
+                        data.string(transport.session_id())
+                            .u8(MSG_USERAUTH_REQUEST)
+                            .string(user)
+                            .string(service)
+                            .string(method)
+                            .bool(true)
+                            .string(algorithm)
+                            .string(key);
+                        if keys::verify(algorithm, key, data.as_bytes(), signature)? {
+                            tracing::info!(
+                                user,
+                                key = keys::fingerprint(key),
+                                "Client authenticated"
+                            );
+                            transport.send(&[MSG_USERAUTH_SUCCESS]).await?;
+                            return Ok(user.to_string());
+                        }
+                    }
+                }
+
+                if method != "none" {
+                    attempts += 1;
+                }
+                if attempts >= MAX_AUTH_ATTEMPTS {
+                    transport
+                        .disconnect(
+                            DISCONNECT_NO_MORE_AUTH_METHODS,
+                            "Too many authentication failures",
+                        )
+                        .await?;
+                    bail!("Too many authentication failures");
+                }
+                let mut msg = Writer::message(MSG_USERAUTH_FAILURE);
+                msg.name_list(&["publickey"]).bool(false);
+                transport.send(msg.as_bytes()).await?;
+            }
+            MSG_KEXINIT => transport.key_exchange(packet).await?,
+            MSG_IGNORE | MSG_DEBUG | MSG_UNIMPLEMENTED => {}
+            MSG_DISCONNECT => bail!("The client disconnected"),
+            other => {
+                transport
+                    .disconnect(DISCONNECT_PROTOCOL_ERROR, "Not authenticated")
+                    .await?;
+                bail!("Unexpected message {other} before authentication");
+            }
+        }
+    }
+}
+
+/// Something that happened in one of the tasks serving a channel.
+#[derive(Debug)]
+enum Event {
+    /// The program wrote to its terminal or stdout, or to stderr when
+    /// `data_type` is set.
+    Output {
+        channel: u32,
+        data_type: Option<u32>,
+        data: Vec<u8>,
+    },
+    /// Data sent by the client was passed on to the program.
+    Consumed { channel: u32, amount: u32 },
+    /// The program exited and all of its output was forwarded.
+    Exited { channel: u32, code: u32 },
+}
+
+#[derive(Debug)]
+struct Channel {
+    remote_id: u32,
+    remote_max_packet: u32,
+    /// How much data we may still send to the client.
+    remote_window: Arc<Semaphore>,
+    /// How much data the client may still send to us.
+    local_window: u32,
+    /// Data that was passed on to the program but not acknowledged yet.
+    consumed: u32,
+    term: Option<String>,
+    size: (u32, u32, u32, u32),
+    env: HashMap<String, String>,
+    started: bool,
+    pty: Option<Pty>,
+    /// The process group of the program, once it started.
+    group: Arc<Mutex<Option<TtyForeground>>>,
+    input: Option<mpsc::UnboundedSender<Vec<u8>>>,
+    tasks: Vec<AbortHandle>,
+    closed: bool,
+}
+
+impl Channel {
+    /// Where signals sent by the client go: the foreground job of the
+    /// terminal, or the program itself.
+    fn signal_target(&self) -> Option<TtyForeground> {
+        self.pty
+            .as_ref()
+            .and_then(|pty| pty.foreground())
+            .or_else(|| self.group.lock().unwrap().clone())
+    }
+
+    /// Kills the program, along with whichever job is in the foreground of
+    /// its terminal.
+    fn kill(&self) {
+        let group = self.group.lock().unwrap().clone();
+        let foreground = self.pty.as_ref().and_then(|pty| pty.foreground());
+        for group in group.into_iter().chain(foreground) {
+            group.signal(Signal::Sigkill);
+        }
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        // Programs which ignore the hang up must not outlive the session
+        self.kill();
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// An authenticated connection.
+pub(super) struct Connection {
+    transport: Transport,
+    server: Arc<Server>,
+    user: String,
+    channels: HashMap<u32, Channel>,
+    next_channel: u32,
+    events: mpsc::UnboundedSender<Event>,
+    events_rx: mpsc::UnboundedReceiver<Event>,
+}
+
+impl Connection {
+    pub fn new(transport: Transport, server: Arc<Server>, user: String) -> Self {
+        let (events, events_rx) = mpsc::unbounded_channel();
+        Connection {
+            transport,
+            server,
+            user,
+            channels: HashMap::new(),
+            next_channel: 0,
+            events,
+            events_rx,
+        }
+    }
+
+    pub async fn run(mut self) -> anyhow::Result<()> {
+        loop {
+            tokio::select! {
+                packet = self.transport.next_packet() => {
+                    if !self.on_packet(packet?).await? {
+                        return Ok(());
+                    }
+                }
+                Some(event) = self.events_rx.recv() => self.on_event(event).await?,
+            }
+        }
+    }
+
+    /// Handles a message from the client, returning `false` once the client
+    /// disconnected.
+    async fn on_packet(&mut self, packet: Vec<u8>) -> anyhow::Result<bool> {
+        let mut reader = Reader::new(&packet);
+        match reader.u8()? {
+            MSG_DISCONNECT => return Ok(false),
+            MSG_KEXINIT => self.transport.key_exchange(packet).await?,
+            MSG_GLOBAL_REQUEST => {
+                let _name = reader.utf8()?;
+                if reader.bool()? {
+                    self.transport.send(&[MSG_REQUEST_FAILURE]).await?;
+                }
+            }
+            MSG_CHANNEL_OPEN => {
+                let kind = reader.utf8()?;
+                let remote_id = reader.u32()?;
+                let window = reader.u32()?;
+                let max_packet = reader.u32()?;
+                self.open_channel(kind, remote_id, window, max_packet)
+                    .await?;
+            }
+            MSG_CHANNEL_WINDOW_ADJUST => {
+                let id = reader.u32()?;
+                let amount = reader.u32()?;
+                if let Some(channel) = self.channels.get(&id) {
+                    channel.remote_window.add_permits(amount as usize);
+                }
+            }
+            msg @ (MSG_CHANNEL_DATA | MSG_CHANNEL_EXTENDED_DATA) => {
+                let id = reader.u32()?;
+                if msg == MSG_CHANNEL_EXTENDED_DATA {
+                    let _data_type = reader.u32()?;
+                }
+                let data = reader.string()?;
+                let Some(channel) = self.channels.get_mut(&id) else {
+                    return Ok(true);
+                };
+                if data.len() as u32 > channel.local_window {
+                    bail!("The client sent more data than the window allows");
+                }
+                channel.local_window -= data.len() as u32;
+
+                let consumed = match &channel.input {
+                    Some(input) if msg == MSG_CHANNEL_DATA => input.send(data.to_vec()).is_err(),
+                    _ => true,
+                };
+                if consumed {
+                    self.on_consumed(id, data.len() as u32).await?;
+                }
+            }
+            MSG_CHANNEL_EOF => {
+                let id = reader.u32()?;
+                if let Some(channel) = self.channels.get_mut(&id) {
+                    // The input task sends an end-of-file once it is done
+                    channel.input.take();
+                }
+            }
+            MSG_CHANNEL_CLOSE => {
+                let id = reader.u32()?;
+                if let Some(channel) = self.channels.remove(&id) {
+                    if !channel.closed {
+                        let mut msg = Writer::message(MSG_CHANNEL_CLOSE);
+                        msg.u32(channel.remote_id);
+                        self.transport.send(msg.as_bytes()).await?;
+                    }
+                }
+            }
+            MSG_CHANNEL_REQUEST => {
+                let id = reader.u32()?;
+                let request = reader.utf8()?;
+                let want_reply = reader.bool()?;
+                let success = self.on_request(id, request, &mut reader)?;
+                if let Some(channel) = self.channels.get(&id) {
+                    if want_reply {
+                        let reply = if success {
+                            MSG_CHANNEL_SUCCESS
+                        } else {
+                            MSG_CHANNEL_FAILURE
+                        };
+                        let mut msg = Writer::message(reply);
+                        msg.u32(channel.remote_id);
+                        self.transport.send(msg.as_bytes()).await?;
+                    }
+                }
+            }
+            // Authentication requests after a successful login are ignored
+            MSG_IGNORE | MSG_DEBUG | MSG_UNIMPLEMENTED | MSG_USERAUTH_REQUEST => {}
+            MSG_CHANNEL_SUCCESS | MSG_CHANNEL_FAILURE => {}
+            _ => {
+                let mut msg = Writer::message(MSG_UNIMPLEMENTED);
+                msg.u32(self.transport.last_sequence());
+                self.transport.send(msg.as_bytes()).await?;
+            }
+        }
+
+        Ok(true)
+    }
+
+    async fn open_channel(
+        &mut self,
+        kind: &str,
+        remote_id: u32,
+        window: u32,
+        max_packet: u32,
+    ) -> anyhow::Result<()> {
+        let failure = if kind != "session" {
+            Some((
+                OPEN_ADMINISTRATIVELY_PROHIBITED,
+                "Only sessions are supported",
+            ))
+        } else if self.channels.len() >= MAX_CHANNELS {
+            Some((OPEN_RESOURCE_SHORTAGE, "Too many sessions"))
+        } else {
+            None
+        };
+        if let Some((reason, description)) = failure {
+            let mut msg = Writer::message(MSG_CHANNEL_OPEN_FAILURE);
+            msg.u32(remote_id)
+                .u32(reason)
+                .string(description)
+                .string("");
+            return self.transport.send(msg.as_bytes()).await;
+        }
+
+        let id = self.next_channel;
+        self.next_channel += 1;
+        self.channels.insert(
+            id,
+            Channel {
+                remote_id,
+                remote_max_packet: max_packet,
+                remote_window: Arc::new(Semaphore::new(window as usize)),
+                local_window: LOCAL_WINDOW,
+                consumed: 0,
+                term: None,
+                size: (80, 24, 0, 0),
+                env: HashMap::new(),
+                started: false,
+                pty: None,
+                group: Arc::new(Mutex::new(None)),
+                input: None,
+                tasks: Vec::new(),
+                closed: false,
+            },
+        );
+
+        let mut msg = Writer::message(MSG_CHANNEL_OPEN_CONFIRMATION);
+        msg.u32(remote_id)
+            .u32(id)
+            .u32(LOCAL_WINDOW)
+            .u32(LOCAL_MAX_PACKET);
+        self.transport.send(msg.as_bytes()).await
+    }
+
+    /// Handles a channel request, returning whether it succeeded.
+    fn on_request(&mut self, id: u32, request: &str, reader: &mut Reader) -> anyhow::Result<bool> {
+        let Some(channel) = self.channels.get_mut(&id) else {
+            return Ok(false);
+        };
+        let started = channel.started;
+
+        match request {
+            "pty-req" if !started => {
+                channel.term = Some(reader.utf8()?.to_string());
+                channel.size = (reader.u32()?, reader.u32()?, reader.u32()?, reader.u32()?);
+            }
+            "env" if !started => {
+                let name = reader.utf8()?;
+                let value = reader.utf8()?;
+                channel.env.insert(name.to_string(), value.to_string());
+            }
+            "window-change" => {
+                channel.size = (reader.u32()?, reader.u32()?, reader.u32()?, reader.u32()?);
+                if let Some(pty) = &channel.pty {
+                    let (cols, rows, width, height) = channel.size;
+                    let mut state = pty.state();
+                    state.cols = cols;
+                    state.rows = rows;
+                    state.width = width;
+                    state.height = height;
+                    pty.set_state(state);
+                }
+            }
+            "signal" => {
+                let signal = match reader.utf8()? {
+                    "HUP" => Signal::Sighup,
+                    "INT" => Signal::Sigint,
+                    "QUIT" => Signal::Sigquit,
+                    "KILL" => Signal::Sigkill,
+                    "TERM" => Signal::Sigterm,
+                    "USR1" => Signal::Sigusr1,
+                    "USR2" => Signal::Sigusr2,
+                    _ => return Ok(false),
+                };
+                match channel.signal_target() {
+                    Some(target) => {
+                        target.signal(signal);
+                    }
+                    None => return Ok(false),
+                }
+            }
+            "shell" if !started => self.start(id, None),
+            "exec" if !started => {
+                let command = reader.utf8()?.to_string();
+                self.start(id, Some(command));
+            }
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    /// Starts the package for a session, running `command` instead of an
+    /// interactive shell if one was given.
+    fn start(&mut self, id: u32, command: Option<String>) {
+        let channel = self.channels.get_mut(&id).unwrap();
+        channel.started = true;
+
+        let mut env: HashMap<String, String> = self.server.env.iter().cloned().collect();
+        env.extend(channel.env.clone());
+        env.insert("USER".to_string(), self.user.clone());
+        env.insert("LOGNAME".to_string(), self.user.clone());
+        if let Some(term) = &channel.term {
+            env.insert("TERM".to_string(), term.clone());
+        }
+
+        let package = &self.server.package;
+        let boot_cmd = match command {
+            Some(command) => format!("{package} {command}"),
+            None => package.clone(),
+        };
+
+        let max_packet = channel.remote_max_packet.clamp(1, LOCAL_MAX_PACKET) as usize;
+        let (exited_tx, exited_rx) = watch::channel(false);
+        let (input, input_rx) = mpsc::unbounded_channel();
+        let forward = |reader: Box<dyn AsyncRead + Send + Unpin>, data_type| {
+            tokio::spawn(forward_output(
+                reader,
+                data_type,
+                channel.remote_window.clone(),
+                max_packet,
+                exited_rx.clone(),
+                self.events.clone(),
+                id,
+            ))
+        };
+
+        let mut runtime = self.server.runtime.clone();
+        let outputs: Vec<JoinHandle<()>>;
+        let input_task;
+        let stdio: [Box<dyn VirtualFile + Send + Sync>; 3];
+        if channel.term.is_some() {
+            let (master, slave) = Pty::open();
+            let pty = master.pty().clone();
+            let mut state = pty.state();
+            (state.cols, state.rows, state.width, state.height) = channel.size;
+            pty.set_state(state);
+            runtime.set_tty(Arc::new(pty.clone()));
+
+            let slave = ArcBoxFile::new(Box::new(slave));
+            stdio = [
+                Box::new(slave.clone()),
+                Box::new(slave.clone()),
+                Box::new(slave),
+            ];
+            let (reader, writer) = tokio::io::split(master);
+            outputs = vec![forward(Box::new(reader), None)];
+            input_task = tokio::spawn(forward_input(writer, input_rx, self.events.clone(), id));
+            channel.pty = Some(pty);
+        } else {
+            // Without a terminal the client expects raw bytes in and out
+            let (stdin, program_stdin) = Pipe::channel();
+            let (program_stdout, stdout) = Pipe::channel();
+            let (program_stderr, stderr) = Pipe::channel();
+            stdio = [
+                Box::new(program_stdin),
+                Box::new(program_stdout),
+                Box::new(program_stderr),
+            ];
+            outputs = vec![
+                forward(Box::new(stdout), None),
+                forward(Box::new(stderr), Some(EXTENDED_DATA_STDERR)),
+            ];
+            input_task = tokio::spawn(forward_input(stdin, input_rx, self.events.clone(), id));
+        }
+
+        let [stdin, stdout, stderr] = stdio;
+        let mut console = Console::new(package, Arc::new(runtime))
+            .with_boot_cmd(boot_cmd)
+            .with_env(env)
+            .with_no_welcome(true)
+            .with_stdin(stdin)
+            .with_stdout(stdout)
+            .with_stderr(stderr);
+
+        channel
+            .tasks
+            .extend(outputs.iter().map(|task| task.abort_handle()));
+        let process = tokio::spawn({
+            let pty = channel.pty.clone();
+            let group = channel.group.clone();
+            let events = self.events.clone();
+            async move {
+                let started = tokio::task::spawn_blocking(move || console.run()).await;
+                let code = match started {
+                    Ok(Ok((mut handle, process))) => {
+                        let foreground = TtyForeground::from_process(&process);
+                        *group.lock().unwrap() = Some(foreground.clone());
+                        if let Some(pty) = &pty {
+                            pty.set_foreground(Some(foreground));
+                        }
+                        match handle.wait_finished().await {
+                            Ok(code) => code.raw() as u32,
+                            Err(_) => 1,
+                        }
+                    }
+                    Ok(Err(e)) => {
+                        tracing::warn!(
+                            error = &e as &dyn std::error::Error,
+                            "Unable to start the package"
+                        );
+                        127
+                    }
+                    Err(_) => 1,
+                };
+
+                // Forward all of the output before reporting the exit
+                exited_tx.send(true).ok();
+                for output in outputs {
+                    output.await.ok();
+                }
+                events.send(Event::Exited { channel: id, code }).ok();
+            }
+        });
+
+        channel.input = Some(input);
+        channel
+            .tasks
+            .extend([process.abort_handle(), input_task.abort_handle()]);
+    }
+
+    async fn on_event(&mut self, event: Event) -> anyhow::Result<()> {
+        match event {
+            Event::Output {
+                channel,
+                data_type,
+                data,
+            } => {
+                if let Some(channel) = self.channels.get(&channel) {
+                    let mut msg = match data_type {
+                        Some(data_type) => {
+                            let mut msg = Writer::message(MSG_CHANNEL_EXTENDED_DATA);
+                            msg.u32(channel.remote_id).u32(data_type);
+                            msg
+                        }
+                        None => {
+                            let mut msg = Writer::message(MSG_CHANNEL_DATA);
+                            msg.u32(channel.remote_id);
+                            msg
+                        }
+                    };
+                    msg.string(data);
+                    self.transport.send(msg.as_bytes()).await?;
+                }
+            }
+            Event::Consumed { channel, amount } => self.on_consumed(channel, amount).await?,
+            Event::Exited { channel, code } => {
+                if let Some(channel) = self.channels.get_mut(&channel) {
+                    let mut msg = Writer::message(MSG_CHANNEL_REQUEST);
+                    msg.u32(channel.remote_id)
+                        .string("exit-status")
+                        .bool(false)
+                        .u32(code);
+                    self.transport.send(msg.as_bytes()).await?;
+
+                    for reply in [MSG_CHANNEL_EOF, MSG_CHANNEL_CLOSE] {
+                        let mut msg = Writer::message(reply);
+                        msg.u32(channel.remote_id);
+                        self.transport.send(msg.as_bytes()).await?;
+                    }
+                    channel.closed = true;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Gives the client more room to send data once half of the window was
+    /// passed on to the program.
+    async fn on_consumed(&mut self, id: u32, amount: u32) -> anyhow::Result<()> {
+        let Some(channel) = self.channels.get_mut(&id) else {
+            return Ok(());
+        };
+        channel.consumed += amount;
+        if channel.consumed >= LOCAL_WINDOW / 2 {
+            let adjust = std::mem::take(&mut channel.consumed);
+            channel.local_window += adjust;
+            let mut msg = Writer::message(MSG_CHANNEL_WINDOW_ADJUST);
+            msg.u32(channel.remote_id).u32(adjust);
+            self.transport.send(msg.as_bytes()).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads at most as much output as the client's window allows.
+///
+/// This is cancel safe, window space is only used up once data was read.
+async fn read_output(
+    reader: &mut (impl AsyncRead + Unpin),
+    window: &Semaphore,
+    max_packet: usize,
+) -> Option<Vec<u8>> {
+    let permit = window.acquire().await.ok()?;
+    let mut buffer = vec![0; (window.available_permits() + 1).min(max_packet)];
+    let read = reader.read(&mut buffer).await.ok()?;
+    if read == 0 {
+        return None;
+    }
+
+    permit.forget();
+    if let Ok(permits) = window.try_acquire_many(read as u32 - 1) {
+        permits.forget();
+    }
+    buffer.truncate(read);
+    Some(buffer)
+}
+
+/// Forwards one output stream of the program until it ends, or shortly
+/// after the program exited.
+async fn forward_output(
+    mut reader: Box<dyn AsyncRead + Send + Unpin>,
+    data_type: Option<u32>,
+    window: Arc<Semaphore>,
+    max_packet: usize,
+    mut exited: watch::Receiver<bool>,
+    events: mpsc::UnboundedSender<Event>,
+    channel: u32,
+) {
+    let send = |data| {
+        events
+            .send(Event::Output {
+                channel,
+                data_type,
+                data,
+            })
+            .is_ok()
+    };
+
+    loop {
+        tokio::select! {
+            data = read_output(&mut reader, &window, max_packet) => match data {
+                Some(data) => {
+                    if !send(data) {
+                        return;
+                    }
+                }
+                None => return,
+            },
+            _ = exited.wait_for(|exited| *exited) => break,
+        }
+    }
+
+    // Forward whatever the program wrote right before it exited
+    while let Ok(Some(data)) =
+        tokio::time::timeout(OUTPUT_LINGER, read_output(&mut reader, &window, max_packet)).await
+    {
+        if !send(data) {
+            return;
+        }
+    }
+}
+
+async fn forward_input(
+    mut writer: impl AsyncWrite + Unpin,
+    mut input: mpsc::UnboundedReceiver<Vec<u8>>,
+    events: mpsc::UnboundedSender<Event>,
+    channel: u32,
+) {
+    while let Some(data) = input.recv().await {
+        if writer.write_all(&data).await.is_err() {
+            return;
+        }
+        let amount = data.len() as u32;
+        if events.send(Event::Consumed { channel, amount }).is_err() {
+            return;
+        }
+    }
+
+    // The client won't send anything else, so close the program's stdin
+    writer.shutdown().await.ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+    use crate::commands::ssh_serve::{keys::HostKey, SERVER_VERSION};
+
+    /// Starts authenticating a client that talks plain packets.
+    async fn unencrypted_login() -> (Transport, JoinHandle<anyhow::Result<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+
+        let login = tokio::spawn(async move {
+            let keys = AuthorizedKeys::parse("").unwrap();
+            authenticate(&mut Transport::unencrypted(server), &keys).await
+        });
+        (Transport::unencrypted(client), login)
+    }
+
+    fn userauth_none() -> Vec<u8> {
+        let mut msg = Writer::message(MSG_USERAUTH_REQUEST);
+        msg.string("alice").string("ssh-connection").string("none");
+        msg.into_bytes()
+    }
+
+    #[tokio::test]
+    async fn authentication_requires_the_service_request() {
+        let (mut client, login) = unencrypted_login().await;
+
+        client.send(&userauth_none()).await.unwrap();
+
+        let packet = client.next_packet().await.unwrap();
+        let mut reader = Reader::new(&packet);
+        assert_eq!(reader.u8().unwrap(), MSG_DISCONNECT);
+        assert_eq!(reader.u32().unwrap(), DISCONNECT_PROTOCOL_ERROR);
+        assert!(login.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn authentication_after_the_service_request() {
+        let (mut client, login) = unencrypted_login().await;
+
+        let mut msg = Writer::message(MSG_SERVICE_REQUEST);
+        msg.string("ssh-userauth");
+        client.send(msg.as_bytes()).await.unwrap();
+        client.send(&userauth_none()).await.unwrap();
+
+        let packet = client.next_packet().await.unwrap();
+        let mut reader = Reader::new(&packet);
+        assert_eq!(reader.u8().unwrap(), MSG_SERVICE_ACCEPT);
+        assert_eq!(reader.utf8().unwrap(), "ssh-userauth");
+        let packet = client.next_packet().await.unwrap();
+        let mut reader = Reader::new(&packet);
+        assert_eq!(reader.u8().unwrap(), MSG_USERAUTH_FAILURE);
+        assert_eq!(reader.name_list().unwrap(), ["publickey"]);
+        login.abort();
+    }
+
+    #[tokio::test]
+    async fn openssh_client_logs_in() {
+        if Command::new("ssh").arg("-V").output().is_err() {
+            eprintln!("Skipping, the OpenSSH client isn't installed");
+            return;
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let identity = dir.path().join("id_ed25519");
+        let status = Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-f"])
+            .arg(&identity)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let public = std::fs::read_to_string(identity.with_extension("pub")).unwrap();
+        let authorized_keys = AuthorizedKeys::parse(&public).unwrap();
+
+        let host_key = Arc::new(HostKey::generate().unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let known_hosts = dir.path().join("known_hosts");
+        std::fs::write(
+            &known_hosts,
+            format!(
+                "[127.0.0.1]:{port} {} {}\n",
+                HostKey::ALGORITHM,
+                STANDARD.encode(host_key.public_blob())
+            ),
+        )
+        .unwrap();
+
+        let client = tokio::task::spawn_blocking(move || {
+            Command::new("ssh")
+                .args(["-F", "/dev/null", "-N", "-v", "-p", &port.to_string(), "-i"])
+                .arg(&identity)
+                .args(["-o", "IdentitiesOnly=yes", "-o", "BatchMode=yes"])
+                .args(["-o", "StrictHostKeyChecking=yes", "-o"])
+                .arg(format!("UserKnownHostsFile={}", known_hosts.display()))
+                .arg("alice@127.0.0.1")
+                .output()
+                .unwrap()
+        });
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut transport = Transport::accept(stream, host_key, SERVER_VERSION)
+            .await
+            .unwrap();
+        let user = authenticate(&mut transport, &authorized_keys)
+            .await
+            .unwrap();
+        assert_eq!(user, "alice");
+        transport.disconnect(11, "Done").await.unwrap();
+
+        let output = client.await.unwrap();
+        let log = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            log.contains("Authenticated to 127.0.0.1"),
+            "Unexpected output: {log}"
+        );
+    }
+
+    #[tokio::test]
+    async fn end_of_input_closes_stdin() {
+        let (stdin, mut program_stdin) = Pipe::channel();
+        let (events, mut events_rx) = mpsc::unbounded_channel();
+        let (input, input_rx) = mpsc::unbounded_channel();
+        let task = tokio::spawn(forward_input(stdin, input_rx, events, 0));
+
+        input.send(b"hello".to_vec()).unwrap();
+        drop(input);
+        task.await.unwrap();
+
+        let mut received = Vec::new();
+        program_stdin.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"hello");
+        assert!(matches!(
+            events_rx.recv().await,
+            Some(Event::Consumed {
+                channel: 0,
+                amount: 5
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn stderr_is_forwarded_as_extended_data() {
+        let (mut program_stderr, stderr) = Pipe::channel();
+        let (events, mut events_rx) = mpsc::unbounded_channel();
+        let (_exited_tx, exited_rx) = watch::channel(false);
+        let task = tokio::spawn(forward_output(
+            Box::new(stderr),
+            Some(EXTENDED_DATA_STDERR),
+            Arc::new(Semaphore::new(1024)),
+            1024,
+            exited_rx,
+            events,
+            3,
+        ));
+
+        program_stderr.write_all(b"oops").await.unwrap();
+        drop(program_stderr);
+        task.await.unwrap();
+
+        match events_rx.recv().await {
+            Some(Event::Output {
+                channel: 3,
+                data_type: Some(EXTENDED_DATA_STDERR),
+                data,
+            }) => assert_eq!(data, b"oops"),
+            other => panic!("Unexpected event {other:?}"),
+        }
+    }
+}
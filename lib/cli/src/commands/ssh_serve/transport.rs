@@ -0,0 +1,562 @@
+//! The SSH transport layer ([RFC 4253]).
+//!
+//! Only a single, modern set of algorithms is supported: `curve25519-sha256`
+//! key exchange, `ssh-ed25519` host keys and the
+//! `chacha20-poly1305@openssh.com` cipher. Every OpenSSH release from the
+//! last decade negotiates these out of the box.
+//!
+//! [RFC 4253]: https://www.rfc-editor.org/rfc/rfc4253
+
+use std::sync::Arc;
+
+use anyhow::bail;
+use ring::{
+    aead::chacha20_poly1305_openssh::{OpeningKey, SealingKey, KEY_LEN, TAG_LEN},
+    agreement::{self, EphemeralPrivateKey, X25519},
+    rand::{SecureRandom, SystemRandom},
+};
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use super::{
+    keys::{HostKey, SIGNATURE_ALGORITHMS},
+    wire::*,
+};
+
+const KEX_ALGORITHMS: &[&str] = &["curve25519-sha256", "curve25519-sha256@libssh.org"];
+const CIPHER: &str = "chacha20-poly1305@openssh.com";
+/// Never used, the cipher authenticates packets itself.
+const MAC: &str = "hmac-sha2-256";
+const STRICT_KEX_CLIENT: &str = "kex-strict-c-v00@openssh.com";
+const STRICT_KEX_SERVER: &str = "kex-strict-s-v00@openssh.com";
+const EXT_INFO_CLIENT: &str = "ext-info-c";
+
+/// Largest packet we are willing to receive.
+const MAX_PACKET_LEN: usize = 256 * 1024;
+
+pub(super) const DISCONNECT_PROTOCOL_ERROR: u32 = 2;
+pub(super) const DISCONNECT_KEY_EXCHANGE_FAILED: u32 = 3;
+pub(super) const DISCONNECT_NO_MORE_AUTH_METHODS: u32 = 14;
+
+/// An encrypted connection with a client.
+pub(super) struct Transport {
+    stream: TcpStream,
+    host_key: Arc<HostKey>,
+    rng: SystemRandom,
+    client_version: Vec<u8>,
+    server_version: Vec<u8>,
+    /// The exchange hash of the first key exchange.
+    session_id: Option<Vec<u8>>,
+    /// Set when the client supports "strict kex" (CVE-2023-48795).
+    strict_kex: bool,
+    /// Bytes received from the client that are not part of a packet yet.
+    buffer: Vec<u8>,
+    recv_seq: u32,
+    send_seq: u32,
+    opening: Option<OpeningKey>,
+    sealing: Option<SealingKey>,
+}
+
+impl Transport {
+    /// Exchanges version strings and runs the initial key exchange.
+    pub async fn accept(
+        mut stream: TcpStream,
+        host_key: Arc<HostKey>,
+        server_version: &str,
+    ) -> anyhow::Result<Self> {
+        stream
+            .write_all(format!("{server_version}\r\n").as_bytes())
+            .await?;
+
+        // The client may send other lines before its version string
+        let mut buffer = Vec::new();
+        let client_version = loop {
+            if let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                let mut line: Vec<u8> = buffer.drain(..=end).collect();
+                line.pop();
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                if line.starts_with(b"SSH-") {
+                    if !line.starts_with(b"SSH-2.0-") {
+                        bail!("Unsupported protocol version");
+                    }
+                    break line;
+                }
+                continue;
+            }
+            if buffer.len() > 8 * 1024 {
+                bail!("The client didn't send a version string");
+            }
+            if stream.read_buf(&mut buffer).await? == 0 {
+                bail!("The client disconnected");
+            }
+        };
+
+        let mut transport = Transport {
+            stream,
+            host_key,
+            rng: SystemRandom::new(),
+            client_version,
+            server_version: server_version.as_bytes().to_vec(),
+            session_id: None,
+            strict_kex: false,
+            buffer,
+            recv_seq: 0,
+            send_seq: 0,
+            opening: None,
+            sealing: None,
+        };
+
+        let client_kexinit = loop {
+            let packet = transport.next_packet().await?;
+            match packet.first() {
+                Some(&MSG_KEXINIT) => break packet,
+                Some(&MSG_IGNORE | &MSG_DEBUG) => {}
+                _ => bail!("Expected the client's KEXINIT"),
+            }
+        };
+        transport.key_exchange(client_kexinit).await?;
+
+        Ok(transport)
+    }
+
+    /// Wraps a stream without exchanging keys, so tests can talk plain
+    /// packets to either side.
+    #[cfg(test)]
+    pub fn unencrypted(stream: TcpStream) -> Self {
+        Transport {
+            stream,
+            host_key: Arc::new(HostKey::generate().unwrap()),
+            rng: SystemRandom::new(),
+            client_version: b"SSH-2.0-Client".to_vec(),
+            server_version: b"SSH-2.0-Server".to_vec(),
+            session_id: Some(vec![0; 32]),
+            strict_kex: false,
+            buffer: Vec::new(),
+            recv_seq: 0,
+            send_seq: 0,
+            opening: None,
+            sealing: None,
+        }
+    }
+
+    /// The identifier of this connection, as signed during authentication.
+    pub fn session_id(&self) -> &[u8] {
+        self.session_id.as_deref().unwrap_or_default()
+    }
+
+    /// The sequence number of the last packet that was received.
+    pub fn last_sequence(&self) -> u32 {
+        self.recv_seq.wrapping_sub(1)
+    }
+
+    /// Waits for the next packet, returning its payload.
+    ///
+    /// This is cancel safe, so it can be used in a `select!`.
+    pub async fn next_packet(&mut self) -> anyhow::Result<Vec<u8>> {
+        loop {
+            if let Some(payload) = self.try_decode()? {
+                return Ok(payload);
+            }
+            if self.stream.read_buf(&mut self.buffer).await? == 0 {
+                bail!("The client disconnected");
+            }
+        }
+    }
+
+    fn try_decode(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+        if self.buffer.len() < 4 {
+            return Ok(None);
+        }
+        let length: [u8; 4] = self.buffer[..4].try_into().unwrap();
+        let length = match &self.opening {
+            Some(key) => key.decrypt_packet_length(self.recv_seq, length),
+            None => length,
+        };
+        let length = u32::from_be_bytes(length) as usize;
+        if !(5..=MAX_PACKET_LEN).contains(&length) {
+            bail!("Invalid packet length: {length}");
+        }
+        let tag_len = if self.opening.is_some() { TAG_LEN } else { 0 };
+        if self.buffer.len() < 4 + length + tag_len {
+            return Ok(None);
+        }
+
+        let mut packet: Vec<u8> = self.buffer.drain(..4 + length + tag_len).collect();
+        let plaintext = match &self.opening {
+            Some(key) => {
+                let (data, tag) = packet.split_at_mut(4 + length);
+                let tag: &[u8; TAG_LEN] = (&*tag).try_into().unwrap();
+                key.open_in_place(self.recv_seq, data, tag)
+                    .map_err(|_| anyhow::anyhow!("Packet authentication failed"))?
+            }
+            None => &packet[4..],
+        };
+        self.recv_seq = self.recv_seq.wrapping_add(1);
+
+        let padding = plaintext[0] as usize;
+        if padding + 1 >= plaintext.len() {
+            bail!("Invalid padding");
+        }
+        Ok(Some(plaintext[1..plaintext.len() - padding].to_vec()))
+    }
+
+    pub async fn send(&mut self, payload: &[u8]) -> anyhow::Result<()> {
+        // The packet length isn't part of the padded data for the AEAD cipher
+        let aligned = if self.sealing.is_some() { 1 } else { 5 } + payload.len();
+        let mut padding = 8 - aligned % 8;
+        if padding < 4 {
+            padding += 8;
+        }
+
+        let length = 1 + payload.len() + padding;
+        let mut packet = Vec::with_capacity(4 + length + TAG_LEN);
+        packet.extend_from_slice(&(length as u32).to_be_bytes());
+        packet.push(padding as u8);
+        packet.extend_from_slice(payload);
+        let start = packet.len();
+        packet.resize(start + padding, 0);
+        self.rng
+            .fill(&mut packet[start..])
+            .map_err(|_| anyhow::anyhow!("Unable to generate padding"))?;
+
+        if let Some(key) = &self.sealing {
+            let mut tag = [0; TAG_LEN];
+            key.seal_in_place(self.send_seq, &mut packet, &mut tag);
+            packet.extend_from_slice(&tag);
+        }
+        self.send_seq = self.send_seq.wrapping_add(1);
+
+        self.stream.write_all(&packet).await?;
+        Ok(())
+    }
+
+    pub async fn disconnect(&mut self, reason: u32, description: &str) -> anyhow::Result<()> {
+        let mut msg = Writer::message(MSG_DISCONNECT);
+        msg.u32(reason).string(description).string("");
+        self.send(msg.as_bytes()).await
+    }
+
+    /// Runs a key exchange in response to the client's KEXINIT. This is
+    /// used for the initial exchange and when the client re-keys.
+    pub async fn key_exchange(&mut self, client_kexinit: Vec<u8>) -> anyhow::Result<()> {
+        let first = self.session_id.is_none();
+
+        let mut cookie = [0; 16];
+        self.rng
+            .fill(&mut cookie)
+            .map_err(|_| anyhow::anyhow!("Unable to generate a cookie"))?;
+        let mut kex_algorithms = KEX_ALGORITHMS.to_vec();
+        if first {
+            kex_algorithms.push(STRICT_KEX_SERVER);
+        }
+        let mut server_kexinit = Writer::message(MSG_KEXINIT);
+        server_kexinit
+            .bytes(&cookie)
+            .name_list(&kex_algorithms)
+            .name_list(&[HostKey::ALGORITHM])
+            .name_list(&[CIPHER])
+            .name_list(&[CIPHER])
+            .name_list(&[MAC])
+            .name_list(&[MAC])
+            .name_list(&["none"])
+            .name_list(&["none"])
+            .name_list(&[])
+            .name_list(&[])
+            .bool(false)
+            .u32(0);
+        let server_kexinit = server_kexinit.into_bytes();
+        self.send(&server_kexinit).await?;
+
+        let mut reader = Reader::new(&client_kexinit);
+        reader.u8()?;
+        reader.bytes(16)?;
+        let client_kex = reader.name_list()?;
+        let client_host_keys = reader.name_list()?;
+        let client_ciphers = reader.name_list()?;
+        let server_ciphers = reader.name_list()?;
+        let _macs = (reader.name_list()?, reader.name_list()?);
+        let client_compression = reader.name_list()?;
+        let server_compression = reader.name_list()?;
+        let _languages = (reader.name_list()?, reader.name_list()?);
+        let guessed = reader.bool()?;
+
+        let kex = client_kex.iter().find(|name| KEX_ALGORITHMS.contains(name));
+        let negotiated = kex.is_some()
+            && client_host_keys.contains(&HostKey::ALGORITHM)
+            && client_ciphers.contains(&CIPHER)
+            && server_ciphers.contains(&CIPHER)
+            && client_compression.contains(&"none")
+            && server_compression.contains(&"none");
+        if !negotiated {
+            self.disconnect(
+                DISCONNECT_KEY_EXCHANGE_FAILED,
+                "No matching algorithms, an OpenSSH 6.5+ compatible client is required",
+            )
+            .await
+            .ok();
+            bail!("The client doesn't support any of our algorithms");
+        }
+        if first {
+            self.strict_kex = client_kex.contains(&STRICT_KEX_CLIENT);
+        }
+        let send_ext_info = first && client_kex.contains(&EXT_INFO_CLIENT);
+        // A wrong guess means the client's next packet must be ignored
+        let mut skip_guess = guessed && client_kex.first() != kex;
+
+        let client_public = loop {
+            let packet = self.next_packet().await?;
+            if skip_guess {
+                skip_guess = false;
+                continue;
+            }
+            let mut reader = Reader::new(&packet);
+            match reader.u8()? {
+                MSG_KEX_ECDH_INIT => break reader.string()?.to_vec(),
+                MSG_IGNORE | MSG_DEBUG if !self.strict_kex => {}
+                other => bail!("Unexpected message {other} during the key exchange"),
+            }
+        };
+
+        let private = EphemeralPrivateKey::generate(&X25519, &self.rng)
+            .map_err(|_| anyhow::anyhow!("Unable to generate a key"))?;
+        let server_public = private
+            .compute_public_key()
+            .map_err(|_| anyhow::anyhow!("Unable to compute the public key"))?;
+        let shared_secret = agreement::agree_ephemeral(
+            private,
+            &agreement::UnparsedPublicKey::new(&X25519, &client_public),
+            |secret| secret.to_vec(),
+        )
+        .map_err(|_| anyhow::anyhow!("Invalid client public key"))?;
+        if shared_secret.iter().all(|b| *b == 0) {
+            bail!("Invalid client public key");
+        }
+
+        let host_key = self.host_key.public_blob();
+        let exchange_hash = self.exchange_hash(
+            &client_kexinit,
+            &server_kexinit,
+            &host_key,
+            &client_public,
+            server_public.as_ref(),
+            &shared_secret,
+        );
+        let session_id = self
+            .session_id
+            .get_or_insert_with(|| exchange_hash.clone())
+            .clone();
+
+        let mut reply = Writer::message(MSG_KEX_ECDH_REPLY);
+        reply
+            .string(&host_key)
+            .string(server_public.as_ref())
+            .string(self.host_key.sign(&exchange_hash));
+        self.send(reply.as_bytes()).await?;
+        self.send(&[MSG_NEWKEYS]).await?;
+
+        let derive = |letter: u8| derive_key(&shared_secret, &exchange_hash, letter, &session_id);
+        self.sealing = Some(SealingKey::new(&derive(b'D')));
+        if self.strict_kex {
+            self.send_seq = 0;
+        }
+
+        loop {
+            let packet = self.next_packet().await?;
+            match packet.first() {
+                Some(&MSG_NEWKEYS) => break,
+                Some(&MSG_IGNORE | &MSG_DEBUG) if !self.strict_kex => {}
+                _ => bail!("Expected NEWKEYS"),
+            }
+        }
+        self.opening = Some(OpeningKey::new(&derive(b'C')));
+        if self.strict_kex {
+            self.recv_seq = 0;
+        }
+
+        if send_ext_info {
+            let mut msg = Writer::message(MSG_EXT_INFO);
+            msg.u32(1)
+                .string("server-sig-algs")
+                .name_list(SIGNATURE_ALGORITHMS);
+            self.send(msg.as_bytes()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Computes the exchange hash `H` of a key exchange, as described in
+    /// [RFC 5656, section 4].
+    ///
+    /// [RFC 5656, section 4]: https://www.rfc-editor.org/rfc/rfc5656#section-4
+    fn exchange_hash(
+        &self,
+        client_kexinit: &[u8],
+        server_kexinit: &[u8],
+        host_key: &[u8],
+        client_public: &[u8],
+        server_public: &[u8],
+        shared_secret: &[u8],
+    ) -> Vec<u8> {
+        let mut exchange = Writer::new();
+        exchange
+            .string(&self.client_version)
+            .string(&self.server_version)
+            .string(client_kexinit)
+            .string(server_kexinit)
+            .string(host_key)
+            .string(client_public)
+            .string(server_public)
+            .mpint(shared_secret);
+        Sha256::digest(exchange.as_bytes()).to_vec()
+    }
+}
+
+/// Derives a cipher key as described in [RFC 4253, section 7.2].
+///
+/// [RFC 4253, section 7.2]: https://www.rfc-editor.org/rfc/rfc4253#section-7.2
+fn derive_key(
+    shared_secret: &[u8],
+    exchange_hash: &[u8],
+    letter: u8,
+    session_id: &[u8],
+) -> [u8; KEY_LEN] {
+    let mut prefix = Writer::new();
+    prefix.mpint(shared_secret).bytes(exchange_hash);
+
+    let mut key = Sha256::new()
+        .chain_update(prefix.as_bytes())
+        .chain_update([letter])
+        .chain_update(session_id)
+        .finalize()
+        .to_vec();
+    while key.len() < KEY_LEN {
+        let next = Sha256::new()
+            .chain_update(prefix.as_bytes())
+            .chain_update(&key)
+            .finalize();
+        key.extend_from_slice(&next);
+    }
+
+    key[..KEY_LEN].try_into().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Returns both ends of a TCP connection.
+    async fn connected() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (client, server)
+    }
+
+    /// The shared secret starts with a zero byte followed by one with the
+    /// high bit set, to check that it is encoded as a `mpint`.
+    fn shared_secret() -> Vec<u8> {
+        [0x00, 0x9a].into_iter().chain(0..30).collect()
+    }
+
+    #[test]
+    fn derive_key_known_answer() {
+        let exchange_hash: Vec<u8> = (32..64).collect();
+        let session_id: Vec<u8> = (64..96).collect();
+
+        let key = derive_key(&shared_secret(), &exchange_hash, b'C', &session_id);
+
+        assert_eq!(
+            hex::encode(key),
+            "b0f733bc51b40e8c0e777ec7f52abcf91205838b2215a52ea6e7f398facefab3\
+             d5ec7590a7eca5369b6d40041b5ce4d1c1151bdaa0889910c1ce3590d05c3de2"
+        );
+    }
+
+    #[tokio::test]
+    async fn exchange_hash_known_answer() {
+        let (stream, _client) = connected().await;
+        let mut transport = Transport::unencrypted(stream);
+        transport.client_version = b"SSH-2.0-OpenSSH_9.2".to_vec();
+        transport.server_version = b"SSH-2.0-Wasmer".to_vec();
+        let client_kexinit = [[MSG_KEXINIT].as_slice(), &[0; 16]].concat();
+        let server_kexinit = [[MSG_KEXINIT].as_slice(), &[1; 16]].concat();
+
+        let hash = transport.exchange_hash(
+            &client_kexinit,
+            &server_kexinit,
+            b"host key",
+            &[2; 32],
+            &[3; 32],
+            &shared_secret(),
+        );
+
+        assert_eq!(
+            hex::encode(hash),
+            "cc50e5bde90284b9b1dd2d31015e05d96c090a2e34f6e676fbb8d7e7a48b29bf"
+        );
+    }
+
+    #[tokio::test]
+    async fn cipher_known_answer() {
+        let key: [u8; KEY_LEN] = std::array::from_fn(|i| 100 + i as u8);
+        let (stream, _client) = connected().await;
+        let mut transport = Transport::unencrypted(stream);
+        transport.opening = Some(OpeningKey::new(&key));
+        transport.recv_seq = 3;
+        // A CHANNEL_DATA packet sealed with the key as the 4th packet
+        transport.buffer = hex::decode(
+            "44dc4a1cd79cbd06d6f528fff8adc274a8c4290ded8ed9a1d4b69245aa41c421\
+             7abcf986179eecc97cc0ffae",
+        )
+        .unwrap();
+
+        let payload = transport.try_decode().unwrap().unwrap();
+
+        assert_eq!(hex::encode(payload), "5e000000070000000568656c6c6f");
+        assert_eq!(transport.recv_seq, 4);
+    }
+
+    #[tokio::test]
+    async fn tampered_packets_are_rejected() {
+        let key: [u8; KEY_LEN] = std::array::from_fn(|i| 100 + i as u8);
+        let (stream, _client) = connected().await;
+        let mut transport = Transport::unencrypted(stream);
+        transport.opening = Some(OpeningKey::new(&key));
+        transport.recv_seq = 3;
+        let mut packet = hex::decode(
+            "44dc4a1cd79cbd06d6f528fff8adc274a8c4290ded8ed9a1d4b69245aa41c421\
+             7abcf986179eecc97cc0ffae",
+        )
+        .unwrap();
+        packet[10] ^= 1;
+        transport.buffer = packet;
+
+        let err = transport.try_decode().unwrap_err();
+
+        assert_eq!(err.to_string(), "Packet authentication failed");
+    }
+
+    #[tokio::test]
+    async fn sealed_packets_can_be_opened() {
+        let key: [u8; KEY_LEN] = std::array::from_fn(|i| i as u8);
+        let (client, server) = connected().await;
+        let mut client = Transport::unencrypted(client);
+        let mut server = Transport::unencrypted(server);
+        client.sealing = Some(SealingKey::new(&key));
+        server.opening = Some(OpeningKey::new(&key));
+
+        for payload in [b"a".as_slice(), &[7; 5000], b"last"] {
+            client.send(payload).await.unwrap();
+            assert_eq!(server.next_packet().await.unwrap(), payload);
+        }
+    }
+}
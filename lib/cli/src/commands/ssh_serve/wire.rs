@@ -0,0 +1,210 @@
+//! Encoding of the SSH data types ([RFC 4251, section 5]).
+//!
+//! [RFC 4251, section 5]: https://www.rfc-editor.org/rfc/rfc4251#section-5
+
+use anyhow::{bail, Context};
+
+pub(super) const MSG_DISCONNECT: u8 = 1;
+pub(super) const MSG_IGNORE: u8 = 2;
+pub(super) const MSG_UNIMPLEMENTED: u8 = 3;
+pub(super) const MSG_DEBUG: u8 = 4;
+pub(super) const MSG_SERVICE_REQUEST: u8 = 5;
+pub(super) const MSG_SERVICE_ACCEPT: u8 = 6;
+pub(super) const MSG_EXT_INFO: u8 = 7;
+pub(super) const MSG_KEXINIT: u8 = 20;
+pub(super) const MSG_NEWKEYS: u8 = 21;
+pub(super) const MSG_KEX_ECDH_INIT: u8 = 30;
+pub(super) const MSG_KEX_ECDH_REPLY: u8 = 31;
+pub(super) const MSG_USERAUTH_REQUEST: u8 = 50;
+pub(super) const MSG_USERAUTH_FAILURE: u8 = 51;
+pub(super) const MSG_USERAUTH_SUCCESS: u8 = 52;
+pub(super) const MSG_USERAUTH_PK_OK: u8 = 60;
+pub(super) const MSG_GLOBAL_REQUEST: u8 = 80;
+pub(super) const MSG_REQUEST_FAILURE: u8 = 82;
+pub(super) const MSG_CHANNEL_OPEN: u8 = 90;
+pub(super) const MSG_CHANNEL_OPEN_CONFIRMATION: u8 = 91;
+pub(super) const MSG_CHANNEL_OPEN_FAILURE: u8 = 92;
+pub(super) const MSG_CHANNEL_WINDOW_ADJUST: u8 = 93;
+pub(super) const MSG_CHANNEL_DATA: u8 = 94;
+pub(super) const MSG_CHANNEL_EXTENDED_DATA: u8 = 95;
+pub(super) const MSG_CHANNEL_EOF: u8 = 96;
+pub(super) const MSG_CHANNEL_CLOSE: u8 = 97;
+pub(super) const MSG_CHANNEL_REQUEST: u8 = 98;
+pub(super) const MSG_CHANNEL_SUCCESS: u8 = 99;
+pub(super) const MSG_CHANNEL_FAILURE: u8 = 100;
+
+/// Reads SSH data types from a message.
+#[derive(Debug, Clone)]
+pub(super) struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Reader { buf }
+    }
+
+    fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        if self.buf.len() < len {
+            bail!("Unexpected end of message");
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> anyhow::Result<bool> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u32(&mut self) -> anyhow::Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn bytes(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        self.take(len)
+    }
+
+    pub fn string(&mut self) -> anyhow::Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    pub fn utf8(&mut self) -> anyhow::Result<&'a str> {
+        std::str::from_utf8(self.string()?).context("Expected a UTF-8 string")
+    }
+
+    pub fn name_list(&mut self) -> anyhow::Result<Vec<&'a str>> {
+        let names = self.utf8()?;
+        Ok(names.split(',').filter(|name| !name.is_empty()).collect())
+    }
+
+    /// Reads a `mpint`, stripping the sign padding.
+    pub fn mpint(&mut self) -> anyhow::Result<&'a [u8]> {
+        let mut value = self.string()?;
+        while let [0, rest @ ..] = value {
+            value = rest;
+        }
+        Ok(value)
+    }
+}
+
+/// Builds a message out of SSH data types.
+#[derive(Debug, Default, Clone)]
+pub(super) struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        Writer::default()
+    }
+
+    pub fn message(msg: u8) -> Self {
+        let mut writer = Writer::new();
+        writer.u8(msg);
+        writer
+    }
+
+    pub fn u8(&mut self, value: u8) -> &mut Self {
+        self.buf.push(value);
+        self
+    }
+
+    pub fn bool(&mut self, value: bool) -> &mut Self {
+        self.u8(value as u8)
+    }
+
+    pub fn u32(&mut self, value: u32) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(value);
+        self
+    }
+
+    pub fn string(&mut self, value: impl AsRef<[u8]>) -> &mut Self {
+        let value = value.as_ref();
+        self.u32(value.len() as u32);
+        self.bytes(value)
+    }
+
+    pub fn name_list(&mut self, names: &[&str]) -> &mut Self {
+        self.string(names.join(","))
+    }
+
+    /// Writes an unsigned big-endian integer as a `mpint`.
+    pub fn mpint(&mut self, value: &[u8]) -> &mut Self {
+        let start = value.iter().position(|b| *b != 0).unwrap_or(value.len());
+        let value = &value[start..];
+        if value.first().is_some_and(|b| b & 0x80 != 0) {
+            self.u32(value.len() as u32 + 1);
+            self.u8(0);
+            self.bytes(value)
+        } else {
+            self.string(value)
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mpint_examples_from_the_rfc() {
+        let cases: &[(&[u8], &[u8])] = &[
+            (&[], &[0, 0, 0, 0]),
+            (
+                &[0x09, 0xa3, 0x78, 0xf9, 0xb2, 0xe3, 0x32, 0xa7],
+                &[0, 0, 0, 8, 0x09, 0xa3, 0x78, 0xf9, 0xb2, 0xe3, 0x32, 0xa7],
+            ),
+            (&[0x80], &[0, 0, 0, 2, 0x00, 0x80]),
+            (&[0, 0, 0x7f], &[0, 0, 0, 1, 0x7f]),
+        ];
+
+        for (value, encoded) in cases {
+            let mut writer = Writer::new();
+            writer.mpint(value);
+            assert_eq!(writer.as_bytes(), *encoded);
+
+            let decoded = Reader::new(encoded).mpint().unwrap();
+            let start = value.iter().position(|b| *b != 0).unwrap_or(value.len());
+            assert_eq!(decoded, &value[start..]);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut writer = Writer::message(MSG_KEXINIT);
+        writer
+            .u32(42)
+            .bool(true)
+            .string("hello")
+            .name_list(&["a", "b"]);
+        let bytes = writer.into_bytes();
+
+        let mut reader = Reader::new(&bytes);
+        assert_eq!(reader.u8().unwrap(), MSG_KEXINIT);
+        assert_eq!(reader.u32().unwrap(), 42);
+        assert!(reader.bool().unwrap());
+        assert_eq!(reader.utf8().unwrap(), "hello");
+        assert_eq!(reader.name_list().unwrap(), ["a", "b"]);
+        assert!(reader.u8().is_err());
+    }
+}
@@ -1,10 +1,15 @@
 // This file contains code from external sources.
 // Attributions: https://github.com/wasmerio/wasmer/blob/main/docs/ATTRIBUTIONS.md
 
-use cranelift_codegen::{Context, MachSrcLoc};
+use cranelift_codegen::{isa::TargetIsa, Context, LabelValueLoc, MachSrcLoc};
 use std::ops::Range;
-use wasmer_compiler::types::address_map::{FunctionAddressMap, InstructionAddressMap};
-use wasmer_types::SourceLoc;
+use wasmer_compiler::types::address_map::{
+    FunctionAddressMap, FunctionLocalsMap, InstructionAddressMap, LocalLocation, LocalRange,
+};
+use wasmer_compiler::{
+    wptype_to_type, FunctionBinaryReader, FunctionBodyData, MiddlewareBinaryReader,
+};
+use wasmer_types::{CompileError, FunctionIndex, ModuleInfo, SourceLoc};
 
 pub fn get_function_address_map(
     context: &Context,
@@ -38,3 +43,64 @@ pub fn get_function_address_map(
         body_len,
     }
 }
+
+/// Collects where the locals of a function live from the value labels
+/// that the translator attached to them.
+pub fn get_function_locals(
+    isa: &dyn TargetIsa,
+    context: &Context,
+    module: &ModuleInfo,
+    func_index: FunctionIndex,
+    input: &FunctionBodyData<'_>,
+) -> Result<FunctionLocalsMap, CompileError> {
+    let mut types = module.signatures[module.functions[func_index]]
+        .params()
+        .to_vec();
+    let mut reader = MiddlewareBinaryReader::new_with_offset(input.data, input.module_offset);
+    for _ in 0..reader.read_local_count()? {
+        let (count, ty) = reader.read_local_decl()?;
+        types.extend(std::iter::repeat(wptype_to_type(ty)?).take(count as usize));
+    }
+
+    let mut ranges = Vec::new();
+    let mcr = context.compiled_code().unwrap();
+    for (label, locations) in &mcr.value_labels_ranges {
+        let local = label.as_u32();
+        // Other labels, such as the one of the vmctx, are not locals
+        if local as usize >= types.len() {
+            continue;
+        }
+        for range in locations {
+            let location = match range.loc {
+                LabelValueLoc::Reg(reg) => match register_to_dwarf(isa, reg) {
+                    Some(register) => LocalLocation::Register(register),
+                    None => continue,
+                },
+                LabelValueLoc::CFAOffset(offset) => match i32::try_from(offset) {
+                    Ok(offset) => LocalLocation::FrameOffset(offset),
+                    Err(_) => continue,
+                },
+            };
+            ranges.push(LocalRange {
+                local,
+                start: range.start,
+                end: range.end,
+                location,
+            });
+        }
+    }
+    ranges.sort_by_key(|range| (range.start, range.local));
+
+    Ok(FunctionLocalsMap { types, ranges })
+}
+
+#[cfg(feature = "unwind")]
+fn register_to_dwarf(isa: &dyn TargetIsa, reg: cranelift_codegen::Reg) -> Option<u16> {
+    isa.map_regalloc_reg_to_dwarf(reg).ok()
+}
+
+/// The register numbers are only known with the unwinding support.
+#[cfg(not(feature = "unwind"))]
+fn register_to_dwarf(_isa: &dyn TargetIsa, _reg: cranelift_codegen::Reg) -> Option<u16> {
+    None
+}
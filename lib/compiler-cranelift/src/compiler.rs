@@ -4,7 +4,7 @@
 use crate::dwarf::WriterRelocate;
 
 use crate::{
-    address_map::get_function_address_map,
+    address_map::{get_function_address_map, get_function_locals},
     config::Cranelift,
     func_environ::{get_function_name, FuncEnvironment},
     trampoline::{
@@ -154,9 +154,8 @@ impl Compiler for CraneliftCompiler {
                     _ => UserFuncName::default(),
                 };
                 context.func.signature = signatures[module.functions[func_index]].clone();
-                // if generate_debug_info {
-                //     context.func.collect_debug_info();
-                // }
+                // Track where the locals live, to recover them when a trap happens
+                context.func.collect_debug_info();
                 let mut reader =
                     MiddlewareBinaryReader::new_with_offset(input.data, input.module_offset);
                 reader.set_middleware_chain(
@@ -222,6 +221,7 @@ impl Compiler for CraneliftCompiler {
 
                 let range = reader.range();
                 let address_map = get_function_address_map(&context, range, code_buf.len());
+                let locals = get_function_locals(&*isa, &context, module, func_index, input)?;
 
                 Ok((
                     CompiledFunction {
@@ -230,7 +230,11 @@ impl Compiler for CraneliftCompiler {
                             unwind_info,
                         },
                         relocations: func_relocs,
-                        frame_info: CompiledFunctionFrameInfo { address_map, traps },
+                        frame_info: CompiledFunctionFrameInfo {
+                            address_map,
+                            traps,
+                            locals,
+                        },
                     },
                     fde,
                 ))
@@ -266,9 +270,8 @@ impl Compiler for CraneliftCompiler {
                     _ => UserFuncName::default(),
                 };
                 context.func.signature = signatures[module.functions[func_index]].clone();
-                // if generate_debug_info {
-                //     context.func.collect_debug_info();
-                // }
+                // Track where the locals live, to recover them when a trap happens
+                context.func.collect_debug_info();
 
                 let mut reader =
                     MiddlewareBinaryReader::new_with_offset(input.data, input.module_offset);
@@ -335,6 +338,7 @@ impl Compiler for CraneliftCompiler {
 
                 let range = reader.range();
                 let address_map = get_function_address_map(&context, range, code_buf.len());
+                let locals = get_function_locals(&*isa, &context, module, func_index, input)?;
 
                 Ok((
                     CompiledFunction {
@@ -343,7 +347,11 @@ impl Compiler for CraneliftCompiler {
                             unwind_info,
                         },
                         relocations: func_relocs,
-                        frame_info: CompiledFunctionFrameInfo { address_map, traps },
+                        frame_info: CompiledFunctionFrameInfo {
+                            address_map,
+                            traps,
+                            locals,
+                        },
                     },
                     fde,
                 ))
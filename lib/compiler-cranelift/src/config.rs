@@ -144,6 +144,12 @@ impl Cranelift {
             flags.enable("is_pic").expect("should be a valid flag");
         }
 
+        // Keep a chain of frame records through the guest frames, which is
+        // what the sampling profiler walks from its signal handler.
+        flags
+            .enable("preserve_frame_pointers")
+            .expect("should be a valid flag");
+
         // We set up libcall trampolines in engine-universal.
         // These trampolines are always reachable through short jumps.
         flags
@@ -143,6 +143,7 @@ fn declare_wasm_parameters<FE: FuncEnvironment + ?Sized>(
 
             let param_value = builder.block_params(entry_block)[i];
             builder.def_var(local, param_value);
+            builder.set_val_label(param_value, ValueLabel::new(next_local - 1));
         }
         if param_type.purpose == ir::ArgumentPurpose::VMContext {
             let param_value = builder.block_params(entry_block)[i];
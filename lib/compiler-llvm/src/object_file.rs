@@ -660,6 +660,8 @@ where
             frame_info: CompiledFunctionFrameInfo {
                 address_map,
                 traps: vec![],
+                // LLVM doesn't report where locals live yet
+                locals: Default::default(),
             },
         },
         custom_sections,
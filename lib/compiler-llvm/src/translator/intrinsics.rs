@@ -712,7 +712,7 @@ impl<'ctx> Intrinsics<'ctx> {
                 .create_enum_attribute(Attribute::get_named_enum_kind_id("readonly"), 0),
             stack_probe: context.create_string_attribute("probe-stack", "inline-asm"),
             uwtable: context.create_enum_attribute(Attribute::get_named_enum_kind_id("uwtable"), 1),
-            frame_pointer: context.create_string_attribute("frame-pointer", "non-leaf"),
+            frame_pointer: context.create_string_attribute("frame-pointer", "all"),
             void_ty,
             i1_ty,
             i2_ty,
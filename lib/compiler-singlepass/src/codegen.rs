@@ -6700,7 +6700,12 @@ impl<'a, M: Machine> FuncGen<'a, M> {
             CompiledFunction {
                 body: FunctionBody { body, unwind_info },
                 relocations: self.relocations.clone(),
-                frame_info: CompiledFunctionFrameInfo { traps, address_map },
+                frame_info: CompiledFunctionFrameInfo {
+                    traps,
+                    address_map,
+                    // Locals are not tracked yet
+                    locals: Default::default(),
+                },
             },
             fde,
         ))
@@ -0,0 +1,97 @@
+//! Symbolization of wasm frames using the DWARF sections embedded in the
+//! original module.
+//!
+//! Toolchains such as `clang` and `rustc` emit the DWARF debug sections
+//! (`.debug_info`, `.debug_line`, ...) as custom sections of the wasm module.
+//! Addresses in those sections are offsets relative to the start of the code
+//! section contents, so a frame's module offset can be mapped back to a
+//! source location once the code section offset is known.
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use gimli::{EndianArcSlice, LittleEndian, SectionId};
+use wasmer_types::{FrameSymbol, ModuleInfo};
+
+type Reader = EndianArcSlice<LittleEndian>;
+
+/// Resolves module offsets into source-level frames.
+pub(crate) struct DwarfSymbolizer {
+    /// `addr2line` caches the parsed units internally, which requires
+    /// exclusive access while looking up addresses.
+    context: Mutex<addr2line::Context<Reader>>,
+    code_section_offset: usize,
+}
+
+impl DwarfSymbolizer {
+    /// Creates a symbolizer for the given module.
+    ///
+    /// Returns `None` if the module carries no (valid) DWARF information.
+    pub(crate) fn new(module: &ModuleInfo) -> Option<Self> {
+        let code_section_offset = module.code_section_offset?;
+        if !module
+            .custom_sections
+            .contains_key(SectionId::DebugInfo.name())
+        {
+            return None;
+        }
+
+        let load = |id: SectionId| -> Result<Reader, gimli::Error> {
+            let data: Arc<[u8]> = match module.custom_sections.get(id.name()) {
+                Some(index) => Arc::from(&module.custom_sections_data[*index][..]),
+                None => Arc::from(&[][..]),
+            };
+            Ok(EndianArcSlice::new(data, LittleEndian))
+        };
+        let dwarf = gimli::Dwarf::load(load).ok()?;
+        let context = addr2line::Context::from_dwarf(dwarf).ok()?;
+
+        Some(Self {
+            context: Mutex::new(context),
+            code_section_offset,
+        })
+    }
+
+    /// Returns the source frames for the instruction at `module_offset`,
+    /// innermost (inlined) frame first.
+    pub(crate) fn symbolize(&self, module_offset: usize) -> Vec<FrameSymbol> {
+        let Some(address) = module_offset.checked_sub(self.code_section_offset) else {
+            return Vec::new();
+        };
+        let Ok(context) = self.context.lock() else {
+            return Vec::new();
+        };
+        let Ok(mut frames) = context.find_frames(address as u64).skip_all_loads() else {
+            return Vec::new();
+        };
+
+        let mut symbols = Vec::new();
+        while let Ok(Some(frame)) = frames.next() {
+            let function = frame
+                .function
+                .as_ref()
+                .and_then(|function| function.demangle().ok())
+                .map(|name| name.into_owned());
+            let (file, line, column) = match frame.location {
+                Some(location) => (
+                    location.file.map(String::from),
+                    location.line,
+                    location.column,
+                ),
+                None => (None, None, None),
+            };
+            symbols.push(FrameSymbol::new(function, file, line, column));
+        }
+        symbols
+    }
+}
+
+impl fmt::Debug for DwarfSymbolizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DwarfSymbolizer")
+            .field("code_section_offset", &self.code_section_offset)
+            .finish_non_exhaustive()
+    }
+}
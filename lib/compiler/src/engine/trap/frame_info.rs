@@ -12,21 +12,24 @@
 //! FRAME_INFO.register(module, compiled_functions);
 //! ```
 
+use super::dwarf::DwarfSymbolizer;
 use crate::types::address_map::{
     ArchivedFunctionAddressMap, ArchivedInstructionAddressMap, FunctionAddressMap,
-    InstructionAddressMap,
+    FunctionLocalsMap, InstructionAddressMap, LocalLocation,
 };
 use crate::types::function::{ArchivedCompiledFunctionFrameInfo, CompiledFunctionFrameInfo};
 use crate::ArtifactBuildFromArchive;
 use rkyv::vec::ArchivedVec;
+use std::borrow::Cow;
 use std::collections::BTreeMap;
-use std::sync::{Arc, LazyLock, RwLock};
+use std::sync::{Arc, LazyLock, OnceLock, RwLock};
 use wasmer_types::lib::std::{cmp, ops::Deref};
 use wasmer_types::{
     entity::{BoxedSlice, EntityRef, PrimaryMap},
-    FrameInfo, LocalFunctionIndex, ModuleInfo, SourceLoc, TrapInformation,
+    FrameInfo, FrameSymbol, FrameValue, LocalFunctionIndex, ModuleInfo, SourceLoc, TrapInformation,
+    Type,
 };
-use wasmer_vm::FunctionBodyPtr;
+use wasmer_vm::{FunctionBodyPtr, TrapFrame, TrapState};
 
 /// This is a global cache of backtrace frame information for all active
 ///
@@ -64,9 +67,26 @@ struct ModuleInfoFrameInfo {
     functions: BTreeMap<usize, FunctionInfo>,
     module: Arc<ModuleInfo>,
     frame_infos: FrameInfosVariant,
+    /// Lazily parsed DWARF information, only built the first time a frame
+    /// of this module needs to be symbolized.
+    symbolizer: OnceLock<Option<DwarfSymbolizer>>,
 }
 
 impl ModuleInfoFrameInfo {
+    /// Resolves the source-level frames for a module offset, if the module
+    /// has debug information.
+    fn symbols(&self, srcloc: SourceLoc) -> Vec<FrameSymbol> {
+        match self
+            .symbolizer
+            .get_or_init(|| DwarfSymbolizer::new(&self.module))
+        {
+            Some(symbolizer) if !srcloc.is_default() => {
+                symbolizer.symbolize(srcloc.bits() as usize)
+            }
+            _ => Vec::new(),
+        }
+    }
+
     fn function_debug_info(
         &self,
         local_index: LocalFunctionIndex,
@@ -138,13 +158,81 @@ impl GlobalFrameInfo {
             None => instr_map.start_srcloc(),
         };
         let func_index = module.module.func_index(func.local_index);
-        Some(FrameInfo::new(
-            module.module.name(),
-            func_index.index() as u32,
-            module.module.function_names.get(&func_index).cloned(),
-            instr_map.start_srcloc(),
-            instr,
-        ))
+        Some(
+            FrameInfo::new(
+                module.module.name(),
+                func_index.index() as u32,
+                module.module.function_names.get(&func_index).cloned(),
+                instr_map.start_srcloc(),
+                instr,
+            )
+            .with_symbols(module.symbols(instr)),
+        )
+    }
+
+    /// Recovers the values of the locals of a frame from the state of the
+    /// thread when it trapped.
+    ///
+    /// Registers only hold the values of the innermost frame, the locals of
+    /// the other frames are only known when they live in the stack frame.
+    pub fn lookup_frame_locals(
+        &self,
+        frame: &TrapFrame,
+        innermost: bool,
+        state: &TrapState,
+    ) -> Vec<Option<FrameValue>> {
+        let Some(module) = self.module_info(frame.pc) else {
+            return Vec::new();
+        };
+        let Some(func) = module.function_info(frame.pc) else {
+            return Vec::new();
+        };
+        let debug_info = module.function_debug_info(func.local_index);
+        let map = debug_info.locals();
+        let offset = (frame.pc - func.start) as u32;
+
+        let mut locals = vec![None; map.types.len()];
+        for range in &map.ranges {
+            if range.start > offset {
+                break;
+            }
+            if offset >= range.end {
+                continue;
+            }
+            let Some(ty) = map.types.get(range.local as usize) else {
+                continue;
+            };
+            let size = match ty {
+                Type::I32 | Type::F32 => 4,
+                Type::I64 | Type::F64 => 8,
+                Type::V128 => 16,
+                _ => continue,
+            };
+            let bits = match range.location {
+                LocalLocation::Register(register) if innermost => state.register(register),
+                LocalLocation::Register(_) => None,
+                LocalLocation::FrameOffset(offset) => frame
+                    .cfa
+                    .checked_add_signed(offset as isize)
+                    .and_then(|addr| state.read_stack(addr, size))
+                    .map(|bytes| {
+                        let mut value = [0; 16];
+                        value[..size].copy_from_slice(bytes);
+                        u128::from_le_bytes(value)
+                    }),
+            };
+            let Some(bits) = bits else {
+                continue;
+            };
+            locals[range.local as usize] = Some(match ty {
+                Type::I32 => FrameValue::I32(bits as u32 as i32),
+                Type::I64 => FrameValue::I64(bits as u64 as i64),
+                Type::F32 => FrameValue::F32(f32::from_bits(bits as u32)),
+                Type::F64 => FrameValue::F64(f64::from_bits(bits as u64)),
+                _ => FrameValue::V128(bits),
+            });
+        }
+        locals
     }
 
     /// Fetches trap information about a program counter in a backtrace.
@@ -159,6 +247,12 @@ impl GlobalFrameInfo {
         Some(traps[idx])
     }
 
+    /// Returns the range of addresses that the code of each registered
+    /// module occupies.
+    pub fn code_ranges(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.ranges.iter().map(|(end, info)| (info.start, *end))
+    }
+
     /// Gets a module given a pc
     fn module_info(&self, pc: usize) -> Option<&ModuleInfoFrameInfo> {
         let (end, module_info) = self.ranges.range(pc..).next()?;
@@ -235,6 +329,16 @@ impl CompiledFunctionFrameInfoVariant<'_> {
         }
     }
 
+    /// Gets the locals map for the frame info
+    pub fn locals(&self) -> Cow<'_, FunctionLocalsMap> {
+        match self {
+            CompiledFunctionFrameInfoVariant::Ref(info) => Cow::Borrowed(&info.locals),
+            CompiledFunctionFrameInfoVariant::Archived(info) => {
+                Cow::Owned(rkyv::deserialize::<_, rkyv::rancor::Error>(&info.locals).unwrap())
+            }
+        }
+    }
+
     /// Gets the traps for the frame info
     pub fn traps(&self) -> VecTrapInformationVariant {
         match self {
@@ -407,6 +511,7 @@ pub fn register(
             functions,
             module,
             frame_infos,
+            symbolizer: OnceLock::new(),
         },
     );
     assert!(prev.is_none());
@@ -1,7 +1,12 @@
+mod dwarf;
 mod frame_info;
+#[cfg(target_os = "linux")]
+mod profiler;
 mod stack;
 pub use frame_info::{
     register as register_frame_info, CompiledFunctionFrameInfoVariant, FrameInfosVariant,
     FunctionExtent, GlobalFrameInfoRegistration, FRAME_INFO,
 };
+#[cfg(target_os = "linux")]
+pub use profiler::{Profile, ProfileSample, Profiler};
 pub use stack::get_trace_and_trapcode;
@@ -0,0 +1,344 @@
+//! A sampling profiler for guest code.
+//!
+//! A background thread periodically sends `SIGPROF` to every thread of the
+//! process, one at a time. When the signal interrupts a thread that is
+//! executing compiled WebAssembly code the handler follows the chain of frame
+//! records that the compilers keep for guest code and hands the program
+//! counters back to the sampling thread, which symbolicates them with the
+//! [`FRAME_INFO`] registry.
+//!
+//! Host code that the guest called (imports, libcalls and trampolines) can not
+//! be unwound from a signal handler, the unwinder takes locks that the
+//! interrupted code may be holding. Time spent there is recorded as a single
+//! host sample instead.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering},
+        Arc, OnceLock,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use wasmer_types::FrameInfo;
+
+use super::frame_info::FRAME_INFO;
+
+/// Maximum number of frames that are recorded for a sample
+const MAX_DEPTH: usize = 256;
+/// Maximum number of modules whose code is recognized by the signal handler
+const MAX_CODE_RANGES: usize = 64;
+/// How long to wait for a thread to take its sample before skipping it
+const SAMPLE_TIMEOUT: Duration = Duration::from_millis(10);
+/// How long to wait for a thread that started taking its sample to finish
+const TAKING_TIMEOUT: Duration = Duration::from_millis(100);
+
+const IDLE: u8 = 0;
+const REQUESTED: u8 = 1;
+const TAKING: u8 = 2;
+const DONE: u8 = 3;
+
+/// State shared with the signal handler, which can not allocate or take
+/// locks and thus only touches atomics.
+static STATE: AtomicU8 = AtomicU8::new(IDLE);
+static SAMPLE_PCS: [AtomicUsize; MAX_DEPTH] = [const { AtomicUsize::new(0) }; MAX_DEPTH];
+static SAMPLE_LEN: AtomicUsize = AtomicUsize::new(0);
+static SAMPLE_HOST: AtomicBool = AtomicBool::new(false);
+static CODE_RANGES: [(AtomicUsize, AtomicUsize); MAX_CODE_RANGES] =
+    [const { (AtomicUsize::new(0), AtomicUsize::new(0)) }; MAX_CODE_RANGES];
+static CODE_RANGES_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// A call stack of guest frames (innermost first) and the number of times it
+/// was sampled.
+#[derive(Debug, Clone)]
+pub struct ProfileSample {
+    /// Whether the thread was running host code called by the guest, whose
+    /// callers are not known (the frames are then empty)
+    pub host: bool,
+    /// The guest frames of the call stack, innermost first
+    pub frames: Vec<FrameInfo>,
+    /// How many times this call stack was sampled
+    pub count: u64,
+}
+
+/// The samples that were collected by a [`Profiler`].
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    /// Time between two samples
+    pub period: Duration,
+    /// How long the profiler ran for
+    pub duration: Duration,
+    /// The distinct call stacks that were sampled
+    pub samples: Vec<ProfileSample>,
+}
+
+/// A running sampling profiler, see the [module documentation](self).
+pub struct Profiler {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<Profile>,
+}
+
+impl Profiler {
+    /// Starts sampling the guest call stacks of every thread `frequency`
+    /// times per second.
+    pub fn start(frequency: u32) -> std::io::Result<Self> {
+        install_handler()?;
+
+        let period = Duration::from_secs(1) / frequency.max(1);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = std::thread::Builder::new()
+            .name("wasmer-profiler".to_string())
+            .spawn({
+                let stop = stop.clone();
+                move || sample_loop(period, &stop)
+            })?;
+        Ok(Self { stop, thread })
+    }
+
+    /// Stops sampling and returns the collected samples.
+    pub fn stop(self) -> Profile {
+        self.stop.store(true, Ordering::SeqCst);
+        self.thread.join().unwrap_or_default()
+    }
+}
+
+fn install_handler() -> std::io::Result<()> {
+    static INSTALLED: OnceLock<std::io::Result<()>> = OnceLock::new();
+
+    // The handler stays installed for the rest of the process so that a
+    // late signal never hits the default action (which terminates it)
+    let res = INSTALLED.get_or_init(|| unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_sigprof as usize;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK | libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(libc::SIGPROF, &action, std::ptr::null_mut()) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    });
+    match res {
+        Ok(()) => Ok(()),
+        Err(err) => Err(std::io::Error::new(err.kind(), err.to_string())),
+    }
+}
+
+unsafe extern "C" fn on_sigprof(
+    _signum: libc::c_int,
+    _siginfo: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+) {
+    if STATE
+        .compare_exchange(REQUESTED, TAKING, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        return;
+    }
+
+    let (pc, sp, mut fp) = get_registers(&*(context as *const libc::ucontext_t));
+    let mut len = 0;
+    let mut host = false;
+    if is_guest_code(pc) {
+        SAMPLE_PCS[0].store(pc, Ordering::Relaxed);
+        len = 1;
+
+        // Each frame record holds the frame pointer and the return address
+        // of the caller. Only records on the Wasm stack are read and the walk
+        // stops at the first caller that is not guest code, so this never
+        // touches memory that the host may have unmapped.
+        let record_size = 2 * std::mem::size_of::<usize>();
+        while len < MAX_DEPTH
+            && fp >= sp
+            && fp % std::mem::align_of::<usize>() == 0
+            && wasmer_vm::is_on_wasm_stack(fp)
+            && wasmer_vm::is_on_wasm_stack(fp + record_size - 1)
+        {
+            let record = fp as *const usize;
+            let (next, ret) = (record.read(), record.add(1).read());
+            if !is_guest_code(ret) {
+                break;
+            }
+            // Return addresses point after the call instruction
+            SAMPLE_PCS[len].store(ret - 1, Ordering::Relaxed);
+            len += 1;
+            // The stack grows down, so callers have higher frame pointers
+            if next <= fp {
+                break;
+            }
+            fp = next;
+        }
+    } else {
+        host = wasmer_vm::is_in_wasm_call();
+    }
+
+    SAMPLE_LEN.store(len, Ordering::SeqCst);
+    SAMPLE_HOST.store(host, Ordering::SeqCst);
+    STATE.store(DONE, Ordering::SeqCst);
+}
+
+fn is_guest_code(pc: usize) -> bool {
+    let len = CODE_RANGES_LEN.load(Ordering::SeqCst);
+    CODE_RANGES[..len].iter().any(|(start, end)| {
+        start.load(Ordering::Relaxed) <= pc && pc <= end.load(Ordering::Relaxed)
+    })
+}
+
+/// Returns the program counter, stack pointer and frame pointer of the
+/// interrupted code (the frame pointer is 0 where frame records are not
+/// walked)
+unsafe fn get_registers(context: &libc::ucontext_t) -> (usize, usize, usize) {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86_64")] {
+            let regs = &context.uc_mcontext.gregs;
+            (
+                regs[libc::REG_RIP as usize] as usize,
+                regs[libc::REG_RSP as usize] as usize,
+                regs[libc::REG_RBP as usize] as usize,
+            )
+        } else if #[cfg(target_arch = "aarch64")] {
+            let mcontext = &context.uc_mcontext;
+            (mcontext.pc as usize, mcontext.sp as usize, mcontext.regs[29] as usize)
+        } else if #[cfg(any(target_arch = "riscv64", target_arch = "riscv32"))] {
+            (context.uc_mcontext.__gregs[libc::REG_PC] as usize, 0, 0)
+        } else {
+            let _ = context;
+            (0, 0, 0)
+        }
+    }
+}
+
+/// What a thread was doing when it was sampled
+enum Sample {
+    /// The program counters of the guest frames, innermost first
+    Guest(Vec<usize>),
+    /// Host code called by the guest
+    Host,
+    /// Neither guest code nor host code called by it
+    Idle,
+    /// The thread did not finish taking its sample in time
+    Stuck,
+}
+
+fn sample_loop(period: Duration, stop: &AtomicBool) -> Profile {
+    let started = Instant::now();
+    let pid = std::process::id() as libc::pid_t;
+    let own_tid = unsafe { libc::gettid() };
+
+    let mut symbols: HashMap<usize, Option<FrameInfo>> = HashMap::new();
+    let mut stacks: HashMap<(bool, Vec<usize>), u64> = HashMap::new();
+
+    'sampling: while !stop.load(Ordering::SeqCst) {
+        std::thread::sleep(period);
+        update_code_ranges();
+
+        let Ok(tasks) = std::fs::read_dir("/proc/self/task") else {
+            break;
+        };
+        let tids = tasks
+            .filter_map(|task| task.ok()?.file_name().to_str()?.parse().ok())
+            .filter(|tid| *tid != own_tid);
+        for tid in tids {
+            let pcs = match sample_thread(pid, tid) {
+                Sample::Guest(pcs) => pcs,
+                Sample::Host => {
+                    *stacks.entry((true, Vec::new())).or_default() += 1;
+                    continue;
+                }
+                Sample::Idle => continue,
+                // The handler may still write to the sample, so no other
+                // sample can be taken safely
+                Sample::Stuck => break 'sampling,
+            };
+
+            // Symbolicate while the modules are still registered
+            let info = FRAME_INFO.read().unwrap();
+            let stack = pcs
+                .into_iter()
+                .filter(|pc| {
+                    symbols
+                        .entry(*pc)
+                        .or_insert_with(|| info.lookup_frame_info(*pc))
+                        .is_some()
+                })
+                .collect::<Vec<_>>();
+            if !stack.is_empty() {
+                *stacks.entry((false, stack)).or_default() += 1;
+            }
+        }
+    }
+
+    let samples = stacks
+        .into_iter()
+        .map(|((host, stack), count)| ProfileSample {
+            host,
+            frames: stack
+                .iter()
+                .filter_map(|pc| symbols.get(pc).cloned().flatten())
+                .collect(),
+            count,
+        })
+        .collect();
+    Profile {
+        period,
+        duration: started.elapsed(),
+        samples,
+    }
+}
+
+fn update_code_ranges() {
+    let info = FRAME_INFO.read().unwrap();
+    let mut len = 0;
+    for ((start, end), slot) in info.code_ranges().zip(CODE_RANGES.iter()) {
+        slot.0.store(start, Ordering::Relaxed);
+        slot.1.store(end, Ordering::Relaxed);
+        len += 1;
+    }
+    CODE_RANGES_LEN.store(len, Ordering::SeqCst);
+}
+
+/// Interrupts a thread and returns what it was running
+fn sample_thread(pid: libc::pid_t, tid: libc::pid_t) -> Sample {
+    STATE.store(REQUESTED, Ordering::SeqCst);
+    if unsafe { libc::syscall(libc::SYS_tgkill, pid, tid, libc::SIGPROF) } != 0 {
+        STATE.store(IDLE, Ordering::SeqCst);
+        return Sample::Idle;
+    }
+
+    let deadline = Instant::now() + SAMPLE_TIMEOUT;
+    loop {
+        match STATE.load(Ordering::SeqCst) {
+            DONE => break,
+            // Once the handler started it is allowed to finish, as long as it
+            // does so in time
+            TAKING if Instant::now() < deadline + TAKING_TIMEOUT => std::thread::yield_now(),
+            TAKING => return Sample::Stuck,
+            _ if Instant::now() < deadline => std::thread::yield_now(),
+            _ => {
+                // The thread did not handle the signal in time (it may have
+                // exited or be blocking it), make sure a late handler does
+                // not take the next sample
+                if STATE
+                    .compare_exchange(REQUESTED, IDLE, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+                {
+                    return Sample::Idle;
+                }
+            }
+        }
+    }
+
+    let len = SAMPLE_LEN.load(Ordering::SeqCst);
+    let host = SAMPLE_HOST.load(Ordering::SeqCst);
+    let pcs = SAMPLE_PCS[..len]
+        .iter()
+        .map(|pc| pc.load(Ordering::Relaxed))
+        .collect::<Vec<_>>();
+    STATE.store(IDLE, Ordering::SeqCst);
+    match (host, pcs.is_empty()) {
+        (true, _) => Sample::Host,
+        (false, false) => Sample::Guest(pcs),
+        (false, true) => Sample::Idle,
+    }
+}
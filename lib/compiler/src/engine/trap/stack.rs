@@ -1,21 +1,25 @@
 use super::frame_info::{GlobalFrameInfo, FRAME_INFO};
 use backtrace::Backtrace;
 use wasmer_types::{FrameInfo, TrapCode};
-use wasmer_vm::Trap;
+use wasmer_vm::{Trap, TrapState};
 
 /// Given a `Trap`, this function returns the Wasm trace and the trap code.
 pub fn get_trace_and_trapcode(trap: &Trap) -> (Vec<FrameInfo>, Option<TrapCode>) {
     let info = FRAME_INFO.read().unwrap();
     match &trap {
         // A user error
-        Trap::User(_err) => (wasm_trace(&info, None, &Backtrace::new_unresolved()), None),
+        Trap::User(_err) => (
+            wasm_trace(&info, None, &Backtrace::new_unresolved(), None),
+            None,
+        ),
         // A trap caused by the VM being Out of Memory
-        Trap::OOM { backtrace } => (wasm_trace(&info, None, backtrace), None),
+        Trap::OOM { backtrace } => (wasm_trace(&info, None, backtrace, None), None),
         // A trap caused by an error on the generated machine code for a Wasm function
         Trap::Wasm {
             pc,
             signal_trap,
             backtrace,
+            state,
         } => {
             let trap_code = info
                 .lookup_trap_info(*pc)
@@ -23,13 +27,16 @@ pub fn get_trace_and_trapcode(trap: &Trap) -> (Vec<FrameInfo>, Option<TrapCode>)
                     info.trap_code
                 });
 
-            (wasm_trace(&info, Some(*pc), backtrace), Some(trap_code))
+            (
+                wasm_trace(&info, Some(*pc), backtrace, Some(state)),
+                Some(trap_code),
+            )
         }
         // A trap triggered manually from the Wasmer runtime
         Trap::Lib {
             trap_code,
             backtrace,
-        } => (wasm_trace(&info, None, backtrace), Some(*trap_code)),
+        } => (wasm_trace(&info, None, backtrace, None), Some(*trap_code)),
     }
 }
 
@@ -37,7 +44,12 @@ fn wasm_trace(
     info: &GlobalFrameInfo,
     trap_pc: Option<usize>,
     backtrace: &Backtrace,
+    state: Option<&TrapState>,
 ) -> Vec<FrameInfo> {
+    // The frames found through the frame pointers, which are in the same
+    // order as the backtrace but may miss some of its frames
+    let mut frames = state.map(|state| state.frames()).unwrap_or_default();
+
     // Let's construct the trace
     backtrace
         .frames()
@@ -58,9 +70,21 @@ fn wasm_trace(
                 // previous instruction (the call instruction) so we subtract one as
                 // the lookup.
                 let pc_to_lookup = if Some(pc) == trap_pc { pc } else { pc - 1 };
-                Some(pc_to_lookup)
+                Some((pc, pc_to_lookup))
             }
         })
-        .filter_map(|pc| info.lookup_frame_info(pc))
+        .filter_map(|(pc, pc_to_lookup)| {
+            let frame = info.lookup_frame_info(pc_to_lookup)?;
+            let Some(state) = state else {
+                return Some(frame);
+            };
+            let Some(index) = frames.iter().position(|frame| frame.pc == pc) else {
+                return Some(frame);
+            };
+            let innermost = Some(pc) == trap_pc;
+            let locals = info.lookup_frame_locals(&frames[index], innermost, state);
+            frames = &frames[index + 1..];
+            Some(frame.with_locals(locals))
+        })
         .collect::<Vec<_>>()
 }
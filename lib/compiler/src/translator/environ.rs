@@ -405,6 +405,11 @@ impl<'data> ModuleEnvironment<'data> {
         Ok(())
     }
 
+    pub(crate) fn declare_code_section(&mut self, offset: usize) -> WasmResult<()> {
+        self.module.code_section_offset = Some(offset);
+        Ok(())
+    }
+
     pub(crate) fn define_function_body(
         &mut self,
         _module_translation_state: &ModuleTranslationState,
@@ -11,7 +11,7 @@ use crate::lib::std::vec::Vec;
 use rkyv::{Archive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
 #[cfg(feature = "enable-serde")]
 use serde::{Deserialize, Serialize};
-use wasmer_types::SourceLoc;
+use wasmer_types::{SourceLoc, Type};
 
 /// Single source location to generated address mapping.
 #[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
@@ -51,3 +51,49 @@ pub struct FunctionAddressMap {
     /// Generated function body length.
     pub body_len: usize,
 }
+
+/// Where the value of a local lives.
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "artifact-size", derive(loupe::MemoryUsage))]
+#[derive(RkyvSerialize, RkyvDeserialize, Archive, Debug, Clone, Copy, PartialEq, Eq)]
+#[rkyv(derive(Debug))]
+pub enum LocalLocation {
+    /// In a register, identified by its DWARF register number.
+    Register(u16),
+    /// In the stack frame, at an offset from the canonical frame address.
+    FrameOffset(i32),
+}
+
+/// Where a local lives while a range of the function's code runs.
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "artifact-size", derive(loupe::MemoryUsage))]
+#[derive(RkyvSerialize, RkyvDeserialize, Archive, Debug, Clone, Copy, PartialEq, Eq)]
+#[rkyv(derive(Debug))]
+pub struct LocalRange {
+    /// The index of the local, parameters come first.
+    pub local: u32,
+
+    /// Offset in the function body where the range starts.
+    pub start: u32,
+
+    /// Offset in the function body where the range ends (exclusive).
+    pub end: u32,
+
+    /// Where the local lives during the range.
+    pub location: LocalLocation,
+}
+
+/// The locals of a function and where their values live.
+///
+/// Compilers that don't track locals leave this empty.
+#[cfg_attr(feature = "artifact-size", derive(loupe::MemoryUsage))]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+#[derive(RkyvSerialize, RkyvDeserialize, Archive, Debug, Clone, PartialEq, Eq, Default)]
+#[rkyv(derive(Debug))]
+pub struct FunctionLocalsMap {
+    /// The types of the locals, starting with the parameters.
+    pub types: Vec<Type>,
+
+    /// Where the locals live, sorted by the `LocalRange::start` field.
+    pub ranges: Vec<LocalRange>,
+}
@@ -10,7 +10,7 @@
 //! module (`CompiledFunction`).
 
 use super::{
-    address_map::FunctionAddressMap,
+    address_map::{FunctionAddressMap, FunctionLocalsMap},
     relocation::Relocation,
     section::{CustomSection, SectionIndex},
     unwind::{
@@ -43,6 +43,9 @@ pub struct CompiledFunctionFrameInfo {
 
     /// The address map.
     pub address_map: FunctionAddressMap,
+
+    /// Where the locals of the function live.
+    pub locals: FunctionLocalsMap,
 }
 
 /// The function body.
@@ -110,6 +110,37 @@ pub struct ExecutableJobCompatibilityMapV1 {
     pub other: IndexMap<String, serde_json::Value>,
 }
 
+impl Job {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn trigger(&self) -> &JobTrigger {
+        &self.trigger
+    }
+
+    pub fn action(&self) -> &JobActionCase {
+        &self.action.action
+    }
+}
+
+impl ExecutableJob {
+    /// The package that contains the command to run, if it differs from the
+    /// app's package.
+    pub fn package(&self) -> Option<&PackageSource> {
+        self.package.as_ref()
+    }
+
+    /// The command to run, if it isn't the package's entrypoint.
+    pub fn command(&self) -> Option<&str> {
+        self.command.as_deref()
+    }
+
+    pub fn cli_args(&self) -> Option<&[String]> {
+        self.cli_args.as_deref()
+    }
+}
+
 impl Serialize for JobTrigger {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
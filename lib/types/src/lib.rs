@@ -104,7 +104,7 @@ pub use crate::memory::MemoryStyle;
 pub use crate::table::TableStyle;
 pub use serialize::MetadataHeader;
 // TODO: OnCalledAction is needed for asyncify. It will be refactored with https://github.com/wasmerio/wasmer/issues/3451
-pub use crate::stack::{FrameInfo, SourceLoc, TrapInformation};
+pub use crate::stack::{FrameInfo, FrameSymbol, FrameValue, SourceLoc, TrapInformation};
 pub use crate::store_id::StoreId;
 pub use crate::trapcode::{OnCalledAction, TrapCode};
 pub use crate::utils::is_wasm;
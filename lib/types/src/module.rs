@@ -174,6 +174,11 @@ pub struct ModuleInfo {
     /// The data for each CustomSection in the module.
     pub custom_sections_data: PrimaryMap<CustomSectionIndex, Box<[u8]>>,
 
+    /// Offset of the code section contents in the original wasm module.
+    ///
+    /// DWARF addresses are relative to this offset.
+    pub code_section_offset: Option<usize>,
+
     /// Number of imported functions in the module.
     pub num_imported_functions: usize,
 
@@ -212,6 +217,7 @@ pub struct ArchivableModuleInfo {
     tags: PrimaryMap<TagIndex, SignatureIndex>,
     custom_sections: IndexMap<String, CustomSectionIndex>,
     custom_sections_data: PrimaryMap<CustomSectionIndex, Box<[u8]>>,
+    code_section_offset: Option<usize>,
     num_imported_functions: usize,
     num_imported_tables: usize,
     num_imported_tags: usize,
@@ -240,6 +246,7 @@ impl From<ModuleInfo> for ArchivableModuleInfo {
             tags: it.tags,
             custom_sections: it.custom_sections,
             custom_sections_data: it.custom_sections_data,
+            code_section_offset: it.code_section_offset,
             num_imported_functions: it.num_imported_functions,
             num_imported_tables: it.num_imported_tables,
             num_imported_tags: it.num_imported_tags,
@@ -271,6 +278,7 @@ impl From<ArchivableModuleInfo> for ModuleInfo {
             tags: it.tags,
             custom_sections: it.custom_sections,
             custom_sections_data: it.custom_sections_data,
+            code_section_offset: it.code_section_offset,
             num_imported_functions: it.num_imported_functions,
             num_imported_tables: it.num_imported_tables,
             num_imported_tags: it.num_imported_tags,
@@ -335,6 +343,7 @@ impl PartialEq for ModuleInfo {
             && self.tags == other.tags
             && self.custom_sections == other.custom_sections
             && self.custom_sections_data == other.custom_sections_data
+            && self.code_section_offset == other.code_section_offset
             && self.num_imported_functions == other.num_imported_functions
             && self.num_imported_tables == other.num_imported_tables
             && self.num_imported_tags == other.num_imported_tags
@@ -16,6 +16,11 @@ pub struct FrameInfo {
     func_start: SourceLoc,
     /// The source location of the instruction
     instr: SourceLoc,
+    /// The source-level frames for the instruction, if debug info is available
+    symbols: Vec<FrameSymbol>,
+    /// The values of the locals of the function, if the compiler recorded
+    /// where they live
+    locals: Vec<Option<FrameValue>>,
 }
 
 impl FrameInfo {
@@ -33,9 +38,25 @@ impl FrameInfo {
             function_name,
             func_start,
             instr,
+            symbols: Vec::new(),
+            locals: Vec::new(),
         }
     }
 
+    /// Attaches the source-level frames resolved from the module's debug
+    /// information.
+    pub fn with_symbols(mut self, symbols: Vec<FrameSymbol>) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    /// Attaches the values of the locals of the function, starting with its
+    /// parameters.
+    pub fn with_locals(mut self, locals: Vec<Option<FrameValue>>) -> Self {
+        self.locals = locals;
+        self
+    }
+
     /// Returns the WebAssembly function index for this frame.
     ///
     /// This function index is the index in the function index space of the
@@ -93,4 +114,91 @@ impl FrameInfo {
     pub fn func_offset(&self) -> usize {
         (self.instr.bits() - self.func_start.bits()) as usize
     }
+
+    /// Returns the source-level frames for this frame's program counter.
+    ///
+    /// These are resolved from the DWARF debug information embedded in the
+    /// original wasm module, when present. Because of inlining, a single wasm
+    /// frame may map to several source frames: the innermost (inlined) frame
+    /// comes first and the frame of the enclosing function comes last.
+    ///
+    /// This returns an empty slice when the module has no debug information.
+    pub fn symbols(&self) -> &[FrameSymbol] {
+        &self.symbols
+    }
+
+    /// Returns the values of the locals of the function at the time the
+    /// frame was captured, starting with its parameters.
+    ///
+    /// A local is `None` when its value could not be recovered, for example
+    /// because it was not live at that point. This returns an empty slice
+    /// when the compiler does not record where locals live.
+    pub fn locals(&self) -> &[Option<FrameValue>] {
+        &self.locals
+    }
+}
+
+/// The value of a local of a frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameValue {
+    /// A 32-bit integer.
+    I32(i32),
+    /// A 64-bit integer.
+    I64(i64),
+    /// A 32-bit float.
+    F32(f32),
+    /// A 64-bit float.
+    F64(f64),
+    /// A 128-bit vector.
+    V128(u128),
+}
+
+/// A source-level frame resolved from the debug information of a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameSymbol {
+    /// The (demangled) name of the function, if known.
+    function: Option<String>,
+    /// The path of the source file, if known.
+    file: Option<String>,
+    /// The line number in the source file, if known.
+    line: Option<u32>,
+    /// The column number in the source file, if known.
+    column: Option<u32>,
+}
+
+impl FrameSymbol {
+    /// Creates a new [FrameSymbol].
+    pub fn new(
+        function: Option<String>,
+        file: Option<String>,
+        line: Option<u32>,
+        column: Option<u32>,
+    ) -> Self {
+        Self {
+            function,
+            file,
+            line,
+            column,
+        }
+    }
+
+    /// Returns the name of the source function, if known.
+    pub fn function(&self) -> Option<&str> {
+        self.function.as_deref()
+    }
+
+    /// Returns the path of the source file, if known.
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    /// Returns the line number in the source file, if known.
+    pub fn line(&self) -> Option<u32> {
+        self.line
+    }
+
+    /// Returns the column number in the source file, if known.
+    pub fn column(&self) -> Option<u32> {
+        self.column
+    }
 }
@@ -7,11 +7,14 @@ pub mod composite;
 pub mod host;
 pub mod loopback;
 pub mod meta;
+#[cfg(feature = "resolver")]
+pub mod resolver;
 pub mod ruleset;
 #[cfg(feature = "remote")]
 pub mod rx_tx;
 #[cfg(feature = "remote")]
 pub mod server;
+pub mod shaping;
 pub mod tcp_pair;
 #[cfg(feature = "tokio")]
 #[cfg(test)]
@@ -22,10 +25,13 @@ pub use client::{RemoteNetworkingClient, RemoteNetworkingClientDriver};
 pub use composite::CompositeTcpListener;
 pub use loopback::LoopbackNetworking;
 use pin_project_lite::pin_project;
+#[cfg(feature = "resolver")]
+pub use resolver::{HostsFile, ResolverNetworking, Zone, ZoneSource};
 #[cfg(feature = "rkyv")]
 use rkyv::{Archive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
 #[cfg(feature = "remote")]
 pub use server::{RemoteNetworkingServer, RemoteNetworkingServerDriver};
+pub use shaping::{NetworkConditions, ShapedNetworking};
 use std::fmt;
 use std::mem::MaybeUninit;
 use std::net::IpAddr;
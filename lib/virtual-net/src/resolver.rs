@@ -0,0 +1,1133 @@
+//! A configurable DNS resolver which can be layered on top of any
+//! [`VirtualNetworking`] implementation.
+//!
+//! [`ResolverNetworking`] forwards everything to the networking it wraps,
+//! except for [`VirtualNetworking::resolve`]. Names are looked up in order:
+//!
+//! 1. the static hosts entries (see [`HostsFile`])
+//! 2. answers which are still in the cache
+//! 3. the zone with the longest matching suffix (split-horizon), which either
+//!    answers from its own records or forwards the query to its DNS server
+//! 4. the DNS server the guest asked for, or the configured upstream server
+//! 5. the wrapped networking's own resolver
+//!
+//! DNS servers are queried over UDP using sockets from the wrapped
+//! networking, so any firewall rules it applies still hold. Truncated
+//! answers are asked for again over TCP.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::mem::MaybeUninit;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::{
+    DynVirtualNetworking, IpCidr, IpRoute, NetworkError, Result, StreamSecurity,
+    VirtualConnectedSocketExt, VirtualConnectionlessSocketExt, VirtualIcmpSocket,
+    VirtualNetworking, VirtualRawSocket, VirtualTcpListener, VirtualTcpSocket, VirtualUdpSocket,
+};
+
+/// The port DNS servers listen on when none is given.
+pub const DNS_PORT: u16 = 53;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+const DEFAULT_ATTEMPTS: usize = 2;
+const DEFAULT_MAX_TTL: Duration = Duration::from_secs(60);
+/// How long a name which doesn't exist is remembered for.
+const NEGATIVE_TTL: Duration = Duration::from_secs(10);
+const MAX_CACHE_ENTRIES: usize = 1024;
+
+/// Static name to address mappings, in the format of `/etc/hosts`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostsFile {
+    entries: HashMap<String, Vec<IpAddr>>,
+}
+
+impl HostsFile {
+    pub fn new() -> Self {
+        HostsFile::default()
+    }
+
+    /// Parse the contents of a hosts file.
+    ///
+    /// Each line holds an IP address followed by one or more names, and
+    /// everything after a `#` is a comment.
+    pub fn parse(contents: &str) -> std::result::Result<Self, HostsFileError> {
+        let mut hosts = HostsFile::new();
+
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            let Some(address) = fields.next() else {
+                continue;
+            };
+            let ip = IpAddr::from_str(address).map_err(|_| HostsFileError::InvalidAddress {
+                line: index + 1,
+                address: address.to_string(),
+            })?;
+
+            let mut names = fields.peekable();
+            if names.peek().is_none() {
+                return Err(HostsFileError::MissingNames {
+                    line: index + 1,
+                    address: ip,
+                });
+            }
+            for name in names {
+                hosts.insert(name, ip);
+            }
+        }
+
+        Ok(hosts)
+    }
+
+    /// Add an address for a name, keeping any addresses it already has.
+    pub fn insert(&mut self, name: &str, ip: IpAddr) {
+        let addrs = self.entries.entry(normalize(name)).or_default();
+        if !addrs.contains(&ip) {
+            addrs.push(ip);
+        }
+    }
+
+    /// The addresses for a name, if it has any.
+    pub fn lookup(&self, name: &str) -> Option<&[IpAddr]> {
+        self.entries
+            .get(&normalize(name))
+            .map(|addrs| addrs.as_slice())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl FromStr for HostsFile {
+    type Err = HostsFileError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        HostsFile::parse(s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum HostsFileError {
+    #[error("line {line}: \"{address}\" isn't a valid IP address")]
+    InvalidAddress { line: usize, address: String },
+    #[error("line {line}: no names were given for {address}")]
+    MissingNames { line: usize, address: IpAddr },
+}
+
+/// Where the answers for a [`Zone`] come from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ZoneSource {
+    /// Forward queries to this DNS server.
+    Server(SocketAddr),
+    /// Answer from a fixed set of records. Any other name in the zone
+    /// doesn't exist.
+    Static(HostsFile),
+}
+
+/// A domain (and all of its subdomains) which is resolved differently from
+/// everything else.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Zone {
+    pub suffix: String,
+    pub source: ZoneSource,
+}
+
+impl Zone {
+    pub fn new(suffix: &str, source: ZoneSource) -> Self {
+        Zone {
+            suffix: normalize(suffix),
+            source,
+        }
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        name == self.suffix
+            || name
+                .strip_suffix(self.suffix.as_str())
+                .is_some_and(|prefix| prefix.ends_with('.'))
+    }
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    /// Empty when the name doesn't exist.
+    addrs: Vec<IpAddr>,
+    expires: Instant,
+}
+
+/// Wraps another [`VirtualNetworking`] implementation and answers DNS
+/// queries using static records, split-horizon zones and DNS servers of its
+/// own choosing.
+#[derive(Debug)]
+pub struct ResolverNetworking {
+    inner: DynVirtualNetworking,
+    hosts: HostsFile,
+    zones: Vec<Zone>,
+    upstream: Option<SocketAddr>,
+    timeout: Duration,
+    attempts: usize,
+    max_ttl: Duration,
+    /// Answers keyed by name and record type
+    cache: Mutex<HashMap<(String, u16), CacheEntry>>,
+}
+
+impl ResolverNetworking {
+    pub fn new(inner: DynVirtualNetworking) -> Self {
+        ResolverNetworking {
+            inner,
+            hosts: HostsFile::default(),
+            zones: Vec::new(),
+            upstream: None,
+            timeout: DEFAULT_TIMEOUT,
+            attempts: DEFAULT_ATTEMPTS,
+            max_ttl: DEFAULT_MAX_TTL,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Add static records which take precedence over everything else.
+    pub fn with_hosts(mut self, hosts: HostsFile) -> Self {
+        for (name, addrs) in hosts.entries {
+            for ip in addrs {
+                self.hosts.insert(&name, ip);
+            }
+        }
+        self
+    }
+
+    pub fn with_zone(mut self, zone: Zone) -> Self {
+        self.zones.push(zone);
+        self
+    }
+
+    /// Send any query which isn't handled by a zone to this DNS server
+    /// instead of the wrapped networking's resolver.
+    pub fn with_upstream(mut self, server: SocketAddr) -> Self {
+        self.upstream = Some(server);
+        self
+    }
+
+    /// How long to wait for each answer from a DNS server.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How many times a query is sent before giving up.
+    pub fn with_attempts(mut self, attempts: usize) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// The longest time an answer is cached for. Answers from the wrapped
+    /// networking carry no TTL, so they are always kept this long.
+    ///
+    /// Use [`Duration::ZERO`] to disable caching.
+    pub fn with_max_ttl(mut self, max_ttl: Duration) -> Self {
+        self.max_ttl = max_ttl;
+        self
+    }
+
+    async fn lookup(
+        &self,
+        name: &str,
+        port: Option<u16>,
+        dns_server: Option<IpAddr>,
+    ) -> Result<Vec<IpAddr>> {
+        if let Some(addrs) = self.hosts.lookup(name) {
+            return Ok(addrs.to_vec());
+        }
+
+        if let Some(addrs) = self.cached(name) {
+            return found(addrs);
+        }
+
+        let zone = self
+            .zones
+            .iter()
+            .filter(|zone| zone.contains(name))
+            .max_by_key(|zone| zone.suffix.len());
+
+        let server = match zone.map(|zone| &zone.source) {
+            Some(ZoneSource::Static(records)) => {
+                return found(records.lookup(name).unwrap_or_default().to_vec());
+            }
+            Some(ZoneSource::Server(server)) => Some(*server),
+            None => dns_server
+                .map(|ip| SocketAddr::new(ip, DNS_PORT))
+                .or(self.upstream),
+        };
+
+        let addrs = match server {
+            Some(server) => self.query(name, server).await?,
+            None => {
+                let addrs = self.inner.resolve(name, port, dns_server).await?;
+                let (v4, v6): (Vec<IpAddr>, Vec<IpAddr>) =
+                    addrs.iter().partition(|ip| ip.is_ipv4());
+                self.remember(name, wire::TYPE_A, &v4, self.max_ttl);
+                self.remember(name, wire::TYPE_AAAA, &v6, self.max_ttl);
+                addrs
+            }
+        };
+
+        found(addrs)
+    }
+
+    /// The addresses of a name, if both its A and AAAA records are cached.
+    fn cached(&self, name: &str) -> Option<Vec<IpAddr>> {
+        let mut addrs = self.cached_records(name, wire::TYPE_A)?;
+        addrs.extend(self.cached_records(name, wire::TYPE_AAAA)?);
+        Some(addrs)
+    }
+
+    fn cached_records(&self, name: &str, record_type: u16) -> Option<Vec<IpAddr>> {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .get(&(name.to_string(), record_type))
+            .filter(|entry| entry.expires > Instant::now())
+            .map(|entry| entry.addrs.clone())
+    }
+
+    fn remember(&self, name: &str, record_type: u16, addrs: &[IpAddr], ttl: Duration) {
+        let ttl = if addrs.is_empty() {
+            ttl.min(NEGATIVE_TTL)
+        } else {
+            ttl
+        }
+        .min(self.max_ttl);
+        if ttl.is_zero() {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHE_ENTRIES {
+            cache.retain(|_, entry| entry.expires > now);
+        }
+        if cache.len() >= MAX_CACHE_ENTRIES {
+            if let Some(oldest) = cache
+                .iter()
+                .min_by_key(|(_, entry)| entry.expires)
+                .map(|(key, _)| key.clone())
+            {
+                cache.remove(&oldest);
+            }
+        }
+        cache.insert(
+            (name.to_string(), record_type),
+            CacheEntry {
+                addrs: addrs.to_vec(),
+                expires: now + ttl,
+            },
+        );
+    }
+
+    /// Ask a DNS server for both the IPv4 and IPv6 addresses of a name,
+    /// caching the answer for each record type separately.
+    async fn query(&self, name: &str, server: SocketAddr) -> Result<Vec<IpAddr>> {
+        let mut socket = None;
+        let mut addrs = Vec::new();
+
+        for record_type in [wire::TYPE_A, wire::TYPE_AAAA] {
+            if let Some(cached) = self.cached_records(name, record_type) {
+                addrs.extend(cached);
+                continue;
+            }
+
+            let socket = match &mut socket {
+                Some(socket) => socket,
+                None => socket.insert(self.bind_udp_for(server).await?),
+            };
+            let query = wire::encode_query(query_id(), name, record_type)?;
+            let answer = match self.exchange(socket.as_mut(), server, &query).await? {
+                Exchange::Answer(answer) => answer,
+                Exchange::Truncated => self.exchange_tcp(server, &query).await?,
+            };
+
+            match answer {
+                Some(answer) => {
+                    let ttl = if answer.addrs.is_empty() {
+                        NEGATIVE_TTL
+                    } else {
+                        answer.ttl
+                    };
+                    self.remember(name, record_type, &answer.addrs, ttl);
+                    addrs.extend(answer.addrs);
+                }
+                // A name which doesn't exist won't have any other records
+                None => {
+                    self.remember(name, wire::TYPE_A, &[], NEGATIVE_TTL);
+                    self.remember(name, wire::TYPE_AAAA, &[], NEGATIVE_TTL);
+                    break;
+                }
+            }
+        }
+
+        Ok(addrs)
+    }
+
+    async fn bind_udp_for(&self, server: SocketAddr) -> Result<Box<dyn VirtualUdpSocket + Sync>> {
+        self.inner
+            .bind_udp(unspecified_for(server), false, false)
+            .await
+    }
+
+    /// Send a query and wait for its response, retrying when nothing comes
+    /// back in time.
+    async fn exchange(
+        &self,
+        socket: &mut (dyn VirtualUdpSocket + Sync),
+        server: SocketAddr,
+        query: &[u8],
+    ) -> Result<Exchange> {
+        let mut buffer = [MaybeUninit::<u8>::uninit(); wire::MAX_MESSAGE_SIZE];
+
+        for attempt in 1..=self.attempts {
+            socket.send_to(query, server).await?;
+
+            let deadline = tokio::time::Instant::now() + self.timeout;
+            loop {
+                let received =
+                    tokio::time::timeout_at(deadline, socket.recv_from(&mut buffer)).await;
+                let (len, from) = match received {
+                    Ok(result) => result?,
+                    Err(_) => break,
+                };
+                if from != server {
+                    continue;
+                }
+
+                // SAFETY: recv_from() initialized the first `len` bytes
+                let message = unsafe { std::slice::from_raw_parts(buffer.as_ptr().cast(), len) };
+                match wire::decode_response(query, message) {
+                    Ok(answer) => return Ok(Exchange::Answer(answer)),
+                    // Anything which doesn't answer our question may be
+                    // spoofed, so keep waiting for the real response
+                    Err(wire::DecodeError::WrongId | wire::DecodeError::WrongQuestion) => continue,
+                    Err(wire::DecodeError::Truncated) => return Ok(Exchange::Truncated),
+                    Err(e) => {
+                        tracing::debug!(%server, error = &e as &dyn std::error::Error, "Invalid DNS response");
+                        return Err(NetworkError::InvalidData);
+                    }
+                }
+            }
+
+            tracing::debug!(%server, attempt, "DNS query timed out");
+        }
+
+        Err(NetworkError::TimedOut)
+    }
+
+    /// Send a query over TCP, for answers which don't fit in a UDP
+    /// message.
+    async fn exchange_tcp(&self, server: SocketAddr, query: &[u8]) -> Result<Option<wire::Answer>> {
+        let exchange = async {
+            let mut socket = self
+                .inner
+                .connect_tcp(unspecified_for(server), server)
+                .await?;
+
+            // Messages are prefixed with their length
+            let mut request = Vec::with_capacity(query.len() + 2);
+            request.extend((query.len() as u16).to_be_bytes());
+            request.extend(query);
+            let mut sent = 0;
+            while sent < request.len() {
+                sent += socket.send(&request[sent..]).await?;
+            }
+            socket.flush().await?;
+
+            let mut len = [0; 2];
+            recv_exact(socket.as_mut(), &mut len).await?;
+            let mut message = vec![0; u16::from_be_bytes(len) as usize];
+            recv_exact(socket.as_mut(), &mut message).await?;
+            Ok::<_, NetworkError>(message)
+        };
+
+        let message = tokio::time::timeout(self.timeout, exchange)
+            .await
+            .map_err(|_| NetworkError::TimedOut)??;
+        wire::decode_response(query, &message).map_err(|e| {
+            tracing::debug!(%server, error = &e as &dyn std::error::Error, "Invalid DNS response over TCP");
+            NetworkError::InvalidData
+        })
+    }
+}
+
+enum Exchange {
+    /// The addresses, or `None` when the name doesn't exist
+    Answer(Option<wire::Answer>),
+    /// The answer didn't fit in a UDP message
+    Truncated,
+}
+
+async fn recv_exact(socket: &mut (dyn VirtualTcpSocket + Sync), buf: &mut [u8]) -> Result<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        // SAFETY: u8 and MaybeUninit<u8> have the same layout and recv()
+        // never writes uninitialized bytes
+        let rest = unsafe {
+            std::slice::from_raw_parts_mut(
+                buf[filled..].as_mut_ptr().cast::<MaybeUninit<u8>>(),
+                buf.len() - filled,
+            )
+        };
+        match socket.recv(rest).await? {
+            0 => return Err(NetworkError::ConnectionReset),
+            n => filled += n,
+        }
+    }
+    Ok(())
+}
+
+fn unspecified_for(server: SocketAddr) -> SocketAddr {
+    match server {
+        SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+        SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+    }
+}
+
+/// Names which don't exist are reported as
+/// [`NetworkError::AddressNotAvailable`].
+fn found(addrs: Vec<IpAddr>) -> Result<Vec<IpAddr>> {
+    if addrs.is_empty() {
+        Err(NetworkError::AddressNotAvailable)
+    } else {
+        Ok(addrs)
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.trim().trim_end_matches('.').to_ascii_lowercase()
+}
+
+fn query_id() -> u16 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.finish() as u16
+}
+
+#[async_trait::async_trait]
+impl VirtualNetworking for ResolverNetworking {
+    async fn bridge(
+        &self,
+        network: &str,
+        access_token: &str,
+        security: StreamSecurity,
+    ) -> Result<()> {
+        self.inner.bridge(network, access_token, security).await
+    }
+
+    async fn unbridge(&self) -> Result<()> {
+        self.inner.unbridge().await
+    }
+
+    async fn dhcp_acquire(&self) -> Result<Vec<IpAddr>> {
+        self.inner.dhcp_acquire().await
+    }
+
+    async fn ip_add(&self, ip: IpAddr, prefix: u8) -> Result<()> {
+        self.inner.ip_add(ip, prefix).await
+    }
+
+    async fn ip_remove(&self, ip: IpAddr) -> Result<()> {
+        self.inner.ip_remove(ip).await
+    }
+
+    async fn ip_clear(&self) -> Result<()> {
+        self.inner.ip_clear().await
+    }
+
+    async fn ip_list(&self) -> Result<Vec<IpCidr>> {
+        self.inner.ip_list().await
+    }
+
+    async fn mac(&self) -> Result<[u8; 6]> {
+        self.inner.mac().await
+    }
+
+    async fn gateway_set(&self, ip: IpAddr) -> Result<()> {
+        self.inner.gateway_set(ip).await
+    }
+
+    async fn route_add(
+        &self,
+        cidr: IpCidr,
+        via_router: IpAddr,
+        preferred_until: Option<Duration>,
+        expires_at: Option<Duration>,
+    ) -> Result<()> {
+        self.inner
+            .route_add(cidr, via_router, preferred_until, expires_at)
+            .await
+    }
+
+    async fn route_remove(&self, cidr: IpAddr) -> Result<()> {
+        self.inner.route_remove(cidr).await
+    }
+
+    async fn route_clear(&self) -> Result<()> {
+        self.inner.route_clear().await
+    }
+
+    async fn route_list(&self) -> Result<Vec<IpRoute>> {
+        self.inner.route_list().await
+    }
+
+    async fn bind_raw(&self) -> Result<Box<dyn VirtualRawSocket + Sync>> {
+        self.inner.bind_raw().await
+    }
+
+    async fn listen_tcp(
+        &self,
+        addr: SocketAddr,
+        only_v6: bool,
+        reuse_port: bool,
+        reuse_addr: bool,
+    ) -> Result<Box<dyn VirtualTcpListener + Sync>> {
+        self.inner
+            .listen_tcp(addr, only_v6, reuse_port, reuse_addr)
+            .await
+    }
+
+    async fn bind_udp(
+        &self,
+        addr: SocketAddr,
+        reuse_port: bool,
+        reuse_addr: bool,
+    ) -> Result<Box<dyn VirtualUdpSocket + Sync>> {
+        self.inner.bind_udp(addr, reuse_port, reuse_addr).await
+    }
+
+    async fn bind_icmp(&self, addr: IpAddr) -> Result<Box<dyn VirtualIcmpSocket + Sync>> {
+        self.inner.bind_icmp(addr).await
+    }
+
+    async fn connect_tcp(
+        &self,
+        addr: SocketAddr,
+        peer: SocketAddr,
+    ) -> Result<Box<dyn VirtualTcpSocket + Sync>> {
+        self.inner.connect_tcp(addr, peer).await
+    }
+
+    async fn resolve(
+        &self,
+        host: &str,
+        port: Option<u16>,
+        dns_server: Option<IpAddr>,
+    ) -> Result<Vec<IpAddr>> {
+        if let Ok(ip) = IpAddr::from_str(host) {
+            return Ok(vec![ip]);
+        }
+
+        self.lookup(&normalize(host), port, dns_server).await
+    }
+}
+
+/// Just enough of the DNS wire format (RFC 1035) to ask for A and AAAA
+/// records.
+mod wire {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::time::Duration;
+
+    use crate::{NetworkError, Result};
+
+    pub(super) const TYPE_A: u16 = 1;
+    pub(super) const TYPE_AAAA: u16 = 28;
+    const CLASS_IN: u16 = 1;
+    /// Without EDNS, servers never send more than this over UDP.
+    pub(super) const MAX_MESSAGE_SIZE: usize = 512;
+
+    const FLAG_RESPONSE: u16 = 0x8000;
+    const FLAG_TRUNCATED: u16 = 0x0200;
+    const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+    const RCODE_MASK: u16 = 0x000f;
+    const RCODE_NO_ERROR: u16 = 0;
+    const RCODE_NAME_ERROR: u16 = 3;
+    const HEADER_LEN: usize = 12;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub(super) struct Answer {
+        pub addrs: Vec<IpAddr>,
+        pub ttl: Duration,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+    pub(super) enum DecodeError {
+        #[error("the response is for a different query")]
+        WrongId,
+        #[error("the response doesn't answer the question which was asked")]
+        WrongQuestion,
+        #[error("the response didn't fit in the message")]
+        Truncated,
+        #[error("the message isn't a response")]
+        NotAResponse,
+        #[error("the server failed with response code {0}")]
+        Failed(u16),
+        #[error("the message is truncated or malformed")]
+        Malformed,
+    }
+
+    pub(super) fn encode_query(id: u16, name: &str, record_type: u16) -> Result<Vec<u8>> {
+        let mut message = Vec::with_capacity(HEADER_LEN + name.len() + 6);
+        message.extend(id.to_be_bytes());
+        message.extend(FLAG_RECURSION_DESIRED.to_be_bytes());
+        // One question, no answer, authority or additional records
+        message.extend([0, 1, 0, 0, 0, 0, 0, 0]);
+
+        if name.len() > 253 {
+            return Err(NetworkError::InvalidInput);
+        }
+        for label in name.split('.') {
+            if label.is_empty() || label.len() > 63 {
+                return Err(NetworkError::InvalidInput);
+            }
+            message.push(label.len() as u8);
+            message.extend(label.as_bytes());
+        }
+        message.push(0);
+
+        message.extend(record_type.to_be_bytes());
+        message.extend(CLASS_IN.to_be_bytes());
+        Ok(message)
+    }
+
+    /// Pull the addresses out of the response to a query made by
+    /// [`encode_query`], or `None` if the name doesn't exist.
+    pub(super) fn decode_response(
+        query: &[u8],
+        message: &[u8],
+    ) -> std::result::Result<Option<Answer>, DecodeError> {
+        let mut reader = Reader { message, pos: 0 };
+
+        if reader.u16()? != u16::from_be_bytes([query[0], query[1]]) {
+            return Err(DecodeError::WrongId);
+        }
+        let flags = reader.u16()?;
+        if flags & FLAG_RESPONSE == 0 {
+            return Err(DecodeError::NotAResponse);
+        }
+        let questions = reader.u16()?;
+        let answers = reader.u16()?;
+        reader.skip(4)?;
+
+        // The response must repeat our question. Servers are free to change
+        // the case of the name.
+        let question = &query[HEADER_LEN..];
+        match reader.take(question.len()) {
+            Ok(echoed) if questions == 1 && echoed.eq_ignore_ascii_case(question) => {}
+            _ => return Err(DecodeError::WrongQuestion),
+        }
+
+        if flags & FLAG_TRUNCATED != 0 {
+            return Err(DecodeError::Truncated);
+        }
+        match flags & RCODE_MASK {
+            RCODE_NO_ERROR => {}
+            RCODE_NAME_ERROR => return Ok(None),
+            rcode => return Err(DecodeError::Failed(rcode)),
+        }
+
+        let mut addrs = Vec::new();
+        let mut ttl = u32::MAX;
+
+        // Any CNAMEs come first and the records for their targets follow, so
+        // every address in the answer section belongs to the name we asked
+        // about.
+        for _ in 0..answers {
+            reader.skip_name()?;
+            let record_type = reader.u16()?;
+            let class = reader.u16()?;
+            let record_ttl = reader.u32()?;
+            let len = reader.u16()? as usize;
+            let data = reader.take(len)?;
+
+            let ip = match (record_type, class, data.len()) {
+                (TYPE_A, CLASS_IN, 4) => {
+                    IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(data).unwrap()))
+                }
+                (TYPE_AAAA, CLASS_IN, 16) => {
+                    IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(data).unwrap()))
+                }
+                _ => continue,
+            };
+            addrs.push(ip);
+            ttl = ttl.min(record_ttl);
+        }
+
+        let ttl = if addrs.is_empty() { 0 } else { ttl };
+        Ok(Some(Answer {
+            addrs,
+            ttl: Duration::from_secs(ttl.into()),
+        }))
+    }
+
+    struct Reader<'a> {
+        message: &'a [u8],
+        pos: usize,
+    }
+
+    impl<'a> Reader<'a> {
+        fn take(&mut self, len: usize) -> std::result::Result<&'a [u8], DecodeError> {
+            let bytes = self
+                .message
+                .get(self.pos..self.pos + len)
+                .ok_or(DecodeError::Malformed)?;
+            self.pos += len;
+            Ok(bytes)
+        }
+
+        fn skip(&mut self, len: usize) -> std::result::Result<(), DecodeError> {
+            self.take(len).map(|_| ())
+        }
+
+        fn u16(&mut self) -> std::result::Result<u16, DecodeError> {
+            let bytes = self.take(2)?;
+            Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+        }
+
+        fn u32(&mut self) -> std::result::Result<u32, DecodeError> {
+            let bytes = self.take(4)?;
+            Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        }
+
+        /// Skip over a (possibly compressed) name.
+        fn skip_name(&mut self) -> std::result::Result<(), DecodeError> {
+            loop {
+                let len = self.take(1)?[0];
+                match len {
+                    0 => return Ok(()),
+                    // A pointer always ends the name
+                    len if len & 0xc0 == 0xc0 => return self.skip(1),
+                    len if len & 0xc0 == 0 => self.skip(len as usize)?,
+                    _ => return Err(DecodeError::Malformed),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    use super::*;
+
+    /// Networking which counts how often it is asked to resolve something.
+    #[derive(Debug, Default)]
+    struct CountingNetworking {
+        lookups: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl VirtualNetworking for CountingNetworking {
+        async fn resolve(
+            &self,
+            host: &str,
+            _port: Option<u16>,
+            _dns_server: Option<IpAddr>,
+        ) -> Result<Vec<IpAddr>> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            match host {
+                "example.com" => Ok(vec![Ipv4Addr::new(93, 184, 215, 14).into()]),
+                _ => Err(NetworkError::AddressNotAvailable),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_a_hosts_file() {
+        let hosts = HostsFile::parse(
+            "# Services\n\
+             10.0.0.1  db  db.internal  # primary\n\
+             \n\
+             ::1 localhost\n\
+             127.0.0.1 LocalHost.\n",
+        )
+        .unwrap();
+
+        assert_eq!(hosts.lookup("db"), Some(&[IpAddr::from([10, 0, 0, 1])][..]));
+        assert_eq!(
+            hosts.lookup("DB.internal."),
+            Some(&[IpAddr::from([10, 0, 0, 1])][..])
+        );
+        assert_eq!(
+            hosts.lookup("localhost").unwrap(),
+            [
+                Ipv6Addr::LOCALHOST.into(),
+                IpAddr::from(Ipv4Addr::LOCALHOST)
+            ]
+        );
+        assert_eq!(hosts.lookup("cache"), None);
+
+        assert_eq!(
+            HostsFile::parse("10.0.0.1 db\n10.0.0.x cache\n").unwrap_err(),
+            HostsFileError::InvalidAddress {
+                line: 2,
+                address: "10.0.0.x".to_string()
+            }
+        );
+        assert!(matches!(
+            HostsFile::parse("10.0.0.1\n").unwrap_err(),
+            HostsFileError::MissingNames { line: 1, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn static_records_and_zones() {
+        let inner = Arc::new(CountingNetworking::default());
+        let mut zone = HostsFile::new();
+        zone.insert("api.svc.cluster", Ipv4Addr::new(10, 1, 0, 1).into());
+        let resolver = ResolverNetworking::new(inner.clone())
+            .with_hosts("10.0.0.1 db".parse().unwrap())
+            .with_zone(Zone::new("svc.cluster.", ZoneSource::Static(zone)));
+
+        assert_eq!(
+            resolver.resolve("DB", None, None).await.unwrap(),
+            [IpAddr::from([10, 0, 0, 1])]
+        );
+        assert_eq!(
+            resolver
+                .resolve("api.svc.cluster", None, None)
+                .await
+                .unwrap(),
+            [IpAddr::from([10, 1, 0, 1])]
+        );
+        assert_eq!(
+            resolver.resolve("web.svc.cluster", None, None).await,
+            Err(NetworkError::AddressNotAvailable)
+        );
+        assert_eq!(
+            resolver.resolve("192.168.1.1", None, None).await.unwrap(),
+            [IpAddr::from([192, 168, 1, 1])]
+        );
+        // None of those should have reached the wrapped networking
+        assert_eq!(inner.lookups.load(Ordering::SeqCst), 0);
+
+        // "notsvc.cluster" isn't inside "svc.cluster"
+        assert_eq!(
+            resolver.resolve("notsvc.cluster", None, None).await,
+            Err(NetworkError::AddressNotAvailable)
+        );
+        assert_eq!(inner.lookups.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn cache_answers_from_the_wrapped_networking() {
+        let inner = Arc::new(CountingNetworking::default());
+        let resolver = ResolverNetworking::new(inner.clone());
+
+        for _ in 0..3 {
+            resolver.resolve("example.com", None, None).await.unwrap();
+        }
+        assert_eq!(inner.lookups.load(Ordering::SeqCst), 1);
+
+        let uncached = ResolverNetworking::new(inner.clone()).with_max_ttl(Duration::ZERO);
+        uncached.resolve("example.com", None, None).await.unwrap();
+        uncached.resolve("example.com", None, None).await.unwrap();
+        assert_eq!(inner.lookups.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn decode_a_response() {
+        let query = wire::encode_query(0x1234, "www.example.com", wire::TYPE_A).unwrap();
+        let response = respond(&query, &[("www.example.com", [1, 2, 3, 4])]);
+
+        let answer = wire::decode_response(&query, &response).unwrap().unwrap();
+
+        assert_eq!(answer.addrs, [IpAddr::from([1, 2, 3, 4])]);
+        assert_eq!(answer.ttl, Duration::from_secs(300));
+        let other_id = wire::encode_query(0x4321, "www.example.com", wire::TYPE_A).unwrap();
+        assert_eq!(
+            wire::decode_response(&other_id, &response),
+            Err(wire::DecodeError::WrongId)
+        );
+        assert_eq!(
+            wire::decode_response(&query, &response[..response.len() - 1]),
+            Err(wire::DecodeError::Malformed)
+        );
+    }
+
+    #[test]
+    fn reject_responses_to_other_questions() {
+        let query = wire::encode_query(0x1234, "www.example.com", wire::TYPE_A).unwrap();
+
+        let other_name = wire::encode_query(0x1234, "www.example.org", wire::TYPE_A).unwrap();
+        let response = respond(&other_name, &[("www.example.org", [1, 2, 3, 4])]);
+        assert_eq!(
+            wire::decode_response(&query, &response),
+            Err(wire::DecodeError::WrongQuestion)
+        );
+
+        let other_type = wire::encode_query(0x1234, "www.example.com", wire::TYPE_AAAA).unwrap();
+        let response = respond(&other_type, &[]);
+        assert_eq!(
+            wire::decode_response(&query, &response),
+            Err(wire::DecodeError::WrongQuestion)
+        );
+
+        // Servers may randomize the case of the name
+        let upper = wire::encode_query(0x1234, "WWW.example.COM", wire::TYPE_A).unwrap();
+        let response = respond(&upper, &[("WWW.example.COM", [1, 2, 3, 4])]);
+        let answer = wire::decode_response(&query, &response).unwrap().unwrap();
+        assert_eq!(answer.addrs, [IpAddr::from([1, 2, 3, 4])]);
+    }
+
+    #[test]
+    fn reject_truncated_responses() {
+        let query = wire::encode_query(0x1234, "www.example.com", wire::TYPE_A).unwrap();
+        let mut response = respond(&query, &[("www.example.com", [1, 2, 3, 4])]);
+        response[2] |= 0x02;
+
+        assert_eq!(
+            wire::decode_response(&query, &response),
+            Err(wire::DecodeError::Truncated)
+        );
+    }
+
+    #[test]
+    fn cache_each_record_type_separately() {
+        let resolver = ResolverNetworking::new(Arc::new(CountingNetworking::default()));
+        let v4 = IpAddr::from([10, 0, 0, 1]);
+        let v6 = IpAddr::from(Ipv6Addr::LOCALHOST);
+
+        resolver.remember("db", wire::TYPE_A, &[v4], Duration::from_secs(30));
+        assert_eq!(resolver.cached_records("db", wire::TYPE_A), Some(vec![v4]));
+        assert_eq!(resolver.cached_records("db", wire::TYPE_AAAA), None);
+        assert_eq!(resolver.cached("db"), None);
+
+        resolver.remember("db", wire::TYPE_AAAA, &[v6], Duration::from_secs(30));
+        assert_eq!(resolver.cached("db"), Some(vec![v4, v6]));
+    }
+
+    #[cfg(feature = "host-net")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn forward_queries_to_a_dns_server() {
+        let server = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buffer = [0; 512];
+            loop {
+                let (len, peer) = server.recv_from(&mut buffer).await.unwrap();
+                let response = respond(&buffer[..len], &[("db.internal", [10, 0, 0, 5])]);
+                server.send_to(&response, peer).await.unwrap();
+            }
+        });
+
+        let inner = Arc::new(CountingNetworking::default());
+        let local = Arc::new(crate::host::LocalNetworking::new());
+        let resolver = ResolverNetworking::new(local)
+            .with_zone(Zone::new("internal", ZoneSource::Server(server_addr)))
+            .with_timeout(Duration::from_secs(5));
+
+        assert_eq!(
+            resolver.resolve("db.internal", None, None).await.unwrap(),
+            [IpAddr::from([10, 0, 0, 5])]
+        );
+        assert_eq!(
+            resolver.resolve("cache.internal", None, None).await,
+            Err(NetworkError::AddressNotAvailable)
+        );
+
+        let upstream = ResolverNetworking::new(inner.clone()).with_upstream(server_addr);
+        assert_eq!(
+            upstream.resolve("db.internal", None, None).await,
+            Err(NetworkError::Unsupported),
+            "the query should go through the wrapped networking's sockets"
+        );
+        assert_eq!(inner.lookups.load(Ordering::SeqCst), 0);
+    }
+
+    #[cfg(feature = "host-net")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn retry_truncated_answers_over_tcp() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let server = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let listener = tokio::net::TcpListener::bind(server_addr).await.unwrap();
+        tokio::spawn(async move {
+            let mut buffer = [0; 512];
+            loop {
+                let (len, peer) = server.recv_from(&mut buffer).await.unwrap();
+                let mut response = respond(&buffer[..len], &[("big.internal", [10, 0, 0, 9])]);
+                response[2] |= 0x02;
+                server.send_to(&response, peer).await.unwrap();
+            }
+        });
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let len = stream.read_u16().await.unwrap();
+                let mut query = vec![0; len as usize];
+                stream.read_exact(&mut query).await.unwrap();
+                let response = respond(&query, &[("big.internal", [10, 0, 0, 9])]);
+                stream.write_u16(response.len() as u16).await.unwrap();
+                stream.write_all(&response).await.unwrap();
+            }
+        });
+
+        let local = Arc::new(crate::host::LocalNetworking::new());
+        let resolver = ResolverNetworking::new(local)
+            .with_upstream(server_addr)
+            .with_timeout(Duration::from_secs(5));
+
+        assert_eq!(
+            resolver.resolve("big.internal", None, None).await.unwrap(),
+            [IpAddr::from([10, 0, 0, 9])]
+        );
+    }
+
+    /// A tiny DNS server which only knows about A records.
+    fn respond(query: &[u8], records: &[(&str, [u8; 4])]) -> Vec<u8> {
+        // The question section ends with the type and class
+        let question = &query[12..];
+        let name_len = question.iter().position(|&b| b == 0).unwrap() + 1;
+        let name = decode_name(&question[..name_len]);
+        let record_type = u16::from_be_bytes([question[name_len], question[name_len + 1]]);
+        let addr = records
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, addr)| addr);
+
+        let mut response = query[..2].to_vec();
+        let rcode = if addr.is_some() { 0 } else { 3 };
+        response.extend([0x81, 0x80 | rcode]);
+        let answers = u16::from(addr.is_some() && record_type == wire::TYPE_A);
+        response.extend([0, 1, 0, answers as u8, 0, 0, 0, 0]);
+        response.extend(&question[..name_len + 4]);
+
+        if let (Some(addr), 1) = (addr, answers) {
+            // A pointer back to the name in the question
+            response.extend([0xc0, 12]);
+            response.extend(wire::TYPE_A.to_be_bytes());
+            response.extend(1_u16.to_be_bytes());
+            response.extend(300_u32.to_be_bytes());
+            response.extend(4_u16.to_be_bytes());
+            response.extend(addr);
+        }
+
+        response
+    }
+
+    fn decode_name(mut encoded: &[u8]) -> String {
+        let mut labels = Vec::new();
+        while encoded[0] != 0 {
+            let len = encoded[0] as usize;
+            labels.push(String::from_utf8(encoded[1..=len].to_vec()).unwrap());
+            encoded = &encoded[len + 1..];
+        }
+        labels.join(".")
+    }
+}
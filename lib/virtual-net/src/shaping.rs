@@ -0,0 +1,1401 @@
+//! Simulates bad network conditions on top of any [`VirtualNetworking`]
+//! implementation.
+//!
+//! [`ShapedNetworking`] wraps the TCP and UDP sockets of the networking it
+//! is given and applies a set of [`NetworkConditions`] to them: latency,
+//! jitter, bandwidth limits, dropped datagrams and connection resets. The
+//! conditions can be the same for every socket, or depend on which
+//! [`Ruleset`] the remote address matches.
+//!
+//! Every random decision comes from a generator which is seeded per socket
+//! from the configured seed and the addresses the socket talks to, so the
+//! same seed always produces the same sequence of faults, no matter which
+//! order unrelated sockets are created in.
+
+use std::collections::{HashMap, VecDeque};
+use std::mem::MaybeUninit;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use bytes::{Buf, Bytes};
+use virtual_mio::{ArcInterestHandler, InterestType};
+
+use crate::ruleset::{Direction, Ruleset};
+use crate::{
+    InterestHandler, IpCidr, IpRoute, NetworkError, Result, SocketStatus, StreamSecurity,
+    VirtualConnectedSocket, VirtualConnectionlessSocket, VirtualIcmpSocket, VirtualIoSource,
+    VirtualNetworking, VirtualRawSocket, VirtualSocket, VirtualTcpListener, VirtualTcpSocket,
+    VirtualUdpSocket,
+};
+
+/// The most a bandwidth limit lets through in a single burst.
+const BURST: Duration = Duration::from_millis(50);
+/// Bursts are never smaller than a typical packet.
+const MIN_BURST_BYTES: f64 = 1500.0;
+/// How much received data is held back before the wrapped socket is left
+/// to apply its own back pressure.
+const MAX_BUFFERED_BYTES: usize = 256 * 1024;
+const MAX_BUFFERED_DATAGRAMS: usize = 256;
+const MAX_DATAGRAM_SIZE: usize = 65_536;
+
+/// The faults applied to a socket.
+///
+/// Latency and jitter are added to data as it is received, so a request
+/// and its response take `latency` longer to complete. Bandwidth is limited
+/// in both directions.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NetworkConditions {
+    /// How long received data is held back for.
+    pub latency: Duration,
+    /// A random extra delay of up to this much. TCP data is never
+    /// reordered, but UDP datagrams may be.
+    pub jitter: Duration,
+    /// The number of bytes per second which can be sent and received,
+    /// or `None` for no limit.
+    pub bandwidth: Option<u64>,
+    /// The chance (from `0.0` to `1.0`) that a UDP datagram is dropped.
+    pub packet_loss: f64,
+    /// The chance (from `0.0` to `1.0`) that a TCP connection is reset
+    /// whenever data is sent or received.
+    pub reset_probability: f64,
+    /// Reset TCP connections once this many bytes have been sent and
+    /// received.
+    pub reset_after: Option<u64>,
+}
+
+impl NetworkConditions {
+    pub fn new() -> Self {
+        NetworkConditions::default()
+    }
+
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn with_bandwidth(mut self, bytes_per_second: u64) -> Self {
+        self.bandwidth = Some(bytes_per_second);
+        self
+    }
+
+    pub fn with_packet_loss(mut self, probability: f64) -> Self {
+        self.packet_loss = probability;
+        self
+    }
+
+    pub fn with_reset_probability(mut self, probability: f64) -> Self {
+        self.reset_probability = probability;
+        self
+    }
+
+    pub fn with_reset_after(mut self, bytes: u64) -> Self {
+        self.reset_after = Some(bytes);
+        self
+    }
+}
+
+/// Wraps another [`VirtualNetworking`] implementation and applies
+/// [`NetworkConditions`] to its TCP and UDP sockets.
+#[derive(Debug)]
+pub struct ShapedNetworking {
+    inner: crate::DynVirtualNetworking,
+    shaper: Shaper,
+}
+
+impl ShapedNetworking {
+    pub fn new(inner: crate::DynVirtualNetworking) -> Self {
+        ShapedNetworking {
+            inner,
+            shaper: Shaper {
+                rules: Vec::new(),
+                default: None,
+                seed: 0,
+                sockets: Arc::new(Mutex::new(HashMap::new())),
+            },
+        }
+    }
+
+    /// The conditions for any remote address which doesn't match a rule.
+    pub fn with_conditions(mut self, conditions: NetworkConditions) -> Self {
+        self.shaper.default = Some(conditions);
+        self
+    }
+
+    /// Use different conditions for remote addresses which the ruleset
+    /// allows. Rules are checked in the order they were added.
+    pub fn with_rule(mut self, ruleset: Ruleset, conditions: NetworkConditions) -> Self {
+        self.shaper.rules.push((ruleset, conditions));
+        self
+    }
+
+    /// Seed the random number generators. Each socket gets its own
+    /// generator, derived from this seed, the addresses it talks to and how
+    /// many sockets with the same addresses were created before it.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.shaper.seed = seed;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl VirtualNetworking for ShapedNetworking {
+    async fn bridge(
+        &self,
+        network: &str,
+        access_token: &str,
+        security: StreamSecurity,
+    ) -> Result<()> {
+        self.inner.bridge(network, access_token, security).await
+    }
+
+    async fn unbridge(&self) -> Result<()> {
+        self.inner.unbridge().await
+    }
+
+    async fn dhcp_acquire(&self) -> Result<Vec<IpAddr>> {
+        self.inner.dhcp_acquire().await
+    }
+
+    async fn ip_add(&self, ip: IpAddr, prefix: u8) -> Result<()> {
+        self.inner.ip_add(ip, prefix).await
+    }
+
+    async fn ip_remove(&self, ip: IpAddr) -> Result<()> {
+        self.inner.ip_remove(ip).await
+    }
+
+    async fn ip_clear(&self) -> Result<()> {
+        self.inner.ip_clear().await
+    }
+
+    async fn ip_list(&self) -> Result<Vec<IpCidr>> {
+        self.inner.ip_list().await
+    }
+
+    async fn mac(&self) -> Result<[u8; 6]> {
+        self.inner.mac().await
+    }
+
+    async fn gateway_set(&self, ip: IpAddr) -> Result<()> {
+        self.inner.gateway_set(ip).await
+    }
+
+    async fn route_add(
+        &self,
+        cidr: IpCidr,
+        via_router: IpAddr,
+        preferred_until: Option<Duration>,
+        expires_at: Option<Duration>,
+    ) -> Result<()> {
+        self.inner
+            .route_add(cidr, via_router, preferred_until, expires_at)
+            .await
+    }
+
+    async fn route_remove(&self, cidr: IpAddr) -> Result<()> {
+        self.inner.route_remove(cidr).await
+    }
+
+    async fn route_clear(&self) -> Result<()> {
+        self.inner.route_clear().await
+    }
+
+    async fn route_list(&self) -> Result<Vec<IpRoute>> {
+        self.inner.route_list().await
+    }
+
+    async fn bind_raw(&self) -> Result<Box<dyn VirtualRawSocket + Sync>> {
+        self.inner.bind_raw().await
+    }
+
+    async fn listen_tcp(
+        &self,
+        addr: SocketAddr,
+        only_v6: bool,
+        reuse_port: bool,
+        reuse_addr: bool,
+    ) -> Result<Box<dyn VirtualTcpListener + Sync>> {
+        let inner = self
+            .inner
+            .listen_tcp(addr, only_v6, reuse_port, reuse_addr)
+            .await?;
+        Ok(Box::new(ShapedTcpListener {
+            inner,
+            shaper: self.shaper.clone(),
+        }))
+    }
+
+    async fn bind_udp(
+        &self,
+        addr: SocketAddr,
+        reuse_port: bool,
+        reuse_addr: bool,
+    ) -> Result<Box<dyn VirtualUdpSocket + Sync>> {
+        let inner = self.inner.bind_udp(addr, reuse_port, reuse_addr).await?;
+        Ok(Box::new(ShapedUdpSocket {
+            inner,
+            rng: self.shaper.rng(SocketIdentity::Udp { local: addr }),
+            shaper: self.shaper.clone(),
+            buckets: HashMap::new(),
+            inbound: VecDeque::new(),
+            waiter: Waiter::default(),
+        }))
+    }
+
+    async fn bind_icmp(&self, addr: IpAddr) -> Result<Box<dyn VirtualIcmpSocket + Sync>> {
+        self.inner.bind_icmp(addr).await
+    }
+
+    async fn connect_tcp(
+        &self,
+        addr: SocketAddr,
+        peer: SocketAddr,
+    ) -> Result<Box<dyn VirtualTcpSocket + Sync>> {
+        let socket = self.inner.connect_tcp(addr, peer).await?;
+        Ok(self
+            .shaper
+            .wrap_tcp(socket, peer, SocketIdentity::Outbound { peer }))
+    }
+
+    async fn resolve(
+        &self,
+        host: &str,
+        port: Option<u16>,
+        dns_server: Option<IpAddr>,
+    ) -> Result<Vec<IpAddr>> {
+        self.inner.resolve(host, port, dns_server).await
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Shaper {
+    rules: Vec<(Ruleset, NetworkConditions)>,
+    default: Option<NetworkConditions>,
+    seed: u64,
+    /// How many sockets were created for each identity.
+    sockets: Arc<Mutex<HashMap<u64, u64>>>,
+}
+
+/// What a socket is seeded from. Ephemeral ports are left out, since they
+/// change from one run to the next.
+#[derive(Debug, Clone, Copy)]
+enum SocketIdentity {
+    /// A connection to a peer
+    Outbound { peer: SocketAddr },
+    /// A connection accepted by a listener
+    Inbound { local: SocketAddr, peer: IpAddr },
+    /// A UDP socket, identified by the address it was asked to bind to
+    Udp { local: SocketAddr },
+}
+
+impl SocketIdentity {
+    fn key(self) -> u64 {
+        let (kind, ip, port) = match self {
+            SocketIdentity::Outbound { peer } => (0, peer.ip(), peer.port()),
+            SocketIdentity::Inbound { local, peer } => (1, peer, local.port()),
+            SocketIdentity::Udp { local } => (2, local.ip(), local.port()),
+        };
+        let ip = match ip {
+            IpAddr::V4(ip) => ip.to_ipv6_mapped().to_bits(),
+            IpAddr::V6(ip) => ip.to_bits(),
+        };
+
+        [
+            (ip >> 64) as u64,
+            ip as u64,
+            ((kind as u64) << 16) | port as u64,
+        ]
+        .into_iter()
+        .fold(0, |key, part| Rng::new(key ^ part).next_u64())
+    }
+}
+
+impl Shaper {
+    /// Find the conditions for a remote address, along with a number which
+    /// identifies where they came from.
+    fn select(
+        &self,
+        peer: SocketAddr,
+        direction: Direction,
+    ) -> Option<(usize, &NetworkConditions)> {
+        self.rules
+            .iter()
+            .position(|(ruleset, _)| ruleset.allows_socket(peer, direction))
+            .map(|index| (index, &self.rules[index].1))
+            .or_else(|| {
+                self.default
+                    .as_ref()
+                    .map(|conditions| (self.rules.len(), conditions))
+            })
+    }
+
+    /// The generator for a new socket.
+    fn rng(&self, identity: SocketIdentity) -> Rng {
+        let key = identity.key();
+        let index = {
+            let mut sockets = self.sockets.lock().unwrap();
+            let count = sockets.entry(key).or_default();
+            *count += 1;
+            *count - 1
+        };
+        Rng::new(self.seed ^ Rng::new(key ^ index).next_u64())
+    }
+
+    fn wrap_tcp(
+        &self,
+        socket: Box<dyn VirtualTcpSocket + Sync>,
+        peer: SocketAddr,
+        identity: SocketIdentity,
+    ) -> Box<dyn VirtualTcpSocket + Sync> {
+        let direction = match identity {
+            SocketIdentity::Inbound { .. } => Direction::Inbound,
+            _ => Direction::Outbound,
+        };
+        let Some((_, conditions)) = self.select(peer, direction) else {
+            return socket;
+        };
+
+        let mut rng = self.rng(identity);
+        Box::new(ShapedTcpSocket {
+            inner: socket,
+            send: Bucket::new(conditions.bandwidth),
+            recv: Bucket::new(conditions.bandwidth),
+            conditions: conditions.clone(),
+            reset_rng: Rng::new(rng.next_u64()),
+            rng,
+            inbound: VecDeque::new(),
+            buffered: 0,
+            last_release: None,
+            error: None,
+            transferred: 0,
+            reset: false,
+            waiter: Waiter::default(),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct ShapedTcpListener {
+    inner: Box<dyn VirtualTcpListener + Sync>,
+    shaper: Shaper,
+}
+
+impl VirtualIoSource for ShapedTcpListener {
+    fn remove_handler(&mut self) {
+        self.inner.remove_handler()
+    }
+
+    fn poll_read_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<usize>> {
+        self.inner.poll_read_ready(cx)
+    }
+
+    fn poll_write_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<usize>> {
+        self.inner.poll_write_ready(cx)
+    }
+}
+
+impl VirtualTcpListener for ShapedTcpListener {
+    fn try_accept(&mut self) -> Result<(Box<dyn VirtualTcpSocket + Sync>, SocketAddr)> {
+        let (socket, peer) = self.inner.try_accept()?;
+        let identity = SocketIdentity::Inbound {
+            local: self.inner.addr_local()?,
+            peer: peer.ip(),
+        };
+        Ok((self.shaper.wrap_tcp(socket, peer, identity), peer))
+    }
+
+    fn set_handler(&mut self, handler: Box<dyn InterestHandler + Send + Sync>) -> Result<()> {
+        self.inner.set_handler(handler)
+    }
+
+    fn addr_local(&self) -> Result<SocketAddr> {
+        self.inner.addr_local()
+    }
+
+    fn set_ttl(&mut self, ttl: u8) -> Result<()> {
+        self.inner.set_ttl(ttl)
+    }
+
+    fn ttl(&self) -> Result<u8> {
+        self.inner.ttl()
+    }
+}
+
+/// Received data which is held back until `release`. Empty data marks the
+/// end of the stream.
+#[derive(Debug)]
+struct Chunk {
+    release: Instant,
+    data: Bytes,
+}
+
+#[derive(Debug)]
+struct ShapedTcpSocket {
+    inner: Box<dyn VirtualTcpSocket + Sync>,
+    conditions: NetworkConditions,
+    /// Decides the delay of each received chunk
+    rng: Rng,
+    /// Decides whether the connection is reset after each transfer
+    reset_rng: Rng,
+    send: Option<Bucket>,
+    recv: Option<Bucket>,
+    inbound: VecDeque<Chunk>,
+    buffered: usize,
+    /// Chunks are never released before the ones received earlier.
+    last_release: Option<Instant>,
+    /// An error from the wrapped socket, returned once everything before it
+    /// has been received.
+    error: Option<NetworkError>,
+    transferred: u64,
+    reset: bool,
+    waiter: Waiter,
+}
+
+impl ShapedTcpSocket {
+    fn check_reset(&self) -> Result<()> {
+        if self.reset {
+            Err(NetworkError::ConnectionReset)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Account for data which was sent or received, and decide whether the
+    /// connection gets reset before the next operation. Operations which
+    /// transfer nothing never cause a reset, so how often a socket is
+    /// polled doesn't change the faults it sees.
+    fn transferred(&mut self, len: usize) {
+        self.transferred += len as u64;
+
+        let limit_reached = self
+            .conditions
+            .reset_after
+            .is_some_and(|limit| self.transferred >= limit);
+        if limit_reached || self.reset_rng.chance(self.conditions.reset_probability) {
+            tracing::debug!("Resetting the connection");
+            self.reset = true;
+            self.inbound.clear();
+            self.buffered = 0;
+            self.inner.close().ok();
+        }
+    }
+
+    /// Move everything the wrapped socket has received into the inbound
+    /// queue.
+    fn pump(&mut self) {
+        let mut buffer = [MaybeUninit::<u8>::uninit(); 16 * 1024];
+
+        while self.error.is_none()
+            && self.buffered < MAX_BUFFERED_BYTES
+            && !self
+                .inbound
+                .back()
+                .is_some_and(|chunk| chunk.data.is_empty())
+        {
+            let data = match self.inner.try_recv(&mut buffer) {
+                Ok(len) => {
+                    // SAFETY: try_recv() initialized the first `len` bytes
+                    let received =
+                        unsafe { std::slice::from_raw_parts(buffer.as_ptr().cast(), len) };
+                    Bytes::copy_from_slice(received)
+                }
+                Err(NetworkError::WouldBlock) => break,
+                Err(e) => {
+                    self.error = Some(e);
+                    break;
+                }
+            };
+
+            let release = Instant::now() + self.rng.delay(&self.conditions);
+            let release = self.last_release.map_or(release, |last| last.max(release));
+            self.last_release = Some(release);
+            self.buffered += data.len();
+            self.inbound.push_back(Chunk { release, data });
+        }
+    }
+
+    /// The amount of received data which can be handed over now, or when
+    /// to check again.
+    fn readable(&mut self) -> std::result::Result<usize, Option<Instant>> {
+        let Some(chunk) = self.inbound.front() else {
+            return Err(None);
+        };
+        let now = Instant::now();
+        if chunk.release > now {
+            return Err(Some(chunk.release));
+        }
+        match self.recv.as_mut() {
+            Some(bucket) => bucket.available(now).map_err(Some),
+            None => Ok(chunk.data.len()),
+        }
+    }
+}
+
+impl VirtualIoSource for ShapedTcpSocket {
+    fn remove_handler(&mut self) {
+        self.waiter.remove_handler();
+        self.inner.remove_handler()
+    }
+
+    fn poll_read_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<usize>> {
+        if self.reset {
+            return Poll::Ready(Err(NetworkError::ConnectionReset));
+        }
+        self.pump();
+        match self.readable() {
+            Ok(_) => Poll::Ready(Ok(self.buffered)),
+            Err(Some(when)) => {
+                self.waiter
+                    .wake_at(when, InterestType::Readable, Some(cx.waker()));
+                Poll::Pending
+            }
+            Err(None) => match self.error {
+                Some(e) => Poll::Ready(Err(e)),
+                None => self.inner.poll_read_ready(cx),
+            },
+        }
+    }
+
+    fn poll_write_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<usize>> {
+        if self.reset {
+            return Poll::Ready(Err(NetworkError::ConnectionReset));
+        }
+        if let Some(bucket) = self.send.as_mut() {
+            if let Err(when) = bucket.available(Instant::now()) {
+                self.waiter
+                    .wake_at(when, InterestType::Writable, Some(cx.waker()));
+                return Poll::Pending;
+            }
+        }
+        self.inner.poll_write_ready(cx)
+    }
+}
+
+impl VirtualSocket for ShapedTcpSocket {
+    fn set_ttl(&mut self, ttl: u32) -> Result<()> {
+        self.inner.set_ttl(ttl)
+    }
+
+    fn ttl(&self) -> Result<u32> {
+        self.inner.ttl()
+    }
+
+    fn addr_local(&self) -> Result<SocketAddr> {
+        self.inner.addr_local()
+    }
+
+    fn status(&self) -> Result<SocketStatus> {
+        if self.reset {
+            return Ok(SocketStatus::Failed);
+        }
+        self.inner.status()
+    }
+
+    fn set_handler(&mut self, handler: Box<dyn InterestHandler + Send + Sync>) -> Result<()> {
+        let handler = self.waiter.set_handler(handler);
+        self.inner.set_handler(Box::new(handler))
+    }
+}
+
+impl VirtualConnectedSocket for ShapedTcpSocket {
+    fn set_linger(&mut self, linger: Option<Duration>) -> Result<()> {
+        self.inner.set_linger(linger)
+    }
+
+    fn linger(&self) -> Result<Option<Duration>> {
+        self.inner.linger()
+    }
+
+    fn try_send(&mut self, data: &[u8]) -> Result<usize> {
+        self.check_reset()?;
+
+        let now = Instant::now();
+        let len = match self.send.as_mut() {
+            Some(bucket) => match bucket.available(now) {
+                Ok(available) => available.min(data.len()),
+                Err(when) => {
+                    self.waiter.wake_at(when, InterestType::Writable, None);
+                    return Err(NetworkError::WouldBlock);
+                }
+            },
+            None => data.len(),
+        };
+
+        let sent = self.inner.try_send(&data[..len])?;
+        if let Some(bucket) = self.send.as_mut() {
+            bucket.take(sent);
+        }
+        if sent > 0 {
+            self.transferred(sent);
+        }
+        Ok(sent)
+    }
+
+    fn try_flush(&mut self) -> Result<()> {
+        self.inner.try_flush()
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.inner.close()
+    }
+
+    fn try_recv(&mut self, buf: &mut [MaybeUninit<u8>]) -> Result<usize> {
+        self.check_reset()?;
+        self.pump();
+
+        let available = match self.readable() {
+            Ok(available) => available,
+            Err(Some(when)) => {
+                self.waiter.wake_at(when, InterestType::Readable, None);
+                return Err(NetworkError::WouldBlock);
+            }
+            Err(None) => {
+                return match self.error {
+                    Some(e) => Err(e),
+                    None => Err(NetworkError::WouldBlock),
+                };
+            }
+        };
+
+        let chunk = self.inbound.front_mut().unwrap();
+        if chunk.data.is_empty() {
+            // The end of the stream
+            return Ok(0);
+        }
+
+        let len = available.min(chunk.data.len()).min(buf.len());
+        for (dst, src) in buf.iter_mut().zip(&chunk.data[..len]) {
+            dst.write(*src);
+        }
+        chunk.data.advance(len);
+        if chunk.data.is_empty() {
+            self.inbound.pop_front();
+        }
+        self.buffered -= len;
+        if let Some(bucket) = self.recv.as_mut() {
+            bucket.take(len);
+        }
+        if len > 0 {
+            self.transferred(len);
+        }
+
+        Ok(len)
+    }
+}
+
+impl VirtualTcpSocket for ShapedTcpSocket {
+    fn set_recv_buf_size(&mut self, size: usize) -> Result<()> {
+        self.inner.set_recv_buf_size(size)
+    }
+
+    fn recv_buf_size(&self) -> Result<usize> {
+        self.inner.recv_buf_size()
+    }
+
+    fn set_send_buf_size(&mut self, size: usize) -> Result<()> {
+        self.inner.set_send_buf_size(size)
+    }
+
+    fn send_buf_size(&self) -> Result<usize> {
+        self.inner.send_buf_size()
+    }
+
+    fn set_nodelay(&mut self, reuse: bool) -> Result<()> {
+        self.inner.set_nodelay(reuse)
+    }
+
+    fn nodelay(&self) -> Result<bool> {
+        self.inner.nodelay()
+    }
+
+    fn set_keepalive(&mut self, keepalive: bool) -> Result<()> {
+        self.inner.set_keepalive(keepalive)
+    }
+
+    fn keepalive(&self) -> Result<bool> {
+        self.inner.keepalive()
+    }
+
+    fn set_dontroute(&mut self, dontroute: bool) -> Result<()> {
+        self.inner.set_dontroute(dontroute)
+    }
+
+    fn dontroute(&self) -> Result<bool> {
+        self.inner.dontroute()
+    }
+
+    fn addr_peer(&self) -> Result<SocketAddr> {
+        self.inner.addr_peer()
+    }
+
+    fn shutdown(&mut self, how: Shutdown) -> Result<()> {
+        self.inner.shutdown(how)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.reset || self.inner.is_closed()
+    }
+}
+
+#[derive(Debug)]
+struct Datagram {
+    release: Instant,
+    from: SocketAddr,
+    /// Identifies the conditions which applied to the datagram.
+    rule: Option<usize>,
+    data: Bytes,
+}
+
+#[derive(Debug)]
+struct ShapedUdpSocket {
+    inner: Box<dyn VirtualUdpSocket + Sync>,
+    shaper: Shaper,
+    rng: Rng,
+    /// Separate send and receive bandwidth limits for each set of
+    /// conditions.
+    buckets: HashMap<usize, (Option<Bucket>, Option<Bucket>)>,
+    /// Ordered by release time.
+    inbound: VecDeque<Datagram>,
+    waiter: Waiter,
+}
+
+impl ShapedUdpSocket {
+    fn buckets(&mut self, rule: usize) -> &mut (Option<Bucket>, Option<Bucket>) {
+        let shaper = &self.shaper;
+        self.buckets.entry(rule).or_insert_with(|| {
+            let bandwidth = match shaper.rules.get(rule) {
+                Some((_, conditions)) => conditions.bandwidth,
+                None => shaper.default.as_ref().and_then(|c| c.bandwidth),
+            };
+            (Bucket::new(bandwidth), Bucket::new(bandwidth))
+        })
+    }
+
+    /// Move every datagram the wrapped socket has received into the inbound
+    /// queue, dropping some of them along the way.
+    fn pump(&mut self) -> Result<()> {
+        let mut buffer = vec![MaybeUninit::<u8>::uninit(); MAX_DATAGRAM_SIZE];
+
+        while self.inbound.len() < MAX_BUFFERED_DATAGRAMS {
+            let (len, from) = match self.inner.try_recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(NetworkError::WouldBlock) => break,
+                Err(e) if self.inbound.is_empty() => return Err(e),
+                Err(_) => break,
+            };
+
+            let now = Instant::now();
+            let (rule, release) = match self.shaper.select(from, Direction::Inbound) {
+                Some((rule, conditions)) => {
+                    if self.rng.chance(conditions.packet_loss) {
+                        tracing::trace!(%from, len, "Dropping a received datagram");
+                        continue;
+                    }
+                    (Some(rule), now + self.rng.delay(conditions))
+                }
+                None => (None, now),
+            };
+
+            // SAFETY: try_recv_from() initialized the first `len` bytes
+            let received = unsafe { std::slice::from_raw_parts(buffer.as_ptr().cast(), len) };
+            let datagram = Datagram {
+                release,
+                from,
+                rule,
+                data: Bytes::copy_from_slice(received),
+            };
+            let index = self.inbound.partition_point(|d| d.release <= release);
+            self.inbound.insert(index, datagram);
+        }
+
+        Ok(())
+    }
+}
+
+impl VirtualIoSource for ShapedUdpSocket {
+    fn remove_handler(&mut self) {
+        self.waiter.remove_handler();
+        self.inner.remove_handler()
+    }
+
+    fn poll_read_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<usize>> {
+        if let Err(e) = self.pump() {
+            return Poll::Ready(Err(e));
+        }
+        match self.inbound.front() {
+            Some(datagram) if datagram.release <= Instant::now() => {
+                Poll::Ready(Ok(datagram.data.len()))
+            }
+            Some(datagram) => {
+                self.waiter
+                    .wake_at(datagram.release, InterestType::Readable, Some(cx.waker()));
+                Poll::Pending
+            }
+            None => self.inner.poll_read_ready(cx),
+        }
+    }
+
+    fn poll_write_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<usize>> {
+        self.inner.poll_write_ready(cx)
+    }
+}
+
+impl VirtualSocket for ShapedUdpSocket {
+    fn set_ttl(&mut self, ttl: u32) -> Result<()> {
+        self.inner.set_ttl(ttl)
+    }
+
+    fn ttl(&self) -> Result<u32> {
+        self.inner.ttl()
+    }
+
+    fn addr_local(&self) -> Result<SocketAddr> {
+        self.inner.addr_local()
+    }
+
+    fn status(&self) -> Result<SocketStatus> {
+        self.inner.status()
+    }
+
+    fn set_handler(&mut self, handler: Box<dyn InterestHandler + Send + Sync>) -> Result<()> {
+        let handler = self.waiter.set_handler(handler);
+        self.inner.set_handler(Box::new(handler))
+    }
+}
+
+impl VirtualConnectionlessSocket for ShapedUdpSocket {
+    fn try_send_to(&mut self, data: &[u8], addr: SocketAddr) -> Result<usize> {
+        let Some((rule, conditions)) = self.shaper.select(addr, Direction::Outbound) else {
+            return self.inner.try_send_to(data, addr);
+        };
+        if self.rng.chance(conditions.packet_loss) {
+            tracing::trace!(%addr, len = data.len(), "Dropping a sent datagram");
+            return Ok(data.len());
+        }
+
+        let now = Instant::now();
+        if let (Some(bucket), _) = self.buckets(rule) {
+            if let Err(when) = bucket.available(now) {
+                self.waiter.wake_at(when, InterestType::Writable, None);
+                return Err(NetworkError::WouldBlock);
+            }
+        }
+
+        let sent = self.inner.try_send_to(data, addr)?;
+        if let (Some(bucket), _) = self.buckets(rule) {
+            bucket.take(sent);
+        }
+        Ok(sent)
+    }
+
+    fn try_recv_from(&mut self, buf: &mut [MaybeUninit<u8>]) -> Result<(usize, SocketAddr)> {
+        self.pump()?;
+
+        let Some(datagram) = self.inbound.front() else {
+            return Err(NetworkError::WouldBlock);
+        };
+        let now = Instant::now();
+        if datagram.release > now {
+            let when = datagram.release;
+            self.waiter.wake_at(when, InterestType::Readable, None);
+            return Err(NetworkError::WouldBlock);
+        }
+        if let Some(rule) = datagram.rule {
+            if let (_, Some(bucket)) = self.buckets(rule) {
+                if let Err(when) = bucket.available(now) {
+                    self.waiter.wake_at(when, InterestType::Readable, None);
+                    return Err(NetworkError::WouldBlock);
+                }
+            }
+        }
+
+        let datagram = self.inbound.pop_front().unwrap();
+        if let Some(rule) = datagram.rule {
+            if let (_, Some(bucket)) = self.buckets(rule) {
+                bucket.take(datagram.data.len());
+            }
+        }
+
+        // Like a real UDP socket, anything which doesn't fit is discarded
+        let len = datagram.data.len().min(buf.len());
+        for (dst, src) in buf.iter_mut().zip(&datagram.data[..len]) {
+            dst.write(*src);
+        }
+        Ok((len, datagram.from))
+    }
+}
+
+impl VirtualUdpSocket for ShapedUdpSocket {
+    fn set_broadcast(&mut self, broadcast: bool) -> Result<()> {
+        self.inner.set_broadcast(broadcast)
+    }
+
+    fn broadcast(&self) -> Result<bool> {
+        self.inner.broadcast()
+    }
+
+    fn set_multicast_loop_v4(&mut self, val: bool) -> Result<()> {
+        self.inner.set_multicast_loop_v4(val)
+    }
+
+    fn multicast_loop_v4(&self) -> Result<bool> {
+        self.inner.multicast_loop_v4()
+    }
+
+    fn set_multicast_loop_v6(&mut self, val: bool) -> Result<()> {
+        self.inner.set_multicast_loop_v6(val)
+    }
+
+    fn multicast_loop_v6(&self) -> Result<bool> {
+        self.inner.multicast_loop_v6()
+    }
+
+    fn set_multicast_ttl_v4(&mut self, ttl: u32) -> Result<()> {
+        self.inner.set_multicast_ttl_v4(ttl)
+    }
+
+    fn multicast_ttl_v4(&self) -> Result<u32> {
+        self.inner.multicast_ttl_v4()
+    }
+
+    fn join_multicast_v4(&mut self, multiaddr: Ipv4Addr, iface: Ipv4Addr) -> Result<()> {
+        self.inner.join_multicast_v4(multiaddr, iface)
+    }
+
+    fn leave_multicast_v4(&mut self, multiaddr: Ipv4Addr, iface: Ipv4Addr) -> Result<()> {
+        self.inner.leave_multicast_v4(multiaddr, iface)
+    }
+
+    fn join_multicast_v6(&mut self, multiaddr: Ipv6Addr, iface: u32) -> Result<()> {
+        self.inner.join_multicast_v6(multiaddr, iface)
+    }
+
+    fn leave_multicast_v6(&mut self, multiaddr: Ipv6Addr, iface: u32) -> Result<()> {
+        self.inner.leave_multicast_v6(multiaddr, iface)
+    }
+
+    fn addr_peer(&self) -> Result<Option<SocketAddr>> {
+        self.inner.addr_peer()
+    }
+}
+
+/// A token bucket which refills at a fixed number of bytes per second.
+///
+/// The bucket may go into debt so a datagram which is bigger than the
+/// burst size can still be sent. Nothing else goes through until the debt
+/// has been paid off.
+#[derive(Debug)]
+struct Bucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(bytes_per_second: Option<u64>) -> Option<Self> {
+        let rate = bytes_per_second? as f64;
+        let capacity = (rate * BURST.as_secs_f64()).max(MIN_BURST_BYTES);
+        Some(Bucket {
+            rate,
+            capacity,
+            tokens: capacity,
+            updated: Instant::now(),
+        })
+    }
+
+    /// How many bytes can go through now, or when to try again.
+    fn available(&mut self, now: Instant) -> std::result::Result<usize, Instant> {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.updated = now;
+
+        if self.tokens >= 1.0 {
+            Ok(self.tokens as usize)
+        } else if self.rate > 0.0 {
+            let wait = (1.0 - self.tokens) / self.rate;
+            Err(now + Duration::from_secs_f64(wait))
+        } else {
+            // Nothing ever gets through, but check back every now and then
+            Err(now + Duration::from_secs(1))
+        }
+    }
+
+    fn take(&mut self, bytes: usize) {
+        self.tokens -= bytes as f64;
+    }
+}
+
+/// A small deterministic random number generator (SplitMix64).
+#[derive(Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in the range `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.next_f64() < probability
+    }
+
+    fn delay(&mut self, conditions: &NetworkConditions) -> Duration {
+        if conditions.jitter.is_zero() {
+            conditions.latency
+        } else {
+            conditions.latency + conditions.jitter.mul_f64(self.next_f64())
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct WaiterState {
+    handler: Option<ArcInterestHandler>,
+    interests: Vec<InterestType>,
+    wakers: Vec<Waker>,
+    /// When the earliest pending wake up will happen.
+    scheduled: Option<Instant>,
+}
+
+/// Notifies a socket's handler once data which was held back is ready.
+#[derive(Debug, Clone, Default)]
+struct Waiter {
+    state: Arc<Mutex<WaiterState>>,
+}
+
+impl Waiter {
+    fn set_handler(&self, handler: Box<dyn InterestHandler + Send + Sync>) -> ArcInterestHandler {
+        let handler = ArcInterestHandler::new(handler);
+        let mut state = self.state.lock().unwrap();
+        state.handler = Some(handler.clone());
+        handler
+    }
+
+    fn remove_handler(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.handler.take();
+    }
+
+    fn wake_at(&self, when: Instant, interest: InterestType, waker: Option<&Waker>) {
+        let mut state = self.state.lock().unwrap();
+        if !state.interests.contains(&interest) {
+            state.interests.push(interest);
+        }
+        if let Some(waker) = waker {
+            if !state.wakers.iter().any(|w| w.will_wake(waker)) {
+                state.wakers.push(waker.clone());
+            }
+        }
+
+        if state.scheduled.is_some_and(|scheduled| scheduled <= when) {
+            return;
+        }
+        state.scheduled = Some(when);
+        drop(state);
+
+        let waiter = self.clone();
+        timer::schedule(when, move || waiter.wake());
+    }
+
+    fn wake(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.scheduled = None;
+        let interests = std::mem::take(&mut state.interests);
+        let wakers = std::mem::take(&mut state.wakers);
+        if let Some(handler) = state.handler.as_mut() {
+            for interest in interests {
+                handler.push_interest(interest);
+            }
+        }
+        drop(state);
+
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// A single background thread which runs callbacks at a given time, so
+/// sockets don't need access to an async runtime.
+mod timer {
+    use std::collections::BTreeMap;
+    use std::sync::{Condvar, Mutex, OnceLock};
+    use std::time::Instant;
+
+    type Task = Box<dyn FnOnce() + Send>;
+
+    #[derive(Default)]
+    struct Timer {
+        queue: Mutex<Queue>,
+        changed: Condvar,
+    }
+
+    /// Tasks are ordered by when they run, then by when they were added.
+    #[derive(Default)]
+    struct Queue {
+        next_id: u64,
+        tasks: BTreeMap<(Instant, u64), Task>,
+    }
+
+    pub(super) fn schedule(when: Instant, task: impl FnOnce() + Send + 'static) {
+        static TIMER: OnceLock<&'static Timer> = OnceLock::new();
+
+        let timer = *TIMER.get_or_init(|| {
+            let timer: &'static Timer = Box::leak(Box::default());
+            std::thread::Builder::new()
+                .name("virtual-net-shaping".to_string())
+                .spawn(move || timer.run())
+                .expect("Unable to start the timer thread");
+            timer
+        });
+
+        let mut queue = timer.queue.lock().unwrap();
+        queue.next_id += 1;
+        let id = queue.next_id;
+        queue.tasks.insert((when, id), Box::new(task));
+        timer.changed.notify_one();
+    }
+
+    impl Timer {
+        fn run(&self) {
+            let mut queue = self.queue.lock().unwrap();
+            loop {
+                let now = Instant::now();
+                let next = queue.tasks.first_key_value().map(|(&key, _)| key);
+                match next {
+                    Some(key) if key.0 <= now => {
+                        let task = queue.tasks.remove(&key).unwrap();
+                        drop(queue);
+                        task();
+                        queue = self.queue.lock().unwrap();
+                    }
+                    Some((when, _)) => {
+                        queue = self.changed.wait_timeout(queue, when - now).unwrap().0;
+                    }
+                    None => {
+                        queue = self.changed.wait(queue).unwrap();
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        LoopbackNetworking, VirtualConnectedSocketExt, VirtualTcpListenerExt, VirtualTcpSocket,
+    };
+
+    use super::*;
+
+    const SERVER: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 8080);
+    const CLIENT: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+
+    /// Accept a connection through the shaped networking, returning both
+    /// ends of it.
+    async fn connect(
+        shaped: ShapedNetworking,
+        loopback: &LoopbackNetworking,
+    ) -> (
+        Box<dyn VirtualTcpSocket + Sync>,
+        Box<dyn VirtualTcpSocket + Sync>,
+    ) {
+        let mut listener = shaped
+            .listen_tcp(SERVER, false, false, false)
+            .await
+            .unwrap();
+        let client = loopback.loopback_connect_to(CLIENT, SERVER).unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (server, Box::new(client))
+    }
+
+    async fn recv_exact(socket: &mut Box<dyn VirtualTcpSocket + Sync>, len: usize) -> Vec<u8> {
+        let mut received = Vec::new();
+        let mut buffer = [MaybeUninit::<u8>::uninit(); 1024];
+        while received.len() < len {
+            let n = socket.recv(&mut buffer).await.unwrap();
+            assert_ne!(n, 0, "Unexpected end of stream");
+            // SAFETY: recv() initialized the first `n` bytes
+            received.extend(unsafe { std::slice::from_raw_parts(buffer.as_ptr().cast(), n) });
+        }
+        received
+    }
+
+    #[tokio::test]
+    async fn latency_delays_received_data() {
+        let loopback = LoopbackNetworking::new();
+        let shaped = ShapedNetworking::new(Arc::new(loopback.clone()))
+            .with_conditions(NetworkConditions::new().with_latency(Duration::from_millis(200)));
+        let (mut server, mut client) = connect(shaped, &loopback).await;
+
+        let start = Instant::now();
+        client.send(b"ping").await.unwrap();
+        let received = recv_exact(&mut server, 4).await;
+
+        assert_eq!(received, b"ping");
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        // Only received data is delayed
+        let start = Instant::now();
+        server.send(b"pong").await.unwrap();
+        assert_eq!(recv_exact(&mut client, 4).await, b"pong");
+        assert!(start.elapsed() < Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn bandwidth_limits_throughput() {
+        let loopback = LoopbackNetworking::new();
+        let shaped = ShapedNetworking::new(Arc::new(loopback.clone()))
+            .with_conditions(NetworkConditions::new().with_bandwidth(10_000));
+        let (mut server, mut client) = connect(shaped, &loopback).await;
+        let data = vec![42_u8; 4_000];
+
+        let start = Instant::now();
+        client.send(&data).await.unwrap();
+        let received = recv_exact(&mut server, data.len()).await;
+
+        assert_eq!(received, data);
+        // The first 1500 bytes are a burst, the rest go at 10KB/s
+        assert!(start.elapsed() >= Duration::from_millis(250));
+    }
+
+    #[tokio::test]
+    async fn connections_are_reset() {
+        let loopback = LoopbackNetworking::new();
+        let shaped = ShapedNetworking::new(Arc::new(loopback.clone()))
+            .with_conditions(NetworkConditions::new().with_reset_after(4));
+        let (mut server, mut client) = connect(shaped, &loopback).await;
+
+        client.send(b"ping").await.unwrap();
+        assert_eq!(recv_exact(&mut server, 4).await, b"ping");
+
+        assert_eq!(
+            server.send(b"pong").await,
+            Err(NetworkError::ConnectionReset)
+        );
+        assert!(server.is_closed());
+        assert_eq!(server.status().unwrap(), SocketStatus::Failed);
+    }
+
+    #[tokio::test]
+    async fn only_matching_connections_are_shaped() {
+        let loopback = LoopbackNetworking::new();
+        let ruleset: Ruleset = "ipv4:allow=10.0.0.0/8:*".parse().unwrap();
+        let shaped = ShapedNetworking::new(Arc::new(loopback.clone())).with_rule(
+            ruleset,
+            NetworkConditions::new().with_latency(Duration::from_secs(60)),
+        );
+        let (mut server, mut client) = connect(shaped, &loopback).await;
+
+        client.send(b"ping").await.unwrap();
+        assert_eq!(recv_exact(&mut server, 4).await, b"ping");
+    }
+
+    #[test]
+    fn faults_are_reproducible() {
+        let faults = |seed: u64| {
+            let shaper = ShapedNetworking::new(Arc::new(LoopbackNetworking::new()))
+                .with_seed(seed)
+                .shaper;
+            let conditions = NetworkConditions::new()
+                .with_jitter(Duration::from_millis(100))
+                .with_packet_loss(0.5);
+            let peer = SocketIdentity::Outbound { peer: SERVER };
+            (0..2)
+                .map(|_| {
+                    let mut rng = shaper.rng(peer);
+                    (0..32)
+                        .map(|_| (rng.chance(conditions.packet_loss), rng.delay(&conditions)))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let first = faults(1);
+        assert_eq!(first, faults(1));
+        assert_ne!(first, faults(2));
+        // Each socket gets a different sequence
+        assert_ne!(first[0], first[1]);
+        let dropped = first[0].iter().filter(|(dropped, _)| *dropped).count();
+        assert!((8..=24).contains(&dropped), "{dropped} out of 32 dropped");
+    }
+
+    #[test]
+    fn sockets_are_seeded_by_what_they_talk_to() {
+        let other = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 443);
+        let shaper = || {
+            ShapedNetworking::new(Arc::new(LoopbackNetworking::new()))
+                .with_seed(7)
+                .shaper
+        };
+
+        let first = shaper();
+        let a = first
+            .rng(SocketIdentity::Outbound { peer: SERVER })
+            .next_u64();
+        let b = first
+            .rng(SocketIdentity::Outbound { peer: other })
+            .next_u64();
+
+        // Creating the sockets in a different order doesn't change them
+        let second = shaper();
+        assert_eq!(
+            second
+                .rng(SocketIdentity::Outbound { peer: other })
+                .next_u64(),
+            b
+        );
+        assert_eq!(
+            second.rng(SocketIdentity::Udp { local: CLIENT }).next_u64(),
+            shaper()
+                .rng(SocketIdentity::Udp { local: CLIENT })
+                .next_u64()
+        );
+        assert_eq!(
+            second
+                .rng(SocketIdentity::Outbound { peer: SERVER })
+                .next_u64(),
+            a
+        );
+        assert_ne!(a, b);
+    }
+
+    #[tokio::test]
+    async fn polling_does_not_cause_resets() {
+        let loopback = LoopbackNetworking::new();
+        let shaped = ShapedNetworking::new(Arc::new(loopback.clone()))
+            .with_conditions(NetworkConditions::new().with_reset_probability(1.0));
+        let (mut server, mut client) = connect(shaped, &loopback).await;
+
+        let mut buffer = [MaybeUninit::<u8>::uninit(); 16];
+        for _ in 0..100 {
+            assert_eq!(server.try_recv(&mut buffer), Err(NetworkError::WouldBlock));
+        }
+
+        client.send(b"ping").await.unwrap();
+        assert_eq!(recv_exact(&mut server, 4).await, b"ping");
+        assert_eq!(
+            server.try_recv(&mut buffer),
+            Err(NetworkError::ConnectionReset)
+        );
+    }
+}
@@ -4,14 +4,17 @@
 //! This is the module that facilitates the usage of Traps
 //! in Wasmer Runtime
 
+mod state;
 #[allow(clippy::module_inception)]
 mod trap;
 mod traphandlers;
 
+pub use state::{TrapFrame, TrapState};
 pub use trap::Trap;
 pub use traphandlers::{
-    catch_traps, on_host_stack, raise_lib_trap, raise_user_trap, set_stack_size,
-    wasmer_call_trampoline, TrapHandlerFn, VMConfig,
+    await_future, catch_traps, catch_traps_async, is_in_wasm_call, is_on_wasm_stack, on_host_stack,
+    raise_lib_trap, raise_user_trap, set_stack_size, wasmer_call_trampoline,
+    wasmer_call_trampoline_async, TrapHandlerFn, VMConfig,
 };
 pub use traphandlers::{init_traps, resume_panic};
 pub use wasmer_types::TrapCode;
@@ -0,0 +1,182 @@
+//! The state of a thread at the time generated code trapped.
+//!
+//! The trap handler captures the registers of the thread, follows the frame
+//! pointers of the guest frames and copies the part of the stack that holds
+//! them, before the stack is unwound. The values of the locals of each
+//! frame can then be recovered from it with the local maps that the
+//! compilers record.
+
+use std::ptr;
+
+/// The DWARF number of the frame pointer register.
+#[cfg(target_arch = "x86_64")]
+const FRAME_POINTER: Option<u16> = Some(6);
+#[cfg(target_arch = "aarch64")]
+const FRAME_POINTER: Option<u16> = Some(29);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const FRAME_POINTER: Option<u16> = None;
+
+/// Offset of the canonical frame address from the frame pointer, the frame
+/// pointer points at the saved frame pointer and return address.
+const FRAME_RECORD_SIZE: usize = 2 * std::mem::size_of::<usize>();
+
+/// Stop following frame pointers after this many frames.
+const MAX_FRAMES: usize = 4096;
+
+/// A frame found by following the frame pointers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrapFrame {
+    /// The program counter of the frame: where the trap happened for the
+    /// innermost frame, the return address for the others.
+    pub pc: usize,
+    /// The canonical frame address of the frame.
+    pub cfa: usize,
+}
+
+/// The registers and the stack of a thread at the time it trapped.
+#[derive(Debug, Clone, Default)]
+pub struct TrapState {
+    /// Registers by their DWARF register number.
+    registers: Vec<(u16, u128)>,
+    /// The frames, innermost first.
+    frames: Vec<TrapFrame>,
+    /// The address of the first byte of `stack`.
+    stack_start: usize,
+    stack: Vec<u8>,
+}
+
+impl TrapState {
+    /// Captures the state of the thread that trapped at `pc`.
+    ///
+    /// # Safety
+    ///
+    /// `sp` and every address for which `in_bounds` returns `true` must be
+    /// readable memory of the stack of the thread.
+    pub(crate) unsafe fn capture(
+        pc: usize,
+        sp: usize,
+        registers: Vec<(u16, u128)>,
+        in_bounds: impl Fn(usize) -> bool,
+    ) -> Self {
+        let fp = FRAME_POINTER.and_then(|fp| {
+            registers
+                .iter()
+                .find(|(register, _)| *register == fp)
+                .map(|(_, value)| *value as usize)
+        });
+        let Some(mut fp) = fp else {
+            return Self {
+                registers,
+                ..Default::default()
+            };
+        };
+
+        let mut frames = vec![TrapFrame {
+            pc,
+            cfa: fp.wrapping_add(FRAME_RECORD_SIZE),
+        }];
+        while frames.len() < MAX_FRAMES
+            && fp % std::mem::align_of::<usize>() == 0
+            && in_bounds(fp)
+            && in_bounds(fp + FRAME_RECORD_SIZE - 1)
+        {
+            let caller_fp = ptr::read(fp as *const usize);
+            let return_address = ptr::read((fp + FRAME_RECORD_SIZE / 2) as *const usize);
+            // Frame pointers only ever lead to older frames
+            if caller_fp <= fp || return_address == 0 {
+                break;
+            }
+            frames.push(TrapFrame {
+                pc: return_address,
+                cfa: caller_fp.wrapping_add(FRAME_RECORD_SIZE),
+            });
+            fp = caller_fp;
+        }
+
+        let end = frames
+            .iter()
+            .map(|frame| frame.cfa)
+            .filter(|cfa| *cfa > sp && in_bounds(*cfa - 1))
+            .max()
+            .unwrap_or(sp);
+        let stack = std::slice::from_raw_parts(sp as *const u8, end - sp).to_vec();
+
+        Self {
+            registers,
+            frames,
+            stack_start: sp,
+            stack,
+        }
+    }
+
+    /// The value of a register, by its DWARF register number.
+    pub fn register(&self, register: u16) -> Option<u128> {
+        self.registers
+            .iter()
+            .find(|(number, _)| *number == register)
+            .map(|(_, value)| *value)
+    }
+
+    /// The frames that were found by following the frame pointers,
+    /// innermost first.
+    pub fn frames(&self) -> &[TrapFrame] {
+        &self.frames
+    }
+
+    /// Reads `len` bytes of the stack at `addr`, if they were captured.
+    pub fn read_stack(&self, addr: usize, len: usize) -> Option<&[u8]> {
+        let start = addr.checked_sub(self.stack_start)?;
+        self.stack.get(start..start.checked_add(len)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_frame_pointers() {
+        let pc = 0x1000;
+        // Two frame records, the outer one ends the chain
+        let mut stack = [0usize; 8];
+        let base = stack.as_ptr() as usize;
+        let word = std::mem::size_of::<usize>();
+        stack[2] = base + 4 * word;
+        stack[3] = 0x2000;
+        stack[4] = base + 6 * word;
+        stack[5] = 0x3000;
+        stack[6] = 0;
+        stack[7] = 0;
+        let in_bounds = |addr: usize| (base..base + 8 * word).contains(&addr);
+
+        let registers = match FRAME_POINTER {
+            Some(fp) => vec![(fp, (base + 2 * word) as u128), (0, 42)],
+            None => return,
+        };
+        let state = unsafe { TrapState::capture(pc, base, registers, in_bounds) };
+
+        assert_eq!(
+            state.frames(),
+            [
+                TrapFrame {
+                    pc,
+                    cfa: base + 4 * word
+                },
+                TrapFrame {
+                    pc: 0x2000,
+                    cfa: base + 6 * word
+                },
+                TrapFrame {
+                    pc: 0x3000,
+                    cfa: base + 8 * word
+                },
+            ]
+        );
+        assert_eq!(state.register(0), Some(42));
+        assert_eq!(
+            state.read_stack(base + 3 * word, word),
+            Some(&0x2000usize.to_ne_bytes()[..])
+        );
+        assert_eq!(state.read_stack(base + 8 * word, 1), None);
+    }
+}
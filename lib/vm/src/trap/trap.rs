@@ -1,3 +1,4 @@
+use super::state::TrapState;
 use backtrace::Backtrace;
 use std::error::Error;
 use std::fmt;
@@ -19,6 +20,8 @@ pub enum Trap {
         backtrace: Backtrace,
         /// Optional trapcode associated to the signal that caused the trap
         signal_trap: Option<TrapCode>,
+        /// The registers and stack of the thread when the trap occurred
+        state: TrapState,
     },
 
     /// A trap raised from a wasm libcall
@@ -55,11 +58,17 @@ impl Trap {
     /// Construct a new Wasm trap with the given source location and backtrace.
     ///
     /// Internally saves a backtrace when constructed.
-    pub fn wasm(pc: usize, backtrace: Backtrace, signal_trap: Option<TrapCode>) -> Self {
+    pub fn wasm(
+        pc: usize,
+        backtrace: Backtrace,
+        signal_trap: Option<TrapCode>,
+        state: TrapState,
+    ) -> Self {
         Self::Wasm {
             pc,
             backtrace,
             signal_trap,
+            state,
         }
     }
 
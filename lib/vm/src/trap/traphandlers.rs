@@ -6,6 +6,7 @@
 //! WebAssembly trap handling, which is built on top of the lower-level
 //! signalhandling mechanisms.
 
+use super::state::TrapState;
 use crate::vmcontext::{VMFunctionContext, VMTrampoline};
 use crate::{Trap, VMContext, VMFunctionBody};
 use backtrace::Backtrace;
@@ -17,13 +18,16 @@ use scopeguard::defer;
 use std::any::Any;
 use std::cell::Cell;
 use std::error::Error;
+use std::future::Future;
 use std::io;
 use std::mem;
 #[cfg(unix)]
 use std::mem::MaybeUninit;
+use std::pin::Pin;
 use std::ptr::{self, NonNull};
 use std::sync::atomic::{compiler_fence, AtomicPtr, AtomicUsize, Ordering};
 use std::sync::{LazyLock, Once};
+use std::task::{Context, Poll};
 use wasmer_types::TrapCode;
 
 /// Configuration for the runtime VM
@@ -260,6 +264,7 @@ cfg_if::cfg_if! {
                 sp,
                 maybe_fault_address,
                 trap_code,
+                || get_registers(&*(context as *const ucontext_t)),
                 |regs| update_context(ucontext, regs),
                 |handler| handler(signum, siginfo, context),
             );
@@ -351,6 +356,67 @@ cfg_if::cfg_if! {
             (pc, sp)
         }
 
+        /// Returns the general purpose registers (and the vector registers
+        /// where they are easy to get to) by their DWARF register number.
+        unsafe fn get_registers(context: &ucontext_t) -> Vec<(u16, u128)> {
+            let mut registers = Vec::new();
+            cfg_if::cfg_if! {
+                if #[cfg(all(
+                    any(target_os = "linux", target_os = "android"),
+                    target_arch = "x86_64",
+                ))] {
+                    let gregs = &context.uc_mcontext.gregs;
+                    let order = [
+                        libc::REG_RAX, libc::REG_RDX, libc::REG_RCX, libc::REG_RBX,
+                        libc::REG_RSI, libc::REG_RDI, libc::REG_RBP, libc::REG_RSP,
+                        libc::REG_R8, libc::REG_R9, libc::REG_R10, libc::REG_R11,
+                        libc::REG_R12, libc::REG_R13, libc::REG_R14, libc::REG_R15,
+                    ];
+                    for (number, reg) in order.into_iter().enumerate() {
+                        registers.push((number as u16, gregs[reg as usize] as u64 as u128));
+                    }
+                    if let Some(fpregs) = context.uc_mcontext.fpregs.as_ref() {
+                        for (i, xmm) in fpregs._xmm.iter().enumerate() {
+                            let mut value = 0u128;
+                            for (j, element) in xmm.element.iter().enumerate() {
+                                value |= (*element as u128) << (32 * j);
+                            }
+                            registers.push((17 + i as u16, value));
+                        }
+                    }
+                } else if #[cfg(all(target_vendor = "apple", target_arch = "x86_64"))] {
+                    let ss = &(*context.uc_mcontext).__ss;
+                    let order = [
+                        ss.__rax, ss.__rdx, ss.__rcx, ss.__rbx, ss.__rsi, ss.__rdi, ss.__rbp,
+                        ss.__rsp, ss.__r8, ss.__r9, ss.__r10, ss.__r11, ss.__r12, ss.__r13,
+                        ss.__r14, ss.__r15,
+                    ];
+                    for (number, value) in order.into_iter().enumerate() {
+                        registers.push((number as u16, value as u128));
+                    }
+                } else if #[cfg(all(
+                    any(target_os = "linux", target_os = "android"),
+                    target_arch = "aarch64",
+                ))] {
+                    for (number, value) in context.uc_mcontext.regs.iter().enumerate() {
+                        registers.push((number as u16, *value as u128));
+                    }
+                    registers.push((31, context.uc_mcontext.sp as u128));
+                } else if #[cfg(all(target_vendor = "apple", target_arch = "aarch64"))] {
+                    let ss = &(*context.uc_mcontext).__ss;
+                    for (number, value) in ss.__x.iter().enumerate() {
+                        registers.push((number as u16, *value as u128));
+                    }
+                    registers.push((29, ss.__fp as u128));
+                    registers.push((30, ss.__lr as u128));
+                    registers.push((31, ss.__sp as u128));
+                } else {
+                    let _ = context;
+                }
+            }
+            registers
+        }
+
         unsafe fn update_context(context: &mut ucontext_t, regs: TrapHandlerRegs) {
             cfg_if::cfg_if! {
                 if #[cfg(all(
@@ -553,6 +619,7 @@ cfg_if::cfg_if! {
                 sp,
                 maybe_fault_address,
                 trap_code,
+                Vec::new,
                 |regs| update_context(context, regs),
                 |handler| handler(exception_info),
             );
@@ -697,6 +764,34 @@ pub unsafe fn wasmer_call_trampoline(
     })
 }
 
+/// Asynchronous version of [`wasmer_call_trampoline`], see
+/// [`catch_traps_async`].
+///
+/// # Safety
+///
+/// Wildly unsafe because it calls raw function pointers and reads/writes raw
+/// function pointers. `values_vec` must stay valid until the returned future
+/// completes or is dropped.
+pub unsafe fn wasmer_call_trampoline_async(
+    trap_handler: Option<*const TrapHandlerFn<'static>>,
+    config: &VMConfig,
+    vmctx: VMFunctionContext,
+    trampoline: VMTrampoline,
+    callee: *const VMFunctionBody,
+    values_vec: *mut u8,
+) -> impl Future<Output = Result<(), Trap>> {
+    catch_traps_async(trap_handler, config, move || {
+        mem::transmute::<
+            unsafe extern "C" fn(
+                *mut VMContext,
+                *const VMFunctionBody,
+                *mut wasmer_types::RawValue,
+            ),
+            extern "C" fn(VMFunctionContext, *const VMFunctionBody, *mut u8),
+        >(trampoline)(vmctx, callee, values_vec);
+    })
+}
+
 /// Catches any wasm traps that happen within the execution of `closure`,
 /// returning them as a `Result`.
 ///
@@ -719,19 +814,123 @@ where
     on_wasm_stack(stack_size, trap_handler, closure).map_err(UnwindReason::into_trap)
 }
 
-// We need two separate thread-local variables here:
+/// Asynchronous version of [`catch_traps`].
+///
+/// The returned future runs `closure` on a separate Wasm stack, just like
+/// [`catch_traps`] does. Host functions called from within `closure` may use
+/// [`await_future`] to wait on a future: the Wasm stack is then suspended and
+/// the returned future yields `Poll::Pending` until it is polled again.
+///
+/// Dropping the returned future before it completes abandons the Wasm stack
+/// without running the destructors of the objects that live on it.
+///
+/// # Safety
+///
+/// Highly unsafe since `closure` won't have any dtors run.
+pub unsafe fn catch_traps_async<F, R: 'static>(
+    trap_handler: Option<*const TrapHandlerFn<'static>>,
+    config: &VMConfig,
+    closure: F,
+) -> impl Future<Output = Result<R, Trap>>
+where
+    F: FnOnce() -> R + 'static,
+{
+    let stack_size = config
+        .wasm_stack_size
+        .unwrap_or_else(|| DEFAULT_STACK_SIZE.load(Ordering::Relaxed));
+    let init = lazy_per_thread_init();
+    let coro = init.is_ok().then(|| {
+        let stack = STACK_POOL
+            .pop()
+            .unwrap_or_else(|| DefaultStack::new(stack_size).unwrap());
+        Coroutine::with_stack(stack, move |yielder, ()| {
+            YIELDER.with(|cell| cell.set(Some(yielder.into())));
+            Ok(closure())
+        })
+    });
+
+    AsyncWasmCall {
+        coro,
+        init: init.err(),
+        trap_handler,
+    }
+}
+
+/// Waits for `future` to complete from within a host function that was
+/// called by Wasm code running under [`catch_traps_async`].
+///
+/// The future is polled on the Wasm stack. Whenever it is pending, the Wasm
+/// stack is suspended and the future returned by [`catch_traps_async`]
+/// yields back to its executor, resuming here once it gets polled again.
+///
+/// Returns `None` if the current Wasm call was not started with
+/// [`catch_traps_async`], or if this isn't called on the Wasm stack (for
+/// example from within [`on_host_stack`]).
+pub fn await_future<F: Future>(future: F) -> Option<F::Output> {
+    let mut future = std::pin::pin!(future);
+    loop {
+        let yielder = YIELDER.with(|cell| cell.get())?;
+        let cx = ASYNC_CONTEXT.with(|cell| cell.get())?;
+
+        // Safety: the context is only set while `AsyncWasmCall::poll` is
+        // resuming the coroutine we are running on.
+        let cx = unsafe { &mut *cx.as_ptr() };
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            return Some(output);
+        }
+
+        unsafe { yielder.as_ref() }.suspend(WasmYield::Pending);
+
+        // We may have been resumed by a different `poll` call, which resets
+        // the thread-local state on exit.
+        YIELDER.with(|cell| cell.set(Some(yielder)));
+    }
+}
+
+// We need three separate thread-local variables here:
 // - YIELDER is set within the new stack and is used to unwind back to the root
 //   of the stack from inside it.
 // - TRAP_HANDLER is set from outside the new stack and is solely used from
 //   signal handlers. It must be atomic since it is used by signal handlers.
+// - ASYNC_CONTEXT is set from outside the new stack while an asynchronous
+//   call is being polled, and is used to poll futures from inside it.
 //
 // We also do per-thread signal stack initialization on the first time
 // TRAP_HANDLER is accessed.
 thread_local! {
-    static YIELDER: Cell<Option<NonNull<Yielder<(), UnwindReason>>>> = const { Cell::new(None) };
+    static YIELDER: Cell<Option<NonNull<Yielder<(), WasmYield>>>> = const { Cell::new(None) };
     static TRAP_HANDLER: AtomicPtr<TrapHandlerContext> = const { AtomicPtr::new(ptr::null_mut()) };
+    static ASYNC_CONTEXT: Cell<Option<NonNull<Context<'static>>>> = const { Cell::new(None) };
+}
+
+/// Returns whether the current thread is inside a call into WebAssembly,
+/// either running compiled code or host code that it called.
+///
+/// This only reads a thread-local, so it can be called from a signal handler.
+pub fn is_in_wasm_call() -> bool {
+    TRAP_HANDLER.with(|ptr| !ptr.load(Ordering::Relaxed).is_null())
 }
 
+/// Returns whether `addr` lies within the Wasm stack of the call that the
+/// current thread is inside of (including its guard pages).
+///
+/// This only reads a thread-local, so it can be called from a signal handler.
+pub fn is_on_wasm_stack(addr: usize) -> bool {
+    let ptr = TRAP_HANDLER.with(|ptr| ptr.load(Ordering::Relaxed));
+    if ptr.is_null() {
+        return false;
+    }
+    let ctx = unsafe { &*ptr };
+    (ctx.stack_ptr_in_bounds)(ctx.inner, addr)
+}
+
+/// Allocating a new stack is pretty expensive since it involves several
+/// system calls. We therefore keep a cache of pre-allocated stacks which
+/// allows them to be reused multiple times.
+// FIXME(Amanieu): We should refactor this to avoid the lock.
+static STACK_POOL: LazyLock<crossbeam_queue::SegQueue<DefaultStack>> =
+    LazyLock::new(crossbeam_queue::SegQueue::new);
+
 /// Read-only information that is used by signal handlers to handle and recover
 /// from traps.
 #[allow(clippy::type_complexity)]
@@ -743,8 +942,10 @@ struct TrapHandlerContext {
         usize,
         Option<usize>,
         Option<TrapCode>,
+        &dyn Fn() -> Vec<(u16, u128)>,
         &mut dyn FnMut(TrapHandlerRegs),
     ) -> bool,
+    stack_ptr_in_bounds: fn(*const u8, usize) -> bool,
     custom_trap: Option<*const TrapHandlerFn<'static>>,
 }
 struct TrapHandlerContextInner<T> {
@@ -768,6 +969,7 @@ impl TrapHandlerContext {
             sp: usize,
             maybe_fault_address: Option<usize>,
             trap_code: Option<TrapCode>,
+            registers: &dyn Fn() -> Vec<(u16, u128)>,
             update_regs: &mut dyn FnMut(TrapHandlerRegs),
         ) -> bool {
             unsafe {
@@ -776,14 +978,23 @@ impl TrapHandlerContext {
                     sp,
                     maybe_fault_address,
                     trap_code,
+                    registers,
                     update_regs,
                 )
             }
         }
+        fn in_bounds<T>(ptr: *const u8, sp: usize) -> bool {
+            unsafe {
+                (*(ptr as *const TrapHandlerContextInner<T>))
+                    .coro_trap_handler
+                    .stack_ptr_in_bounds(sp)
+            }
+        }
         let inner = TrapHandlerContextInner { coro_trap_handler };
         let ctx = Self {
             inner: &inner as *const _ as *const u8,
             handle_trap: func::<T>,
+            stack_ptr_in_bounds: in_bounds::<T>,
             custom_trap,
         };
 
@@ -808,6 +1019,7 @@ impl TrapHandlerContext {
         sp: usize,
         maybe_fault_address: Option<usize>,
         trap_code: Option<TrapCode>,
+        registers: impl Fn() -> Vec<(u16, u128)>,
         mut update_regs: impl FnMut(TrapHandlerRegs),
         call_handler: impl Fn(&TrapHandlerFn<'static>) -> bool,
     ) -> bool {
@@ -831,6 +1043,7 @@ impl TrapHandlerContext {
             sp,
             maybe_fault_address,
             trap_code,
+            &registers,
             &mut update_regs,
         )
     }
@@ -843,6 +1056,7 @@ impl<T> TrapHandlerContextInner<T> {
         sp: usize,
         maybe_fault_address: Option<usize>,
         trap_code: Option<TrapCode>,
+        registers: &dyn Fn() -> Vec<(u16, u128)>,
         update_regs: &mut dyn FnMut(TrapHandlerRegs),
     ) -> bool {
         // Check if this trap occurred while executing on the Wasm stack. We can
@@ -867,10 +1081,14 @@ impl<T> TrapHandlerContextInner<T> {
         // read invalid memory addresses.
         //
         // See: https://github.com/rust-lang/backtrace-rs/pull/357
-        let backtrace = if signal_trap == Some(TrapCode::StackOverflow) {
-            Backtrace::from(vec![])
+        // The same goes for following the frame pointers.
+        let (backtrace, state) = if signal_trap == Some(TrapCode::StackOverflow) {
+            (Backtrace::from(vec![]), TrapState::default())
         } else {
-            Backtrace::new_unresolved()
+            let state = TrapState::capture(pc, sp, registers(), |addr| {
+                self.coro_trap_handler.stack_ptr_in_bounds(addr)
+            });
+            (Backtrace::new_unresolved(), state)
         };
 
         // Set up the register state for exception return to force the
@@ -879,6 +1097,7 @@ impl<T> TrapHandlerContextInner<T> {
             backtrace,
             signal_trap,
             pc,
+            state,
         };
         let regs = self
             .coro_trap_handler
@@ -888,6 +1107,14 @@ impl<T> TrapHandlerContextInner<T> {
     }
 }
 
+/// The values a coroutine running Wasm code can suspend with.
+enum WasmYield {
+    /// Execution must be unwound back to the root of the stack.
+    Unwind(UnwindReason),
+    /// A host function is waiting on a pending future.
+    Pending,
+}
+
 enum UnwindReason {
     /// A panic caused by the host
     Panic(Box<dyn Any + Send>),
@@ -900,6 +1127,7 @@ enum UnwindReason {
         backtrace: Backtrace,
         pc: usize,
         signal_trap: Option<TrapCode>,
+        state: TrapState,
     },
 }
 
@@ -912,7 +1140,8 @@ impl UnwindReason {
                 backtrace,
                 pc,
                 signal_trap,
-            } => Trap::wasm(pc, backtrace, signal_trap),
+                state,
+            } => Trap::wasm(pc, backtrace, signal_trap, state),
             Self::Panic(panic) => std::panic::resume_unwind(panic),
         }
     }
@@ -923,7 +1152,7 @@ unsafe fn unwind_with(reason: UnwindReason) -> ! {
         .with(|cell| cell.replace(None))
         .expect("not running on Wasm stack");
 
-    yielder.as_ref().suspend(reason);
+    yielder.as_ref().suspend(WasmYield::Unwind(reason));
 
     // on_wasm_stack will forcibly reset the coroutine stack after yielding.
     unreachable!();
@@ -937,13 +1166,6 @@ fn on_wasm_stack<F: FnOnce() -> T + 'static, T: 'static>(
     trap_handler: Option<*const TrapHandlerFn<'static>>,
     f: F,
 ) -> Result<T, UnwindReason> {
-    // Allocating a new stack is pretty expensive since it involves several
-    // system calls. We therefore keep a cache of pre-allocated stacks which
-    // allows them to be reused multiple times.
-    // FIXME(Amanieu): We should refactor this to avoid the lock.
-    static STACK_POOL: LazyLock<crossbeam_queue::SegQueue<DefaultStack>> =
-        LazyLock::new(crossbeam_queue::SegQueue::new);
-
     let stack = STACK_POOL
         .pop()
         .unwrap_or_else(|| DefaultStack::new(stack_size).unwrap());
@@ -957,16 +1179,25 @@ fn on_wasm_stack<F: FnOnce() -> T + 'static, T: 'static>(
         Ok(f())
     });
 
-    // Ensure that YIELDER is reset on exit even if the coroutine panics,
+    // Synchronous calls can't be suspended, even when they are nested in an
+    // asynchronous one.
+    let async_context = ASYNC_CONTEXT.with(|cell| cell.replace(None));
+
+    // The call may be nested in a host function that was called from Wasm
+    // without switching to the host stack (e.g. an asynchronous one), in
+    // which case the yielder of the outer call must be restored on exit, even
+    // if the coroutine panics.
+    let outer_yielder = YIELDER.with(|cell| cell.get());
     defer! {
-        YIELDER.with(|cell| cell.set(None));
+        YIELDER.with(|cell| cell.set(outer_yielder));
+        ASYNC_CONTEXT.with(|cell| cell.set(async_context));
     }
 
     // Set up metadata for the trap handler for the duration of the coroutine
     // execution. This is restored to its previous value afterwards.
     TrapHandlerContext::install(trap_handler, coro.trap_handler(), || {
         match coro.resume(()) {
-            CoroutineResult::Yield(trap) => {
+            CoroutineResult::Yield(WasmYield::Unwind(trap)) => {
                 // This came from unwind_with which requires that there be only
                 // Wasm code on the stack.
                 unsafe {
@@ -974,11 +1205,85 @@ fn on_wasm_stack<F: FnOnce() -> T + 'static, T: 'static>(
                 }
                 Err(trap)
             }
+            CoroutineResult::Yield(WasmYield::Pending) => {
+                unreachable!("synchronous Wasm calls can't be suspended")
+            }
             CoroutineResult::Return(result) => result,
         }
     })
 }
 
+/// The future returned by [`catch_traps_async`].
+struct AsyncWasmCall<R> {
+    coro: Option<Coroutine<(), WasmYield, Result<R, UnwindReason>, DefaultStack>>,
+    /// Error from the per-thread initialization, reported on the first poll.
+    init: Option<Trap>,
+    trap_handler: Option<*const TrapHandlerFn<'static>>,
+}
+
+impl<R> Future for AsyncWasmCall<R> {
+    type Output = Result<R, Trap>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if let Some(trap) = this.init.take() {
+            return Poll::Ready(Err(trap));
+        }
+        let coro = this
+            .coro
+            .as_mut()
+            .expect("`AsyncWasmCall` polled after completion");
+
+        let cx = NonNull::from(cx).cast::<Context<'static>>();
+        let async_context = ASYNC_CONTEXT.with(|cell| cell.replace(Some(cx)));
+        let outer_yielder = YIELDER.with(|cell| cell.get());
+
+        // Restore the thread-local state even if the coroutine panics.
+        defer! {
+            YIELDER.with(|cell| cell.set(outer_yielder));
+            ASYNC_CONTEXT.with(|cell| cell.set(async_context));
+        }
+
+        let result = TrapHandlerContext::install(this.trap_handler, coro.trap_handler(), || {
+            match coro.resume(()) {
+                CoroutineResult::Yield(WasmYield::Unwind(trap)) => {
+                    // This came from unwind_with which requires that there be
+                    // only Wasm code on the stack.
+                    unsafe {
+                        coro.force_reset();
+                    }
+                    Poll::Ready(Err(trap))
+                }
+                CoroutineResult::Yield(WasmYield::Pending) => Poll::Pending,
+                CoroutineResult::Return(result) => Poll::Ready(result),
+            }
+        });
+
+        match result {
+            Poll::Ready(result) => {
+                if let Some(coro) = this.coro.take() {
+                    STACK_POOL.push(coro.into_stack());
+                }
+                Poll::Ready(result.map_err(UnwindReason::into_trap))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<R> Drop for AsyncWasmCall<R> {
+    fn drop(&mut self) {
+        if let Some(mut coro) = self.coro.take() {
+            // The stack can't be unwound through Wasm frames, so abandon
+            // whatever is still on it instead.
+            unsafe {
+                coro.force_reset();
+            }
+            STACK_POOL.push(coro.into_stack());
+        }
+    }
+}
+
 /// When executing on the Wasm stack, temporarily switch back to the host stack
 /// to perform an operation that should not be constrainted by the Wasm stack
 /// limits.
@@ -505,3 +505,137 @@ where
     #[inline]
     fn zero_padding_bytes(&self, _bytes: &mut [MaybeUninit<u8>]) {}
 }
+
+/// Set of signals, bit `n - 1` represents signal `n`
+pub type Sigset = u64;
+
+#[doc = " What `thread_sigmask` does with the signal set it is given."]
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq, num_enum :: TryFromPrimitive, Hash)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub enum SigmaskHow {
+    #[doc = " Add the signals to the mask (`SIG_BLOCK`)."]
+    Block,
+    #[doc = " Remove the signals from the mask (`SIG_UNBLOCK`)."]
+    Unblock,
+    #[doc = " Replace the mask with the signals (`SIG_SETMASK`)."]
+    Setmask,
+    #[doc = " Unknown."]
+    Unknown,
+}
+impl core::fmt::Debug for SigmaskHow {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SigmaskHow::Block => f.debug_tuple("SIG_BLOCK").finish(),
+            SigmaskHow::Unblock => f.debug_tuple("SIG_UNBLOCK").finish(),
+            SigmaskHow::Setmask => f.debug_tuple("SIG_SETMASK").finish(),
+            SigmaskHow::Unknown => f.debug_tuple("Unknown").finish(),
+        }
+    }
+}
+
+unsafe impl wasmer::FromToNativeWasmType for SigmaskHow {
+    type Native = i32;
+
+    fn to_native(self) -> Self::Native {
+        self as i32
+    }
+
+    fn from_native(n: Self::Native) -> Self {
+        match n {
+            0 => Self::Block,
+            1 => Self::Unblock,
+            2 => Self::Setmask,
+
+            q => {
+                tracing::debug!("could not serialize number {q} to enum SigmaskHow");
+                Self::Unknown
+            }
+        }
+    }
+
+    fn is_from_store(&self, _store: &impl wasmer::AsStoreRef) -> bool {
+        false
+    }
+}
+
+#[doc = " How a process reacts to a signal."]
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, num_enum :: TryFromPrimitive, Hash)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub enum SigDisposition {
+    #[doc = " The default action for the signal (`SIG_DFL`)."]
+    Default,
+    #[doc = " The signal is discarded (`SIG_IGN`)."]
+    Ignore,
+    #[doc = " The signal is passed to the callback registered with `callback_signal`."]
+    Handler,
+}
+
+wai_bindgen_rust::bitflags::bitflags! {
+    #[doc = " Flags that change how a signal handler is invoked."]
+    #[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+    pub struct SigActionFlags : u32 {
+        #[doc = " Blocking calls interrupted by the handler are restarted rather than failing with `EINTR`."]
+        const RESTART = 1 << 0;
+        #[doc = " The disposition is reset to the default when the handler is invoked."]
+        const RESETHAND = 1 << 1;
+        #[doc = " The signal isn't blocked while its own handler runs."]
+        const NODEFER = 1 << 2;
+    }
+}
+
+#[doc = " The action taken when a signal is delivered, see `proc_sigaction`."]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub struct SigAction {
+    #[doc = " Additional signals that are blocked while the handler runs."]
+    pub mask: Sigset,
+    pub flags: SigActionFlags,
+    pub disposition: SigDisposition,
+}
+impl Default for SigAction {
+    fn default() -> Self {
+        SigAction {
+            mask: 0,
+            flags: SigActionFlags::empty(),
+            disposition: SigDisposition::Default,
+        }
+    }
+}
+
+#[doc = " The layout of `SigAction` in the memory of the guest, which may hold"]
+#[doc = " any value and is validated when converted."]
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct SigActionRaw {
+    pub mask: Sigset,
+    pub flags: u32,
+    pub disposition: u32,
+}
+unsafe impl ValueType for SigActionRaw {
+    #[inline]
+    fn zero_padding_bytes(&self, _bytes: &mut [MaybeUninit<u8>]) {}
+}
+
+impl TryFrom<SigActionRaw> for SigAction {
+    type Error = Errno;
+
+    fn try_from(raw: SigActionRaw) -> Result<Self, Self::Error> {
+        Ok(SigAction {
+            mask: raw.mask,
+            flags: SigActionFlags::from_bits(raw.flags).ok_or(Errno::Inval)?,
+            disposition: SigDisposition::try_from(raw.disposition).map_err(|_| Errno::Inval)?,
+        })
+    }
+}
+
+impl From<SigAction> for SigActionRaw {
+    fn from(action: SigAction) -> Self {
+        SigActionRaw {
+            mask: action.mask,
+            flags: action.flags.bits(),
+            disposition: action.disposition as u32,
+        }
+    }
+}
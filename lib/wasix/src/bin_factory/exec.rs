@@ -1,5 +1,7 @@
 use std::sync::Arc;
 
+#[cfg(feature = "journal")]
+use crate::{journal::DynJournal, syscalls::restore_snapshot};
 use crate::{
     os::task::{
         thread::{RewindResultType, WasiThreadRunGuard},
@@ -7,12 +9,13 @@ use crate::{
     },
     runtime::{
         task_manager::{
-            TaskWasm, TaskWasmRecycle, TaskWasmRecycleProperties, TaskWasmRunProperties,
+            SpawnMemoryTypeOrStore, TaskWasm, TaskWasmRecycle, TaskWasmRecycleProperties,
+            TaskWasmRunProperties,
         },
         TaintReason,
     },
     syscalls::rewind_ext,
-    RewindState, SpawnError, WasiError, WasiRuntimeError,
+    RewindState, RewindStateOption, SpawnError, WasiError, WasiRuntimeError,
 };
 use tracing::*;
 use wasmer::{Function, Memory32, Memory64, Module, Store};
@@ -169,31 +172,16 @@ pub fn run_exec(props: TaskWasmRunProperties) {
     let thread = WasiThreadRunGuard::new(ctx.data(&store).thread.clone());
     let recycle = props.recycle;
 
-    // Perform the initialization
-    let ctx = {
-        // If this module exports an _initialize function, run that first.
-        if let Ok(initialize) = unsafe { ctx.data(&store).inner() }
-            .instance
-            .exports
-            .get_function("_initialize")
-        {
-            let initialize = initialize.clone();
-            if let Err(err) = initialize.call(&mut store, &[]) {
-                thread.thread.set_status_finished(Err(err.into()));
-                ctx.data(&store)
-                    .blocking_on_exit(Some(Errno::Noexec.into()));
-                unsafe { run_recycle(recycle, ctx, store) };
-                return;
-            }
-        }
-
-        WasiFunctionEnv { env: ctx.env }
-    };
-
-    // Bootstrap the process
     // Unsafe: The bootstrap must be executed in the same thread that runs the
     //         actual WASM code
-    let rewind_state = match unsafe { ctx.bootstrap(&mut store) } {
+    let rewind_state = match unsafe {
+        initialize(
+            &ctx,
+            &mut store,
+            #[cfg(feature = "journal")]
+            None,
+        )
+    } {
         Ok(r) => r,
         Err(err) => {
             tracing::warn!("failed to bootstrap - {}", err);
@@ -213,6 +201,118 @@ pub fn run_exec(props: TaskWasmRunProperties) {
     call_module(ctx, store, thread, rewind_state, recycle);
 }
 
+/// Runs everything that comes before the entry point of a module: the
+/// `_initialize` function of reactors, the restoration of `snapshot` and the
+/// replay of the runtime's journals.
+///
+/// # SAFETY
+/// This must be executed from the same thread that runs the entry point (see
+/// [`WasiFunctionEnv::bootstrap`]).
+#[allow(clippy::result_large_err)]
+unsafe fn initialize(
+    ctx: &WasiFunctionEnv,
+    store: &mut Store,
+    #[cfg(feature = "journal")] snapshot: Option<Arc<DynJournal>>,
+) -> Result<RewindStateOption, WasiRuntimeError> {
+    // If this module exports an _initialize function, run that first.
+    if let Ok(initialize) = ctx
+        .data(store)
+        .inner()
+        .instance
+        .exports
+        .get_function("_initialize")
+    {
+        let initialize = initialize.clone();
+        initialize.call(store, &[])?;
+    }
+
+    #[allow(unused_mut)]
+    let mut rewind_state = None;
+    #[cfg(feature = "journal")]
+    if let Some(snapshot) = snapshot {
+        let env = ctx.env.clone().into_mut(store);
+        rewind_state = restore_snapshot(env, snapshot, true)?
+            .map(|rewind| (rewind, RewindResultType::RewindRestart));
+    }
+
+    // Bootstrap the process
+    let bootstrapped = ctx.bootstrap(store)?;
+    Ok(bootstrapped.or(rewind_state))
+}
+
+/// A module instance that was created ahead of time and is ready to have
+/// its entry point called by [`run_prepared_exec`].
+#[derive(derive_more::Debug)]
+pub struct PreparedExec {
+    ctx: WasiFunctionEnv,
+    #[debug(ignore)]
+    store: Store,
+    #[debug(ignore)]
+    rewind_state: RewindStateOption,
+    #[debug(ignore)]
+    thread: WasiThreadRunGuard,
+}
+
+/// Instantiates a module and runs everything that comes before its entry
+/// point, so the cost of doing so is not paid by whoever runs it later.
+///
+/// When a snapshot is given the instance is restored from it, and its entry
+/// point resumes from where the snapshot was taken.
+///
+/// Note: The instance is moved to the thread that calls [`run_prepared_exec`],
+/// which only the `sys` backend supports.
+#[allow(clippy::result_large_err)]
+pub fn prepare_exec(
+    module: Module,
+    env: WasiEnv,
+    #[cfg(feature = "journal")] snapshot: Option<Arc<DynJournal>>,
+) -> Result<PreparedExec, WasiRuntimeError> {
+    let spawn_type = match module.imports().memories().next() {
+        Some(memory) => SpawnMemoryTypeOrStore::Type(*memory.ty()),
+        None => SpawnMemoryTypeOrStore::New,
+    };
+    let (ctx, mut store) = WasiFunctionEnv::new_with_store(module, env, None, spawn_type, false)?;
+    let thread = WasiThreadRunGuard::new(ctx.data(&store).thread.clone());
+
+    match unsafe {
+        initialize(
+            &ctx,
+            &mut store,
+            #[cfg(feature = "journal")]
+            snapshot,
+        )
+    } {
+        Ok(rewind_state) => Ok(PreparedExec {
+            ctx,
+            store,
+            rewind_state,
+            thread,
+        }),
+        Err(err) => {
+            thread
+                .thread
+                .set_status_finished(Err(WasiRuntimeError::Anyhow(Arc::new(anyhow::anyhow!(
+                    "{err}"
+                )))));
+            ctx.data(&store)
+                .blocking_on_exit(Some(Errno::Noexec.into()));
+            Err(err)
+        }
+    }
+}
+
+/// Calls the entry point of an instance created by [`prepare_exec`].
+pub fn run_prepared_exec(prepared: PreparedExec, recycle: Option<Box<TaskWasmRecycle>>) {
+    let PreparedExec {
+        ctx,
+        store,
+        rewind_state,
+        thread,
+    } = prepared;
+    debug!("wasi[{}]::called main()", ctx.data(&store).pid());
+    call_module(ctx, store, thread, rewind_state, recycle);
+}
+
 fn get_start(ctx: &WasiFunctionEnv, store: &Store) -> Option<Function> {
     unsafe { ctx.data(store).inner() }
         .instance
@@ -319,6 +419,7 @@ fn call_module(
                     Ok(Errno::Noexec)
                 }
                 Err(err) => {
+                    ctx.write_coredump(&mut store, &err);
                     runtime.on_taint(TaintReason::RuntimeError(err.clone()));
                     Err(WasiRuntimeError::from(err))
                 }
@@ -19,12 +19,18 @@ mod exec;
 pub use self::{
     binary_package::*,
     exec::{
-        run_exec, spawn_exec, spawn_exec_module, spawn_exec_wasm, spawn_load_module,
-        spawn_load_wasm, spawn_union_fs,
+        prepare_exec, run_exec, run_prepared_exec, spawn_exec, spawn_exec_module, spawn_exec_wasm,
+        spawn_load_module, spawn_load_wasm, spawn_union_fs, PreparedExec,
     },
 };
 use crate::{
-    os::{command::Commands, task::TaskJoinHandle},
+    os::{
+        command::{
+            builtins::cmd_coreutils::{spawn_coreutil, Coreutil},
+            Commands,
+        },
+        task::TaskJoinHandle,
+    },
     Runtime, SpawnError, WasiEnv,
 };
 
@@ -74,12 +80,21 @@ impl BinFactory {
     ) -> Pin<Box<dyn Future<Output = Result<TaskJoinHandle, SpawnError>> + 'a>> {
         Box::pin(async move {
             // Find the binary (or die trying) and make the spawn type
-            let res = self
+            let executable = self
                 .get_executable(name.as_str(), Some(env.fs_root()))
-                .await
-                .ok_or_else(|| SpawnError::BinaryNotFound {
-                    binary: name.clone(),
-                });
+                .await;
+
+            // The native core utilities are only used when no package
+            // provides the command, and only where they were asked for
+            if executable.is_none() && env.capabilities.native_coreutils {
+                if let Some(util) = Coreutil::from_command(name.as_str()) {
+                    return spawn_coreutil(util, env, &self.runtime);
+                }
+            }
+
+            let res = executable.ok_or_else(|| SpawnError::BinaryNotFound {
+                binary: name.clone(),
+            });
             if res.is_err() {
                 env.on_exit(Some(Errno::Noent.into())).await;
             }
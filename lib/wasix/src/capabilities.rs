@@ -1,4 +1,4 @@
-use std::time::Duration;
+use std::{path::PathBuf, time::Duration};
 
 use crate::http::HttpClientCapabilityV1;
 
@@ -8,6 +8,15 @@ pub struct Capabilities {
     pub insecure_allow_all: bool,
     pub http_client: HttpClientCapabilityV1,
     pub threading: CapabilityThreadingV1,
+    /// Directory that core dumps are written to when a process or thread
+    /// traps.
+    pub coredump_dir: Option<PathBuf>,
+    /// File that the core dump of the main thread of the root process is
+    /// written to when it traps, instead of into [`Self::coredump_dir`].
+    pub coredump_on_trap: Option<PathBuf>,
+    /// Runs the native core utilities (`ls`, `cat`, ...) for commands that
+    /// no package provides, which only the console turns on.
+    pub native_coreutils: bool,
 }
 
 impl Capabilities {
@@ -16,6 +25,9 @@ impl Capabilities {
             insecure_allow_all: false,
             http_client: Default::default(),
             threading: Default::default(),
+            coredump_dir: None,
+            coredump_on_trap: None,
+            native_coreutils: false,
         }
     }
 
@@ -26,10 +38,16 @@ impl Capabilities {
             insecure_allow_all,
             http_client,
             threading,
+            coredump_dir,
+            coredump_on_trap,
+            native_coreutils,
         } = other;
         self.insecure_allow_all |= insecure_allow_all;
         self.http_client.update(http_client);
         self.threading.update(threading);
+        self.coredump_dir = coredump_dir.or(self.coredump_dir.take());
+        self.coredump_on_trap = coredump_on_trap.or(self.coredump_on_trap.take());
+        self.native_coreutils |= native_coreutils;
     }
 }
 
@@ -1503,7 +1503,10 @@ impl WasiFs {
                 Kind::Dir { .. } => Filetype::Directory,
                 Kind::Symlink { .. } => Filetype::SymbolicLink,
                 Kind::Socket { socket } => match &socket.inner.protected.read().unwrap().kind {
-                    InodeSocketKind::TcpStream { .. } => Filetype::SocketStream,
+                    InodeSocketKind::TcpStream { .. }
+                    | InodeSocketKind::UnixListener(_)
+                    | InodeSocketKind::UnixStream(_) => Filetype::SocketStream,
+                    InodeSocketKind::UnixDatagram(_) => Filetype::SocketDgram,
                     InodeSocketKind::Raw { .. } => Filetype::SocketRaw,
                     InodeSocketKind::PreSocket { props, .. } => match props.ty {
                         Socktype::Stream => Filetype::SocketStream,
@@ -1779,6 +1782,22 @@ impl WasiFs {
         ))
     }
 
+    /// Installs a descriptor that was passed over from another process (over
+    /// a unix socket) in the first free slot. It keeps sharing the offset
+    /// with the descriptor it was copied from.
+    pub(crate) fn install_fd(&self, fd: Fd) -> WasiFd {
+        let mut fd_flags = fd.inner.fd_flags;
+        fd_flags.set(Fdflagsext::CLOEXEC, false);
+        self.fd_map.write().unwrap().insert_first_free(Fd {
+            inner: FdInner {
+                fd_flags,
+                ..fd.inner
+            },
+            is_stdio: false,
+            ..fd
+        })
+    }
+
     /// Low level function to remove an inode, that is it deletes the WASI FS's
     /// knowledge of a file.
     ///
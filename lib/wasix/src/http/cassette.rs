@@ -0,0 +1,475 @@
+//! Recording HTTP traffic so it can be replayed later.
+//!
+//! A [`RecordingHttpClient`] sends requests through another [`HttpClient`]
+//! and writes every request/response pair to a [`Cassette`] file. A
+//! [`ReplayHttpClient`] answers requests from a cassette without touching
+//! the network, which makes tests hermetic.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{Context, Error};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::future::BoxFuture;
+use http::{header::HeaderName, HeaderMap, HeaderValue, Method, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use super::{DynHttpClient, HttpClient, HttpRequest, HttpResponse};
+
+/// Request headers which are never written to a cassette.
+const REDACTED_HEADERS: &[&str] = &["authorization", "proxy-authorization", "cookie"];
+
+/// A recording of HTTP interactions.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let contents = std::fs::read(path)
+            .with_context(|| format!("Unable to read \"{}\"", path.display()))?;
+        serde_json::from_slice(&contents)
+            .with_context(|| format!("Unable to parse \"{}\" as a cassette", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let contents = serde_json::to_vec_pretty(self)?;
+        std::fs::write(path, contents)
+            .with_context(|| format!("Unable to write \"{}\"", path.display()))
+    }
+}
+
+/// A request and the response it received.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<RecordedBody>,
+}
+
+impl RecordedRequest {
+    fn new(request: &HttpRequest) -> Self {
+        let headers = request
+            .headers
+            .iter()
+            .filter(|(name, _)| !REDACTED_HEADERS.contains(&name.as_str()))
+            .map(|(name, value)| (name.to_string(), lossy(value)))
+            .collect();
+
+        RecordedRequest {
+            method: request.method.to_string(),
+            url: request.url.to_string(),
+            headers,
+            body: request.body.as_deref().map(RecordedBody::new),
+        }
+    }
+
+    fn header(&self, name: &HeaderName) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name.as_str()))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<RecordedBody>,
+    #[serde(default)]
+    pub redirected: bool,
+}
+
+impl RecordedResponse {
+    fn new(response: &HttpResponse) -> Self {
+        RecordedResponse {
+            status: response.status.as_u16(),
+            headers: response
+                .headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), lossy(value)))
+                .collect(),
+            body: response.body.as_deref().map(RecordedBody::new),
+            redirected: response.redirected,
+        }
+    }
+
+    fn to_response(&self) -> Result<HttpResponse, Error> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            headers.append(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+
+        Ok(HttpResponse {
+            body: self.body.as_ref().map(RecordedBody::to_bytes).transpose()?,
+            redirected: self.redirected,
+            status: StatusCode::from_u16(self.status)?,
+            headers,
+        })
+    }
+}
+
+/// A body, stored as text when it is valid UTF-8.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RecordedBody {
+    Text(String),
+    Binary { base64: String },
+}
+
+impl RecordedBody {
+    fn new(body: &[u8]) -> Self {
+        match std::str::from_utf8(body) {
+            Ok(text) => RecordedBody::Text(text.to_string()),
+            Err(_) => RecordedBody::Binary {
+                base64: STANDARD.encode(body),
+            },
+        }
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        match self {
+            RecordedBody::Text(text) => Ok(text.clone().into_bytes()),
+            RecordedBody::Binary { base64 } => Ok(STANDARD.decode(base64)?),
+        }
+    }
+}
+
+fn lossy(value: &HeaderValue) -> String {
+    String::from_utf8_lossy(value.as_bytes()).into_owned()
+}
+
+/// An [`HttpClient`] which saves every request it sends, along with the
+/// response, to a cassette file.
+///
+/// The file is rewritten after each interaction so nothing is lost if the
+/// process exits early. `Authorization`, `Proxy-Authorization` and `Cookie`
+/// headers are left out of the recording.
+#[derive(Debug)]
+pub struct RecordingHttpClient {
+    inner: DynHttpClient,
+    path: PathBuf,
+    cassette: Mutex<Cassette>,
+}
+
+impl RecordingHttpClient {
+    pub fn new(inner: DynHttpClient, path: impl Into<PathBuf>) -> Self {
+        RecordingHttpClient {
+            inner,
+            path: path.into(),
+            cassette: Mutex::new(Cassette::default()),
+        }
+    }
+
+    /// The interactions recorded so far.
+    pub fn cassette(&self) -> Cassette {
+        self.cassette.lock().unwrap().clone()
+    }
+}
+
+impl HttpClient for RecordingHttpClient {
+    fn request(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, Error>> {
+        Box::pin(async move {
+            let recorded = RecordedRequest::new(&request);
+            let response = self.inner.request(request).await?;
+
+            let mut cassette = self.cassette.lock().unwrap();
+            cassette.interactions.push(Interaction {
+                request: recorded,
+                response: RecordedResponse::new(&response),
+            });
+            cassette.save(&self.path)?;
+
+            Ok(response)
+        })
+    }
+}
+
+/// Which parts of a request need to match a recording for it to be replayed.
+///
+/// The method and URL (minus the fragment) always need to match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchRules {
+    /// Compare query strings.
+    pub query: bool,
+    /// Compare request bodies.
+    pub body: bool,
+    /// Request headers which need to have the same value.
+    pub headers: Vec<HeaderName>,
+}
+
+impl Default for MatchRules {
+    fn default() -> Self {
+        MatchRules {
+            query: true,
+            body: false,
+            headers: Vec::new(),
+        }
+    }
+}
+
+impl MatchRules {
+    fn matches(&self, recorded: &RecordedRequest, request: &HttpRequest) -> bool {
+        let Ok(mut recorded_url) = url::Url::parse(&recorded.url) else {
+            return false;
+        };
+        let mut url = request.url.clone();
+        recorded_url.set_fragment(None);
+        url.set_fragment(None);
+        if !self.query {
+            recorded_url.set_query(None);
+            url.set_query(None);
+        }
+
+        recorded
+            .method
+            .eq_ignore_ascii_case(request.method.as_str())
+            && recorded_url == url
+            && (!self.body
+                || recorded.body.as_ref().and_then(|b| b.to_bytes().ok()) == request.body)
+            && self.headers.iter().all(|name| {
+                recorded.header(name) == request.headers.get(name).and_then(|v| v.to_str().ok())
+            })
+    }
+}
+
+/// The error returned when a [`ReplayHttpClient`] doesn't have a recording
+/// for a request.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("No recorded interaction matches {method} {url}")]
+pub struct NoRecordedInteraction {
+    pub method: Method,
+    pub url: String,
+}
+
+/// An [`HttpClient`] which answers requests from a cassette.
+///
+/// Matching interactions are replayed in the order they were recorded. Once
+/// they have all been used, the last one is repeated.
+#[derive(Debug)]
+pub struct ReplayHttpClient {
+    interactions: Vec<Interaction>,
+    rules: MatchRules,
+    used: Mutex<Vec<bool>>,
+}
+
+impl ReplayHttpClient {
+    pub fn new(cassette: Cassette) -> Self {
+        let used = vec![false; cassette.interactions.len()];
+        ReplayHttpClient {
+            interactions: cassette.interactions,
+            rules: MatchRules::default(),
+            used: Mutex::new(used),
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Cassette::load(path).map(ReplayHttpClient::new)
+    }
+
+    pub fn with_rules(mut self, rules: MatchRules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    fn find(&self, request: &HttpRequest) -> Option<&Interaction> {
+        let mut used = self.used.lock().unwrap();
+        let mut last_match = None;
+
+        for (index, interaction) in self.interactions.iter().enumerate() {
+            if !self.rules.matches(&interaction.request, request) {
+                continue;
+            }
+            if !used[index] {
+                used[index] = true;
+                return Some(interaction);
+            }
+            last_match = Some(interaction);
+        }
+
+        last_match
+    }
+}
+
+impl HttpClient for ReplayHttpClient {
+    fn request(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, Error>> {
+        let result = match self.find(&request) {
+            Some(interaction) => interaction.response.to_response(),
+            None => Err(NoRecordedInteraction {
+                method: request.method.clone(),
+                url: request.url.to_string(),
+            }
+            .into()),
+        };
+
+        Box::pin(async move { result })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use http::header;
+
+    use super::*;
+
+    /// Counts requests and says which one it was.
+    #[derive(Debug, Default)]
+    struct Counter(AtomicUsize);
+
+    impl HttpClient for Counter {
+        fn request(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, Error>> {
+            let n = self.0.fetch_add(1, Ordering::SeqCst);
+            let body = match request.url.path() {
+                "/binary" => vec![0xff, 0x00, n as u8],
+                _ => format!("response {n}").into_bytes(),
+            };
+            let mut headers = HeaderMap::new();
+            headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+
+            Box::pin(async move {
+                Ok(HttpResponse {
+                    body: Some(body),
+                    redirected: false,
+                    status: StatusCode::CREATED,
+                    headers,
+                })
+            })
+        }
+    }
+
+    fn get(url: &str) -> HttpRequest {
+        http::Request::get(url)
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .header("x-tenant", "a")
+            .body(())
+            .unwrap()
+            .into()
+    }
+
+    #[tokio::test]
+    async fn record_and_replay() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("cassette.json");
+        let recorder = RecordingHttpClient::new(Arc::new(Counter::default()), &path);
+
+        for url in [
+            "https://example.com/a",
+            "https://example.com/a",
+            "https://example.com/binary",
+        ] {
+            recorder.request(get(url)).await.unwrap();
+        }
+
+        let cassette = Cassette::load(&path).unwrap();
+        assert_eq!(cassette, recorder.cassette());
+        let request = &cassette.interactions[0].request;
+        assert!(request.header(&header::AUTHORIZATION).is_none());
+        assert_eq!(
+            request.header(&HeaderName::from_static("x-tenant")),
+            Some("a")
+        );
+
+        let replay = ReplayHttpClient::from_file(&path).unwrap();
+        let mut bodies = Vec::new();
+        for url in [
+            "https://example.com/a",
+            "https://example.com/a",
+            // The last match is repeated once the recordings are used up
+            "https://example.com/a",
+            "https://example.com/binary",
+        ] {
+            let response = replay.request(get(url)).await.unwrap();
+            assert_eq!(response.status, StatusCode::CREATED);
+            assert_eq!(response.headers[header::CONTENT_TYPE], "text/plain");
+            bodies.push(response.body.unwrap());
+        }
+
+        assert_eq!(
+            bodies,
+            [
+                b"response 0".to_vec(),
+                b"response 1".to_vec(),
+                b"response 1".to_vec(),
+                vec![0xff, 0x00, 2],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_requests_are_errors() {
+        let replay = ReplayHttpClient::new(Cassette::default());
+
+        let error = replay
+            .request(get("https://example.com/missing"))
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error.downcast::<NoRecordedInteraction>().unwrap(),
+            NoRecordedInteraction {
+                method: Method::GET,
+                url: "https://example.com/missing".to_string(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn configurable_matching() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("cassette.json");
+        let recorder = RecordingHttpClient::new(Arc::new(Counter::default()), path);
+        recorder
+            .request(get("https://example.com/search?q=1"))
+            .await
+            .unwrap();
+        let cassette = recorder.cassette();
+
+        let strict = ReplayHttpClient::new(cassette.clone());
+        assert!(strict
+            .request(get("https://example.com/search?q=2"))
+            .await
+            .is_err());
+
+        let rules = MatchRules {
+            query: false,
+            headers: vec![HeaderName::from_static("x-tenant")],
+            ..Default::default()
+        };
+        let relaxed = ReplayHttpClient::new(cassette).with_rules(rules);
+        relaxed
+            .request(get("https://example.com/search?q=2"))
+            .await
+            .unwrap();
+        let other_tenant: HttpRequest = http::Request::get("https://example.com/search")
+            .header("x-tenant", "b")
+            .body(())
+            .unwrap()
+            .into();
+        assert!(relaxed.request(other_tenant).await.is_err());
+    }
+}
@@ -4,11 +4,16 @@ use futures::future::BoxFuture;
 use http::{HeaderMap, Method, StatusCode};
 use url::Url;
 
+use super::HttpHostRule;
+
 /// Defines http client permissions.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct HttpClientCapabilityV1 {
     pub allow_all: bool,
     pub allowed_hosts: BTreeSet<String>,
+    /// Fine-grained rules for particular hosts, enforced by
+    /// [`HttpPolicyClient`](super::HttpPolicyClient).
+    pub rules: Vec<HttpHostRule>,
 }
 
 impl HttpClientCapabilityV1 {
@@ -16,6 +21,7 @@ impl HttpClientCapabilityV1 {
         Self {
             allow_all: false,
             allowed_hosts: Default::default(),
+            rules: Vec::new(),
         }
     }
 
@@ -23,24 +29,36 @@ impl HttpClientCapabilityV1 {
         Self {
             allow_all: true,
             allowed_hosts: Default::default(),
+            rules: Vec::new(),
         }
     }
 
     pub fn is_deny_all(&self) -> bool {
-        !self.allow_all && self.allowed_hosts.is_empty()
+        !self.allow_all && self.allowed_hosts.is_empty() && self.rules.is_empty()
     }
 
     pub fn can_access_domain(&self, domain: &str) -> bool {
-        self.allow_all || self.allowed_hosts.contains(domain)
+        self.allow_all || self.allowed_hosts.contains(domain) || self.rule_for(domain).is_some()
+    }
+
+    /// The rule that applies to requests for `host`, preferring exact matches
+    /// over the most specific wildcard.
+    pub fn rule_for(&self, host: &str) -> Option<&HttpHostRule> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.matches(host))
+            .max_by_key(|rule| (!rule.host.starts_with("*."), rule.host.len()))
     }
 
     pub fn update(&mut self, other: HttpClientCapabilityV1) {
         let HttpClientCapabilityV1 {
             allow_all,
             allowed_hosts,
+            rules,
         } = other;
         self.allow_all |= allow_all;
         self.allowed_hosts.extend(allowed_hosts);
+        self.rules.extend(rules);
     }
 }
 
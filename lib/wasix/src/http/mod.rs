@@ -1,4 +1,6 @@
+pub mod cassette;
 mod client;
+mod policy;
 
 #[cfg(feature = "host-reqwest")]
 pub mod reqwest;
@@ -10,6 +12,7 @@ mod web_http_client;
 pub use self::web_http_client::WebHttpClient;
 
 pub use self::client::*;
+pub use self::policy::{HttpHostRule, HttpPolicyClient, PolicyViolation, RateLimit};
 
 pub(crate) const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "-", env!("CARGO_PKG_VERSION"));
 
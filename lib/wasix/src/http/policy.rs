@@ -0,0 +1,443 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use futures::future::BoxFuture;
+use http::{header::HeaderName, HeaderValue, Method};
+
+use super::{DynHttpClient, HttpClient, HttpClientCapabilityV1, HttpRequest, HttpResponse};
+
+/// Restrictions on the requests an instance may send to a particular host.
+///
+/// Empty lists don't restrict anything, so a rule which only names a host
+/// allows any request to that host.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct HttpHostRule {
+    /// The host this rule applies to, either an exact name
+    /// (`api.example.com`) or a wildcard matching any subdomain
+    /// (`*.example.com`).
+    pub host: String,
+    /// The methods requests may use.
+    pub methods: Vec<Method>,
+    /// Requests must be for a path starting with one of these prefixes.
+    pub path_prefixes: Vec<String>,
+    /// Headers added to every request, replacing whatever the instance set.
+    pub inject_headers: Vec<(HeaderName, HeaderValue)>,
+    /// Headers removed from every request before it is sent.
+    pub strip_headers: Vec<HeaderName>,
+    /// The maximum number of requests to this host within a period of time.
+    pub rate_limit: Option<RateLimit>,
+    /// The maximum size of request and response bodies, in bytes.
+    pub max_body_size: Option<u64>,
+    /// How long a request may take before it is abandoned.
+    pub timeout: Option<Duration>,
+}
+
+impl HttpHostRule {
+    pub fn new(host: impl Into<String>) -> Self {
+        HttpHostRule {
+            host: host.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Does this rule apply to `host`?
+    pub fn matches(&self, host: &str) -> bool {
+        match self.host.strip_prefix("*.") {
+            Some(domain) => host
+                .strip_suffix(domain)
+                .is_some_and(|subdomain| subdomain.len() > 1 && subdomain.ends_with('.')),
+            None => self.host.eq_ignore_ascii_case(host),
+        }
+    }
+}
+
+/// Allow at most `requests` requests in every `period`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub period: Duration,
+}
+
+/// The reason an [`HttpPolicyClient`] refused to send a request or return its
+/// response.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PolicyViolation {
+    #[error("Requests to \"{host}\" are not allowed")]
+    HostNotAllowed { host: String },
+    #[error("{method} requests to \"{host}\" are not allowed")]
+    MethodNotAllowed { host: String, method: Method },
+    #[error("Requests for \"{path}\" on \"{host}\" are not allowed")]
+    PathNotAllowed { host: String, path: String },
+    #[error("The request body is {size} bytes, but \"{host}\" only allows {limit}")]
+    RequestTooLarge { host: String, size: u64, limit: u64 },
+    #[error("The response body is {size} bytes, but \"{host}\" only allows {limit}")]
+    ResponseTooLarge { host: String, size: u64, limit: u64 },
+    #[error("Too many requests to \"{host}\"")]
+    RateLimited { host: String },
+    #[error("The request to \"{host}\" timed out after {timeout:?}")]
+    TimedOut { host: String, timeout: Duration },
+}
+
+/// An [`HttpClient`] which only sends requests that are allowed by a
+/// [`HttpClientCapabilityV1`], applying the matching [`HttpHostRule`] to
+/// each of them.
+///
+/// Requests to hosts without a rule are only sent when the capability's
+/// allowlist lets them through. Refused requests fail with a
+/// [`PolicyViolation`].
+#[derive(Debug)]
+pub struct HttpPolicyClient {
+    inner: DynHttpClient,
+    capability: HttpClientCapabilityV1,
+    /// The requests seen by each rule with a rate limit, keyed by its host.
+    windows: Mutex<HashMap<String, Window>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    started: Instant,
+    requests: u32,
+}
+
+impl HttpPolicyClient {
+    pub fn new(inner: DynHttpClient, capability: HttpClientCapabilityV1) -> Self {
+        HttpPolicyClient {
+            inner,
+            capability,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Check a request against the policy, rewriting its headers as
+    /// required.
+    fn authorize(
+        &self,
+        request: &mut HttpRequest,
+    ) -> Result<Option<&HttpHostRule>, PolicyViolation> {
+        let host = request.url.host_str().unwrap_or_default().to_string();
+
+        let Some(rule) = self.capability.rule_for(&host) else {
+            if self.capability.can_access_domain(&host) {
+                return Ok(None);
+            }
+            return Err(PolicyViolation::HostNotAllowed { host });
+        };
+
+        if !rule.methods.is_empty() && !rule.methods.contains(&request.method) {
+            return Err(PolicyViolation::MethodNotAllowed {
+                host,
+                method: request.method.clone(),
+            });
+        }
+
+        let path = request.url.path();
+        if !rule.path_prefixes.is_empty()
+            && !rule
+                .path_prefixes
+                .iter()
+                .any(|prefix| path.starts_with(prefix.as_str()))
+        {
+            return Err(PolicyViolation::PathNotAllowed {
+                host,
+                path: path.to_string(),
+            });
+        }
+
+        if let Some(limit) = rule.max_body_size {
+            let size = request.body.as_ref().map_or(0, |body| body.len() as u64);
+            if size > limit {
+                return Err(PolicyViolation::RequestTooLarge { host, size, limit });
+            }
+        }
+
+        if let Some(rate_limit) = rule.rate_limit {
+            if !self.record_request(rule, rate_limit) {
+                return Err(PolicyViolation::RateLimited { host });
+            }
+        }
+
+        for name in &rule.strip_headers {
+            request.headers.remove(name);
+        }
+        for (name, value) in &rule.inject_headers {
+            request.headers.insert(name.clone(), value.clone());
+        }
+
+        Ok(Some(rule))
+    }
+
+    /// Count a request against a rule's rate limit, returning `false` if the
+    /// limit has already been reached.
+    fn record_request(&self, rule: &HttpHostRule, rate_limit: RateLimit) -> bool {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        let window = windows.entry(rule.host.clone()).or_insert(Window {
+            started: now,
+            requests: 0,
+        });
+
+        if now.duration_since(window.started) >= rate_limit.period {
+            *window = Window {
+                started: now,
+                requests: 0,
+            };
+        }
+
+        if window.requests >= rate_limit.requests {
+            return false;
+        }
+        window.requests += 1;
+        true
+    }
+}
+
+impl HttpClient for HttpPolicyClient {
+    fn request(
+        &self,
+        mut request: HttpRequest,
+    ) -> BoxFuture<'_, Result<HttpResponse, anyhow::Error>> {
+        Box::pin(async move {
+            let host = request.url.host_str().unwrap_or_default().to_string();
+            let Some(rule) = self.authorize(&mut request)? else {
+                return self.inner.request(request).await;
+            };
+
+            let response = match rule.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, self.inner.request(request))
+                    .await
+                    .map_err(|_| PolicyViolation::TimedOut {
+                        host: host.clone(),
+                        timeout,
+                    })??,
+                None => self.inner.request(request).await?,
+            };
+
+            if let Some(limit) = rule.max_body_size {
+                let size = response.body.as_ref().map_or(0, |body| body.len() as u64);
+                if size > limit {
+                    return Err(PolicyViolation::ResponseTooLarge { host, size, limit }.into());
+                }
+            }
+
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use http::{header, HeaderMap, StatusCode};
+
+    use super::*;
+
+    /// Records the requests it was asked to send and echoes their body.
+    #[derive(Debug, Default)]
+    struct Recorder {
+        requests: Mutex<Vec<HttpRequest>>,
+    }
+
+    impl HttpClient for Recorder {
+        fn request(
+            &self,
+            request: HttpRequest,
+        ) -> BoxFuture<'_, Result<HttpResponse, anyhow::Error>> {
+            let body = request.body.clone();
+            self.requests.lock().unwrap().push(request);
+            Box::pin(async move {
+                Ok(HttpResponse {
+                    body,
+                    redirected: false,
+                    status: StatusCode::OK,
+                    headers: HeaderMap::new(),
+                })
+            })
+        }
+    }
+
+    fn client(rules: Vec<HttpHostRule>) -> (HttpPolicyClient, Arc<Recorder>) {
+        let recorder = Arc::new(Recorder::default());
+        let mut capability = HttpClientCapabilityV1::new();
+        capability.rules = rules;
+        let client = HttpPolicyClient::new(recorder.clone(), capability);
+        (client, recorder)
+    }
+
+    fn request(method: Method, url: &str, body: Option<&str>) -> HttpRequest {
+        http::Request::builder()
+            .method(method)
+            .uri(url)
+            .header(header::COOKIE, "session=secret")
+            .body(body.map(|b| b.as_bytes().to_vec()))
+            .unwrap()
+            .into()
+    }
+
+    fn violation(result: Result<HttpResponse, anyhow::Error>) -> PolicyViolation {
+        result
+            .unwrap_err()
+            .downcast::<PolicyViolation>()
+            .expect("a policy violation")
+    }
+
+    #[test]
+    fn match_hosts() {
+        let exact = HttpHostRule::new("api.example.com");
+        assert!(exact.matches("api.example.com"));
+        assert!(!exact.matches("example.com"));
+
+        let wildcard = HttpHostRule::new("*.example.com");
+        assert!(wildcard.matches("api.example.com"));
+        assert!(wildcard.matches("a.b.example.com"));
+        assert!(!wildcard.matches("example.com"));
+        assert!(!wildcard.matches("badexample.com"));
+    }
+
+    #[tokio::test]
+    async fn hosts_without_a_rule_use_the_allowlist() {
+        let (client, recorder) = client(vec![HttpHostRule::new("api.example.com")]);
+
+        client
+            .request(request(Method::GET, "https://api.example.com/", None))
+            .await
+            .unwrap();
+        let error = violation(
+            client
+                .request(request(Method::GET, "https://evil.example.org/", None))
+                .await,
+        );
+
+        assert_eq!(
+            error,
+            PolicyViolation::HostNotAllowed {
+                host: "evil.example.org".to_string()
+            }
+        );
+        assert_eq!(recorder.requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn restrict_methods_and_paths() {
+        let rule = HttpHostRule {
+            methods: vec![Method::GET],
+            path_prefixes: vec!["/v1/".to_string()],
+            ..HttpHostRule::new("api.example.com")
+        };
+        let (client, _) = client(vec![rule]);
+
+        client
+            .request(request(
+                Method::GET,
+                "https://api.example.com/v1/users",
+                None,
+            ))
+            .await
+            .unwrap();
+        let error = violation(
+            client
+                .request(request(
+                    Method::POST,
+                    "https://api.example.com/v1/users",
+                    None,
+                ))
+                .await,
+        );
+        assert!(matches!(error, PolicyViolation::MethodNotAllowed { .. }));
+        let error = violation(
+            client
+                .request(request(Method::GET, "https://api.example.com/admin", None))
+                .await,
+        );
+        assert!(matches!(error, PolicyViolation::PathNotAllowed { .. }));
+    }
+
+    #[tokio::test]
+    async fn rewrite_headers() {
+        let rule = HttpHostRule {
+            inject_headers: vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_static("Bearer token"),
+            )],
+            strip_headers: vec![header::COOKIE],
+            ..HttpHostRule::new("*.example.com")
+        };
+        let (client, recorder) = client(vec![rule]);
+
+        client
+            .request(request(Method::GET, "https://api.example.com/", None))
+            .await
+            .unwrap();
+
+        let requests = recorder.requests.lock().unwrap();
+        assert_eq!(requests[0].headers[header::AUTHORIZATION], "Bearer token");
+        assert!(!requests[0].headers.contains_key(header::COOKIE));
+    }
+
+    #[tokio::test]
+    async fn limit_body_sizes() {
+        let rule = HttpHostRule {
+            max_body_size: Some(4),
+            ..HttpHostRule::new("api.example.com")
+        };
+        let (client, recorder) = client(vec![rule]);
+
+        client
+            .request(request(
+                Method::POST,
+                "https://api.example.com/",
+                Some("tiny"),
+            ))
+            .await
+            .unwrap();
+        let error = violation(
+            client
+                .request(request(
+                    Method::POST,
+                    "https://api.example.com/",
+                    Some("too big"),
+                ))
+                .await,
+        );
+
+        assert_eq!(
+            error,
+            PolicyViolation::RequestTooLarge {
+                host: "api.example.com".to_string(),
+                size: 7,
+                limit: 4,
+            }
+        );
+        assert_eq!(recorder.requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn rate_limit_requests() {
+        let rule = HttpHostRule {
+            rate_limit: Some(RateLimit {
+                requests: 2,
+                period: Duration::from_secs(3600),
+            }),
+            ..HttpHostRule::new("api.example.com")
+        };
+        let (client, recorder) = client(vec![rule]);
+
+        for _ in 0..2 {
+            client
+                .request(request(Method::GET, "https://api.example.com/", None))
+                .await
+                .unwrap();
+        }
+        let error = violation(
+            client
+                .request(request(Method::GET, "https://api.example.com/", None))
+                .await,
+        );
+
+        assert!(matches!(error, PolicyViolation::RateLimited { .. }));
+        assert_eq!(recorder.requests.lock().unwrap().len(), 2);
+    }
+}
@@ -51,6 +51,8 @@ pub mod journal;
 mod rewind;
 pub mod runners;
 pub mod runtime;
+#[cfg(feature = "snapshot-init")]
+pub mod snapshot_init;
 mod state;
 mod syscalls;
 mod utils;
@@ -549,6 +551,13 @@ fn wasix_exports_32(mut store: &mut impl AsStoreMut, env: &FunctionEnv<WasiEnv>)
         "proc_fork" => Function::new_typed_with_env(&mut store, env, proc_fork::<Memory32>),
         "proc_join" => Function::new_typed_with_env(&mut store, env, proc_join::<Memory32>),
         "proc_signal" => Function::new_typed_with_env(&mut store, env, proc_signal::<Memory32>),
+        "proc_sigaction" => Function::new_typed_with_env(&mut store, env, proc_sigaction::<Memory32>),
+        "proc_sigqueue" => Function::new_typed_with_env(&mut store, env, proc_sigqueue),
+        "proc_signal_group" => Function::new_typed_with_env(&mut store, env, proc_signal_group),
+        "proc_getpgid" => Function::new_typed_with_env(&mut store, env, proc_getpgid::<Memory32>),
+        "proc_setpgid" => Function::new_typed_with_env(&mut store, env, proc_setpgid),
+        "proc_getsid" => Function::new_typed_with_env(&mut store, env, proc_getsid::<Memory32>),
+        "proc_setsid" => Function::new_typed_with_env(&mut store, env, proc_setsid::<Memory32>),
         "proc_exec" => Function::new_typed_with_env(&mut store, env, proc_exec::<Memory32>),
         "proc_exec2" => Function::new_typed_with_env(&mut store, env, proc_exec2::<Memory32>),
         "proc_raise" => Function::new_typed_with_env(&mut store, env, proc_raise),
@@ -560,6 +569,12 @@ fn wasix_exports_32(mut store: &mut impl AsStoreMut, env: &FunctionEnv<WasiEnv>)
         "random_get" => Function::new_typed_with_env(&mut store, env, random_get::<Memory32>),
         "tty_get" => Function::new_typed_with_env(&mut store, env, tty_get::<Memory32>),
         "tty_set" => Function::new_typed_with_env(&mut store, env, tty_set::<Memory32>),
+        "tty_foreground_get" => Function::new_typed_with_env(&mut store, env, tty_foreground_get::<Memory32>),
+        "tty_foreground_set" => Function::new_typed_with_env(&mut store, env, tty_foreground_set),
+        "pty_open" => Function::new_typed_with_env(&mut store, env, pty_open::<Memory32>),
+        "pty_get" => Function::new_typed_with_env(&mut store, env, pty_get::<Memory32>),
+        "pty_set" => Function::new_typed_with_env(&mut store, env, pty_set::<Memory32>),
+        "pty_foreground_set" => Function::new_typed_with_env(&mut store, env, pty_foreground_set),
         "getcwd" => Function::new_typed_with_env(&mut store, env, getcwd::<Memory32>),
         "chdir" => Function::new_typed_with_env(&mut store, env, chdir::<Memory32>),
         "callback_signal" => Function::new_typed_with_env(&mut store, env, callback_signal::<Memory32>),
@@ -568,10 +583,16 @@ fn wasix_exports_32(mut store: &mut impl AsStoreMut, env: &FunctionEnv<WasiEnv>)
         "thread_sleep" => Function::new_typed_with_env(&mut store, env, thread_sleep::<Memory32>),
         "thread_id" => Function::new_typed_with_env(&mut store, env, thread_id::<Memory32>),
         "thread_signal" => Function::new_typed_with_env(&mut store, env, thread_signal),
+        "thread_sigmask" => Function::new_typed_with_env(&mut store, env, thread_sigmask::<Memory32>),
+        "thread_sigpending" => Function::new_typed_with_env(&mut store, env, thread_sigpending::<Memory32>),
         "thread_join" => Function::new_typed_with_env(&mut store, env, thread_join::<Memory32>),
         "thread_parallelism" => Function::new_typed_with_env(&mut store, env, thread_parallelism::<Memory32>),
         "thread_exit" => Function::new_typed_with_env(&mut store, env, thread_exit),
         "sched_yield" => Function::new_typed_with_env(&mut store, env, sched_yield::<Memory32>),
+        "shm_map" => Function::new_typed_with_env(&mut store, env, shm_map::<Memory32>),
+        "shm_open" => Function::new_typed_with_env(&mut store, env, shm_open::<Memory32>),
+        "shm_unlink" => Function::new_typed_with_env(&mut store, env, shm_unlink::<Memory32>),
+        "shm_unmap" => Function::new_typed_with_env(&mut store, env, shm_unmap::<Memory32>),
         "stack_checkpoint" => Function::new_typed_with_env(&mut store, env, stack_checkpoint::<Memory32>),
         "stack_restore" => Function::new_typed_with_env(&mut store, env, stack_restore::<Memory32>),
         "futex_wait" => Function::new_typed_with_env(&mut store, env, futex_wait::<Memory32>),
@@ -593,6 +614,8 @@ fn wasix_exports_32(mut store: &mut impl AsStoreMut, env: &FunctionEnv<WasiEnv>)
         "sock_status" => Function::new_typed_with_env(&mut store, env, sock_status::<Memory32>),
         "sock_addr_local" => Function::new_typed_with_env(&mut store, env, sock_addr_local::<Memory32>),
         "sock_addr_peer" => Function::new_typed_with_env(&mut store, env, sock_addr_peer::<Memory32>),
+        "sock_addr_local_unix" => Function::new_typed_with_env(&mut store, env, sock_addr_local_unix::<Memory32>),
+        "sock_addr_peer_unix" => Function::new_typed_with_env(&mut store, env, sock_addr_peer_unix::<Memory32>),
         "sock_open" => Function::new_typed_with_env(&mut store, env, sock_open::<Memory32>),
         "sock_pair" => Function::new_typed_with_env(&mut store, env, sock_pair::<Memory32>),
         "sock_set_opt_flag" => Function::new_typed_with_env(&mut store, env, sock_set_opt_flag),
@@ -606,14 +629,20 @@ fn wasix_exports_32(mut store: &mut impl AsStoreMut, env: &FunctionEnv<WasiEnv>)
         "sock_join_multicast_v6" => Function::new_typed_with_env(&mut store, env, sock_join_multicast_v6::<Memory32>),
         "sock_leave_multicast_v6" => Function::new_typed_with_env(&mut store, env, sock_leave_multicast_v6::<Memory32>),
         "sock_bind" => Function::new_typed_with_env(&mut store, env, sock_bind::<Memory32>),
+        "sock_bind_unix" => Function::new_typed_with_env(&mut store, env, sock_bind_unix::<Memory32>),
         "sock_listen" => Function::new_typed_with_env(&mut store, env, sock_listen::<Memory32>),
         "sock_accept" => Function::new_typed_with_env(&mut store, env, sock_accept_v2::<Memory32>),
         "sock_accept_v2" => Function::new_typed_with_env(&mut store, env, sock_accept_v2::<Memory32>),
         "sock_connect" => Function::new_typed_with_env(&mut store, env, sock_connect::<Memory32>),
+        "sock_connect_unix" => Function::new_typed_with_env(&mut store, env, sock_connect_unix::<Memory32>),
         "sock_recv" => Function::new_typed_with_env(&mut store, env, sock_recv::<Memory32>),
         "sock_recv_from" => Function::new_typed_with_env(&mut store, env, sock_recv_from::<Memory32>),
+        "sock_recv_from_unix" => Function::new_typed_with_env(&mut store, env, sock_recv_from_unix::<Memory32>),
+        "sock_recv_fds" => Function::new_typed_with_env(&mut store, env, sock_recv_fds::<Memory32>),
         "sock_send" => Function::new_typed_with_env(&mut store, env, sock_send::<Memory32>),
         "sock_send_to" => Function::new_typed_with_env(&mut store, env, sock_send_to::<Memory32>),
+        "sock_send_to_unix" => Function::new_typed_with_env(&mut store, env, sock_send_to_unix::<Memory32>),
+        "sock_send_fds" => Function::new_typed_with_env(&mut store, env, sock_send_fds::<Memory32>),
         "sock_send_file" => Function::new_typed_with_env(&mut store, env, sock_send_file::<Memory32>),
         "sock_shutdown" => Function::new_typed_with_env(&mut store, env, sock_shutdown),
         "resolve" => Function::new_typed_with_env(&mut store, env, resolve::<Memory32>),
@@ -677,6 +706,13 @@ fn wasix_exports_64(mut store: &mut impl AsStoreMut, env: &FunctionEnv<WasiEnv>)
         "proc_fork" => Function::new_typed_with_env(&mut store, env, proc_fork::<Memory64>),
         "proc_join" => Function::new_typed_with_env(&mut store, env, proc_join::<Memory64>),
         "proc_signal" => Function::new_typed_with_env(&mut store, env, proc_signal::<Memory64>),
+        "proc_sigaction" => Function::new_typed_with_env(&mut store, env, proc_sigaction::<Memory64>),
+        "proc_sigqueue" => Function::new_typed_with_env(&mut store, env, proc_sigqueue),
+        "proc_signal_group" => Function::new_typed_with_env(&mut store, env, proc_signal_group),
+        "proc_getpgid" => Function::new_typed_with_env(&mut store, env, proc_getpgid::<Memory64>),
+        "proc_setpgid" => Function::new_typed_with_env(&mut store, env, proc_setpgid),
+        "proc_getsid" => Function::new_typed_with_env(&mut store, env, proc_getsid::<Memory64>),
+        "proc_setsid" => Function::new_typed_with_env(&mut store, env, proc_setsid::<Memory64>),
         "proc_exec" => Function::new_typed_with_env(&mut store, env, proc_exec::<Memory64>),
         "proc_exec2" => Function::new_typed_with_env(&mut store, env, proc_exec2::<Memory64>),
         "proc_raise" => Function::new_typed_with_env(&mut store, env, proc_raise),
@@ -688,6 +724,12 @@ fn wasix_exports_64(mut store: &mut impl AsStoreMut, env: &FunctionEnv<WasiEnv>)
         "random_get" => Function::new_typed_with_env(&mut store, env, random_get::<Memory64>),
         "tty_get" => Function::new_typed_with_env(&mut store, env, tty_get::<Memory64>),
         "tty_set" => Function::new_typed_with_env(&mut store, env, tty_set::<Memory64>),
+        "tty_foreground_get" => Function::new_typed_with_env(&mut store, env, tty_foreground_get::<Memory64>),
+        "tty_foreground_set" => Function::new_typed_with_env(&mut store, env, tty_foreground_set),
+        "pty_open" => Function::new_typed_with_env(&mut store, env, pty_open::<Memory64>),
+        "pty_get" => Function::new_typed_with_env(&mut store, env, pty_get::<Memory64>),
+        "pty_set" => Function::new_typed_with_env(&mut store, env, pty_set::<Memory64>),
+        "pty_foreground_set" => Function::new_typed_with_env(&mut store, env, pty_foreground_set),
         "getcwd" => Function::new_typed_with_env(&mut store, env, getcwd::<Memory64>),
         "chdir" => Function::new_typed_with_env(&mut store, env, chdir::<Memory64>),
         "callback_signal" => Function::new_typed_with_env(&mut store, env, callback_signal::<Memory64>),
@@ -696,10 +738,16 @@ fn wasix_exports_64(mut store: &mut impl AsStoreMut, env: &FunctionEnv<WasiEnv>)
         "thread_sleep" => Function::new_typed_with_env(&mut store, env, thread_sleep::<Memory64>),
         "thread_id" => Function::new_typed_with_env(&mut store, env, thread_id::<Memory64>),
         "thread_signal" => Function::new_typed_with_env(&mut store, env, thread_signal),
+        "thread_sigmask" => Function::new_typed_with_env(&mut store, env, thread_sigmask::<Memory64>),
+        "thread_sigpending" => Function::new_typed_with_env(&mut store, env, thread_sigpending::<Memory64>),
         "thread_join" => Function::new_typed_with_env(&mut store, env, thread_join::<Memory64>),
         "thread_parallelism" => Function::new_typed_with_env(&mut store, env, thread_parallelism::<Memory64>),
         "thread_exit" => Function::new_typed_with_env(&mut store, env, thread_exit),
         "sched_yield" => Function::new_typed_with_env(&mut store, env, sched_yield::<Memory64>),
+        "shm_map" => Function::new_typed_with_env(&mut store, env, shm_map::<Memory64>),
+        "shm_open" => Function::new_typed_with_env(&mut store, env, shm_open::<Memory64>),
+        "shm_unlink" => Function::new_typed_with_env(&mut store, env, shm_unlink::<Memory64>),
+        "shm_unmap" => Function::new_typed_with_env(&mut store, env, shm_unmap::<Memory64>),
         "stack_checkpoint" => Function::new_typed_with_env(&mut store, env, stack_checkpoint::<Memory64>),
         "stack_restore" => Function::new_typed_with_env(&mut store, env, stack_restore::<Memory64>),
         "futex_wait" => Function::new_typed_with_env(&mut store, env, futex_wait::<Memory64>),
@@ -721,6 +769,8 @@ fn wasix_exports_64(mut store: &mut impl AsStoreMut, env: &FunctionEnv<WasiEnv>)
         "sock_status" => Function::new_typed_with_env(&mut store, env, sock_status::<Memory64>),
         "sock_addr_local" => Function::new_typed_with_env(&mut store, env, sock_addr_local::<Memory64>),
         "sock_addr_peer" => Function::new_typed_with_env(&mut store, env, sock_addr_peer::<Memory64>),
+        "sock_addr_local_unix" => Function::new_typed_with_env(&mut store, env, sock_addr_local_unix::<Memory64>),
+        "sock_addr_peer_unix" => Function::new_typed_with_env(&mut store, env, sock_addr_peer_unix::<Memory64>),
         "sock_open" => Function::new_typed_with_env(&mut store, env, sock_open::<Memory64>),
         "sock_pair" => Function::new_typed_with_env(&mut store, env, sock_pair::<Memory64>),
         "sock_set_opt_flag" => Function::new_typed_with_env(&mut store, env, sock_set_opt_flag),
@@ -734,14 +784,20 @@ fn wasix_exports_64(mut store: &mut impl AsStoreMut, env: &FunctionEnv<WasiEnv>)
         "sock_join_multicast_v6" => Function::new_typed_with_env(&mut store, env, sock_join_multicast_v6::<Memory64>),
         "sock_leave_multicast_v6" => Function::new_typed_with_env(&mut store, env, sock_leave_multicast_v6::<Memory64>),
         "sock_bind" => Function::new_typed_with_env(&mut store, env, sock_bind::<Memory64>),
+        "sock_bind_unix" => Function::new_typed_with_env(&mut store, env, sock_bind_unix::<Memory64>),
         "sock_listen" => Function::new_typed_with_env(&mut store, env, sock_listen::<Memory64>),
         "sock_accept" => Function::new_typed_with_env(&mut store, env, sock_accept_v2::<Memory64>),
         "sock_accept_v2" => Function::new_typed_with_env(&mut store, env, sock_accept_v2::<Memory64>),
         "sock_connect" => Function::new_typed_with_env(&mut store, env, sock_connect::<Memory64>),
+        "sock_connect_unix" => Function::new_typed_with_env(&mut store, env, sock_connect_unix::<Memory64>),
         "sock_recv" => Function::new_typed_with_env(&mut store, env, sock_recv::<Memory64>),
         "sock_recv_from" => Function::new_typed_with_env(&mut store, env, sock_recv_from::<Memory64>),
+        "sock_recv_from_unix" => Function::new_typed_with_env(&mut store, env, sock_recv_from_unix::<Memory64>),
+        "sock_recv_fds" => Function::new_typed_with_env(&mut store, env, sock_recv_fds::<Memory64>),
         "sock_send" => Function::new_typed_with_env(&mut store, env, sock_send::<Memory64>),
         "sock_send_to" => Function::new_typed_with_env(&mut store, env, sock_send_to::<Memory64>),
+        "sock_send_to_unix" => Function::new_typed_with_env(&mut store, env, sock_send_to_unix::<Memory64>),
+        "sock_send_fds" => Function::new_typed_with_env(&mut store, env, sock_send_fds::<Memory64>),
         "sock_send_file" => Function::new_typed_with_env(&mut store, env, sock_send_file::<Memory64>),
         "sock_shutdown" => Function::new_typed_with_env(&mut store, env, sock_shutdown),
         "resolve" => Function::new_typed_with_env(&mut store, env, resolve::<Memory64>),
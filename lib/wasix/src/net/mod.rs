@@ -17,6 +17,7 @@ use wasmer_wasix_types::{
 };
 
 pub mod socket;
+pub mod unix;
 
 #[allow(dead_code)]
 pub(crate) fn read_ip<M: MemorySize>(
@@ -187,6 +188,29 @@ pub(crate) fn read_ip_port<M: MemorySize>(
     })
 }
 
+/// Writes the path of a unix socket to the guest. `len` holds the size of
+/// the buffer on input and the length of the path on output, the path is
+/// truncated when it doesn't fit (like `getsockname`).
+pub(crate) fn write_unix_path<M: MemorySize>(
+    memory: &MemoryView,
+    buf: WasmPtr<u8, M>,
+    len: WasmPtr<M::Offset, M>,
+    path: Option<&str>,
+) -> Result<(), Errno> {
+    let path = path.unwrap_or_default().as_bytes();
+    let buf_len: u64 = len.read(memory).map_err(crate::mem_error_to_wasi)?.into();
+    let written = path.len().min(buf_len.try_into().unwrap_or(usize::MAX));
+    let written_len: M::Offset = written.try_into().map_err(|_| Errno::Overflow)?;
+    buf.slice(memory, written_len)
+        .and_then(|slice| slice.write_slice(&path[..written]))
+        .map_err(crate::mem_error_to_wasi)?;
+
+    let path_len: M::Offset = path.len().try_into().map_err(|_| Errno::Overflow)?;
+    len.write(memory, path_len)
+        .map_err(crate::mem_error_to_wasi)?;
+    Ok(())
+}
+
 #[allow(dead_code)]
 pub(crate) fn write_ip_port<M: MemorySize>(
     memory: &MemoryView,
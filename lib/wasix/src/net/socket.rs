@@ -13,13 +13,15 @@ use std::{
 use serde_derive::{Deserialize, Serialize};
 use virtual_mio::InterestHandler;
 use virtual_net::{
-    net_error_into_io_err, NetworkError, VirtualIcmpSocket, VirtualNetworking, VirtualRawSocket,
-    VirtualTcpListener, VirtualTcpSocket, VirtualUdpSocket,
+    net_error_into_io_err, NetworkError, VirtualConnectedSocket, VirtualIcmpSocket,
+    VirtualIoSource, VirtualNetworking, VirtualRawSocket, VirtualSocket, VirtualTcpListener,
+    VirtualTcpSocket, VirtualUdpSocket,
 };
 use wasmer_types::MemorySize;
 use wasmer_wasix_types::wasi::{Addressfamily, Errno, Rights, SockProto, Sockoption, Socktype};
 
-use crate::{net::net_error_into_wasi_err, VirtualTaskManager};
+use super::unix::{UnixDatagram, UnixListener, UnixSocketNamespace, UnixStream, UNIX_SOCKET_ADDR};
+use crate::{fs::Fd, net::net_error_into_wasi_err, VirtualTaskManager};
 
 #[derive(Debug)]
 #[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
@@ -82,6 +84,9 @@ pub enum InodeSocketKind {
         multicast_ttl: u32,
         is_dead: bool,
     },
+    UnixListener(UnixListener),
+    UnixStream(UnixStream),
+    UnixDatagram(UnixDatagram),
 }
 
 pub enum WasiSocketOption {
@@ -353,7 +358,7 @@ impl InodeSocket {
         &self,
         tasks: &dyn VirtualTaskManager,
         net: &dyn VirtualNetworking,
-        _backlog: usize,
+        backlog: usize,
     ) -> Result<Option<InodeSocket>, Errno> {
         let timeout = self
             .opt_time(TimeType::AcceptTimeout)
@@ -430,6 +435,18 @@ impl InodeSocket {
                     tracing::warn!("wasi[?]::sock_listen - failed - not supported(udp-socket)");
                     return Err(Errno::Notsup);
                 }
+                InodeSocketKind::UnixListener(listener) => {
+                    listener.listen(backlog);
+                    return Ok(None);
+                }
+                InodeSocketKind::UnixStream(_) => {
+                    tracing::warn!("wasi[?]::sock_listen - failed - not supported(unix-stream)");
+                    return Err(Errno::Notsup);
+                }
+                InodeSocketKind::UnixDatagram(_) => {
+                    tracing::warn!("wasi[?]::sock_listen - failed - not supported(unix-datagram)");
+                    return Err(Errno::Notsup);
+                }
             }
         };
 
@@ -450,7 +467,7 @@ impl InodeSocket {
         tasks: &dyn VirtualTaskManager,
         nonblocking: bool,
         timeout: Option<Duration>,
-    ) -> Result<(Box<dyn VirtualTcpSocket + Sync>, SocketAddr), Errno> {
+    ) -> Result<(InodeSocketKind, SocketAddr), Errno> {
         struct SocketAccepter<'a> {
             sock: &'a InodeSocket,
             nonblocking: bool,
@@ -465,7 +482,7 @@ impl InodeSocket {
             }
         }
         impl<'a> Future for SocketAccepter<'a> {
-            type Output = Result<(Box<dyn VirtualTcpSocket + Sync>, SocketAddr), Errno>;
+            type Output = Result<(InodeSocketKind, SocketAddr), Errno>;
             fn poll(
                 mut self: Pin<&mut Self>,
                 cx: &mut std::task::Context<'_>,
@@ -474,7 +491,14 @@ impl InodeSocket {
                     let mut inner = self.sock.inner.protected.write().unwrap();
                     return match &mut inner.kind {
                         InodeSocketKind::TcpListener { socket, .. } => match socket.try_accept() {
-                            Ok((child, addr)) => Poll::Ready(Ok((child, addr))),
+                            Ok((child, addr)) => Poll::Ready(Ok((
+                                InodeSocketKind::TcpStream {
+                                    socket: child,
+                                    write_timeout: None,
+                                    read_timeout: None,
+                                },
+                                addr,
+                            ))),
                             Err(NetworkError::WouldBlock) if self.nonblocking => {
                                 Poll::Ready(Err(Errno::Again))
                             }
@@ -490,6 +514,23 @@ impl InodeSocket {
                             Err(NetworkError::WouldBlock) => Poll::Pending,
                             Err(err) => Poll::Ready(Err(net_error_into_wasi_err(err))),
                         },
+                        InodeSocketKind::UnixListener(listener) => match listener.try_accept() {
+                            Ok(child) => Poll::Ready(Ok((
+                                InodeSocketKind::UnixStream(child),
+                                UNIX_SOCKET_ADDR,
+                            ))),
+                            Err(NetworkError::WouldBlock) if self.nonblocking => {
+                                Poll::Ready(Err(Errno::Again))
+                            }
+                            Err(NetworkError::WouldBlock) if !self.handler_registered => {
+                                listener.set_handler(cx.waker().into());
+                                drop(inner);
+                                self.handler_registered = true;
+                                continue;
+                            }
+                            Err(NetworkError::WouldBlock) => Poll::Pending,
+                            Err(err) => Poll::Ready(Err(net_error_into_wasi_err(err))),
+                        },
                         InodeSocketKind::PreSocket { .. } => Poll::Ready(Err(Errno::Notconn)),
                         _ => Poll::Ready(Err(Errno::Notsup)),
                     };
@@ -524,6 +565,14 @@ impl InodeSocket {
             InodeSocketKind::Raw(_) => {}
             InodeSocketKind::PreSocket { .. } => return Err(Errno::Notconn),
             InodeSocketKind::RemoteSocket { .. } => {}
+            InodeSocketKind::UnixListener(_) => {}
+            InodeSocketKind::UnixStream(socket) => {
+                socket
+                    .socket_mut()
+                    .close()
+                    .map_err(net_error_into_wasi_err)?;
+            }
+            InodeSocketKind::UnixDatagram(_) => {}
         };
         Ok(())
     }
@@ -630,6 +679,9 @@ impl InodeSocket {
                 true => WasiSocketStatus::Closed,
                 false => WasiSocketStatus::Opened,
             },
+            InodeSocketKind::UnixListener(_)
+            | InodeSocketKind::UnixStream(_)
+            | InodeSocketKind::UnixDatagram(_) => WasiSocketStatus::Opened,
             _ => WasiSocketStatus::Failed,
         })
     }
@@ -637,6 +689,9 @@ impl InodeSocket {
     pub fn addr_local(&self) -> Result<SocketAddr, Errno> {
         let inner = self.inner.protected.read().unwrap();
         Ok(match &inner.kind {
+            InodeSocketKind::PreSocket { props, .. } if props.family == Addressfamily::Unix => {
+                UNIX_SOCKET_ADDR
+            }
             InodeSocketKind::PreSocket { props, addr, .. } => {
                 if let Some(addr) = addr {
                     *addr
@@ -664,6 +719,9 @@ impl InodeSocket {
             InodeSocketKind::RemoteSocket {
                 local_addr: addr, ..
             } => *addr,
+            InodeSocketKind::UnixListener(_)
+            | InodeSocketKind::UnixStream(_)
+            | InodeSocketKind::UnixDatagram(_) => UNIX_SOCKET_ADDR,
             _ => return Err(Errno::Notsup),
         })
     }
@@ -671,6 +729,9 @@ impl InodeSocket {
     pub fn addr_peer(&self) -> Result<SocketAddr, Errno> {
         let inner = self.inner.protected.read().unwrap();
         Ok(match &inner.kind {
+            InodeSocketKind::PreSocket { props, .. } if props.family == Addressfamily::Unix => {
+                UNIX_SOCKET_ADDR
+            }
             InodeSocketKind::PreSocket { props, .. } => SocketAddr::new(
                 match props.family {
                     Addressfamily::Inet4 => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
@@ -701,6 +762,7 @@ impl InodeSocket {
                         })
                 })?,
             InodeSocketKind::RemoteSocket { peer_addr, .. } => *peer_addr,
+            InodeSocketKind::UnixStream(_) | InodeSocketKind::UnixDatagram(_) => UNIX_SOCKET_ADDR,
             _ => return Err(Errno::Notsup),
         })
     }
@@ -1097,6 +1159,12 @@ impl InodeSocket {
                     let res = match &mut inner.kind {
                         InodeSocketKind::Raw(socket) => socket.try_send(self.data),
                         InodeSocketKind::TcpStream { socket, .. } => socket.try_send(self.data),
+                        InodeSocketKind::UnixStream(socket) => {
+                            socket.socket_mut().try_send(self.data)
+                        }
+                        InodeSocketKind::UnixDatagram(socket) => {
+                            socket.try_send_to(self.data, None)
+                        }
                         InodeSocketKind::UdpSocket { socket, peer } => {
                             if let Some(peer) = peer {
                                 socket.try_send_to(self.data, *peer)
@@ -1267,6 +1335,12 @@ impl InodeSocket {
                     let res = match &mut inner.kind {
                         InodeSocketKind::Raw(socket) => socket.try_recv(self.data),
                         InodeSocketKind::TcpStream { socket, .. } => socket.try_recv(self.data),
+                        InodeSocketKind::UnixStream(socket) => {
+                            socket.socket_mut().try_recv(self.data)
+                        }
+                        InodeSocketKind::UnixDatagram(socket) => {
+                            socket.try_recv_from(self.data).map(|(amt, _)| amt)
+                        }
                         InodeSocketKind::UdpSocket { socket, peer } => {
                             if let Some(peer) = peer {
                                 match socket.try_recv_from(self.data) {
@@ -1363,6 +1437,13 @@ impl InodeSocket {
                         InodeSocketKind::UdpSocket { socket, .. } => {
                             socket.try_recv_from(self.data)
                         }
+                        InodeSocketKind::UnixStream(socket) => socket
+                            .socket_mut()
+                            .try_recv(self.data)
+                            .map(|amt| (amt, UNIX_SOCKET_ADDR)),
+                        InodeSocketKind::UnixDatagram(socket) => socket
+                            .try_recv_from(self.data)
+                            .map(|(amt, _)| (amt, UNIX_SOCKET_ADDR)),
                         InodeSocketKind::RemoteSocket {
                             is_dead, peer_addr, ..
                         } => {
@@ -1417,6 +1498,12 @@ impl InodeSocket {
             InodeSocketKind::TcpStream { socket, .. } => {
                 socket.shutdown(how).map_err(net_error_into_wasi_err)?;
             }
+            InodeSocketKind::UnixStream(socket) => {
+                socket
+                    .socket_mut()
+                    .shutdown(how)
+                    .map_err(net_error_into_wasi_err)?;
+            }
             InodeSocketKind::RemoteSocket { .. } => return Ok(()),
             InodeSocketKind::PreSocket { .. } => return Err(Errno::Notconn),
             _ => return Err(Errno::Notsup),
@@ -1430,7 +1517,9 @@ impl InodeSocket {
             match &mut guard.kind {
                 InodeSocketKind::TcpStream { .. }
                 | InodeSocketKind::UdpSocket { .. }
-                | InodeSocketKind::Raw(..) => true,
+                | InodeSocketKind::Raw(..)
+                | InodeSocketKind::UnixStream(_)
+                | InodeSocketKind::UnixDatagram(_) => true,
                 InodeSocketKind::RemoteSocket { is_dead, .. } => !(*is_dead),
                 _ => false,
             }
@@ -1438,6 +1527,266 @@ impl InodeSocket {
             false
         }
     }
+
+    /// Binds a unix socket to a path that was already resolved and created
+    /// in the file system
+    pub fn bind_unix(
+        &self,
+        namespace: &Arc<UnixSocketNamespace>,
+        path: String,
+    ) -> Result<Option<InodeSocket>, Errno> {
+        let mut inner = self.inner.protected.write().unwrap();
+        let (kind, handler) = match &mut inner.kind {
+            InodeSocketKind::PreSocket { props, .. } => {
+                if props.family != Addressfamily::Unix {
+                    return Err(Errno::Afnosupport);
+                }
+                let kind = match props.ty {
+                    Socktype::Stream => {
+                        InodeSocketKind::UnixListener(UnixListener::bind(namespace, path)?)
+                    }
+                    Socktype::Dgram => {
+                        InodeSocketKind::UnixDatagram(UnixDatagram::bind(namespace.clone(), path)?)
+                    }
+                    _ => return Err(Errno::Notsup),
+                };
+                (kind, props.handler.take())
+            }
+            InodeSocketKind::UnixListener(_)
+            | InodeSocketKind::UnixStream(_)
+            | InodeSocketKind::UnixDatagram(_) => return Err(Errno::Inval),
+            _ => return Err(Errno::Afnosupport),
+        };
+        drop(inner);
+
+        let socket = InodeSocket::new(kind);
+        if let Some(handler) = handler {
+            let mut inner = socket.inner.protected.write().unwrap();
+            inner
+                .set_handler(handler)
+                .map_err(net_error_into_wasi_err)?;
+        }
+        Ok(Some(socket))
+    }
+
+    /// Connects a unix socket to the socket bound to a path
+    pub fn connect_unix(
+        &self,
+        namespace: &Arc<UnixSocketNamespace>,
+        path: String,
+    ) -> Result<Option<InodeSocket>, Errno> {
+        let mut inner = self.inner.protected.write().unwrap();
+        let (kind, handler) = match &mut inner.kind {
+            InodeSocketKind::PreSocket { props, .. } => {
+                if props.family != Addressfamily::Unix {
+                    return Err(Errno::Afnosupport);
+                }
+                let kind = match props.ty {
+                    Socktype::Stream => {
+                        InodeSocketKind::UnixStream(UnixStream::connect(namespace, &path, None)?)
+                    }
+                    Socktype::Dgram => {
+                        let mut socket = UnixDatagram::unbound(namespace.clone());
+                        socket.connect(path)?;
+                        InodeSocketKind::UnixDatagram(socket)
+                    }
+                    _ => return Err(Errno::Notsup),
+                };
+                (kind, props.handler.take())
+            }
+            // A socket that was bound but never listened on connects from its path
+            InodeSocketKind::UnixListener(listener) if !listener.is_listening() => {
+                let local = Some(listener.path().to_string());
+                let kind =
+                    InodeSocketKind::UnixStream(UnixStream::connect(namespace, &path, local)?);
+                (kind, None)
+            }
+            InodeSocketKind::UnixDatagram(socket) => {
+                socket.connect(path)?;
+                return Ok(None);
+            }
+            InodeSocketKind::UnixListener(_) => return Err(Errno::Inval),
+            InodeSocketKind::UnixStream(_) => return Err(Errno::Isconn),
+            _ => return Err(Errno::Afnosupport),
+        };
+        drop(inner);
+
+        let socket = InodeSocket::new(kind);
+        if let Some(handler) = handler {
+            let mut inner = socket.inner.protected.write().unwrap();
+            inner
+                .set_handler(handler)
+                .map_err(net_error_into_wasi_err)?;
+        }
+        Ok(Some(socket))
+    }
+
+    /// Returns the path that a unix socket is bound to
+    pub fn addr_local_unix(&self) -> Result<Option<String>, Errno> {
+        let inner = self.inner.protected.read().unwrap();
+        Ok(match &inner.kind {
+            InodeSocketKind::PreSocket { props, .. } if props.family == Addressfamily::Unix => None,
+            InodeSocketKind::UnixListener(socket) => Some(socket.path().to_string()),
+            InodeSocketKind::UnixStream(socket) => socket.local_path().map(|p| p.to_string()),
+            InodeSocketKind::UnixDatagram(socket) => socket.local_path().map(|p| p.to_string()),
+            _ => return Err(Errno::Afnosupport),
+        })
+    }
+
+    /// Returns the path of the socket that a unix socket is connected to
+    pub fn addr_peer_unix(&self) -> Result<Option<String>, Errno> {
+        let inner = self.inner.protected.read().unwrap();
+        match &inner.kind {
+            InodeSocketKind::PreSocket { props, .. } if props.family == Addressfamily::Unix => {
+                Err(Errno::Notconn)
+            }
+            InodeSocketKind::UnixListener(_) => Err(Errno::Notconn),
+            InodeSocketKind::UnixStream(socket) => Ok(socket.peer_path().map(|p| p.to_string())),
+            InodeSocketKind::UnixDatagram(socket) => match socket.peer_path() {
+                Some(path) => Ok(Some(path.to_string())),
+                None => Err(Errno::Notconn),
+            },
+            _ => Err(Errno::Afnosupport),
+        }
+    }
+
+    /// Sends a datagram to the unix socket bound to a path. Datagrams are
+    /// queued by the receiver straight away so this never blocks.
+    pub fn send_to_unix(&self, buf: &[u8], path: &str) -> Result<usize, Errno> {
+        let mut inner = self.inner.protected.write().unwrap();
+        match &mut inner.kind {
+            InodeSocketKind::UnixDatagram(socket) => socket
+                .try_send_to(buf, Some(path))
+                .map_err(net_error_into_wasi_err),
+            InodeSocketKind::PreSocket { props, .. } if props.family == Addressfamily::Unix => {
+                match props.ty {
+                    Socktype::Dgram => Err(Errno::Notconn),
+                    _ => Err(Errno::Notsup),
+                }
+            }
+            InodeSocketKind::UnixStream(_) => Err(Errno::Isconn),
+            InodeSocketKind::UnixListener(_) => Err(Errno::Notsup),
+            _ => Err(Errno::Afnosupport),
+        }
+    }
+
+    /// Receives a datagram from a unix socket along with the path of the
+    /// socket that sent it (if it was bound)
+    pub async fn recv_from_unix(
+        &self,
+        tasks: &dyn VirtualTaskManager,
+        buf: &mut [MaybeUninit<u8>],
+        timeout: Option<Duration>,
+        nonblocking: bool,
+    ) -> Result<(usize, Option<String>), Errno> {
+        struct SocketReceiver<'a, 'b> {
+            inner: &'a InodeSocketInner,
+            data: &'b mut [MaybeUninit<u8>],
+            nonblocking: bool,
+            handler_registered: bool,
+        }
+        impl<'a, 'b> Drop for SocketReceiver<'a, 'b> {
+            fn drop(&mut self) {
+                if self.handler_registered {
+                    let mut inner = self.inner.protected.write().unwrap();
+                    inner.remove_handler();
+                }
+            }
+        }
+        impl<'a, 'b> Future for SocketReceiver<'a, 'b> {
+            type Output = Result<(usize, Option<String>), Errno>;
+            fn poll(
+                mut self: Pin<&mut Self>,
+                cx: &mut std::task::Context<'_>,
+            ) -> Poll<Self::Output> {
+                let mut inner = self.inner.protected.write().unwrap();
+                loop {
+                    let res = match &mut inner.kind {
+                        InodeSocketKind::UnixDatagram(socket) => socket.try_recv_from(self.data),
+                        InodeSocketKind::UnixStream(socket) => socket
+                            .socket_mut()
+                            .try_recv(self.data)
+                            .map(|amt| (amt, socket.peer_path().map(|p| p.to_string()))),
+                        InodeSocketKind::PreSocket { .. } => {
+                            return Poll::Ready(Err(Errno::Notconn))
+                        }
+                        _ => return Poll::Ready(Err(Errno::Notsup)),
+                    };
+                    return match res {
+                        Ok(ret) => Poll::Ready(Ok(ret)),
+                        Err(NetworkError::WouldBlock) if self.nonblocking => {
+                            Poll::Ready(Err(Errno::Again))
+                        }
+                        Err(NetworkError::WouldBlock) if !self.handler_registered => {
+                            inner
+                                .set_handler(cx.waker().into())
+                                .map_err(net_error_into_wasi_err)?;
+                            self.handler_registered = true;
+                            continue;
+                        }
+                        Err(NetworkError::WouldBlock) => Poll::Pending,
+                        Err(err) => Poll::Ready(Err(net_error_into_wasi_err(err))),
+                    };
+                }
+            }
+        }
+
+        let poller = SocketReceiver {
+            inner: &self.inner,
+            data: buf,
+            nonblocking,
+            handler_registered: false,
+        };
+        if let Some(timeout) = timeout {
+            tokio::select! {
+                res = poller => res,
+                _ = tasks.sleep_now(timeout) => Err(Errno::Timedout)
+            }
+        } else {
+            poller.await
+        }
+    }
+
+    /// Queues file descriptors to be passed to the other end of a unix
+    /// socket (with the next datagram for datagram sockets)
+    pub fn send_fds(&self, fds: Vec<Fd>) -> Result<(), Errno> {
+        let mut inner = self.inner.protected.write().unwrap();
+        match &mut inner.kind {
+            InodeSocketKind::UnixStream(socket) => socket.send_fds(fds),
+            InodeSocketKind::UnixDatagram(socket) => {
+                socket.send_fds(fds);
+                Ok(())
+            }
+            InodeSocketKind::PreSocket { .. } | InodeSocketKind::UnixListener(_) => {
+                Err(Errno::Notconn)
+            }
+            _ => Err(Errno::Notsup),
+        }
+    }
+
+    /// Takes the file descriptors that were passed to a unix socket
+    pub fn recv_fds(&self) -> Result<Vec<Fd>, Errno> {
+        let mut inner = self.inner.protected.write().unwrap();
+        match &mut inner.kind {
+            InodeSocketKind::UnixStream(socket) => Ok(socket.recv_fds()),
+            InodeSocketKind::UnixDatagram(socket) => Ok(socket.recv_fds()),
+            InodeSocketKind::PreSocket { .. } | InodeSocketKind::UnixListener(_) => {
+                Err(Errno::Notconn)
+            }
+            _ => Err(Errno::Notsup),
+        }
+    }
+
+    /// Returns file descriptors taken with [`Self::recv_fds`] that could not
+    /// be delivered to the socket, so that they are received next
+    pub fn unrecv_fds(&self, fds: Vec<Fd>) {
+        let mut inner = self.inner.protected.write().unwrap();
+        match &mut inner.kind {
+            InodeSocketKind::UnixStream(socket) => socket.unrecv_fds(fds),
+            InodeSocketKind::UnixDatagram(socket) => socket.unrecv_fds(fds),
+            _ => {}
+        }
+    }
 }
 
 impl InodeSocketProtected {
@@ -1454,6 +1803,9 @@ impl InodeSocketProtected {
             InodeSocketKind::RemoteSocket { props, .. } => {
                 props.handler.take();
             }
+            InodeSocketKind::UnixListener(socket) => socket.remove_handler(),
+            InodeSocketKind::UnixStream(socket) => socket.socket_mut().remove_handler(),
+            InodeSocketKind::UnixDatagram(socket) => socket.remove_handler(),
         }
     }
 
@@ -1464,6 +1816,9 @@ impl InodeSocketProtected {
             InodeSocketKind::UdpSocket { socket, .. } => socket.poll_read_ready(cx),
             InodeSocketKind::Raw(socket) => socket.poll_read_ready(cx),
             InodeSocketKind::Icmp(socket) => socket.poll_read_ready(cx),
+            InodeSocketKind::UnixListener(socket) => socket.poll_read_ready(cx),
+            InodeSocketKind::UnixStream(socket) => socket.socket_mut().poll_read_ready(cx),
+            InodeSocketKind::UnixDatagram(socket) => socket.poll_read_ready(cx),
             InodeSocketKind::PreSocket { .. } => Poll::Pending,
             InodeSocketKind::RemoteSocket { is_dead, .. } => match is_dead {
                 true => Poll::Ready(Ok(0)),
@@ -1480,6 +1835,9 @@ impl InodeSocketProtected {
             InodeSocketKind::UdpSocket { socket, .. } => socket.poll_write_ready(cx),
             InodeSocketKind::Raw(socket) => socket.poll_write_ready(cx),
             InodeSocketKind::Icmp(socket) => socket.poll_write_ready(cx),
+            InodeSocketKind::UnixListener(_) => Poll::Pending,
+            InodeSocketKind::UnixStream(socket) => socket.socket_mut().poll_write_ready(cx),
+            InodeSocketKind::UnixDatagram(socket) => socket.poll_write_ready(cx),
             InodeSocketKind::PreSocket { .. } => Poll::Pending,
             InodeSocketKind::RemoteSocket { is_dead, .. } => match is_dead {
                 true => Poll::Ready(Ok(0)),
@@ -1499,6 +1857,15 @@ impl InodeSocketProtected {
             InodeSocketKind::UdpSocket { socket, .. } => socket.set_handler(handler),
             InodeSocketKind::Raw(socket) => socket.set_handler(handler),
             InodeSocketKind::Icmp(socket) => socket.set_handler(handler),
+            InodeSocketKind::UnixListener(socket) => {
+                socket.set_handler(handler);
+                Ok(())
+            }
+            InodeSocketKind::UnixStream(socket) => socket.socket_mut().set_handler(handler),
+            InodeSocketKind::UnixDatagram(socket) => {
+                socket.set_handler(handler);
+                Ok(())
+            }
             InodeSocketKind::PreSocket { props, .. }
             | InodeSocketKind::RemoteSocket { props, .. } => {
                 props.handler.replace(handler);
@@ -0,0 +1,591 @@
+//! Unix domain sockets that connect processes running in the same runtime.
+//!
+//! Sockets are bound to paths in the file system of the process. Binding
+//! creates an entry at that path (so it shows up in directory listings and
+//! can't be bound twice) and registers the socket in the
+//! [`UnixSocketNamespace`] of the control plane, which is where connecting
+//! processes find it. The bytes never leave the runtime, streams use the
+//! same in-memory socket pairs as the loopback network.
+//!
+//! File descriptors can be passed over connected sockets (the equivalent of
+//! `SCM_RIGHTS`). They are queued next to the data and installed in the fd
+//! table of the receiving process when it asks for them.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    mem::MaybeUninit,
+    net::{Ipv4Addr, SocketAddr},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex, Weak},
+    task::{Context, Poll, Waker},
+};
+
+use virtual_mio::{InterestHandler, InterestType};
+use virtual_net::{tcp_pair::TcpSocketHalf, NetworkError, VirtualTcpSocket};
+use wasmer_wasix_types::wasi::Errno;
+
+use crate::fs::Fd;
+
+/// Unix sockets don't have an IP address, this is what they report to the
+/// calls that only understand IP addresses
+pub const UNIX_SOCKET_ADDR: SocketAddr =
+    SocketAddr::new(std::net::IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
+
+/// Longest path that a unix socket can be bound to (the size of `sun_path`)
+pub const UNIX_PATH_MAX: usize = 108;
+
+/// Maximum number of connections waiting to be accepted
+const DEFAULT_BACKLOG: usize = 128;
+
+/// Maximum number of datagrams waiting to be received
+const MAX_QUEUED_DATAGRAMS: usize = 256;
+
+/// Maximum number of file descriptors in a single message
+pub const MAX_PASSED_FDS: usize = 253;
+
+/// Size of the in-memory buffers of a stream
+const STREAM_BUFFER_SIZE: usize = 256 * 1024;
+
+/// Turns a path passed to `bind` or `connect` into the absolute path that
+/// identifies the socket
+pub fn resolve_path(current_dir: &str, path: &str) -> Result<String, Errno> {
+    if path.is_empty() {
+        return Err(Errno::Noent);
+    }
+    if path.len() >= UNIX_PATH_MAX {
+        return Err(Errno::Nametoolong);
+    }
+
+    let path = Path::new(current_dir).join(path);
+    let mut resolved = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => resolved.push(name),
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+        }
+    }
+    Ok(resolved.to_string_lossy().into_owned())
+}
+
+/// File descriptors that are in flight between two sockets
+type FdQueue = Arc<Mutex<VecDeque<Vec<Fd>>>>;
+
+#[derive(Debug, Clone)]
+enum UnixBinding {
+    Stream(Weak<UnixListenerShared>),
+    Datagram(Weak<UnixDatagramShared>),
+}
+
+impl UnixBinding {
+    fn is_alive(&self) -> bool {
+        match self {
+            Self::Stream(shared) => shared.strong_count() > 0,
+            Self::Datagram(shared) => shared.strong_count() > 0,
+        }
+    }
+}
+
+/// Paths that unix sockets are bound to, shared by all the processes of a
+/// control plane
+#[derive(Debug, Default)]
+pub struct UnixSocketNamespace {
+    bindings: Mutex<HashMap<String, UnixBinding>>,
+}
+
+impl UnixSocketNamespace {
+    fn bind(&self, path: &str, binding: UnixBinding) -> Result<(), Errno> {
+        let mut bindings = self.bindings.lock().unwrap();
+        bindings.retain(|_, binding| binding.is_alive());
+        if bindings.contains_key(path) {
+            return Err(Errno::Addrinuse);
+        }
+        bindings.insert(path.to_string(), binding);
+        Ok(())
+    }
+
+    fn lookup(&self, path: &str) -> Option<UnixBinding> {
+        let bindings = self.bindings.lock().unwrap();
+        bindings
+            .get(path)
+            .filter(|binding| binding.is_alive())
+            .cloned()
+    }
+
+    fn lookup_stream(&self, path: &str) -> Result<Arc<UnixListenerShared>, Errno> {
+        match self.lookup(path) {
+            Some(UnixBinding::Stream(shared)) => shared.upgrade().ok_or(Errno::Connrefused),
+            Some(UnixBinding::Datagram(_)) => Err(Errno::Prototype),
+            None => Err(Errno::Connrefused),
+        }
+    }
+
+    fn lookup_datagram(&self, path: &str) -> Result<Arc<UnixDatagramShared>, Errno> {
+        match self.lookup(path) {
+            Some(UnixBinding::Datagram(shared)) => shared.upgrade().ok_or(Errno::Connrefused),
+            Some(UnixBinding::Stream(_)) => Err(Errno::Prototype),
+            None => Err(Errno::Connrefused),
+        }
+    }
+
+    /// Returns true if a live socket is bound to the path
+    pub fn is_bound(&self, path: &str) -> bool {
+        self.lookup(path).is_some()
+    }
+}
+
+/// Wakes up whoever is waiting on a socket
+#[derive(Debug, Default)]
+struct Waiters {
+    handler: Option<Box<dyn InterestHandler + Send + Sync>>,
+    wakers: Vec<Waker>,
+}
+
+impl Waiters {
+    fn notify(&mut self, interest: InterestType) {
+        if let Some(handler) = self.handler.as_mut() {
+            handler.push_interest(interest);
+        }
+        self.wakers.drain(..).for_each(|w| w.wake());
+    }
+
+    fn register(&mut self, cx: &mut Context<'_>) {
+        if !self.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            self.wakers.push(cx.waker().clone());
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct UnixListenerState {
+    listening: bool,
+    backlog: VecDeque<UnixStream>,
+    max_backlog: usize,
+    waiters: Waiters,
+}
+
+#[derive(Debug)]
+struct UnixListenerShared {
+    path: String,
+    state: Mutex<UnixListenerState>,
+}
+
+impl UnixListenerShared {
+    fn connect(&self, local: Option<String>) -> Result<UnixStream, Errno> {
+        let mut state = self.state.lock().unwrap();
+        if !state.listening {
+            return Err(Errno::Connrefused);
+        }
+        if state.backlog.len() >= state.max_backlog {
+            return Err(Errno::Again);
+        }
+        let (server, client) = UnixStream::pair(Some(self.path.clone()), local);
+        state.backlog.push_back(server);
+        state.waiters.notify(InterestType::Readable);
+        Ok(client)
+    }
+}
+
+/// Stream socket that is bound to a path and (once `listen` is called)
+/// accepts connections
+#[derive(Debug)]
+pub struct UnixListener {
+    shared: Arc<UnixListenerShared>,
+}
+
+impl UnixListener {
+    /// Binds a stream socket to a path
+    pub fn bind(namespace: &UnixSocketNamespace, path: String) -> Result<Self, Errno> {
+        let shared = Arc::new(UnixListenerShared {
+            path: path.clone(),
+            state: Default::default(),
+        });
+        namespace.bind(&path, UnixBinding::Stream(Arc::downgrade(&shared)))?;
+        Ok(Self { shared })
+    }
+
+    /// Starts accepting connections
+    pub fn listen(&self, backlog: usize) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.listening = true;
+        state.max_backlog = match backlog {
+            0 => DEFAULT_BACKLOG,
+            n => n.min(DEFAULT_BACKLOG),
+        };
+    }
+
+    pub fn is_listening(&self) -> bool {
+        self.shared.state.lock().unwrap().listening
+    }
+
+    pub fn path(&self) -> &str {
+        &self.shared.path
+    }
+
+    pub fn try_accept(&mut self) -> Result<UnixStream, NetworkError> {
+        let mut state = self.shared.state.lock().unwrap();
+        state.backlog.pop_front().ok_or(NetworkError::WouldBlock)
+    }
+
+    pub fn set_handler(&mut self, mut handler: Box<dyn InterestHandler + Send + Sync>) {
+        let mut state = self.shared.state.lock().unwrap();
+        if !state.backlog.is_empty() {
+            handler.push_interest(InterestType::Readable);
+        }
+        state.waiters.handler.replace(handler);
+    }
+
+    pub fn remove_handler(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.waiters.handler.take();
+    }
+
+    pub fn poll_read_ready(&mut self, cx: &mut Context<'_>) -> Poll<virtual_net::Result<usize>> {
+        let mut state = self.shared.state.lock().unwrap();
+        if !state.backlog.is_empty() {
+            return Poll::Ready(Ok(state.backlog.len()));
+        }
+        state.waiters.register(cx);
+        Poll::Pending
+    }
+}
+
+/// Connected stream socket
+#[derive(Debug)]
+pub struct UnixStream {
+    socket: TcpSocketHalf,
+    local: Option<String>,
+    peer: Option<String>,
+    fds_tx: FdQueue,
+    fds_rx: FdQueue,
+}
+
+impl UnixStream {
+    /// Creates two streams that are connected to each other
+    pub fn pair(local: Option<String>, peer: Option<String>) -> (Self, Self) {
+        let (half1, half2) =
+            TcpSocketHalf::channel(STREAM_BUFFER_SIZE, UNIX_SOCKET_ADDR, UNIX_SOCKET_ADDR);
+        let fds1 = FdQueue::default();
+        let fds2 = FdQueue::default();
+        let end1 = Self {
+            socket: half1,
+            local: local.clone(),
+            peer: peer.clone(),
+            fds_tx: fds1.clone(),
+            fds_rx: fds2.clone(),
+        };
+        let end2 = Self {
+            socket: half2,
+            local: peer,
+            peer: local,
+            fds_tx: fds2,
+            fds_rx: fds1,
+        };
+        (end1, end2)
+    }
+
+    /// Connects to the socket that is bound to a path
+    pub fn connect(
+        namespace: &UnixSocketNamespace,
+        path: &str,
+        local: Option<String>,
+    ) -> Result<Self, Errno> {
+        namespace.lookup_stream(path)?.connect(local)
+    }
+
+    pub fn local_path(&self) -> Option<&str> {
+        self.local.as_deref()
+    }
+
+    pub fn peer_path(&self) -> Option<&str> {
+        self.peer.as_deref()
+    }
+
+    /// Queues file descriptors for the other end of the stream
+    pub fn send_fds(&self, fds: Vec<Fd>) -> Result<(), Errno> {
+        if self.socket.is_closed() {
+            return Err(Errno::Pipe);
+        }
+        self.fds_tx.lock().unwrap().push_back(fds);
+        Ok(())
+    }
+
+    /// Takes the next batch of file descriptors sent by the other end
+    pub fn recv_fds(&self) -> Vec<Fd> {
+        self.fds_rx.lock().unwrap().pop_front().unwrap_or_default()
+    }
+
+    /// Puts back descriptors taken by [`Self::recv_fds`] that could not be
+    /// delivered, they are the next batch that is received
+    pub fn unrecv_fds(&self, fds: Vec<Fd>) {
+        if !fds.is_empty() {
+            self.fds_rx.lock().unwrap().push_front(fds);
+        }
+    }
+
+    pub fn socket(&self) -> &TcpSocketHalf {
+        &self.socket
+    }
+
+    pub fn socket_mut(&mut self) -> &mut TcpSocketHalf {
+        &mut self.socket
+    }
+}
+
+/// Message sent to a datagram socket
+#[derive(Debug)]
+struct UnixMessage {
+    from: Option<String>,
+    data: Vec<u8>,
+    fds: Vec<Fd>,
+}
+
+#[derive(Debug, Default)]
+struct UnixDatagramState {
+    queue: VecDeque<UnixMessage>,
+    waiters: Waiters,
+}
+
+#[derive(Debug, Default)]
+struct UnixDatagramShared {
+    state: Mutex<UnixDatagramState>,
+}
+
+impl UnixDatagramShared {
+    fn push(&self, message: UnixMessage) -> Result<usize, NetworkError> {
+        let mut state = self.state.lock().unwrap();
+        // Senders aren't woken up when the queue drains, so rather than
+        // blocking forever a full queue fails the send (like `ENOBUFS`)
+        if state.queue.len() >= MAX_QUEUED_DATAGRAMS {
+            return Err(NetworkError::InsufficientMemory);
+        }
+        let len = message.data.len();
+        state.queue.push_back(message);
+        state.waiters.notify(InterestType::Readable);
+        Ok(len)
+    }
+}
+
+/// Datagram socket, optionally bound to a path and connected to another one
+#[derive(Debug)]
+pub struct UnixDatagram {
+    namespace: Arc<UnixSocketNamespace>,
+    shared: Arc<UnixDatagramShared>,
+    local: Option<String>,
+    peer: Option<String>,
+    /// Descriptors that go out with the next datagram
+    pending_fds: Vec<Fd>,
+    /// Descriptors that came with the last datagram that was received
+    received_fds: Vec<Fd>,
+}
+
+impl UnixDatagram {
+    /// Creates a datagram socket that isn't bound to any path
+    pub fn unbound(namespace: Arc<UnixSocketNamespace>) -> Self {
+        Self {
+            namespace,
+            shared: Default::default(),
+            local: None,
+            peer: None,
+            pending_fds: Vec::new(),
+            received_fds: Vec::new(),
+        }
+    }
+
+    /// Binds a datagram socket to a path
+    pub fn bind(namespace: Arc<UnixSocketNamespace>, path: String) -> Result<Self, Errno> {
+        let socket = Self::unbound(namespace);
+        socket
+            .namespace
+            .bind(&path, UnixBinding::Datagram(Arc::downgrade(&socket.shared)))?;
+        Ok(Self {
+            local: Some(path),
+            ..socket
+        })
+    }
+
+    /// Sets the default destination of the socket
+    pub fn connect(&mut self, path: String) -> Result<(), Errno> {
+        self.namespace.lookup_datagram(&path)?;
+        self.peer.replace(path);
+        Ok(())
+    }
+
+    pub fn local_path(&self) -> Option<&str> {
+        self.local.as_deref()
+    }
+
+    pub fn peer_path(&self) -> Option<&str> {
+        self.peer.as_deref()
+    }
+
+    /// Attaches file descriptors to the next datagram that is sent
+    pub fn send_fds(&mut self, fds: Vec<Fd>) {
+        self.pending_fds.extend(fds);
+    }
+
+    /// Takes the file descriptors that came with the last datagram
+    pub fn recv_fds(&mut self) -> Vec<Fd> {
+        std::mem::take(&mut self.received_fds)
+    }
+
+    /// Puts back descriptors taken by [`Self::recv_fds`] that could not be
+    /// delivered
+    pub fn unrecv_fds(&mut self, fds: Vec<Fd>) {
+        self.received_fds = fds;
+    }
+
+    /// Sends a datagram to the socket bound to `path`, or to the connected
+    /// peer when no path is given
+    pub fn try_send_to(&mut self, data: &[u8], path: Option<&str>) -> Result<usize, NetworkError> {
+        let path = path
+            .or(self.peer.as_deref())
+            .ok_or(NetworkError::NotConnected)?;
+        let target = self
+            .namespace
+            .lookup_datagram(path)
+            .map_err(|_| NetworkError::ConnectionRefused)?;
+        let sent = target.push(UnixMessage {
+            from: self.local.clone(),
+            data: data.to_vec(),
+            fds: std::mem::take(&mut self.pending_fds),
+        })?;
+        Ok(sent)
+    }
+
+    /// Receives the next datagram, returning its length and the path of the
+    /// socket that sent it. Datagrams that don't fit are truncated.
+    pub fn try_recv_from(
+        &mut self,
+        buf: &mut [MaybeUninit<u8>],
+    ) -> Result<(usize, Option<String>), NetworkError> {
+        let message = {
+            let mut state = self.shared.state.lock().unwrap();
+            // A connected socket only receives from its peer
+            let idx = state
+                .queue
+                .iter()
+                .position(|m| self.peer.is_none() || m.from == self.peer)
+                .ok_or(NetworkError::WouldBlock)?;
+            state.queue.remove(idx).unwrap()
+        };
+
+        let len = message.data.len().min(buf.len());
+        for (dst, src) in buf.iter_mut().zip(&message.data[..len]) {
+            dst.write(*src);
+        }
+        self.received_fds = message.fds;
+        Ok((len, message.from))
+    }
+
+    pub fn set_handler(&mut self, mut handler: Box<dyn InterestHandler + Send + Sync>) {
+        let mut state = self.shared.state.lock().unwrap();
+        if !state.queue.is_empty() {
+            handler.push_interest(InterestType::Readable);
+        }
+        handler.push_interest(InterestType::Writable);
+        state.waiters.handler.replace(handler);
+    }
+
+    pub fn remove_handler(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.waiters.handler.take();
+    }
+
+    pub fn poll_read_ready(&mut self, cx: &mut Context<'_>) -> Poll<virtual_net::Result<usize>> {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(message) = state.queue.front() {
+            return Poll::Ready(Ok(message.data.len()));
+        }
+        state.waiters.register(cx);
+        Poll::Pending
+    }
+
+    pub fn poll_write_ready(&mut self, _cx: &mut Context<'_>) -> Poll<virtual_net::Result<usize>> {
+        Poll::Ready(Ok(STREAM_BUFFER_SIZE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use virtual_net::VirtualConnectedSocket;
+
+    use super::*;
+
+    fn recv(stream: &mut UnixStream) -> Vec<u8> {
+        let mut buf = [MaybeUninit::<u8>::uninit(); 64];
+        let read = stream.socket_mut().try_recv(&mut buf).unwrap();
+        buf[..read]
+            .iter()
+            .map(|b| unsafe { b.assume_init() })
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_path() {
+        assert_eq!(resolve_path("/home", "sock").unwrap(), "/home/sock");
+        assert_eq!(
+            resolve_path("/home", "/tmp/./a/../sock").unwrap(),
+            "/tmp/sock"
+        );
+        assert_eq!(resolve_path("/", "").unwrap_err(), Errno::Noent);
+        let long = "a".repeat(UNIX_PATH_MAX);
+        assert_eq!(resolve_path("/", &long).unwrap_err(), Errno::Nametoolong);
+    }
+
+    #[test]
+    fn test_stream_connect_and_accept() {
+        let ns = UnixSocketNamespace::default();
+        let mut listener = UnixListener::bind(&ns, "/run/app.sock".to_string()).unwrap();
+        assert_eq!(
+            UnixListener::bind(&ns, "/run/app.sock".to_string()).unwrap_err(),
+            Errno::Addrinuse
+        );
+
+        // Bound but not listening yet
+        assert_eq!(
+            UnixStream::connect(&ns, "/run/app.sock", None).unwrap_err(),
+            Errno::Connrefused
+        );
+        listener.listen(0);
+
+        let mut client = UnixStream::connect(&ns, "/run/app.sock", None).unwrap();
+        let mut server = listener.try_accept().unwrap();
+        assert_eq!(client.peer_path(), Some("/run/app.sock"));
+        assert_eq!(server.local_path(), Some("/run/app.sock"));
+
+        client.socket_mut().try_send(b"ping").unwrap();
+        assert_eq!(recv(&mut server), b"ping");
+
+        // Once the listener is gone the path can be bound again
+        drop(listener);
+        assert_eq!(
+            UnixStream::connect(&ns, "/run/app.sock", None).unwrap_err(),
+            Errno::Connrefused
+        );
+        UnixListener::bind(&ns, "/run/app.sock".to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_datagrams() {
+        let ns = Arc::new(UnixSocketNamespace::default());
+        let mut server = UnixDatagram::bind(ns.clone(), "/run/log".to_string()).unwrap();
+        let mut client = UnixDatagram::bind(ns.clone(), "/run/client".to_string()).unwrap();
+        assert_eq!(
+            UnixStream::connect(&ns, "/run/log", None).unwrap_err(),
+            Errno::Prototype
+        );
+
+        client.try_send_to(b"hello", Some("/run/log")).unwrap();
+        let mut buf = [MaybeUninit::<u8>::uninit(); 3];
+        let (len, from) = server.try_recv_from(&mut buf).unwrap();
+        assert_eq!(len, 3);
+        assert_eq!(from.as_deref(), Some("/run/client"));
+        assert!(matches!(
+            server.try_recv_from(&mut buf),
+            Err(NetworkError::WouldBlock)
+        ));
+    }
+}
@@ -0,0 +1,121 @@
+use std::{any::Any, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use wasmer::FunctionEnvMut;
+
+use super::cmd_cron::{finish, JobOptions};
+use crate::{
+    os::{command::VirtualCommand, scheduler::JobSchedule, task::TaskJoinHandle},
+    Runtime, SpawnError, WasiEnv,
+};
+
+const HELP: &str = r#"USAGE:
+    at [OPTIONS] <TIME> <PACKAGE> [ARGS]...
+
+ARGS:
+    <TIME>    `now`, a delay such as `+5m` or an RFC 3339 timestamp
+
+OPTIONS:
+    --command <NAME>      Command of the package to run
+    --env <KEY=VALUE>     Environment variable of the job
+    --retries <N>         Retry failed runs up to N times
+    --timeout <DURATION>  Terminate runs that take longer (e.g. 30s, 5m)
+
+Use `cron list`, `cron log` and `cron remove` to manage the job.
+"#;
+
+/// Schedules a package to run once in the control plane of the process
+/// that calls it.
+#[derive(Debug, Clone)]
+pub struct CmdAt {
+    runtime: Arc<dyn Runtime + Send + Sync + 'static>,
+}
+
+impl CmdAt {
+    const NAME: &'static str = "at";
+
+    pub fn new(runtime: Arc<dyn Runtime + Send + Sync + 'static>) -> Self {
+        Self { runtime }
+    }
+
+    fn run(
+        &self,
+        parent_ctx: &FunctionEnvMut<'_, WasiEnv>,
+        args: &[String],
+    ) -> Result<String, String> {
+        let (options, args) = JobOptions::parse(args)?;
+        let (time, args) = args.split_first().ok_or(HELP)?;
+        let at = parse_time(time, Utc::now())?;
+        let spec = options.into_spec(JobSchedule::At(at), args)?;
+
+        let id = parent_ctx
+            .data()
+            .control_plane
+            .schedule_job(self.runtime.clone(), spec)
+            .map_err(|err| err.to_string())?;
+        Ok(format!("job {id} at {}\n", at.to_rfc3339()))
+    }
+}
+
+impl VirtualCommand for CmdAt {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn exec(
+        &self,
+        parent_ctx: &FunctionEnvMut<'_, WasiEnv>,
+        _name: &str,
+        env: &mut Option<WasiEnv>,
+    ) -> Result<TaskJoinHandle, SpawnError> {
+        let env = env.as_ref().ok_or(SpawnError::UnknownError)?;
+        let args = env.state.args.lock().unwrap().clone();
+        let args = args.get(1..).unwrap_or_default();
+
+        let res = self.run(parent_ctx, args);
+        Ok(finish(env, res))
+    }
+}
+
+fn parse_time(time: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    if time == "now" {
+        return Ok(now);
+    }
+    if let Some(delay) = time.strip_prefix('+') {
+        let delay = humantime::parse_duration(delay)
+            .map_err(|err| format!("invalid delay {delay}: {err}"))?;
+        let delay = chrono::Duration::from_std(delay).map_err(|err| err.to_string())?;
+        return now
+            .checked_add_signed(delay)
+            .ok_or_else(|| format!("invalid delay: {time}"));
+    }
+    DateTime::parse_from_rfc3339(time)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|err| format!("invalid time {time}: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_time() {
+        let now = Utc.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap();
+        assert_eq!(parse_time("now", now).unwrap(), now);
+        assert_eq!(
+            parse_time("+1h 30m", now).unwrap(),
+            Utc.with_ymd_and_hms(2024, 5, 1, 11, 30, 0).unwrap()
+        );
+        assert_eq!(
+            parse_time("2024-06-01T08:00:00+02:00", now).unwrap(),
+            Utc.with_ymd_and_hms(2024, 6, 1, 6, 0, 0).unwrap()
+        );
+        assert!(parse_time("tomorrow", now).is_err());
+    }
+}
@@ -0,0 +1,969 @@
+use std::{
+    future::Future,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use virtual_fs::{AsyncReadExt, AsyncWriteExt, FileSystem, FsError, VirtualFile};
+use wasmer_wasix_types::wasi::ExitCode;
+
+use crate::{
+    fs::WasiFsRoot,
+    os::task::{
+        process::WasiProcess,
+        signal::{SignalDefault, SignalSet},
+        thread::WasiThread,
+        TaskJoinHandle,
+    },
+    Runtime, SpawnError, WasiEnv,
+};
+
+type StdioFile = Box<dyn VirtualFile + Send + Sync + 'static>;
+
+/// The basic file and environment utilities that are implemented natively
+/// so that a console has a usable shell without a coreutils package.
+///
+/// They are only used when no package provides a command of the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coreutil {
+    Cat,
+    Cp,
+    Echo,
+    Env,
+    Ls,
+    Mkdir,
+    Mv,
+    Pwd,
+    Rm,
+    Touch,
+}
+
+impl Coreutil {
+    pub const ALL: [Coreutil; 10] = [
+        Coreutil::Cat,
+        Coreutil::Cp,
+        Coreutil::Echo,
+        Coreutil::Env,
+        Coreutil::Ls,
+        Coreutil::Mkdir,
+        Coreutil::Mv,
+        Coreutil::Pwd,
+        Coreutil::Rm,
+        Coreutil::Touch,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Coreutil::Cat => "cat",
+            Coreutil::Cp => "cp",
+            Coreutil::Echo => "echo",
+            Coreutil::Env => "env",
+            Coreutil::Ls => "ls",
+            Coreutil::Mkdir => "mkdir",
+            Coreutil::Mv => "mv",
+            Coreutil::Pwd => "pwd",
+            Coreutil::Rm => "rm",
+            Coreutil::Touch => "touch",
+        }
+    }
+
+    /// Finds the utility for a command name, either bare (`ls`) or under
+    /// `/bin` (`/bin/ls`)
+    pub fn from_command(command: &str) -> Option<Self> {
+        let name = command.strip_prefix("/bin/").unwrap_or(command);
+        Self::ALL.into_iter().find(|util| util.name() == name)
+    }
+
+    /// The single letter flags that the utility accepts
+    fn flags(&self) -> &'static str {
+        match self {
+            Coreutil::Cp => "rR",
+            Coreutil::Ls => "al",
+            Coreutil::Mkdir => "p",
+            Coreutil::Rm => "rRf",
+            _ => "",
+        }
+    }
+}
+
+/// Spawns the utility as the process of `env`, it runs natively on the
+/// task manager and reacts to the signals sent to that process
+pub fn spawn_coreutil(
+    util: Coreutil,
+    env: WasiEnv,
+    runtime: &Arc<dyn Runtime + Send + Sync + 'static>,
+) -> Result<TaskJoinHandle, SpawnError> {
+    let pid = env.pid();
+    let join_handle = env.thread.join_handle();
+    let args = env.state.args.lock().unwrap().clone();
+    let utils = Utils::new(util, &env);
+
+    runtime
+        .task_manager()
+        .task_shared(Box::new(move || {
+            Box::pin(async move {
+                env.thread.set_status_running();
+                let args = args.get(1..).unwrap_or_default();
+                let code = run_as_process(&env.process, &env.thread, utils.run(args)).await;
+                env.on_exit(Some(code)).await;
+                env.thread.set_status_finished(Ok(code));
+            })
+        }))
+        .map_err(|err| {
+            tracing::error!(%pid, "failed to launch {} - {}", util.name(), err);
+            SpawnError::UnknownError
+        })?;
+
+    Ok(join_handle)
+}
+
+/// Drives `work` the way a process runs: it makes no progress while the
+/// process is stopped and it ends on a signal that terminates the process
+async fn run_as_process(
+    process: &WasiProcess,
+    thread: &WasiThread,
+    work: impl Future<Output = ExitCode>,
+) -> ExitCode {
+    let mut work = std::pin::pin!(work);
+    loop {
+        process.wait_for_continue().await;
+        tokio::select! {
+            biased;
+            _ = thread.wait_for_signal() => {
+                for sig in thread.pop_signals() {
+                    if SignalSet::default_action(sig).is_some_and(SignalDefault::terminates) {
+                        // The shell convention for a process killed by a signal
+                        return ExitCode::from(128 + sig as i32);
+                    }
+                    tracing::trace!(pid=%process.pid(), sig, "Signal ignored");
+                }
+            }
+            _ = process.wait_for_stopped() => {}
+            code = &mut work => return code,
+        }
+    }
+}
+
+/// Splits the leading single letter flags (e.g. `-rf`) from the operands,
+/// a flag that is not in `supported` is returned as the error
+fn split_flags<'a>(args: &'a [String], supported: &str) -> Result<(Vec<char>, &'a [String]), char> {
+    let mut flags = Vec::new();
+    let mut args = args;
+    while let Some((arg, rest)) = args.split_first() {
+        if arg == "--" {
+            args = rest;
+            break;
+        }
+        match arg.strip_prefix('-') {
+            Some(arg) if !arg.is_empty() => {
+                if let Some(flag) = arg.chars().find(|flag| !supported.contains(*flag)) {
+                    return Err(flag);
+                }
+                flags.extend(arg.chars())
+            }
+            _ => break,
+        }
+        args = rest;
+    }
+    Ok((flags, args))
+}
+
+/// Turns a path into an absolute path without any `.` or `..` components
+fn resolve_path(cwd: &str, path: &str) -> PathBuf {
+    let mut resolved = PathBuf::from("/");
+    for component in Path::new(cwd).join(path).components() {
+        match component {
+            Component::Normal(name) => resolved.push(name),
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+        }
+    }
+    resolved
+}
+
+/// Expands the backslash escapes understood by `echo -e`, the output
+/// stops at `\c`
+fn unescape(arg: &str, out: &mut Vec<u8>) -> bool {
+    let mut chars = arg.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            out.extend(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        let byte = match chars.next() {
+            Some('\\') => b'\\',
+            Some('a') => 0x07,
+            Some('b') => 0x08,
+            Some('c') => return false,
+            Some('e') => 0x1b,
+            Some('f') => 0x0c,
+            Some('n') => b'\n',
+            Some('r') => b'\r',
+            Some('t') => b'\t',
+            Some('v') => 0x0b,
+            Some(c @ ('0' | 'x')) => {
+                let (radix, max_digits) = if c == '0' { (8, 3) } else { (16, 2) };
+                let mut value = 0u32;
+                let mut digits = 0;
+                while digits < max_digits {
+                    match chars.peek().and_then(|d| d.to_digit(radix)) {
+                        Some(d) => value = value * radix + d,
+                        None => break,
+                    }
+                    chars.next();
+                    digits += 1;
+                }
+                if c == 'x' && digits == 0 {
+                    out.extend(b"\\x");
+                    continue;
+                }
+                value as u8
+            }
+            Some(c) => {
+                out.push(b'\\');
+                let mut buf = [0; 4];
+                out.extend(c.encode_utf8(&mut buf).as_bytes());
+                continue;
+            }
+            None => b'\\',
+        };
+        out.push(byte);
+    }
+    true
+}
+
+/// Formats a timestamp in nanoseconds since the epoch as `YYYY-MM-DD HH:MM`
+/// in UTC
+fn format_time(nanos: u64) -> String {
+    let secs = nanos / 1_000_000_000;
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+
+    // Converts the days since the epoch to a civil date, see
+    // <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        secs / 3_600,
+        secs % 3_600 / 60
+    )
+}
+
+/// Returns the `ls -l` mode string, the file system does not keep track
+/// of permissions so they are the defaults for each type of file
+fn mode_string(ft: &virtual_fs::FileType) -> &'static str {
+    if ft.is_dir() {
+        "drwxr-xr-x"
+    } else if ft.is_symlink() {
+        "lrwxrwxrwx"
+    } else if ft.is_char_device() {
+        "crw-rw-rw-"
+    } else if ft.is_block_device() {
+        "brw-rw----"
+    } else if ft.is_socket() {
+        "srwxrwxrwx"
+    } else if ft.is_fifo() {
+        "prw-r--r--"
+    } else {
+        "-rw-r--r--"
+    }
+}
+
+struct Utils {
+    util: Coreutil,
+    fs: WasiFsRoot,
+    cwd: String,
+    envs: Vec<Vec<u8>>,
+    stdin: Option<StdioFile>,
+    stdout: Option<StdioFile>,
+    stderr: Option<StdioFile>,
+    failed: bool,
+}
+
+impl Utils {
+    fn new(util: Coreutil, env: &WasiEnv) -> Self {
+        Self {
+            util,
+            fs: env.fs_root().clone(),
+            cwd: env.state.fs.current_dir.lock().unwrap().clone(),
+            envs: env.state.envs.lock().unwrap().clone(),
+            stdin: env.stdin().ok().flatten(),
+            stdout: env.stdout().ok().flatten(),
+            stderr: env.stderr().ok().flatten(),
+            failed: false,
+        }
+    }
+
+    async fn run(mut self, args: &[String]) -> ExitCode {
+        if self.util == Coreutil::Echo {
+            self.echo(args).await;
+        } else {
+            match split_flags(args, self.util.flags()) {
+                Ok((flags, operands)) => self.run_with_flags(&flags, operands).await,
+                Err(flag) => self.error(format!("invalid option -- '{flag}'")).await,
+            }
+        }
+        if let Some(stdout) = self.stdout.as_mut() {
+            stdout.flush().await.ok();
+        }
+        if self.failed {
+            1.into()
+        } else {
+            0.into()
+        }
+    }
+
+    async fn run_with_flags(&mut self, flags: &[char], operands: &[String]) {
+        match self.util {
+            Coreutil::Cat => self.cat(operands).await,
+            Coreutil::Cp => self.cp(flags, operands).await,
+            Coreutil::Echo => unreachable!("echo parses its own flags"),
+            Coreutil::Env => self.env().await,
+            Coreutil::Ls => self.ls(flags, operands).await,
+            Coreutil::Mkdir => self.mkdir(flags, operands).await,
+            Coreutil::Mv => self.mv(operands).await,
+            Coreutil::Pwd => self.pwd().await,
+            Coreutil::Rm => self.rm(flags, operands).await,
+            Coreutil::Touch => self.touch(operands).await,
+        }
+    }
+
+    fn resolve(&self, path: &str) -> PathBuf {
+        resolve_path(&self.cwd, path)
+    }
+
+    async fn out(&mut self, data: &[u8]) {
+        if let Some(stdout) = self.stdout.as_mut() {
+            if stdout.write_all(data).await.is_err() {
+                self.failed = true;
+            }
+        }
+    }
+
+    async fn error(&mut self, msg: impl std::fmt::Display) {
+        let msg = format!("{}: {msg}\n", self.util.name());
+        if let Some(stderr) = self.stderr.as_mut() {
+            stderr.write_all(msg.as_bytes()).await.ok();
+        }
+        self.failed = true;
+    }
+
+    async fn fs_error(&mut self, path: &str, err: FsError) {
+        self.error(format!("{path}: {err}")).await;
+    }
+
+    async fn read_file(&self, path: &Path) -> Result<Vec<u8>, FsError> {
+        let mut file = self.fs.new_open_options().read(true).open(path)?;
+        let mut data = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut data).await?;
+        Ok(data)
+    }
+
+    async fn write_file(&self, path: &Path, data: &[u8]) -> Result<(), FsError> {
+        let mut file = self
+            .fs
+            .new_open_options()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.write_all(data).await?;
+        Ok(())
+    }
+
+    /// Returns the destination of a `cp` or `mv` of `src` to `dest`, which
+    /// is inside `dest` when it is a directory
+    fn destination(&self, src: &Path, dest: &Path) -> PathBuf {
+        match (self.fs.metadata(dest), src.file_name()) {
+            (Ok(meta), Some(name)) if meta.is_dir() => dest.join(name),
+            _ => dest.to_path_buf(),
+        }
+    }
+
+    async fn cat(&mut self, paths: &[String]) {
+        let stdin = ["-".to_string()];
+        let paths = if paths.is_empty() { &stdin[..] } else { paths };
+        for path in paths {
+            let res = if path == "-" {
+                match self.stdin.take() {
+                    Some(mut stdin) => {
+                        let res = self.copy_to_stdout(&mut stdin).await;
+                        self.stdin = Some(stdin);
+                        res
+                    }
+                    None => Ok(()),
+                }
+            } else {
+                let file = self
+                    .fs
+                    .new_open_options()
+                    .read(true)
+                    .open(self.resolve(path));
+                match file {
+                    Ok(mut file) => self.copy_to_stdout(&mut file).await,
+                    Err(err) => Err(err),
+                }
+            };
+            if let Err(err) = res {
+                self.fs_error(path, err).await;
+            }
+        }
+    }
+
+    /// Writes everything that is read from `file` to stdout as soon as it
+    /// arrives
+    async fn copy_to_stdout(&mut self, file: &mut StdioFile) -> Result<(), FsError> {
+        let mut buf = vec![0; 8192];
+        loop {
+            let read = file.read(&mut buf).await?;
+            if read == 0 {
+                return Ok(());
+            }
+            if let Some(stdout) = self.stdout.as_mut() {
+                stdout.write_all(&buf[..read]).await?;
+                stdout.flush().await?;
+            }
+        }
+    }
+
+    async fn cp(&mut self, flags: &[char], paths: &[String]) {
+        let recursive = flags.iter().any(|f| matches!(f, 'r' | 'R'));
+        let Some((dest, srcs)) = paths.split_last().filter(|(_, srcs)| !srcs.is_empty()) else {
+            self.error("usage: cp [-r] <SOURCE>... <DEST>").await;
+            return;
+        };
+        let dest = self.resolve(dest);
+        for src in srcs {
+            let from = self.resolve(src);
+            let to = self.destination(&from, &dest);
+            if let Err(err) = self.copy(&from, &to, recursive).await {
+                self.fs_error(src, err).await;
+            }
+        }
+    }
+
+    async fn copy(&self, from: &Path, to: &Path, recursive: bool) -> Result<(), FsError> {
+        if !self.fs.metadata(from)?.is_dir() {
+            let data = self.read_file(from).await?;
+            return self.write_file(to, &data).await;
+        }
+        if !recursive {
+            return Err(FsError::BaseNotDirectory);
+        }
+        if self.fs.metadata(to).is_err() {
+            self.fs.create_dir(to)?;
+        }
+        for entry in self.fs.read_dir(from)? {
+            let entry = entry?;
+            Box::pin(self.copy(&entry.path, &to.join(entry.file_name()), recursive)).await?;
+        }
+        Ok(())
+    }
+
+    async fn echo(&mut self, args: &[String]) {
+        let mut newline = true;
+        let mut escapes = false;
+        let mut args = args;
+        while let Some((flags, rest)) = args.split_first() {
+            match flags.strip_prefix('-') {
+                Some(flags) if !flags.is_empty() && flags.chars().all(|f| "neE".contains(f)) => {
+                    for flag in flags.chars() {
+                        match flag {
+                            'n' => newline = false,
+                            'e' => escapes = true,
+                            _ => escapes = false,
+                        }
+                    }
+                }
+                _ => break,
+            }
+            args = rest;
+        }
+
+        let mut out = Vec::new();
+        for (i, arg) in args.iter().enumerate() {
+            if i > 0 {
+                out.push(b' ');
+            }
+            if !escapes {
+                out.extend(arg.as_bytes());
+            } else if !unescape(arg, &mut out) {
+                newline = false;
+                break;
+            }
+        }
+        if newline {
+            out.push(b'\n');
+        }
+        self.out(&out).await;
+    }
+
+    async fn env(&mut self) {
+        let mut out = Vec::new();
+        for var in self.envs.iter() {
+            out.extend(var);
+            out.push(b'\n');
+        }
+        self.out(&out).await;
+    }
+
+    async fn ls(&mut self, flags: &[char], paths: &[String]) {
+        let all = flags.contains(&'a');
+        let long = flags.contains(&'l');
+        let cwd = [".".to_string()];
+        let paths = if paths.is_empty() { &cwd[..] } else { paths };
+
+        for (i, path) in paths.iter().enumerate() {
+            let resolved = self.resolve(path);
+            let meta = match self.fs.metadata(&resolved) {
+                Ok(meta) => meta,
+                Err(err) => {
+                    self.fs_error(path, err).await;
+                    continue;
+                }
+            };
+            if !meta.is_dir() {
+                self.ls_entry(path, &resolved, &meta, long).await;
+                continue;
+            }
+            let entries = match self.fs.read_dir(&resolved) {
+                Ok(entries) => entries,
+                Err(err) => {
+                    self.fs_error(path, err).await;
+                    continue;
+                }
+            };
+            let mut entries: Vec<_> = entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| (entry.file_name().to_string_lossy().into_owned(), entry))
+                .filter(|(name, _)| all || !name.starts_with('.'))
+                .collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            if paths.len() > 1 {
+                if i > 0 {
+                    self.out(b"\n").await;
+                }
+                self.out(format!("{path}:\n").as_bytes()).await;
+            }
+            if long {
+                self.out(format!("total {}\n", entries.len()).as_bytes())
+                    .await;
+            }
+            for (name, entry) in entries {
+                let meta = entry.metadata.unwrap_or_default();
+                self.ls_entry(&name, &entry.path, &meta, long).await;
+            }
+        }
+    }
+
+    async fn ls_entry(&mut self, name: &str, path: &Path, meta: &virtual_fs::Metadata, long: bool) {
+        let line = if long {
+            let target = match meta.file_type().is_symlink() {
+                true => self
+                    .fs
+                    .readlink(path)
+                    .map(|target| format!(" -> {}", target.display()))
+                    .unwrap_or_default(),
+                false => String::new(),
+            };
+            format!(
+                "{} 1 root root {:>10} {} {name}{target}\n",
+                mode_string(&meta.file_type()),
+                meta.len(),
+                format_time(meta.modified),
+            )
+        } else {
+            format!("{name}\n")
+        };
+        self.out(line.as_bytes()).await;
+    }
+
+    async fn mkdir(&mut self, flags: &[char], paths: &[String]) {
+        let parents = flags.contains(&'p');
+        for path in paths {
+            let resolved = self.resolve(path);
+            let res = if parents {
+                resolved
+                    .ancestors()
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .rev()
+                    .filter(|dir| self.fs.metadata(dir).is_err())
+                    .try_for_each(|dir| self.fs.create_dir(dir))
+            } else {
+                self.fs.create_dir(&resolved)
+            };
+            if let Err(err) = res {
+                self.fs_error(path, err).await;
+            }
+        }
+    }
+
+    async fn mv(&mut self, paths: &[String]) {
+        let Some((dest, srcs)) = paths.split_last().filter(|(_, srcs)| !srcs.is_empty()) else {
+            self.error("usage: mv <SOURCE>... <DEST>").await;
+            return;
+        };
+        let dest = self.resolve(dest);
+        for src in srcs {
+            let from = self.resolve(src);
+            let to = self.destination(&from, &dest);
+            if let Err(err) = self.fs.rename(&from, &to).await {
+                self.fs_error(src, err).await;
+            }
+        }
+    }
+
+    async fn pwd(&mut self) {
+        let cwd = format!("{}\n", self.cwd);
+        self.out(cwd.as_bytes()).await;
+    }
+
+    async fn rm(&mut self, flags: &[char], paths: &[String]) {
+        let recursive = flags.iter().any(|f| matches!(f, 'r' | 'R'));
+        let force = flags.contains(&'f');
+        for path in paths {
+            match self.remove(&self.resolve(path), recursive) {
+                Err(FsError::EntryNotFound) if force => {}
+                Err(err) => self.fs_error(path, err).await,
+                Ok(()) => {}
+            }
+        }
+    }
+
+    fn remove(&self, path: &Path, recursive: bool) -> Result<(), FsError> {
+        if !self.fs.metadata(path)?.is_dir() {
+            return self.fs.remove_file(path);
+        }
+        if !recursive {
+            return Err(FsError::BaseNotDirectory);
+        }
+        for entry in self.fs.read_dir(path)? {
+            self.remove(&entry?.path, recursive)?;
+        }
+        self.fs.remove_dir(path)
+    }
+
+    async fn touch(&mut self, paths: &[String]) {
+        for path in paths {
+            let res = self
+                .fs
+                .new_open_options()
+                .write(true)
+                .create(true)
+                .open(self.resolve(path));
+            if let Err(err) = res {
+                self.fs_error(path, err).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use virtual_fs::{Pipe, TmpFileSystem};
+    use wasmer_wasix_types::types::Signal;
+
+    use super::*;
+    use crate::{
+        bin_factory::BinFactory, capabilities::Capabilities, os::task::signal::SIGRTMIN,
+        runtime::task_manager::VirtualTaskManager, PluggableRuntime,
+    };
+
+    fn runtime() -> Arc<dyn Runtime + Send + Sync + 'static> {
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "sys-thread")] {
+                let tasks: Arc<dyn VirtualTaskManager + Send + Sync> = Arc::new(
+                    crate::runtime::task_manager::tokio::TokioTaskManager::new(
+                        tokio::runtime::Handle::current(),
+                    ),
+                );
+                Arc::new(PluggableRuntime::new(tasks))
+            } else {
+                unimplemented!("Unable to get the task manager")
+            }
+        }
+    }
+
+    struct Spawned {
+        process: WasiProcess,
+        handle: TaskJoinHandle,
+        stdin: Pipe,
+        stdout: Pipe,
+        stderr: Pipe,
+    }
+
+    impl Spawned {
+        async fn output(mut self) -> (ExitCode, String, String) {
+            drop(self.stdin);
+            let code = self.handle.wait_finished().await.unwrap();
+            let mut stdout = String::new();
+            self.stdout.read_to_string(&mut stdout).await.unwrap();
+            let mut stderr = String::new();
+            self.stderr.read_to_string(&mut stderr).await.unwrap();
+            (code, stdout, stderr)
+        }
+    }
+
+    /// Spawns a command the same way a process does, through the binary
+    /// factory
+    async fn spawn(fs: &TmpFileSystem, args: &[&str]) -> Spawned {
+        let runtime = runtime();
+        let (stdin, stdin_child) = Pipe::channel();
+        let (stdout, stdout_child) = Pipe::channel();
+        let (stderr, stderr_child) = Pipe::channel();
+        let env = WasiEnv::builder(args[0])
+            .args(&args[1..])
+            .env("GREETING", "hello")
+            .sandbox_fs(fs.clone())
+            .current_dir("/")
+            .stdin(Box::new(stdin_child))
+            .stdout(Box::new(stdout_child))
+            .stderr(Box::new(stderr_child))
+            .runtime(runtime.clone())
+            .capabilities(Capabilities {
+                native_coreutils: true,
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        let process = env.process.clone();
+        let handle = BinFactory::new(runtime)
+            .spawn(args[0].to_string(), env)
+            .await
+            .unwrap();
+        Spawned {
+            process,
+            handle,
+            stdin,
+            stdout,
+            stderr,
+        }
+    }
+
+    async fn run(fs: &TmpFileSystem, args: &[&str]) -> (ExitCode, String, String) {
+        spawn(fs, args).await.output().await
+    }
+
+    #[test]
+    fn test_resolve_path() {
+        assert_eq!(resolve_path("/home", "file"), PathBuf::from("/home/file"));
+        assert_eq!(
+            resolve_path("/home", "/etc/./hosts"),
+            PathBuf::from("/etc/hosts")
+        );
+        assert_eq!(resolve_path("/home/user", "../.."), PathBuf::from("/"));
+        assert_eq!(resolve_path("/", "../a/../b"), PathBuf::from("/b"));
+    }
+
+    #[test]
+    fn test_split_flags() {
+        let args: Vec<String> = ["-rf", "-R", "a", "-b"].map(String::from).to_vec();
+        let (flags, rest) = split_flags(&args, "rRf").unwrap();
+        assert_eq!(flags, vec!['r', 'f', 'R']);
+        assert_eq!(rest, &args[2..]);
+
+        let args: Vec<String> = ["--", "-a"].map(String::from).to_vec();
+        let (flags, rest) = split_flags(&args, "").unwrap();
+        assert!(flags.is_empty());
+        assert_eq!(rest, &args[1..]);
+
+        let args: Vec<String> = ["-rv", "a"].map(String::from).to_vec();
+        assert_eq!(split_flags(&args, "rRf"), Err('v'));
+
+        let args: Vec<String> = ["-"].map(String::from).to_vec();
+        assert_eq!(split_flags(&args, ""), Ok((vec![], &args[..])));
+    }
+
+    #[test]
+    fn test_unescape() {
+        let mut out = Vec::new();
+        assert!(unescape(r"a\tb\n\\\x41\0101\q", &mut out));
+        assert_eq!(out, b"a\tb\n\\AA\\q");
+
+        let mut out = Vec::new();
+        assert!(!unescape(r"stop\chere", &mut out));
+        assert_eq!(out, b"stop");
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(0), "1970-01-01 00:00");
+        assert_eq!(format_time(951_827_696 * 1_000_000_000), "2000-02-29 12:34");
+    }
+
+    #[tokio::test]
+    #[cfg_attr(
+        not(feature = "sys-thread"),
+        ignore = "The tokio task manager isn't available on this platform"
+    )]
+    async fn test_echo_and_env() {
+        let fs = TmpFileSystem::new();
+
+        let (code, out, _) = run(&fs, &["echo", "a", "b"]).await;
+        assert_eq!(code, ExitCode::from(0));
+        assert_eq!(out, "a b\n");
+
+        let (_, out, _) = run(&fs, &["echo", "-ne", r"a\tb\n"]).await;
+        assert_eq!(out, "a\tb\n");
+
+        let (_, out, _) = run(&fs, &["echo", "-E", r"a\tb"]).await;
+        assert_eq!(out, "a\\tb\n");
+
+        let (_, out, _) = run(&fs, &["/bin/env"]).await;
+        assert!(out.lines().any(|line| line == "GREETING=hello"));
+    }
+
+    #[tokio::test]
+    #[cfg_attr(
+        not(feature = "sys-thread"),
+        ignore = "The tokio task manager isn't available on this platform"
+    )]
+    async fn test_file_utilities() {
+        let fs = TmpFileSystem::new();
+
+        let (code, _, _) = run(&fs, &["mkdir", "-p", "/a/b"]).await;
+        assert_eq!(code, ExitCode::from(0));
+        run(&fs, &["touch", "/a/b/empty"]).await;
+        fs.new_open_options()
+            .write(true)
+            .create(true)
+            .open("/a/file.txt")
+            .unwrap()
+            .write_all(b"contents")
+            .await
+            .unwrap();
+
+        let (_, out, _) = run(&fs, &["cp", "-r", "/a", "/c"]).await;
+        assert_eq!(out, "");
+        let (_, out, _) = run(&fs, &["cat", "/c/file.txt"]).await;
+        assert_eq!(out, "contents");
+
+        run(&fs, &["mv", "/c/file.txt", "/c/b"]).await;
+        let (_, out, _) = run(&fs, &["ls", "/c", "/c/b"]).await;
+        assert_eq!(out, "/c:\nb\n\n/c/b:\nempty\nfile.txt\n");
+
+        let (_, out, _) = run(&fs, &["ls", "-l", "/c/b"]).await;
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines[0], "total 2");
+        assert!(lines[1].starts_with("-rw-r--r-- 1 root root          0 "));
+        assert!(lines[2].starts_with("-rw-r--r-- 1 root root          8 "));
+        assert!(lines[2].ends_with(" file.txt"));
+        let (_, out, _) = run(&fs, &["ls", "-l", "/"]).await;
+        assert!(out.lines().any(|line| line.starts_with("drwxr-xr-x ")));
+
+        let (code, _, err) = run(&fs, &["rm", "/c"]).await;
+        assert_eq!(code, ExitCode::from(1));
+        assert!(err.starts_with("rm: /c: "));
+        let (code, _, _) = run(&fs, &["rm", "-rf", "/c", "/missing"]).await;
+        assert_eq!(code, ExitCode::from(0));
+        assert!(fs.metadata("/c".as_ref()).is_err());
+    }
+
+    #[tokio::test]
+    #[cfg_attr(
+        not(feature = "sys-thread"),
+        ignore = "The tokio task manager isn't available on this platform"
+    )]
+    async fn test_unsupported_flags_are_rejected() {
+        let fs = TmpFileSystem::new();
+        fs.create_dir("/dir".as_ref()).unwrap();
+
+        let (code, _, err) = run(&fs, &["rm", "-rv", "/dir"]).await;
+        assert_eq!(code, ExitCode::from(1));
+        assert_eq!(err, "rm: invalid option -- 'v'\n");
+        assert!(fs.metadata("/dir".as_ref()).is_ok());
+    }
+
+    #[tokio::test]
+    #[cfg_attr(
+        not(feature = "sys-thread"),
+        ignore = "The tokio task manager isn't available on this platform"
+    )]
+    async fn test_cat_streams_stdin() {
+        let fs = TmpFileSystem::new();
+        let mut cat = spawn(&fs, &["cat"]).await;
+
+        // The output arrives while stdin is still open
+        for line in ["first\n", "second\n"] {
+            cat.stdin.write_all(line.as_bytes()).await.unwrap();
+            let mut buf = vec![0; line.len()];
+            cat.stdout.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, line.as_bytes());
+        }
+
+        let (code, out, _) = cat.output().await;
+        assert_eq!(code, ExitCode::from(0));
+        assert_eq!(out, "");
+    }
+
+    #[tokio::test]
+    #[cfg_attr(
+        not(feature = "sys-thread"),
+        ignore = "The tokio task manager isn't available on this platform"
+    )]
+    async fn test_signals_reach_the_command() {
+        let fs = TmpFileSystem::new();
+        let mut cat = spawn(&fs, &["cat"]).await;
+
+        cat.process.signal_process(Signal::Sigtstp);
+        assert_eq!(cat.process.stopped(), Some(Signal::Sigtstp));
+        cat.process.signal_process(Signal::Sigcont);
+        assert_eq!(cat.process.stopped(), None);
+
+        cat.stdin.write_all(b"still running\n").await.unwrap();
+        let mut buf = vec![0; 14];
+        cat.stdout.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, b"still running\n");
+
+        cat.process.signal_process(Signal::Sigint);
+        let code = cat.handle.wait_finished().await.unwrap();
+        assert_eq!(code, ExitCode::from(128 + Signal::Sigint as i32));
+    }
+
+    #[tokio::test]
+    #[cfg_attr(
+        not(feature = "sys-thread"),
+        ignore = "The tokio task manager isn't available on this platform"
+    )]
+    async fn test_realtime_signals_exit_with_their_number() {
+        let fs = TmpFileSystem::new();
+        let mut cat = spawn(&fs, &["cat"]).await;
+
+        cat.process.queue_signal(SIGRTMIN + 2).unwrap();
+        let code = cat.handle.wait_finished().await.unwrap();
+        assert_eq!(code, ExitCode::from(128 + SIGRTMIN as i32 + 2));
+    }
+
+    #[tokio::test]
+    #[cfg_attr(
+        not(feature = "sys-thread"),
+        ignore = "The tokio task manager isn't available on this platform"
+    )]
+    async fn test_coreutils_need_the_capability() {
+        let fs = TmpFileSystem::new();
+        let runtime = runtime();
+        let env = WasiEnv::builder("cat")
+            .sandbox_fs(fs.clone())
+            .runtime(runtime.clone())
+            .build()
+            .unwrap();
+        let err = BinFactory::new(runtime)
+            .spawn("cat".to_string(), env)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, SpawnError::BinaryNotFound { .. }), "{err}");
+    }
+}
@@ -0,0 +1,233 @@
+use std::{any::Any, fmt::Write as _, sync::Arc, time::Duration};
+
+use virtual_fs::AsyncWriteExt;
+use wasmer::FunctionEnvMut;
+use wasmer_config::app::CronExpression;
+use wasmer_wasix_types::wasi::Errno;
+
+use crate::{
+    os::{
+        command::VirtualCommand,
+        scheduler::{JobId, JobInfo, JobSchedule, JobSpec},
+        task::{OwnedTaskStatus, TaskJoinHandle},
+    },
+    runtime::task_manager::InlineWaker,
+    Runtime, SpawnError, WasiEnv,
+};
+
+const HELP: &str = r#"USAGE:
+    cron <SUBCOMMAND>
+
+SUBCOMMANDS:
+    add [OPTIONS] <SCHEDULE> <PACKAGE> [ARGS]...
+                   Run a package every time a cron expression fires
+    list           List the scheduled jobs
+    log <ID>       Show the output of the last runs of a job
+    remove <ID>    Remove a job
+
+OPTIONS:
+    --command <NAME>      Command of the package to run
+    --env <KEY=VALUE>     Environment variable of the job
+    --retries <N>         Retry failed runs up to N times
+    --timeout <DURATION>  Terminate runs that take longer (e.g. 30s, 5m)
+"#;
+
+/// Schedules packages to run periodically in the control plane of the
+/// process that calls it.
+#[derive(Debug, Clone)]
+pub struct CmdCron {
+    runtime: Arc<dyn Runtime + Send + Sync + 'static>,
+}
+
+impl CmdCron {
+    const NAME: &'static str = "cron";
+
+    pub fn new(runtime: Arc<dyn Runtime + Send + Sync + 'static>) -> Self {
+        Self { runtime }
+    }
+
+    fn run(
+        &self,
+        parent_ctx: &FunctionEnvMut<'_, WasiEnv>,
+        args: &[String],
+    ) -> Result<String, String> {
+        let control_plane = &parent_ctx.data().control_plane;
+        let scheduler = control_plane.scheduler();
+
+        match args.split_first() {
+            Some((cmd, args)) if cmd == "add" => {
+                let (options, args) = JobOptions::parse(args)?;
+                let (schedule, args) = args.split_first().ok_or(HELP)?;
+                let schedule = schedule
+                    .parse::<CronExpression>()
+                    .map_err(|err| err.to_string())?;
+                let spec = options.into_spec(JobSchedule::Cron(schedule), args)?;
+                let id = control_plane
+                    .schedule_job(self.runtime.clone(), spec)
+                    .map_err(|err| err.to_string())?;
+                Ok(format!("job {id}\n"))
+            }
+            Some((cmd, [])) if cmd == "list" => Ok(format_jobs(&scheduler.list())),
+            Some((cmd, [id])) if cmd == "log" => {
+                let job = scheduler
+                    .get(parse_job_id(id)?)
+                    .ok_or_else(|| format!("no such job: {id}"))?;
+                Ok(format_runs(&job))
+            }
+            Some((cmd, [id])) if cmd == "remove" => match scheduler.cancel(parse_job_id(id)?) {
+                true => Ok(String::new()),
+                false => Err(format!("no such job: {id}")),
+            },
+            _ => Err(HELP.to_string()),
+        }
+    }
+}
+
+impl VirtualCommand for CmdCron {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn exec(
+        &self,
+        parent_ctx: &FunctionEnvMut<'_, WasiEnv>,
+        _name: &str,
+        env: &mut Option<WasiEnv>,
+    ) -> Result<TaskJoinHandle, SpawnError> {
+        let env = env.as_ref().ok_or(SpawnError::UnknownError)?;
+        let args = env.state.args.lock().unwrap().clone();
+        let args = args.get(1..).unwrap_or_default();
+
+        let res = self.run(parent_ctx, args);
+        Ok(finish(env, res))
+    }
+}
+
+/// Options shared by `cron add` and `at`
+#[derive(Debug, Default)]
+pub(super) struct JobOptions {
+    command: Option<String>,
+    env: Vec<(String, String)>,
+    retries: u32,
+    timeout: Option<Duration>,
+}
+
+impl JobOptions {
+    /// Parses the options in front of the positional arguments
+    pub(super) fn parse(mut args: &[String]) -> Result<(Self, &[String]), String> {
+        let mut options = Self::default();
+        while let Some((option, rest)) = args.split_first() {
+            if !option.starts_with("--") {
+                break;
+            }
+            let (value, rest) = rest
+                .split_first()
+                .ok_or_else(|| format!("missing value for {option}"))?;
+            match option.as_str() {
+                "--command" => options.command = Some(value.clone()),
+                "--env" => {
+                    let (key, value) = value
+                        .split_once('=')
+                        .ok_or_else(|| format!("invalid environment variable: {value}"))?;
+                    options.env.push((key.to_string(), value.to_string()));
+                }
+                "--retries" => {
+                    options.retries = value
+                        .parse()
+                        .map_err(|_| format!("invalid number of retries: {value}"))?
+                }
+                "--timeout" => {
+                    let timeout = humantime::parse_duration(value)
+                        .map_err(|err| format!("invalid timeout {value}: {err}"))?;
+                    options.timeout = Some(timeout);
+                }
+                _ => return Err(format!("unknown option: {option}")),
+            }
+            args = rest;
+        }
+        Ok((options, args))
+    }
+
+    /// Turns the options and the `<PACKAGE> [ARGS]...` arguments into a job
+    pub(super) fn into_spec(
+        self,
+        schedule: JobSchedule,
+        args: &[String],
+    ) -> Result<JobSpec, String> {
+        let (package, args) = args.split_first().ok_or("missing package")?;
+        let package = package
+            .parse()
+            .map_err(|err| format!("invalid package {package}: {err}"))?;
+        Ok(JobSpec {
+            command: self.command,
+            args: args.to_vec(),
+            env: self.env,
+            retries: self.retries,
+            timeout: self.timeout,
+            ..JobSpec::new(package, schedule)
+        })
+    }
+}
+
+/// Writes the outcome of a command to stdout or stderr and returns its exit
+/// code
+pub(super) fn finish(env: &WasiEnv, res: Result<String, String>) -> TaskJoinHandle {
+    let (out, msg, code) = match res {
+        Ok(msg) => (env.stdout(), msg, Errno::Success),
+        Err(msg) => (env.stderr(), msg, Errno::Inval),
+    };
+    if let Ok(Some(mut out)) = out {
+        InlineWaker::block_on(out.write_all(msg.as_bytes())).ok();
+    }
+    OwnedTaskStatus::new_finished_with_code(code.into()).handle()
+}
+
+fn parse_job_id(id: &str) -> Result<JobId, String> {
+    id.parse().map_err(|_| format!("invalid job id: {id}"))
+}
+
+fn format_jobs(jobs: &[JobInfo]) -> String {
+    let mut out = String::new();
+    for job in jobs {
+        let next_run = match job.next_run {
+            Some(next_run) => next_run.to_rfc3339(),
+            None => "-".to_string(),
+        };
+        let last_result = match job.runs.last() {
+            Some(run) if run.is_success() => "ok",
+            Some(_) => "failed",
+            None => "-",
+        };
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}",
+            job.id, job.spec.schedule, next_run, last_result, job.spec.package
+        )
+        .ok();
+    }
+    out
+}
+
+fn format_runs(job: &JobInfo) -> String {
+    let mut out = String::new();
+    for run in &job.runs {
+        let result = match &run.result {
+            Ok(code) => format!("exit code {}", code.raw()),
+            Err(err) => err.clone(),
+        };
+        writeln!(
+            out,
+            "--- {} ({result}, {} attempt(s))",
+            run.started.to_rfc3339(),
+            run.attempts
+        )
+        .ok();
+        out.push_str(&String::from_utf8_lossy(&run.stdout));
+        out.push_str(&String::from_utf8_lossy(&run.stderr));
+    }
+    out
+}
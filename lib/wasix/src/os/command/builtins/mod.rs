@@ -1 +1,6 @@
+#[cfg(feature = "scheduler")]
+pub mod cmd_at;
+pub mod cmd_coreutils;
+#[cfg(feature = "scheduler")]
+pub mod cmd_cron;
 pub mod cmd_wasmer;
@@ -48,6 +48,11 @@ impl Commands {
         let mut cmd = Self::new();
         let cmd_wasmer = builtins::cmd_wasmer::CmdWasmer::new(runtime.clone());
         cmd.register_command(cmd_wasmer);
+        #[cfg(feature = "scheduler")]
+        {
+            cmd.register_command(builtins::cmd_cron::CmdCron::new(runtime.clone()));
+            cmd.register_command(builtins::cmd_at::CmdAt::new(runtime.clone()));
+        }
 
         cmd
     }
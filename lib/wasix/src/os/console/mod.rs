@@ -222,7 +222,10 @@ impl Console {
         let builder = crate::runners::wasi::WasiRunner::new()
             .with_envs(self.env.clone().into_iter())
             .with_args(args)
-            .with_capabilities(self.capabilities.clone())
+            .with_capabilities(Capabilities {
+                native_coreutils: true,
+                ..self.capabilities.clone()
+            })
             .with_stdin(Box::new(self.stdin.clone()))
             .with_stdout(Box::new(self.stdout.clone()))
             .with_stderr(Box::new(self.stderr.clone()))
@@ -1,5 +1,8 @@
 pub mod common;
 pub mod console;
+#[cfg(feature = "scheduler")]
+pub mod scheduler;
+pub mod shm;
 pub mod tty;
 
 pub mod command;
@@ -0,0 +1,410 @@
+//! Runs packages on a schedule inside a running control plane.
+//!
+//! Jobs either run every time a cron expression fires (like `cron`) or once
+//! at a given time (like `at`). Every run spawns a fresh process of the
+//! package in the control plane that the job was scheduled in, captures
+//! what it writes to stdout and stderr, and is retried when it fails.
+//!
+//! Jobs only run while the control plane is alive, there is no persistence
+//! across restarts.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Display,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use futures::future::{self, Either};
+use virtual_fs::{AsyncReadExt, NullFile, Pipe};
+use wasmer_config::{app::CronExpression, package::PackageSource};
+use wasmer_wasix_types::wasi::{Errno, ExitCode};
+
+use crate::{
+    bin_factory::{spawn_exec, BinaryPackage},
+    os::task::control_plane::{WasiControlPlane, WasiControlPlaneHandle},
+    runners::wasi::WasiRunner,
+    Runtime, WasiEnv, WasiThreadError,
+};
+
+/// Only the end of the output of a run is kept
+const MAX_CAPTURED_OUTPUT: usize = 64 * 1024;
+
+/// Number of runs that are kept for every job
+const MAX_JOB_RUNS: usize = 10;
+
+/// How long the output of a run is drained after the process exited
+const OUTPUT_GRACE_PERIOD: Duration = Duration::from_secs(1);
+
+pub type JobId = u64;
+
+/// When a job runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobSchedule {
+    /// Every time the cron expression fires
+    Cron(CronExpression),
+    /// Once, at the given time (straight away if it is in the past)
+    At(DateTime<Utc>),
+}
+
+impl JobSchedule {
+    /// Returns when the job is due next, after it last ran at `last_run`
+    pub fn next_run(
+        &self,
+        now: DateTime<Utc>,
+        last_run: Option<DateTime<Utc>>,
+    ) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Cron(expr) => saffron::Cron::new(expr.cron.clone()).next_after(now),
+            Self::At(_) if last_run.is_some() => None,
+            Self::At(at) => Some((*at).max(now)),
+        }
+    }
+}
+
+impl Display for JobSchedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cron(expr) => write!(f, "{}", expr.parsed_from),
+            Self::At(at) => write!(f, "at {}", at.to_rfc3339()),
+        }
+    }
+}
+
+/// A package and the way it is run by a job
+#[derive(Debug, Clone)]
+pub struct JobSpec {
+    pub package: PackageSource,
+    /// The command to run, defaults to the entrypoint of the package
+    pub command: Option<String>,
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub schedule: JobSchedule,
+    /// How many times a failed run is retried straight away
+    pub retries: u32,
+    /// Attempts that take longer than this are terminated
+    pub timeout: Option<Duration>,
+}
+
+impl JobSpec {
+    pub fn new(package: PackageSource, schedule: JobSchedule) -> Self {
+        Self {
+            package,
+            command: None,
+            args: Vec::new(),
+            env: Vec::new(),
+            schedule,
+            retries: 0,
+            timeout: None,
+        }
+    }
+}
+
+/// Outcome of one run of a job
+#[derive(Debug, Clone)]
+pub struct JobRun {
+    pub started: DateTime<Utc>,
+    pub finished: DateTime<Utc>,
+    /// Number of attempts, 1 unless the job had to be retried
+    pub attempts: u32,
+    /// Exit code of the last attempt, or why it couldn't be run
+    pub result: Result<ExitCode, String>,
+    /// End of what the last attempt wrote to stdout
+    pub stdout: Vec<u8>,
+    /// End of what the last attempt wrote to stderr
+    pub stderr: Vec<u8>,
+}
+
+impl JobRun {
+    pub fn is_success(&self) -> bool {
+        matches!(&self.result, Ok(code) if code.is_success())
+    }
+}
+
+/// Snapshot of a scheduled job
+#[derive(Debug, Clone)]
+pub struct JobInfo {
+    pub id: JobId,
+    pub spec: JobSpec,
+    pub next_run: Option<DateTime<Utc>>,
+    /// The most recent runs, oldest first
+    pub runs: Vec<JobRun>,
+}
+
+#[derive(Debug)]
+struct JobEntry {
+    spec: JobSpec,
+    next_run: Option<DateTime<Utc>>,
+    runs: VecDeque<JobRun>,
+    _cancel: tokio::sync::oneshot::Sender<()>,
+}
+
+/// Jobs that are scheduled in a control plane
+#[derive(Debug, Default)]
+pub struct JobScheduler {
+    next_id: AtomicU64,
+    jobs: Mutex<BTreeMap<JobId, JobEntry>>,
+}
+
+impl JobScheduler {
+    /// Adds a job, the runs are driven by a task on the task manager of the
+    /// runtime
+    pub(crate) fn schedule(
+        &self,
+        control_plane: WasiControlPlaneHandle,
+        runtime: Arc<dyn Runtime + Send + Sync>,
+        spec: JobSpec,
+    ) -> Result<JobId, WasiThreadError> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let (cancel_tx, cancel_rx) = tokio::sync::oneshot::channel();
+        let entry = JobEntry {
+            next_run: spec.schedule.next_run(Utc::now(), None),
+            spec,
+            runs: VecDeque::new(),
+            _cancel: cancel_tx,
+        };
+        self.jobs.lock().unwrap().insert(id, entry);
+
+        let tasks = runtime.task_manager().clone();
+        let res = tasks.task_shared(Box::new(move || {
+            Box::pin(drive_job(control_plane, runtime, id, cancel_rx))
+        }));
+        if let Err(err) = res {
+            self.jobs.lock().unwrap().remove(&id);
+            return Err(err);
+        }
+        Ok(id)
+    }
+
+    /// Removes a job, a run that is in progress is left to finish
+    pub fn cancel(&self, id: JobId) -> bool {
+        self.jobs.lock().unwrap().remove(&id).is_some()
+    }
+
+    pub fn get(&self, id: JobId) -> Option<JobInfo> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.get(&id).map(|entry| JobInfo::new(id, entry))
+    }
+
+    pub fn list(&self) -> Vec<JobInfo> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.iter()
+            .map(|(id, entry)| JobInfo::new(*id, entry))
+            .collect()
+    }
+}
+
+impl JobInfo {
+    fn new(id: JobId, entry: &JobEntry) -> Self {
+        Self {
+            id,
+            spec: entry.spec.clone(),
+            next_run: entry.next_run,
+            runs: entry.runs.iter().cloned().collect(),
+        }
+    }
+}
+
+/// Waits for a job to be due and runs it, until it is cancelled, has no
+/// more runs or the control plane goes away
+async fn drive_job(
+    control_plane: WasiControlPlaneHandle,
+    runtime: Arc<dyn Runtime + Send + Sync>,
+    id: JobId,
+    mut cancelled: tokio::sync::oneshot::Receiver<()>,
+) {
+    loop {
+        // The control plane isn't kept alive while waiting
+        let (spec, due) = {
+            let Some(control_plane) = control_plane.upgrade() else {
+                return;
+            };
+            let jobs = control_plane.scheduler().jobs.lock().unwrap();
+            let Some(entry) = jobs.get(&id) else {
+                return;
+            };
+            let Some(due) = entry.next_run else {
+                return;
+            };
+            (entry.spec.clone(), due)
+        };
+
+        let wait = (due - Utc::now()).to_std().unwrap_or_default();
+        let sleep = runtime.task_manager().sleep_now(wait);
+        if let Either::Right(_) = future::select(sleep, &mut cancelled).await {
+            return;
+        }
+
+        let Some(plane) = control_plane.upgrade() else {
+            return;
+        };
+        tracing::debug!(job = id, package = %spec.package, "Running a scheduled job");
+        let run = run_job(&plane, &runtime, &spec).await;
+        if !run.is_success() {
+            tracing::debug!(job = id, result = ?run.result, attempts = run.attempts, "Scheduled job failed");
+        }
+
+        let mut jobs = plane.scheduler().jobs.lock().unwrap();
+        let Some(entry) = jobs.get_mut(&id) else {
+            return;
+        };
+        entry.next_run = spec.schedule.next_run(Utc::now(), Some(run.started));
+        entry.runs.push_back(run);
+        if entry.runs.len() > MAX_JOB_RUNS {
+            entry.runs.pop_front();
+        }
+    }
+}
+
+/// Runs a job, retrying it as many times as it allows
+async fn run_job(
+    control_plane: &WasiControlPlane,
+    runtime: &Arc<dyn Runtime + Send + Sync>,
+    spec: &JobSpec,
+) -> JobRun {
+    let started = Utc::now();
+    let attempts = spec.retries.saturating_add(1);
+    let mut attempt = 1;
+
+    loop {
+        let (result, stdout, stderr) = run_once(control_plane, runtime, spec).await;
+        let run = JobRun {
+            started,
+            finished: Utc::now(),
+            attempts: attempt,
+            result,
+            stdout,
+            stderr,
+        };
+        if run.is_success() || attempt >= attempts {
+            return run;
+        }
+        attempt += 1;
+    }
+}
+
+async fn run_once(
+    control_plane: &WasiControlPlane,
+    runtime: &Arc<dyn Runtime + Send + Sync>,
+    spec: &JobSpec,
+) -> (Result<ExitCode, String>, Vec<u8>, Vec<u8>) {
+    let (stdout_tx, stdout_rx) = Pipe::channel();
+    let (stderr_tx, stderr_rx) = Pipe::channel();
+    let stdout = Arc::new(Mutex::new(Vec::new()));
+    let stderr = Arc::new(Mutex::new(Vec::new()));
+
+    let finished = async {
+        let pkg = BinaryPackage::from_registry(&spec.package, runtime.as_ref())
+            .await
+            .map_err(|err| format!("{err:#}"))?;
+        let command = match &spec.command {
+            Some(command) => command.clone(),
+            None => pkg
+                .infer_entrypoint()
+                .map_err(|err| format!("{err:#}"))?
+                .to_string(),
+        };
+
+        let wasi = webc::metadata::annotations::Wasi::new(&command);
+        let mut init = WasiRunner::new()
+            .with_args(spec.args.clone())
+            .with_envs(spec.env.clone())
+            .with_stdin(Box::<NullFile>::default())
+            .with_stdout(Box::new(stdout_tx))
+            .with_stderr(Box::new(stderr_tx))
+            .prepare_webc_env(&command, &wasi, Some(&pkg), runtime.clone(), None)
+            .and_then(|builder| Ok(builder.build_init()?))
+            .map_err(|err| format!("{err:#}"))?;
+        // The job runs next to the other processes of the control plane
+        init.control_plane = control_plane.clone();
+        let env = WasiEnv::from_init(init, pkg.hash()).map_err(|err| err.to_string())?;
+        let process = env.process.clone();
+
+        let mut handle = spawn_exec(pkg, &command, env, runtime)
+            .await
+            .map_err(|err| err.to_string())?;
+        let exit = match spec.timeout {
+            Some(timeout) => {
+                let sleep = runtime.task_manager().sleep_now(timeout);
+                match future::select(Box::pin(handle.wait_finished()), sleep).await {
+                    Either::Left((exit, _)) => exit,
+                    Either::Right(_) => {
+                        process.terminate(Errno::Timedout.into());
+                        return Err(format!(
+                            "timed out after {}",
+                            humantime::format_duration(timeout)
+                        ));
+                    }
+                }
+            }
+            None => handle.wait_finished().await,
+        };
+        exit.map_err(|err| err.to_string())
+    };
+
+    let capture = future::join(
+        capture(stdout_rx, stdout.clone()),
+        capture(stderr_rx, stderr.clone()),
+    );
+    let result = match future::select(Box::pin(finished), Box::pin(capture)).await {
+        Either::Left((result, capture)) => {
+            // Whatever is still buffered in the pipes belongs to the run
+            let sleep = runtime.task_manager().sleep_now(OUTPUT_GRACE_PERIOD);
+            future::select(capture, sleep).await;
+            result
+        }
+        Either::Right((_, finished)) => finished.await,
+    };
+
+    let stdout = std::mem::take(&mut *stdout.lock().unwrap());
+    let stderr = std::mem::take(&mut *stderr.lock().unwrap());
+    (result, stdout, stderr)
+}
+
+/// Reads a pipe until it is closed, keeping the end of what was written
+async fn capture(mut pipe: Pipe, output: Arc<Mutex<Vec<u8>>>) {
+    let mut buf = [0u8; 4096];
+    loop {
+        match pipe.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(read) => {
+                let mut output = output.lock().unwrap();
+                output.extend_from_slice(&buf[..read]);
+                if output.len() > MAX_CAPTURED_OUTPUT {
+                    let excess = output.len() - MAX_CAPTURED_OUTPUT;
+                    output.drain(..excess);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_next_run() {
+        let now = Utc.with_ymd_and_hms(2024, 5, 1, 10, 15, 30).unwrap();
+
+        let hourly = JobSchedule::Cron("@hourly".parse().unwrap());
+        let next = Utc.with_ymd_and_hms(2024, 5, 1, 11, 0, 0).unwrap();
+        assert_eq!(hourly.next_run(now, None), Some(next));
+        assert_eq!(hourly.next_run(now, Some(now)), Some(next));
+
+        let later = Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+        let at = JobSchedule::At(later);
+        assert_eq!(at.next_run(now, None), Some(later));
+        assert_eq!(at.next_run(now, Some(later)), None);
+
+        // Times in the past are due straight away
+        let earlier = Utc.with_ymd_and_hms(2024, 4, 1, 0, 0, 0).unwrap();
+        assert_eq!(JobSchedule::At(earlier).next_run(now, None), Some(now));
+    }
+}
@@ -0,0 +1,704 @@
+//! Named shared memory objects that processes can map into their memory.
+//!
+//! A [`SharedMemoryObject`] is created with `shm_open` and lives in the
+//! [`SharedMemoryNamespace`] of the control plane until it is unlinked and
+//! the last file descriptor or mapping referring to it goes away. Each object
+//! is backed by an unnamed host file that is mapped with `MAP_SHARED`, so any
+//! number of processes can place a view of it over a range of their linear
+//! memory and see each others writes immediately.
+//!
+//! Mappings replace the host pages of the guest memory with pages of the
+//! file, they are not `VMSharedMemory` instances and the engine does not know
+//! about them. This has two restrictions:
+//!
+//! - they are only available on unix hosts with the `sys` feature;
+//! - the memory must be a static one (the kind that 32-bit guests get on
+//!   64-bit hosts), a dynamic memory is reallocated when it grows which
+//!   would silently drop the mapping. Other memories fail with `Notsup`.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+use virtual_fs::{FsError, VirtualFile};
+use wasmer::{AsStoreRef, Memory, MemoryStyle, MemoryView, WASM_PAGE_SIZE};
+use wasmer_wasix_types::wasi::Errno;
+
+/// Longest name that a shared memory object can have
+pub const SHM_NAME_MAX: usize = 255;
+
+/// Mappings must start and end on a WebAssembly page boundary, which is a
+/// multiple of the page size of every host
+pub const SHM_MAP_ALIGN: u64 = WASM_PAGE_SIZE as u64;
+
+/// Shared memory object created with `shm_open`
+#[derive(Debug)]
+pub struct SharedMemoryObject {
+    name: String,
+    state: Mutex<SharedMemoryState>,
+}
+
+#[derive(Debug)]
+struct SharedMemoryState {
+    file: File,
+    /// Ends (offsets into the object) of the regions that are currently
+    /// mapped by any process, with the number of mappings that end there.
+    /// The object can't shrink below the furthest one as the host would fault
+    /// on the missing pages.
+    mapped_ends: BTreeMap<u64, usize>,
+}
+
+impl SharedMemoryObject {
+    fn new(name: String) -> io::Result<Self> {
+        Ok(Self {
+            name,
+            state: Mutex::new(SharedMemoryState {
+                file: tempfile::tempfile()?,
+                mapped_ends: BTreeMap::new(),
+            }),
+        })
+    }
+
+    /// Name the object was created with (without the leading `/`)
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Current size of the object in bytes
+    pub fn size(&self) -> u64 {
+        self.state.lock().unwrap().size()
+    }
+
+    /// Resizes the object, new bytes are zeroed
+    pub fn set_len(&self, len: u64) -> Result<(), FsError> {
+        let state = self.state.lock().unwrap();
+        if len < state.mapped_len() {
+            return Err(FsError::PermissionDenied);
+        }
+        state.file.set_len(len).map_err(Into::into)
+    }
+
+    /// Reads bytes from the object starting at `offset`
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        state.file.seek(SeekFrom::Start(offset))?;
+        state.file.read(buf)
+    }
+
+    /// Writes bytes into the object starting at `offset`, growing it if needed
+    pub fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        state.file.seek(SeekFrom::Start(offset))?;
+        state.file.write(buf)
+    }
+}
+
+impl SharedMemoryState {
+    fn size(&self) -> u64 {
+        self.file.metadata().map(|m| m.len()).unwrap_or(0)
+    }
+
+    /// End of the furthest region that is currently mapped
+    fn mapped_len(&self) -> u64 {
+        self.mapped_ends.keys().next_back().copied().unwrap_or(0)
+    }
+
+    fn add_mapped(&mut self, end: u64) {
+        *self.mapped_ends.entry(end).or_default() += 1;
+    }
+
+    fn remove_mapped(&mut self, end: u64) {
+        if let Some(count) = self.mapped_ends.get_mut(&end) {
+            *count -= 1;
+            if *count == 0 {
+                self.mapped_ends.remove(&end);
+            }
+        }
+    }
+}
+
+/// All the named shared memory objects of a control plane
+#[derive(Debug, Default)]
+pub struct SharedMemoryNamespace {
+    objects: Mutex<HashMap<String, Arc<SharedMemoryObject>>>,
+}
+
+impl SharedMemoryNamespace {
+    /// Opens the named object, creating it when `create` is set. Fails with
+    /// `Exist` if the object already exists and `exclusive` is set.
+    pub fn open(
+        &self,
+        name: &str,
+        create: bool,
+        exclusive: bool,
+    ) -> Result<Arc<SharedMemoryObject>, Errno> {
+        let name = Self::normalize(name)?;
+        let mut objects = self.objects.lock().unwrap();
+        if let Some(object) = objects.get(name) {
+            if create && exclusive {
+                return Err(Errno::Exist);
+            }
+            return Ok(object.clone());
+        }
+        if !create {
+            return Err(Errno::Noent);
+        }
+
+        let object = SharedMemoryObject::new(name.to_string()).map_err(|err| {
+            tracing::warn!("failed to create the shared memory object - {}", err);
+            Errno::Nomem
+        })?;
+        let object = Arc::new(object);
+        objects.insert(name.to_string(), object.clone());
+        Ok(object)
+    }
+
+    /// Removes the name of an object, it is freed once it is no longer open
+    /// or mapped by any process
+    pub fn unlink(&self, name: &str) -> Result<(), Errno> {
+        let name = Self::normalize(name)?;
+        let mut objects = self.objects.lock().unwrap();
+        objects.remove(name).map(|_| ()).ok_or(Errno::Noent)
+    }
+
+    /// Names are a single path component which may start with a `/`
+    fn normalize(name: &str) -> Result<&str, Errno> {
+        let name = name.strip_prefix('/').unwrap_or(name);
+        if name.len() > SHM_NAME_MAX {
+            return Err(Errno::Nametoolong);
+        }
+        if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+            return Err(Errno::Inval);
+        }
+        Ok(name)
+    }
+}
+
+/// File handle that refers to a shared memory object
+#[derive(Debug)]
+pub struct SharedMemoryFile {
+    object: Arc<SharedMemoryObject>,
+    cursor: u64,
+}
+
+impl SharedMemoryFile {
+    pub fn new(object: Arc<SharedMemoryObject>) -> Self {
+        Self { object, cursor: 0 }
+    }
+
+    pub fn object(&self) -> &Arc<SharedMemoryObject> {
+        &self.object
+    }
+}
+
+impl AsyncSeek for SharedMemoryFile {
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let cursor = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.object.size().checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.cursor.checked_add_signed(delta),
+        };
+        self.cursor = cursor.ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(self.cursor))
+    }
+}
+
+impl AsyncRead for SharedMemoryFile {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let read = self
+            .object
+            .read_at(buf.initialize_unfilled(), self.cursor)?;
+        buf.advance(read);
+        self.cursor += read as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for SharedMemoryFile {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let written = self.object.write_at(buf, self.cursor)?;
+        self.cursor += written as u64;
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl VirtualFile for SharedMemoryFile {
+    fn last_accessed(&self) -> u64 {
+        0
+    }
+
+    fn last_modified(&self) -> u64 {
+        0
+    }
+
+    fn created_time(&self) -> u64 {
+        0
+    }
+
+    fn size(&self) -> u64 {
+        self.object.size()
+    }
+
+    fn set_len(&mut self, new_size: u64) -> virtual_fs::Result<()> {
+        self.object.set_len(new_size)
+    }
+
+    fn unlink(&mut self) -> virtual_fs::Result<()> {
+        Ok(())
+    }
+
+    fn poll_read_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
+        let available = self.object.size().saturating_sub(self.cursor);
+        Poll::Ready(Ok(available as usize))
+    }
+
+    fn poll_write_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
+        Poll::Ready(Ok(8192))
+    }
+}
+
+/// Region of a shared memory object that is mapped into a process
+#[derive(Debug, Clone)]
+pub struct SharedMemoryMapping {
+    /// Guest address of the start of the mapping
+    pub addr: u64,
+    /// Length of the mapping in bytes
+    pub len: u64,
+    /// Offset into the object that the mapping starts at
+    pub offset: u64,
+    pub object: Arc<SharedMemoryObject>,
+}
+
+impl SharedMemoryMapping {
+    fn end(&self) -> u64 {
+        self.addr + self.len
+    }
+
+    /// Offset into the object that the mapping ends at
+    fn object_end(&self) -> u64 {
+        self.offset + self.len
+    }
+
+    /// Places the mapping over the guest memory, a mapping that was applied
+    /// must be recorded in the [`SharedMemoryMappings`] of the process which
+    /// releases it when it is removed
+    ///
+    /// # Safety
+    ///
+    /// The memory must not be resized while this runs and any reference
+    /// into the mapped range of the memory is invalidated.
+    pub(crate) unsafe fn apply(
+        &self,
+        memory: &Memory,
+        store: &impl AsStoreRef,
+    ) -> Result<(), Errno> {
+        check_static(memory, store)?;
+        let memory = memory.view(store);
+        check_range(&memory, self.addr, self.len)?;
+        if self.offset % SHM_MAP_ALIGN != 0 {
+            return Err(Errno::Inval);
+        }
+        let end = self.offset.checked_add(self.len).ok_or(Errno::Inval)?;
+
+        // The object can't shrink between the size check and the mapping
+        let mut state = self.object.state.lock().unwrap();
+        if end > state.size() {
+            return Err(Errno::Nxio);
+        }
+        host::map_file(&memory, self.addr, self.len, &state.file, self.offset)?;
+        state.add_mapped(end);
+        Ok(())
+    }
+}
+
+/// Detaches a range of guest memory from whatever is mapped there, the range
+/// is left filled with zeros
+///
+/// # Safety
+///
+/// The memory must not be resized while this runs and any reference into
+/// the range is invalidated.
+pub(crate) unsafe fn unmap_range(
+    memory: &Memory,
+    store: &impl AsStoreRef,
+    addr: u64,
+    len: u64,
+) -> Result<(), Errno> {
+    check_static(memory, store)?;
+    let memory = memory.view(store);
+    check_range(&memory, addr, len)?;
+    host::map_zeroed(&memory, addr, len)
+}
+
+/// Host pages can only be replaced in memories that never move, a dynamic
+/// memory is reallocated when it grows which would silently drop the mapping
+fn check_static(memory: &Memory, store: &impl AsStoreRef) -> Result<(), Errno> {
+    match memory.style(store) {
+        Some(MemoryStyle::Static { .. }) => Ok(()),
+        _ => Err(Errno::Notsup),
+    }
+}
+
+/// Makes sure that a range is aligned and lies within the memory
+fn check_range(memory: &MemoryView, addr: u64, len: u64) -> Result<(), Errno> {
+    if len == 0 || addr % SHM_MAP_ALIGN != 0 || len % SHM_MAP_ALIGN != 0 {
+        return Err(Errno::Inval);
+    }
+    match addr.checked_add(len) {
+        Some(end) if end <= memory.data_size() => Ok(()),
+        _ => Err(Errno::Nomem),
+    }
+}
+
+/// Shared memory regions that are mapped into a process, kept sorted and
+/// without overlaps. The regions are released when they are removed or the
+/// process goes away.
+#[derive(Debug, Default)]
+pub struct SharedMemoryMappings {
+    mappings: Vec<SharedMemoryMapping>,
+}
+
+impl SharedMemoryMappings {
+    /// Adds a mapping, replacing whatever was mapped in that range before
+    pub fn insert(&mut self, mapping: SharedMemoryMapping) {
+        self.remove(mapping.addr, mapping.len);
+        let idx = self.mappings.partition_point(|m| m.addr < mapping.addr);
+        self.mappings.insert(idx, mapping);
+    }
+
+    /// Removes a range, splitting any mapping that only partially overlaps it
+    pub fn remove(&mut self, addr: u64, len: u64) {
+        let end = addr + len;
+        let mut mappings = Vec::with_capacity(self.mappings.len() + 1);
+        for mapping in self.mappings.drain(..) {
+            if mapping.end() <= addr || mapping.addr >= end {
+                mappings.push(mapping);
+                continue;
+            }
+
+            // The pieces that are left are still mapped, the rest of the
+            // mapping no longer keeps the object from shrinking
+            let mut state = mapping.object.state.lock().unwrap();
+            if mapping.addr < addr {
+                let piece = SharedMemoryMapping {
+                    len: addr - mapping.addr,
+                    ..mapping.clone()
+                };
+                state.add_mapped(piece.object_end());
+                mappings.push(piece);
+            }
+            if mapping.end() > end {
+                let piece = SharedMemoryMapping {
+                    addr: end,
+                    len: mapping.end() - end,
+                    offset: mapping.offset + (end - mapping.addr),
+                    object: mapping.object.clone(),
+                };
+                state.add_mapped(piece.object_end());
+                mappings.push(piece);
+            }
+            state.remove_mapped(mapping.object_end());
+        }
+        self.mappings = mappings;
+    }
+
+    pub fn clear(&mut self) {
+        for mapping in self.mappings.drain(..) {
+            let mut state = mapping.object.state.lock().unwrap();
+            state.remove_mapped(mapping.object_end());
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SharedMemoryMapping> {
+        self.mappings.iter()
+    }
+}
+
+impl Drop for SharedMemoryMappings {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(all(unix, feature = "sys"))]
+mod host {
+    use std::{fs::File, io, os::fd::AsRawFd};
+
+    use wasmer::MemoryView;
+    use wasmer_wasix_types::wasi::Errno;
+
+    pub(super) unsafe fn map_file(
+        memory: &MemoryView,
+        addr: u64,
+        len: u64,
+        file: &File,
+        offset: u64,
+    ) -> Result<(), Errno> {
+        map_fixed(
+            memory,
+            addr,
+            len,
+            libc::MAP_SHARED,
+            file.as_raw_fd(),
+            offset as libc::off_t,
+        )
+    }
+
+    pub(super) unsafe fn map_zeroed(memory: &MemoryView, addr: u64, len: u64) -> Result<(), Errno> {
+        map_fixed(memory, addr, len, libc::MAP_PRIVATE | libc::MAP_ANON, -1, 0)
+    }
+
+    unsafe fn map_fixed(
+        memory: &MemoryView,
+        addr: u64,
+        len: u64,
+        flags: libc::c_int,
+        fd: libc::c_int,
+        offset: libc::off_t,
+    ) -> Result<(), Errno> {
+        let ptr = memory.data_ptr().add(addr as usize);
+        let ret = libc::mmap(
+            ptr as *mut libc::c_void,
+            len as usize,
+            libc::PROT_READ | libc::PROT_WRITE,
+            flags | libc::MAP_FIXED,
+            fd,
+            offset,
+        );
+        if ret == libc::MAP_FAILED {
+            let err = io::Error::last_os_error();
+            tracing::warn!("failed to map shared memory into the guest - {}", err);
+            return Err(Errno::Nomem);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(all(unix, feature = "sys")))]
+mod host {
+    use std::fs::File;
+
+    use wasmer::MemoryView;
+    use wasmer_wasix_types::wasi::Errno;
+
+    pub(super) unsafe fn map_file(
+        _memory: &MemoryView,
+        _addr: u64,
+        _len: u64,
+        _file: &File,
+        _offset: u64,
+    ) -> Result<(), Errno> {
+        Err(Errno::Notsup)
+    }
+
+    pub(super) unsafe fn map_zeroed(
+        _memory: &MemoryView,
+        _addr: u64,
+        _len: u64,
+    ) -> Result<(), Errno> {
+        Err(Errno::Notsup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(object: &Arc<SharedMemoryObject>, addr: u64, len: u64) -> SharedMemoryMapping {
+        SharedMemoryMapping {
+            addr,
+            len,
+            offset: 0,
+            object: object.clone(),
+        }
+    }
+
+    #[test]
+    fn test_namespace_open_and_unlink() {
+        let ns = SharedMemoryNamespace::default();
+        assert_eq!(ns.open("/db", false, false).unwrap_err(), Errno::Noent);
+        let a = ns.open("/db", true, true).unwrap();
+        assert_eq!(ns.open("db", true, true).unwrap_err(), Errno::Exist);
+        let b = ns.open("db", true, false).unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(ns.open("/a/b", true, false).unwrap_err(), Errno::Inval);
+
+        a.write_at(b"hello", 2).unwrap();
+        assert_eq!(b.size(), 7);
+        let mut buf = [0u8; 7];
+        b.read_at(&mut buf, 0).unwrap();
+        assert_eq!(&buf, b"\0\0hello");
+
+        ns.unlink("/db").unwrap();
+        assert_eq!(ns.unlink("/db").unwrap_err(), Errno::Noent);
+        let c = ns.open("/db", true, true).unwrap();
+        assert!(!Arc::ptr_eq(&a, &c));
+    }
+
+    #[cfg(all(unix, feature = "sys"))]
+    #[test]
+    fn test_mapping_is_shared_between_processes() {
+        use wasmer::{MemoryType, Store};
+
+        let ns = SharedMemoryNamespace::default();
+        let object = ns.open("/shared", true, false).unwrap();
+        object.set_len(2 * SHM_MAP_ALIGN).unwrap();
+        let mapping = SharedMemoryMapping {
+            addr: SHM_MAP_ALIGN,
+            len: 2 * SHM_MAP_ALIGN,
+            offset: 0,
+            object: object.clone(),
+        };
+
+        // Each process has its own store and memory
+        let mut store_a = Store::default();
+        let memory_a = Memory::new(&mut store_a, MemoryType::new(4, Some(16), false)).unwrap();
+        let mut store_b = Store::default();
+        let memory_b = Memory::new(&mut store_b, MemoryType::new(4, Some(16), false)).unwrap();
+        unsafe {
+            mapping.apply(&memory_a, &store_a).unwrap();
+            mapping.apply(&memory_b, &store_b).unwrap();
+        }
+
+        let mut buf = [0u8; 5];
+        memory_a
+            .view(&store_a)
+            .write(SHM_MAP_ALIGN + 5, b"hello")
+            .unwrap();
+        memory_b
+            .view(&store_b)
+            .read(SHM_MAP_ALIGN + 5, &mut buf)
+            .unwrap();
+        assert_eq!(&buf, b"hello");
+
+        memory_b
+            .view(&store_b)
+            .write(2 * SHM_MAP_ALIGN, b"world")
+            .unwrap();
+        memory_a
+            .view(&store_a)
+            .read(2 * SHM_MAP_ALIGN, &mut buf)
+            .unwrap();
+        assert_eq!(&buf, b"world");
+        object.read_at(&mut buf, SHM_MAP_ALIGN).unwrap();
+        assert_eq!(&buf, b"world");
+
+        // Growing a static memory keeps the mapping in place
+        memory_a.grow(&mut store_a, 1).unwrap();
+        memory_a
+            .view(&store_a)
+            .write(SHM_MAP_ALIGN, b"again")
+            .unwrap();
+        memory_b
+            .view(&store_b)
+            .read(SHM_MAP_ALIGN, &mut buf)
+            .unwrap();
+        assert_eq!(&buf, b"again");
+
+        // The object can't shrink below what is mapped
+        assert_eq!(
+            object.set_len(SHM_MAP_ALIGN),
+            Err(FsError::PermissionDenied)
+        );
+        let outside = SharedMemoryMapping {
+            offset: 2 * SHM_MAP_ALIGN,
+            ..mapping
+        };
+        assert_eq!(
+            unsafe { outside.apply(&memory_a, &store_a) },
+            Err(Errno::Nxio)
+        );
+    }
+
+    #[cfg(all(unix, feature = "sys"))]
+    #[test]
+    fn test_unmap_then_truncate() {
+        use wasmer::{MemoryType, Store};
+
+        let ns = SharedMemoryNamespace::default();
+        let object = ns.open("/truncate", true, false).unwrap();
+        object.set_len(3 * SHM_MAP_ALIGN).unwrap();
+        let mut store = Store::default();
+        let memory = Memory::new(&mut store, MemoryType::new(4, Some(16), false)).unwrap();
+        let mut mappings = SharedMemoryMappings::default();
+
+        let whole = mapping(&object, 0, 3 * SHM_MAP_ALIGN);
+        unsafe { whole.apply(&memory, &store).unwrap() };
+        mappings.insert(whole);
+        assert_eq!(
+            object.set_len(2 * SHM_MAP_ALIGN),
+            Err(FsError::PermissionDenied)
+        );
+
+        // Unmapping the tail lets the object shrink down to what is left
+        unsafe { unmap_range(&memory, &store, 2 * SHM_MAP_ALIGN, SHM_MAP_ALIGN).unwrap() };
+        mappings.remove(2 * SHM_MAP_ALIGN, SHM_MAP_ALIGN);
+        object.set_len(2 * SHM_MAP_ALIGN).unwrap();
+        assert_eq!(
+            object.set_len(SHM_MAP_ALIGN),
+            Err(FsError::PermissionDenied)
+        );
+
+        // Once nothing is mapped the object can be truncated to nothing
+        unsafe { unmap_range(&memory, &store, 0, 2 * SHM_MAP_ALIGN).unwrap() };
+        mappings.remove(0, 2 * SHM_MAP_ALIGN);
+        object.set_len(0).unwrap();
+        assert_eq!(object.size(), 0);
+    }
+
+    #[test]
+    fn test_mappings_split_on_remove() {
+        let ns = SharedMemoryNamespace::default();
+        let object = ns.open("/buf", true, false).unwrap();
+        let mut mappings = SharedMemoryMappings::default();
+        mappings.insert(mapping(&object, 0x10000, 0x40000));
+        mappings.remove(0x20000, 0x10000);
+
+        let ranges: Vec<_> = mappings.iter().map(|m| (m.addr, m.len, m.offset)).collect();
+        assert_eq!(
+            ranges,
+            vec![(0x10000, 0x10000, 0), (0x30000, 0x20000, 0x20000)]
+        );
+
+        mappings.insert(mapping(&object, 0x40000, 0x20000));
+        let ranges: Vec<_> = mappings.iter().map(|m| (m.addr, m.len, m.offset)).collect();
+        assert_eq!(
+            ranges,
+            vec![
+                (0x10000, 0x10000, 0),
+                (0x30000, 0x10000, 0x20000),
+                (0x40000, 0x20000, 0)
+            ]
+        );
+    }
+}
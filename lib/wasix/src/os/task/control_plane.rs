@@ -1,5 +1,6 @@
 use std::{
     collections::HashMap,
+    path::PathBuf,
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc, RwLock,
@@ -7,8 +8,16 @@ use std::{
     time::Duration,
 };
 
-use crate::{WasiProcess, WasiProcessId};
+use crate::{
+    net::unix::UnixSocketNamespace, os::shm::SharedMemoryNamespace, WasiProcess, WasiProcessId,
+};
+#[cfg(feature = "scheduler")]
+use crate::{
+    os::scheduler::{JobId, JobScheduler, JobSpec},
+    Runtime, WasiThreadError,
+};
 use wasmer_types::ModuleHash;
+use wasmer_wasix_types::wasi::Signal;
 
 #[derive(Debug, Clone)]
 pub struct WasiControlPlane {
@@ -48,6 +57,12 @@ pub struct ControlPlaneConfig {
     /// time that it will pause the CPU)
     /// (default = off)
     pub enable_exponential_cpu_backoff: Option<Duration>,
+    /// Directory that core dumps of processes and threads that trap are
+    /// written to (default = off)
+    pub coredump_dir: Option<PathBuf>,
+    /// File that the core dump of the root process is written to when its
+    /// main thread traps (default = off)
+    pub coredump_on_trap: Option<PathBuf>,
 }
 
 impl ControlPlaneConfig {
@@ -56,6 +71,8 @@ impl ControlPlaneConfig {
             max_task_count: None,
             enable_asynchronous_threading: false,
             enable_exponential_cpu_backoff: None,
+            coredump_dir: None,
+            coredump_on_trap: None,
         }
     }
 }
@@ -73,6 +90,16 @@ struct State {
     /// Total number of active tasks (threads) across all processes.
     task_count: Arc<AtomicUsize>,
 
+    /// Named shared memory objects (`shm_open`)
+    shared_memory: SharedMemoryNamespace,
+
+    /// Paths that unix sockets are bound to
+    unix_sockets: Arc<UnixSocketNamespace>,
+
+    /// Jobs that run packages on a schedule
+    #[cfg(feature = "scheduler")]
+    scheduler: JobScheduler,
+
     /// Mutable state.
     mutable: RwLock<MutableState>,
 }
@@ -92,6 +119,10 @@ impl WasiControlPlane {
             state: Arc::new(State {
                 config,
                 task_count: Arc::new(AtomicUsize::new(0)),
+                shared_memory: Default::default(),
+                unix_sockets: Default::default(),
+                #[cfg(feature = "scheduler")]
+                scheduler: Default::default(),
                 mutable: RwLock::new(MutableState {
                     process_seed: 0,
                     processes: Default::default(),
@@ -114,6 +145,33 @@ impl WasiControlPlane {
         &self.state.config
     }
 
+    /// Returns the named shared memory objects of this control plane
+    pub fn shared_memory(&self) -> &SharedMemoryNamespace {
+        &self.state.shared_memory
+    }
+
+    /// Returns the paths that unix sockets are bound to
+    pub fn unix_sockets(&self) -> &Arc<UnixSocketNamespace> {
+        &self.state.unix_sockets
+    }
+
+    /// Returns the jobs that are scheduled in this control plane
+    #[cfg(feature = "scheduler")]
+    pub fn scheduler(&self) -> &JobScheduler {
+        &self.state.scheduler
+    }
+
+    /// Schedules a package to run in this control plane, the runs are
+    /// spawned with the given runtime
+    #[cfg(feature = "scheduler")]
+    pub fn schedule_job(
+        &self,
+        runtime: Arc<dyn Runtime + Send + Sync>,
+        spec: JobSpec,
+    ) -> Result<JobId, WasiThreadError> {
+        self.state.scheduler.schedule(self.handle(), runtime, spec)
+    }
+
     /// Register a new task.
     ///
     // Currently just increments the task counter.
@@ -169,6 +227,40 @@ impl WasiControlPlane {
     }
 }
 
+impl WasiControlPlane {
+    /// Returns the processes of a process group that are still running
+    pub fn process_group(&self, pgid: WasiProcessId) -> Vec<WasiProcess> {
+        self.state
+            .mutable
+            .read()
+            .unwrap()
+            .processes
+            .values()
+            .filter(|process| process.try_join().is_none() && process.pgid() == pgid)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns true if a running process of the session belongs to the
+    /// process group
+    pub fn session_has_group(&self, sid: WasiProcessId, pgid: WasiProcessId) -> bool {
+        self.process_group(pgid)
+            .iter()
+            .any(|process| process.sid() == sid)
+    }
+
+    /// Sends a signal to every process of a process group, returns false if
+    /// the group has no running process
+    pub fn signal_process_group(&self, pgid: WasiProcessId, signal: Signal) -> bool {
+        let group = self.process_group(pgid);
+        tracing::trace!(%pgid, members = group.len(), "signal-process-group({:?})", signal);
+        for process in group.iter() {
+            process.signal_process(signal);
+        }
+        !group.is_empty()
+    }
+}
+
 impl MutableState {
     fn next_process_id(&mut self) -> Result<WasiProcessId, ControlPlaneError> {
         // TODO: reuse terminated ids, handle wrap-around, ...
@@ -211,9 +303,20 @@ pub enum ControlPlaneError {
 
 #[cfg(test)]
 mod tests {
-    use wasmer_wasix_types::wasix::ThreadStartType;
-
-    use crate::{os::task::thread::WasiMemoryLayout, utils::xxhash_random};
+    use wasmer_wasix_types::{
+        types::Signal,
+        wasi::{Errno, SigAction, SigActionFlags, SigDisposition},
+        wasix::ThreadStartType,
+    };
+
+    use crate::{
+        os::task::{
+            process::ChildStatus,
+            signal::{SignalSet, SIGRTMIN},
+            thread::WasiMemoryLayout,
+        },
+        utils::xxhash_random,
+    };
 
     use super::*;
 
@@ -224,6 +327,8 @@ mod tests {
             max_task_count: Some(2),
             enable_asynchronous_threading: false,
             enable_exponential_cpu_backoff: None,
+            coredump_dir: None,
+            coredump_on_trap: None,
         });
 
         let p1 = p.new_process(xxhash_random()).unwrap();
@@ -247,6 +352,8 @@ mod tests {
             max_task_count: Some(2),
             enable_asynchronous_threading: false,
             enable_exponential_cpu_backoff: None,
+            coredump_dir: None,
+            coredump_on_trap: None,
         });
 
         let p1 = p.new_process(xxhash_random()).unwrap();
@@ -269,4 +376,219 @@ mod tests {
             ControlPlaneError::TaskLimitReached { max: 2 }
         );
     }
+
+    #[test]
+    fn test_control_plane_process_groups() {
+        let p = WasiControlPlane::new(ControlPlaneConfig::new());
+
+        let leader = p.new_process(xxhash_random()).unwrap();
+        let child = p.new_process(xxhash_random()).unwrap();
+        child.inherit_process_group(&leader);
+        let other = p.new_process(xxhash_random()).unwrap();
+
+        assert_eq!(leader.pgid(), leader.pid());
+        assert_eq!(child.pgid(), leader.pid());
+        assert_eq!(child.sid(), leader.pid());
+        assert_eq!(other.pgid(), other.pid());
+
+        let group: Vec<_> = p
+            .process_group(leader.pid())
+            .iter()
+            .map(|process| process.pid())
+            .collect();
+        assert_eq!(group.len(), 2);
+        assert!(group.contains(&leader.pid()));
+        assert!(group.contains(&child.pid()));
+
+        // The leader of a group can't start a new session but its child can
+        assert_eq!(leader.setsid(), Err(Errno::Perm));
+        assert_eq!(child.setsid(), Ok(child.pid()));
+        assert_eq!(child.sid(), child.pid());
+        assert!(p.session_has_group(leader.sid(), leader.pid()));
+        assert!(!p.session_has_group(leader.sid(), child.pid()));
+    }
+
+    #[test]
+    fn test_control_plane_blocked_signals_stay_pending() {
+        let p = WasiControlPlane::new(ControlPlaneConfig::new());
+        let p1 = p.new_process(xxhash_random()).unwrap();
+        let t1 = p1
+            .new_thread(WasiMemoryLayout::default(), ThreadStartType::MainThread)
+            .unwrap();
+        let thread = t1.as_thread();
+
+        let blocked: SignalSet = [Signal::Sigint as u8, Signal::Sigkill as u8]
+            .into_iter()
+            .collect();
+        thread.set_signal_mask(blocked);
+        assert_eq!(
+            thread.signal_mask().iter().collect::<Vec<_>>(),
+            [Signal::Sigint as u8]
+        );
+
+        thread.signal(Signal::Sigint);
+        thread.signal(Signal::Sigint);
+        thread.signal(Signal::Sigusr1);
+        assert!(thread.pending_signals().contains(Signal::Sigint as u8));
+        assert!(!thread.deliverable_signals().contains(Signal::Sigint as u8));
+
+        // Standard signals are merged and blocked ones are left behind
+        assert_eq!(thread.pop_signals(), [Signal::Sigusr1 as u8]);
+        assert_eq!(thread.pop_signals(), Vec::<u8>::new());
+
+        thread.set_signal_mask(SignalSet::empty());
+        assert_eq!(thread.pop_signals(), [Signal::Sigint as u8]);
+        assert!(thread.pending_signals().is_empty());
+    }
+
+    #[test]
+    fn test_control_plane_realtime_signals_are_queued_in_order() {
+        let p = WasiControlPlane::new(ControlPlaneConfig::new());
+        let p1 = p.new_process(xxhash_random()).unwrap();
+        let t1 = p1
+            .new_thread(WasiMemoryLayout::default(), ThreadStartType::MainThread)
+            .unwrap();
+        let thread = t1.as_thread();
+
+        thread.set_signal_mask([SIGRTMIN + 1].into_iter().collect());
+        p1.queue_signal(SIGRTMIN + 2).unwrap();
+        p1.queue_signal(SIGRTMIN + 1).unwrap();
+        p1.queue_signal(SIGRTMIN).unwrap();
+        p1.queue_signal(SIGRTMIN + 2).unwrap();
+        p1.queue_signal(Signal::Sighup as u8).unwrap();
+
+        // Standard signals come first, then real-time ones lowest first and
+        // without merging instances of the same signal
+        assert_eq!(
+            thread.pop_signals(),
+            [Signal::Sighup as u8, SIGRTMIN, SIGRTMIN + 2, SIGRTMIN + 2]
+        );
+
+        thread.set_signal_mask(SignalSet::empty());
+        assert_eq!(thread.pop_signals(), [SIGRTMIN + 1]);
+        assert_eq!(p1.queue_signal(0), Err(Errno::Inval));
+    }
+
+    #[test]
+    fn test_control_plane_signals_restart_with_sa_restart() {
+        let p = WasiControlPlane::new(ControlPlaneConfig::new());
+        let p1 = p.new_process(xxhash_random()).unwrap();
+
+        let handler = SigAction {
+            disposition: SigDisposition::Handler,
+            ..Default::default()
+        };
+        p1.set_signal_action(Signal::Sigusr1 as u8, handler)
+            .unwrap();
+        p1.set_signal_action(
+            Signal::Sigusr2 as u8,
+            SigAction {
+                flags: SigActionFlags::RESTART,
+                ..handler
+            },
+        )
+        .unwrap();
+        p1.set_signal_action(
+            Signal::Sigchld as u8,
+            SigAction {
+                disposition: SigDisposition::Ignore,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert!(p1.signals_restart(&[Signal::Sigusr2 as u8]));
+        assert!(p1.signals_restart(&[Signal::Sigusr2 as u8, Signal::Sigchld as u8]));
+        assert!(!p1.signals_restart(&[Signal::Sigusr1 as u8]));
+        assert!(!p1.signals_restart(&[Signal::Sigusr2 as u8, Signal::Sigusr1 as u8]));
+        assert_eq!(
+            p1.set_signal_action(Signal::Sigkill as u8, handler),
+            Err(Errno::Inval)
+        );
+    }
+
+    #[test]
+    fn test_control_plane_stop_and_continue() {
+        let p = WasiControlPlane::new(ControlPlaneConfig::new());
+        let p1 = p.new_process(xxhash_random()).unwrap();
+        let t1 = p1
+            .new_thread(WasiMemoryLayout::default(), ThreadStartType::MainThread)
+            .unwrap();
+
+        p1.signal_process(Signal::Sigtstp);
+        assert_eq!(p1.stopped(), Some(Signal::Sigtstp));
+        assert_eq!(t1.as_thread().pop_signals(), Vec::<u8>::new());
+
+        // A stopped thread waits until the process is continued
+        let waiter = {
+            let p1 = p1.clone();
+            std::thread::spawn(move || p1.wait_while_stopped())
+        };
+        p1.signal_process(Signal::Sigcont);
+        waiter.join().unwrap();
+        assert_eq!(p1.stopped(), None);
+
+        // The stop is reported once, and not at all once continued
+        assert_eq!(p1.take_stop_report(), None);
+        p1.signal_process(Signal::Sigstop);
+        assert_eq!(p1.take_stop_report(), Some(Signal::Sigstop));
+        assert_eq!(p1.take_stop_report(), None);
+
+        // Killing a stopped process lets it run to exit
+        p1.signal_process(Signal::Sigkill);
+        assert_eq!(p1.stopped(), None);
+    }
+
+    #[test]
+    fn test_control_plane_caught_and_blocked_stop_signals() {
+        let p = WasiControlPlane::new(ControlPlaneConfig::new());
+        let p1 = p.new_process(xxhash_random()).unwrap();
+        let t1 = p1
+            .new_thread(WasiMemoryLayout::default(), ThreadStartType::MainThread)
+            .unwrap();
+        let thread = t1.as_thread();
+
+        // A caught SIGTSTP is delivered rather than stopping the process
+        let handler = SigAction {
+            disposition: SigDisposition::Handler,
+            ..Default::default()
+        };
+        p1.set_signal_action(Signal::Sigtstp as u8, handler)
+            .unwrap();
+        p1.signal_process(Signal::Sigtstp);
+        assert_eq!(p1.stopped(), None);
+        assert_eq!(thread.pop_signals(), [Signal::Sigtstp as u8]);
+
+        // A blocked SIGTTIN stays pending, SIGSTOP can't be blocked
+        thread.set_signal_mask([Signal::Sigttin as u8].into_iter().collect());
+        p1.signal_process(Signal::Sigttin);
+        assert_eq!(p1.stopped(), None);
+        assert!(thread.pending_signals().contains(Signal::Sigttin as u8));
+        assert!(p1.is_stop_signal(Signal::Sigttin as u8));
+
+        p1.signal_process(Signal::Sigstop);
+        assert_eq!(p1.stopped(), Some(Signal::Sigstop));
+
+        // Continuing discards the pending stop signals
+        p1.signal_process(Signal::Sigcont);
+        assert_eq!(p1.stopped(), None);
+        assert!(!thread.pending_signals().contains(Signal::Sigttin as u8));
+    }
+
+    #[tokio::test]
+    async fn test_control_plane_join_reports_stopped_children() {
+        let p = WasiControlPlane::new(ControlPlaneConfig::new());
+        let mut parent = p.new_process(xxhash_random()).unwrap();
+        let child = p.new_process(xxhash_random()).unwrap();
+        parent.lock().children.push(child.clone());
+
+        child.signal_process(Signal::Sigtstp);
+        assert_eq!(
+            parent.join_any_child_or_stop().await,
+            Ok((child.pid(), ChildStatus::Stopped(Signal::Sigtstp)))
+        );
+
+        // Stopped children are still children of the process
+        assert_eq!(parent.lock().children.len(), 1);
+    }
 }
@@ -20,19 +20,23 @@ use wasmer::FunctionEnvMut;
 use wasmer_types::ModuleHash;
 use wasmer_wasix_types::{
     types::Signal,
-    wasi::{Errno, ExitCode, Snapshot0Clockid},
+    wasi::{Errno, ExitCode, SigAction, SigActionFlags, SigDisposition, Snapshot0Clockid},
     wasix::ThreadStartType,
 };
 
 use crate::{
-    os::task::signal::WasiSignalInterval, syscalls::platform_clock_time_get, WasiThread,
-    WasiThreadHandle, WasiThreadId,
+    os::{
+        shm::{SharedMemoryMapping, SharedMemoryMappings},
+        task::signal::WasiSignalInterval,
+    },
+    syscalls::platform_clock_time_get,
+    WasiThread, WasiThreadHandle, WasiThreadId,
 };
 
 use super::{
     backoff::WasiProcessCpuBackoff,
     control_plane::{ControlPlaneError, WasiControlPlaneHandle},
-    signal::{SignalDeliveryError, SignalHandlerAbi},
+    signal::{SignalDeliveryError, SignalHandlerAbi, SignalSet},
     task_join_handle::OwnedTaskStatus,
     thread::WasiMemoryLayout,
     TaskStatus,
@@ -155,6 +159,10 @@ impl Into<Range<u64>> for MemorySnapshotRegion {
 pub struct WasiProcessInner {
     /// Unique ID of this process
     pub pid: WasiProcessId,
+    /// Process group this process belongs to, used for job control
+    pub(crate) pgid: WasiProcessId,
+    /// Session this process belongs to
+    pub(crate) sid: WasiProcessId,
     /// Number of threads waiting for children to exit
     pub(crate) waiting: Arc<AtomicU32>,
     /// The threads that make up this process
@@ -163,8 +171,13 @@ pub struct WasiProcessInner {
     pub thread_count: u32,
     /// Signals that will be triggered at specific intervals
     pub signal_intervals: HashMap<Signal, WasiSignalInterval>,
+    /// Actions registered with `sigaction`, signals that are missing
+    /// use the default action
+    pub(crate) signal_actions: HashMap<u8, SigAction>,
     /// List of all the children spawned from this thread
     pub children: Vec<WasiProcess>,
+    /// Shared memory objects that are mapped into the memory of the process
+    pub(crate) shm_mappings: SharedMemoryMappings,
     /// Represents a checkpoint which blocks all the threads
     /// and then executes some maintenance action
     pub checkpoint: WasiProcessCheckpoint,
@@ -184,6 +197,45 @@ pub struct WasiProcessInner {
     /// which will be used to determine if the CPU should be
     /// throttled or not
     pub(super) backoff: WasiProcessCpuBackoff,
+    /// Signal that stopped the process, set until it receives `SIGCONT`
+    pub(crate) stopped: Option<Signal>,
+    /// Stop that has not yet been reported to a parent joining with
+    /// `WAKE_STOPPED`
+    pub(crate) unreported_stop: Option<Signal>,
+}
+
+impl WasiProcessInner {
+    /// Returns true if the signal is discarded rather than delivered
+    fn is_signal_ignored(&self, sig: u8) -> bool {
+        self.signal_actions
+            .get(&sig)
+            .is_some_and(|action| action.disposition == SigDisposition::Ignore)
+    }
+
+    /// Picks the thread that receives a signal sent to the whole process,
+    /// preferring the main thread and threads that don't block the signal
+    fn signal_target(&self, sig: u8) -> Option<&WasiThread> {
+        let mut threads: Vec<_> = self.threads.values().collect();
+        threads.sort_by_key(|thread| (!thread.is_main(), thread.tid()));
+        threads
+            .iter()
+            .find(|thread| !thread.signal_mask().contains(sig))
+            .or_else(|| threads.first())
+            .copied()
+    }
+
+    /// Returns true if the signal stops the process when it is delivered,
+    /// `SIGSTOP` always does and the other job control signals do when
+    /// they use the default action
+    fn is_stop_signal(&self, sig: u8) -> bool {
+        match Signal::try_from(sig) {
+            Ok(Signal::Sigstop) => true,
+            Ok(Signal::Sigtstp | Signal::Sigttin | Signal::Sigttou) => {
+                !self.signal_actions.contains_key(&sig)
+            }
+            _ => false,
+        }
+    }
 }
 
 pub enum MaybeCheckpointResult<'a> {
@@ -415,10 +467,14 @@ impl WasiProcess {
         let inner = Arc::new((
             Mutex::new(WasiProcessInner {
                 pid,
+                pgid: pid,
+                sid: pid,
                 threads: Default::default(),
                 thread_count: Default::default(),
                 signal_intervals: Default::default(),
+                signal_actions: Default::default(),
                 children: Default::default(),
+                shm_mappings: Default::default(),
                 checkpoint: WasiProcessCheckpoint::Execute,
                 wakers: Default::default(),
                 waiting: waiting.clone(),
@@ -428,6 +484,8 @@ impl WasiProcess {
                 snapshot_memory_hash: Default::default(),
                 disable_journaling_after_checkpoint: false,
                 backoff: WasiProcessCpuBackoff::new(max_cpu_backoff_time, max_cpu_cool_off_time),
+                stopped: None,
+                unreported_stop: None,
             }),
             Condvar::new(),
         ));
@@ -462,6 +520,12 @@ impl WasiProcess {
 
     pub(super) fn set_pid(&mut self, pid: WasiProcessId) {
         self.pid = pid;
+
+        // New processes lead their own group and session until they
+        // inherit the ones of their parent
+        let mut inner = self.inner.0.lock().unwrap();
+        inner.pgid = pid;
+        inner.sid = pid;
     }
 
     /// Gets the process ID of this process
@@ -479,6 +543,51 @@ impl WasiProcess {
             .unwrap_or(WasiProcessId(0))
     }
 
+    /// Gets the ID of the process group this process belongs to
+    pub fn pgid(&self) -> WasiProcessId {
+        self.inner.0.lock().unwrap().pgid
+    }
+
+    /// Gets the ID of the session this process belongs to
+    pub fn sid(&self) -> WasiProcessId {
+        self.inner.0.lock().unwrap().sid
+    }
+
+    /// Moves the process to another process group of its session
+    pub(crate) fn set_pgid(&self, pgid: WasiProcessId) {
+        self.inner.0.lock().unwrap().pgid = pgid;
+    }
+
+    /// Makes the process the leader of a new session and of a new process
+    /// group, which fails if it already leads a process group
+    pub fn setsid(&self) -> Result<WasiProcessId, Errno> {
+        let mut inner = self.inner.0.lock().unwrap();
+        if inner.pgid == self.pid {
+            return Err(Errno::Perm);
+        }
+        inner.pgid = self.pid;
+        inner.sid = self.pid;
+        Ok(self.pid)
+    }
+
+    /// Puts the process in the process group and session of its parent,
+    /// as done by `fork`
+    pub fn inherit_process_group(&self, parent: &WasiProcess) {
+        let (pgid, sid) = {
+            let parent = parent.inner.0.lock().unwrap();
+            (parent.pgid, parent.sid)
+        };
+        let mut inner = self.inner.0.lock().unwrap();
+        inner.pgid = pgid;
+        inner.sid = sid;
+    }
+
+    /// Returns true if the process is one of the children of this process
+    pub fn is_child(&self, pid: WasiProcessId) -> bool {
+        let inner = self.inner.0.lock().unwrap();
+        inner.children.iter().any(|child| child.pid() == pid)
+    }
+
     /// Gains access to the process internals
     // TODO: Make this private, all inner access should be exposed with methods.
     pub fn lock(&self) -> MutexGuard<'_, WasiProcessInner> {
@@ -562,7 +671,24 @@ impl WasiProcess {
         let pid = self.pid();
         tracing::trace!(%pid, %tid, "signal-thread({:?})", signal);
 
-        let inner = self.inner.0.lock().unwrap();
+        let mut inner = self.inner.0.lock().unwrap();
+        if signal == Signal::Sigcont || signal == Signal::Sigkill {
+            continue_process_internal(&self.inner, &mut inner);
+        }
+        if inner.is_signal_ignored(signal as u8) {
+            trace!(%pid, %tid, "ignored-signal({:?})", signal);
+            return;
+        }
+
+        // Stopping a thread stops the whole process
+        let blocked = inner
+            .threads
+            .get(&tid)
+            .is_some_and(|thread| thread.signal_mask().contains(signal as u8));
+        if inner.is_stop_signal(signal as u8) && !blocked {
+            stop_process_internal(&self.inner, &mut inner, signal);
+            return;
+        }
         if let Some(thread) = inner.threads.get(&tid) {
             thread.signal(signal);
         } else {
@@ -580,6 +706,205 @@ impl WasiProcess {
         signal_process_internal(&self.inner, signal);
     }
 
+    /// Sends a signal to the process, real-time signals are queued on one
+    /// of the threads that doesn't block them
+    pub fn queue_signal(&self, sig: u8) -> Result<(), Errno> {
+        if !SignalSet::is_valid(sig) {
+            return Err(Errno::Inval);
+        }
+        if !SignalSet::is_realtime(sig) {
+            let signal = Signal::try_from(sig).map_err(|_| Errno::Inval)?;
+            self.signal_process(signal);
+            return Ok(());
+        }
+
+        let inner = self.inner.0.lock().unwrap();
+        if inner.is_signal_ignored(sig) {
+            return Ok(());
+        }
+        match inner.signal_target(sig) {
+            Some(thread) => thread.queue_signal(sig),
+            None => Err(Errno::Srch),
+        }
+    }
+
+    /// Returns true if the signal stops the process when it is delivered
+    pub fn is_stop_signal(&self, sig: u8) -> bool {
+        self.inner.0.lock().unwrap().is_stop_signal(sig)
+    }
+
+    /// Stops the process, its threads pause at their next syscall until
+    /// the process receives `SIGCONT`
+    pub fn stop(&self, signal: Signal) {
+        let mut inner = self.inner.0.lock().unwrap();
+        stop_process_internal(&self.inner, &mut inner, signal);
+    }
+
+    /// Continues the process if it is stopped
+    pub fn resume(&self) {
+        let mut inner = self.inner.0.lock().unwrap();
+        continue_process_internal(&self.inner, &mut inner);
+    }
+
+    /// Returns the signal that stopped the process, if it is stopped
+    pub fn stopped(&self) -> Option<Signal> {
+        self.inner.0.lock().unwrap().stopped
+    }
+
+    /// Blocks the calling thread for as long as the process is stopped
+    pub fn wait_while_stopped(&self) {
+        let mut inner = self.inner.0.lock().unwrap();
+        while inner.stopped.is_some() {
+            inner = self.inner.1.wait(inner).unwrap();
+        }
+    }
+
+    /// Returns the signal that stopped the process if the stop was not
+    /// reported yet, each stop is only reported once
+    pub fn take_stop_report(&self) -> Option<Signal> {
+        self.inner.0.lock().unwrap().unreported_stop.take()
+    }
+
+    /// Waits for the process to be stopped and reports the stop
+    pub async fn wait_for_stop(&self) -> Signal {
+        use futures::Future;
+        use std::{
+            pin::Pin,
+            task::{Context, Poll},
+        };
+
+        struct Poller {
+            inner: LockableWasiProcessInner,
+        }
+        impl Future for Poller {
+            type Output = Signal;
+            fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+                let mut guard = self.inner.0.lock().unwrap();
+                if let Some(signal) = guard.unreported_stop.take() {
+                    return Poll::Ready(signal);
+                }
+                if !guard.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                    guard.wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+        }
+        Poller {
+            inner: self.inner.clone(),
+        }
+        .await
+    }
+
+    /// Waits for the process to be stopped, without reporting the stop
+    pub async fn wait_for_stopped(&self) {
+        self.wait_for_stopped_state(true).await
+    }
+
+    /// Waits for the process to be continued, returns immediately if it
+    /// is not stopped
+    pub async fn wait_for_continue(&self) {
+        self.wait_for_stopped_state(false).await
+    }
+
+    async fn wait_for_stopped_state(&self, stopped: bool) {
+        use futures::Future;
+        use std::{
+            pin::Pin,
+            task::{Context, Poll},
+        };
+
+        struct Poller {
+            inner: LockableWasiProcessInner,
+            stopped: bool,
+        }
+        impl Future for Poller {
+            type Output = ();
+            fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+                let mut guard = self.inner.0.lock().unwrap();
+                if guard.stopped.is_some() == self.stopped {
+                    return Poll::Ready(());
+                }
+                if !guard.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                    guard.wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+        }
+        Poller {
+            inner: self.inner.clone(),
+            stopped,
+        }
+        .await
+    }
+
+    /// Returns the action taken when the signal is delivered
+    pub fn signal_action(&self, sig: u8) -> SigAction {
+        let inner = self.inner.0.lock().unwrap();
+        inner.signal_actions.get(&sig).copied().unwrap_or_default()
+    }
+
+    /// Returns true if a blocking call interrupted by these signals is
+    /// restarted, which is when each of them is ignored or handled by a
+    /// handler registered with `SA_RESTART`
+    pub fn signals_restart(&self, signals: &[u8]) -> bool {
+        signals.iter().all(|sig| {
+            let action = self.signal_action(*sig);
+            action.disposition == SigDisposition::Ignore
+                || action.flags.contains(SigActionFlags::RESTART)
+        })
+    }
+
+    /// Changes the action taken when the signal is delivered and returns
+    /// the previous one
+    pub fn set_signal_action(&self, sig: u8, action: SigAction) -> Result<SigAction, Errno> {
+        if !SignalSet::is_catchable(sig) {
+            return Err(Errno::Inval);
+        }
+
+        let mut inner = self.inner.0.lock().unwrap();
+        let old = match action.disposition {
+            SigDisposition::Default => inner.signal_actions.remove(&sig),
+            _ => inner.signal_actions.insert(sig, action),
+        };
+
+        // Ignoring a signal also discards the instances that are pending
+        if action.disposition == SigDisposition::Ignore {
+            for thread in inner.threads.values() {
+                thread.discard_signal(sig);
+            }
+        }
+
+        Ok(old.unwrap_or_default())
+    }
+
+    /// Copies the signal actions of another process, as done by `fork`
+    pub fn inherit_signal_actions(&self, parent: &WasiProcess) {
+        let actions = parent.inner.0.lock().unwrap().signal_actions.clone();
+        self.inner.0.lock().unwrap().signal_actions = actions;
+    }
+
+    /// Returns the shared memory regions that are mapped into the process
+    pub fn shared_memory_mappings(&self) -> Vec<SharedMemoryMapping> {
+        let inner = self.inner.0.lock().unwrap();
+        inner.shm_mappings.iter().cloned().collect()
+    }
+
+    /// Records that a shared memory region was mapped into the process
+    pub(crate) fn add_shared_memory_mapping(&self, mapping: SharedMemoryMapping) {
+        self.inner.0.lock().unwrap().shm_mappings.insert(mapping);
+    }
+
+    /// Records that a range of the process memory no longer maps shared memory
+    pub(crate) fn remove_shared_memory_mappings(&self, addr: u64, len: u64) {
+        self.inner.0.lock().unwrap().shm_mappings.remove(addr, len);
+    }
+
+    /// Forgets all the shared memory mappings, used when the process
+    /// replaces its memory (for instance on `exec`)
+    pub(crate) fn clear_shared_memory_mappings(&self) {
+        self.inner.0.lock().unwrap().shm_mappings.clear();
+    }
+
     /// Takes a snapshot of the process and disables journaling returning
     /// a future that can be waited on for the snapshot to complete
     ///
@@ -796,20 +1121,110 @@ impl WasiProcess {
         Ok(Some((child.pid, code)))
     }
 
+    /// Waits for any of the children to finish or to be stopped, unlike
+    /// finished children the stopped ones remain children of this process
+    pub async fn join_any_child_or_stop(&mut self) -> Result<(WasiProcessId, ChildStatus), Errno> {
+        let _guard = WasiProcessWait::new(self);
+        let children: Vec<_> = {
+            let inner = self.inner.0.lock().unwrap();
+            inner.children.clone()
+        };
+        if children.is_empty() {
+            return Err(Errno::Child);
+        }
+
+        let mut waits = Vec::new();
+        for child in children {
+            if let Some(process) = self.compute.must_upgrade().get_process(child.pid) {
+                let inner = self.inner.clone();
+                waits.push(async move {
+                    let join = Box::pin(process.join());
+                    let stop = Box::pin(process.wait_for_stop());
+                    let status = match futures::future::select(join, stop).await {
+                        futures::future::Either::Left((res, _)) => {
+                            let mut inner = inner.0.lock().unwrap();
+                            inner.children.retain(|a| a.pid != child.pid);
+                            ChildStatus::Exited(res.unwrap_or_else(|e| {
+                                e.as_exit_code().unwrap_or_else(|| Errno::Canceled.into())
+                            }))
+                        }
+                        futures::future::Either::Right((signal, _)) => ChildStatus::Stopped(signal),
+                    };
+                    (child.pid, status)
+                })
+            }
+        }
+        if waits.is_empty() {
+            return Err(Errno::Child);
+        }
+        Ok(futures::future::select_all(waits.into_iter().map(Box::pin))
+            .await
+            .0)
+    }
+
     /// Terminate the process and all its threads
     pub fn terminate(&self, exit_code: ExitCode) {
         // FIXME: this is wrong, threads might still be running!
         // Need special logic for the main thread.
-        let guard = self.inner.0.lock().unwrap();
+        let mut guard = self.inner.0.lock().unwrap();
+        continue_process_internal(&self.inner, &mut guard);
         for thread in guard.threads.values() {
             thread.set_status_finished(Ok(exit_code))
         }
     }
 }
 
+/// How a child process changed, as reported when joining on it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChildStatus {
+    /// The child finished with an exit code
+    Exited(ExitCode),
+    /// The child was stopped by a signal and can be continued with `SIGCONT`
+    Stopped(Signal),
+}
+
+/// Stops the process, a pending `SIGCONT` is discarded
+fn stop_process_internal(
+    process: &LockableWasiProcessInner,
+    guard: &mut WasiProcessInner,
+    signal: Signal,
+) {
+    tracing::trace!(pid=%guard.pid, "stop-process({:?})", signal);
+    for thread in guard.threads.values() {
+        thread.discard_signal(Signal::Sigcont as u8);
+    }
+    guard.stopped = Some(signal);
+    guard.unreported_stop = Some(signal);
+    for waker in guard.wakers.drain(..) {
+        waker.wake();
+    }
+    process.1.notify_all();
+}
+
+/// Continues a stopped process, pending stop signals are discarded
+fn continue_process_internal(process: &LockableWasiProcessInner, guard: &mut WasiProcessInner) {
+    for thread in guard.threads.values() {
+        for signal in [
+            Signal::Sigstop,
+            Signal::Sigtstp,
+            Signal::Sigttin,
+            Signal::Sigttou,
+        ] {
+            thread.discard_signal(signal as u8);
+        }
+    }
+    if guard.stopped.take().is_some() {
+        tracing::trace!(pid=%guard.pid, "continue-process");
+        guard.unreported_stop = None;
+        for waker in guard.wakers.drain(..) {
+            waker.wake();
+        }
+        process.1.notify_all();
+    }
+}
+
 /// Signals all the threads in this process
 fn signal_process_internal(process: &LockableWasiProcessInner, signal: Signal) {
-    #[allow(unused_mut)]
     let mut guard = process.0.lock().unwrap();
     let pid = guard.pid;
     tracing::trace!(%pid, "signal-process({:?})", signal);
@@ -836,6 +1251,12 @@ fn signal_process_internal(process: &LockableWasiProcessInner, signal: Signal) {
         };
     }
 
+    // Continuing a process works even if `SIGCONT` is ignored, and a
+    // killed process has to run to exit
+    if signal == Signal::Sigcont || signal == Signal::Sigkill {
+        continue_process_internal(process, &mut guard);
+    }
+
     // Check if there are subprocesses that will receive this signal
     // instead of this process
     if guard.waiting.load(Ordering::Acquire) > 0 {
@@ -849,9 +1270,38 @@ fn signal_process_internal(process: &LockableWasiProcessInner, signal: Signal) {
         }
     }
 
-    // Otherwise just send the signal to all the threads
+    if guard.is_signal_ignored(signal as u8) {
+        tracing::trace!(%pid, "ignored-signal({:?})", signal);
+        return;
+    }
+
+    // Stop signals take effect as soon as they are sent, unless all the
+    // threads block them in which case they stay pending
+    if guard.is_stop_signal(signal as u8) {
+        let blocked = !guard.threads.is_empty()
+            && guard
+                .threads
+                .values()
+                .all(|thread| thread.signal_mask().contains(signal as u8));
+        if !blocked {
+            stop_process_internal(process, &mut guard, signal);
+            return;
+        }
+    }
+
+    // Otherwise just send the signal to all the threads that don't block
+    // it, if they all do it stays pending on one of them
+    let mut delivered = false;
     for thread in guard.threads.values() {
-        thread.signal(signal);
+        if !thread.signal_mask().contains(signal as u8) {
+            thread.signal(signal);
+            delivered = true;
+        }
+    }
+    if !delivered {
+        if let Some(thread) = guard.signal_target(signal as u8) {
+            thread.signal(signal);
+        }
     }
 }
 
@@ -1,6 +1,13 @@
 use std::{sync::Arc, time::Duration};
 
-use wasmer_wasix_types::types::Signal;
+use wasmer_wasix_types::{types::Signal, wasi::Sigset};
+
+/// The first real-time signal
+pub const SIGRTMIN: u8 = 32;
+/// The last real-time signal
+pub const SIGRTMAX: u8 = 64;
+/// The maximum number of real-time signals that can be queued on a thread
+pub const SIGQUEUE_MAX: usize = 1024;
 
 #[derive(thiserror::Error, Debug)]
 #[error("Signal could not be delivered")]
@@ -17,6 +24,161 @@ where
 
 pub type DynSignalHandlerAbi = dyn SignalHandlerAbi + Send + Sync + 'static;
 
+/// The default action of a signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SignalDefault {
+    /// Terminates the process
+    Terminate,
+    /// Terminates the process with a core dump
+    Core,
+    /// Discards the signal
+    Ignore,
+    /// Stops the process
+    Stop,
+    /// Continues the process if it is stopped
+    Continue,
+}
+
+impl SignalDefault {
+    /// Returns true if the action ends the process
+    pub fn terminates(self) -> bool {
+        matches!(self, SignalDefault::Terminate | SignalDefault::Core)
+    }
+}
+
+/// Set of signals, including the real-time ones
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SignalSet(Sigset);
+
+impl SignalSet {
+    pub const fn empty() -> Self {
+        SignalSet(0)
+    }
+
+    pub const fn from_bits(bits: Sigset) -> Self {
+        SignalSet(bits)
+    }
+
+    pub const fn bits(&self) -> Sigset {
+        self.0
+    }
+
+    /// Returns true if `sig` is a valid signal number
+    pub fn is_valid(sig: u8) -> bool {
+        (1..=SIGRTMAX).contains(&sig)
+    }
+
+    /// Returns true if `sig` is a real-time signal
+    pub fn is_realtime(sig: u8) -> bool {
+        (SIGRTMIN..=SIGRTMAX).contains(&sig)
+    }
+
+    /// Returns true if `sig` can be blocked, caught or ignored
+    /// (everything but `SIGKILL` and `SIGSTOP`)
+    pub fn is_catchable(sig: u8) -> bool {
+        Self::is_valid(sig) && sig != Signal::Sigkill as u8 && sig != Signal::Sigstop as u8
+    }
+
+    /// Returns the action taken when `sig` is delivered to a process that
+    /// neither handles nor ignores it, as listed by POSIX (real-time signals
+    /// terminate the process)
+    pub fn default_action(sig: u8) -> Option<SignalDefault> {
+        let Ok(signal) = Signal::try_from(sig) else {
+            return Self::is_realtime(sig).then_some(SignalDefault::Terminate);
+        };
+        Some(match signal {
+            Signal::Signone => return None,
+            Signal::Sighup
+            | Signal::Sigint
+            | Signal::Sigkill
+            | Signal::Sigusr1
+            | Signal::Sigusr2
+            | Signal::Sigpipe
+            | Signal::Sigalrm
+            | Signal::Sigterm
+            | Signal::Sigstkflt
+            | Signal::Sigvtalrm
+            | Signal::Sigprof
+            | Signal::Sigpoll
+            | Signal::Sigpwr => SignalDefault::Terminate,
+            Signal::Sigquit
+            | Signal::Sigill
+            | Signal::Sigtrap
+            | Signal::Sigabrt
+            | Signal::Sigbus
+            | Signal::Sigfpe
+            | Signal::Sigsegv
+            | Signal::Sigxcpu
+            | Signal::Sigxfsz
+            | Signal::Sigsys => SignalDefault::Core,
+            Signal::Sigchld | Signal::Sigurg | Signal::Sigwinch => SignalDefault::Ignore,
+            Signal::Sigstop | Signal::Sigtstp | Signal::Sigttin | Signal::Sigttou => {
+                SignalDefault::Stop
+            }
+            Signal::Sigcont => SignalDefault::Continue,
+        })
+    }
+
+    fn bit(sig: u8) -> Sigset {
+        if Self::is_valid(sig) {
+            1 << (sig - 1)
+        } else {
+            0
+        }
+    }
+
+    pub fn contains(&self, sig: u8) -> bool {
+        self.0 & Self::bit(sig) != 0
+    }
+
+    pub fn insert(&mut self, sig: u8) {
+        self.0 |= Self::bit(sig);
+    }
+
+    pub fn remove(&mut self, sig: u8) {
+        self.0 &= !Self::bit(sig);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn union(self, other: SignalSet) -> SignalSet {
+        SignalSet(self.0 | other.0)
+    }
+
+    pub fn intersection(self, other: SignalSet) -> SignalSet {
+        SignalSet(self.0 & other.0)
+    }
+
+    pub fn difference(self, other: SignalSet) -> SignalSet {
+        SignalSet(self.0 & !other.0)
+    }
+
+    /// Removes the signals that can't be blocked
+    pub fn catchable(self) -> SignalSet {
+        let mut ret = self;
+        ret.remove(Signal::Sigkill as u8);
+        ret.remove(Signal::Sigstop as u8);
+        ret
+    }
+
+    /// Iterates over the signals in the set, lowest first
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (1..=SIGRTMAX).filter(|sig| self.contains(*sig))
+    }
+}
+
+impl FromIterator<u8> for SignalSet {
+    fn from_iter<T: IntoIterator<Item = u8>>(iter: T) -> Self {
+        let mut ret = SignalSet::empty();
+        for sig in iter {
+            ret.insert(sig);
+        }
+        ret
+    }
+}
+
 #[derive(Debug)]
 pub struct WasiSignalInterval {
     /// Signal that will be raised
@@ -55,3 +217,118 @@ pub fn default_signal_handler() -> Arc<DynSignalHandlerAbi> {
     }
     Arc::new(DefaultHandler {})
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signal_set_covers_realtime_signals() {
+        let mut set = SignalSet::empty();
+        set.insert(Signal::Sighup as u8);
+        set.insert(SIGRTMIN);
+        set.insert(SIGRTMAX);
+        set.insert(0);
+        set.insert(SIGRTMAX + 1);
+
+        assert_eq!(set.bits(), 1 | (1 << 31) | (1 << 63));
+        assert!(set.contains(SIGRTMAX));
+        assert!(!set.contains(0));
+        assert_eq!(
+            set.iter().collect::<Vec<_>>(),
+            [Signal::Sighup as u8, SIGRTMIN, SIGRTMAX]
+        );
+    }
+
+    #[test]
+    fn kill_and_stop_cant_be_blocked() {
+        let set: SignalSet = [
+            Signal::Sigint as u8,
+            Signal::Sigkill as u8,
+            Signal::Sigstop as u8,
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            set.catchable().iter().collect::<Vec<_>>(),
+            [Signal::Sigint as u8]
+        );
+        assert!(!SignalSet::is_catchable(Signal::Sigkill as u8));
+        assert!(SignalSet::is_catchable(SIGRTMIN));
+    }
+
+    #[test]
+    fn default_action_terminates() {
+        for signal in [
+            Signal::Sighup,
+            Signal::Sigkill,
+            Signal::Sigusr1,
+            Signal::Sigpipe,
+        ] {
+            assert_eq!(
+                SignalSet::default_action(signal as u8),
+                Some(SignalDefault::Terminate)
+            );
+        }
+        assert_eq!(
+            SignalSet::default_action(SIGRTMIN + 3),
+            Some(SignalDefault::Terminate)
+        );
+        assert!(SignalDefault::Terminate.terminates());
+    }
+
+    #[test]
+    fn default_action_dumps_core() {
+        for signal in [
+            Signal::Sigquit,
+            Signal::Sigabrt,
+            Signal::Sigsegv,
+            Signal::Sigsys,
+        ] {
+            assert_eq!(
+                SignalSet::default_action(signal as u8),
+                Some(SignalDefault::Core)
+            );
+        }
+        assert!(SignalDefault::Core.terminates());
+    }
+
+    #[test]
+    fn default_action_ignores() {
+        for signal in [Signal::Sigchld, Signal::Sigurg, Signal::Sigwinch] {
+            assert_eq!(
+                SignalSet::default_action(signal as u8),
+                Some(SignalDefault::Ignore)
+            );
+        }
+        assert!(!SignalDefault::Ignore.terminates());
+    }
+
+    #[test]
+    fn default_action_stops() {
+        for signal in [
+            Signal::Sigstop,
+            Signal::Sigtstp,
+            Signal::Sigttin,
+            Signal::Sigttou,
+        ] {
+            assert_eq!(
+                SignalSet::default_action(signal as u8),
+                Some(SignalDefault::Stop)
+            );
+        }
+        assert!(!SignalDefault::Stop.terminates());
+    }
+
+    #[test]
+    fn default_action_continues() {
+        assert_eq!(
+            SignalSet::default_action(Signal::Sigcont as u8),
+            Some(SignalDefault::Continue)
+        );
+        assert!(!SignalDefault::Continue.terminates());
+        assert_eq!(SignalSet::default_action(0), None);
+        assert_eq!(SignalSet::default_action(SIGRTMAX + 1), None);
+    }
+}
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     ops::{Deref, DerefMut},
     sync::{Arc, Condvar, Mutex, Weak},
     task::Waker,
@@ -23,6 +23,7 @@ use crate::{
 
 use super::{
     control_plane::TaskCountGuard,
+    signal::{SignalSet, SIGQUEUE_MAX},
     task_join_handle::{OwnedTaskStatus, TaskJoinHandle},
 };
 
@@ -235,12 +236,77 @@ pub(crate) struct RewindResult {
     pub rewind_result: RewindResultType,
 }
 
+/// Signals that were sent to a thread but not yet delivered
+#[derive(Debug, Default)]
+struct ThreadSignals {
+    /// Standard signals, each of them is pending at most once
+    pending: Vec<Signal>,
+    /// Real-time signals, every one of them is delivered
+    queued: VecDeque<u8>,
+    /// Signals that stay pending until they are unblocked
+    mask: SignalSet,
+    wakers: Vec<Waker>,
+}
+
+impl ThreadSignals {
+    fn pending_set(&self) -> SignalSet {
+        self.pending
+            .iter()
+            .map(|sig| *sig as u8)
+            .chain(self.queued.iter().copied())
+            .collect()
+    }
+
+    fn has_deliverable(&self) -> bool {
+        self.pending
+            .iter()
+            .any(|sig| !self.mask.contains(*sig as u8))
+            || self.queued.iter().any(|sig| !self.mask.contains(*sig))
+    }
+
+    fn pop_deliverable(&mut self) -> Vec<u8> {
+        let mask = self.mask;
+        let mut ret = Vec::new();
+        self.pending.retain(|sig| {
+            if mask.contains(*sig as u8) {
+                return true;
+            }
+            ret.push(*sig as u8);
+            false
+        });
+
+        // Real-time signals are delivered lowest first, and in the order
+        // they were sent for the same signal
+        let mut realtime = Vec::new();
+        self.queued.retain(|sig| {
+            if mask.contains(*sig) {
+                return true;
+            }
+            realtime.push(*sig);
+            false
+        });
+        realtime.sort();
+        ret.extend(realtime);
+        ret
+    }
+
+    fn subscribe(&mut self, waker: &Waker) {
+        if !self.wakers.iter().any(|w| w.will_wake(waker)) {
+            self.wakers.push(waker.clone());
+        }
+    }
+
+    fn wake(&mut self) {
+        self.wakers.drain(..).for_each(|w| w.wake());
+    }
+}
+
 #[derive(Debug)]
 struct WasiThreadState {
     is_main: bool,
     pid: WasiProcessId,
     id: WasiThreadId,
-    signals: Mutex<(Vec<Signal>, Vec<Waker>)>,
+    signals: Mutex<ThreadSignals>,
     stack: Mutex<ThreadStack>,
     status: Arc<OwnedTaskStatus>,
     #[cfg(feature = "journal")]
@@ -270,7 +336,7 @@ impl WasiThread {
                 pid,
                 id,
                 status,
-                signals: Mutex::new((Vec::new(), Vec::new())),
+                signals: Mutex::new(ThreadSignals::default()),
                 stack: Mutex::new(ThreadStack::default()),
                 #[cfg(feature = "journal")]
                 check_pointing: AtomicBool::new(false),
@@ -303,11 +369,6 @@ impl WasiThread {
         self.state.status.handle()
     }
 
-    // TODO: this should be private, access should go through utility methods.
-    pub fn signals(&self) -> &Mutex<(Vec<Signal>, Vec<Waker>)> {
-        &self.state.signals
-    }
-
     pub fn set_status_running(&self) {
         self.state.status.set_running();
     }
@@ -350,16 +411,51 @@ impl WasiThread {
         tracing::trace!(%tid, "signal-thread({:?})", signal);
 
         let mut guard = self.state.signals.lock().unwrap();
-        if !guard.0.contains(&signal) {
-            guard.0.push(signal);
+        if !guard.pending.contains(&signal) {
+            guard.pending.push(signal);
+        }
+        if !guard.mask.contains(signal as u8) {
+            guard.wake();
         }
-        guard.1.drain(..).for_each(|w| w.wake());
     }
 
-    /// Returns all the signals that are waiting to be processed
+    /// Adds a signal for this thread to process, unlike standard signals
+    /// real-time signals are queued rather than merged with a pending one
+    pub fn queue_signal(&self, sig: u8) -> Result<(), Errno> {
+        if !SignalSet::is_valid(sig) {
+            return Err(Errno::Inval);
+        }
+        if !SignalSet::is_realtime(sig) {
+            let signal = Signal::try_from(sig).map_err(|_| Errno::Inval)?;
+            self.signal(signal);
+            return Ok(());
+        }
+
+        let tid = self.tid();
+        tracing::trace!(%tid, sig, "queue-signal");
+
+        let mut guard = self.state.signals.lock().unwrap();
+        if guard.queued.len() >= SIGQUEUE_MAX {
+            return Err(Errno::Again);
+        }
+        guard.queued.push_back(sig);
+        if !guard.mask.contains(sig) {
+            guard.wake();
+        }
+        Ok(())
+    }
+
+    /// Drops any instance of a signal that is still pending
+    pub fn discard_signal(&self, sig: u8) {
+        let mut guard = self.state.signals.lock().unwrap();
+        guard.pending.retain(|s| *s as u8 != sig);
+        guard.queued.retain(|s| *s != sig);
+    }
+
+    /// Returns true if any of the signals is waiting to be processed
     pub fn has_signal(&self, signals: &[Signal]) -> bool {
         let guard = self.state.signals.lock().unwrap();
-        for s in guard.0.iter() {
+        for s in guard.pending.iter() {
             if signals.contains(s) {
                 return true;
             }
@@ -367,6 +463,34 @@ impl WasiThread {
         false
     }
 
+    /// Returns the signals that are currently blocked
+    pub fn signal_mask(&self) -> SignalSet {
+        self.state.signals.lock().unwrap().mask
+    }
+
+    /// Replaces the set of blocked signals and returns the previous one,
+    /// signals that can't be blocked are silently left out
+    pub fn set_signal_mask(&self, mask: SignalSet) -> SignalSet {
+        let mut guard = self.state.signals.lock().unwrap();
+        let old = std::mem::replace(&mut guard.mask, mask.catchable());
+        if guard.has_deliverable() {
+            guard.wake();
+        }
+        old
+    }
+
+    /// Returns all the signals that were sent to this thread and are not
+    /// yet processed, including the blocked ones
+    pub fn pending_signals(&self) -> SignalSet {
+        self.state.signals.lock().unwrap().pending_set()
+    }
+
+    /// Returns the pending signals that are not blocked
+    pub fn deliverable_signals(&self) -> SignalSet {
+        let guard = self.state.signals.lock().unwrap();
+        guard.pending_set().difference(guard.mask)
+    }
+
     /// Waits for a signal to arrive
     pub async fn wait_for_signal(&self) {
         // This poller will process any signals when the main working function is idle
@@ -389,37 +513,35 @@ impl WasiThread {
     }
 
     /// Returns all the signals that are waiting to be processed
-    pub fn pop_signals_or_subscribe(&self, waker: &Waker) -> Option<Vec<Signal>> {
+    /// (blocked signals stay pending)
+    pub fn pop_signals_or_subscribe(&self, waker: &Waker) -> Option<Vec<u8>> {
         let mut guard = self.state.signals.lock().unwrap();
-        let mut ret = Vec::new();
-        std::mem::swap(&mut ret, &mut guard.0);
+        let ret = guard.pop_deliverable();
         match ret.is_empty() {
             true => {
-                if !guard.1.iter().any(|w| w.will_wake(waker)) {
-                    guard.1.push(waker.clone());
-                }
+                guard.subscribe(waker);
                 None
             }
             false => Some(ret),
         }
     }
 
-    /// Returns all the signals that are waiting to be processed
+    /// Returns true if there are signals waiting to be processed
+    /// (blocked signals are not counted)
     pub fn has_signals_or_subscribe(&self, waker: &Waker) -> bool {
         let mut guard = self.state.signals.lock().unwrap();
-        let has_signals = !guard.0.is_empty();
-        if !has_signals && !guard.1.iter().any(|w| w.will_wake(waker)) {
-            guard.1.push(waker.clone());
+        let has_signals = guard.has_deliverable();
+        if !has_signals {
+            guard.subscribe(waker);
         }
         has_signals
     }
 
     /// Returns all the signals that are waiting to be processed
-    pub fn pop_signals(&self) -> Vec<Signal> {
+    /// (blocked signals stay pending)
+    pub fn pop_signals(&self) -> Vec<u8> {
         let mut guard = self.state.signals.lock().unwrap();
-        let mut ret = Vec::new();
-        std::mem::swap(&mut ret, &mut guard.0);
-        ret
+        guard.pop_deliverable()
     }
 
     /// Adds a stack snapshot and removes dead ones
@@ -9,10 +9,15 @@ use wasmer_wasix_types::wasi::{Signal, Snapshot0Clockid};
 
 use crate::syscalls::platform_clock_time_get;
 
-use super::task::signal::SignalHandlerAbi;
+use super::task::{
+    control_plane::WasiControlPlaneHandle,
+    process::{WasiProcess, WasiProcessId},
+    signal::SignalHandlerAbi,
+};
 
 const TTY_MOBILE_PAUSE: u128 = std::time::Duration::from_millis(200).as_nanos();
 
+pub mod pty;
 pub mod tty_sys;
 
 #[derive(Debug)]
@@ -441,6 +446,32 @@ impl Default for WasiTtyState {
     }
 }
 
+/// The process group that receives the job control signals raised by a
+/// terminal
+#[derive(Debug, Clone)]
+pub struct TtyForeground {
+    pub pgid: WasiProcessId,
+    pub control_plane: WasiControlPlaneHandle,
+}
+
+impl TtyForeground {
+    /// Brings the process group of a process to the foreground
+    pub fn from_process(process: &WasiProcess) -> Self {
+        Self {
+            pgid: process.pgid(),
+            control_plane: process.compute.clone(),
+        }
+    }
+
+    /// Sends a signal to every process of the group, returns false if the
+    /// group no longer has any running process
+    pub fn signal(&self, signal: Signal) -> bool {
+        self.control_plane
+            .upgrade()
+            .is_some_and(|plane| plane.signal_process_group(self.pgid, signal))
+    }
+}
+
 /// Provides access to a TTY.
 pub trait TtyBridge: std::fmt::Debug {
     /// Resets the values
@@ -451,4 +482,19 @@ pub trait TtyBridge: std::fmt::Debug {
 
     /// Set the TTY state.
     fn tty_set(&self, _tty_state: WasiTtyState);
+
+    /// Retrieve the process group in the foreground of the TTY, if the TTY
+    /// keeps track of it.
+    fn foreground_get(&self) -> Option<TtyForeground> {
+        None
+    }
+
+    /// Set the process group in the foreground of the TTY.
+    fn foreground_set(&self, _foreground: Option<TtyForeground>) {}
+
+    /// Whether background process groups that write to the TTY are stopped
+    /// with `SIGTTOU` (`TOSTOP`).
+    fn tostop(&self) -> bool {
+        false
+    }
 }
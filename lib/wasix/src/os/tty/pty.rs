@@ -0,0 +1,732 @@
+//! Pseudo-terminal pairs that guests can allocate for themselves.
+//!
+//! A [`Pty`] is made of a master end (held by the terminal emulator, the
+//! multiplexer or the SSH server) and a slave end (handed to the interactive
+//! program as its stdio). Bytes written to the master pass through a small
+//! line discipline before they become readable on the slave, while bytes
+//! written to the slave are post-processed and become readable on the master.
+//!
+//! Each pair keeps its own [`WasiTtyState`] so that changing the terminal
+//! modes of one session does not affect the console or any other pty.
+
+use std::{
+    collections::VecDeque,
+    io::{self, SeekFrom},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Waker},
+};
+
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+use virtual_fs::{FsError, VirtualFile};
+use wasmer_wasix_types::wasi::Signal;
+
+use super::{TtyBridge, TtyForeground, WasiTtyState};
+
+/// Maximum number of bytes that are buffered in each direction before
+/// writers are made to wait.
+const MAX_BUFFERED: usize = 64 * 1024;
+
+/// Used to give every pty pair a unique number (`/dev/pts/N`)
+static NEXT_PTY_ID: AtomicU32 = AtomicU32::new(0);
+
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const CTRL_U: u8 = 0x15;
+const CTRL_Z: u8 = 0x1A;
+const CTRL_BACKSLASH: u8 = 0x1C;
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
+
+#[derive(Debug, Default)]
+struct PtyInner {
+    state: WasiTtyState,
+    /// Bytes that are ready to be read by the slave
+    input: VecDeque<u8>,
+    /// Bytes that are ready to be read by the master
+    output: VecDeque<u8>,
+    /// Line that is currently being edited in canonical mode
+    line: Vec<u8>,
+    /// Set when `^D` was pressed on an empty line
+    eof: bool,
+    /// Set when the master shut down its writing side, the slave reads an
+    /// end-of-file once everything before it was read
+    input_closed: bool,
+    /// Whether `^C`, `^\` and `^Z` raise signals (`ISIG`), independently
+    /// of canonical mode
+    signals: bool,
+    /// Whether background process groups that write to the slave are
+    /// stopped with `SIGTTOU` (`TOSTOP`)
+    tostop: bool,
+    master_open: bool,
+    slave_open: bool,
+    foreground: Option<TtyForeground>,
+    /// Master waiting for output to read
+    master_read_waker: Option<Waker>,
+    /// Master waiting for room in the input buffer
+    master_write_waker: Option<Waker>,
+    /// Slave waiting for input to read
+    slave_read_waker: Option<Waker>,
+    /// Slave waiting for room in the output buffer
+    slave_write_waker: Option<Waker>,
+}
+
+fn wake(waker: &mut Option<Waker>) {
+    if let Some(waker) = waker.take() {
+        waker.wake();
+    }
+}
+
+impl PtyInner {
+    fn master_readable(&self) -> bool {
+        !self.output.is_empty() || !self.slave_open
+    }
+
+    fn slave_readable(&self) -> bool {
+        !self.input.is_empty() || self.eof || self.input_closed || !self.master_open
+    }
+
+    fn echo(&mut self, data: &[u8]) {
+        if self.state.echo {
+            self.output.extend(data);
+        }
+    }
+
+    /// Runs bytes written to the master through the line discipline and
+    /// returns the signals that need to be raised on the foreground process group.
+    fn feed_input(&mut self, data: &[u8]) -> Vec<Signal> {
+        let mut signals = Vec::new();
+
+        for &byte in data {
+            if self.signals {
+                let signal = match byte {
+                    CTRL_C => Some((Signal::Sigint, b"^C\r\n")),
+                    CTRL_BACKSLASH => Some((Signal::Sigquit, b"^\\\r\n")),
+                    CTRL_Z => Some((Signal::Sigtstp, b"^Z\r\n")),
+                    _ => None,
+                };
+                if let Some((signal, echo)) = signal {
+                    self.line.clear();
+                    self.echo(echo);
+                    signals.push(signal);
+                    continue;
+                }
+            }
+
+            if !self.state.line_buffered {
+                self.input.push_back(byte);
+                self.echo(&[byte]);
+                continue;
+            }
+
+            match byte {
+                b'\r' | b'\n' => {
+                    self.line.push(b'\n');
+                    self.input.extend(self.line.drain(..));
+                    self.echo(b"\r\n");
+                }
+                BACKSPACE | DELETE => {
+                    if self.pop_char() {
+                        self.echo(b"\x08 \x08");
+                    }
+                }
+                CTRL_U => {
+                    while self.pop_char() {
+                        self.echo(b"\x08 \x08");
+                    }
+                }
+                CTRL_D => {
+                    if self.line.is_empty() {
+                        self.eof = true;
+                    } else {
+                        self.input.extend(self.line.drain(..));
+                    }
+                }
+                _ => {
+                    self.line.push(byte);
+                    self.echo(&[byte]);
+                }
+            }
+        }
+        signals
+    }
+
+    /// Removes the last (UTF-8) character from the line being edited
+    fn pop_char(&mut self) -> bool {
+        let mut removed = false;
+        while let Some(byte) = self.line.pop() {
+            removed = true;
+            // Stop once we removed the leading byte of the character
+            if byte & 0xC0 != 0x80 {
+                break;
+            }
+        }
+        removed
+    }
+
+    /// Processes bytes written by the slave before they reach the master
+    fn feed_output(&mut self, data: &[u8]) {
+        if self.state.line_feeds {
+            for &byte in data {
+                if byte == b'\n' {
+                    self.output.push_back(b'\r');
+                }
+                self.output.push_back(byte);
+            }
+        } else {
+            self.output.extend(data);
+        }
+    }
+
+    /// Reads the next chunk that is available to the slave, in canonical
+    /// mode this never returns more than a single line.
+    fn read_input(&mut self, buf: &mut ReadBuf<'_>) -> usize {
+        let mut amt = self.input.len().min(buf.remaining());
+        if self.state.line_buffered {
+            if let Some(pos) = self.input.iter().take(amt).position(|b| *b == b'\n') {
+                amt = pos + 1;
+            }
+        }
+        let data: Vec<u8> = self.input.drain(..amt).collect();
+        buf.put_slice(&data);
+        amt
+    }
+}
+
+/// A pseudo-terminal pair
+#[derive(Debug, Clone)]
+pub struct Pty {
+    id: u32,
+    inner: Arc<Mutex<PtyInner>>,
+}
+
+impl Pty {
+    /// Allocates a new pty pair with default terminal modes
+    pub fn open() -> (PtyMaster, PtySlave) {
+        let pty = Pty {
+            id: NEXT_PTY_ID.fetch_add(1, Ordering::Relaxed),
+            inner: Arc::new(Mutex::new(PtyInner {
+                state: WasiTtyState {
+                    cols: 80,
+                    rows: 24,
+                    ..Default::default()
+                }
+                .with_default_modes(),
+                signals: true,
+                master_open: true,
+                slave_open: true,
+                ..Default::default()
+            })),
+        };
+        (PtyMaster { pty: pty.clone() }, PtySlave { pty })
+    }
+
+    /// Number of the pty (as in `/dev/pts/N`)
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Returns the terminal modes and window size of this pty
+    pub fn state(&self) -> WasiTtyState {
+        self.inner.lock().unwrap().state.clone()
+    }
+
+    /// Updates the terminal modes, if the window size changed then the
+    /// foreground process group receives a `SIGWINCH`
+    pub fn set_state(&self, state: WasiTtyState) {
+        let foreground = {
+            let mut inner = self.inner.lock().unwrap();
+            let resized = inner.state.cols != state.cols
+                || inner.state.rows != state.rows
+                || inner.state.width != state.width
+                || inner.state.height != state.height;
+            // Leaving canonical mode releases whatever was typed so far
+            if inner.state.line_buffered && !state.line_buffered {
+                let line = std::mem::take(&mut inner.line);
+                inner.input.extend(line);
+                wake(&mut inner.slave_read_waker);
+            }
+            inner.state = state;
+            if resized {
+                inner.foreground.clone()
+            } else {
+                None
+            }
+        };
+        if let Some(foreground) = foreground {
+            foreground.signal(Signal::Sigwinch);
+        }
+    }
+
+    /// Whether the signal characters (`^C`, `^\`, `^Z`) raise signals on
+    /// the foreground process group instead of being passed through
+    pub fn signals(&self) -> bool {
+        self.inner.lock().unwrap().signals
+    }
+
+    /// Enables or disables the signal characters (`ISIG`), this is separate
+    /// from canonical mode so raw programs can still be interrupted
+    pub fn set_signals(&self, enabled: bool) {
+        self.inner.lock().unwrap().signals = enabled;
+    }
+
+    /// Whether background process groups that write to the slave receive
+    /// `SIGTTOU`
+    pub fn tostop(&self) -> bool {
+        self.inner.lock().unwrap().tostop
+    }
+
+    /// Enables or disables `SIGTTOU` on background writes (`TOSTOP`), it is
+    /// off by default so background jobs can print
+    pub fn set_tostop(&self, enabled: bool) {
+        self.inner.lock().unwrap().tostop = enabled;
+    }
+
+    /// Returns the process group that receives the signals raised by this pty
+    pub fn foreground(&self) -> Option<TtyForeground> {
+        self.inner.lock().unwrap().foreground.clone()
+    }
+
+    /// Sets the process group that receives the signals raised by this pty
+    pub fn set_foreground(&self, foreground: Option<TtyForeground>) {
+        self.inner.lock().unwrap().foreground = foreground;
+    }
+
+    /// Sends a signal to the foreground process group of this pty
+    pub fn signal_foreground(&self, signal: Signal) {
+        if let Some(foreground) = self.foreground() {
+            foreground.signal(signal);
+        }
+    }
+}
+
+impl TtyBridge for Pty {
+    fn reset(&self) {
+        let state = self.state().with_default_modes();
+        self.set_state(state);
+    }
+
+    fn tty_get(&self) -> WasiTtyState {
+        self.state()
+    }
+
+    fn tty_set(&self, tty_state: WasiTtyState) {
+        self.set_state(tty_state);
+    }
+
+    fn foreground_get(&self) -> Option<TtyForeground> {
+        self.foreground()
+    }
+
+    fn foreground_set(&self, foreground: Option<TtyForeground>) {
+        self.set_foreground(foreground);
+    }
+
+    fn tostop(&self) -> bool {
+        Pty::tostop(self)
+    }
+}
+
+impl WasiTtyState {
+    /// Echo, canonical input and CRLF translation, like a freshly opened pty
+    fn with_default_modes(self) -> Self {
+        Self {
+            echo: true,
+            line_buffered: true,
+            line_feeds: true,
+            ..self
+        }
+    }
+}
+
+/// Master end of a [`Pty`]
+#[derive(Debug)]
+pub struct PtyMaster {
+    pty: Pty,
+}
+
+impl PtyMaster {
+    pub fn pty(&self) -> &Pty {
+        &self.pty
+    }
+}
+
+impl Drop for PtyMaster {
+    fn drop(&mut self) {
+        let foreground = {
+            let mut inner = self.pty.inner.lock().unwrap();
+            inner.master_open = false;
+            wake(&mut inner.slave_read_waker);
+            wake(&mut inner.slave_write_waker);
+            inner.foreground.clone()
+        };
+        // Closing the master is the equivalent of hanging up the line
+        if let Some(foreground) = foreground {
+            foreground.signal(Signal::Sighup);
+        }
+    }
+}
+
+/// Slave end of a [`Pty`]
+#[derive(Debug)]
+pub struct PtySlave {
+    pty: Pty,
+}
+
+impl PtySlave {
+    pub fn pty(&self) -> &Pty {
+        &self.pty
+    }
+}
+
+impl Drop for PtySlave {
+    fn drop(&mut self) {
+        let mut inner = self.pty.inner.lock().unwrap();
+        inner.slave_open = false;
+        wake(&mut inner.master_read_waker);
+        wake(&mut inner.master_write_waker);
+    }
+}
+
+impl AsyncRead for PtyMaster {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let mut inner = self.pty.inner.lock().unwrap();
+        if !inner.output.is_empty() {
+            let amt = inner.output.len().min(buf.remaining());
+            let data: Vec<u8> = inner.output.drain(..amt).collect();
+            buf.put_slice(&data);
+            wake(&mut inner.slave_write_waker);
+            return Poll::Ready(Ok(()));
+        }
+        if !inner.slave_open {
+            return Poll::Ready(Ok(()));
+        }
+        inner.master_read_waker.replace(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for PtyMaster {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let signals = {
+            let mut inner = self.pty.inner.lock().unwrap();
+            if !inner.slave_open || inner.input_closed {
+                return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+            }
+            if inner.input.len() >= MAX_BUFFERED {
+                inner.master_write_waker.replace(cx.waker().clone());
+                return Poll::Pending;
+            }
+            let signals = inner.feed_input(buf);
+            wake(&mut inner.slave_read_waker);
+            if !inner.output.is_empty() {
+                wake(&mut inner.master_read_waker);
+            }
+            signals
+        };
+        for signal in signals {
+            self.pty.signal_foreground(signal);
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    /// Closes the input of the slave without hanging up, the line being
+    /// edited is released and then the slave reads an end-of-file
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut inner = self.pty.inner.lock().unwrap();
+        let line = std::mem::take(&mut inner.line);
+        inner.input.extend(line);
+        inner.input_closed = true;
+        wake(&mut inner.slave_read_waker);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for PtySlave {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let mut inner = self.pty.inner.lock().unwrap();
+        if !inner.input.is_empty() {
+            inner.read_input(buf);
+            wake(&mut inner.master_write_waker);
+            return Poll::Ready(Ok(()));
+        }
+        if inner.eof {
+            inner.eof = false;
+            return Poll::Ready(Ok(()));
+        }
+        if inner.input_closed || !inner.master_open {
+            return Poll::Ready(Ok(()));
+        }
+        inner.slave_read_waker.replace(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for PtySlave {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut inner = self.pty.inner.lock().unwrap();
+        if !inner.master_open {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        if inner.output.len() >= MAX_BUFFERED {
+            inner.slave_write_waker.replace(cx.waker().clone());
+            return Poll::Pending;
+        }
+        inner.feed_output(buf);
+        wake(&mut inner.master_read_waker);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+macro_rules! impl_pty_file {
+    (
+        $ty:ty,
+        read: ($read_buffer:ident, $readable:ident, $read_waker:ident),
+        write: ($write_buffer:ident, $peer_open:ident, $write_waker:ident)
+    ) => {
+        impl AsyncSeek for $ty {
+            fn start_seek(self: Pin<&mut Self>, _position: SeekFrom) -> io::Result<()> {
+                Ok(())
+            }
+
+            fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+                Poll::Ready(Ok(0))
+            }
+        }
+
+        impl VirtualFile for $ty {
+            fn last_accessed(&self) -> u64 {
+                0
+            }
+
+            fn last_modified(&self) -> u64 {
+                0
+            }
+
+            fn created_time(&self) -> u64 {
+                0
+            }
+
+            fn size(&self) -> u64 {
+                0
+            }
+
+            fn set_len(&mut self, _new_size: u64) -> virtual_fs::Result<()> {
+                Err(FsError::PermissionDenied)
+            }
+
+            fn unlink(&mut self) -> virtual_fs::Result<()> {
+                Ok(())
+            }
+
+            fn poll_read_ready(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+            ) -> Poll<io::Result<usize>> {
+                let mut inner = self.pty.inner.lock().unwrap();
+                if inner.$readable() {
+                    return Poll::Ready(Ok(inner.$read_buffer.len()));
+                }
+                inner.$read_waker.replace(cx.waker().clone());
+                Poll::Pending
+            }
+
+            fn poll_write_ready(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+            ) -> Poll<io::Result<usize>> {
+                let mut inner = self.pty.inner.lock().unwrap();
+                let buffered = inner.$write_buffer.len();
+                if buffered < MAX_BUFFERED || !inner.$peer_open {
+                    return Poll::Ready(Ok(MAX_BUFFERED.saturating_sub(buffered)));
+                }
+                inner.$write_waker.replace(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    };
+}
+
+impl_pty_file!(
+    PtyMaster,
+    read: (output, master_readable, master_read_waker),
+    write: (input, slave_open, master_write_waker)
+);
+impl_pty_file!(
+    PtySlave,
+    read: (input, slave_readable, slave_read_waker),
+    write: (output, master_open, slave_write_waker)
+);
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    async fn read_available(file: &mut (impl AsyncRead + Unpin)) -> String {
+        let mut buf = [0u8; 256];
+        let read = file.read(&mut buf).await.unwrap();
+        String::from_utf8_lossy(&buf[..read]).to_string()
+    }
+
+    #[tokio::test]
+    async fn canonical_mode_edits_lines_and_echoes() {
+        let (mut master, mut slave) = Pty::open();
+
+        master.write_all(b"lsx\x7f -l\r").await.unwrap();
+        assert_eq!(read_available(&mut slave).await, "ls -l\n");
+        assert_eq!(read_available(&mut master).await, "lsx\x08 \x08 -l\r\n");
+
+        slave.write_all(b"total 0\n").await.unwrap();
+        assert_eq!(read_available(&mut master).await, "total 0\r\n");
+    }
+
+    #[tokio::test]
+    async fn raw_mode_passes_bytes_through() {
+        let (mut master, mut slave) = Pty::open();
+        let mut state = master.pty().state();
+        state.echo = false;
+        state.line_buffered = false;
+        slave.pty().set_state(state);
+        slave.pty().set_signals(false);
+
+        master.write_all(b"\x03q").await.unwrap();
+        assert_eq!(read_available(&mut slave).await, "\x03q");
+    }
+
+    #[tokio::test]
+    async fn signal_characters_do_not_depend_on_canonical_mode() {
+        let (mut master, mut slave) = Pty::open();
+        let mut state = master.pty().state();
+        state.echo = false;
+        state.line_buffered = false;
+        slave.pty().set_state(state);
+
+        // ^C is swallowed (there is no foreground group to signal) while
+        // everything around it is passed through
+        master.write_all(b"a\x03b").await.unwrap();
+        assert_eq!(read_available(&mut slave).await, "ab");
+    }
+
+    #[tokio::test]
+    async fn ctrl_d_makes_the_slave_readable() {
+        let (mut master, mut slave) = Pty::open();
+        let ready = |slave: &mut PtySlave| {
+            let waker = futures::task::noop_waker();
+            let mut cx = Context::from_waker(&waker);
+            Pin::new(slave).poll_read_ready(&mut cx)
+        };
+
+        assert!(ready(&mut slave).is_pending());
+        master.write_all(b"\x04").await.unwrap();
+        assert!(matches!(ready(&mut slave), Poll::Ready(Ok(0))));
+        assert_eq!(read_available(&mut slave).await, "");
+        assert!(ready(&mut slave).is_pending());
+    }
+
+    #[tokio::test]
+    async fn reading_and_writing_wait_independently() {
+        let (master, mut slave) = Pty::open();
+        let mut state = master.pty().state();
+        state.echo = false;
+        state.line_buffered = false;
+        slave.pty().set_state(state);
+        let (mut master_rx, mut master_tx) = tokio::io::split(master);
+
+        // Fill the input so the next write has to wait
+        master_tx.write_all(&[b'x'; MAX_BUFFERED]).await.unwrap();
+        let writer = tokio::spawn(async move {
+            master_tx.write_all(b"more").await.unwrap();
+            master_tx
+        });
+        let reader = tokio::spawn(async move {
+            let output = read_available(&mut master_rx).await;
+            (output, master_rx)
+        });
+        tokio::task::yield_now().await;
+
+        // Both must be woken up, even though they wait on the same end
+        slave.write_all(b"hello").await.unwrap();
+        let (output, _master_rx) = tokio::time::timeout(Duration::from_secs(5), reader)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(output, "hello");
+
+        let mut drained = 0;
+        while drained < MAX_BUFFERED {
+            let mut buf = vec![0; MAX_BUFFERED];
+            drained += slave.read(&mut buf).await.unwrap();
+        }
+        let _master_tx = tokio::time::timeout(Duration::from_secs(5), writer)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(read_available(&mut slave).await, "more");
+    }
+
+    #[tokio::test]
+    async fn ctrl_d_and_hangup_produce_eof() {
+        let (mut master, mut slave) = Pty::open();
+
+        master.write_all(b"\x04").await.unwrap();
+        assert_eq!(read_available(&mut slave).await, "");
+
+        master.write_all(b"abc").await.unwrap();
+        drop(master);
+        assert_eq!(read_available(&mut slave).await, "");
+        assert!(slave.write_all(b"x").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn shutting_down_the_master_closes_the_input() {
+        let (mut master, mut slave) = Pty::open();
+
+        master.write_all(b"partial").await.unwrap();
+        master.shutdown().await.unwrap();
+        assert_eq!(read_available(&mut slave).await, "partial");
+        // Unlike ^D the end-of-file stays
+        assert_eq!(read_available(&mut slave).await, "");
+        assert_eq!(read_available(&mut slave).await, "");
+        assert!(master.write_all(b"x").await.is_err());
+
+        // The output still works, after the echo of the input
+        assert_eq!(read_available(&mut master).await, "partial");
+        slave.write_all(b"bye\n").await.unwrap();
+        assert_eq!(read_available(&mut master).await, "bye\r\n");
+    }
+}
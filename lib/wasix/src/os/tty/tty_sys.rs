@@ -1,9 +1,13 @@
-use super::TtyBridge;
+use std::sync::{Arc, Mutex};
+
+use super::{TtyBridge, TtyForeground};
 use crate::WasiTtyState;
 
 /// [`TtyBridge`] implementation for Unix systems.
 #[derive(Debug, Default, Clone)]
-pub struct SysTty;
+pub struct SysTty {
+    foreground: Arc<Mutex<Option<TtyForeground>>>,
+}
 
 impl TtyBridge for SysTty {
     fn reset(&self) {
@@ -50,6 +54,14 @@ impl TtyBridge for SysTty {
             sys::set_mode_no_line_feeds().ok();
         }
     }
+
+    fn foreground_get(&self) -> Option<TtyForeground> {
+        self.foreground.lock().unwrap().clone()
+    }
+
+    fn foreground_set(&self, foreground: Option<TtyForeground>) {
+        *self.foreground.lock().unwrap() = foreground;
+    }
 }
 
 mod sys_terminal_size {
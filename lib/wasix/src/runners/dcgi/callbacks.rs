@@ -37,6 +37,10 @@ impl wcgi::Callbacks for DcgiCallbacks {
         self.inner.on_stderr_error(error)
     }
 
+    fn on_pool_metrics(&self, metrics: wcgi::PoolMetrics) {
+        self.inner.on_pool_metrics(metrics)
+    }
+
     async fn recycle_env(&self, conf: RecycleEnvConfig) {
         tracing::debug!("recycling DCGI instance");
 
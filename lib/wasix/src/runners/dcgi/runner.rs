@@ -14,7 +14,7 @@ use crate::{
         wcgi::{self, NoOpWcgiCallbacks, WcgiRunner},
         MappedDirectory,
     },
-    runtime::{DynRuntime, OverriddenRuntime},
+    runtime::{metrics::Metrics, DynRuntime, OverriddenRuntime},
     Runtime,
 };
 
@@ -29,10 +29,10 @@ pub struct DcgiRunner {
 impl DcgiRunner {
     pub fn new(factory: DcgiInstanceFactory) -> Self {
         let callbacks = DcgiCallbacks::new(factory, NoOpWcgiCallbacks);
+        let mut inner = wcgi::Config::new(callbacks.clone());
+        inner.runner_name = "dcgi";
         DcgiRunner {
-            config: Config {
-                inner: wcgi::Config::new(callbacks.clone()),
-            },
+            config: Config { inner },
             inner: WcgiRunner::new(callbacks),
         }
     }
@@ -92,9 +92,17 @@ impl crate::runners::Runner for DcgiRunner {
         let runtime = OverriddenRuntime::new(runtime).with_journals(journals);
         let runtime = Arc::new(runtime) as Arc<DynRuntime>;
 
-        //We now pass the runtime to the handlers
-        let handler = self.prepare_handler(command_name, pkg, Arc::clone(&runtime))?;
-        self.inner.run_command_with_handler(handler, runtime)
+        // The WCGI runner does the actual work, so it needs to use our
+        // settings (we swap them back afterwards)
+        std::mem::swap(self.inner.config(), &mut self.config.inner);
+        let result = (|| {
+            //We now pass the runtime to the handlers
+            let handler = self.prepare_handler(command_name, pkg, Arc::clone(&runtime))?;
+            self.inner.run_command_with_handler(handler, runtime)
+        })();
+        std::mem::swap(self.inner.config(), &mut self.config.inner);
+
+        result
     }
 }
 
@@ -113,6 +121,12 @@ impl Config {
         self
     }
 
+    /// Record request, instance and memory metrics in a [`Metrics`] registry.
+    pub fn metrics(&mut self, metrics: Arc<Metrics>) -> &mut Self {
+        self.inner.metrics(metrics);
+        self
+    }
+
     /// Add an argument to the WASI executable's command-line arguments.
     pub fn arg(&mut self, arg: impl Into<String>) -> &mut Self {
         self.inner.arg(arg);
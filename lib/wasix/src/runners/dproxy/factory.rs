@@ -10,7 +10,7 @@ use wasmer_journal::{DynJournal, RecombinedJournal};
 
 use crate::{
     runners::Runner,
-    runtime::{DynRuntime, OverriddenRuntime},
+    runtime::{metrics::ActiveInstance, DynRuntime, OverriddenRuntime},
 };
 
 use super::{
@@ -52,6 +52,8 @@ impl DProxyInstanceFactory {
     }
 
     pub async fn spin_up(&self, handler: &Handler, shard: Shard) -> anyhow::Result<DProxyInstance> {
+        let start = Instant::now();
+
         // Get the runtime with its already wired local networking
         let runtime = handler.runtime.clone();
 
@@ -99,6 +101,11 @@ impl DProxyInstanceFactory {
         let connector_inner = connector.clone();
         let runtime = Arc::new(runtime) as Arc<DynRuntime>;
         let mut runner = handler.config.inner.clone();
+        let active = handler
+            .config
+            .metrics
+            .as_ref()
+            .map(|metrics| ActiveInstance::new(Arc::clone(metrics), "dproxy"));
         runtime
             .task_manager()
             .clone()
@@ -115,8 +122,13 @@ impl DProxyInstanceFactory {
                     state.instance.remove(&shard);
                 }
                 connector_inner.shutdown();
+                drop(active);
             }))?;
 
+        if let Some(metrics) = &handler.config.metrics {
+            metrics.record_instance_creation("dproxy", start.elapsed());
+        }
+
         // Return an instance
         Ok(DProxyInstance {
             last_used: Arc::new(Mutex::new(Instant::now())),
@@ -1,7 +1,6 @@
 use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 use anyhow::{Context, Error};
-use futures::{stream::FuturesUnordered, StreamExt};
 use http::Request;
 use tower::ServiceBuilder;
 use tower_http::{catch_panic::CatchPanicLayer, cors::CorsLayer, trace::TraceLayer};
@@ -9,8 +8,12 @@ use webc::metadata::Command;
 
 use crate::{
     bin_factory::BinaryPackage,
-    runners::wasi::WasiRunner,
-    runtime::{task_manager::VirtualTaskManagerExt, DynRuntime},
+    runners::{
+        server::{serve, MetricsLayer},
+        wasi::WasiRunner,
+        TlsConfig,
+    },
+    runtime::{metrics::Metrics, task_manager::VirtualTaskManagerExt, DynRuntime},
 };
 
 use super::factory::DProxyInstanceFactory;
@@ -59,6 +62,7 @@ impl crate::runners::Runner for DProxyRunner {
         // We create a HTTP server which will reverse proxy all the requests
         // to the proxy workload
         let service = ServiceBuilder::new()
+            .layer(MetricsLayer::new(self.config.metrics.clone(), "dproxy"))
             .layer(
                 TraceLayer::new_for_http()
                     .make_span_with(|request: &Request<hyper::body::Incoming>| {
@@ -78,45 +82,21 @@ impl crate::runners::Runner for DProxyRunner {
         let address = self.config.addr;
         tracing::info!(%address, "Starting the DProxy server");
 
+        let http2 = self.config.http2;
+        let tls = self.config.tls.clone();
         runtime
             .task_manager()
             .spawn_and_block_on(async move {
-                let (mut shutdown, _abort_handle) =
-                    futures::future::abortable(futures::future::pending::<()>());
-
                 let listener = tokio::net::TcpListener::bind(&address).await?;
-                let graceful = hyper_util::server::graceful::GracefulShutdown::new();
-
-                let http = hyper::server::conn::http1::Builder::new();
-
-                let mut futs = FuturesUnordered::new();
-
-                loop {
-                    tokio::select! {
-                        Ok((stream, _addr)) = listener.accept() => {
-                            let io = hyper_util::rt::tokio::TokioIo::new(stream);
-                            let service = hyper_util::service::TowerToHyperService::new(service.clone());
-                            let conn = http.serve_connection(io, service);
-                            // watch this connection
-                            let fut = graceful.watch(conn);
-                            futs.push(async move {
-                                if let Err(e) = fut.await {
-                                    eprintln!("Error serving connection: {e:?}");
-                                }
-                            });
-                        },
-
-                        _ = futs.next() => {}
-
-                        _ = &mut shutdown => {
-                            tracing::info!("Shutting down gracefully");
-                            // stop the accept loop
-                            break;
-                        }
-                    }
-                }
-
-                Ok::<_, anyhow::Error>(())
+                let service = hyper_util::service::TowerToHyperService::new(service);
+                serve(
+                    listener,
+                    service,
+                    http2,
+                    tls.as_ref(),
+                    futures::future::pending(),
+                )
+                .await
             })
             .context("Unable to start the server")??;
 
@@ -128,6 +108,9 @@ impl crate::runners::Runner for DProxyRunner {
 pub struct Config {
     pub(crate) inner: WasiRunner,
     pub(crate) addr: SocketAddr,
+    pub(crate) http2: bool,
+    pub(crate) tls: Option<TlsConfig>,
+    pub(crate) metrics: Option<Arc<Metrics>>,
     pub(crate) pkg: BinaryPackage,
     pub(crate) proxy_connect_init_timeout: Duration,
     pub(crate) proxy_connect_nominal_timeout: Duration,
@@ -139,6 +122,9 @@ impl Config {
             inner,
             pkg: pkg.clone(),
             addr: ([127, 0, 0, 1], 8000).into(),
+            http2: false,
+            tls: None,
+            metrics: None,
             proxy_connect_init_timeout: Duration::from_secs(30),
             proxy_connect_nominal_timeout: Duration::from_secs(30),
         }
@@ -148,6 +134,25 @@ impl Config {
         self.addr = addr;
         self
     }
+
+    /// Serve HTTP/2 alongside HTTP/1, negotiated through ALPN when TLS is
+    /// enabled and with prior knowledge (h2c) otherwise.
+    pub fn http2(&mut self, enabled: bool) -> &mut Self {
+        self.http2 = enabled;
+        self
+    }
+
+    /// Terminate TLS using the given certificate and private key.
+    pub fn tls(&mut self, tls: TlsConfig) -> &mut Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Record request and instance metrics in a [`Metrics`] registry.
+    pub fn metrics(&mut self, metrics: Arc<Metrics>) -> &mut Self {
+        self.metrics = Some(metrics);
+        self
+    }
 }
 
 #[cfg(test)]
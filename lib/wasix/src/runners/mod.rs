@@ -4,12 +4,18 @@ mod runner;
 pub mod dcgi;
 #[cfg(feature = "webc_runner_rt_dproxy")]
 pub mod dproxy;
+#[cfg(feature = "webc_runner_rt_proxy_wasm")]
+pub mod proxy_wasm;
 pub mod wasi;
 mod wasi_common;
 #[cfg(feature = "webc_runner_rt_wcgi")]
 pub mod wcgi;
 
-#[cfg(any(feature = "webc_runner_rt_wcgi", feature = "webc_runner_rt_dproxy"))]
+#[cfg(any(
+    feature = "webc_runner_rt_wcgi",
+    feature = "webc_runner_rt_dproxy",
+    feature = "webc_runner_rt_proxy_wasm"
+))]
 mod body {
     use http_body_util::{combinators::BoxBody, BodyExt, Full};
 
@@ -30,9 +36,26 @@ mod body {
     }
 }
 
-#[cfg(any(feature = "webc_runner_rt_wcgi", feature = "webc_runner_rt_dproxy"))]
+#[cfg(any(
+    feature = "webc_runner_rt_wcgi",
+    feature = "webc_runner_rt_dproxy",
+    feature = "webc_runner_rt_proxy_wasm"
+))]
 pub use self::body::*;
 
+#[cfg(any(
+    feature = "webc_runner_rt_wcgi",
+    feature = "webc_runner_rt_dproxy",
+    feature = "webc_runner_rt_proxy_wasm"
+))]
+mod server;
+#[cfg(any(
+    feature = "webc_runner_rt_wcgi",
+    feature = "webc_runner_rt_dproxy",
+    feature = "webc_runner_rt_proxy_wasm"
+))]
+pub use self::server::TlsConfig;
+
 pub use self::{
     runner::Runner,
     wasi_common::{
@@ -43,7 +66,11 @@ pub use self::{
 // For some reason, providing the same code to on_response() in a lambda
 // causes lifetime-related errors, so we use an owned struct instead to
 // make *absolutely* sure it's 'static.
-#[cfg(any(feature = "webc_runner_rt_wcgi", feature = "webc_runner_rt_dproxy"))]
+#[cfg(any(
+    feature = "webc_runner_rt_wcgi",
+    feature = "webc_runner_rt_dproxy",
+    feature = "webc_runner_rt_proxy_wasm"
+))]
 mod response_tracing {
     use tower_http::trace::OnResponse;
 
@@ -0,0 +1,250 @@
+//! Constants and encodings from the [Proxy-Wasm ABI][spec].
+//!
+//! [spec]: https://github.com/proxy-wasm/spec/tree/master/abi-versions/v0.2.1
+
+use wasmer::Module;
+
+/// The export that tells us which version of the ABI a module was built for.
+pub(crate) const ABI_VERSION_PREFIX: &str = "proxy_abi_version_";
+
+/// The ABI versions this runner knows how to drive.
+pub(crate) const SUPPORTED_ABI_VERSIONS: &[&str] = &["0_2_0", "0_2_1"];
+
+/// The version of the ABI a module was built for (e.g. `0_2_1`), if it is a
+/// Proxy-Wasm module at all.
+pub(crate) fn abi_version(module: &Module) -> Option<String> {
+    module.exports().find_map(|export| {
+        export
+            .name()
+            .strip_prefix(ABI_VERSION_PREFIX)
+            .map(String::from)
+    })
+}
+
+/// Headers and trailers, including pseudo-headers such as `:path`.
+pub(crate) type HeaderPairs = Vec<(String, String)>;
+
+/// The status code returned by every host function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub(crate) enum Status {
+    Ok = 0,
+    NotFound = 1,
+    BadArgument = 2,
+    SerializationFailure = 3,
+    InvalidMemoryAccess = 6,
+    CasMismatch = 8,
+    InternalFailure = 10,
+    Unimplemented = 12,
+}
+
+/// The value returned by the guest's stream callbacks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Action {
+    Continue,
+    Pause,
+}
+
+impl Action {
+    pub(crate) fn from_raw(raw: i32) -> Self {
+        match raw {
+            0 => Action::Continue,
+            _ => Action::Pause,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MapType {
+    RequestHeaders,
+    RequestTrailers,
+    ResponseHeaders,
+    ResponseTrailers,
+    HttpCallResponseHeaders,
+    HttpCallResponseTrailers,
+}
+
+impl MapType {
+    pub(crate) fn from_raw(raw: i32) -> Option<Self> {
+        match raw {
+            0 => Some(MapType::RequestHeaders),
+            1 => Some(MapType::RequestTrailers),
+            2 => Some(MapType::ResponseHeaders),
+            3 => Some(MapType::ResponseTrailers),
+            6 => Some(MapType::HttpCallResponseHeaders),
+            7 => Some(MapType::HttpCallResponseTrailers),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BufferType {
+    RequestBody,
+    ResponseBody,
+    HttpCallResponseBody,
+    VmConfiguration,
+    PluginConfiguration,
+}
+
+impl BufferType {
+    pub(crate) fn from_raw(raw: i32) -> Option<Self> {
+        match raw {
+            0 => Some(BufferType::RequestBody),
+            1 => Some(BufferType::ResponseBody),
+            4 => Some(BufferType::HttpCallResponseBody),
+            6 => Some(BufferType::VmConfiguration),
+            7 => Some(BufferType::PluginConfiguration),
+            _ => None,
+        }
+    }
+}
+
+/// The stream a filter asks to resume or close.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StreamType {
+    Request,
+    Response,
+}
+
+impl StreamType {
+    pub(crate) fn from_raw(raw: i32) -> Option<Self> {
+        match raw {
+            0 => Some(StreamType::Request),
+            1 => Some(StreamType::Response),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MetricType {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl MetricType {
+    pub(crate) fn from_raw(raw: i32) -> Option<Self> {
+        match raw {
+            0 => Some(MetricType::Counter),
+            1 => Some(MetricType::Gauge),
+            2 => Some(MetricType::Histogram),
+            _ => None,
+        }
+    }
+}
+
+/// Encode header pairs the way the guest expects them.
+///
+/// The map starts with the number of pairs, followed by the length of each
+/// key and value, followed by the keys and values themselves, each one
+/// terminated by a nul byte. All integers are little-endian `u32`s.
+pub(crate) fn serialize_map(pairs: &[(String, String)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&(pairs.len() as u32).to_le_bytes());
+    for (key, value) in pairs {
+        out.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    }
+    for (key, value) in pairs {
+        out.extend_from_slice(key.as_bytes());
+        out.push(0);
+        out.extend_from_slice(value.as_bytes());
+        out.push(0);
+    }
+
+    out
+}
+
+/// The inverse of [`serialize_map()`].
+pub(crate) fn deserialize_map(bytes: &[u8]) -> Result<HeaderPairs, Status> {
+    if bytes.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut reader = Reader(bytes);
+    let count = reader.u32()? as usize;
+    let sizes = (0..count)
+        .map(|_| Ok((reader.u32()? as usize, reader.u32()? as usize)))
+        .collect::<Result<Vec<_>, Status>>()?;
+
+    sizes
+        .into_iter()
+        .map(|(key_len, value_len)| {
+            let key = reader.string(key_len)?;
+            let value = reader.string(value_len)?;
+            Ok((key, value))
+        })
+        .collect()
+}
+
+/// Split a property path (e.g. `request\0path`) into its segments.
+pub(crate) fn split_path(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .split(|b| *b == 0)
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| String::from_utf8_lossy(segment).into_owned())
+        .collect()
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], Status> {
+        if self.0.len() < len {
+            return Err(Status::SerializationFailure);
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32, Status> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// Read a string and the nul byte that follows it.
+    fn string(&mut self, len: usize) -> Result<String, Status> {
+        let bytes = self.take(len + 1)?;
+        Ok(String::from_utf8_lossy(&bytes[..len]).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_header_maps() {
+        let pairs = vec![
+            (":path".to_string(), "/".to_string()),
+            ("content-type".to_string(), "text/plain".to_string()),
+            ("x-empty".to_string(), String::new()),
+        ];
+
+        let bytes = serialize_map(&pairs);
+
+        assert_eq!(&bytes[..4], &3_u32.to_le_bytes());
+        assert_eq!(&bytes[4..12], &[5, 0, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(deserialize_map(&bytes).unwrap(), pairs);
+    }
+
+    #[test]
+    fn truncated_maps_are_rejected() {
+        let bytes = serialize_map(&[("key".to_string(), "value".to_string())]);
+
+        assert_eq!(
+            deserialize_map(&bytes[..bytes.len() - 1]),
+            Err(Status::SerializationFailure)
+        );
+        assert_eq!(deserialize_map(&[]), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn split_property_paths() {
+        assert_eq!(split_path(b"request\0path\0"), vec!["request", "path"]);
+        assert_eq!(split_path(b"plugin_root_id"), vec!["plugin_root_id"]);
+    }
+}
@@ -0,0 +1,893 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    time::Instant,
+};
+
+use anyhow::{Context, Error};
+use bytes::Bytes;
+use http::{header, HeaderMap, HeaderName, HeaderValue, Method, Response, StatusCode};
+use tokio::sync::oneshot;
+use url::Url;
+use wasmer::{FunctionEnv, Instance, Module, Store, Value};
+use wasmer_types::ModuleHash;
+
+use super::{
+    abi::{self, Action, HeaderPairs, StreamType},
+    host::{self, header_value, HostState, HttpCallResponse, StreamAction, StreamData},
+    Config,
+};
+use crate::{
+    http::{DynHttpClient, HttpRequest, HttpRequestOptions, HttpResponse},
+    runners::{body_from_data, Body},
+    runtime::task_manager::VirtualTaskManager,
+    Runtime, WasiEnvBuilder, WasiFunctionEnv,
+};
+
+/// The ID of the context which owns the filter's configuration, timers and
+/// HTTP calls that aren't tied to a request.
+pub(crate) const ROOT_CONTEXT_ID: u32 = 1;
+
+pub(crate) type Reply = oneshot::Sender<Result<Response<Body>, Error>>;
+
+/// Something the [`Filter`] needs to react to.
+pub(crate) enum Event {
+    /// A new request came in.
+    Request {
+        headers: HeaderPairs,
+        body: Bytes,
+        reply: Reply,
+    },
+    /// The upstream answered a request.
+    Upstream {
+        context_id: u32,
+        result: Result<HttpResponse, Error>,
+    },
+    /// An HTTP call made by the guest finished.
+    HttpCallResponse {
+        context_id: u32,
+        token: u32,
+        result: Result<HttpResponse, Error>,
+    },
+}
+
+/// Where a request is in its journey through the filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    RequestHeaders,
+    RequestBody,
+    Upstream,
+    WaitingForUpstream,
+    ResponseHeaders,
+    ResponseBody,
+    Respond,
+}
+
+impl Step {
+    /// The stream that has to be resumed for this step to be taken.
+    fn stream_type(self) -> Option<StreamType> {
+        match self {
+            Step::RequestHeaders | Step::RequestBody | Step::Upstream => Some(StreamType::Request),
+            Step::ResponseHeaders | Step::ResponseBody | Step::Respond => {
+                Some(StreamType::Response)
+            }
+            Step::WaitingForUpstream => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Stream {
+    /// The next step to take.
+    step: Step,
+    /// Set when the guest asked us to wait before taking the next step.
+    paused: bool,
+    reply: Reply,
+}
+
+/// A Proxy-Wasm guest, along with the requests it is handling.
+///
+/// The guest is single-threaded, so a filter is owned by a dedicated thread
+/// which reacts to [`Event`]s (see [`Filter::run()`]). Anything which
+/// requires waiting (e.g. talking to the upstream) is done in the
+/// background and reported back as another [`Event`].
+pub(crate) struct Filter {
+    store: Store,
+    instance: Instance,
+    env: FunctionEnv<HostState>,
+    wasi_env: WasiFunctionEnv,
+    upstream: Url,
+    clusters: HashMap<String, Url>,
+    http_client: DynHttpClient,
+    tasks: Arc<dyn VirtualTaskManager>,
+    events: mpsc::Sender<Event>,
+    streams: HashMap<u32, Stream>,
+    next_context_id: u32,
+    /// Set when the guest trapped, after which it won't be called again.
+    failed: bool,
+}
+
+impl Filter {
+    pub(crate) fn new(
+        config: &Config,
+        module: &Module,
+        module_hash: ModuleHash,
+        mut builder: WasiEnvBuilder,
+        runtime: &Arc<dyn Runtime + Send + Sync>,
+        events: mpsc::Sender<Event>,
+    ) -> Result<Self, Error> {
+        let version = abi::abi_version(module).context("Not a Proxy-Wasm module")?;
+        anyhow::ensure!(
+            abi::SUPPORTED_ABI_VERSIONS.contains(&version.as_str()),
+            "Version {} of the Proxy-Wasm ABI isn't supported",
+            version.replace('_', ".")
+        );
+
+        let http_client = runtime
+            .http_client()
+            .cloned()
+            .context("The runtime doesn't have an HTTP client")?;
+
+        let mut store = runtime.new_store();
+        let env = FunctionEnv::new(
+            &mut store,
+            HostState::new(
+                config.root_id.clone(),
+                config.vm_configuration.clone(),
+                config.plugin_configuration.clone(),
+            ),
+        );
+        let imports = host::imports(module, &mut store, &env);
+        builder.add_imports(&imports);
+
+        let (instance, wasi_env) = builder
+            .instantiate_ext(module.clone(), module_hash, &mut store)
+            .context("Unable to instantiate the filter")?;
+
+        let memory = instance.exports.get_memory("memory")?.clone();
+        let allocate = instance
+            .exports
+            .get_typed_function(&store, "proxy_on_memory_allocate")
+            .or_else(|_| instance.exports.get_typed_function(&store, "malloc"))
+            .context("The filter doesn't export \"proxy_on_memory_allocate\"")?;
+        env.as_mut(&mut store).init(memory, allocate);
+
+        // Filters built as WASI commands do their setup in main()
+        if let Ok(start) = instance.exports.get_function("_start") {
+            crate::run_wasi_func_start(start, &mut store)
+                .context("The filter's _start function failed")?;
+        }
+
+        Ok(Filter {
+            store,
+            instance,
+            env,
+            wasi_env,
+            upstream: config.upstream.clone(),
+            clusters: config.clusters.clone(),
+            http_client,
+            tasks: Arc::clone(runtime.task_manager()),
+            events,
+            streams: HashMap::new(),
+            next_context_id: ROOT_CONTEXT_ID + 1,
+            failed: false,
+        })
+    }
+
+    /// Create the root context and hand it the VM and plugin configuration.
+    pub(crate) fn start(&mut self) -> Result<(), Error> {
+        self.call("proxy_on_context_create", ROOT_CONTEXT_ID, &[0])?;
+
+        let size = self.host().vm_configuration().len() as i32;
+        if self.call("proxy_on_vm_start", ROOT_CONTEXT_ID, &[size])? == Some(0) {
+            anyhow::bail!("The filter failed to start");
+        }
+
+        let size = self.host().plugin_configuration().len() as i32;
+        if self.call("proxy_on_configure", ROOT_CONTEXT_ID, &[size])? == Some(0) {
+            anyhow::bail!("The filter rejected its configuration");
+        }
+
+        self.process_host_requests();
+        Ok(())
+    }
+
+    /// React to events, calling the root context's `proxy_on_tick()` at the
+    /// interval the guest asked for.
+    pub(crate) fn run(mut self, events: mpsc::Receiver<Event>) {
+        let mut next_tick = None;
+
+        loop {
+            let event = match self.host().tick_period {
+                Some(period) => {
+                    let deadline = *next_tick.get_or_insert_with(|| Instant::now() + period);
+                    match events.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                        Ok(event) => Some(event),
+                        Err(RecvTimeoutError::Timeout) => None,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+                None => match events.recv() {
+                    Ok(event) => Some(event),
+                    Err(_) => break,
+                },
+            };
+
+            match event {
+                Some(event) => self.handle(event),
+                None => {
+                    next_tick = None;
+                    self.tick();
+                }
+            }
+        }
+
+        self.wasi_env.on_exit(&mut self.store, None);
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Request {
+                headers,
+                body,
+                reply,
+            } => self.on_request(headers, body, reply),
+            Event::Upstream { context_id, result } => self.on_upstream_response(context_id, result),
+            Event::HttpCallResponse {
+                context_id,
+                token,
+                result,
+            } => self.on_http_call_response(context_id, token, result),
+        }
+    }
+
+    fn host(&mut self) -> &mut HostState {
+        self.env.as_mut(&mut self.store)
+    }
+
+    /// Call one of the guest's callbacks on behalf of a context, returning
+    /// `None` if the guest doesn't export it.
+    fn call(&mut self, name: &str, context_id: u32, args: &[i32]) -> Result<Option<i32>, Error> {
+        anyhow::ensure!(!self.failed, "The filter has crashed");
+
+        let Ok(function) = self.instance.exports.get_function(name).cloned() else {
+            return Ok(None);
+        };
+
+        self.host().effective_context = context_id;
+        let args: Vec<Value> = std::iter::once(context_id as i32)
+            .chain(args.iter().copied())
+            .map(Value::I32)
+            .collect();
+        let result = function
+            .call(&mut self.store, &args)
+            .with_context(|| format!("\"{name}\" failed"))?;
+
+        Ok(result.first().and_then(Value::i32))
+    }
+
+    fn on_request(&mut self, headers: HeaderPairs, body: Bytes, reply: Reply) {
+        if self.failed {
+            let _ = reply.send(Ok(error_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "The filter has crashed",
+            )));
+            return;
+        }
+
+        let context_id = self.next_context_id;
+        self.next_context_id += 1;
+
+        self.host().streams.insert(
+            context_id,
+            StreamData {
+                request_headers: headers,
+                request_body: body.into(),
+                ..Default::default()
+            },
+        );
+        self.streams.insert(
+            context_id,
+            Stream {
+                step: Step::RequestHeaders,
+                paused: false,
+                reply,
+            },
+        );
+
+        match self.call(
+            "proxy_on_context_create",
+            context_id,
+            &[ROOT_CONTEXT_ID as i32],
+        ) {
+            Ok(_) => self.advance(context_id),
+            Err(e) => self.crash(e),
+        }
+    }
+
+    /// Keep taking steps until the request has been answered or the guest
+    /// asks us to wait.
+    fn advance(&mut self, context_id: u32) {
+        loop {
+            let step = match self.streams.get(&context_id) {
+                Some(stream) if !stream.paused => stream.step,
+                // Either the guest asked us to wait or it already answered
+                // the request
+                _ => return,
+            };
+
+            let (num_headers, request_body, response_body) = {
+                let data = &self.host().streams[&context_id];
+                let num_headers = match step {
+                    Step::ResponseHeaders => data.response_headers.len(),
+                    _ => data.request_headers.len(),
+                };
+                (
+                    num_headers as i32,
+                    data.request_body.len() as i32,
+                    data.response_body.len() as i32,
+                )
+            };
+
+            let (next, outcome) = match step {
+                Step::RequestHeaders => {
+                    let end_of_stream = (request_body == 0) as i32;
+                    let next = if request_body == 0 {
+                        Step::Upstream
+                    } else {
+                        Step::RequestBody
+                    };
+                    let outcome = self.call_action(
+                        "proxy_on_request_headers",
+                        context_id,
+                        &[num_headers, end_of_stream],
+                    );
+                    (next, outcome)
+                }
+                Step::RequestBody => {
+                    let outcome =
+                        self.call_action("proxy_on_request_body", context_id, &[request_body, 1]);
+                    (Step::Upstream, outcome)
+                }
+                Step::Upstream => {
+                    let outcome = self.forward(context_id).map(|_| Action::Pause);
+                    (Step::WaitingForUpstream, outcome)
+                }
+                Step::WaitingForUpstream => (Step::WaitingForUpstream, Ok(Action::Pause)),
+                Step::ResponseHeaders => {
+                    let end_of_stream = (response_body == 0) as i32;
+                    let next = if response_body == 0 {
+                        Step::Respond
+                    } else {
+                        Step::ResponseBody
+                    };
+                    let outcome = self.call_action(
+                        "proxy_on_response_headers",
+                        context_id,
+                        &[num_headers, end_of_stream],
+                    );
+                    (next, outcome)
+                }
+                Step::ResponseBody => {
+                    let outcome =
+                        self.call_action("proxy_on_response_body", context_id, &[response_body, 1]);
+                    (Step::Respond, outcome)
+                }
+                Step::Respond => {
+                    let response = self.response(context_id);
+                    self.finish(context_id, response);
+                    return;
+                }
+            };
+
+            if let Some(stream) = self.streams.get_mut(&context_id) {
+                stream.step = next;
+                stream.paused = outcome.as_ref().is_ok_and(|a| *a == Action::Pause);
+            }
+
+            match outcome {
+                Ok(_) => self.process_host_requests(),
+                Err(e) => {
+                    self.crash(e);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn call_action(&mut self, name: &str, context_id: u32, args: &[i32]) -> Result<Action, Error> {
+        let action = self.call(name, context_id, args)?;
+        Ok(action.map(Action::from_raw).unwrap_or(Action::Continue))
+    }
+
+    /// Send the (possibly modified) request to the upstream.
+    fn forward(&mut self, context_id: u32) -> Result<(), Error> {
+        let upstream = self.upstream.clone();
+        let data = &self.host().streams[&context_id];
+        let request = http_request(&upstream, &data.request_headers, &data.request_body)?;
+
+        let client = Arc::clone(&self.http_client);
+        let events = self.events.clone();
+        self.tasks.task_shared(Box::new(move || {
+            Box::pin(async move {
+                let result = client.request(request).await;
+                let _ = events.send(Event::Upstream { context_id, result });
+            })
+        }))?;
+
+        Ok(())
+    }
+
+    fn on_upstream_response(&mut self, context_id: u32, result: Result<HttpResponse, Error>) {
+        if !self.streams.contains_key(&context_id) {
+            return;
+        }
+
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::warn!(error = &*e, "Unable to reach the upstream");
+                let response =
+                    error_response(StatusCode::BAD_GATEWAY, "Unable to reach the upstream");
+                self.finish(context_id, Ok(response));
+                return;
+            }
+        };
+
+        if let Some(data) = self.host().streams.get_mut(&context_id) {
+            data.response_headers = response_headers(&response);
+            data.response_body = response.body.unwrap_or_default();
+        }
+        if let Some(stream) = self.streams.get_mut(&context_id) {
+            stream.step = Step::ResponseHeaders;
+            stream.paused = false;
+        }
+        self.advance(context_id);
+    }
+
+    fn on_http_call_response(
+        &mut self,
+        context_id: u32,
+        token: u32,
+        result: Result<HttpResponse, Error>,
+    ) {
+        if context_id != ROOT_CONTEXT_ID && !self.streams.contains_key(&context_id) {
+            return;
+        }
+
+        let response = match result {
+            Ok(response) => HttpCallResponse {
+                headers: response_headers(&response),
+                body: response.body.unwrap_or_default(),
+                trailers: Vec::new(),
+            },
+            Err(e) => {
+                // The guest is told about failures through an empty response
+                tracing::warn!(error = &*e, token, "HTTP call failed");
+                HttpCallResponse::default()
+            }
+        };
+        let args = [
+            token as i32,
+            response.headers.len() as i32,
+            response.body.len() as i32,
+            0,
+        ];
+
+        self.host().http_call_response = Some(response);
+        let result = self.call("proxy_on_http_call_response", context_id, &args);
+        self.host().http_call_response = None;
+
+        match result {
+            Ok(_) => self.process_host_requests(),
+            Err(e) => self.crash(e),
+        }
+    }
+
+    fn tick(&mut self) {
+        match self.call("proxy_on_tick", ROOT_CONTEXT_ID, &[]) {
+            Ok(_) => self.process_host_requests(),
+            Err(e) => self.crash(e),
+        }
+    }
+
+    /// Act on whatever the guest asked for through host functions.
+    fn process_host_requests(&mut self) {
+        let calls = std::mem::take(&mut self.host().http_calls);
+        for call in calls {
+            self.dispatch_http_call(call);
+        }
+
+        let actions = std::mem::take(&mut self.host().actions);
+        for (context_id, action) in actions {
+            match action {
+                StreamAction::Continue(stream_type) => {
+                    if let Some(stream) = self.streams.get_mut(&context_id) {
+                        if stream.paused && stream.step.stream_type() == Some(stream_type) {
+                            stream.paused = false;
+                            self.advance(context_id);
+                        }
+                    }
+                }
+                StreamAction::Close => {
+                    self.finish(context_id, Err(Error::msg("The filter closed the stream")));
+                }
+                StreamAction::LocalResponse(local) => {
+                    let response = build_response(local.status, &local.headers, local.body);
+                    self.finish(context_id, response);
+                }
+            }
+        }
+    }
+
+    fn dispatch_http_call(&mut self, call: host::HttpCall) {
+        let host::HttpCall {
+            token,
+            context_id,
+            upstream,
+            headers,
+            body,
+            timeout,
+        } = call;
+
+        let request = match self.clusters.get(&upstream) {
+            Some(url) => Ok(url.clone()),
+            None => Url::parse(&format!("http://{upstream}/"))
+                .with_context(|| format!("Unknown upstream, \"{upstream}\"")),
+        }
+        .and_then(|base| http_request(&base, &headers, &body));
+
+        let client = Arc::clone(&self.http_client);
+        let events = self.events.clone();
+        let result = self.tasks.task_shared(Box::new(move || {
+            Box::pin(async move {
+                let result = match request {
+                    Ok(request) if timeout.is_zero() => client.request(request).await,
+                    Ok(request) => tokio::time::timeout(timeout, client.request(request))
+                        .await
+                        .unwrap_or_else(|_| Err(anyhow::anyhow!("Timed out after {timeout:?}"))),
+                    Err(e) => Err(e),
+                };
+                let _ = events.send(Event::HttpCallResponse {
+                    context_id,
+                    token,
+                    result,
+                });
+            })
+        }));
+
+        if let Err(e) = result {
+            tracing::warn!(
+                error = &e as &dyn std::error::Error,
+                "Unable to dispatch an HTTP call"
+            );
+        }
+    }
+
+    /// Turn the response headers and body into the final response.
+    fn response(&mut self, context_id: u32) -> Result<Response<Body>, Error> {
+        let data = self
+            .host()
+            .streams
+            .get_mut(&context_id)
+            .context("Unknown stream")?;
+        let status = header_value(&data.response_headers, ":status")
+            .and_then(|status| status.parse().ok())
+            .unwrap_or(200);
+        let body = std::mem::take(&mut data.response_body);
+
+        build_response(status, &data.response_headers, body)
+    }
+
+    /// Answer a request and let the guest clean up after it.
+    fn finish(&mut self, context_id: u32, response: Result<Response<Body>, Error>) {
+        let Some(stream) = self.streams.remove(&context_id) else {
+            return;
+        };
+        let _ = stream.reply.send(response);
+
+        for callback in ["proxy_on_done", "proxy_on_log", "proxy_on_delete"] {
+            if self.failed {
+                break;
+            }
+            if let Err(e) = self.call(callback, context_id, &[]) {
+                self.crash(e);
+            }
+        }
+
+        self.host().streams.remove(&context_id);
+    }
+
+    /// The guest trapped, so it can't be trusted with any more requests.
+    fn crash(&mut self, error: Error) {
+        tracing::error!(error = &*error, "The filter crashed");
+        self.failed = true;
+
+        for (context_id, stream) in self.streams.drain() {
+            let _ = stream.reply.send(Ok(error_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "The filter has crashed",
+            )));
+            self.env.as_mut(&mut self.store).streams.remove(&context_id);
+        }
+    }
+}
+
+/// Headers which would be wrong once the guest modified the body.
+fn is_hop_by_hop(name: &str) -> bool {
+    name.starts_with(':')
+        || name.eq_ignore_ascii_case("content-length")
+        || name.eq_ignore_ascii_case("transfer-encoding")
+}
+
+fn http_request(base: &Url, headers: &HeaderPairs, body: &[u8]) -> Result<HttpRequest, Error> {
+    let method: Method = header_value(headers, ":method")
+        .unwrap_or_else(|| "GET".to_string())
+        .parse()
+        .context("Invalid method")?;
+    let path = header_value(headers, ":path").unwrap_or_else(|| "/".to_string());
+    let url = base
+        .join(&path)
+        .with_context(|| format!("Invalid path, \"{path}\""))?;
+
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+        if is_hop_by_hop(name) {
+            continue;
+        }
+        header_map.append(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_str(value)?,
+        );
+    }
+    if let Some(authority) = header_value(headers, ":authority") {
+        header_map.insert(header::HOST, HeaderValue::from_str(&authority)?);
+    }
+
+    Ok(HttpRequest {
+        url,
+        method,
+        headers: header_map,
+        body: if body.is_empty() {
+            None
+        } else {
+            Some(body.to_vec())
+        },
+        options: HttpRequestOptions::default(),
+    })
+}
+
+fn response_headers(response: &HttpResponse) -> HeaderPairs {
+    let status = (":status".to_string(), response.status.as_u16().to_string());
+    let headers = response.headers.iter().map(|(name, value)| {
+        (
+            name.to_string(),
+            String::from_utf8_lossy(value.as_bytes()).into_owned(),
+        )
+    });
+
+    std::iter::once(status).chain(headers).collect()
+}
+
+fn build_response(
+    status: u16,
+    headers: &HeaderPairs,
+    body: Vec<u8>,
+) -> Result<Response<Body>, Error> {
+    let mut builder = Response::builder().status(status);
+    for (name, value) in headers {
+        if !is_hop_by_hop(name) {
+            builder = builder.header(name, value);
+        }
+    }
+
+    builder
+        .body(body_from_data(body))
+        .context("The filter generated an invalid response")
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(body_from_data(format!("{message}\n")))
+        .expect("the response is always valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use futures::future::BoxFuture;
+    use http_body_util::BodyExt;
+
+    use super::*;
+    use crate::{
+        http::HttpClient,
+        runners::wasi::WasiRunner,
+        runtime::{task_manager::tokio::TokioTaskManager, PluggableRuntime},
+    };
+
+    /// An upstream which records requests and always says hello.
+    #[derive(Debug, Default)]
+    struct Upstream {
+        requests: Mutex<Vec<HttpRequest>>,
+    }
+
+    impl HttpClient for Upstream {
+        fn request(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, Error>> {
+            self.requests.lock().unwrap().push(request);
+            Box::pin(async {
+                Ok(HttpResponse {
+                    body: Some(b"hello".to_vec()),
+                    redirected: false,
+                    status: StatusCode::OK,
+                    headers: HeaderMap::new(),
+                })
+            })
+        }
+    }
+
+    /// A filter with a bump allocator and a few strings at known offsets.
+    fn filter_wat(callbacks: &str) -> String {
+        let auth_call = abi::serialize_map(
+            &[
+                (":method", "GET"),
+                (":path", "/check"),
+                (":authority", "auth"),
+            ]
+            .map(|(k, v)| (k.to_string(), v.to_string())),
+        );
+        let auth_call: String = auth_call.iter().map(|b| format!("\\{b:02x}")).collect();
+
+        format!(
+            r#"(module
+                (import "env" "proxy_add_header_map_value" (func $add_header (param i32 i32 i32 i32 i32) (result i32)))
+                (import "env" "proxy_send_local_response" (func $local_response (param i32 i32 i32 i32 i32 i32 i32 i32) (result i32)))
+                (import "env" "proxy_http_call" (func $http_call (param i32 i32 i32 i32 i32 i32 i32 i32 i32 i32) (result i32)))
+                (import "env" "proxy_continue_stream" (func $continue_stream (param i32) (result i32)))
+                (memory (export "memory") 1)
+                (global $heap (mut i32) (i32.const 4096))
+                (data (i32.const 0) "x-filtered")
+                (data (i32.const 16) "yes")
+                (data (i32.const 32) "denied")
+                (data (i32.const 48) "auth")
+                (data (i32.const 64) "{auth_call}")
+                (func (export "proxy_abi_version_0_2_1"))
+                (func (export "proxy_on_memory_allocate") (param $size i32) (result i32)
+                    (global.get $heap)
+                    (global.set $heap (i32.add (global.get $heap) (local.get $size))))
+                (func (export "proxy_on_context_create") (param i32 i32))
+                {callbacks}
+            )"#
+        )
+    }
+
+    async fn send(
+        wat: &str,
+        config: &Config,
+        headers: &[(&str, &str)],
+    ) -> (Response<Body>, Arc<Upstream>) {
+        let upstream = Arc::new(Upstream::default());
+        let tasks = TokioTaskManager::new(tokio::runtime::Handle::current());
+        let mut runtime = PluggableRuntime::new(Arc::new(tasks));
+        runtime
+            .set_engine(Some(wasmer::Engine::default()))
+            .set_http_client(Arc::clone(&upstream));
+        let runtime: Arc<dyn Runtime + Send + Sync> = Arc::new(runtime);
+
+        let module = Module::new(&runtime.engine(), wat).unwrap();
+        let builder = WasiEnvBuilder::new("filter").runtime(Arc::clone(&runtime));
+        let (events, receiver) = mpsc::channel();
+        let mut filter = Filter::new(
+            config,
+            &module,
+            ModuleHash::xxhash_from_bytes([0; 8]),
+            builder,
+            &runtime,
+            events.clone(),
+        )
+        .unwrap();
+        filter.start().unwrap();
+        std::thread::spawn(move || filter.run(receiver));
+
+        let (reply, response) = oneshot::channel();
+        let headers = headers
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        events
+            .send(Event::Request {
+                headers,
+                body: Bytes::new(),
+                reply,
+            })
+            .unwrap();
+
+        (response.await.unwrap().unwrap(), upstream)
+    }
+
+    async fn body(response: Response<Body>) -> String {
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    const GET: &[(&str, &str)] = &[(":method", "GET"), (":path", "/index.html")];
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn modify_headers_on_the_way_through() {
+        let wat = filter_wat(
+            r#"
+            (func (export "proxy_on_request_headers") (param i32 i32 i32) (result i32)
+                (drop (call $add_header (i32.const 0) (i32.const 0) (i32.const 10) (i32.const 16) (i32.const 3)))
+                (i32.const 0))
+            (func (export "proxy_on_response_headers") (param i32 i32 i32) (result i32)
+                (drop (call $add_header (i32.const 2) (i32.const 0) (i32.const 10) (i32.const 16) (i32.const 3)))
+                (i32.const 0))
+            "#,
+        );
+
+        let (response, upstream) = send(&wat, &Config::new(WasiRunner::new()), GET).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-filtered"], "yes");
+        assert_eq!(body(response).await, "hello");
+        let requests = upstream.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].url.as_str(), "http://127.0.0.1:8080/index.html");
+        assert_eq!(requests[0].headers["x-filtered"], "yes");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn answer_with_a_local_response() {
+        let wat = filter_wat(
+            r#"
+            (func (export "proxy_on_request_headers") (param i32 i32 i32) (result i32)
+                (drop (call $local_response
+                    (i32.const 403) (i32.const 0) (i32.const 0) (i32.const 32) (i32.const 6)
+                    (i32.const 0) (i32.const 0) (i32.const -1)))
+                (i32.const 1))
+            "#,
+        );
+
+        let (response, upstream) = send(&wat, &Config::new(WasiRunner::new()), GET).await;
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(body(response).await, "denied");
+        assert!(upstream.requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pause_until_an_http_call_finishes() {
+        let wat = filter_wat(
+            r#"
+            (func (export "proxy_on_request_headers") (param i32 i32 i32) (result i32)
+                (drop (call $http_call
+                    (i32.const 48) (i32.const 4) (i32.const 64) (i32.const 69)
+                    (i32.const 0) (i32.const 0) (i32.const 0) (i32.const 0)
+                    (i32.const 1000) (i32.const 1024)))
+                (i32.const 1))
+            (func (export "proxy_on_http_call_response") (param i32 i32 i32 i32 i32)
+                (drop (call $continue_stream (i32.const 0))))
+            "#,
+        );
+        let mut config = Config::new(WasiRunner::new());
+        config.cluster("auth", "http://auth.internal/".parse().unwrap());
+
+        let (response, upstream) = send(&wat, &config, GET).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let requests = upstream.requests.lock().unwrap();
+        let urls: Vec<_> = requests.iter().map(|r| r.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "http://auth.internal/check",
+                "http://127.0.0.1:8080/index.html"
+            ]
+        );
+        assert_eq!(requests[0].headers[header::HOST], "auth");
+    }
+}
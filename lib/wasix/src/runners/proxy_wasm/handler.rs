@@ -0,0 +1,132 @@
+use std::{
+    pin::Pin,
+    sync::mpsc,
+    task::{Context, Poll},
+};
+
+use futures::{Future, FutureExt};
+use http::{header, Request, Response};
+use http_body_util::BodyExt;
+use tokio::sync::oneshot;
+use tower::Service;
+
+use super::{abi::HeaderPairs, filter::Event};
+use crate::runners::Body;
+
+/// Handler which passes requests to the [`super::filter::Filter`] thread.
+#[derive(Clone, Debug)]
+pub(crate) struct Handler {
+    events: mpsc::Sender<Event>,
+    scheme: &'static str,
+}
+
+impl Handler {
+    pub(crate) fn new(events: mpsc::Sender<Event>, tls: bool) -> Self {
+        Handler {
+            events,
+            scheme: if tls { "https" } else { "http" },
+        }
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    pub(crate) async fn handle(
+        &self,
+        req: Request<hyper::body::Incoming>,
+    ) -> anyhow::Result<Response<Body>> {
+        let (parts, body) = req.into_parts();
+        let body = body.collect().await?.to_bytes();
+        let headers = request_headers(&parts, self.scheme);
+
+        let (reply, response) = oneshot::channel();
+        self.events
+            .send(Event::Request {
+                headers,
+                body,
+                reply,
+            })
+            .map_err(|_| anyhow::anyhow!("The filter is no longer running"))?;
+
+        response
+            .await
+            .map_err(|_| anyhow::anyhow!("The filter dropped the request"))?
+    }
+}
+
+/// The request's headers, with the pseudo-headers Proxy-Wasm filters expect.
+fn request_headers(parts: &http::request::Parts, scheme: &str) -> HeaderPairs {
+    let authority = parts
+        .headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .or_else(|| parts.uri.authority().map(|a| a.as_str()))
+        .unwrap_or_default();
+    let path = parts
+        .uri
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or("/");
+
+    let pseudo_headers = [
+        (":method", parts.method.as_str()),
+        (":path", path),
+        (":authority", authority),
+        (":scheme", scheme),
+    ]
+    .map(|(name, value)| (name.to_string(), value.to_string()));
+
+    let headers = parts
+        .headers
+        .iter()
+        .filter(|(name, _)| *name != header::HOST)
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        });
+
+    pseudo_headers.into_iter().chain(headers).collect()
+}
+
+impl Service<Request<hyper::body::Incoming>> for Handler {
+    type Response = Response<Body>;
+    type Error = anyhow::Error;
+    type Future = Pin<Box<dyn Future<Output = anyhow::Result<Response<Body>>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<hyper::body::Incoming>) -> Self::Future {
+        let handler = self.clone();
+        let fut = async move { handler.handle(request).await };
+        fut.boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_pseudo_headers() {
+        let (parts, _) = Request::post("/upload?name=x")
+            .header(header::HOST, "example.com")
+            .header("x-custom", "value")
+            .body(())
+            .unwrap()
+            .into_parts();
+
+        let headers = request_headers(&parts, "https");
+
+        let expected = [
+            (":method", "POST"),
+            (":path", "/upload?name=x"),
+            (":authority", "example.com"),
+            (":scheme", "https"),
+            ("x-custom", "value"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+        assert_eq!(headers, expected);
+    }
+}
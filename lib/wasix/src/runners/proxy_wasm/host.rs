@@ -0,0 +1,869 @@
+//! The host side of the Proxy-Wasm ABI.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tracing::level_filters::LevelFilter;
+use wasmer::{
+    AsStoreMut, ExternType, Function, FunctionEnv, FunctionEnvMut, Imports, Memory, Module,
+    RuntimeError, Type, TypedFunction, Value,
+};
+
+use super::abi::{
+    deserialize_map, serialize_map, split_path, BufferType, HeaderPairs, MapType, MetricType,
+    Status, StreamType,
+};
+
+/// The headers and bodies of an HTTP stream going through the filter.
+#[derive(Debug, Default)]
+pub(crate) struct StreamData {
+    pub(crate) request_headers: HeaderPairs,
+    pub(crate) request_body: Vec<u8>,
+    pub(crate) request_trailers: HeaderPairs,
+    pub(crate) response_headers: HeaderPairs,
+    pub(crate) response_body: Vec<u8>,
+    pub(crate) response_trailers: HeaderPairs,
+}
+
+/// The response to an HTTP call, available while the guest's
+/// `proxy_on_http_call_response()` callback runs.
+#[derive(Debug, Default)]
+pub(crate) struct HttpCallResponse {
+    pub(crate) headers: HeaderPairs,
+    pub(crate) body: Vec<u8>,
+    pub(crate) trailers: HeaderPairs,
+}
+
+/// An HTTP call dispatched by the guest.
+#[derive(Debug)]
+pub(crate) struct HttpCall {
+    pub(crate) token: u32,
+    pub(crate) context_id: u32,
+    pub(crate) upstream: String,
+    pub(crate) headers: HeaderPairs,
+    pub(crate) body: Vec<u8>,
+    pub(crate) timeout: Duration,
+}
+
+/// A response generated by the guest instead of the upstream.
+#[derive(Debug)]
+pub(crate) struct LocalResponse {
+    pub(crate) status: u16,
+    pub(crate) headers: HeaderPairs,
+    pub(crate) body: Vec<u8>,
+}
+
+/// Something the guest asked the host to do with one of its streams.
+#[derive(Debug)]
+pub(crate) enum StreamAction {
+    Continue(StreamType),
+    Close,
+    LocalResponse(LocalResponse),
+}
+
+#[derive(Debug)]
+struct Metric {
+    name: String,
+    kind: MetricType,
+    value: u64,
+}
+
+/// The state shared between the host functions and the filter driving the
+/// guest.
+///
+/// Requests made through host functions (e.g. resuming a stream or
+/// dispatching an HTTP call) are queued up and acted upon once the guest
+/// callback that made them returns.
+#[derive(derive_more::Debug)]
+pub(crate) struct HostState {
+    #[debug(ignore)]
+    memory: Option<Memory>,
+    #[debug(ignore)]
+    allocate: Option<TypedFunction<i32, i32>>,
+    root_id: String,
+    vm_configuration: Vec<u8>,
+    plugin_configuration: Vec<u8>,
+    /// The context host functions operate on.
+    pub(crate) effective_context: u32,
+    pub(crate) streams: HashMap<u32, StreamData>,
+    pub(crate) http_call_response: Option<HttpCallResponse>,
+    pub(crate) actions: Vec<(u32, StreamAction)>,
+    pub(crate) http_calls: Vec<HttpCall>,
+    pub(crate) tick_period: Option<Duration>,
+    next_token: u32,
+    /// Shared data, along with the CAS value of each entry.
+    shared_data: HashMap<String, (Vec<u8>, u32)>,
+    properties: HashMap<String, Vec<u8>>,
+    metrics: Vec<Metric>,
+}
+
+impl HostState {
+    pub(crate) fn new(
+        root_id: String,
+        vm_configuration: Vec<u8>,
+        plugin_configuration: Vec<u8>,
+    ) -> Self {
+        HostState {
+            memory: None,
+            allocate: None,
+            root_id,
+            vm_configuration,
+            plugin_configuration,
+            effective_context: 0,
+            streams: HashMap::new(),
+            http_call_response: None,
+            actions: Vec::new(),
+            http_calls: Vec::new(),
+            tick_period: None,
+            next_token: 1,
+            shared_data: HashMap::new(),
+            properties: HashMap::new(),
+            metrics: Vec::new(),
+        }
+    }
+
+    /// Give the host functions access to the guest once it is instantiated.
+    pub(crate) fn init(&mut self, memory: Memory, allocate: TypedFunction<i32, i32>) {
+        self.memory = Some(memory);
+        self.allocate = Some(allocate);
+    }
+
+    pub(crate) fn vm_configuration(&self) -> &[u8] {
+        &self.vm_configuration
+    }
+
+    pub(crate) fn plugin_configuration(&self) -> &[u8] {
+        &self.plugin_configuration
+    }
+
+    fn stream(&self) -> Result<&StreamData, Status> {
+        self.streams
+            .get(&self.effective_context)
+            .ok_or(Status::NotFound)
+    }
+
+    fn stream_mut(&mut self) -> Result<&mut StreamData, Status> {
+        self.streams
+            .get_mut(&self.effective_context)
+            .ok_or(Status::NotFound)
+    }
+
+    fn map(&self, map_type: i32) -> Result<&HeaderPairs, Status> {
+        let call_response = || self.http_call_response.as_ref().ok_or(Status::NotFound);
+        match MapType::from_raw(map_type).ok_or(Status::BadArgument)? {
+            MapType::RequestHeaders => Ok(&self.stream()?.request_headers),
+            MapType::RequestTrailers => Ok(&self.stream()?.request_trailers),
+            MapType::ResponseHeaders => Ok(&self.stream()?.response_headers),
+            MapType::ResponseTrailers => Ok(&self.stream()?.response_trailers),
+            MapType::HttpCallResponseHeaders => Ok(&call_response()?.headers),
+            MapType::HttpCallResponseTrailers => Ok(&call_response()?.trailers),
+        }
+    }
+
+    fn map_mut(&mut self, map_type: i32) -> Result<&mut HeaderPairs, Status> {
+        match MapType::from_raw(map_type).ok_or(Status::BadArgument)? {
+            MapType::RequestHeaders => Ok(&mut self.stream_mut()?.request_headers),
+            MapType::RequestTrailers => Ok(&mut self.stream_mut()?.request_trailers),
+            MapType::ResponseHeaders => Ok(&mut self.stream_mut()?.response_headers),
+            MapType::ResponseTrailers => Ok(&mut self.stream_mut()?.response_trailers),
+            // The response to an HTTP call is read-only
+            MapType::HttpCallResponseHeaders | MapType::HttpCallResponseTrailers => {
+                Err(Status::BadArgument)
+            }
+        }
+    }
+
+    fn buffer(&self, buffer_type: i32) -> Result<&[u8], Status> {
+        match BufferType::from_raw(buffer_type).ok_or(Status::BadArgument)? {
+            BufferType::RequestBody => Ok(&self.stream()?.request_body),
+            BufferType::ResponseBody => Ok(&self.stream()?.response_body),
+            BufferType::HttpCallResponseBody => self
+                .http_call_response
+                .as_ref()
+                .map(|response| response.body.as_slice())
+                .ok_or(Status::NotFound),
+            BufferType::VmConfiguration => Ok(&self.vm_configuration),
+            BufferType::PluginConfiguration => Ok(&self.plugin_configuration),
+        }
+    }
+
+    fn buffer_mut(&mut self, buffer_type: i32) -> Result<&mut Vec<u8>, Status> {
+        match BufferType::from_raw(buffer_type).ok_or(Status::BadArgument)? {
+            BufferType::RequestBody => Ok(&mut self.stream_mut()?.request_body),
+            BufferType::ResponseBody => Ok(&mut self.stream_mut()?.response_body),
+            _ => Err(Status::BadArgument),
+        }
+    }
+
+    fn property(&self, path: &[String]) -> Option<Vec<u8>> {
+        let key = path.join(".");
+        if let Some(value) = self.properties.get(&key) {
+            return Some(value.clone());
+        }
+
+        let stream = self.stream().ok();
+        let request_header = |name: &str| {
+            stream
+                .and_then(|s| header_value(&s.request_headers, name))
+                .map(String::into_bytes)
+        };
+
+        match key.as_str() {
+            "plugin_root_id" => Some(self.root_id.clone().into_bytes()),
+            "request.path" => request_header(":path"),
+            "request.url_path" => request_header(":path").map(|path| {
+                let end = path.iter().position(|b| *b == b'?').unwrap_or(path.len());
+                path[..end].to_vec()
+            }),
+            "request.host" => request_header(":authority"),
+            "request.method" => request_header(":method"),
+            "request.scheme" => request_header(":scheme"),
+            "response.code" => stream
+                .and_then(|s| header_value(&s.response_headers, ":status"))
+                .map(String::into_bytes),
+            _ => None,
+        }
+    }
+}
+
+/// The value of a header, with repeated headers joined by commas.
+pub(crate) fn header_value(pairs: &HeaderPairs, name: &str) -> Option<String> {
+    let values: Vec<&str> = pairs
+        .iter()
+        .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+        .collect();
+
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.join(","))
+    }
+}
+
+/// Create the host functions a Proxy-Wasm module imports.
+///
+/// Host functions we don't implement (e.g. gRPC calls and shared queues)
+/// are stubbed out so modules which import them can still be instantiated,
+/// and return [`Status::Unimplemented`] when called.
+pub(crate) fn imports(
+    module: &Module,
+    store: &mut impl AsStoreMut,
+    env: &FunctionEnv<HostState>,
+) -> Imports {
+    macro_rules! host_functions {
+        ($($name:ident),* $(,)?) => {
+            [$(
+                (stringify!($name), Function::new_typed_with_env(store, env, $name)),
+            )*]
+        };
+    }
+
+    let functions = host_functions![
+        proxy_log,
+        proxy_get_log_level,
+        proxy_get_current_time_nanoseconds,
+        proxy_set_tick_period_milliseconds,
+        proxy_get_buffer_bytes,
+        proxy_get_buffer_status,
+        proxy_set_buffer_bytes,
+        proxy_get_header_map_pairs,
+        proxy_set_header_map_pairs,
+        proxy_get_header_map_size,
+        proxy_get_header_map_value,
+        proxy_add_header_map_value,
+        proxy_replace_header_map_value,
+        proxy_remove_header_map_value,
+        proxy_get_property,
+        proxy_set_property,
+        proxy_get_shared_data,
+        proxy_set_shared_data,
+        proxy_continue_stream,
+        proxy_close_stream,
+        proxy_send_local_response,
+        proxy_http_call,
+        proxy_set_effective_context,
+        proxy_done,
+        proxy_define_metric,
+        proxy_increment_metric,
+        proxy_record_metric,
+        proxy_get_metric,
+    ];
+
+    let mut imports = Imports::new();
+    for (name, function) in functions {
+        imports.define("env", name, function);
+    }
+
+    for import in module.imports() {
+        let name = import.name();
+        let ExternType::Function(ty) = import.ty() else {
+            continue;
+        };
+        if import.module() != "env" || !name.starts_with("proxy_") || imports.exists("env", name) {
+            continue;
+        }
+
+        let stub_name = name.to_string();
+        let results = ty.results().to_vec();
+        let stub = Function::new_with_env(store, env, ty.clone(), move |_env, _args| {
+            tracing::warn!(function = %stub_name, "Called an unimplemented Proxy-Wasm host function");
+            results
+                .iter()
+                .map(|ty| match ty {
+                    Type::I32 => Ok(Value::I32(Status::Unimplemented as i32)),
+                    Type::I64 => Ok(Value::I64(0)),
+                    Type::F32 => Ok(Value::F32(0.0)),
+                    Type::F64 => Ok(Value::F64(0.0)),
+                    other => Err(RuntimeError::new(format!(
+                        "Unable to return a {other} from \"{stub_name}\""
+                    ))),
+                })
+                .collect()
+        });
+        imports.define("env", name, stub);
+    }
+
+    imports
+}
+
+/// Run a host function, translating its result into a status code.
+fn status(f: impl FnOnce() -> Result<(), Status>) -> i32 {
+    match f() {
+        Ok(()) => Status::Ok as i32,
+        Err(status) => status as i32,
+    }
+}
+
+fn read(env: &FunctionEnvMut<HostState>, ptr: i32, len: i32) -> Result<Vec<u8>, Status> {
+    let memory = env.data().memory.as_ref().ok_or(Status::InternalFailure)?;
+    let view = memory.view(env);
+    let (ptr, len) = (ptr as u32 as u64, len as u32 as u64);
+    if ptr + len > view.data_size() {
+        return Err(Status::InvalidMemoryAccess);
+    }
+
+    let mut buffer = vec![0; len as usize];
+    view.read(ptr, &mut buffer)
+        .map_err(|_| Status::InvalidMemoryAccess)?;
+    Ok(buffer)
+}
+
+fn read_string(env: &FunctionEnvMut<HostState>, ptr: i32, len: i32) -> Result<String, Status> {
+    let bytes = read(env, ptr, len)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn write(env: &FunctionEnvMut<HostState>, ptr: i32, data: &[u8]) -> Result<(), Status> {
+    let memory = env.data().memory.as_ref().ok_or(Status::InternalFailure)?;
+    memory
+        .view(env)
+        .write(ptr as u32 as u64, data)
+        .map_err(|_| Status::InvalidMemoryAccess)
+}
+
+fn write_u32(env: &FunctionEnvMut<HostState>, ptr: i32, value: u32) -> Result<(), Status> {
+    write(env, ptr, &value.to_le_bytes())
+}
+
+fn write_u64(env: &FunctionEnvMut<HostState>, ptr: i32, value: u64) -> Result<(), Status> {
+    write(env, ptr, &value.to_le_bytes())
+}
+
+/// Copy `data` into memory allocated by the guest, and tell it where to find
+/// the copy.
+fn return_bytes(
+    env: &mut FunctionEnvMut<HostState>,
+    data: &[u8],
+    return_ptr: i32,
+    return_size: i32,
+) -> Result<(), Status> {
+    let ptr = if data.is_empty() {
+        0
+    } else {
+        let allocate = env.data().allocate.clone().ok_or(Status::InternalFailure)?;
+        let ptr = allocate
+            .call(env, data.len() as i32)
+            .map_err(|_| Status::InternalFailure)?;
+        if ptr == 0 {
+            return Err(Status::InternalFailure);
+        }
+        write(env, ptr, data)?;
+        ptr
+    };
+
+    write_u32(env, return_ptr, ptr as u32)?;
+    write_u32(env, return_size, data.len() as u32)
+}
+
+fn proxy_log(env: FunctionEnvMut<HostState>, level: i32, ptr: i32, size: i32) -> i32 {
+    status(|| {
+        let message = read_string(&env, ptr, size)?;
+        match level {
+            0 => tracing::trace!(target: "proxy_wasm", "{message}"),
+            1 => tracing::debug!(target: "proxy_wasm", "{message}"),
+            2 => tracing::info!(target: "proxy_wasm", "{message}"),
+            3 => tracing::warn!(target: "proxy_wasm", "{message}"),
+            4 | 5 => tracing::error!(target: "proxy_wasm", "{message}"),
+            _ => return Err(Status::BadArgument),
+        }
+        Ok(())
+    })
+}
+
+fn proxy_get_log_level(env: FunctionEnvMut<HostState>, return_level: i32) -> i32 {
+    status(|| {
+        let level = match LevelFilter::current() {
+            LevelFilter::TRACE => 0,
+            LevelFilter::DEBUG => 1,
+            LevelFilter::INFO => 2,
+            LevelFilter::WARN => 3,
+            LevelFilter::ERROR => 4,
+            LevelFilter::OFF => 5,
+        };
+        write_u32(&env, return_level, level)
+    })
+}
+
+fn proxy_get_current_time_nanoseconds(env: FunctionEnvMut<HostState>, return_time: i32) -> i32 {
+    status(|| {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        write_u64(&env, return_time, now.as_nanos() as u64)
+    })
+}
+
+fn proxy_set_tick_period_milliseconds(mut env: FunctionEnvMut<HostState>, period: i32) -> i32 {
+    let period = period as u32;
+    env.data_mut().tick_period = if period == 0 {
+        None
+    } else {
+        Some(Duration::from_millis(period.into()))
+    };
+    Status::Ok as i32
+}
+
+fn proxy_get_buffer_bytes(
+    mut env: FunctionEnvMut<HostState>,
+    buffer_type: i32,
+    start: i32,
+    max_size: i32,
+    return_ptr: i32,
+    return_size: i32,
+) -> i32 {
+    status(|| {
+        let buffer = env.data().buffer(buffer_type)?;
+        let start = start as u32 as usize;
+        if start > buffer.len() {
+            return Err(Status::BadArgument);
+        }
+        let end = buffer
+            .len()
+            .min(start.saturating_add(max_size as u32 as usize));
+        let data = buffer[start..end].to_vec();
+        return_bytes(&mut env, &data, return_ptr, return_size)
+    })
+}
+
+fn proxy_get_buffer_status(
+    env: FunctionEnvMut<HostState>,
+    buffer_type: i32,
+    return_length: i32,
+    return_flags: i32,
+) -> i32 {
+    status(|| {
+        let length = env.data().buffer(buffer_type)?.len();
+        write_u32(&env, return_length, length as u32)?;
+        write_u32(&env, return_flags, 0)
+    })
+}
+
+/// Replace `size` bytes starting at `start` with the guest's data, which
+/// also covers prepending (`start` and `size` are zero) and appending
+/// (`start` is the buffer's length).
+fn proxy_set_buffer_bytes(
+    mut env: FunctionEnvMut<HostState>,
+    buffer_type: i32,
+    start: i32,
+    size: i32,
+    data_ptr: i32,
+    data_size: i32,
+) -> i32 {
+    status(|| {
+        let data = read(&env, data_ptr, data_size)?;
+        let buffer = env.data_mut().buffer_mut(buffer_type)?;
+        let start = (start as u32 as usize).min(buffer.len());
+        let end = start.saturating_add(size as u32 as usize).min(buffer.len());
+        buffer.splice(start..end, data);
+        Ok(())
+    })
+}
+
+fn proxy_get_header_map_pairs(
+    mut env: FunctionEnvMut<HostState>,
+    map_type: i32,
+    return_ptr: i32,
+    return_size: i32,
+) -> i32 {
+    status(|| {
+        let map = serialize_map(env.data().map(map_type)?);
+        return_bytes(&mut env, &map, return_ptr, return_size)
+    })
+}
+
+fn proxy_set_header_map_pairs(
+    mut env: FunctionEnvMut<HostState>,
+    map_type: i32,
+    ptr: i32,
+    size: i32,
+) -> i32 {
+    status(|| {
+        let pairs = deserialize_map(&read(&env, ptr, size)?)?;
+        *env.data_mut().map_mut(map_type)? = pairs;
+        Ok(())
+    })
+}
+
+fn proxy_get_header_map_size(
+    env: FunctionEnvMut<HostState>,
+    map_type: i32,
+    return_size: i32,
+) -> i32 {
+    status(|| {
+        let size = serialize_map(env.data().map(map_type)?).len();
+        write_u32(&env, return_size, size as u32)
+    })
+}
+
+fn proxy_get_header_map_value(
+    mut env: FunctionEnvMut<HostState>,
+    map_type: i32,
+    key_ptr: i32,
+    key_size: i32,
+    return_ptr: i32,
+    return_size: i32,
+) -> i32 {
+    status(|| {
+        let key = read_string(&env, key_ptr, key_size)?;
+        let value = header_value(env.data().map(map_type)?, &key).ok_or(Status::NotFound)?;
+        return_bytes(&mut env, value.as_bytes(), return_ptr, return_size)
+    })
+}
+
+fn proxy_add_header_map_value(
+    mut env: FunctionEnvMut<HostState>,
+    map_type: i32,
+    key_ptr: i32,
+    key_size: i32,
+    value_ptr: i32,
+    value_size: i32,
+) -> i32 {
+    status(|| {
+        let key = read_string(&env, key_ptr, key_size)?.to_ascii_lowercase();
+        let value = read_string(&env, value_ptr, value_size)?;
+        env.data_mut().map_mut(map_type)?.push((key, value));
+        Ok(())
+    })
+}
+
+fn proxy_replace_header_map_value(
+    mut env: FunctionEnvMut<HostState>,
+    map_type: i32,
+    key_ptr: i32,
+    key_size: i32,
+    value_ptr: i32,
+    value_size: i32,
+) -> i32 {
+    status(|| {
+        let key = read_string(&env, key_ptr, key_size)?.to_ascii_lowercase();
+        let value = read_string(&env, value_ptr, value_size)?;
+        let map = env.data_mut().map_mut(map_type)?;
+        // Keep the header where it was, dropping any repeated values
+        let position = map.iter().position(|(k, _)| k.eq_ignore_ascii_case(&key));
+        map.retain(|(k, _)| !k.eq_ignore_ascii_case(&key));
+        match position {
+            Some(index) => map.insert(index, (key, value)),
+            None => map.push((key, value)),
+        }
+        Ok(())
+    })
+}
+
+fn proxy_remove_header_map_value(
+    mut env: FunctionEnvMut<HostState>,
+    map_type: i32,
+    key_ptr: i32,
+    key_size: i32,
+) -> i32 {
+    status(|| {
+        let key = read_string(&env, key_ptr, key_size)?;
+        env.data_mut()
+            .map_mut(map_type)?
+            .retain(|(k, _)| !k.eq_ignore_ascii_case(&key));
+        Ok(())
+    })
+}
+
+fn proxy_get_property(
+    mut env: FunctionEnvMut<HostState>,
+    path_ptr: i32,
+    path_size: i32,
+    return_ptr: i32,
+    return_size: i32,
+) -> i32 {
+    status(|| {
+        let path = split_path(&read(&env, path_ptr, path_size)?);
+        let value = env.data().property(&path).ok_or(Status::NotFound)?;
+        return_bytes(&mut env, &value, return_ptr, return_size)
+    })
+}
+
+fn proxy_set_property(
+    mut env: FunctionEnvMut<HostState>,
+    path_ptr: i32,
+    path_size: i32,
+    value_ptr: i32,
+    value_size: i32,
+) -> i32 {
+    status(|| {
+        let path = split_path(&read(&env, path_ptr, path_size)?);
+        let value = read(&env, value_ptr, value_size)?;
+        env.data_mut().properties.insert(path.join("."), value);
+        Ok(())
+    })
+}
+
+fn proxy_get_shared_data(
+    mut env: FunctionEnvMut<HostState>,
+    key_ptr: i32,
+    key_size: i32,
+    return_ptr: i32,
+    return_size: i32,
+    return_cas: i32,
+) -> i32 {
+    status(|| {
+        let key = read_string(&env, key_ptr, key_size)?;
+        let (value, cas) = env
+            .data()
+            .shared_data
+            .get(&key)
+            .cloned()
+            .ok_or(Status::NotFound)?;
+        return_bytes(&mut env, &value, return_ptr, return_size)?;
+        write_u32(&env, return_cas, cas)
+    })
+}
+
+/// Store a value, which fails with [`Status::CasMismatch`] if `cas` is
+/// non-zero and the value was updated since the guest last read it.
+fn proxy_set_shared_data(
+    mut env: FunctionEnvMut<HostState>,
+    key_ptr: i32,
+    key_size: i32,
+    value_ptr: i32,
+    value_size: i32,
+    cas: i32,
+) -> i32 {
+    status(|| {
+        let key = read_string(&env, key_ptr, key_size)?;
+        let value = read(&env, value_ptr, value_size)?;
+        let entry = env
+            .data_mut()
+            .shared_data
+            .entry(key)
+            .or_insert_with(|| (Vec::new(), 0));
+        if cas != 0 && cas as u32 != entry.1 {
+            return Err(Status::CasMismatch);
+        }
+        *entry = (value, entry.1.wrapping_add(1).max(1));
+        Ok(())
+    })
+}
+
+fn proxy_continue_stream(mut env: FunctionEnvMut<HostState>, stream_type: i32) -> i32 {
+    status(|| {
+        let stream_type = StreamType::from_raw(stream_type).ok_or(Status::BadArgument)?;
+        let state = env.data_mut();
+        state
+            .actions
+            .push((state.effective_context, StreamAction::Continue(stream_type)));
+        Ok(())
+    })
+}
+
+fn proxy_close_stream(mut env: FunctionEnvMut<HostState>, stream_type: i32) -> i32 {
+    status(|| {
+        StreamType::from_raw(stream_type).ok_or(Status::BadArgument)?;
+        let state = env.data_mut();
+        state
+            .actions
+            .push((state.effective_context, StreamAction::Close));
+        Ok(())
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn proxy_send_local_response(
+    mut env: FunctionEnvMut<HostState>,
+    status_code: i32,
+    _details_ptr: i32,
+    _details_size: i32,
+    body_ptr: i32,
+    body_size: i32,
+    headers_ptr: i32,
+    headers_size: i32,
+    _grpc_status: i32,
+) -> i32 {
+    status(|| {
+        let status = u16::try_from(status_code)
+            .ok()
+            .filter(|code| (100..1000).contains(code))
+            .ok_or(Status::BadArgument)?;
+        let body = read(&env, body_ptr, body_size)?;
+        let headers = deserialize_map(&read(&env, headers_ptr, headers_size)?)?;
+
+        let state = env.data_mut();
+        let response = LocalResponse {
+            status,
+            headers,
+            body,
+        };
+        state.actions.push((
+            state.effective_context,
+            StreamAction::LocalResponse(response),
+        ));
+        Ok(())
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn proxy_http_call(
+    mut env: FunctionEnvMut<HostState>,
+    upstream_ptr: i32,
+    upstream_size: i32,
+    headers_ptr: i32,
+    headers_size: i32,
+    body_ptr: i32,
+    body_size: i32,
+    _trailers_ptr: i32,
+    _trailers_size: i32,
+    timeout_ms: i32,
+    return_token: i32,
+) -> i32 {
+    status(|| {
+        let upstream = read_string(&env, upstream_ptr, upstream_size)?;
+        let headers = deserialize_map(&read(&env, headers_ptr, headers_size)?)?;
+        let body = read(&env, body_ptr, body_size)?;
+        // Like Envoy, we need to know where the request is going
+        for required in [":method", ":path", ":authority"] {
+            if header_value(&headers, required).is_none() {
+                return Err(Status::BadArgument);
+            }
+        }
+
+        let state = env.data_mut();
+        let token = state.next_token;
+        state.next_token = state.next_token.wrapping_add(1).max(1);
+        state.http_calls.push(HttpCall {
+            token,
+            context_id: state.effective_context,
+            upstream,
+            headers,
+            body,
+            timeout: Duration::from_millis((timeout_ms as u32).into()),
+        });
+
+        write_u32(&env, return_token, token)
+    })
+}
+
+fn proxy_set_effective_context(mut env: FunctionEnvMut<HostState>, context_id: i32) -> i32 {
+    status(|| {
+        let state = env.data_mut();
+        let context_id = context_id as u32;
+        if context_id != super::filter::ROOT_CONTEXT_ID && !state.streams.contains_key(&context_id)
+        {
+            return Err(Status::BadArgument);
+        }
+        state.effective_context = context_id;
+        Ok(())
+    })
+}
+
+fn proxy_done(_env: FunctionEnvMut<HostState>) -> i32 {
+    Status::Ok as i32
+}
+
+fn proxy_define_metric(
+    mut env: FunctionEnvMut<HostState>,
+    metric_type: i32,
+    name_ptr: i32,
+    name_size: i32,
+    return_id: i32,
+) -> i32 {
+    status(|| {
+        let kind = MetricType::from_raw(metric_type).ok_or(Status::BadArgument)?;
+        let name = read_string(&env, name_ptr, name_size)?;
+        let metrics = &mut env.data_mut().metrics;
+        let id = match metrics.iter().position(|m| m.name == name) {
+            Some(id) => id,
+            None => {
+                metrics.push(Metric {
+                    name,
+                    kind,
+                    value: 0,
+                });
+                metrics.len() - 1
+            }
+        };
+        write_u32(&env, return_id, id as u32)
+    })
+}
+
+fn proxy_increment_metric(mut env: FunctionEnvMut<HostState>, id: i32, offset: i64) -> i32 {
+    status(|| {
+        let metric = env
+            .data_mut()
+            .metrics
+            .get_mut(id as u32 as usize)
+            .ok_or(Status::NotFound)?;
+        match metric.kind {
+            MetricType::Histogram => return Err(Status::BadArgument),
+            MetricType::Counter if offset < 0 => return Err(Status::BadArgument),
+            _ => {}
+        }
+        metric.value = metric.value.saturating_add_signed(offset);
+        tracing::trace!(name = %metric.name, value = metric.value, "Metric updated");
+        Ok(())
+    })
+}
+
+fn proxy_record_metric(mut env: FunctionEnvMut<HostState>, id: i32, value: i64) -> i32 {
+    status(|| {
+        let metric = env
+            .data_mut()
+            .metrics
+            .get_mut(id as u32 as usize)
+            .ok_or(Status::NotFound)?;
+        metric.value = value as u64;
+        tracing::trace!(name = %metric.name, value = metric.value, "Metric recorded");
+        Ok(())
+    })
+}
+
+fn proxy_get_metric(env: FunctionEnvMut<HostState>, id: i32, return_value: i32) -> i32 {
+    status(|| {
+        let metric = env
+            .data()
+            .metrics
+            .get(id as u32 as usize)
+            .ok_or(Status::NotFound)?;
+        if metric.kind == MetricType::Histogram {
+            return Err(Status::BadArgument);
+        }
+        write_u64(&env, return_value, metric.value)
+    })
+}
@@ -0,0 +1,19 @@
+//! A runner for [Proxy-Wasm][spec] filters, which sits in front of an
+//! upstream server so filters can be tested without Envoy.
+//!
+//! [spec]: https://github.com/proxy-wasm/spec
+
+mod abi;
+mod filter;
+mod handler;
+mod host;
+mod runner;
+
+use wasmer::Module;
+
+pub use self::runner::{Config, ProxyWasmRunner, PROXY_WASM_RUNNER_URI};
+
+/// Check whether a module was built against the Proxy-Wasm ABI.
+pub fn is_proxy_wasm_module(module: &Module) -> bool {
+    abi::abi_version(module).is_some()
+}
@@ -0,0 +1,279 @@
+use std::{collections::HashMap, net::SocketAddr, sync::mpsc, sync::Arc};
+
+use anyhow::{Context, Error};
+use http::Request;
+use tokio::sync::oneshot;
+use tower::ServiceBuilder;
+use tower_http::{catch_panic::CatchPanicLayer, trace::TraceLayer};
+use url::Url;
+use wasmer::Module;
+use wasmer_types::ModuleHash;
+use webc::metadata::{annotations::Wasi, Command};
+
+use super::{filter::Filter, handler::Handler};
+use crate::{
+    bin_factory::BinaryPackage,
+    runners::{
+        server::{serve, MetricsLayer},
+        wasi::WasiRunner,
+        TlsConfig,
+    },
+    runtime::{metrics::Metrics, task_manager::VirtualTaskManagerExt},
+    Runtime, WasiEnvBuilder,
+};
+
+/// The base URI used by a Proxy-Wasm runner.
+pub const PROXY_WASM_RUNNER_URI: &str = "https://webc.org/runner/proxy-wasm";
+
+/// A runner which serves HTTP requests by passing them through a
+/// [Proxy-Wasm][spec] filter on their way to and from an upstream server,
+/// the same way Envoy would.
+///
+/// The filter sees each request and response in two steps, their headers
+/// followed by their whole body.
+///
+/// [spec]: https://github.com/proxy-wasm/spec
+#[derive(Debug)]
+pub struct ProxyWasmRunner {
+    config: Config,
+}
+
+impl ProxyWasmRunner {
+    pub fn new(inner: WasiRunner) -> Self {
+        ProxyWasmRunner {
+            config: Config::new(inner),
+        }
+    }
+
+    pub fn config(&mut self) -> &mut Config {
+        &mut self.config
+    }
+
+    /// Serve requests using a filter which isn't part of a package.
+    pub fn run_module(
+        &mut self,
+        program_name: &str,
+        module: &Module,
+        module_hash: ModuleHash,
+        runtime: Arc<dyn Runtime + Send + Sync>,
+    ) -> Result<(), Error> {
+        let wasi = Wasi::new(program_name);
+        let builder = self
+            .config
+            .inner
+            .prepare_webc_env(program_name, &wasi, None, Arc::clone(&runtime), None)
+            .context("Unable to prepare the WASI environment")?;
+
+        self.run(module.clone(), module_hash, builder, runtime)
+    }
+
+    fn run(
+        &mut self,
+        module: Module,
+        module_hash: ModuleHash,
+        builder: WasiEnvBuilder,
+        runtime: Arc<dyn Runtime + Send + Sync>,
+    ) -> Result<(), Error> {
+        let (events, receiver) = mpsc::channel();
+        let (ready_sender, ready) = oneshot::channel();
+
+        // The guest is driven from its own thread because calling into it
+        // blocks
+        let config = self.config.clone();
+        let rt = Arc::clone(&runtime);
+        let filter_events = events.clone();
+        runtime.task_manager().task_dedicated(Box::new(move || {
+            let filter = Filter::new(&config, &module, module_hash, builder, &rt, filter_events)
+                .and_then(|mut filter| filter.start().map(|_| filter));
+            match filter {
+                Ok(filter) => {
+                    let _ = ready_sender.send(Ok(()));
+                    filter.run(receiver);
+                }
+                Err(e) => {
+                    let _ = ready_sender.send(Err(e));
+                }
+            }
+        }))?;
+
+        let handler = Handler::new(events, self.config.tls.is_some());
+        let service = ServiceBuilder::new()
+            .layer(MetricsLayer::new(self.config.metrics.clone(), "proxy-wasm"))
+            .layer(
+                TraceLayer::new_for_http()
+                    .make_span_with(|request: &Request<hyper::body::Incoming>| {
+                        tracing::info_span!(
+                            "request",
+                            method = %request.method(),
+                            uri = %request.uri(),
+                            status_code = tracing::field::Empty,
+                        )
+                    })
+                    .on_response(super::super::response_tracing::OnResponseTracer),
+            )
+            .layer(CatchPanicLayer::new())
+            .service(handler);
+
+        let address = self.config.addr;
+        let upstream = self.config.upstream.clone();
+        let http2 = self.config.http2;
+        let tls = self.config.tls.clone();
+        runtime
+            .task_manager()
+            .spawn_and_block_on(async move {
+                ready
+                    .await
+                    .context("The filter exited unexpectedly")?
+                    .context("Unable to start the filter")?;
+
+                tracing::info!(%address, %upstream, "Starting the Proxy-Wasm server");
+                let listener = tokio::net::TcpListener::bind(&address).await?;
+                let service = hyper_util::service::TowerToHyperService::new(service);
+                serve(
+                    listener,
+                    service,
+                    http2,
+                    tls.as_ref(),
+                    futures::future::pending(),
+                )
+                .await
+            })
+            .context("Unable to start the server")??;
+
+        Ok(())
+    }
+}
+
+impl crate::runners::Runner for ProxyWasmRunner {
+    fn can_run_command(command: &Command) -> Result<bool, Error> {
+        Ok(command.runner.starts_with(PROXY_WASM_RUNNER_URI))
+    }
+
+    fn run_command(
+        &mut self,
+        command_name: &str,
+        pkg: &BinaryPackage,
+        runtime: Arc<dyn Runtime + Send + Sync>,
+    ) -> Result<(), Error> {
+        let cmd = pkg
+            .get_command(command_name)
+            .with_context(|| format!("The package doesn't contain a \"{command_name}\" command"))?;
+        let wasi = cmd
+            .metadata()
+            .annotation("wasi")?
+            .unwrap_or_else(|| Wasi::new(command_name));
+        let module = runtime.load_module_sync(&cmd.atom())?;
+
+        let builder = self
+            .config
+            .inner
+            .prepare_webc_env(command_name, &wasi, Some(pkg), Arc::clone(&runtime), None)
+            .context("Unable to prepare the WASI environment")?;
+
+        self.run(module, pkg.hash(), builder, runtime)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub(crate) inner: WasiRunner,
+    pub(crate) addr: SocketAddr,
+    pub(crate) http2: bool,
+    pub(crate) tls: Option<TlsConfig>,
+    pub(crate) metrics: Option<Arc<Metrics>>,
+    pub(crate) upstream: Url,
+    pub(crate) clusters: HashMap<String, Url>,
+    pub(crate) root_id: String,
+    pub(crate) vm_configuration: Vec<u8>,
+    pub(crate) plugin_configuration: Vec<u8>,
+}
+
+impl Config {
+    pub fn new(inner: WasiRunner) -> Self {
+        Self {
+            inner,
+            addr: ([127, 0, 0, 1], 8000).into(),
+            http2: false,
+            tls: None,
+            metrics: None,
+            upstream: "http://127.0.0.1:8080/".parse().unwrap(),
+            clusters: HashMap::new(),
+            root_id: String::new(),
+            vm_configuration: Vec::new(),
+            plugin_configuration: Vec::new(),
+        }
+    }
+
+    pub fn addr(&mut self, addr: SocketAddr) -> &mut Self {
+        self.addr = addr;
+        self
+    }
+
+    /// Serve HTTP/2 alongside HTTP/1, negotiated through ALPN when TLS is
+    /// enabled and with prior knowledge (h2c) otherwise.
+    pub fn http2(&mut self, enabled: bool) -> &mut Self {
+        self.http2 = enabled;
+        self
+    }
+
+    /// Terminate TLS using the given certificate and private key.
+    pub fn tls(&mut self, tls: TlsConfig) -> &mut Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Record request metrics in a [`Metrics`] registry.
+    pub fn metrics(&mut self, metrics: Arc<Metrics>) -> &mut Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// The server requests are forwarded to once the filter lets them
+    /// through.
+    pub fn upstream(&mut self, upstream: Url) -> &mut Self {
+        self.upstream = upstream;
+        self
+    }
+
+    /// Send the HTTP calls a filter makes to the `name` cluster to `url`.
+    ///
+    /// Calls to unknown clusters are sent to `http://<name>/`, so a cluster
+    /// can also be named after the host and port it lives on.
+    pub fn cluster(&mut self, name: impl Into<String>, url: Url) -> &mut Self {
+        self.clusters.insert(name.into(), url);
+        self
+    }
+
+    /// The root ID, which filters can use to tell their configurations
+    /// apart.
+    pub fn root_id(&mut self, root_id: impl Into<String>) -> &mut Self {
+        self.root_id = root_id.into();
+        self
+    }
+
+    /// The configuration passed to the filter's `proxy_on_vm_start()`.
+    pub fn vm_configuration(&mut self, configuration: impl Into<Vec<u8>>) -> &mut Self {
+        self.vm_configuration = configuration.into();
+        self
+    }
+
+    /// The configuration passed to the filter's `proxy_on_configure()`.
+    pub fn plugin_configuration(&mut self, configuration: impl Into<Vec<u8>>) -> &mut Self {
+        self.plugin_configuration = configuration.into();
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn send_and_sync() {
+        fn assert_send<T: Send>() {}
+        fn assert_sync<T: Sync>() {}
+
+        assert_send::<ProxyWasmRunner>();
+        assert_sync::<ProxyWasmRunner>();
+    }
+}
@@ -0,0 +1,475 @@
+//! The HTTP server shared by the WCGI and DProxy runners.
+
+use std::{
+    error::Error as StdError,
+    future::Future,
+    io::BufReader,
+    path::PathBuf,
+    sync::Arc,
+    task::{Context as TaskContext, Poll},
+    time::Instant,
+};
+
+use anyhow::{Context, Error};
+use futures::{future::BoxFuture, stream::FuturesUnordered, FutureExt, StreamExt};
+use http::{Request, Response};
+use hyper::body::{Body, Incoming};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto::Builder,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+};
+use tokio_rustls::{
+    rustls::{
+        crypto::ring::default_provider,
+        pki_types::{CertificateDer, PrivateKeyDer},
+        ServerConfig,
+    },
+    TlsAcceptor,
+};
+
+use crate::runtime::metrics::Metrics;
+
+/// The certificate and private key used to terminate TLS connections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsConfig {
+    /// A PEM file containing the certificate chain, leaf certificate first.
+    pub cert: PathBuf,
+    /// A PEM file containing the certificate's private key.
+    pub key: PathBuf,
+}
+
+impl TlsConfig {
+    pub fn new(cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        TlsConfig {
+            cert: cert.into(),
+            key: key.into(),
+        }
+    }
+
+    /// Load the certificate and key, advertising the HTTP versions that will
+    /// be served through ALPN.
+    fn acceptor(&self, http2: bool) -> Result<TlsAcceptor, Error> {
+        let cert = std::fs::File::open(&self.cert)
+            .with_context(|| format!("Unable to open \"{}\"", self.cert.display()))?;
+        let certs = rustls_pemfile::certs(&mut BufReader::new(cert))
+            .collect::<Result<Vec<CertificateDer<'static>>, _>>()
+            .with_context(|| format!("Unable to parse \"{}\"", self.cert.display()))?;
+        anyhow::ensure!(
+            !certs.is_empty(),
+            "\"{}\" doesn't contain any certificates",
+            self.cert.display()
+        );
+
+        let key = std::fs::File::open(&self.key)
+            .with_context(|| format!("Unable to open \"{}\"", self.key.display()))?;
+        let key: PrivateKeyDer<'static> = rustls_pemfile::private_key(&mut BufReader::new(key))
+            .with_context(|| format!("Unable to parse \"{}\"", self.key.display()))?
+            .with_context(|| format!("\"{}\" doesn't contain a private key", self.key.display()))?;
+
+        let mut config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .context("Invalid TLS certificate or key")?;
+        if http2 {
+            config.alpn_protocols.push(b"h2".to_vec());
+        }
+        config.alpn_protocols.push(b"http/1.1".to_vec());
+
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+/// Serve HTTP requests from `listener` until `shutdown` resolves.
+///
+/// HTTP/1 is always served. When `http2` is set, HTTP/2 is served as well,
+/// either negotiated through ALPN or, on plain text connections, with prior
+/// knowledge (h2c).
+///
+/// HTTP/1 connections can be upgraded (e.g. to a WebSocket) by a service
+/// which answers with `101 Switching Protocols`.
+pub(crate) async fn serve<S, B>(
+    listener: TcpListener,
+    service: S,
+    http2: bool,
+    tls: Option<&TlsConfig>,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), Error>
+where
+    S: hyper::service::Service<Request<Incoming>, Response = Response<B>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    let acceptor = tls
+        .map(|tls| tls.acceptor(http2))
+        .transpose()
+        .context("Unable to set up TLS")?;
+
+    // Only needed when sniffing which version of HTTP a client speaks
+    let builder = http2.then(|| Arc::new(Builder::new(TokioExecutor::new())));
+
+    let mut futs = FuturesUnordered::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            Ok((stream, _addr)) = listener.accept() => {
+                let builder = builder.clone();
+                let service = service.clone();
+                let acceptor = acceptor.clone();
+
+                futs.push(async move {
+                    let result = match acceptor {
+                        Some(acceptor) => match acceptor.accept(stream).await {
+                            Ok(stream) => serve_connection(builder.as_deref(), stream, service).await,
+                            Err(e) => Err(e.into()),
+                        },
+                        None => serve_connection(builder.as_deref(), stream, service).await,
+                    };
+                    if let Err(e) = result {
+                        eprintln!("Error serving connection: {e:?}");
+                    }
+                });
+            },
+
+            // `next()` resolves to `None` straight away while no connections
+            // are open, which must not wake up the loop
+            Some(()) = futs.next() => {}
+
+            _ = &mut shutdown => {
+                tracing::info!("Shutting down gracefully");
+                // stop the accept loop
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A [`tower::Layer`] that records every request handled by a runner in a
+/// [`Metrics`] registry, if there is one.
+#[derive(Debug, Clone)]
+pub(crate) struct MetricsLayer {
+    metrics: Option<Arc<Metrics>>,
+    runner: &'static str,
+}
+
+impl MetricsLayer {
+    pub(crate) fn new(metrics: Option<Arc<Metrics>>, runner: &'static str) -> Self {
+        MetricsLayer { metrics, runner }
+    }
+}
+
+impl<S> tower::Layer<S> for MetricsLayer {
+    type Service = MetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MetricsService {
+            inner,
+            metrics: self.metrics.clone(),
+            runner: self.runner,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct MetricsService<S> {
+    inner: S,
+    metrics: Option<Arc<Metrics>>,
+    runner: &'static str,
+}
+
+impl<S, R, B> tower::Service<R> for MetricsService<S>
+where
+    S: tower::Service<R, Response = Response<B>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<S::Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: R) -> Self::Future {
+        let fut = self.inner.call(req);
+        let Some(metrics) = self.metrics.clone() else {
+            return fut.boxed();
+        };
+
+        let runner = self.runner;
+        let start = Instant::now();
+        async move {
+            let result = fut.await;
+            let status = match &result {
+                Ok(response) => response.status().as_u16(),
+                Err(_) => 500,
+            };
+            metrics.record_request(runner, status, start.elapsed());
+            result
+        }
+        .boxed()
+    }
+}
+
+/// Serve a single connection, using HTTP/1 unless there is a `builder` for
+/// negotiating the version.
+async fn serve_connection<I, S, B>(
+    builder: Option<&Builder<TokioExecutor>>,
+    stream: I,
+    service: S,
+) -> Result<(), Box<dyn StdError + Send + Sync>>
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: hyper::service::Service<Request<Incoming>, Response = Response<B>> + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    let io = TokioIo::new(stream);
+
+    match builder {
+        Some(builder) => builder.serve_connection_with_upgrades(io, service).await,
+        // The auto builder ignores http1_only() when upgrades are enabled
+        None => hyper::server::conn::http1::Builder::new()
+            .serve_connection(io, service)
+            .with_upgrades()
+            .await
+            .map_err(Into::into),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use bytes::Bytes;
+    use http_body_util::{BodyExt, Empty, Full};
+    use hyper::service::service_fn;
+
+    use super::*;
+
+    async fn start(http2: bool) -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let service = service_fn(|req: Request<Incoming>| async move {
+            let version = format!("{:?}", req.version());
+            Ok::<_, Infallible>(Response::new(Full::new(Bytes::from(version))))
+        });
+        tokio::spawn(serve(
+            listener,
+            service,
+            http2,
+            None,
+            futures::future::pending(),
+        ));
+
+        addr
+    }
+
+    async fn get_h2c(addr: std::net::SocketAddr) -> Result<String, Error> {
+        let stream = tokio::net::TcpStream::connect(addr).await?;
+        let (mut sender, conn) =
+            hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+                .await?;
+        tokio::spawn(conn);
+
+        let req = Request::get(format!("http://{addr}/")).body(Empty::<Bytes>::new())?;
+        let body = sender
+            .send_request(req)
+            .await?
+            .into_body()
+            .collect()
+            .await?;
+        Ok(String::from_utf8(body.to_bytes().to_vec())?)
+    }
+
+    async fn get_http1(addr: std::net::SocketAddr) -> Result<String, Error> {
+        let stream = tokio::net::TcpStream::connect(addr).await?;
+        let (mut sender, conn) =
+            hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+        tokio::spawn(conn);
+
+        let req = Request::get("/")
+            .header(http::header::HOST, addr.to_string())
+            .body(Empty::<Bytes>::new())?;
+        let body = sender
+            .send_request(req)
+            .await?
+            .into_body()
+            .collect()
+            .await?;
+        Ok(String::from_utf8(body.to_bytes().to_vec())?)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serve_h2c_with_prior_knowledge() {
+        let addr = start(true).await;
+
+        assert_eq!(get_http1(addr).await.unwrap(), "HTTP/1.1");
+        assert_eq!(get_h2c(addr).await.unwrap(), "HTTP/2.0");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn http2_is_opt_in() {
+        let addr = start(false).await;
+
+        assert_eq!(get_http1(addr).await.unwrap(), "HTTP/1.1");
+        assert!(get_h2c(addr).await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn upgrade_http1_connections() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Echo everything sent after the upgrade
+        let service = service_fn(|mut req: Request<Incoming>| async move {
+            let on_upgrade = hyper::upgrade::on(&mut req);
+            tokio::spawn(async move {
+                let mut io = TokioIo::new(on_upgrade.await.unwrap());
+                let mut buffer = [0; 4];
+                io.read_exact(&mut buffer).await.unwrap();
+                io.write_all(&buffer).await.unwrap();
+            });
+            Response::builder()
+                .status(http::StatusCode::SWITCHING_PROTOCOLS)
+                .header(http::header::UPGRADE, "echo")
+                .header(http::header::CONNECTION, "upgrade")
+                .body(Empty::<Bytes>::new())
+        });
+        tokio::spawn(serve(
+            listener,
+            service,
+            false,
+            None,
+            futures::future::pending(),
+        ));
+
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .unwrap();
+        tokio::spawn(conn.with_upgrades());
+        let req = Request::get("/")
+            .header(http::header::HOST, addr.to_string())
+            .header(http::header::UPGRADE, "echo")
+            .header(http::header::CONNECTION, "upgrade")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let response = sender.send_request(req).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::SWITCHING_PROTOCOLS);
+
+        let mut io = TokioIo::new(hyper::upgrade::on(response).await.unwrap());
+        io.write_all(b"ping").await.unwrap();
+        let mut buffer = [0; 4];
+        io.read_exact(&mut buffer).await.unwrap();
+        assert_eq!(&buffer, b"ping");
+    }
+
+    #[tokio::test]
+    async fn record_request_metrics() {
+        use tower::{Layer, ServiceExt};
+
+        let metrics = Arc::new(Metrics::new());
+        let service = tower::service_fn(|req: Request<()>| async move {
+            let status = if req.uri().path() == "/" { 200 } else { 404 };
+            Response::builder().status(status).body(())
+        });
+        let service = MetricsLayer::new(Some(Arc::clone(&metrics)), "wcgi").layer(service);
+
+        for path in ["/", "/", "/missing"] {
+            let req = Request::get(path).body(()).unwrap();
+            service.clone().oneshot(req).await.unwrap();
+        }
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("wasmer_http_requests_total{runner=\"wcgi\",status=\"200\"} 2\n"));
+        assert!(rendered.contains("wasmer_http_requests_total{runner=\"wcgi\",status=\"404\"} 1\n"));
+        assert!(
+            rendered.contains("wasmer_http_request_duration_seconds_count{runner=\"wcgi\"} 3\n")
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serve_h2_over_tls_through_alpn() {
+        use tokio_rustls::{
+            rustls::{ClientConfig, RootCertStore},
+            TlsConnector,
+        };
+
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let tls = TlsConfig::new(dir.path().join("cert.pem"), dir.path().join("key.pem"));
+        std::fs::write(&tls.cert, cert.cert.pem()).unwrap();
+        std::fs::write(&tls.key, cert.key_pair.serialize_pem()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let service = service_fn(|req: Request<Incoming>| async move {
+            let version = format!("{:?}", req.version());
+            Ok::<_, Infallible>(Response::new(Full::new(Bytes::from(version))))
+        });
+        tokio::spawn(async move {
+            serve(
+                listener,
+                service,
+                true,
+                Some(&tls),
+                futures::future::pending(),
+            )
+            .await
+        });
+
+        let mut roots = RootCertStore::empty();
+        roots.add(cert.cert.der().clone()).unwrap();
+        let mut config = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let stream = TlsConnector::from(Arc::new(config))
+            .connect("localhost".try_into().unwrap(), stream)
+            .await
+            .unwrap();
+        assert_eq!(stream.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+
+        let (mut sender, conn) =
+            hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+                .await
+                .unwrap();
+        tokio::spawn(conn);
+        let req = Request::get(format!("https://localhost:{}/", addr.port()))
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let response = sender.send_request(req).await.unwrap();
+        assert_eq!(response.version(), http::Version::HTTP_2);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "HTTP/2.0");
+    }
+
+    #[test]
+    fn missing_tls_files_are_reported() {
+        let tls = TlsConfig::new("/does/not/exist.pem", "/does/not/exist.key");
+        let Err(error) = tls.acceptor(true) else {
+            panic!("the certificate shouldn't exist");
+        };
+
+        assert!(error.to_string().contains("/does/not/exist.pem"));
+    }
+}
@@ -46,6 +46,9 @@ pub trait Callbacks: std::fmt::Debug + Send + Sync + 'static {
     /// Reading from stderr failed.
     fn on_stderr_error(&self, _error: std::io::Error) {}
 
+    /// The state of the instance pool changed.
+    fn on_pool_metrics(&self, _metrics: PoolMetrics) {}
+
     /// Recycle the WASI environment
     async fn recycle_env(&self, conf: RecycleEnvConfig) {
         default_recycle_env(conf).await
@@ -35,6 +35,9 @@ pub(crate) async fn default_create_env(conf: CreateEnvConfig) -> anyhow::Result<
             insecure_allow_all: true,
             http_client: HttpClientCapabilityV1::new_allow_all(),
             threading: Default::default(),
+            coredump_dir: None,
+            coredump_on_trap: None,
+            native_coreutils: false,
         });
     let env = builder.build()?;
 
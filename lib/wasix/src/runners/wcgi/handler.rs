@@ -1,13 +1,15 @@
-use std::{collections::HashMap, ops::Deref, pin::Pin, sync::Arc};
+use std::{collections::HashMap, ops::Deref, pin::Pin, sync::Arc, time::Instant};
 
 use anyhow::Error;
 use bytes::Bytes;
 use futures::{Future, FutureExt};
-use http::{Request, Response, StatusCode};
+use http::{HeaderValue, Request, Response, StatusCode};
 use http_body_util::BodyExt;
-use hyper::body::Frame;
-use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt};
+use hyper::{body::Frame, upgrade::OnUpgrade};
+use hyper_util::rt::TokioIo;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
 use tracing::Instrument;
+use virtual_fs::Pipe;
 use virtual_mio::InlineWaker;
 use wasmer::Module;
 use wasmer_wasix_types::wasi::ExitCode;
@@ -16,17 +18,22 @@ use wcgi_host::CgiDialect;
 use super::super::Body;
 
 use crate::{
-    bin_factory::run_exec,
+    bin_factory::{prepare_exec, run_exec, run_prepared_exec},
     os::task::OwnedTaskStatus,
     runners::{
         body_from_data, body_from_stream,
         wcgi::{
-            callbacks::{CreateEnvConfig, RecycleEnvConfig},
-            Callbacks,
+            callbacks::{CreateEnvConfig, CreateEnvResult, RecycleEnvConfig},
+            pool::{InstancePool, WarmInstance},
+            upgrade, Callbacks,
         },
     },
-    runtime::task_manager::{TaskWasm, TaskWasmRecycleProperties},
-    Runtime, VirtualTaskManager, WasiEnvBuilder,
+    runtime::{
+        metrics::{ActiveInstance, Metrics},
+        task_manager::{TaskWasm, TaskWasmRecycleProperties},
+    },
+    state::conv_env_vars,
+    Runtime, VirtualTaskManager, WasiEnv, WasiEnvBuilder,
 };
 use wasmer_types::ModuleHash;
 
@@ -43,7 +50,7 @@ impl Handler {
     #[tracing::instrument(level = "debug", skip_all, err)]
     pub(crate) async fn handle<T>(
         &self,
-        req: Request<hyper::body::Incoming>,
+        mut req: Request<hyper::body::Incoming>,
         token: T,
     ) -> Result<Response<Body>, Error>
     where
@@ -51,6 +58,25 @@ impl Handler {
     {
         tracing::debug!(headers=?req.headers());
 
+        let permit = match self.pool.acquire().await {
+            Ok(permit) => permit,
+            Err(e) => {
+                tracing::warn!(error = %e, "Rejecting the request");
+                return Ok(Response::builder()
+                    .status(StatusCode::SERVICE_UNAVAILABLE)
+                    .body(body_from_data(Bytes::from(e.to_string())))?);
+            }
+        };
+        // The slot is only released once the instance has been recycled
+        let token = (token, permit);
+
+        // WebSockets keep the instance around for as long as the connection
+        // is open (see the `upgrade` module)
+        let websocket = upgrade::is_websocket_upgrade(&req).then(|| {
+            let key = req.headers()[http::header::SEC_WEBSOCKET_KEY].clone();
+            (key, hyper::upgrade::on(&mut req))
+        });
+
         let (parts, body) = req.into_parts();
 
         // Note: We want to apply the CGI environment variables *after*
@@ -65,17 +91,15 @@ impl Handler {
         self.dialect
             .prepare_environment_variables(parts, &mut request_specific_env);
 
-        let create = self
-            .callbacks
-            .create_env(CreateEnvConfig {
-                env: request_specific_env,
-                program_name: self.program_name.clone(),
-                module: self.module.clone(),
-                module_hash: self.module_hash,
-                runtime: self.runtime.clone(),
-                setup_builder: self.setup_builder.clone(),
-            })
-            .await?;
+        let (create, prepared) = match self.pool.take_warm() {
+            Some(WarmInstance { create, prepared }) => {
+                tracing::debug!("Using a pre-warmed instance");
+                add_request_env(&create.env, request_specific_env);
+                (create, Some(prepared))
+            }
+            None => (self.create_env(request_specific_env).await?, None),
+        };
+        self.prewarm();
 
         tracing::debug!(
             dialect=%self.dialect,
@@ -86,11 +110,23 @@ impl Handler {
         let env = create.env;
         let module = self.module.clone();
 
+        // The instance counts as active until it is recycled, or until the
+        // recycle function is dropped because it never started
+        let mut active = self
+            .metrics
+            .as_ref()
+            .map(|metrics| ActiveInstance::new(Arc::clone(metrics), self.runner_name));
+
         // The recycle function will attempt to reuse the instance
         let callbacks = Arc::clone(&self.callbacks);
         let recycle = {
             let callbacks = callbacks.clone();
             move |props: TaskWasmRecycleProperties| {
+                if let Some(active) = active.as_mut() {
+                    active.memory_size = Some(props.memory.view(&props.store).data_size());
+                }
+                drop(active);
+
                 InlineWaker::block_on(callbacks.recycle_env(RecycleEnvConfig {
                     env: props.env,
                     store: props.store,
@@ -124,16 +160,24 @@ impl Handler {
         // We run the WCGI thread on the dedicated WASM
         // thread pool that has support for asynchronous
         // threading, etc...
-        task_manager
-            .task_wasm(
+        let spawned = match prepared {
+            // Pre-warmed instances only need their entry point to be called
+            Some(prepared) => {
+                drop(env);
+                task_manager.task_dedicated(Box::new(move || {
+                    run_prepared_exec(prepared, Some(Box::new(recycle)))
+                }))
+            }
+            None => task_manager.task_wasm(
                 TaskWasm::new(Box::new(run_exec), env, module, false)
                     //.with_optional_memory(spawn_type)
                     .with_recycle(Box::new(recycle)),
-            )
-            .map_err(|err| {
-                tracing::warn!("failed to execute WCGI thread - {}", err);
-                err
-            })?;
+            ),
+        };
+        spawned.map_err(|err| {
+            tracing::warn!("failed to execute WCGI thread - {}", err);
+            err
+        })?;
 
         let mut res_body_receiver = tokio::io::BufReader::new(create.body_receiver);
 
@@ -145,6 +189,29 @@ impl Handler {
                 .in_current_span()
         };
 
+        if let Some((key, on_upgrade)) = websocket {
+            // The instance outlives the response, so stderr can't be used as
+            // the response body
+            task_manager
+                .task_shared(Box::new(move || {
+                    Box::pin(async move {
+                        work_consume_stderr.await;
+                    })
+                }))
+                .ok();
+
+            return self
+                .upgrade(
+                    finished,
+                    body,
+                    create.body_sender,
+                    res_body_receiver,
+                    key,
+                    on_upgrade,
+                )
+                .await;
+        }
+
         tracing::trace!(
             dialect=%self.dialect,
             "spawning request forwarder",
@@ -203,18 +270,7 @@ impl Handler {
             "received response parts",
         );
 
-        let chunks = futures::stream::try_unfold(res_body_receiver, |mut r| async move {
-            match r.fill_buf().await {
-                Ok([]) => Ok(None),
-                Ok(chunk) => {
-                    let chunk: bytes::Bytes = chunk.to_vec().into();
-                    r.consume(chunk.len());
-                    Ok(Some((Frame::data(chunk), r)))
-                }
-                Err(e) => Err(anyhow::Error::from(e)),
-            }
-        });
-        let body = body_from_stream(chunks);
+        let body = response_body(res_body_receiver);
 
         tracing::trace!(
             dialect=%self.dialect,
@@ -226,6 +282,156 @@ impl Handler {
     }
 }
 
+impl Handler {
+    /// Let the instance decide whether to accept a WebSocket upgrade, and
+    /// connect it to the client if it does.
+    async fn upgrade(
+        &self,
+        finished: Arc<OwnedTaskStatus>,
+        request_body: hyper::body::Incoming,
+        mut stdin: Pipe,
+        mut stdout: BufReader<Pipe>,
+        key: HeaderValue,
+        on_upgrade: OnUpgrade,
+    ) -> Result<Response<Body>, Error> {
+        // Unlike a normal request, stdin stays open after the body
+        forward_request_body(request_body, &mut stdin).await?;
+
+        let mut parts = self.dialect.extract_response_header(&mut stdout).await?;
+
+        if parts.status != StatusCode::SWITCHING_PROTOCOLS {
+            tracing::debug!(status=%parts.status, "The instance declined the upgrade");
+            stdin.shutdown().await?;
+            return Ok(Response::from_parts(parts, response_body(stdout)));
+        }
+
+        upgrade::complete_handshake(&mut parts.headers, &key);
+
+        let relay = async move {
+            let client = match on_upgrade.await {
+                Ok(upgraded) => TokioIo::new(upgraded),
+                Err(e) => {
+                    tracing::warn!(error = &e as &dyn std::error::Error, "Upgrade failed");
+                    let _ = stdin.shutdown().await;
+                    return;
+                }
+            };
+
+            if let Err(e) = upgrade::relay(client, stdout, stdin).await {
+                tracing::debug!(
+                    error = &e as &dyn std::error::Error,
+                    "The WebSocket connection was closed",
+                );
+            }
+        };
+
+        // The connection is only done once the instance exits, which is also
+        // when a failure of the instance is known
+        self.runtime.task_manager().task_shared(Box::new(move || {
+            Box::pin(
+                async move {
+                    let (ret, _) = futures::join!(finished.await_termination_anyhow(), relay);
+                    if let Err(e) = ret {
+                        tracing::error!(
+                            error = &*e,
+                            "Unable to drive the WebSocket connection to completion",
+                        );
+                    }
+                }
+                .in_current_span(),
+            )
+        }))?;
+
+        Ok(Response::from_parts(parts, body_from_data(Bytes::new())))
+    }
+
+    async fn create_env(&self, env: HashMap<String, String>) -> Result<CreateEnvResult, Error> {
+        let start = Instant::now();
+        let create = self
+            .callbacks
+            .create_env(CreateEnvConfig {
+                env,
+                program_name: self.program_name.clone(),
+                module: self.module.clone(),
+                module_hash: self.module_hash,
+                runtime: self.runtime.clone(),
+                setup_builder: self.setup_builder.clone(),
+            })
+            .await?;
+
+        if let Some(metrics) = &self.metrics {
+            metrics.record_instance_creation(self.runner_name, start.elapsed());
+        }
+
+        Ok(create)
+    }
+
+    /// Create instances in the background until the pool has as many
+    /// pre-warmed instances as it was configured with.
+    pub(crate) fn prewarm(&self) {
+        for _ in 0..self.pool.reserve_warming() {
+            let handler = self.clone();
+            let spawned = self.runtime.task_manager().task_shared(Box::new(move || {
+                Box::pin(async move {
+                    match handler.create_warm_instance().await {
+                        Ok(instance) => handler.pool.add_warm(instance),
+                        Err(e) => {
+                            tracing::warn!(error = &*e, "Unable to pre-warm an instance");
+                            handler.pool.warming_failed();
+                        }
+                    }
+                })
+            }));
+
+            if spawned.is_err() {
+                self.pool.warming_failed();
+            }
+        }
+    }
+
+    /// Create an environment and instantiate the module in it, up to (but
+    /// not including) the call to its entry point.
+    async fn create_warm_instance(&self) -> Result<WarmInstance, Error> {
+        let create = self.create_env(HashMap::new()).await?;
+
+        let (sender, receiver) = futures::channel::oneshot::channel();
+        let module = self.module.clone();
+        let env = create.env.clone();
+        #[cfg(feature = "journal")]
+        let snapshot = self.prewarm_snapshot.clone();
+        self.runtime
+            .task_manager()
+            .task_dedicated(Box::new(move || {
+                let prepared = prepare_exec(
+                    module,
+                    env,
+                    #[cfg(feature = "journal")]
+                    snapshot,
+                );
+                let _ = sender.send(prepared);
+            }))?;
+        let prepared = receiver.await??;
+
+        Ok(WarmInstance { create, prepared })
+    }
+}
+
+/// Add a request's CGI variables to an environment that was created before
+/// the request arrived, overriding any variables with the same name.
+fn add_request_env(env: &WasiEnv, vars: HashMap<String, String>) {
+    let mut envs = env.state.envs.lock().unwrap();
+    envs.retain(|existing| {
+        !vars.keys().any(|name| {
+            existing.starts_with(name.as_bytes()) && existing.get(name.len()) == Some(&b'=')
+        })
+    });
+    envs.extend(conv_env_vars(
+        vars.into_iter()
+            .map(|(name, value)| (name, value.into_bytes()))
+            .collect(),
+    ));
+}
+
 impl Deref for Handler {
     type Target = Arc<SharedState>;
 
@@ -234,29 +440,57 @@ impl Deref for Handler {
     }
 }
 
+/// Copy the request into our instance, chunk-by-chunk, returning the number
+/// of bytes written.
+///
+/// If the instance dies before we finish writing the body, the instance's
+/// side of the pipe will be automatically closed and we'll error out.
+async fn forward_request_body(
+    mut request_body: hyper::body::Incoming,
+    instance_stdin: &mut (impl AsyncWrite + Unpin),
+) -> Result<usize, Error> {
+    let mut request_size = 0;
+    while let Some(res) = request_body.frame().await {
+        // FIXME(theduke): figure out how to propagate a body error to the
+        // CGI instance.
+        let chunk = res?;
+        if let Some(data) = chunk.data_ref() {
+            request_size += data.len();
+            instance_stdin.write_all(data.as_ref()).await?;
+        } else {
+            // Trailers are not supported...
+        }
+    }
+
+    Ok(request_size)
+}
+
+/// Stream whatever the instance writes to stdout after the CGI headers.
+fn response_body(stdout: BufReader<Pipe>) -> Body {
+    let chunks = futures::stream::try_unfold(stdout, |mut r| async move {
+        match r.fill_buf().await {
+            Ok([]) => Ok(None),
+            Ok(chunk) => {
+                let chunk: bytes::Bytes = chunk.to_vec().into();
+                r.consume(chunk.len());
+                Ok(Some((Frame::data(chunk), r)))
+            }
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    });
+
+    body_from_stream(chunks)
+}
+
 /// Drive the request to completion by streaming the request body to the
 /// instance and waiting for it to exit.
 async fn drive_request_to_completion(
     finished: Arc<OwnedTaskStatus>,
-    mut request_body: hyper::body::Incoming,
+    request_body: hyper::body::Incoming,
     mut instance_stdin: impl AsyncWrite + Send + Sync + Unpin + 'static,
 ) -> Result<ExitCode, Error> {
     let request_body_send = async move {
-        // Copy the request into our instance, chunk-by-chunk. If the instance
-        // dies before we finish writing the body, the instance's side of the
-        // pipe will be automatically closed and we'll error out.
-        let mut request_size = 0;
-        while let Some(res) = request_body.frame().await {
-            // FIXME(theduke): figure out how to propagate a body error to the
-            // CGI instance.
-            let chunk = res?;
-            if let Some(data) = chunk.data_ref() {
-                request_size += data.len();
-                instance_stdin.write_all(data.as_ref()).await?;
-            } else {
-                // Trailers are not supported...
-            }
-        }
+        let request_size = forward_request_body(request_body, &mut instance_stdin).await?;
 
         instance_stdin.shutdown().await?;
         tracing::debug!(
@@ -328,7 +562,14 @@ pub(crate) struct SharedState {
     #[debug(ignore)]
     pub(crate) setup_builder: SetupBuilder,
     pub(crate) callbacks: Arc<dyn Callbacks>,
+    pub(crate) pool: Arc<InstancePool>,
+    pub(crate) metrics: Option<Arc<Metrics>>,
+    pub(crate) runner_name: &'static str,
     pub(crate) runtime: Arc<dyn Runtime + Send + Sync>,
+    /// Journal that pre-warmed instances are restored from.
+    #[cfg(feature = "journal")]
+    #[debug(ignore)]
+    pub(crate) prewarm_snapshot: Option<Arc<crate::journal::DynJournal>>,
 }
 
 impl tower::Service<Request<hyper::body::Incoming>> for Handler {
@@ -1,7 +1,9 @@
 mod callbacks;
 mod create_env;
 mod handler;
+mod pool;
 mod runner;
+mod upgrade;
 
 pub use self::runner::{Config, WcgiRunner};
 pub use callbacks::NoOpWcgiCallbacks;
@@ -9,3 +11,4 @@ pub use callbacks::{Callbacks, CreateEnvConfig, CreateEnvResult, RecycleEnvConfi
 pub(crate) use create_env::default_create_env;
 pub use futures::future::AbortHandle;
 pub(crate) use handler::{Handler, SharedState};
+pub use pool::{PoolConfig, PoolMetrics};
@@ -0,0 +1,298 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use super::{callbacks::CreateEnvResult, Callbacks};
+use crate::bin_factory::PreparedExec;
+
+/// Settings for the pool of instances that handle WCGI requests.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PoolConfig {
+    /// The number of instances to create ahead of time so requests don't
+    /// have to wait for them.
+    pub prewarm: usize,
+    /// The maximum number of requests handled at the same time.
+    pub max_concurrency: Option<usize>,
+    /// The maximum number of requests waiting for a free slot when
+    /// [`PoolConfig::max_concurrency`] is reached. Further requests are
+    /// rejected straight away.
+    pub max_queued: Option<usize>,
+    /// How long a request may wait for a free slot before it is rejected.
+    pub queue_timeout: Option<Duration>,
+}
+
+/// A snapshot of the state of a WCGI runner's instance pool.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolMetrics {
+    /// Requests currently being handled by an instance.
+    pub active: usize,
+    /// Requests waiting for a free slot.
+    pub queued: usize,
+    /// Instances created ahead of time and ready to handle a request.
+    pub warm: usize,
+    /// Requests rejected because the queue was full, since the server
+    /// started.
+    pub rejected: u64,
+    /// Requests rejected because they waited too long in the queue, since
+    /// the server started.
+    pub timed_out: u64,
+}
+
+#[derive(Debug, thiserror::Error, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PoolError {
+    #[error("too many requests are waiting to be handled")]
+    QueueFull,
+    #[error("timed out waiting for a free instance after {0:?}")]
+    TimedOut(Duration),
+}
+
+/// An instance created ahead of time, together with the environment and
+/// pipes it was created with.
+#[derive(derive_more::Debug)]
+pub(crate) struct WarmInstance {
+    #[debug(ignore)]
+    pub(crate) create: CreateEnvResult,
+    pub(crate) prepared: PreparedExec,
+}
+
+/// Caps the number of requests handled concurrently and keeps pre-warmed
+/// instances around.
+#[derive(derive_more::Debug)]
+pub(crate) struct InstancePool {
+    config: PoolConfig,
+    semaphore: Option<Arc<Semaphore>>,
+    #[debug(ignore)]
+    warm: Mutex<Vec<WarmInstance>>,
+    /// Instances that are being created to refill the pool.
+    warming: AtomicUsize,
+    active: AtomicUsize,
+    queued: AtomicUsize,
+    rejected: AtomicU64,
+    timed_out: AtomicU64,
+    callbacks: Arc<dyn Callbacks>,
+}
+
+impl InstancePool {
+    pub(crate) fn new(config: PoolConfig, callbacks: Arc<dyn Callbacks>) -> Self {
+        InstancePool {
+            semaphore: config
+                .max_concurrency
+                .map(|limit| Arc::new(Semaphore::new(limit))),
+            config,
+            warm: Mutex::new(Vec::new()),
+            warming: AtomicUsize::new(0),
+            active: AtomicUsize::new(0),
+            queued: AtomicUsize::new(0),
+            rejected: AtomicU64::new(0),
+            timed_out: AtomicU64::new(0),
+            callbacks,
+        }
+    }
+
+    /// Wait for a slot to handle a request in, which is freed when the
+    /// returned permit is dropped.
+    pub(crate) async fn acquire(self: &Arc<Self>) -> Result<PoolPermit, PoolError> {
+        let semaphore_permit = match &self.semaphore {
+            Some(semaphore) => Some(self.wait_for_slot(semaphore).await?),
+            None => None,
+        };
+
+        self.active.fetch_add(1, Ordering::SeqCst);
+        self.report();
+
+        Ok(PoolPermit {
+            pool: Arc::clone(self),
+            _semaphore_permit: semaphore_permit,
+        })
+    }
+
+    async fn wait_for_slot(
+        &self,
+        semaphore: &Arc<Semaphore>,
+    ) -> Result<OwnedSemaphorePermit, PoolError> {
+        if let Ok(permit) = Arc::clone(semaphore).try_acquire_owned() {
+            return Ok(permit);
+        }
+
+        let queued = self.queued.fetch_add(1, Ordering::SeqCst);
+        if self.config.max_queued.is_some_and(|max| queued >= max) {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            self.rejected.fetch_add(1, Ordering::SeqCst);
+            self.report();
+            return Err(PoolError::QueueFull);
+        }
+        self.report();
+
+        let acquire = Arc::clone(semaphore).acquire_owned();
+        let result = match self.config.queue_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, acquire)
+                .await
+                .map_err(|_| PoolError::TimedOut(timeout)),
+            None => Ok(acquire.await),
+        };
+
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        if result.is_err() {
+            self.timed_out.fetch_add(1, Ordering::SeqCst);
+        }
+        self.report();
+
+        // The semaphore is never closed
+        result.map(|permit| permit.expect("the semaphore was closed"))
+    }
+
+    /// Take a pre-warmed instance, if one is available.
+    pub(crate) fn take_warm(&self) -> Option<WarmInstance> {
+        let instance = self.warm.lock().unwrap().pop();
+        if instance.is_some() {
+            self.report();
+        }
+        instance
+    }
+
+    /// The number of instances that need to be created to fill the pool,
+    /// which the caller promises to hand to [`InstancePool::add_warm()`] or
+    /// [`InstancePool::warming_failed()`].
+    pub(crate) fn reserve_warming(&self) -> usize {
+        let warm = self.warm.lock().unwrap().len();
+        let warming = self.warming.load(Ordering::SeqCst);
+        let missing = self.config.prewarm.saturating_sub(warm + warming);
+        self.warming.fetch_add(missing, Ordering::SeqCst);
+        missing
+    }
+
+    pub(crate) fn add_warm(&self, instance: WarmInstance) {
+        self.warm.lock().unwrap().push(instance);
+        self.warming.fetch_sub(1, Ordering::SeqCst);
+        self.report();
+    }
+
+    pub(crate) fn warming_failed(&self) {
+        self.warming.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn metrics(&self) -> PoolMetrics {
+        PoolMetrics {
+            active: self.active.load(Ordering::SeqCst),
+            queued: self.queued.load(Ordering::SeqCst),
+            warm: self.warm.lock().unwrap().len(),
+            rejected: self.rejected.load(Ordering::SeqCst),
+            timed_out: self.timed_out.load(Ordering::SeqCst),
+        }
+    }
+
+    fn report(&self) {
+        self.callbacks.on_pool_metrics(self.metrics());
+    }
+}
+
+/// Holds one of the pool's slots until the request's instance has finished.
+#[derive(Debug)]
+pub(crate) struct PoolPermit {
+    pool: Arc<InstancePool>,
+    _semaphore_permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for PoolPermit {
+    fn drop(&mut self) {
+        self.pool.active.fetch_sub(1, Ordering::SeqCst);
+        self.pool.report();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runners::wcgi::NoOpWcgiCallbacks;
+
+    fn pool(config: PoolConfig) -> Arc<InstancePool> {
+        Arc::new(InstancePool::new(config, Arc::new(NoOpWcgiCallbacks)))
+    }
+
+    #[tokio::test]
+    async fn unlimited_by_default() {
+        let pool = pool(PoolConfig::default());
+
+        let permits = futures::future::try_join_all((0..10).map(|_| pool.acquire()))
+            .await
+            .unwrap();
+        assert_eq!(pool.metrics().active, 10);
+
+        drop(permits);
+        assert_eq!(pool.metrics().active, 0);
+    }
+
+    #[tokio::test]
+    async fn reject_when_the_queue_is_full() {
+        let pool = pool(PoolConfig {
+            max_concurrency: Some(1),
+            max_queued: Some(0),
+            ..Default::default()
+        });
+
+        let first = pool.acquire().await.unwrap();
+        assert_eq!(pool.acquire().await.unwrap_err(), PoolError::QueueFull);
+        assert_eq!(pool.metrics().rejected, 1);
+
+        drop(first);
+        let _second = pool.acquire().await.unwrap();
+        assert_eq!(pool.metrics().active, 1);
+    }
+
+    #[tokio::test]
+    async fn queued_requests_wait_for_a_slot() {
+        let pool = pool(PoolConfig {
+            max_concurrency: Some(1),
+            ..Default::default()
+        });
+
+        let first = pool.acquire().await.unwrap();
+        let second = tokio::spawn({
+            let pool = Arc::clone(&pool);
+            async move { pool.acquire().await.map(|_| ()) }
+        });
+        while pool.metrics().queued == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        drop(first);
+        second.await.unwrap().unwrap();
+        assert_eq!(pool.metrics().queued, 0);
+    }
+
+    #[tokio::test]
+    async fn queued_requests_time_out() {
+        let pool = pool(PoolConfig {
+            max_concurrency: Some(1),
+            queue_timeout: Some(Duration::from_millis(10)),
+            ..Default::default()
+        });
+
+        let _first = pool.acquire().await.unwrap();
+        assert_eq!(
+            pool.acquire().await.unwrap_err(),
+            PoolError::TimedOut(Duration::from_millis(10))
+        );
+        assert_eq!(pool.metrics().timed_out, 1);
+        assert_eq!(pool.metrics().queued, 0);
+    }
+
+    #[test]
+    fn reserve_warming_only_counts_missing_instances() {
+        let pool = pool(PoolConfig {
+            prewarm: 3,
+            ..Default::default()
+        });
+
+        assert_eq!(pool.reserve_warming(), 3);
+        assert_eq!(pool.reserve_warming(), 0);
+        pool.warming_failed();
+        assert_eq!(pool.reserve_warming(), 1);
+    }
+}
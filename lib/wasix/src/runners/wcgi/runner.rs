@@ -2,7 +2,6 @@ use std::{net::SocketAddr, sync::Arc};
 
 use super::super::Body;
 use anyhow::{Context, Error};
-use futures::{stream::FuturesUnordered, StreamExt};
 use http::{Request, Response};
 use tower::ServiceBuilder;
 use tower_http::{catch_panic::CatchPanicLayer, cors::CorsLayer, trace::TraceLayer};
@@ -16,11 +15,15 @@ use crate::{
     bin_factory::BinaryPackage,
     capabilities::Capabilities,
     runners::{
+        server::{serve, MetricsLayer},
         wasi_common::CommonWasiOptions,
-        wcgi::handler::{Handler, SharedState},
-        MappedDirectory,
+        wcgi::{
+            handler::{Handler, SharedState},
+            pool::{InstancePool, PoolConfig},
+        },
+        MappedDirectory, TlsConfig,
     },
-    runtime::task_manager::VirtualTaskManagerExt,
+    runtime::{metrics::Metrics, task_manager::VirtualTaskManagerExt},
     Runtime, WasiEnvBuilder,
 };
 
@@ -88,10 +91,21 @@ impl WcgiRunner {
             program_name: command_name.to_string(),
             setup_builder: Arc::new(setup_builder),
             callbacks: Arc::clone(&self.config.callbacks),
+            metrics: self.config.metrics.clone(),
+            runner_name: self.config.runner_name,
+            pool: Arc::new(InstancePool::new(
+                self.config.pool.clone(),
+                Arc::clone(&self.config.callbacks),
+            )),
             runtime,
+            #[cfg(feature = "journal")]
+            prewarm_snapshot: self.config.prewarm_snapshot.clone(),
         };
 
-        Ok(Handler::new(Arc::new(shared)))
+        let handler = Handler::new(Arc::new(shared));
+        handler.prewarm();
+
+        Ok(handler)
     }
 
     pub(crate) fn run_command_with_handler<S>(
@@ -111,6 +125,10 @@ impl WcgiRunner {
         S: Clone + Send + Sync + 'static,
     {
         let service = ServiceBuilder::new()
+            .layer(MetricsLayer::new(
+                self.config.metrics.clone(),
+                self.config.runner_name,
+            ))
             .layer(
                 TraceLayer::new_for_http()
                     .make_span_with(|request: &Request<hyper::body::Incoming>| {
@@ -131,45 +149,20 @@ impl WcgiRunner {
         tracing::info!(%address, "Starting the server");
 
         let callbacks = Arc::clone(&self.config.callbacks);
+        let http2 = self.config.http2;
+        let tls = self.config.tls.clone();
         runtime.task_manager().spawn_and_block_on(async move {
-            let (mut shutdown, abort_handle) =
+            let (shutdown, abort_handle) =
                 futures::future::abortable(futures::future::pending::<()>());
 
             callbacks.started(abort_handle);
 
             let listener = tokio::net::TcpListener::bind(&address).await?;
-            let graceful = hyper_util::server::graceful::GracefulShutdown::new();
-
-            let http = hyper::server::conn::http1::Builder::new();
-
-            let mut futs = FuturesUnordered::new();
-
-            loop {
-                tokio::select! {
-                    Ok((stream, _addr)) = listener.accept() => {
-                        let io = hyper_util::rt::tokio::TokioIo::new(stream);
-                        let service = hyper_util::service::TowerToHyperService::new(service.clone());
-                        let conn = http.serve_connection(io, service);
-                        // watch this connection
-                        let fut = graceful.watch(conn);
-                        futs.push(async move {
-                            if let Err(e) = fut.await {
-                                eprintln!("Error serving connection: {e:?}");
-                            }
-                        });
-                    },
-
-                    _ = futs.next() => {}
-
-                    _ = &mut shutdown => {
-                        eprintln!("graceful shutdown signal received");
-                        // stop the accept loop
-                        break;
-                    }
-                }
-            }
-
-            Ok::<_, anyhow::Error>(())
+            let service = hyper_util::service::TowerToHyperService::new(service);
+            serve(listener, service, http2, tls.as_ref(), async {
+                let _ = shutdown.await;
+            })
+            .await
         })??;
 
         Ok(())
@@ -204,6 +197,14 @@ impl crate::runners::Runner for WcgiRunner {
 pub struct Config {
     pub(crate) wasi: CommonWasiOptions,
     pub(crate) addr: SocketAddr,
+    pub(crate) http2: bool,
+    pub(crate) tls: Option<TlsConfig>,
+    pub(crate) pool: PoolConfig,
+    #[cfg(feature = "journal")]
+    pub(crate) prewarm_snapshot: Option<Arc<crate::journal::DynJournal>>,
+    pub(crate) metrics: Option<Arc<Metrics>>,
+    /// The name used to label metrics recorded by this runner.
+    pub(crate) runner_name: &'static str,
     pub(crate) callbacks: Arc<dyn Callbacks>,
 }
 
@@ -213,6 +214,73 @@ impl Config {
         self
     }
 
+    /// Serve HTTP/2 alongside HTTP/1, negotiated through ALPN when TLS is
+    /// enabled and with prior knowledge (h2c) otherwise.
+    pub fn http2(&mut self, enabled: bool) -> &mut Self {
+        self.http2 = enabled;
+        self
+    }
+
+    /// Terminate TLS using the given certificate and private key.
+    pub fn tls(&mut self, tls: TlsConfig) -> &mut Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Create this many instances ahead of time, so requests don't have to
+    /// wait for one to be set up.
+    ///
+    /// Pre-warmed instances get their environment from
+    /// [`Callbacks::create_env()`] and are instantiated, initialized and
+    /// bootstrapped (replaying the runtime's journals) before any request
+    /// arrives. They only receive the request's CGI variables once a request
+    /// is assigned to them, right before their entry point is called.
+    pub fn prewarm(&mut self, count: usize) -> &mut Self {
+        self.pool.prewarm = count;
+        self
+    }
+
+    /// Restore pre-warmed instances from a snapshot stored in this journal,
+    /// so requests resume from where the snapshot was taken (for instance
+    /// when the module first reads from stdin) instead of starting from
+    /// scratch.
+    ///
+    /// The journal is only read from. As the snapshot is taken before any
+    /// request is assigned to the instance, the module must not depend on
+    /// the request's CGI variables before that point.
+    #[cfg(feature = "journal")]
+    pub fn prewarm_snapshot(&mut self, journal: Arc<crate::journal::DynJournal>) -> &mut Self {
+        self.prewarm_snapshot = Some(journal);
+        self
+    }
+
+    /// Limit the number of requests handled at the same time. Requests over
+    /// the limit are queued until an instance finishes.
+    pub fn max_concurrency(&mut self, limit: usize) -> &mut Self {
+        self.pool.max_concurrency = Some(limit);
+        self
+    }
+
+    /// Limit the number of requests waiting for a free slot, rejecting any
+    /// further requests with `503 Service Unavailable`.
+    pub fn max_queued_requests(&mut self, limit: usize) -> &mut Self {
+        self.pool.max_queued = Some(limit);
+        self
+    }
+
+    /// Reject requests with `503 Service Unavailable` once they have waited
+    /// this long for a free slot.
+    pub fn queue_timeout(&mut self, timeout: std::time::Duration) -> &mut Self {
+        self.pool.queue_timeout = Some(timeout);
+        self
+    }
+
+    /// Record request, instance and memory metrics in a [`Metrics`] registry.
+    pub fn metrics(&mut self, metrics: Arc<Metrics>) -> &mut Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
     /// Add an argument to the WASI executable's command-line arguments.
     pub fn arg(&mut self, arg: impl Into<String>) -> &mut Self {
         self.wasi.args.push(arg.into());
@@ -338,6 +406,13 @@ impl Config {
     {
         Self {
             addr: ([127, 0, 0, 1], 8000).into(),
+            http2: false,
+            tls: None,
+            pool: PoolConfig::default(),
+            #[cfg(feature = "journal")]
+            prewarm_snapshot: None,
+            metrics: None,
+            runner_name: "wcgi",
             wasi: CommonWasiOptions::default(),
             callbacks: Arc::new(callbacks),
         }
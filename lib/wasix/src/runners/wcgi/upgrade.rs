@@ -0,0 +1,171 @@
+//! WebSocket support for WCGI.
+//!
+//! CGI only knows about one request and one response, so upgrades are handled
+//! through a small extension to the dialect. When a client asks to upgrade to
+//! a WebSocket, the instance is started as usual, except that its stdin isn't
+//! closed once the request body has been forwarded.
+//!
+//! If the instance answers with `Status: 101`, the runner completes the
+//! handshake on its behalf (filling in `Upgrade`, `Connection` and
+//! `Sec-WebSocket-Accept` when they are missing). From then on, stdin and
+//! stdout carry the raw WebSocket frames for as long as the connection stays
+//! open. Any other status is treated as a normal CGI response.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use http::{header, HeaderMap, HeaderValue, Request, Version};
+use sha1::{Digest, Sha1};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+/// The GUID every server appends to the client's key (RFC 6455, section 4.2.2).
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Is this an HTTP/1.1 request asking to switch to the WebSocket protocol?
+pub(crate) fn is_websocket_upgrade<B>(req: &Request<B>) -> bool {
+    let headers = req.headers();
+
+    req.version() == Version::HTTP_11
+        && has_token(headers, header::CONNECTION, "upgrade")
+        && has_token(headers, header::UPGRADE, "websocket")
+        && headers.contains_key(header::SEC_WEBSOCKET_KEY)
+}
+
+fn has_token(headers: &HeaderMap, name: header::HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case(token))
+}
+
+/// The `Sec-WebSocket-Accept` value which proves to the client that its
+/// handshake was understood.
+pub(crate) fn accept_key(key: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key);
+    hasher.update(WEBSOCKET_GUID);
+    STANDARD.encode(hasher.finalize())
+}
+
+/// Add whatever the instance left out of its `101 Switching Protocols`
+/// response.
+pub(crate) fn complete_handshake(headers: &mut HeaderMap, key: &HeaderValue) {
+    headers
+        .entry(header::UPGRADE)
+        .or_insert(HeaderValue::from_static("websocket"));
+    headers
+        .entry(header::CONNECTION)
+        .or_insert(HeaderValue::from_static("Upgrade"));
+    headers
+        .entry(header::SEC_WEBSOCKET_ACCEPT)
+        .or_insert_with(|| {
+            HeaderValue::from_str(&accept_key(key.as_bytes()))
+                .expect("base64 is always a valid header value")
+        });
+}
+
+/// Shuttle bytes between an upgraded connection and the instance until the
+/// instance closes its stdout.
+///
+/// When the client goes away first, the instance's stdin is closed so it can
+/// finish whatever it was sending and exit.
+pub(crate) async fn relay<C, O, I>(
+    client: C,
+    mut stdout: O,
+    mut stdin: I,
+) -> Result<(), std::io::Error>
+where
+    C: AsyncRead + AsyncWrite,
+    O: AsyncRead + Unpin,
+    I: AsyncWrite + Unpin,
+{
+    let (mut client_rx, mut client_tx) = tokio::io::split(client);
+
+    let to_instance = async move {
+        let result = tokio::io::copy(&mut client_rx, &mut stdin).await;
+        let _ = stdin.shutdown().await;
+        if let Err(e) = result {
+            tracing::debug!(error = &e as &dyn std::error::Error, "The client went away");
+        }
+    };
+    let to_client = async move {
+        tokio::io::copy(&mut stdout, &mut client_tx).await?;
+        client_tx.shutdown().await
+    };
+    tokio::pin!(to_client);
+
+    tokio::select! {
+        result = &mut to_client => result,
+        _ = to_instance => to_client.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+    use virtual_fs::Pipe;
+
+    use super::*;
+
+    fn upgrade_request() -> http::request::Builder {
+        Request::get("/chat")
+            .header(header::HOST, "example.com")
+            .header(header::UPGRADE, "websocket")
+            .header(header::CONNECTION, "keep-alive, Upgrade")
+            .header(header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ==")
+    }
+
+    #[test]
+    fn detect_websocket_upgrades() {
+        assert!(is_websocket_upgrade(&upgrade_request().body(()).unwrap()));
+
+        let plain = Request::get("/chat").body(()).unwrap();
+        assert!(!is_websocket_upgrade(&plain));
+        let h2c = Request::get("/chat")
+            .header(header::UPGRADE, "h2c")
+            .header(header::CONNECTION, "Upgrade")
+            .body(())
+            .unwrap();
+        assert!(!is_websocket_upgrade(&h2c));
+        let http2 = upgrade_request().version(Version::HTTP_2).body(()).unwrap();
+        assert!(!is_websocket_upgrade(&http2));
+    }
+
+    #[test]
+    fn fill_in_the_handshake() {
+        let key = HeaderValue::from_static("dGhlIHNhbXBsZSBub25jZQ==");
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONNECTION, HeaderValue::from_static("upgrade"));
+
+        complete_handshake(&mut headers, &key);
+
+        // The example from RFC 6455, section 1.3
+        assert_eq!(
+            headers[header::SEC_WEBSOCKET_ACCEPT],
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(headers[header::UPGRADE], "websocket");
+        assert_eq!(headers[header::CONNECTION], "upgrade");
+    }
+
+    #[tokio::test]
+    async fn relay_until_the_instance_exits() {
+        let (client, mut server) = tokio::io::duplex(64);
+        let (stdin, mut instance_stdin) = Pipe::channel();
+        let (mut instance_stdout, stdout) = Pipe::channel();
+        let relay = tokio::spawn(relay(client, stdout, stdin));
+
+        server.write_all(b"ping").await.unwrap();
+        let mut buffer = [0; 4];
+        instance_stdin.read_exact(&mut buffer).await.unwrap();
+        assert_eq!(&buffer, b"ping");
+
+        instance_stdout.write_all(b"pong").await.unwrap();
+        instance_stdout.shutdown().await.unwrap();
+        let mut received = Vec::new();
+        server.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"pong");
+
+        relay.await.unwrap().unwrap();
+    }
+}
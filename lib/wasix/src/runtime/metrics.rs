@@ -0,0 +1,365 @@
+//! Metrics collected while serving requests, which can be exported in the
+//! [Prometheus text format][format].
+//!
+//! A single [`Metrics`] registry is meant to be shared between the runners
+//! (e.g. `wcgi::Config::metrics()`) and the module cache
+//! (see [`crate::runtime::module_cache::ModuleCache::with_metrics()`]).
+//!
+//! [format]: https://prometheus.io/docs/instrumenting/exposition_formats/
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// Buckets for durations, in seconds.
+const DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Buckets for memory sizes, in bytes (1 MiB to 4 GiB).
+const MEMORY_BUCKETS: &[f64] = &[
+    1048576.0,
+    4194304.0,
+    16777216.0,
+    67108864.0,
+    268435456.0,
+    1073741824.0,
+    4294967296.0,
+];
+
+/// A registry of the metrics collected by runners and the module cache.
+///
+/// Every method takes the name of the runner doing the recording (e.g.
+/// `"wcgi"`), which is used as the `runner` label.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<(&'static str, u16), u64>>,
+    request_duration: Mutex<BTreeMap<&'static str, Histogram>>,
+    instance_creation: Mutex<BTreeMap<&'static str, Histogram>>,
+    active_instances: Mutex<BTreeMap<&'static str, u64>>,
+    instance_memory: Mutex<BTreeMap<&'static str, Histogram>>,
+    module_cache_hits: AtomicU64,
+    module_cache_misses: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    /// A request was answered with `status` after `duration`.
+    pub fn record_request(&self, runner: &'static str, status: u16, duration: Duration) {
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((runner, status))
+            .or_default() += 1;
+        self.request_duration
+            .lock()
+            .unwrap()
+            .entry(runner)
+            .or_insert_with(|| Histogram::new(DURATION_BUCKETS))
+            .observe(duration.as_secs_f64());
+    }
+
+    /// It took `duration` to set up a new instance.
+    pub fn record_instance_creation(&self, runner: &'static str, duration: Duration) {
+        self.instance_creation
+            .lock()
+            .unwrap()
+            .entry(runner)
+            .or_insert_with(|| Histogram::new(DURATION_BUCKETS))
+            .observe(duration.as_secs_f64());
+    }
+
+    /// An instance started running.
+    pub fn instance_started(&self, runner: &'static str) {
+        *self
+            .active_instances
+            .lock()
+            .unwrap()
+            .entry(runner)
+            .or_default() += 1;
+    }
+
+    /// An instance finished, having grown its linear memory to
+    /// `memory_size` bytes (if known).
+    pub fn instance_finished(&self, runner: &'static str, memory_size: Option<u64>) {
+        if let Some(active) = self.active_instances.lock().unwrap().get_mut(runner) {
+            *active = active.saturating_sub(1);
+        }
+
+        if let Some(memory_size) = memory_size {
+            self.instance_memory
+                .lock()
+                .unwrap()
+                .entry(runner)
+                .or_insert_with(|| Histogram::new(MEMORY_BUCKETS))
+                .observe(memory_size as f64);
+        }
+    }
+
+    /// A module was looked up in the module cache.
+    pub fn record_module_cache_lookup(&self, hit: bool) {
+        let counter = if hit {
+            &self.module_cache_hits
+        } else {
+            &self.module_cache_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Render all metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "wasmer_http_requests_total",
+            "counter",
+            "Number of HTTP requests handled.",
+        );
+        for ((runner, status), count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "wasmer_http_requests_total{{runner=\"{runner}\",status=\"{status}\"}} {count}"
+            );
+        }
+
+        header(
+            &mut out,
+            "wasmer_http_request_duration_seconds",
+            "histogram",
+            "Time taken to respond to HTTP requests.",
+        );
+        for (runner, histogram) in self.request_duration.lock().unwrap().iter() {
+            histogram.render(&mut out, "wasmer_http_request_duration_seconds", runner);
+        }
+
+        header(
+            &mut out,
+            "wasmer_instance_creation_duration_seconds",
+            "histogram",
+            "Time taken to set up new instances.",
+        );
+        for (runner, histogram) in self.instance_creation.lock().unwrap().iter() {
+            histogram.render(
+                &mut out,
+                "wasmer_instance_creation_duration_seconds",
+                runner,
+            );
+        }
+
+        header(
+            &mut out,
+            "wasmer_active_instances",
+            "gauge",
+            "Number of instances currently running.",
+        );
+        for (runner, active) in self.active_instances.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "wasmer_active_instances{{runner=\"{runner}\"}} {active}"
+            );
+        }
+
+        header(
+            &mut out,
+            "wasmer_instance_memory_bytes",
+            "histogram",
+            "Size of the linear memory of instances when they finish.",
+        );
+        for (runner, histogram) in self.instance_memory.lock().unwrap().iter() {
+            histogram.render(&mut out, "wasmer_instance_memory_bytes", runner);
+        }
+
+        header(
+            &mut out,
+            "wasmer_module_cache_lookups_total",
+            "counter",
+            "Number of modules looked up in the module cache.",
+        );
+        let hits = self.module_cache_hits.load(Ordering::Relaxed);
+        let misses = self.module_cache_misses.load(Ordering::Relaxed);
+        let _ = writeln!(
+            out,
+            "wasmer_module_cache_lookups_total{{result=\"hit\"}} {hits}"
+        );
+        let _ = writeln!(
+            out,
+            "wasmer_module_cache_lookups_total{{result=\"miss\"}} {misses}"
+        );
+
+        if let Some(rss) = resident_memory() {
+            header(
+                &mut out,
+                "process_resident_memory_bytes",
+                "gauge",
+                "Resident memory size in bytes.",
+            );
+            let _ = writeln!(out, "process_resident_memory_bytes {rss}");
+        }
+
+        out
+    }
+}
+
+/// Counts an instance as active until it is dropped.
+#[cfg(any(feature = "webc_runner_rt_wcgi", feature = "webc_runner_rt_dproxy"))]
+#[derive(Debug)]
+pub(crate) struct ActiveInstance {
+    metrics: std::sync::Arc<Metrics>,
+    runner: &'static str,
+    /// The size of the instance's linear memory, if known by the time it
+    /// finishes.
+    pub(crate) memory_size: Option<u64>,
+}
+
+#[cfg(any(feature = "webc_runner_rt_wcgi", feature = "webc_runner_rt_dproxy"))]
+impl ActiveInstance {
+    pub(crate) fn new(metrics: std::sync::Arc<Metrics>, runner: &'static str) -> Self {
+        metrics.instance_started(runner);
+        ActiveInstance {
+            metrics,
+            runner,
+            memory_size: None,
+        }
+    }
+}
+
+#[cfg(any(feature = "webc_runner_rt_wcgi", feature = "webc_runner_rt_dproxy"))]
+impl Drop for ActiveInstance {
+    fn drop(&mut self) {
+        self.metrics
+            .instance_finished(self.runner, self.memory_size);
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// The resident memory of the current process.
+#[cfg(target_os = "linux")]
+fn resident_memory() -> Option<u64> {
+    // The second field of statm is the resident set size, in pages
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    // SAFETY: sysconf() has no preconditions
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(pages * u64::try_from(page_size).ok()?)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resident_memory() -> Option<u64> {
+    None
+}
+
+#[derive(Debug)]
+struct Histogram {
+    bounds: &'static [f64],
+    /// The number of observations in each bucket (not cumulative).
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Histogram {
+            bounds,
+            buckets: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        if let Some(bucket) = self.bounds.iter().position(|bound| value <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, runner: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{runner=\"{runner}\",le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{runner=\"{runner}\",le=\"+Inf\"}} {}",
+            self.count
+        );
+        let _ = writeln!(out, "{name}_sum{{runner=\"{runner}\"}} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{{runner=\"{runner}\"}} {}", self.count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_requests() {
+        let metrics = Metrics::new();
+        metrics.record_request("wcgi", 200, Duration::from_millis(3));
+        metrics.record_request("wcgi", 200, Duration::from_millis(30));
+        metrics.record_request("wcgi", 404, Duration::from_secs(20));
+
+        let rendered = metrics.render();
+
+        assert!(rendered.contains("# TYPE wasmer_http_requests_total counter\n"));
+        assert!(rendered.contains("wasmer_http_requests_total{runner=\"wcgi\",status=\"200\"} 2\n"));
+        assert!(rendered.contains("wasmer_http_requests_total{runner=\"wcgi\",status=\"404\"} 1\n"));
+        assert!(rendered.contains(
+            "wasmer_http_request_duration_seconds_bucket{runner=\"wcgi\",le=\"0.001\"} 0\n"
+        ));
+        assert!(rendered.contains(
+            "wasmer_http_request_duration_seconds_bucket{runner=\"wcgi\",le=\"0.005\"} 1\n"
+        ));
+        assert!(rendered.contains(
+            "wasmer_http_request_duration_seconds_bucket{runner=\"wcgi\",le=\"10\"} 2\n"
+        ));
+        assert!(rendered.contains(
+            "wasmer_http_request_duration_seconds_bucket{runner=\"wcgi\",le=\"+Inf\"} 3\n"
+        ));
+        assert!(
+            rendered.contains("wasmer_http_request_duration_seconds_count{runner=\"wcgi\"} 3\n")
+        );
+    }
+
+    #[test]
+    fn track_instances_and_cache_lookups() {
+        let metrics = Metrics::new();
+        metrics.instance_started("dproxy");
+        metrics.instance_started("dproxy");
+        metrics.instance_finished("dproxy", Some(2 * 1024 * 1024));
+        metrics.record_module_cache_lookup(true);
+        metrics.record_module_cache_lookup(false);
+        metrics.record_module_cache_lookup(true);
+
+        let rendered = metrics.render();
+
+        assert!(rendered.contains("wasmer_active_instances{runner=\"dproxy\"} 1\n"));
+        assert!(rendered
+            .contains("wasmer_instance_memory_bytes_bucket{runner=\"dproxy\",le=\"1048576\"} 0\n"));
+        assert!(rendered
+            .contains("wasmer_instance_memory_bytes_bucket{runner=\"dproxy\",le=\"4194304\"} 1\n"));
+        assert!(rendered.contains("wasmer_module_cache_lookups_total{result=\"hit\"} 2\n"));
+        assert!(rendered.contains("wasmer_module_cache_lookups_total{result=\"miss\"} 1\n"));
+    }
+}
@@ -1,3 +1,4 @@
+pub mod metrics;
 pub mod module_cache;
 pub mod package_loader;
 pub mod resolver;
@@ -22,7 +23,7 @@ use wasmer_wasix_types::wasi::ExitCode;
 use crate::journal::DynJournal;
 use crate::{
     http::{DynHttpClient, HttpClient},
-    os::TtyBridge,
+    os::{TtyBridge, TtyForeground},
     runtime::{
         module_cache::{ModuleCache, ThreadLocalCache},
         package_loader::{PackageLoader, UnsupportedPackageLoader},
@@ -183,6 +184,7 @@ pub async fn load_module(
 #[derive(Debug, Default)]
 pub struct DefaultTty {
     state: Mutex<WasiTtyState>,
+    foreground: Mutex<Option<TtyForeground>>,
 }
 
 impl TtyBridge for DefaultTty {
@@ -202,6 +204,14 @@ impl TtyBridge for DefaultTty {
         let mut state = self.state.lock().unwrap();
         *state = tty_state;
     }
+
+    fn foreground_get(&self) -> Option<TtyForeground> {
+        self.foreground.lock().unwrap().clone()
+    }
+
+    fn foreground_set(&self, foreground: Option<TtyForeground>) {
+        *self.foreground.lock().unwrap() = foreground;
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,90 @@
+use std::sync::Arc;
+
+use wasmer::{Engine, Module};
+
+use crate::runtime::{
+    metrics::Metrics,
+    module_cache::{CacheError, ModuleCache, ModuleHash},
+};
+
+/// [`MeteredCache`] is a combinator for the [`ModuleCache`] trait that
+/// records every lookup as a hit or a miss in a [`Metrics`] registry,
+/// typically created via [`ModuleCache::with_metrics()`].
+#[derive(Debug, Clone)]
+pub struct MeteredCache<Inner> {
+    inner: Inner,
+    metrics: Arc<Metrics>,
+}
+
+impl<Inner> MeteredCache<Inner> {
+    pub(crate) fn new(inner: Inner, metrics: Arc<Metrics>) -> Self {
+        MeteredCache { inner, metrics }
+    }
+
+    pub fn inner(&self) -> &Inner {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut Inner {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> Inner {
+        self.inner
+    }
+}
+
+#[async_trait::async_trait]
+impl<Inner> ModuleCache for MeteredCache<Inner>
+where
+    Inner: ModuleCache + Send + Sync,
+{
+    async fn load(&self, key: ModuleHash, engine: &Engine) -> Result<Module, CacheError> {
+        let result = self.inner.load(key, engine).await;
+        self.metrics.record_module_cache_lookup(result.is_ok());
+        result
+    }
+
+    async fn save(
+        &self,
+        key: ModuleHash,
+        engine: &Engine,
+        module: &Module,
+    ) -> Result<(), CacheError> {
+        self.inner.save(key, engine, module).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::module_cache::SharedCache;
+
+    const ADD_WAT: &[u8] = br#"(
+        module
+            (func
+                (export "add")
+                (param $x i64)
+                (param $y i64)
+                (result i64)
+                (i64.add (local.get $x) (local.get $y)))
+        )"#;
+
+    #[tokio::test]
+    async fn record_hits_and_misses() {
+        let engine = Engine::default();
+        let module = Module::new(&engine, ADD_WAT).unwrap();
+        let key = ModuleHash::xxhash_from_bytes([0; 8]);
+        let metrics = Arc::new(Metrics::new());
+        let cache = SharedCache::default().with_metrics(Arc::clone(&metrics));
+
+        assert!(cache.load(key, &engine).await.is_err());
+        cache.save(key, &engine, &module).await.unwrap();
+        cache.load(key, &engine).await.unwrap();
+        cache.load(key, &engine).await.unwrap();
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("wasmer_module_cache_lookups_total{result=\"hit\"} 2\n"));
+        assert!(rendered.contains("wasmer_module_cache_lookups_total{result=\"miss\"} 1\n"));
+    }
+}
@@ -29,17 +29,20 @@
 //!
 //! The `module_cache` module provides combinators for extending and combining
 //! caching strategies. For example, you could use the [`FallbackCache`] to
-//! chain a fast in-memory cache with a slower file-based cache as a fallback.
+//! chain a fast in-memory cache with a slower file-based cache as a fallback,
+//! and the [`MeteredCache`] to count cache hits and misses.
 
 mod fallback;
 #[cfg(feature = "sys-thread")]
 mod filesystem;
+mod metrics;
 mod shared;
 mod thread_local;
 mod types;
 
 pub use self::{
     fallback::FallbackCache,
+    metrics::MeteredCache,
     shared::SharedCache,
     thread_local::ThreadLocalCache,
     types::{CacheError, ModuleCache},
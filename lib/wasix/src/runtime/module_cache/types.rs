@@ -1,9 +1,12 @@
-use std::{fmt::Debug, ops::Deref, path::PathBuf};
+use std::{fmt::Debug, ops::Deref, path::PathBuf, sync::Arc};
 
 use wasmer::{Engine, Module};
 use wasmer_types::ModuleHash;
 
-use crate::runtime::module_cache::FallbackCache;
+use crate::runtime::{
+    metrics::Metrics,
+    module_cache::{FallbackCache, MeteredCache},
+};
 
 /// A cache for compiled WebAssembly modules.
 ///
@@ -67,6 +70,15 @@ pub trait ModuleCache: Debug {
     {
         FallbackCache::new(self, other)
     }
+
+    /// Record whether each lookup was a hit or a miss in a [`Metrics`]
+    /// registry.
+    fn with_metrics(self, metrics: Arc<Metrics>) -> MeteredCache<Self>
+    where
+        Self: Sized,
+    {
+        MeteredCache::new(self, metrics)
+    }
 }
 
 #[async_trait::async_trait]
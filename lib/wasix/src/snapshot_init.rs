@@ -0,0 +1,699 @@
+//! Ahead-of-time pre-initialization of WebAssembly modules.
+//!
+//! A module's initializer is executed once, the state of its memories and
+//! globals is captured, and a new module is emitted where that state is baked
+//! in as data segments and constant global initializers. Modules such as
+//! language interpreters that do a lot of work at start-up can then skip that
+//! work entirely.
+//!
+//! The process is split in three steps so that the caller stays in control of
+//! how the module gets instantiated (e.g. with or without WASI imports):
+//!
+//! 1. [`SnapshotInit::instrument`] exports every internal memory and global so
+//!    they can be read from the outside.
+//! 2. [`SnapshotInit::snapshot`] runs the init function on an instance of the
+//!    instrumented module and captures its state.
+//! 3. [`SnapshotInit::rewrite`] takes the *original* module and produces the
+//!    pre-initialized module.
+//!
+//! Tables are not captured and imported memories, shared memories and the
+//! bulk-memory `memory.init`/`data.drop` instructions are not supported.
+
+use std::ops::Range;
+
+use wasm_encoder::{
+    ConstExpr, DataCountSection, DataSection, Encode, ExportKind, ExportSection, GlobalSection,
+    MemorySection, MemoryType, RawSection,
+};
+use wasmer::{AsStoreMut, ExportError, Instance, MemoryAccessError, RuntimeError, Value};
+use wasmparser::{DataKind, Encoding, ExternalKind, Operator, Parser, Payload, TypeRef};
+
+/// The init function that is used when none is specified (the same one used by
+/// Wizer).
+pub const DEFAULT_INIT_FUNCTION: &str = "wizer.initialize";
+
+/// The export that WASI reactors use for their own initialization.
+const WASI_INITIALIZE: &str = "_initialize";
+
+const GLOBAL_EXPORT_PREFIX: &str = "__wasmer_snapshot_global_";
+const MEMORY_EXPORT_PREFIX: &str = "__wasmer_snapshot_memory_";
+
+/// Runs of zero bytes shorter than this are kept inside a data segment rather
+/// than splitting it, as a new segment costs roughly this many bytes.
+const MIN_ZERO_GAP: usize = 16;
+
+/// Most engines refuse modules with more data segments than this.
+const MAX_DATA_SEGMENTS: usize = 10_000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotInitError {
+    #[error("Unable to parse the WebAssembly module")]
+    Parse(#[from] wasmparser::BinaryReaderError),
+    #[error("Unsupported module: {0}")]
+    Unsupported(String),
+    #[error("The module doesn't export a function named \"{0}\"")]
+    MissingInitFunction(String),
+    #[error("The init function \"{0}\" must not take any arguments or return any values")]
+    InvalidInitFunction(String),
+    #[error("The module was not instrumented for pre-initialization")]
+    NotInstrumented(#[from] ExportError),
+    #[error("The init function trapped")]
+    Trap(#[from] RuntimeError),
+    #[error("Unable to read the instance's memory")]
+    MemoryAccess(#[from] MemoryAccessError),
+    #[error("The snapshot doesn't match the module")]
+    SnapshotMismatch,
+}
+
+/// The state of an instance after its init function has run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// The value of each global defined by the module (imported globals
+    /// excluded). `None` is used for reference-typed globals, which keep their
+    /// original initializer.
+    pub globals: Vec<Option<Value>>,
+    /// The contents of each memory defined by the module.
+    pub memories: Vec<MemorySnapshot>,
+}
+
+/// The contents of a single memory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemorySnapshot {
+    /// The size of the memory, in wasm pages.
+    pub pages: u64,
+    /// The non-zero regions of the memory, as `(offset, bytes)` pairs.
+    pub segments: Vec<(u64, Vec<u8>)>,
+}
+
+/// Pre-initializes WebAssembly modules, in the spirit of
+/// [Wizer](https://github.com/bytecodealliance/wizer).
+#[derive(Debug, Clone)]
+pub struct SnapshotInit {
+    init_function: String,
+    keep_init_function: bool,
+}
+
+impl Default for SnapshotInit {
+    fn default() -> Self {
+        SnapshotInit::new(DEFAULT_INIT_FUNCTION)
+    }
+}
+
+impl SnapshotInit {
+    pub fn new(init_function: impl Into<String>) -> Self {
+        SnapshotInit {
+            init_function: init_function.into(),
+            keep_init_function: false,
+        }
+    }
+
+    /// Keep exporting the init function from the rewritten module (it is
+    /// removed by default so it can't accidentally be run twice).
+    pub fn with_keep_init_function(mut self, keep: bool) -> Self {
+        self.keep_init_function = keep;
+        self
+    }
+
+    pub fn init_function(&self) -> &str {
+        &self.init_function
+    }
+
+    /// Add exports for every memory and global defined by the module so its
+    /// state can be captured by [`SnapshotInit::snapshot`].
+    pub fn instrument(&self, wasm: &[u8]) -> Result<Vec<u8>, SnapshotInitError> {
+        let info = ModuleInfo::parse(wasm)?;
+        let mut module = wasm_encoder::Module::new();
+        let mut exports_emitted = false;
+
+        let emit_exports = |module: &mut wasm_encoder::Module| {
+            let mut exports = ExportSection::new();
+            for export in &info.exports {
+                exports.export(&export.name, export.kind, export.index);
+            }
+            for index in info.imported_globals..info.imported_globals + info.defined_globals() {
+                exports.export(
+                    &format!("{GLOBAL_EXPORT_PREFIX}{index}"),
+                    ExportKind::Global,
+                    index,
+                );
+            }
+            for index in 0..info.memories.len() as u32 {
+                exports.export(
+                    &format!("{MEMORY_EXPORT_PREFIX}{index}"),
+                    ExportKind::Memory,
+                    index,
+                );
+            }
+            module.section(&exports);
+        };
+
+        for section in &info.sections {
+            if !exports_emitted && section_order(section.id) > section_order(EXPORT_SECTION) {
+                emit_exports(&mut module);
+                exports_emitted = true;
+            }
+            if section.id == EXPORT_SECTION {
+                continue;
+            }
+            module.section(&RawSection {
+                id: section.id,
+                data: &wasm[section.range.clone()],
+            });
+        }
+        if !exports_emitted {
+            emit_exports(&mut module);
+        }
+
+        Ok(module.finish())
+    }
+
+    /// Run the init function on an instance of an [instrumented][instrument]
+    /// module and capture the resulting state.
+    ///
+    /// [instrument]: SnapshotInit::instrument
+    pub fn snapshot(
+        &self,
+        store: &mut impl AsStoreMut,
+        instance: &Instance,
+    ) -> Result<Snapshot, SnapshotInitError> {
+        let init = instance
+            .exports
+            .get_function(&self.init_function)
+            .map_err(|_| SnapshotInitError::MissingInitFunction(self.init_function.clone()))?;
+        let ty = init.ty(store);
+        if !ty.params().is_empty() || !ty.results().is_empty() {
+            return Err(SnapshotInitError::InvalidInitFunction(
+                self.init_function.clone(),
+            ));
+        }
+        init.call(store, &[])?;
+
+        self.capture(store, instance)
+    }
+
+    /// Capture the state of an instance of an [instrumented][instrument]
+    /// module without running the init function, for instances that were
+    /// already initialized (e.g. WASI reactors whose `_initialize` export is
+    /// called while being instantiated).
+    ///
+    /// [instrument]: SnapshotInit::instrument
+    pub fn capture(
+        &self,
+        store: &mut impl AsStoreMut,
+        instance: &Instance,
+    ) -> Result<Snapshot, SnapshotInitError> {
+        let mut globals = Vec::new();
+        let mut memories = Vec::new();
+        for (name, _) in instance.exports.iter() {
+            if let Some(index) = name.strip_prefix(GLOBAL_EXPORT_PREFIX) {
+                let index: usize = index
+                    .parse()
+                    .map_err(|_| SnapshotInitError::SnapshotMismatch)?;
+                globals.push((index, name.clone()));
+            } else if let Some(index) = name.strip_prefix(MEMORY_EXPORT_PREFIX) {
+                let index: usize = index
+                    .parse()
+                    .map_err(|_| SnapshotInitError::SnapshotMismatch)?;
+                memories.push((index, name.clone()));
+            }
+        }
+        globals.sort();
+        memories.sort();
+
+        let globals = globals
+            .into_iter()
+            .map(|(_, name)| {
+                let value = instance.exports.get_global(&name)?.get(store);
+                Ok(match value {
+                    Value::I32(_)
+                    | Value::I64(_)
+                    | Value::F32(_)
+                    | Value::F64(_)
+                    | Value::V128(_) => Some(value),
+                    _ => None,
+                })
+            })
+            .collect::<Result<Vec<_>, SnapshotInitError>>()?;
+
+        let memories = memories
+            .into_iter()
+            .map(|(_, name)| {
+                let memory = instance.exports.get_memory(&name)?;
+                let view = memory.view(store);
+                let pages = view.size().0 as u64;
+                let data = view.copy_to_vec()?;
+                Ok(MemorySnapshot {
+                    pages,
+                    segments: non_zero_segments(&data),
+                })
+            })
+            .collect::<Result<Vec<_>, SnapshotInitError>>()?;
+
+        Ok(Snapshot { globals, memories })
+    }
+
+    /// Rewrite the original (non-instrumented) module so that it starts with
+    /// the state captured in the snapshot.
+    ///
+    /// The start function is removed as its effects are part of the
+    /// snapshot, as are the init function and the `_initialize` export used
+    /// by WASI reactors.
+    pub fn rewrite(&self, wasm: &[u8], snapshot: &Snapshot) -> Result<Vec<u8>, SnapshotInitError> {
+        let info = ModuleInfo::parse(wasm)?;
+        if snapshot.globals.len() != info.defined_globals() as usize
+            || snapshot.memories.len() != info.memories.len()
+        {
+            return Err(SnapshotInitError::SnapshotMismatch);
+        }
+
+        let data_segments = snapshot
+            .memories
+            .iter()
+            .map(|m| m.segments.len())
+            .sum::<usize>() as u32;
+
+        let mut module = wasm_encoder::Module::new();
+        let mut data_emitted = false;
+
+        for section in &info.sections {
+            let data = &wasm[section.range.clone()];
+            match section.id {
+                MEMORY_SECTION => {
+                    let mut memories = MemorySection::new();
+                    for (ty, snapshot) in info.memories.iter().zip(&snapshot.memories) {
+                        memories.memory(MemoryType {
+                            minimum: snapshot.pages.max(ty.initial),
+                            maximum: ty.maximum,
+                            memory64: ty.memory64,
+                            shared: ty.shared,
+                            page_size_log2: None,
+                        });
+                    }
+                    module.section(&memories);
+                }
+                GLOBAL_SECTION => {
+                    let mut globals = GlobalSection::new();
+                    for (global, value) in info.globals.iter().zip(&snapshot.globals) {
+                        let mut raw = wasm[global.ty.clone()].to_vec();
+                        match value {
+                            Some(value) => const_expr(value).encode(&mut raw),
+                            None => raw.extend_from_slice(&wasm[global.init.clone()]),
+                        }
+                        globals.raw(&raw);
+                    }
+                    module.section(&globals);
+                }
+                EXPORT_SECTION => {
+                    let mut exports = ExportSection::new();
+                    for export in &info.exports {
+                        let is_init = export.kind == ExportKind::Func
+                            && (export.name == WASI_INITIALIZE
+                                || (export.name == self.init_function && !self.keep_init_function));
+                        if !is_init {
+                            exports.export(&export.name, export.kind, export.index);
+                        }
+                    }
+                    module.section(&exports);
+                }
+                START_SECTION => {}
+                DATA_COUNT_SECTION => {
+                    module.section(&DataCountSection {
+                        count: data_segments,
+                    });
+                }
+                DATA_SECTION => {
+                    module.section(&data_section(&info, snapshot));
+                    data_emitted = true;
+                }
+                _ => {
+                    // Data must come after the code section, but custom
+                    // sections are allowed to follow it.
+                    if section.id != CUSTOM_SECTION
+                        && !data_emitted
+                        && section_order(section.id) > section_order(DATA_SECTION)
+                    {
+                        module.section(&data_section(&info, snapshot));
+                        data_emitted = true;
+                    }
+                    module.section(&RawSection {
+                        id: section.id,
+                        data,
+                    });
+                }
+            }
+        }
+        if !data_emitted && data_segments > 0 {
+            module.section(&data_section(&info, snapshot));
+        }
+
+        Ok(module.finish())
+    }
+}
+
+const CUSTOM_SECTION: u8 = 0;
+const MEMORY_SECTION: u8 = 5;
+const GLOBAL_SECTION: u8 = 6;
+const EXPORT_SECTION: u8 = 7;
+const START_SECTION: u8 = 8;
+const DATA_SECTION: u8 = 11;
+const DATA_COUNT_SECTION: u8 = 12;
+const TAG_SECTION: u8 = 13;
+
+/// The position of a (non-custom) section in a module, which isn't the same
+/// as its id.
+fn section_order(id: u8) -> u8 {
+    match id {
+        CUSTOM_SECTION => 0,
+        1..=5 => id,
+        TAG_SECTION => 6,
+        GLOBAL_SECTION..=START_SECTION => id + 1,
+        9 => 10,
+        DATA_COUNT_SECTION => 11,
+        10 => 12,
+        DATA_SECTION => 13,
+        _ => u8::MAX,
+    }
+}
+
+fn const_expr(value: &Value) -> ConstExpr {
+    match *value {
+        Value::I32(v) => ConstExpr::i32_const(v),
+        Value::I64(v) => ConstExpr::i64_const(v),
+        Value::F32(v) => ConstExpr::f32_const(v),
+        Value::F64(v) => ConstExpr::f64_const(v),
+        Value::V128(v) => ConstExpr::v128_const(v as i128),
+        _ => unreachable!("only numeric globals are captured"),
+    }
+}
+
+fn data_section(info: &ModuleInfo, snapshot: &Snapshot) -> DataSection {
+    let mut data = DataSection::new();
+    for (index, (ty, memory)) in info.memories.iter().zip(&snapshot.memories).enumerate() {
+        for (offset, bytes) in &memory.segments {
+            let offset = if ty.memory64 {
+                ConstExpr::i64_const(*offset as i64)
+            } else {
+                ConstExpr::i32_const(*offset as u32 as i32)
+            };
+            data.active(index as u32, &offset, bytes.iter().copied());
+        }
+    }
+    data
+}
+
+/// Split a memory image into its non-zero regions, merging regions separated
+/// by small gaps.
+fn non_zero_segments(data: &[u8]) -> Vec<(u64, Vec<u8>)> {
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] == 0 {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < data.len() && data[i] != 0 {
+            i += 1;
+        }
+        match runs.last_mut() {
+            Some(last) if start - last.end < MIN_ZERO_GAP => last.end = i,
+            _ => runs.push(start..i),
+        }
+    }
+
+    // Keep merging the closest runs until we're within the segment limit.
+    let mut gap = MIN_ZERO_GAP;
+    while runs.len() > MAX_DATA_SEGMENTS {
+        gap *= 2;
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(runs.len());
+        for run in runs {
+            match merged.last_mut() {
+                Some(last) if run.start - last.end < gap => last.end = run.end,
+                _ => merged.push(run),
+            }
+        }
+        runs = merged;
+    }
+
+    runs.into_iter()
+        .map(|run| (run.start as u64, data[run].to_vec()))
+        .collect()
+}
+
+struct Section {
+    id: u8,
+    range: Range<usize>,
+}
+
+struct GlobalInfo {
+    ty: Range<usize>,
+    init: Range<usize>,
+}
+
+struct ExportInfo {
+    name: String,
+    kind: ExportKind,
+    index: u32,
+}
+
+/// The bits of a module needed to instrument and rewrite it.
+struct ModuleInfo {
+    sections: Vec<Section>,
+    imported_globals: u32,
+    globals: Vec<GlobalInfo>,
+    memories: Vec<wasmparser::MemoryType>,
+    exports: Vec<ExportInfo>,
+}
+
+impl ModuleInfo {
+    fn parse(wasm: &[u8]) -> Result<Self, SnapshotInitError> {
+        let mut info = ModuleInfo {
+            sections: Vec::new(),
+            imported_globals: 0,
+            globals: Vec::new(),
+            memories: Vec::new(),
+            exports: Vec::new(),
+        };
+
+        for payload in Parser::new(0).parse_all(wasm) {
+            let payload = payload?;
+            match &payload {
+                Payload::Version {
+                    encoding: Encoding::Component,
+                    ..
+                } => {
+                    return Err(SnapshotInitError::Unsupported(
+                        "components can't be pre-initialized".to_string(),
+                    ));
+                }
+                Payload::ImportSection(imports) => {
+                    for import in imports.clone() {
+                        match import?.ty {
+                            TypeRef::Global(_) => info.imported_globals += 1,
+                            TypeRef::Memory(_) => {
+                                return Err(SnapshotInitError::Unsupported(
+                                    "imported memories".to_string(),
+                                ));
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                Payload::MemorySection(memories) => {
+                    for memory in memories.clone() {
+                        let memory = memory?;
+                        if memory.shared {
+                            return Err(SnapshotInitError::Unsupported(
+                                "shared memories".to_string(),
+                            ));
+                        }
+                        if memory.page_size_log2.is_some() {
+                            return Err(SnapshotInitError::Unsupported(
+                                "custom page sizes".to_string(),
+                            ));
+                        }
+                        info.memories.push(memory);
+                    }
+                }
+                Payload::GlobalSection(globals) => {
+                    let end = globals.range().end;
+                    let mut items = Vec::new();
+                    for item in globals.clone().into_iter_with_offsets() {
+                        let (offset, global) = item?;
+                        items.push((offset, global.init_expr.get_binary_reader().range()));
+                    }
+                    for (i, (offset, init)) in items.iter().enumerate() {
+                        let item_end = items.get(i + 1).map(|(next, _)| *next).unwrap_or(end);
+                        info.globals.push(GlobalInfo {
+                            ty: *offset..init.start,
+                            init: init.start..item_end,
+                        });
+                    }
+                }
+                Payload::ExportSection(exports) => {
+                    for export in exports.clone() {
+                        let export = export?;
+                        info.exports.push(ExportInfo {
+                            name: export.name.to_string(),
+                            kind: match export.kind {
+                                ExternalKind::Func => ExportKind::Func,
+                                ExternalKind::Table => ExportKind::Table,
+                                ExternalKind::Memory => ExportKind::Memory,
+                                ExternalKind::Global => ExportKind::Global,
+                                ExternalKind::Tag => ExportKind::Tag,
+                            },
+                            index: export.index,
+                        });
+                    }
+                }
+                Payload::DataSection(data) => {
+                    for segment in data.clone() {
+                        if let DataKind::Passive = segment?.kind {
+                            return Err(SnapshotInitError::Unsupported(
+                                "passive data segments".to_string(),
+                            ));
+                        }
+                    }
+                }
+                Payload::CodeSectionEntry(body) => {
+                    for op in body.get_operators_reader()? {
+                        if let Operator::MemoryInit { .. } | Operator::DataDrop { .. } = op? {
+                            return Err(SnapshotInitError::Unsupported(
+                                "the memory.init and data.drop instructions".to_string(),
+                            ));
+                        }
+                    }
+                }
+                _ => {}
+            }
+
+            if let Some((id, range)) = payload.as_section() {
+                info.sections.push(Section { id, range });
+            }
+        }
+
+        Ok(info)
+    }
+
+    fn defined_globals(&self) -> u32 {
+        self.globals.len() as u32
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use wasmer::{imports, Module, Store};
+
+    use super::*;
+
+    const WAT: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (global $counter (mut i32) (i32.const 0))
+            (global $ratio (mut f64) (f64.const 0))
+            (global (export "base") i32 (i32.const 1024))
+            (data (i32.const 1024) "hello")
+            (func (export "wizer.initialize")
+                (global.set $counter (i32.const 42))
+                (global.set $ratio (f64.const 0.5))
+                (i32.store8 (i32.const 1024) (i32.const 72))
+                (drop (memory.grow (i32.const 1)))
+                (i32.store (i32.const 70000) (i32.const 0x01020304)))
+            (func (export "counter") (result i32) (global.get $counter))
+            (func (export "ratio") (result f64) (global.get $ratio))
+            (func (export "load") (param i32) (result i32) (i32.load8_u (local.get 0)))
+        )
+    "#;
+
+    fn pre_initialize(wasm: &[u8], snapshot_init: &SnapshotInit) -> Vec<u8> {
+        let mut store = Store::default();
+        let instrumented = snapshot_init.instrument(wasm).unwrap();
+        let module = Module::new(&store, &instrumented).unwrap();
+        let instance = Instance::new(&mut store, &module, &imports! {}).unwrap();
+        let snapshot = snapshot_init.snapshot(&mut store, &instance).unwrap();
+        snapshot_init.rewrite(wasm, &snapshot).unwrap()
+    }
+
+    #[test]
+    fn state_is_baked_into_the_module() {
+        let wasm = wasmer::wat2wasm(WAT.as_bytes()).unwrap();
+        let rewritten = pre_initialize(&wasm, &SnapshotInit::default());
+
+        let mut store = Store::default();
+        let module = Module::new(&store, &rewritten).unwrap();
+        let instance = Instance::new(&mut store, &module, &imports! {}).unwrap();
+
+        assert!(instance
+            .exports
+            .get_function(DEFAULT_INIT_FUNCTION)
+            .is_err());
+        assert!(instance
+            .exports
+            .iter()
+            .all(|(name, _)| !name.starts_with("__wasmer_snapshot")));
+
+        let call = |store: &mut Store, name: &str, args: &[Value]| {
+            instance
+                .exports
+                .get_function(name)
+                .unwrap()
+                .call(store, args)
+                .unwrap()[0]
+                .clone()
+        };
+        assert_eq!(call(&mut store, "counter", &[]), Value::I32(42));
+        assert_eq!(call(&mut store, "ratio", &[]), Value::F64(0.5));
+        assert_eq!(
+            call(&mut store, "load", &[Value::I32(1024)]),
+            Value::I32(72)
+        );
+        assert_eq!(
+            call(&mut store, "load", &[Value::I32(1025)]),
+            Value::I32(101)
+        );
+        assert_eq!(
+            call(&mut store, "load", &[Value::I32(70000)]),
+            Value::I32(4)
+        );
+
+        let memory = instance.exports.get_memory("memory").unwrap();
+        assert_eq!(memory.view(&store).size().0, 2);
+    }
+
+    #[test]
+    fn init_function_can_be_kept() {
+        let wasm = wasmer::wat2wasm(WAT.as_bytes()).unwrap();
+        let snapshot_init = SnapshotInit::default().with_keep_init_function(true);
+        let rewritten = pre_initialize(&wasm, &snapshot_init);
+
+        let store = Store::default();
+        let module = Module::new(&store, &rewritten).unwrap();
+        assert!(module
+            .exports()
+            .any(|export| export.name() == DEFAULT_INIT_FUNCTION));
+    }
+
+    #[test]
+    fn passive_segments_are_rejected() {
+        let wasm = wasmer::wat2wasm(
+            br#"(module (memory 1) (data "x") (func (export "wizer.initialize")))"#,
+        )
+        .unwrap();
+
+        let err = SnapshotInit::default().instrument(&wasm).unwrap_err();
+
+        assert!(matches!(err, SnapshotInitError::Unsupported(_)));
+    }
+
+    #[test]
+    fn small_gaps_are_merged() {
+        let mut data = vec![0_u8; 256];
+        data[10] = 1;
+        data[12] = 2;
+        data[200] = 3;
+
+        let segments = non_zero_segments(&data);
+
+        assert_eq!(segments, vec![(10, vec![1, 0, 2]), (200, vec![3])],);
+    }
+}
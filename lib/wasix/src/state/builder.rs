@@ -18,7 +18,9 @@ use crate::{
     bin_factory::{BinFactory, BinaryPackage},
     capabilities::Capabilities,
     fs::{WasiFs, WasiFsRoot, WasiInodes},
+    http::HttpPolicyClient,
     os::task::control_plane::{ControlPlaneConfig, ControlPlaneError, WasiControlPlane},
+    runtime::OverriddenRuntime,
     state::WasiState,
     syscalls::{
         rewind_ext2,
@@ -914,14 +916,27 @@ impl WasiEnvBuilder {
         let uses = self.uses;
         let map_commands = self.map_commands;
 
-        let bin_factory = BinFactory::new(runtime.clone());
-
         let capabilities = self.capabilites;
 
+        // Requests sent through the runtime's HTTP client on behalf of this
+        // instance need to satisfy its policy
+        let runtime = match runtime.http_client() {
+            Some(client) if !capabilities.http_client.rules.is_empty() => {
+                let client =
+                    HttpPolicyClient::new(client.clone(), capabilities.http_client.clone());
+                Arc::new(OverriddenRuntime::new(runtime).with_http_client(Arc::new(client)))
+            }
+            _ => runtime,
+        };
+
+        let bin_factory = BinFactory::new(runtime.clone());
+
         let plane_config = ControlPlaneConfig {
             max_task_count: capabilities.threading.max_threads,
             enable_asynchronous_threading: capabilities.threading.enable_asynchronous_threading,
             enable_exponential_cpu_backoff: capabilities.threading.enable_exponential_cpu_backoff,
+            coredump_dir: capabilities.coredump_dir.clone(),
+            coredump_on_trap: capabilities.coredump_on_trap.clone(),
         };
         let control_plane = WasiControlPlane::new(plane_config);
 
@@ -1099,9 +1114,17 @@ impl WasiEnvBuilder {
         if attach_ctrl_c {
             tokio::spawn({
                 let process = env.data(&store).process.clone();
+                let runtime = env.data(&store).runtime.clone();
                 async move {
+                    use wasmer_wasix_types::wasi::Signal;
+
                     while tokio::signal::ctrl_c().await.is_ok() {
-                        process.signal_process(wasmer_wasix_types::wasi::Signal::Sigint);
+                        // The foreground process group of the terminal gets
+                        // the signal if the guest uses job control
+                        let foreground = runtime.tty().and_then(|tty| tty.foreground_get());
+                        if !foreground.is_some_and(|fg| fg.signal(Signal::Sigint)) {
+                            process.signal_process(Signal::Sigint);
+                        }
                     }
                 }
             });
@@ -17,7 +17,7 @@ use wasmer::{
 use wasmer_config::package::PackageSource;
 use wasmer_wasix_types::{
     types::Signal,
-    wasi::{Errno, ExitCode, Snapshot0Clockid},
+    wasi::{Errno, ExitCode, SigAction, SigActionFlags, SigDisposition, Snapshot0Clockid},
     wasix::ThreadStartType,
 };
 use webc::metadata::annotations::Wasi;
@@ -32,6 +32,7 @@ use crate::{
     os::task::{
         control_plane::ControlPlaneError,
         process::{WasiProcess, WasiProcessId},
+        signal::{SignalDefault, SignalSet},
         thread::{WasiMemoryLayout, WasiThread, WasiThreadHandle, WasiThreadId},
     },
     runtime::{task_manager::InlineWaker, SpawnMemoryType},
@@ -388,6 +389,12 @@ impl WasiEnv {
         let thread = handle.as_thread();
         thread.copy_stack_from(&self.thread);
 
+        // The child inherits the signal actions and the mask, but none of
+        // the pending signals
+        process.inherit_signal_actions(&self.process);
+        thread.set_signal_mask(self.thread.signal_mask());
+        process.inherit_process_group(&self.process);
+
         let state = Arc::new(self.state.fork());
 
         let bin_factory = self.bin_factory.clone();
@@ -696,9 +703,12 @@ impl WasiEnv {
 
     /// Porcesses any signals that are batched up or any forced exit codes
     pub fn process_signals_and_exit(ctx: &mut FunctionEnvMut<'_, Self>) -> WasiResult<bool> {
+        // A stopped process does not go any further until it is continued
+        let env = ctx.data();
+        env.process.wait_while_stopped();
+
         // If a signal handler has never been set then we need to handle signals
         // differently
-        let env = ctx.data();
         let inner = env
             .try_inner()
             .ok_or_else(|| WasiError::Exit(Errno::Fault.into()))?;
@@ -706,16 +716,25 @@ impl WasiEnv {
             let signals = env.thread.pop_signals();
             if !signals.is_empty() {
                 for sig in signals {
-                    if sig == Signal::Sigint
-                        || sig == Signal::Sigquit
-                        || sig == Signal::Sigkill
-                        || sig == Signal::Sigabrt
-                        || sig == Signal::Sigpipe
-                    {
-                        let exit_code = env.thread.set_or_get_exit_code_for_signal(sig);
-                        return Err(WasiError::Exit(exit_code));
-                    } else {
-                        tracing::trace!(pid=%env.pid(), ?sig, "Signal ignored");
+                    if env.process.signal_action(sig).disposition == SigDisposition::Ignore {
+                        continue;
+                    }
+                    match SignalSet::default_action(sig) {
+                        Some(SignalDefault::Stop) if env.process.is_stop_signal(sig) => {
+                            if let Ok(signal) = Signal::try_from(sig) {
+                                env.process.stop(signal);
+                                env.process.wait_while_stopped();
+                            }
+                        }
+                        Some(action) if action.terminates() => {
+                            // Real-time signals exit the same way as SIGTERM
+                            let signal = Signal::try_from(sig).unwrap_or(Signal::Sigterm);
+                            let exit_code = env.thread.set_or_get_exit_code_for_signal(signal);
+                            return Err(WasiError::Exit(exit_code));
+                        }
+                        _ => {
+                            tracing::trace!(pid=%env.pid(), sig, "Signal ignored");
+                        }
                     }
                 }
                 return Ok(Ok(true));
@@ -756,7 +775,7 @@ impl WasiEnv {
 
     pub(crate) fn process_signals_internal(
         ctx: &mut FunctionEnvMut<'_, Self>,
-        mut signals: Vec<Signal>,
+        mut signals: Vec<u8>,
     ) -> Result<bool, WasiError> {
         let env = ctx.data();
         let inner = env
@@ -785,19 +804,56 @@ impl WasiEnv {
                         let elapsed = now - signal.last_signal;
                         if elapsed >= signal.interval.as_nanos() {
                             signal.last_signal = now;
-                            signals.push(signal.signal);
+                            signals.push(signal.signal as u8);
                         }
                     }
                 }
             }
 
             for signal in signals {
+                let action = ctx.data().process.signal_action(signal);
+                if action.disposition == SigDisposition::Ignore {
+                    continue;
+                }
+
+                // Stop signals that were blocked when sent stop the
+                // process once they are unblocked
+                let process = ctx.data().process.clone();
+                if process.is_stop_signal(signal) {
+                    if let Ok(signal) = Signal::try_from(signal) {
+                        process.stop(signal);
+                        process.wait_while_stopped();
+                    }
+                    continue;
+                }
+
                 tracing::trace!(
                     pid=%ctx.data().pid(),
-                    ?signal,
+                    signal,
                     "processing signal via handler",
                 );
-                if let Err(err) = handler.call(ctx, signal as i32) {
+
+                // The handler runs with its own mask (and the signal itself)
+                // blocked, the previous mask is restored when it returns
+                let thread = ctx.data().thread.clone();
+                let mut mask = thread
+                    .signal_mask()
+                    .union(SignalSet::from_bits(action.mask));
+                if !action.flags.contains(SigActionFlags::NODEFER) {
+                    mask.insert(signal);
+                }
+                let old_mask = thread.set_signal_mask(mask);
+                if action.flags.contains(SigActionFlags::RESETHAND) {
+                    ctx.data()
+                        .process
+                        .set_signal_action(signal, SigAction::default())
+                        .ok();
+                }
+
+                let res = handler.call(ctx, signal as i32);
+                thread.set_signal_mask(old_mask);
+
+                if let Err(err) = res {
                     match err.downcast::<WasiError>() {
                         Ok(wasi_err) => {
                             tracing::warn!(
@@ -810,7 +866,7 @@ impl WasiEnv {
                         Err(runtime_err) => {
                             // anything other than a kill command should report
                             // the error, killed things may not gracefully close properly
-                            if signal != Signal::Sigkill {
+                            if signal != Signal::Sigkill as u8 {
                                 tracing::warn!(
                                     pid=%ctx.data().pid(),
                                     runtime_err=&runtime_err as &dyn std::error::Error,
@@ -2,7 +2,8 @@ use std::sync::Arc;
 
 use tracing::trace;
 use wasmer::{
-    AsStoreMut, AsStoreRef, ExportError, FunctionEnv, Imports, Instance, Memory, Module, Store,
+    AsStoreMut, AsStoreRef, CoredumpBuilder, ExportError, FunctionEnv, Imports, Instance, Memory,
+    Module, RuntimeError, Store,
 };
 use wasmer_wasix_types::wasi::ExitCode;
 
@@ -14,7 +15,7 @@ use crate::{
     import_object_for_all_wasi_versions,
     runtime::task_manager::SpawnMemoryTypeOrStore,
     state::WasiInstanceHandles,
-    utils::{get_wasi_version, get_wasi_versions, store::restore_store_snapshot},
+    utils::{get_wasi_version, get_wasi_versions, store::restore_store_snapshot, WasiVersion},
     RewindStateOption, StoreSnapshot, WasiEnv, WasiError, WasiRuntimeError, WasiThreadError,
 };
 
@@ -316,6 +317,49 @@ impl WasiFunctionEnv {
         self.data(store).blocking_on_exit(process_exit_code);
     }
 
+    /// Writes a core dump of the thread that failed with `err`, into the
+    /// coredump file of the control plane if this is the main thread of the
+    /// root process or into its coredump directory otherwise (if either is
+    /// configured)
+    pub(crate) fn write_coredump(&self, store: &mut impl AsStoreMut, err: &RuntimeError) {
+        let env = self.data(store);
+        let config = env.control_plane.config();
+        let (pid, tid) = (env.pid(), env.tid());
+        let path = match (&config.coredump_on_trap, &config.coredump_dir) {
+            (Some(path), _) if env.process.parent.is_none() && env.thread.is_main() => path.clone(),
+            (_, Some(dir)) => dir.join(format!("core.{pid}.{tid}.wasm")),
+            _ => return,
+        };
+        let program = env
+            .state
+            .args
+            .lock()
+            .unwrap()
+            .first()
+            .cloned()
+            .unwrap_or_default();
+        let instance = env.try_inner().map(|inner| inner.instance.clone());
+
+        let mut coredump =
+            CoredumpBuilder::new(program.as_str()).with_thread(format!("{tid}"), err.trace());
+        if let Some(instance) = instance {
+            let memory64 = get_wasi_versions(instance.module(), false)
+                .is_some_and(|versions| versions.contains(&WasiVersion::Wasix64v1));
+            coredump = coredump
+                .with_memory64(memory64)
+                .with_instance(store, &instance);
+        }
+
+        match std::fs::write(&path, coredump.serialize()) {
+            Ok(()) => tracing::info!(path=%path.display(), %pid, %tid, "wrote a core dump"),
+            Err(err) => tracing::warn!(
+                path=%path.display(),
+                error=&err as &dyn std::error::Error,
+                "unable to write a core dump",
+            ),
+        }
+    }
+
     /// Bootstraps this main thread and context with any journals that
     /// may be present
     ///
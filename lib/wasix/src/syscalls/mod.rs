@@ -88,6 +88,7 @@ pub(crate) use self::types::{
         Addressfamily, Advice, Clockid, Dircookie, Dirent, Errno, Event, EventFdReadwrite,
         Eventrwflags, Eventtype, ExitCode, Fd as WasiFd, Fdflags, Fdflagsext, Fdstat, Filesize,
         Filestat, Filetype, Fstflags, Linkcount, Longsize, OptionFd, Pid, Prestat, Rights,
+        SigAction, SigActionFlags, SigActionRaw, SigDisposition, SigmaskHow, Sigset,
         Snapshot0Clockid, Sockoption, Sockstatus, Socktype, StackSnapshot,
         StdioMode as WasiStdioMode, Streamsecurity, Subscription, SubscriptionFsReadwrite, Tid,
         Timestamp, TlKey, TlUser, TlVal, Tty, Whence,
@@ -334,16 +335,31 @@ where
     {
         type Output = Result<Fut::Output, WasiError>;
         fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-            if let Poll::Ready(res) = Pin::new(&mut self.pinned_work).poll(cx) {
-                return Poll::Ready(Ok(res));
-            }
-            if let Some(signals) = self.ctx.data().thread.pop_signals_or_subscribe(cx.waker()) {
-                if let Err(err) = WasiEnv::process_signals_internal(self.ctx, signals) {
-                    return Poll::Ready(Err(err));
+            loop {
+                // The work does not complete while the process is stopped
+                self.ctx.data().process.wait_while_stopped();
+
+                if let Poll::Ready(res) = Pin::new(&mut self.pinned_work).poll(cx) {
+                    return Poll::Ready(Ok(res));
+                }
+                let Some(signals) = self.ctx.data().thread.pop_signals_or_subscribe(cx.waker())
+                else {
+                    return Poll::Pending;
+                };
+
+                // The call is restarted if every handler was registered
+                // with `SA_RESTART`
+                let restart = {
+                    let env = self.ctx.data();
+                    let has_handler = env.try_inner().is_some_and(|inner| inner.signal.is_some());
+                    has_handler && env.process.signals_restart(&signals)
+                };
+                match WasiEnv::process_signals_internal(self.ctx, signals) {
+                    Err(err) => return Poll::Ready(Err(err)),
+                    Ok(_) if restart => continue,
+                    Ok(_) => return Poll::Ready(Ok(Err(Errno::Intr))),
                 }
-                return Poll::Ready(Ok(Err(Errno::Intr)));
             }
-            Poll::Pending
         }
     }
 
@@ -387,22 +403,16 @@ where
         }
         if env.thread.has_signals_or_subscribe(cx.waker()) {
             let has_exit = {
-                let signals = env.thread.signals().lock().unwrap();
-                signals
-                    .0
-                    .iter()
-                    .filter_map(|sig| {
-                        if *sig == Signal::Sigint
-                            || *sig == Signal::Sigquit
-                            || *sig == Signal::Sigkill
-                            || *sig == Signal::Sigabrt
-                        {
-                            Some(env.thread.set_or_get_exit_code_for_signal(*sig))
-                        } else {
-                            None
-                        }
-                    })
-                    .next()
+                let signals = env.thread.deliverable_signals();
+                [
+                    Signal::Sigint,
+                    Signal::Sigquit,
+                    Signal::Sigkill,
+                    Signal::Sigabrt,
+                ]
+                .into_iter()
+                .find(|sig| signals.contains(*sig as u8))
+                .map(|sig| env.thread.set_or_get_exit_code_for_signal(sig))
             };
 
             return match WasiEnv::process_signals_and_exit(self.ctx) {
@@ -128,6 +128,7 @@ pub(crate) fn fd_read_internal<M: MemorySize>(
     should_update_cursor: bool,
 ) -> WasiResult<usize> {
     wasi_try_ok_ok!(WasiEnv::process_signals_and_exit(ctx)?);
+    wasi_try_ok_ok!(check_terminal_access(ctx, fd, TerminalAccess::Read)?);
 
     let env = ctx.data();
     let memory = unsafe { env.memory_view(&ctx) };
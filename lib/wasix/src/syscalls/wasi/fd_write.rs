@@ -31,6 +31,7 @@ pub fn fd_write<M: MemorySize>(
     nwritten: WasmPtr<M::Offset, M>,
 ) -> Result<Errno, WasiError> {
     wasi_try_ok!(WasiEnv::process_signals_and_exit(&mut ctx)?);
+    wasi_try_ok!(check_terminal_access(&mut ctx, fd, TerminalAccess::Write)?);
 
     let env = ctx.data();
     let enable_journal = env.enable_journal;
@@ -28,19 +28,38 @@ mod port_unbridge;
 mod proc_exec;
 mod proc_exec2;
 mod proc_fork;
+mod proc_getpgid;
+mod proc_getsid;
 mod proc_id;
 mod proc_join;
 mod proc_parent;
+mod proc_setpgid;
+mod proc_setsid;
+mod proc_sigaction;
 mod proc_signal;
+mod proc_signal_group;
+mod proc_sigqueue;
 mod proc_snapshot;
 mod proc_spawn;
+mod pty_foreground_set;
+mod pty_get;
+mod pty_open;
+mod pty_set;
 mod resolve;
 mod sched_yield;
+mod shm_map;
+mod shm_open;
+mod shm_unlink;
+mod shm_unmap;
 mod sock_accept;
 mod sock_addr_local;
+mod sock_addr_local_unix;
 mod sock_addr_peer;
+mod sock_addr_peer_unix;
 mod sock_bind;
+mod sock_bind_unix;
 mod sock_connect;
+mod sock_connect_unix;
 mod sock_get_opt_flag;
 mod sock_get_opt_size;
 mod sock_get_opt_time;
@@ -52,10 +71,14 @@ mod sock_listen;
 mod sock_open;
 mod sock_pair;
 mod sock_recv;
+mod sock_recv_fds;
 mod sock_recv_from;
+mod sock_recv_from_unix;
 mod sock_send;
+mod sock_send_fds;
 mod sock_send_file;
 mod sock_send_to;
+mod sock_send_to_unix;
 mod sock_set_opt_flag;
 mod sock_set_opt_size;
 mod sock_set_opt_time;
@@ -67,9 +90,13 @@ mod thread_exit;
 mod thread_id;
 mod thread_join;
 mod thread_parallelism;
+mod thread_sigmask;
 mod thread_signal;
+mod thread_sigpending;
 mod thread_sleep;
 mod thread_spawn;
+mod tty_foreground_get;
+mod tty_foreground_set;
 mod tty_get;
 mod tty_set;
 
@@ -103,19 +130,38 @@ pub use port_unbridge::*;
 pub use proc_exec::*;
 pub use proc_exec2::*;
 pub use proc_fork::*;
+pub use proc_getpgid::*;
+pub use proc_getsid::*;
 pub use proc_id::*;
 pub use proc_join::*;
 pub use proc_parent::*;
+pub use proc_setpgid::*;
+pub use proc_setsid::*;
+pub use proc_sigaction::*;
 pub use proc_signal::*;
+pub use proc_signal_group::*;
+pub use proc_sigqueue::*;
 pub use proc_snapshot::*;
 pub use proc_spawn::*;
+pub use pty_foreground_set::*;
+pub use pty_get::*;
+pub use pty_open::*;
+pub use pty_set::*;
 pub use resolve::*;
 pub use sched_yield::*;
+pub use shm_map::*;
+pub use shm_open::*;
+pub use shm_unlink::*;
+pub use shm_unmap::*;
 pub use sock_accept::*;
 pub use sock_addr_local::*;
+pub use sock_addr_local_unix::*;
 pub use sock_addr_peer::*;
+pub use sock_addr_peer_unix::*;
 pub use sock_bind::*;
+pub use sock_bind_unix::*;
 pub use sock_connect::*;
+pub use sock_connect_unix::*;
 pub use sock_get_opt_flag::*;
 pub use sock_get_opt_size::*;
 pub use sock_get_opt_time::*;
@@ -127,10 +173,14 @@ pub use sock_listen::*;
 pub use sock_open::*;
 pub use sock_pair::*;
 pub use sock_recv::*;
+pub use sock_recv_fds::*;
 pub use sock_recv_from::*;
+pub use sock_recv_from_unix::*;
 pub use sock_send::*;
+pub use sock_send_fds::*;
 pub use sock_send_file::*;
 pub use sock_send_to::*;
+pub use sock_send_to_unix::*;
 pub use sock_set_opt_flag::*;
 pub use sock_set_opt_size::*;
 pub use sock_set_opt_time::*;
@@ -142,9 +192,13 @@ pub use thread_exit::*;
 pub use thread_id::*;
 pub use thread_join::*;
 pub use thread_parallelism::*;
+pub use thread_sigmask::*;
 pub use thread_signal::*;
+pub use thread_sigpending::*;
 pub use thread_sleep::*;
 pub use thread_spawn::*;
+pub use tty_foreground_get::*;
+pub use tty_foreground_set::*;
 pub use tty_get::*;
 pub use tty_set::*;
 
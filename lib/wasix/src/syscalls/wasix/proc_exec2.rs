@@ -105,6 +105,10 @@ pub fn proc_exec2<M: MemorySize>(
         Ok(())
     });
 
+    // The new program starts with a fresh memory so none of the shared
+    // memory mappings survive
+    ctx.data().process.clear_shared_memory_mappings();
+
     let new_store = ctx.data().runtime.new_store();
 
     // If we are in a vfork we need to first spawn a subprocess of this type
@@ -154,6 +154,7 @@ pub fn proc_fork<M: MemorySize>(
         let module = unsafe { ctx.data().inner() }.module_clone();
         let memory = unsafe { ctx.data().inner() }.memory_clone();
         let spawn_type = SpawnMemoryType::CopyMemory(memory, ctx.as_store_ref());
+        let shm_mappings = ctx.data().process.shared_memory_mappings();
 
         // Spawn a new process with this current execution environment
         let signaler = Box::new(child_env.process.clone());
@@ -167,6 +168,23 @@ pub fn proc_fork<M: MemorySize>(
                 let ctx = props.ctx;
                 let mut store = props.store;
 
+                // Shared memory regions were copied like the rest of the memory
+                // so they are mapped again to keep sharing them with the parent
+                {
+                    let env = ctx.data(&store);
+                    let memory = unsafe { env.memory() };
+                    for mapping in shm_mappings.iter() {
+                        if let Err(err) = unsafe { mapping.apply(memory, &store) } {
+                            warn!(
+                                "failed to map shared memory into the forked process - errno={}",
+                                err
+                            );
+                            return;
+                        }
+                        env.process.add_shared_memory_mapping(mapping.clone());
+                    }
+                }
+
                 // Rewind the stack and carry on
                 {
                     trace!("rewinding child");
@@ -0,0 +1,36 @@
+use super::*;
+use crate::{os::task::process::WasiProcess, syscalls::*};
+
+/// ### `proc_getpgid()`
+/// Returns the process group of a process
+/// Note: This is similar to `getpgid` in POSIX.
+///
+/// ## Parameters
+///
+/// * `pid` - The process, or 0 for the calling process
+#[instrument(level = "trace", skip_all, fields(%pid, pgid = field::Empty), ret)]
+pub fn proc_getpgid<M: MemorySize>(
+    ctx: FunctionEnvMut<'_, WasiEnv>,
+    pid: Pid,
+    ret_pgid: WasmPtr<Pid, M>,
+) -> Errno {
+    let env = ctx.data();
+    let process = wasi_try!(process_from_pid(env, pid));
+
+    let pgid: Pid = process.pgid().raw();
+    Span::current().record("pgid", pgid);
+
+    let memory = unsafe { env.memory_view(&ctx) };
+    wasi_try_mem!(ret_pgid.write(&memory, pgid));
+
+    Errno::Success
+}
+
+/// Looks up a process, where 0 stands for the calling process
+pub(crate) fn process_from_pid(env: &WasiEnv, pid: Pid) -> Result<WasiProcess, Errno> {
+    if pid == 0 {
+        return Ok(env.process.clone());
+    }
+    let pid: WasiProcessId = pid.into();
+    env.control_plane.get_process(pid).ok_or(Errno::Srch)
+}
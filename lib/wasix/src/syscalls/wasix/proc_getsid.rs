@@ -0,0 +1,27 @@
+use super::*;
+use crate::syscalls::*;
+
+/// ### `proc_getsid()`
+/// Returns the session of a process
+/// Note: This is similar to `getsid` in POSIX.
+///
+/// ## Parameters
+///
+/// * `pid` - The process, or 0 for the calling process
+#[instrument(level = "trace", skip_all, fields(%pid, sid = field::Empty), ret)]
+pub fn proc_getsid<M: MemorySize>(
+    ctx: FunctionEnvMut<'_, WasiEnv>,
+    pid: Pid,
+    ret_sid: WasmPtr<Pid, M>,
+) -> Errno {
+    let env = ctx.data();
+    let process = wasi_try!(process_from_pid(env, pid));
+
+    let sid: Pid = process.sid().raw();
+    Span::current().record("sid", sid);
+
+    let memory = unsafe { env.memory_view(&ctx) };
+    wasi_try_mem!(ret_sid.write(&memory, sid));
+
+    Errno::Success
+}
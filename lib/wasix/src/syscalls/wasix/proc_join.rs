@@ -5,12 +5,13 @@ use wasmer::FromToNativeWasmType;
 use wasmer_wasix_types::wasi::{JoinFlags, JoinStatus, JoinStatusType, JoinStatusUnion, OptionPid};
 
 use super::*;
-use crate::{syscalls::*, WasiProcess};
+use crate::{os::task::process::ChildStatus, syscalls::*, WasiProcess};
 
 #[derive(Serialize, Deserialize)]
 enum JoinStatusResult {
     Nothing,
     ExitNormal(WasiProcessId, ExitCode),
+    Stopped(WasiProcessId, u8),
     Err(Errno),
 }
 
@@ -66,6 +67,20 @@ pub(super) fn proc_join_internal<M: MemorySize + 'static>(
                         },
                     }
                 }
+                JoinStatusResult::Stopped(pid, signal) => {
+                    let option_pid = OptionPid {
+                        tag: OptionTag::Some,
+                        pid: pid.raw() as Pid,
+                    };
+                    pid_ptr.write(&view, option_pid).ok();
+
+                    JoinStatus {
+                        tag: JoinStatusType::Stopped,
+                        u: JoinStatusUnion {
+                            stopped: Signal::try_from(signal).unwrap_or(Signal::Sigstop),
+                        },
+                    }
+                }
                 JoinStatusResult::Err(err) => {
                     ret = err;
                     JoinStatus {
@@ -114,6 +129,31 @@ pub(super) fn proc_join_internal<M: MemorySize + 'static>(
 
     // If the ID is maximum then it means wait for any of the children
     let pid = match option_pid {
+        None if flags.contains(JoinFlags::WAKE_STOPPED) => {
+            let mut process = ctx.data_mut().process.clone();
+
+            // We wait for any process to exit or stop
+            let res = __asyncify_with_deep_sleep::<M, _, _>(ctx, async move {
+                match process.join_any_child_or_stop().await {
+                    Ok((pid, ChildStatus::Exited(exit_code))) => {
+                        tracing::trace!(%pid, %exit_code, "triggered child join");
+                        JoinStatusResult::ExitNormal(pid, exit_code)
+                    }
+                    Ok((pid, ChildStatus::Stopped(signal))) => {
+                        tracing::trace!(%pid, ?signal, "triggered child stop");
+                        JoinStatusResult::Stopped(pid, signal as u8)
+                    }
+                    Err(err) => {
+                        tracing::trace!(%err, "error triggered on child join");
+                        JoinStatusResult::Err(err)
+                    }
+                }
+            })?;
+            return match res {
+                AsyncifyAction::Finish(ctx, result) => ret_result(ctx, result),
+                AsyncifyAction::Unwind => Ok(Errno::Success),
+            };
+        }
         None => {
             let mut process = ctx.data_mut().process.clone();
 
@@ -149,16 +189,21 @@ pub(super) fn proc_join_internal<M: MemorySize + 'static>(
     let pid: WasiProcessId = pid.into();
 
     // Waiting for a process that is an explicit child will join it
-    // meaning it will no longer be a sub-process of the main process
+    // meaning it will no longer be a sub-process of the main process,
+    // unless it is only reported as stopped
+    let parent = ctx.data().process.clone();
+    let wake_stopped = flags.contains(JoinFlags::WAKE_STOPPED);
     let mut process = {
-        let mut inner = ctx.data().process.lock();
+        let mut inner = parent.lock();
         let process = inner
             .children
             .iter()
             .filter(|c| c.pid == pid)
             .map(Clone::clone)
             .next();
-        inner.children.retain(|c| c.pid != pid);
+        if !wake_stopped {
+            inner.children.retain(|c| c.pid != pid);
+        }
         process
     };
 
@@ -181,17 +226,35 @@ pub(super) fn proc_join_internal<M: MemorySize + 'static>(
         if flags.contains(JoinFlags::NON_BLOCKING) {
             if let Some(status) = process.try_join() {
                 let exit_code = status.unwrap_or_else(|_| Errno::Child.into());
+                parent.lock().children.retain(|c| c.pid != pid);
                 ret_result(ctx, JoinStatusResult::ExitNormal(pid, exit_code))
+            } else if let Some(signal) = process.take_stop_report().filter(|_| wake_stopped) {
+                ret_result(ctx, JoinStatusResult::Stopped(pid, signal as u8))
             } else {
                 ret_result(ctx, JoinStatusResult::Nothing)
             }
         } else {
-            // Wait for the process to finish
-            let process2 = process.clone();
+            // Wait for the process to finish (or to stop)
             let res = __asyncify_with_deep_sleep::<M, _, _>(ctx, async move {
-                let exit_code = process.join().await.unwrap_or_else(|_| Errno::Child.into());
-                tracing::trace!(%exit_code, "triggered child join");
-                JoinStatusResult::ExitNormal(pid, exit_code)
+                let join = Box::pin(process.join());
+                let stop = Box::pin(async {
+                    match wake_stopped {
+                        true => process.wait_for_stop().await,
+                        false => std::future::pending().await,
+                    }
+                });
+                match futures::future::select(join, stop).await {
+                    futures::future::Either::Left((res, _)) => {
+                        let exit_code = res.unwrap_or_else(|_| Errno::Child.into());
+                        tracing::trace!(%exit_code, "triggered child join");
+                        parent.lock().children.retain(|c| c.pid != pid);
+                        JoinStatusResult::ExitNormal(pid, exit_code)
+                    }
+                    futures::future::Either::Right((signal, _)) => {
+                        tracing::trace!(?signal, "triggered child stop");
+                        JoinStatusResult::Stopped(pid, signal as u8)
+                    }
+                }
             })?;
             match res {
                 AsyncifyAction::Finish(ctx, result) => ret_result(ctx, result),
@@ -0,0 +1,38 @@
+use super::*;
+use crate::syscalls::*;
+
+/// ### `proc_setpgid()`
+/// Moves a process to another process group of the same session, or makes
+/// it the leader of a new process group
+/// Note: This is similar to `setpgid` in POSIX.
+///
+/// ## Parameters
+///
+/// * `pid` - The calling process or one of its children, 0 for the calling
+///   process
+/// * `pgid` - The process group, 0 to create a group led by `pid`
+#[instrument(level = "trace", skip_all, fields(%pid, %pgid), ret)]
+pub fn proc_setpgid(ctx: FunctionEnvMut<'_, WasiEnv>, pid: Pid, pgid: Pid) -> Errno {
+    let env = ctx.data();
+    let process = wasi_try!(process_from_pid(env, pid));
+
+    if process.pid() != env.process.pid() && !env.process.is_child(process.pid()) {
+        return Errno::Srch;
+    }
+    let sid = env.process.sid();
+    if process.sid() != sid || process.pid() == sid {
+        // Session leaders and processes of other sessions can't move
+        return Errno::Perm;
+    }
+
+    let pgid: WasiProcessId = match pgid {
+        0 => process.pid(),
+        pgid => pgid.into(),
+    };
+    if pgid != process.pid() && !env.control_plane.session_has_group(sid, pgid) {
+        return Errno::Perm;
+    }
+    process.set_pgid(pgid);
+
+    Errno::Success
+}
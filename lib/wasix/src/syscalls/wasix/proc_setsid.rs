@@ -0,0 +1,25 @@
+use super::*;
+use crate::syscalls::*;
+
+/// ### `proc_setsid()`
+/// Creates a new session led by the calling process, which also becomes
+/// the leader of a new process group
+/// Note: This is similar to `setsid` in POSIX.
+///
+/// Output:
+/// - `Pid`
+///     The ID of the new session
+#[instrument(level = "trace", skip_all, fields(sid = field::Empty), ret)]
+pub fn proc_setsid<M: MemorySize>(
+    ctx: FunctionEnvMut<'_, WasiEnv>,
+    ret_sid: WasmPtr<Pid, M>,
+) -> Errno {
+    let env = ctx.data();
+    let sid: Pid = wasi_try!(env.process.setsid()).raw();
+    Span::current().record("sid", sid);
+
+    let memory = unsafe { env.memory_view(&ctx) };
+    wasi_try_mem!(ret_sid.write(&memory, sid));
+
+    Errno::Success
+}
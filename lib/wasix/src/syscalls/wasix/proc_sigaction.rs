@@ -0,0 +1,41 @@
+use super::*;
+use crate::{os::task::signal::SignalSet, syscalls::*};
+
+/// ### `proc_sigaction()`
+/// Changes the action taken by the process when a signal is delivered
+/// Note: This is similar to `sigaction` in POSIX.
+///
+/// ## Parameters
+///
+/// * `sig` - Signal whose action is changed, real-time signals included
+/// * `action` - The new action, or null to leave it unchanged
+/// * `ret_old_action` - Receives the previous action unless it is null
+#[instrument(level = "trace", skip_all, fields(sig), ret)]
+pub fn proc_sigaction<M: MemorySize>(
+    mut ctx: FunctionEnvMut<'_, WasiEnv>,
+    sig: u8,
+    action: WasmPtr<SigActionRaw, M>,
+    ret_old_action: WasmPtr<SigActionRaw, M>,
+) -> Result<Errno, WasiError> {
+    let env = ctx.data();
+    if !SignalSet::is_valid(sig) {
+        return Ok(Errno::Inval);
+    }
+
+    let memory = unsafe { env.memory_view(&ctx) };
+    let old = if action.is_null() {
+        env.process.signal_action(sig)
+    } else {
+        let action = wasi_try_mem_ok!(action.read(&memory));
+        let mut action = wasi_try_ok!(SigAction::try_from(action));
+        action.mask = SignalSet::from_bits(action.mask).catchable().bits();
+        wasi_try_ok!(env.process.set_signal_action(sig, action))
+    };
+    if !ret_old_action.is_null() {
+        wasi_try_mem_ok!(ret_old_action.write(&memory, old.into()));
+    }
+
+    wasi_try_ok!(WasiEnv::process_signals_and_exit(&mut ctx)?);
+
+    Ok(Errno::Success)
+}
@@ -0,0 +1,30 @@
+use super::*;
+use crate::syscalls::*;
+
+/// ### `proc_signal_group()`
+/// Sends a signal to every process of a process group
+/// Note: This is similar to `killpg` in POSIX.
+///
+/// ## Parameters
+///
+/// * `pgid` - The process group, or 0 for the group of the calling process
+/// * `sig` - Signal to send
+#[instrument(level = "trace", skip_all, fields(%pgid, ?sig), ret)]
+pub fn proc_signal_group(
+    mut ctx: FunctionEnvMut<'_, WasiEnv>,
+    pgid: Pid,
+    sig: Signal,
+) -> Result<Errno, WasiError> {
+    let env = ctx.data();
+    let pgid: WasiProcessId = match pgid {
+        0 => env.process.pgid(),
+        pgid => pgid.into(),
+    };
+    if !env.control_plane.signal_process_group(pgid, sig) {
+        return Ok(Errno::Srch);
+    }
+
+    wasi_try_ok!(WasiEnv::process_signals_and_exit(&mut ctx)?);
+
+    Ok(Errno::Success)
+}
@@ -0,0 +1,36 @@
+use super::*;
+use crate::{os::task::signal::SignalSet, syscalls::*};
+
+/// ### `proc_sigqueue()`
+/// Sends a signal to a process, unlike `proc_signal` this accepts
+/// real-time signals which are queued rather than merged with an
+/// instance that is already pending
+/// Note: This is similar to `sigqueue` in POSIX.
+///
+/// ## Parameters
+///
+/// * `pid` - Process that receives the signal
+/// * `sig` - Signal number, up to `SIGRTMAX`
+#[instrument(level = "trace", skip_all, fields(%pid, sig), ret)]
+pub fn proc_sigqueue(
+    mut ctx: FunctionEnvMut<'_, WasiEnv>,
+    pid: Pid,
+    sig: u8,
+) -> Result<Errno, WasiError> {
+    if !SignalSet::is_valid(sig) {
+        return Ok(Errno::Inval);
+    }
+
+    let process = {
+        let pid: WasiProcessId = pid.into();
+        ctx.data().control_plane.get_process(pid)
+    };
+    let Some(process) = process else {
+        return Ok(Errno::Srch);
+    };
+    wasi_try_ok!(process.queue_signal(sig));
+
+    wasi_try_ok!(WasiEnv::process_signals_and_exit(&mut ctx)?);
+
+    Ok(Errno::Success)
+}
@@ -0,0 +1,34 @@
+use super::*;
+use crate::{os::tty::TtyForeground, syscalls::*};
+
+/// ### `pty_foreground_set()`
+/// Changes the process group that receives the job control signals
+/// (`SIGINT`, `SIGQUIT`, `SIGTSTP`, `SIGWINCH` and `SIGHUP`) raised
+/// by a pseudo-terminal
+///
+/// ## Parameters
+///
+/// * `fd` - Either end of the pseudo-terminal
+/// * `pgid` - Process group that is brought to the foreground
+#[instrument(level = "trace", skip_all, fields(%fd, %pgid), ret)]
+pub fn pty_foreground_set(ctx: FunctionEnvMut<'_, WasiEnv>, fd: WasiFd, pgid: Pid) -> Errno {
+    let env = ctx.data();
+    let pty = wasi_try!(pty_from_fd(env, fd));
+
+    let foreground = wasi_try!(foreground_for_group(env, pgid));
+    pty.set_foreground(Some(foreground));
+
+    Errno::Success
+}
+
+/// Checks that a process group belongs to the session of the caller
+pub(crate) fn foreground_for_group(env: &WasiEnv, pgid: Pid) -> Result<TtyForeground, Errno> {
+    let pgid: WasiProcessId = pgid.into();
+    if !env.control_plane.session_has_group(env.process.sid(), pgid) {
+        return Err(Errno::Perm);
+    }
+    Ok(TtyForeground {
+        pgid,
+        control_plane: env.control_plane.handle(),
+    })
+}
@@ -0,0 +1,68 @@
+use super::*;
+use crate::{
+    os::tty::pty::{Pty, PtyMaster, PtySlave},
+    syscalls::*,
+};
+
+/// ### `pty_get()`
+/// Retrieves the terminal modes and window size of a pseudo-terminal
+///
+/// ## Parameters
+///
+/// * `fd` - Either end of the pseudo-terminal
+#[instrument(level = "trace", skip_all, fields(%fd), ret)]
+pub fn pty_get<M: MemorySize>(
+    ctx: FunctionEnvMut<'_, WasiEnv>,
+    fd: WasiFd,
+    tty_state: WasmPtr<Tty, M>,
+) -> Errno {
+    let env = ctx.data();
+    let pty = wasi_try!(pty_from_fd(env, fd));
+
+    let state = pty.state();
+    let state = Tty {
+        cols: state.cols,
+        rows: state.rows,
+        width: state.width,
+        height: state.height,
+        stdin_tty: state.stdin_tty,
+        stdout_tty: state.stdout_tty,
+        stderr_tty: state.stderr_tty,
+        echo: state.echo,
+        line_buffered: state.line_buffered,
+    };
+
+    let memory = unsafe { env.memory_view(&ctx) };
+    wasi_try_mem!(tty_state.write(&memory, state));
+
+    Errno::Success
+}
+
+/// Returns the pseudo-terminal that is behind a file descriptor
+pub(crate) fn pty_from_fd(env: &WasiEnv, fd: WasiFd) -> Result<Pty, Errno> {
+    pty_end_from_fd(env, fd).map(|(pty, _)| pty)
+}
+
+/// Returns the pseudo-terminal that is behind a file descriptor and true if
+/// the descriptor is its slave end
+pub(crate) fn pty_end_from_fd(env: &WasiEnv, fd: WasiFd) -> Result<(Pty, bool), Errno> {
+    let fd_entry = env.state.fs.get_fd(fd)?;
+    let guard = fd_entry.inode.read();
+    let Kind::File {
+        handle: Some(handle),
+        ..
+    } = guard.deref()
+    else {
+        return Err(Errno::Notty);
+    };
+
+    let handle = handle.read().unwrap();
+    let file = handle.upcast_any_ref();
+    if let Some(master) = file.downcast_ref::<PtyMaster>() {
+        Ok((master.pty().clone(), false))
+    } else if let Some(slave) = file.downcast_ref::<PtySlave>() {
+        Ok((slave.pty().clone(), true))
+    } else {
+        Err(Errno::Notty)
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::RwLock;
+
+use super::*;
+use crate::{
+    os::tty::{pty::Pty, TtyForeground},
+    syscalls::*,
+};
+
+/// ### `pty_open()`
+/// Allocates a new pseudo-terminal pair, the process group of the calling
+/// process becomes the foreground process group of the terminal
+/// Output:
+/// - `Fd`
+///     File handle of the master end of the pseudo-terminal
+/// - `Fd`
+///     File handle of the slave end of the pseudo-terminal
+#[instrument(level = "trace", skip_all, fields(master_fd = field::Empty, slave_fd = field::Empty), ret)]
+pub fn pty_open<M: MemorySize>(
+    mut ctx: FunctionEnvMut<'_, WasiEnv>,
+    ro_master_fd: WasmPtr<WasiFd, M>,
+    ro_slave_fd: WasmPtr<WasiFd, M>,
+) -> Result<Errno, WasiError> {
+    let (master_fd, slave_fd) = wasi_try_ok!(pty_open_internal(&mut ctx));
+
+    let env = ctx.data();
+    let memory = unsafe { env.memory_view(&ctx) };
+
+    Span::current()
+        .record("master_fd", master_fd)
+        .record("slave_fd", slave_fd);
+
+    wasi_try_mem_ok!(ro_master_fd.write(&memory, master_fd));
+    wasi_try_mem_ok!(ro_slave_fd.write(&memory, slave_fd));
+
+    Ok(Errno::Success)
+}
+
+pub fn pty_open_internal(ctx: &mut FunctionEnvMut<'_, WasiEnv>) -> Result<(WasiFd, WasiFd), Errno> {
+    let env = ctx.data();
+    let (_, state, inodes) = unsafe { env.get_memory_and_wasi_state_and_inodes(&ctx, 0) };
+
+    let (master, slave) = Pty::open();
+    let id = master.pty().id();
+    master
+        .pty()
+        .set_foreground(Some(TtyForeground::from_process(&env.process)));
+
+    let stat = Filestat {
+        st_filetype: Filetype::CharacterDevice,
+        ..Filestat::default()
+    };
+    let master_path = format!("/dev/ptm/{id}");
+    let master_inode = state.fs.create_inode_with_stat(
+        inodes,
+        Kind::File {
+            handle: Some(Arc::new(RwLock::new(Box::new(master)))),
+            path: master_path.clone().into(),
+            fd: None,
+        },
+        false,
+        master_path.into(),
+        stat,
+    );
+    let slave_path = format!("/dev/pts/{id}");
+    let slave_inode = state.fs.create_inode_with_stat(
+        inodes,
+        Kind::File {
+            handle: Some(Arc::new(RwLock::new(Box::new(slave)))),
+            path: slave_path.clone().into(),
+            fd: None,
+        },
+        false,
+        slave_path.into(),
+        stat,
+    );
+
+    let rights = Rights::FD_READ
+        | Rights::FD_WRITE
+        | Rights::FD_SYNC
+        | Rights::FD_DATASYNC
+        | Rights::POLL_FD_READWRITE
+        | Rights::FD_FDSTAT_SET_FLAGS
+        | Rights::FD_FILESTAT_GET;
+
+    let master_fd = state.fs.create_fd(
+        rights,
+        rights,
+        Fdflags::empty(),
+        Fdflagsext::empty(),
+        0,
+        master_inode,
+    )?;
+    let slave_fd = state.fs.create_fd(
+        rights,
+        rights,
+        Fdflags::empty(),
+        Fdflagsext::empty(),
+        0,
+        slave_inode,
+    )?;
+
+    Ok((master_fd, slave_fd))
+}
@@ -0,0 +1,46 @@
+use super::*;
+use crate::{syscalls::*, WasiTtyState};
+
+/// ### `pty_set()`
+/// Updates the terminal modes and window size of a pseudo-terminal, the
+/// foreground process receives a `SIGWINCH` when the window was resized
+///
+/// ## Parameters
+///
+/// * `fd` - Either end of the pseudo-terminal
+#[instrument(level = "trace", skip_all, fields(%fd), ret)]
+pub fn pty_set<M: MemorySize>(
+    mut ctx: FunctionEnvMut<'_, WasiEnv>,
+    fd: WasiFd,
+    tty_state: WasmPtr<Tty, M>,
+) -> Result<Errno, WasiError> {
+    let env = ctx.data();
+    let pty = wasi_try_ok!(pty_from_fd(env, fd));
+
+    let memory = unsafe { env.memory_view(&ctx) };
+    let state = wasi_try_mem_ok!(tty_state.read(&memory));
+    let line_feeds = pty.state().line_feeds;
+    debug!(
+        echo = state.echo,
+        line_buffered = state.line_buffered,
+        cols = state.cols,
+        rows = state.rows
+    );
+
+    pty.set_state(WasiTtyState {
+        cols: state.cols,
+        rows: state.rows,
+        width: state.width,
+        height: state.height,
+        stdin_tty: state.stdin_tty,
+        stdout_tty: state.stdout_tty,
+        stderr_tty: state.stderr_tty,
+        echo: state.echo,
+        line_buffered: state.line_buffered,
+        line_feeds,
+    });
+
+    wasi_try_ok!(WasiEnv::process_signals_and_exit(&mut ctx)?);
+
+    Ok(Errno::Success)
+}
@@ -0,0 +1,70 @@
+use super::*;
+use crate::{
+    os::shm::{SharedMemoryFile, SharedMemoryMapping, SharedMemoryObject},
+    syscalls::*,
+};
+
+/// ### `shm_map()`
+/// Maps a region of a shared memory object over a range of the linear
+/// memory, replacing whatever was there. Writes made by any process that
+/// maps the same region are immediately visible to all the others.
+/// Note: This is similar to `mmap` with `MAP_SHARED | MAP_FIXED` in POSIX.
+///
+/// ## Parameters
+///
+/// * `fd` - File handle returned by `shm_open`
+/// * `offset` - Offset into the object, must be a multiple of the wasm page size
+/// * `addr` - Start of the range, must be a multiple of the wasm page size
+/// * `len` - Length of the range, must be a multiple of the wasm page size
+#[instrument(level = "trace", skip_all, fields(%fd, %offset, addr = field::Empty, len = field::Empty), ret)]
+pub fn shm_map<M: MemorySize>(
+    mut ctx: FunctionEnvMut<'_, WasiEnv>,
+    fd: WasiFd,
+    offset: Filesize,
+    addr: M::Offset,
+    len: M::Offset,
+) -> Result<Errno, WasiError> {
+    wasi_try_ok!(WasiEnv::process_signals_and_exit(&mut ctx)?);
+
+    let addr: u64 = addr.into();
+    let len: u64 = len.into();
+    Span::current().record("addr", addr).record("len", len);
+
+    let env = ctx.data();
+    let object = wasi_try_ok!(shm_object_from_fd(env, fd));
+    let mapping = SharedMemoryMapping {
+        addr,
+        len,
+        offset,
+        object,
+    };
+
+    let memory = unsafe { env.memory() };
+    wasi_try_ok!(unsafe { mapping.apply(memory, &ctx) });
+    env.process.add_shared_memory_mapping(mapping);
+
+    Ok(Errno::Success)
+}
+
+/// Returns the shared memory object that is behind a file descriptor
+pub(crate) fn shm_object_from_fd(
+    env: &WasiEnv,
+    fd: WasiFd,
+) -> Result<Arc<SharedMemoryObject>, Errno> {
+    let fd_entry = env.state.fs.get_fd(fd)?;
+    let guard = fd_entry.inode.read();
+    let Kind::File {
+        handle: Some(handle),
+        ..
+    } = guard.deref()
+    else {
+        return Err(Errno::Nodev);
+    };
+
+    let handle = handle.read().unwrap();
+    handle
+        .upcast_any_ref()
+        .downcast_ref::<SharedMemoryFile>()
+        .map(|file| file.object().clone())
+        .ok_or(Errno::Nodev)
+}
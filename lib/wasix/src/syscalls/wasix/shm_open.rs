@@ -0,0 +1,87 @@
+use std::sync::RwLock;
+
+use super::*;
+use crate::{os::shm::SharedMemoryFile, syscalls::*};
+
+/// ### `shm_open()`
+/// Opens a named shared memory object, the object starts out empty and is
+/// sized with `fd_filestat_set_size` before it is mapped with `shm_map`
+/// Note: This is similar to `shm_open` in POSIX.
+///
+/// ## Parameters
+///
+/// * `name` - Name of the object, a single path component that may start with `/`
+/// * `o_flags` - Only `CREATE`, `EXCL` and `TRUNC` are supported
+///
+/// Output:
+/// - `Fd`
+///     File handle that refers to the shared memory object
+#[instrument(level = "trace", skip_all, fields(name = field::Empty, fd = field::Empty), ret)]
+pub fn shm_open<M: MemorySize>(
+    ctx: FunctionEnvMut<'_, WasiEnv>,
+    name: WasmPtr<u8, M>,
+    name_len: M::Offset,
+    o_flags: Oflags,
+    ret_fd: WasmPtr<WasiFd, M>,
+) -> Errno {
+    let env = ctx.data();
+    let (memory, state, inodes) = unsafe { env.get_memory_and_wasi_state_and_inodes(&ctx, 0) };
+    let name = unsafe { get_input_str!(&memory, name, name_len) };
+    Span::current().record("name", name.as_str());
+
+    if o_flags.contains(Oflags::DIRECTORY) {
+        return Errno::Inval;
+    }
+    let object = wasi_try!(env.control_plane.shared_memory().open(
+        &name,
+        o_flags.contains(Oflags::CREATE),
+        o_flags.contains(Oflags::EXCL),
+    ));
+    if o_flags.contains(Oflags::TRUNC) {
+        wasi_try!(object.set_len(0).map_err(fs_error_into_wasi_err));
+    }
+
+    let stat = Filestat {
+        st_filetype: Filetype::RegularFile,
+        st_size: object.size(),
+        ..Filestat::default()
+    };
+    let path = format!("/dev/shm/{}", object.name());
+    let inode = state.fs.create_inode_with_stat(
+        inodes,
+        Kind::File {
+            handle: Some(Arc::new(RwLock::new(Box::new(SharedMemoryFile::new(
+                object,
+            ))))),
+            path: path.clone().into(),
+            fd: None,
+        },
+        false,
+        path.into(),
+        stat,
+    );
+
+    let rights = Rights::FD_READ
+        | Rights::FD_WRITE
+        | Rights::FD_SEEK
+        | Rights::FD_TELL
+        | Rights::FD_SYNC
+        | Rights::FD_DATASYNC
+        | Rights::POLL_FD_READWRITE
+        | Rights::FD_FDSTAT_SET_FLAGS
+        | Rights::FD_FILESTAT_GET
+        | Rights::FD_FILESTAT_SET_SIZE;
+    let fd = wasi_try!(state.fs.create_fd(
+        rights,
+        rights,
+        Fdflags::empty(),
+        Fdflagsext::empty(),
+        0,
+        inode,
+    ));
+    Span::current().record("fd", fd);
+
+    wasi_try_mem!(ret_fd.write(&memory, fd));
+
+    Errno::Success
+}
@@ -0,0 +1,26 @@
+use super::*;
+use crate::syscalls::*;
+
+/// ### `shm_unlink()`
+/// Removes the name of a shared memory object, the object itself lives on
+/// until every file handle and mapping that refers to it is gone
+/// Note: This is similar to `shm_unlink` in POSIX.
+///
+/// ## Parameters
+///
+/// * `name` - Name of the object
+#[instrument(level = "trace", skip_all, fields(name = field::Empty), ret)]
+pub fn shm_unlink<M: MemorySize>(
+    ctx: FunctionEnvMut<'_, WasiEnv>,
+    name: WasmPtr<u8, M>,
+    name_len: M::Offset,
+) -> Errno {
+    let env = ctx.data();
+    let memory = unsafe { env.memory_view(&ctx) };
+    let name = unsafe { get_input_str!(&memory, name, name_len) };
+    Span::current().record("name", name.as_str());
+
+    wasi_try!(env.control_plane.shared_memory().unlink(&name));
+
+    Errno::Success
+}
@@ -0,0 +1,29 @@
+use super::*;
+use crate::{os::shm::unmap_range, syscalls::*};
+
+/// ### `shm_unmap()`
+/// Removes the shared memory mappings from a range of the linear memory,
+/// the range stays accessible but is filled with zeros
+/// Note: This is similar to `munmap` in POSIX.
+///
+/// ## Parameters
+///
+/// * `addr` - Start of the range, must be a multiple of the wasm page size
+/// * `len` - Length of the range, must be a multiple of the wasm page size
+#[instrument(level = "trace", skip_all, fields(addr = field::Empty, len = field::Empty), ret)]
+pub fn shm_unmap<M: MemorySize>(
+    ctx: FunctionEnvMut<'_, WasiEnv>,
+    addr: M::Offset,
+    len: M::Offset,
+) -> Errno {
+    let addr: u64 = addr.into();
+    let len: u64 = len.into();
+    Span::current().record("addr", addr).record("len", len);
+
+    let env = ctx.data();
+    let memory = unsafe { env.memory() };
+    wasi_try!(unsafe { unmap_range(memory, &ctx, addr, len) });
+    env.process.remove_shared_memory_mappings(addr, len);
+
+    Errno::Success
+}
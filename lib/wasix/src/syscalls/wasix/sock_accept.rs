@@ -144,11 +144,7 @@ pub(crate) fn sock_accept_internal(
     ));
 
     let kind = Kind::Socket {
-        socket: InodeSocket::new(InodeSocketKind::TcpStream {
-            socket: child,
-            write_timeout: None,
-            read_timeout: None,
-        }),
+        socket: InodeSocket::new(child),
     };
     let inode = state
         .fs
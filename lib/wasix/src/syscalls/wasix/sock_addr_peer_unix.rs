@@ -0,0 +1,40 @@
+use super::*;
+use crate::syscalls::*;
+
+/// ### `sock_addr_peer_unix()`
+/// Returns the path of the socket that a unix socket is connected to.
+///
+/// Note: This is similar to `getpeername` in POSIX using PF_UNIX
+///
+/// The path is empty when the other end isn't bound.
+///
+/// ## Parameters
+///
+/// * `fd` - Socket that is connected
+/// * `ret_path` - Buffer that receives the path
+/// * `ret_path_len` - Size of the buffer on input, length of the path on output
+#[instrument(level = "trace", skip_all, fields(%sock, path = field::Empty), ret)]
+pub fn sock_addr_peer_unix<M: MemorySize>(
+    mut ctx: FunctionEnvMut<'_, WasiEnv>,
+    sock: WasiFd,
+    ret_path: WasmPtr<u8, M>,
+    ret_path_len: WasmPtr<M::Offset, M>,
+) -> Errno {
+    let path = wasi_try!(__sock_actor(
+        &mut ctx,
+        sock,
+        Rights::empty(),
+        |socket, _| socket.addr_peer_unix()
+    ));
+
+    Span::current().record("path", format!("{path:?}"));
+
+    let memory = unsafe { ctx.data().memory_view(&ctx) };
+    wasi_try!(crate::net::write_unix_path(
+        &memory,
+        ret_path,
+        ret_path_len,
+        path.as_deref()
+    ));
+    Errno::Success
+}
@@ -0,0 +1,56 @@
+use super::*;
+use crate::{net::unix::resolve_path, syscalls::*};
+
+/// ### `sock_bind_unix()`
+/// Bind a unix socket to a path
+/// Note: This is similar to `bind` in POSIX using PF_UNIX
+///
+/// The path must not exist yet, binding the socket creates it.
+///
+/// ## Parameters
+///
+/// * `fd` - File descriptor of the socket to be bind
+/// * `path` - Path to bind the socket to
+#[instrument(level = "trace", skip_all, fields(%sock, path = field::Empty), ret)]
+pub fn sock_bind_unix<M: MemorySize>(
+    mut ctx: FunctionEnvMut<'_, WasiEnv>,
+    sock: WasiFd,
+    path: WasmPtr<u8, M>,
+    path_len: M::Offset,
+) -> Result<Errno, WasiError> {
+    let env = ctx.data();
+    let (memory, state) = unsafe { env.get_memory_and_wasi_state(&ctx, 0) };
+    let path = unsafe { get_input_str_ok!(&memory, path, path_len) };
+    let path = {
+        let current_dir = state.fs.current_dir.lock().unwrap();
+        wasi_try_ok!(resolve_path(current_dir.as_str(), &path))
+    };
+    Span::current().record("path", path.as_str());
+
+    // The socket shows up in the file system (and can't be bound twice)
+    wasi_try_ok!(state
+        .fs_new_open_options()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .map(|_| ())
+        .map_err(|err| match err {
+            FsError::AlreadyExists => Errno::Addrinuse,
+            err => fs_error_into_wasi_err(err),
+        }));
+
+    let namespace = env.control_plane.unix_sockets().clone();
+    let bind_path = path.clone();
+    let res = __sock_upgrade(
+        &mut ctx,
+        sock,
+        Rights::SOCK_BIND,
+        move |socket, _| async move { socket.bind_unix(&namespace, bind_path) },
+    );
+    if let Err(err) = res {
+        ctx.data().state().fs_remove_file(&path).ok();
+        return Ok(err);
+    }
+
+    Ok(Errno::Success)
+}
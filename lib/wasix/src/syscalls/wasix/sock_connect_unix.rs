@@ -0,0 +1,57 @@
+use super::*;
+use crate::{net::unix::resolve_path, syscalls::*};
+
+/// ### `sock_connect_unix()`
+/// Connects a unix socket to the socket bound to a path.
+/// Note: This is similar to `connect` in POSIX using PF_UNIX
+///
+/// Stream sockets are connected to a listening socket, datagram sockets
+/// remember the path as the default destination of `sock_send`.
+///
+/// ## Parameters
+///
+/// * `fd` - Socket descriptor
+/// * `path` - Path of the socket to connect to
+#[instrument(level = "trace", skip_all, fields(%sock, path = field::Empty), ret)]
+pub fn sock_connect_unix<M: MemorySize>(
+    mut ctx: FunctionEnvMut<'_, WasiEnv>,
+    sock: WasiFd,
+    path: WasmPtr<u8, M>,
+    path_len: M::Offset,
+) -> Result<Errno, WasiError> {
+    wasi_try_ok!(WasiEnv::process_signals_and_exit(&mut ctx)?);
+
+    let env = ctx.data();
+    let (memory, state) = unsafe { env.get_memory_and_wasi_state(&ctx, 0) };
+    let path = unsafe { get_input_str_ok!(&memory, path, path_len) };
+    let path = {
+        let current_dir = state.fs.current_dir.lock().unwrap();
+        wasi_try_ok!(resolve_path(current_dir.as_str(), &path))
+    };
+    Span::current().record("path", path.as_str());
+
+    let namespace = env.control_plane.unix_sockets().clone();
+    let connect_path = path.clone();
+    let res = __sock_upgrade(
+        &mut ctx,
+        sock,
+        Rights::SOCK_CONNECT,
+        move |socket, _| async move { socket.connect_unix(&namespace, connect_path) },
+    );
+    match res {
+        Ok(()) => Ok(Errno::Success),
+        // Nothing is listening, tell apart a stale socket from a missing one
+        Err(Errno::Connrefused)
+            if ctx
+                .data()
+                .state()
+                .fs
+                .root_fs
+                .metadata(Path::new(&path))
+                .is_err() =>
+        {
+            Ok(Errno::Noent)
+        }
+        Err(err) => Ok(err),
+    }
+}
@@ -2,7 +2,10 @@ use virtual_fs::Pipe;
 
 use super::*;
 use crate::{
-    net::socket::{self, SocketProperties},
+    net::{
+        socket::{self, SocketProperties},
+        unix::UnixStream,
+    },
     syscalls::*,
 };
 
@@ -54,6 +57,25 @@ pub fn sock_pair<M: MemorySize>(
         _ => {}
     }
 
+    // Unix stream sockets are connected for real so that they can pass
+    // file descriptors, the journal replays them as pipes
+    if af == Addressfamily::Unix && ty == Socktype::Stream {
+        let (fd1, fd2) = wasi_try_ok!(sock_pair_unix_internal(&mut ctx));
+
+        #[cfg(feature = "journal")]
+        if ctx.data().enable_journal {
+            JournalEffector::save_sock_pair(&mut ctx, fd1, fd2).map_err(|err| {
+                tracing::error!("failed to save sock_pair event - {}", err);
+                WasiError::Exit(ExitCode::from(Errno::Fault))
+            })?;
+        }
+
+        let memory = unsafe { ctx.data().memory_view(&ctx) };
+        wasi_try_mem_ok!(ro_sock1.write(&memory, fd1));
+        wasi_try_mem_ok!(ro_sock2.write(&memory, fd2));
+        return Ok(Errno::Success);
+    }
+
     // FIXME: currently, socket properties are ignored outright, since they
     // make no sense for the underlying pipe
     let (fd1, fd2) = wasi_try_ok!(sock_pair_internal(&mut ctx, None, None));
@@ -148,3 +170,35 @@ pub(crate) fn sock_pair_internal(
 
     Ok((fd1, fd2))
 }
+
+fn sock_pair_unix_internal(
+    ctx: &mut FunctionEnvMut<'_, WasiEnv>,
+) -> Result<(WasiFd, WasiFd), Errno> {
+    let env = ctx.data();
+    let (_, state, inodes) = unsafe { env.get_memory_and_wasi_state_and_inodes(&ctx, 0) };
+    let (end1, end2) = UnixStream::pair(None, None);
+
+    let rights = Rights::all_socket();
+    let mut fds = [0; 2];
+    for (fd, end) in fds.iter_mut().zip([end1, end2]) {
+        let kind = Kind::Socket {
+            socket: InodeSocket::new(InodeSocketKind::UnixStream(end)),
+        };
+        let inode =
+            state
+                .fs
+                .create_inode_with_default_stat(inodes, kind, false, "socketpair".into());
+        *fd = state.fs.create_fd(
+            rights,
+            rights,
+            Fdflags::empty(),
+            Fdflagsext::empty(),
+            0,
+            inode,
+        )?;
+    }
+    Span::current().record("sock1", fds[0]);
+    Span::current().record("sock2", fds[1]);
+
+    Ok((fds[0], fds[1]))
+}
@@ -0,0 +1,64 @@
+use super::*;
+use crate::syscalls::*;
+
+/// ### `sock_recv_fds()`
+/// Receives the file descriptors passed over a unix socket.
+/// Note: This is similar to `recvmsg` with `SCM_RIGHTS` in POSIX.
+///
+/// On a stream socket this takes the next batch of descriptors that was
+/// sent, on a datagram socket the ones that came with the last datagram
+/// that was received. Descriptors that don't fit in the buffer stay queued
+/// and are returned by the next call.
+///
+/// ## Parameters
+///
+/// * `fd` - Socket descriptor
+/// * `fds` - Buffer that receives the new descriptors
+///
+/// ## Return
+///
+/// Number of descriptors stored in `fds`
+#[instrument(level = "trace", skip_all, fields(%sock, nfds = field::Empty), ret)]
+pub fn sock_recv_fds<M: MemorySize>(
+    mut ctx: FunctionEnvMut<'_, WasiEnv>,
+    sock: WasiFd,
+    fds: WasmPtr<WasiFd, M>,
+    fds_len: M::Offset,
+    ret_nfds: WasmPtr<M::Offset, M>,
+) -> Errno {
+    // The buffers are checked before any descriptor is taken off the socket,
+    // as descriptors that can't be stored would be lost
+    let capacity = {
+        let env = ctx.data();
+        let memory = unsafe { env.memory_view(&ctx) };
+        let fds = wasi_try_mem!(fds.slice(&memory, fds_len));
+        wasi_try_mem!(ret_nfds.write(&memory, M::ZERO));
+        fds.len() as usize
+    };
+
+    let received = wasi_try!(__sock_actor(
+        &mut ctx,
+        sock,
+        Rights::SOCK_RECV,
+        |socket, _| {
+            let mut received = socket.recv_fds()?;
+            if received.len() > capacity {
+                socket.unrecv_fds(received.split_off(capacity));
+            }
+            Ok(received)
+        }
+    ));
+    Span::current().record("nfds", received.len());
+
+    let env = ctx.data();
+    let (memory, state) = unsafe { env.get_memory_and_wasi_state(&ctx, 0) };
+    let nfds: M::Offset = wasi_try!(received.len().try_into().map_err(|_| Errno::Overflow));
+    let installed = received
+        .into_iter()
+        .map(|fd| state.fs.install_fd(fd))
+        .collect::<Vec<_>>();
+    let fds = wasi_try_mem!(fds.slice(&memory, nfds));
+    wasi_try_mem!(fds.write_slice(&installed));
+    wasi_try_mem!(ret_nfds.write(&memory, nfds));
+    Errno::Success
+}
@@ -0,0 +1,47 @@
+use super::*;
+use crate::{net::unix::MAX_PASSED_FDS, syscalls::*};
+
+/// ### `sock_send_fds()`
+/// Passes file descriptors to the process at the other end of a unix socket.
+/// Note: This is similar to `sendmsg` with `SCM_RIGHTS` in POSIX.
+///
+/// The descriptors are duplicated straight away, so they can be closed once
+/// this returns. On a datagram socket they go out with the next datagram.
+///
+/// ## Parameters
+///
+/// * `fd` - Socket descriptor
+/// * `fds` - Descriptors to pass, at most 253
+#[instrument(level = "trace", skip_all, fields(%sock, nfds = field::Empty), ret)]
+pub fn sock_send_fds<M: MemorySize>(
+    mut ctx: FunctionEnvMut<'_, WasiEnv>,
+    sock: WasiFd,
+    fds: WasmPtr<WasiFd, M>,
+    fds_len: M::Offset,
+) -> Errno {
+    let env = ctx.data();
+    let (memory, state) = unsafe { env.get_memory_and_wasi_state(&ctx, 0) };
+    let fds_len: u64 = fds_len.into();
+    if fds_len > MAX_PASSED_FDS as u64 {
+        return Errno::Inval;
+    }
+    Span::current().record("nfds", fds_len);
+
+    let fds = wasi_try_mem!(fds.slice(
+        &memory,
+        wasi_try!(fds_len.try_into().map_err(|_| Errno::Overflow))
+    ));
+    let fds = wasi_try_mem!(fds.read_to_vec());
+    let fds = wasi_try!(fds
+        .into_iter()
+        .map(|fd| state.fs.get_fd(fd))
+        .collect::<Result<Vec<_>, Errno>>());
+
+    wasi_try!(__sock_actor(
+        &mut ctx,
+        sock,
+        Rights::SOCK_SEND,
+        |socket, _| socket.send_fds(fds)
+    ));
+    Errno::Success
+}
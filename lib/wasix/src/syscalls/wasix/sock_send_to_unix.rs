@@ -0,0 +1,63 @@
+use super::*;
+use crate::{net::unix::resolve_path, syscalls::*};
+
+/// ### `sock_send_to_unix()`
+/// Send a datagram on a unix socket to the socket bound to a path.
+/// Note: This is similar to `sendto` in POSIX using PF_UNIX, though it also
+/// supports writing the data from multiple buffers in the manner of `writev`.
+///
+/// ## Parameters
+///
+/// * `si_data` - List of scatter/gather vectors that make up the datagram
+/// * `si_flags` - Message flags.
+/// * `path` - Path of the socket to send the datagram to
+///
+/// ## Return
+///
+/// Number of bytes transmitted.
+#[instrument(level = "trace", skip_all, fields(%sock, path = field::Empty, nsent = field::Empty), ret)]
+pub fn sock_send_to_unix<M: MemorySize>(
+    mut ctx: FunctionEnvMut<'_, WasiEnv>,
+    sock: WasiFd,
+    si_data: WasmPtr<__wasi_ciovec_t<M>, M>,
+    si_data_len: M::Offset,
+    _si_flags: SiFlags,
+    path: WasmPtr<u8, M>,
+    path_len: M::Offset,
+    ret_data_len: WasmPtr<M::Offset, M>,
+) -> Result<Errno, WasiError> {
+    wasi_try_ok!(WasiEnv::process_signals_and_exit(&mut ctx)?);
+
+    let env = ctx.data();
+    let (memory, state) = unsafe { env.get_memory_and_wasi_state(&ctx, 0) };
+    let path = unsafe { get_input_str_ok!(&memory, path, path_len) };
+    let path = {
+        let current_dir = state.fs.current_dir.lock().unwrap();
+        wasi_try_ok!(resolve_path(current_dir.as_str(), &path))
+    };
+    Span::current().record("path", path.as_str());
+
+    // A datagram is sent as a whole
+    let iovs_arr = wasi_try_mem_ok!(si_data.slice(&memory, si_data_len));
+    let mut data = Vec::new();
+    for iovs in iovs_arr.iter() {
+        let iovs = wasi_try_mem_ok!(iovs.read());
+        let buf = wasi_try_mem_ok!(WasmPtr::<u8, M>::new(iovs.buf).slice(&memory, iovs.buf_len));
+        data.extend(wasi_try_mem_ok!(buf.read_to_vec()));
+    }
+
+    let bytes_written = wasi_try_ok!(__sock_actor(
+        &mut ctx,
+        sock,
+        Rights::SOCK_SEND_TO,
+        move |socket, _| socket.send_to_unix(&data, &path)
+    ));
+    Span::current().record("nsent", bytes_written);
+
+    let memory = unsafe { ctx.data().memory_view(&ctx) };
+    let bytes_written: M::Offset =
+        wasi_try_ok!(bytes_written.try_into().map_err(|_| Errno::Overflow));
+    wasi_try_mem_ok!(ret_data_len.write(&memory, bytes_written));
+
+    Ok(Errno::Success)
+}
@@ -0,0 +1,43 @@
+use super::*;
+use crate::{os::task::signal::SignalSet, syscalls::*};
+
+/// ### `thread_sigmask()`
+/// Examines and changes the signals blocked by the current thread, blocked
+/// signals stay pending until they are unblocked
+/// Note: This is similar to `pthread_sigmask` in POSIX.
+///
+/// ## Parameters
+///
+/// * `how` - Whether the signals are blocked, unblocked or replace the mask
+/// * `set` - The signals to change, or null to leave the mask unchanged
+/// * `ret_old_set` - Receives the previous mask unless it is null
+#[instrument(level = "trace", skip_all, fields(?how), ret)]
+pub fn thread_sigmask<M: MemorySize>(
+    mut ctx: FunctionEnvMut<'_, WasiEnv>,
+    how: SigmaskHow,
+    set: WasmPtr<Sigset, M>,
+    ret_old_set: WasmPtr<Sigset, M>,
+) -> Result<Errno, WasiError> {
+    let env = ctx.data();
+    let memory = unsafe { env.memory_view(&ctx) };
+
+    let old = env.thread.signal_mask();
+    if !set.is_null() {
+        let set = SignalSet::from_bits(wasi_try_mem_ok!(set.read(&memory)));
+        let mask = match how {
+            SigmaskHow::Block => old.union(set),
+            SigmaskHow::Unblock => old.difference(set),
+            SigmaskHow::Setmask => set,
+            SigmaskHow::Unknown => return Ok(Errno::Inval),
+        };
+        env.thread.set_signal_mask(mask);
+    }
+    if !ret_old_set.is_null() {
+        wasi_try_mem_ok!(ret_old_set.write(&memory, old.bits()));
+    }
+
+    // Signals that were just unblocked are delivered before returning
+    wasi_try_ok!(WasiEnv::process_signals_and_exit(&mut ctx)?);
+
+    Ok(Errno::Success)
+}
@@ -0,0 +1,23 @@
+use super::*;
+use crate::syscalls::*;
+
+/// ### `thread_sigpending()`
+/// Returns the signals that are pending on the current thread because
+/// they are blocked
+/// Note: This is similar to `sigpending` in POSIX.
+#[instrument(level = "trace", skip_all, ret)]
+pub fn thread_sigpending<M: MemorySize>(
+    ctx: FunctionEnvMut<'_, WasiEnv>,
+    ret_set: WasmPtr<Sigset, M>,
+) -> Errno {
+    let env = ctx.data();
+    let pending = env
+        .thread
+        .pending_signals()
+        .intersection(env.thread.signal_mask());
+
+    let memory = unsafe { env.memory_view(&ctx) };
+    wasi_try_mem!(ret_set.write(&memory, pending.bits()));
+
+    Errno::Success
+}
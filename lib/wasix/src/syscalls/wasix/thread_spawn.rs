@@ -103,6 +103,11 @@ pub fn thread_spawn_internal_from_wasi<M: MemorySize>(
     let thread_id: Tid = thread_handle.id().into();
     Span::current().record("tid", thread_id);
 
+    // New threads start with the signal mask of the thread that created them
+    thread_handle
+        .as_thread()
+        .set_signal_mask(env.thread.signal_mask());
+
     // Spawn the thread
     thread_spawn_internal_using_layout::<M>(ctx, thread_handle, layout, start_ptr_offset, None)?;
 
@@ -239,6 +244,7 @@ fn call_module<M: MemorySize>(
                 }
                 Err(err) => {
                     debug!("failed with runtime error: {}", err);
+                    env.write_coredump(store, &err);
                     env.data(&store)
                         .runtime
                         .on_taint(TaintReason::RuntimeError(err));
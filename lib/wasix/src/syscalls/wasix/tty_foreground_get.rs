@@ -0,0 +1,141 @@
+use super::*;
+use crate::{
+    os::{tty::TtyForeground, TtyBridge},
+    syscalls::*,
+};
+
+/// ### `tty_foreground_get()`
+/// Returns the process group in the foreground of a terminal, if none was
+/// set then the group of the calling process is returned
+/// Note: This is similar to `tcgetpgrp` in POSIX.
+///
+/// ## Parameters
+///
+/// * `fd` - A pseudo-terminal or a standard stream connected to the terminal
+#[instrument(level = "trace", skip_all, fields(%fd, pgid = field::Empty), ret)]
+pub fn tty_foreground_get<M: MemorySize>(
+    ctx: FunctionEnvMut<'_, WasiEnv>,
+    fd: WasiFd,
+    ret_pgid: WasmPtr<Pid, M>,
+) -> Errno {
+    let env = ctx.data();
+    let foreground = wasi_try!(with_terminal(env, fd, |tty| tty.foreground_get()));
+    let pgid = match foreground {
+        Some(foreground) => foreground.pgid,
+        None => env.process.pgid(),
+    };
+    let pgid: Pid = pgid.raw();
+    Span::current().record("pgid", pgid);
+
+    let memory = unsafe { env.memory_view(&ctx) };
+    wasi_try_mem!(ret_pgid.write(&memory, pgid));
+
+    Errno::Success
+}
+
+/// Runs `f` on the terminal behind a file descriptor, which is either a
+/// pseudo-terminal or the terminal of the runtime for the standard streams
+pub(crate) fn with_terminal<T>(
+    env: &WasiEnv,
+    fd: WasiFd,
+    f: impl FnOnce(&dyn TtyBridge) -> T,
+) -> Result<T, Errno> {
+    match pty_from_fd(env, fd) {
+        Ok(pty) => return Ok(f(&pty)),
+        Err(Errno::Notty) => {}
+        Err(err) => return Err(err),
+    }
+
+    let tty = env.runtime.tty().ok_or(Errno::Notty)?;
+    let state = tty.tty_get();
+    let is_tty = match fd {
+        __WASI_STDIN_FILENO => state.stdin_tty,
+        __WASI_STDOUT_FILENO => state.stdout_tty,
+        __WASI_STDERR_FILENO => state.stderr_tty,
+        _ => false,
+    };
+    if !is_tty {
+        return Err(Errno::Notty);
+    }
+    Ok(f(tty))
+}
+
+/// How a process accesses its terminal, which decides the job control
+/// signal raised when it is not in the foreground process group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TerminalAccess {
+    /// Reading input, raises `SIGTTIN`
+    Read,
+    /// Writing output, raises `SIGTTOU` if the terminal has `TOSTOP` set
+    Write,
+    /// Changing the terminal, raises `SIGTTOU`
+    Control,
+}
+
+/// Enforces job control on an access to the terminal behind a file
+/// descriptor by a process outside of its foreground process group. The
+/// group of the caller is signalled, which stops it by default, and the
+/// access is checked again once it is continued. Processes that ignore or
+/// block the signal can't read (`EIO`) but can write and change the terminal.
+pub(crate) fn check_terminal_access(
+    ctx: &mut FunctionEnvMut<'_, WasiEnv>,
+    fd: WasiFd,
+    access: TerminalAccess,
+) -> WasiResult<()> {
+    let signal = match access {
+        TerminalAccess::Read => Signal::Sigttin,
+        TerminalAccess::Write | TerminalAccess::Control => Signal::Sigttou,
+    };
+    loop {
+        let env = ctx.data();
+        let Some(foreground) = terminal_foreground(env, fd, access) else {
+            return Ok(Ok(()));
+        };
+        let pgid = env.process.pgid();
+        if foreground.pgid == pgid {
+            return Ok(Ok(()));
+        }
+
+        let sig = signal as u8;
+        if env.process.signal_action(sig).disposition == SigDisposition::Ignore
+            || env.thread.signal_mask().contains(sig)
+        {
+            return Ok(match access {
+                TerminalAccess::Read => Err(Errno::Io),
+                TerminalAccess::Write | TerminalAccess::Control => Ok(()),
+            });
+        }
+
+        tracing::trace!(%fd, %pgid, ?signal, "background terminal access");
+        env.control_plane.signal_process_group(pgid, signal);
+        let stopped = env.process.stopped().is_some();
+        wasi_try_ok_ok!(WasiEnv::process_signals_and_exit(ctx)?);
+
+        // A signal that is caught interrupts the call instead
+        if !stopped {
+            return Ok(Err(Errno::Intr));
+        }
+    }
+}
+
+/// Returns the foreground process group of the terminal behind a file
+/// descriptor if job control applies to the access, the master end of a
+/// pseudo-terminal is not subject to it
+fn terminal_foreground(env: &WasiEnv, fd: WasiFd, access: TerminalAccess) -> Option<TtyForeground> {
+    let foreground = |tty: &dyn TtyBridge| match access {
+        TerminalAccess::Write if !tty.tostop() => None,
+        _ => tty.foreground_get(),
+    };
+    match pty_end_from_fd(env, fd) {
+        Ok((pty, true)) => return foreground(&pty),
+        Ok((_, false)) => return None,
+        Err(_) => {}
+    }
+
+    if fd > __WASI_STDERR_FILENO {
+        return None;
+    }
+    let ret = foreground(env.runtime.tty()?)?;
+    with_terminal(env, fd, |_| ()).ok()?;
+    Some(ret)
+}
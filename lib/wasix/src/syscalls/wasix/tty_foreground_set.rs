@@ -0,0 +1,33 @@
+use super::*;
+use crate::syscalls::*;
+
+/// ### `tty_foreground_set()`
+/// Changes the process group in the foreground of a terminal, it receives
+/// the job control signals raised by the terminal
+/// Note: This is similar to `tcsetpgrp` in POSIX.
+///
+/// ## Parameters
+///
+/// * `fd` - A pseudo-terminal or a standard stream connected to the terminal
+/// * `pgid` - A process group of the session of the calling process
+///
+/// A process outside of the foreground process group receives `SIGTTOU`
+/// unless it blocks or ignores it.
+#[instrument(level = "trace", skip_all, fields(%fd, %pgid), ret)]
+pub fn tty_foreground_set(
+    mut ctx: FunctionEnvMut<'_, WasiEnv>,
+    fd: WasiFd,
+    pgid: Pid,
+) -> Result<Errno, WasiError> {
+    wasi_try_ok!(check_terminal_access(
+        &mut ctx,
+        fd,
+        TerminalAccess::Control
+    )?);
+
+    let env = ctx.data();
+    let foreground = wasi_try_ok!(foreground_for_group(env, pgid));
+    wasi_try_ok!(with_terminal(env, fd, |tty| tty.foreground_set(Some(foreground))));
+
+    Ok(Errno::Success)
+}
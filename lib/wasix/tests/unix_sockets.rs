@@ -0,0 +1,66 @@
+use wasmer::{Module, Store};
+use wasmer_wasix::WasiEnv;
+
+#[test]
+fn test_recv_fds_keeps_what_does_not_fit() {
+    let mut store = Store::default();
+    let module = Module::new(
+        &store,
+        br#"
+    (module
+        (import "wasix_32v1" "sock_pair"
+            (func $sock_pair (param i32 i32 i32 i32 i32) (result i32)))
+        (import "wasix_32v1" "sock_send_fds"
+            (func $sock_send_fds (param i32 i32 i32) (result i32)))
+        (import "wasix_32v1" "sock_recv_fds"
+            (func $sock_recv_fds (param i32 i32 i32 i32) (result i32)))
+
+        (memory 1)
+        (export "memory" (memory 0))
+
+        ;; Traps when a syscall fails
+        (func $check (param $errno i32)
+            (if (local.get $errno) (then unreachable)))
+
+        ;; Receives into the buffer at 64 and returns how many descriptors
+        ;; were stored
+        (func $recv (param $len i32) (result i32)
+            (call $check
+                (call $sock_recv_fds (i32.load (i32.const 4)) (i32.const 64) (local.get $len) (i32.const 8)))
+            (i32.load (i32.const 8)))
+
+        (func $main (export "_start")
+            ;; A connected pair of unix stream sockets at 0 and 4
+            (call $check
+                (call $sock_pair (i32.const 3) (i32.const 1) (i32.const 0) (i32.const 0) (i32.const 4)))
+
+            ;; Pass stdin, stdout and stderr in one batch
+            (i32.store (i32.const 16) (i32.const 0))
+            (i32.store (i32.const 20) (i32.const 1))
+            (i32.store (i32.const 24) (i32.const 2))
+            (call $check
+                (call $sock_send_fds (i32.load (i32.const 0)) (i32.const 16) (i32.const 3)))
+
+            ;; A return pointer outside of the memory fails without taking
+            ;; the descriptors off the socket
+            (if (i32.eqz
+                    (call $sock_recv_fds (i32.load (i32.const 4)) (i32.const 64) (i32.const 1) (i32.const 65536)))
+                (then unreachable))
+
+            ;; Only one descriptor fits, the other two are received next
+            (if (i32.ne (call $recv (i32.const 1)) (i32.const 1)) (then unreachable))
+            (if (i32.ne (call $recv (i32.const 4)) (i32.const 2)) (then unreachable))
+            (if (i32.eq (i32.load (i32.const 64)) (i32.load (i32.const 68))) (then unreachable))
+            (if (i32.ne (call $recv (i32.const 4)) (i32.const 0)) (then unreachable))
+        )
+    )
+    "#,
+    )
+    .unwrap();
+
+    let builder = WasiEnv::builder("command-name");
+    std::thread::spawn(move || builder.run_with_store(module, &mut store))
+        .join()
+        .unwrap()
+        .unwrap();
+}
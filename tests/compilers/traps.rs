@@ -246,6 +246,212 @@ RuntimeError: unreachable
     Ok(())
 }
 
+#[cfg_attr(target_env = "musl", ignore)]
+#[compiler_test(traps)]
+fn trap_display_dwarf(config: crate::Config) -> Result<()> {
+    use gimli::write::{
+        Address, AttributeValue, DwarfUnit, EndianVec, LineProgram, LineString, Sections,
+    };
+
+    let mut store = config.store();
+    let wasm = wat2wasm(
+        br#"
+        (module $m
+            (func $die unreachable)
+            (func (export "bar") call $die)
+        )
+    "#,
+    )?
+    .into_owned();
+
+    // Find out where the trap happens, relative to the code section
+    let module = Module::new(&store, &wasm)?;
+    let instance = Instance::new(&mut store, &module, &imports! {})?;
+    let e = instance
+        .exports
+        .get_function("bar")?
+        .call(&mut store, &[])
+        .expect_err("error calling function");
+    assert!(e.trace().iter().all(|frame| frame.symbols().is_empty()));
+    let (code_start, code_len) = code_section(&wasm);
+    let trap = (e.trace()[0].module_offset() - code_start) as u64;
+
+    // Describe the code as `trap_here` from `lib.rs`, with `inner` inlined
+    // at the trapping instruction.
+    let encoding = gimli::Encoding {
+        format: gimli::Format::Dwarf32,
+        version: 4,
+        address_size: 4,
+    };
+    let mut dwarf = DwarfUnit::new(encoding);
+    let mut program = LineProgram::new(
+        encoding,
+        Default::default(),
+        LineString::String(b"/src".to_vec()),
+        LineString::String(b"lib.rs".to_vec()),
+        None,
+    );
+    let dir = program.default_directory();
+    let file = program.add_file(LineString::String(b"lib.rs".to_vec()), dir, None);
+    program.begin_sequence(Some(Address::Constant(0)));
+    for (address_offset, line, column) in [(0, 1, 1), (trap, 10, 9), (trap + 1, 4, 1)] {
+        let row = program.row();
+        row.file = file;
+        row.address_offset = address_offset;
+        row.line = line;
+        row.column = column;
+        program.generate_row();
+    }
+    program.end_sequence(code_len as u64);
+    dwarf.unit.line_program = program;
+
+    let root = dwarf.unit.root();
+    let cu = dwarf.unit.get_mut(root);
+    cu.set(
+        gimli::DW_AT_name,
+        AttributeValue::String(b"lib.rs".to_vec()),
+    );
+    cu.set(
+        gimli::DW_AT_comp_dir,
+        AttributeValue::String(b"/src".to_vec()),
+    );
+    cu.set(
+        gimli::DW_AT_low_pc,
+        AttributeValue::Address(Address::Constant(0)),
+    );
+    cu.set(gimli::DW_AT_high_pc, AttributeValue::Udata(code_len as u64));
+
+    let inner = dwarf.unit.add(root, gimli::DW_TAG_subprogram);
+    let entry = dwarf.unit.get_mut(inner);
+    entry.set(gimli::DW_AT_name, AttributeValue::String(b"inner".to_vec()));
+    entry.set(
+        gimli::DW_AT_inline,
+        AttributeValue::Inline(gimli::DW_INL_inlined),
+    );
+
+    let outer = dwarf.unit.add(root, gimli::DW_TAG_subprogram);
+    let entry = dwarf.unit.get_mut(outer);
+    entry.set(
+        gimli::DW_AT_name,
+        AttributeValue::String(b"trap_here".to_vec()),
+    );
+    entry.set(
+        gimli::DW_AT_low_pc,
+        AttributeValue::Address(Address::Constant(0)),
+    );
+    entry.set(gimli::DW_AT_high_pc, AttributeValue::Udata(code_len as u64));
+
+    let inlined = dwarf.unit.add(outer, gimli::DW_TAG_inlined_subroutine);
+    let entry = dwarf.unit.get_mut(inlined);
+    entry.set(gimli::DW_AT_abstract_origin, AttributeValue::UnitRef(inner));
+    entry.set(
+        gimli::DW_AT_low_pc,
+        AttributeValue::Address(Address::Constant(trap)),
+    );
+    entry.set(gimli::DW_AT_high_pc, AttributeValue::Udata(1));
+    entry.set(
+        gimli::DW_AT_call_file,
+        AttributeValue::FileIndex(Some(file)),
+    );
+    entry.set(gimli::DW_AT_call_line, AttributeValue::Udata(3));
+    entry.set(gimli::DW_AT_call_column, AttributeValue::Udata(5));
+
+    let mut sections = Sections::new(EndianVec::new(gimli::LittleEndian));
+    dwarf.write(&mut sections)?;
+    let mut wasm = wasm;
+    sections.for_each(|id, data| {
+        if !data.slice().is_empty() {
+            append_custom_section(&mut wasm, id.name(), data.slice());
+        }
+        Ok::<_, gimli::write::Error>(())
+    })?;
+
+    let module = Module::new(&store, &wasm)?;
+    let instance = Instance::new(&mut store, &module, &imports! {})?;
+    let e = instance
+        .exports
+        .get_function("bar")?
+        .call(&mut store, &[])
+        .expect_err("error calling function");
+
+    let symbols = e.trace()[0].symbols();
+    assert_eq!(symbols.len(), 2);
+    assert_eq!(symbols[0].function(), Some("inner"));
+    assert_eq!(symbols[0].file(), Some("/src/lib.rs"));
+    assert_eq!(symbols[0].line(), Some(10));
+    assert_eq!(symbols[0].column(), Some(9));
+    assert_eq!(symbols[1].function(), Some("trap_here"));
+    assert_eq!(symbols[1].line(), Some(3));
+    assert_eq!(symbols[1].column(), Some(5));
+
+    let display = e.to_string();
+    assert!(
+        display.starts_with(
+            "\
+RuntimeError: unreachable
+    at die (m[0]:0x"
+        ),
+        "{display}"
+    );
+    assert!(
+        display.contains(
+            "
+        inlined inner at /src/lib.rs:10:9
+        at /src/lib.rs:3:5
+    at <unnamed> (m[1]:0x"
+        ),
+        "{display}"
+    );
+    return Ok(());
+
+    /// Returns the offset and length of the code section contents.
+    fn code_section(wasm: &[u8]) -> (usize, usize) {
+        let mut offset = 8;
+        loop {
+            let id = wasm[offset];
+            let (size, leb_len) = read_leb(&wasm[offset + 1..]);
+            let start = offset + 1 + leb_len;
+            if id == 10 {
+                return (start, size);
+            }
+            offset = start + size;
+        }
+    }
+
+    fn read_leb(bytes: &[u8]) -> (usize, usize) {
+        let mut value = 0;
+        for (i, byte) in bytes.iter().enumerate() {
+            value |= ((byte & 0x7f) as usize) << (7 * i);
+            if byte & 0x80 == 0 {
+                return (value, i + 1);
+            }
+        }
+        unreachable!()
+    }
+
+    fn write_leb(out: &mut Vec<u8>, mut value: usize) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(byte);
+                return;
+            }
+            out.push(byte | 0x80);
+        }
+    }
+
+    fn append_custom_section(wasm: &mut Vec<u8>, name: &str, data: &[u8]) {
+        let mut contents = Vec::new();
+        write_leb(&mut contents, name.len());
+        contents.extend_from_slice(name.as_bytes());
+        contents.extend_from_slice(data);
+        wasm.push(0);
+        write_leb(wasm, contents.len());
+        wasm.extend_from_slice(&contents);
+    }
+}
+
 #[compiler_test(traps)]
 fn trap_start_function_import(config: crate::Config) -> Result<()> {
     let mut store = config.store();
@@ -1162,6 +1162,77 @@ file.write("Hello, world!")
     assert_eq!(file_contents, "Hello, world!");
 }
 
+/// Profiles a WASIX program that busy loops on its main thread and on a
+/// thread that it spawns, the guest loop of the spawned thread and the host
+/// time of the main thread must both be in the profile.
+#[test]
+#[cfg_attr(not(target_os = "linux"), ignore = "the profiler only runs on Linux")]
+#[cfg_attr(feature = "wasmi", ignore = "wasmi currently does not support threads")]
+#[cfg_attr(feature = "wamr", ignore = "the profiler needs a compiler backend")]
+#[cfg_attr(feature = "v8", ignore = "the profiler needs a compiler backend")]
+fn profile_busy_loops_on_spawned_threads() {
+    // The main thread spins on the clock (host code) for a second while the
+    // thread it spawned spins on a flag that the main thread sets once it is
+    // done
+    let wat = r#"
+    (module
+        (import "wasix_32v1" "clock_time_get"
+          (func $clock_time_get (param i32 i64 i32) (result i32)))
+        (import "wasix_32v1" "thread_spawn_v2"
+          (func $thread_spawn (param i32 i32) (result i32)))
+        (import "env" "memory" (memory $memory 1 1 shared))
+        (export "memory" (memory $memory))
+
+        (func $now (result i64)
+          (drop (call $clock_time_get (i32.const 1) (i64.const 1) (i32.const 256)))
+          (i64.load (i32.const 256)))
+
+        (func $main_spin (param $until i64)
+          (loop $spin
+            (br_if $spin (i64.lt_u (call $now) (local.get $until)))))
+
+        (func $worker_spin
+          (loop $spin
+            (br_if $spin (i32.eqz (i32.atomic.load (i32.const 512))))))
+
+        (func $wasi_thread_start (export "wasi_thread_start") (param i32 i32)
+          (call $worker_spin))
+
+        (func $_start (export "_start")
+          (drop (call $thread_spawn (i32.const 1024) (i32.const 264)))
+          (call $main_spin (i64.add (call $now) (i64.const 1000000000)))
+          (i32.atomic.store (i32.const 512) (i32.const 1)))
+      )
+    "#;
+
+    let temp = TempDir::new().unwrap();
+    let module = temp.path().join("busy-loops.wat");
+    std::fs::write(&module, wat).unwrap();
+    let profile = temp.path().join("profile.folded");
+
+    Command::new(get_wasmer_path())
+        .arg("run")
+        .arg("--profile")
+        .arg(&profile)
+        .arg(&module)
+        .env("RUST_LOG", &*RUST_LOG)
+        .assert()
+        .success();
+
+    let profile = std::fs::read_to_string(&profile).unwrap();
+    let stacks = profile.lines().collect::<Vec<_>>();
+    assert!(
+        stacks.iter().any(|stack| stack.starts_with("[host] ")),
+        "{profile}"
+    );
+    assert!(
+        stacks
+            .iter()
+            .any(|stack| stack.starts_with("wasi_thread_start;worker_spin")),
+        "{profile}"
+    );
+}
+
 fn project_root() -> &'static Path {
     Path::new(env!("CARGO_MANIFEST_DIR"))
         .ancestors()